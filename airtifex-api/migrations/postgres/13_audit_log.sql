@@ -0,0 +1,10 @@
+-- entries reference users without a foreign key so that they outlive deleted users
+CREATE TABLE audit_log (
+    id UUID PRIMARY KEY NOT NULL,
+    actor_user_id UUID,
+    action INTEGER NOT NULL,
+    target VARCHAR,
+    timestamp TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX audit_log_timestamp ON audit_log (timestamp);
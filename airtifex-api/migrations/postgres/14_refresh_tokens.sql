@@ -0,0 +1,8 @@
+-- only a digest of the token is stored, deleting a row revokes the token
+CREATE TABLE refresh_tokens (
+    id UUID PRIMARY KEY NOT NULL,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_digest BYTEA NOT NULL UNIQUE,
+    create_date TIMESTAMPTZ NOT NULL,
+    expiration_date TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,2 @@
+-- square PNG thumbnail, NULL when the user didn't upload one
+ALTER TABLE users ADD COLUMN avatar BYTEA;
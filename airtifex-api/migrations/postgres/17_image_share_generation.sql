@@ -0,0 +1,2 @@
+-- bumped to revoke all share links of an image, links are signed with the current value
+ALTER TABLE images ADD COLUMN share_generation BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,19 @@
+CREATE TABLE llm_batches (
+     id          UUID PRIMARY KEY NOT NULL,
+     username    VARCHAR NOT NULL REFERENCES users(username) ON DELETE CASCADE,
+     model       VARCHAR NOT NULL REFERENCES llm_models(name),
+     -- shared by all prompts, NULL when every prompt uses a random seed
+     seed        BIGINT,
+     create_date TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE llm_batch_entries (
+     batch_id UUID NOT NULL REFERENCES llm_batches(id) ON DELETE CASCADE,
+     n        INTEGER NOT NULL,
+     prompt   VARCHAR NOT NULL,
+     status   INTEGER NOT NULL DEFAULT 1,
+     response VARCHAR,
+     error    VARCHAR,
+
+     PRIMARY KEY (batch_id, n)
+);
@@ -0,0 +1,2 @@
+-- speeds up counting the images created recently
+CREATE INDEX images_create_date ON images (create_date);
@@ -0,0 +1,9 @@
+-- freeform tags the owner of an image attached to it
+CREATE TABLE image_tags (
+    image_id UUID NOT NULL REFERENCES images(id) ON DELETE CASCADE,
+    tag VARCHAR NOT NULL,
+    PRIMARY KEY (image_id, tag)
+);
+
+CREATE INDEX image_tags_tag ON image_tags (tag);
+CREATE INDEX images_user_id ON images (user_id);
@@ -0,0 +1,2 @@
+-- number of previous turns of the chat the model is given, all of them when null
+ALTER TABLE chats ADD COLUMN context_turns INTEGER;
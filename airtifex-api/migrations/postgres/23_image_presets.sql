@@ -0,0 +1,17 @@
+-- named generation parameters a user saved to fill the image form with
+CREATE TABLE image_presets (
+     id             UUID PRIMARY KEY NOT NULL,
+     user_id        UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+     name           VARCHAR NOT NULL,
+     prompt         VARCHAR,
+     width          BIGINT,
+     height         BIGINT,
+     n_steps        BIGINT,
+     seed           BIGINT,
+     num_samples    BIGINT,
+     guidance_scale DOUBLE PRECISION,
+     strength       DOUBLE PRECISION,
+     create_date    TIMESTAMPTZ NOT NULL,
+
+     UNIQUE (user_id, name)
+);
@@ -0,0 +1,12 @@
+-- URLs the completed generations of a user are posted to
+CREATE TABLE webhooks (
+     id                 UUID PRIMARY KEY NOT NULL,
+     user_id            UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+     url                VARCHAR NOT NULL,
+     secret             VARCHAR NOT NULL,
+     -- comma separated event names
+     events             VARCHAR NOT NULL,
+     create_date        TIMESTAMPTZ NOT NULL,
+     last_status        VARCHAR,
+     last_delivery_date TIMESTAMPTZ
+);
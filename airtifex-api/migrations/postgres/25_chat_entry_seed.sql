@@ -0,0 +1,2 @@
+-- seed the answer of the bot was sampled with, null for prompts and older answers
+ALTER TABLE chat_entries ADD COLUMN seed BIGINT;
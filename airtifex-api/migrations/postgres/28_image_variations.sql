@@ -0,0 +1,2 @@
+-- image a variation was generated from, null for images that aren't variations
+ALTER TABLE images ADD COLUMN source_image_id UUID REFERENCES images(id) ON DELETE SET NULL;
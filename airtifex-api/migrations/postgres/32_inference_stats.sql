@@ -0,0 +1,11 @@
+-- time requests to the language models waited in the queue and took to generate
+CREATE TABLE inference_stats (
+    id UUID PRIMARY KEY NOT NULL,
+    model VARCHAR NOT NULL,
+    queue_wait_ms BIGINT NOT NULL,
+    generation_ms BIGINT NOT NULL,
+    generated_tokens BIGINT NOT NULL,
+    finish_date TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX inference_stats_finish_date ON inference_stats (finish_date);
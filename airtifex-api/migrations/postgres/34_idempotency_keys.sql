@@ -0,0 +1,14 @@
+-- keys of generation requests that were already processed, a retry with the same key gets the
+-- resource the first request created
+CREATE TABLE idempotency_keys (
+     user_id         UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+     request_key     VARCHAR NOT NULL,
+     request_digest  BYTEA NOT NULL,
+     resource_id     UUID,
+     create_date     TIMESTAMPTZ NOT NULL,
+     expiration_date TIMESTAMPTZ NOT NULL,
+
+     PRIMARY KEY (user_id, request_key)
+);
+
+CREATE INDEX idempotency_keys_expiration_date ON idempotency_keys (expiration_date);
@@ -0,0 +1,3 @@
+ALTER TABLE chats ADD COLUMN mirostat INTEGER;
+ALTER TABLE chats ADD COLUMN mirostat_tau FLOAT;
+ALTER TABLE chats ADD COLUMN mirostat_eta FLOAT;
@@ -0,0 +1,14 @@
+ALTER TABLE image_samples ADD COLUMN actual_seed BIGINT NOT NULL DEFAULT 0;
+
+-- samples are seeded with the seed of their image offset by their index, seeds at the end of
+-- the range wrap around to its start like they do in `sample_seed`
+UPDATE image_samples
+SET actual_seed = (
+    SELECT CASE
+        WHEN i.seed > 9223372036854775807 - (image_samples.n - 1)
+        THEN i.seed - 9223372036854775807 - 1 + (image_samples.n - 1) - 9223372036854775807 - 1
+        ELSE i.seed + (image_samples.n - 1)
+    END
+    FROM images i
+    WHERE i.id = image_samples.image_id
+);
@@ -0,0 +1,6 @@
+ALTER TABLE images ADD COLUMN status INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE images ADD COLUMN error VARCHAR;
+-- images still processing at this point were interrupted
+UPDATE images SET status = CASE WHEN processing THEN 3 ELSE 4 END;
+UPDATE images SET error = 'interrupted by a server restart' WHERE status = 3;
+ALTER TABLE images DROP COLUMN processing;
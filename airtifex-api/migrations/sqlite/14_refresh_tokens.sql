@@ -0,0 +1,8 @@
+-- only a digest of the token is stored, deleting a row revokes the token
+CREATE TABLE refresh_tokens (
+    id UUID PRIMARY KEY NOT NULL,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_digest BLOB NOT NULL UNIQUE,
+    create_date DATETIME NOT NULL,
+    expiration_date DATETIME NOT NULL
+);
@@ -0,0 +1,3 @@
+ALTER TABLE prompts ADD COLUMN is_favorite BOOLEAN NOT NULL DEFAULT FALSE;
+-- position in the user defined order of the prompt list, lower comes first
+ALTER TABLE prompts ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0;
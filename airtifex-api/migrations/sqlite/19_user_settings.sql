@@ -0,0 +1,5 @@
+-- defaults of the generation parameters of a user serialized as JSON
+CREATE TABLE user_settings (
+    user_id UUID PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    settings TEXT NOT NULL
+);
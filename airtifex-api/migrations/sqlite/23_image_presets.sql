@@ -0,0 +1,17 @@
+-- named generation parameters a user saved to fill the image form with
+CREATE TABLE image_presets (
+     id             UUID PRIMARY KEY NOT NULL,
+     user_id        UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+     name           VARCHAR NOT NULL,
+     prompt         VARCHAR,
+     width          INTEGER,
+     height         INTEGER,
+     n_steps        INTEGER,
+     seed           INTEGER,
+     num_samples    INTEGER,
+     guidance_scale REAL,
+     strength       REAL,
+     create_date    DATETIME NOT NULL,
+
+     UNIQUE (user_id, name)
+);
@@ -0,0 +1,2 @@
+-- time of the last successful login, null for users that never logged in
+ALTER TABLE users ADD COLUMN last_login DATETIME;
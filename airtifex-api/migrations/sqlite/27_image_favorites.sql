@@ -0,0 +1,2 @@
+-- images starred by their owner for quick access
+ALTER TABLE images ADD COLUMN is_favorite BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- bumped to revoke all share links of a chat, links are signed with the current value
+ALTER TABLE chats ADD COLUMN share_generation INTEGER NOT NULL DEFAULT 0;
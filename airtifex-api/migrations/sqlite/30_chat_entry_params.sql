@@ -0,0 +1,3 @@
+-- model and sampling parameters the answer of the bot was generated with as compact JSON, null
+-- for prompts and older answers
+ALTER TABLE chat_entries ADD COLUMN params TEXT;
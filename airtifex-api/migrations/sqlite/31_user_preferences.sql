@@ -0,0 +1,6 @@
+-- appearance of the web app of a user serialized as JSON, synced between the devices of the user
+CREATE TABLE user_preferences (
+    user_id UUID PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    preferences TEXT NOT NULL,
+    updated_at DATETIME NOT NULL
+);
@@ -0,0 +1,11 @@
+-- personas a chat can be started with, shared ones are visible to every user
+CREATE TABLE system_prompts (
+     id          UUID PRIMARY KEY NOT NULL,
+     user_id     UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+     name        VARCHAR NOT NULL,
+     content     VARCHAR NOT NULL,
+     shared      BOOLEAN NOT NULL DEFAULT FALSE,
+     create_date DATETIME NOT NULL,
+
+     UNIQUE (user_id, name)
+);
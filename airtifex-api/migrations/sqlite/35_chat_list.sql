@@ -0,0 +1,8 @@
+-- time the user last read the chat, answers of the bot after it are unread
+ALTER TABLE chats ADD COLUMN last_read_date DATETIME;
+
+-- the chats that exist are read up to their latest entry
+UPDATE chats SET last_read_date = (SELECT MAX(entry_date) FROM chat_entries WHERE chat_entries.chat_id = chats.id);
+
+-- the latest entry of every chat is looked up when listing chats
+CREATE INDEX chat_entries_chat_id_entry_date ON chat_entries (chat_id, entry_date);
@@ -0,0 +1,2 @@
+-- limits of the requests a model accepts as JSON, models without them accept every request
+ALTER TABLE image_models ADD COLUMN capabilities VARCHAR;
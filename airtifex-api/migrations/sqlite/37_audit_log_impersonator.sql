@@ -0,0 +1,2 @@
+-- admin that acted as the actor of an entry during an impersonation
+ALTER TABLE audit_log ADD COLUMN impersonator_user_id UUID;
@@ -1,4 +1,7 @@
-use crate::{errors::Error, ApiResponse, SharedAppState};
+use crate::{
+    errors::Error, id::Uuid, models::refresh_token::RefreshToken, ApiResponse, DbPool,
+    SharedAppState, ToAxumResponse,
+};
 use airtifex_core::user::AccountType;
 
 use axum::{
@@ -7,10 +10,10 @@ use axum::{
     headers::{authorization::Bearer, Authorization},
     http::{request::Parts, StatusCode},
     response::{IntoResponse, Response},
-    Json, RequestPartsExt,
+    RequestPartsExt,
 };
 use axum_extra::extract::cookie::{Key, PrivateCookieJar};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use jsonwebtoken::{
     decode, encode, errors::Error as JwtError, Algorithm, DecodingKey, EncodingKey, Header,
     Validation,
@@ -19,7 +22,11 @@ use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use thiserror::Error as ErrorType;
 
-const KEY_VALID_DURATION: i64 = 3600;
+/// Access tokens are short lived, clients obtain new ones with their refresh token.
+const KEY_VALID_DURATION: i64 = 900;
+const REFRESH_TOKEN_VALID_DURATION: i64 = 30 * 24 * 3600;
+/// Impersonations end with their access token, they can't be refreshed.
+const IMPERSONATION_VALID_DURATION: i64 = 600;
 
 static KEYS: Lazy<Keys> = Lazy::new(|| {
     let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
@@ -40,7 +47,11 @@ pub enum AuthError {
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response {
-        (StatusCode::BAD_REQUEST, Json(ApiResponse::failure(self))).into_response()
+        let status = match self {
+            AuthError::InvalidToken(_) | AuthError::InvalidHeader => StatusCode::UNAUTHORIZED,
+            AuthError::InvalidPath => StatusCode::BAD_REQUEST,
+        };
+        ApiResponse::failure(self).into_response(status)
     }
 }
 
@@ -80,35 +91,10 @@ impl FromRequestParts<SharedAppState> for Claims {
         parts: &mut Parts,
         state: &SharedAppState,
     ) -> Result<Self, Self::Rejection> {
-        let cookies = PrivateCookieJar::<Key>::from_request_parts(parts, state)
-            .await
-            .ok();
-
-        let token = if let Some(cookie_token) = cookies.and_then(|c| c.get("Bearer")) {
-            log::trace!("got auth token from cookie");
-            cookie_token.value().to_owned()
-        } else {
-            let TypedHeader(Authorization(bearer)) =
-                match parts.extract::<TypedHeader<Authorization<Bearer>>>().await {
-                    Ok(bearer) => bearer,
-                    Err(e) => {
-                        log::error!("Failed to extract Authorization Bearer header - {e}");
-                        return Err(AuthError::InvalidHeader);
-                    }
-                };
-            log::trace!("got auth token from header");
-            bearer.token().to_owned()
-        };
-        let token_data =
-            match decode::<Claims>(&token, &KEYS.decoding, &Validation::new(Algorithm::HS512)) {
-                Ok(bearer) => bearer,
-                Err(e) => {
-                    log::error!("Failed to decode claims from token - {e}");
-                    return Err(AuthError::InvalidToken(e));
-                }
-            };
-
-        Ok(token_data.claims)
+        Claims::decode_from_parts(parts, state).await.map_err(|e| {
+            log::error!("Failed to authenticate the request - {e}");
+            e
+        })
     }
 }
 
@@ -119,6 +105,7 @@ pub trait Token {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JsonWebToken {
     pub token: String,
+    pub refresh_token: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -126,22 +113,88 @@ pub struct Claims {
     pub sub: String,
     pub role: String,
     pub exp: usize,
+    /// Admin acting as the user `sub`, set on impersonation tokens only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonator: Option<String>,
+}
+
+impl Claims {
+    pub fn is_impersonation(&self) -> bool {
+        self.impersonator.is_some()
+    }
+
+    /// Decodes the claims of the token in the cookie or the `Authorization` header of a request.
+    /// Unlike the extractor it doesn't log requests without a valid token, those are expected
+    /// where authentication is optional.
+    pub async fn decode_from_parts(
+        parts: &mut Parts,
+        state: &SharedAppState,
+    ) -> Result<Self, AuthError> {
+        let cookies = PrivateCookieJar::<Key>::from_request_parts(parts, state)
+            .await
+            .ok();
+
+        let token = if let Some(cookie_token) = cookies.and_then(|c| c.get("Bearer")) {
+            log::trace!("got auth token from cookie");
+            cookie_token.value().to_owned()
+        } else {
+            let TypedHeader(Authorization(bearer)) = parts
+                .extract::<TypedHeader<Authorization<Bearer>>>()
+                .await
+                .map_err(|_| AuthError::InvalidHeader)?;
+            log::trace!("got auth token from header");
+            bearer.token().to_owned()
+        };
+        decode::<Claims>(&token, &KEYS.decoding, &Validation::new(Algorithm::HS512))
+            .map(|token_data| token_data.claims)
+            .map_err(AuthError::InvalidToken)
+    }
 }
 
 pub fn generate_jwt(user: &str, role: AccountType) -> Result<String, Error> {
+    encode_jwt(user, role, None, KEY_VALID_DURATION).map(|(token, _)| token)
+}
+
+/// Issues a short lived access token of `admin` acting as `user`, returns it together with the
+/// time it expires.
+pub fn generate_impersonation_jwt(
+    user: &str,
+    role: AccountType,
+    admin: &str,
+) -> Result<(String, DateTime<Utc>), Error> {
+    encode_jwt(user, role, Some(admin), IMPERSONATION_VALID_DURATION)
+}
+
+fn encode_jwt(
+    user: &str,
+    role: AccountType,
+    impersonator: Option<&str>,
+    valid_secs: i64,
+) -> Result<(String, DateTime<Utc>), Error> {
     let exp = Utc::now()
-        .checked_add_signed(chrono::Duration::seconds(KEY_VALID_DURATION))
-        .ok_or(TokenGenerationError::TimestampGenerationFailed)?
-        .timestamp();
+        .checked_add_signed(chrono::Duration::seconds(valid_secs))
+        .ok_or(TokenGenerationError::TimestampGenerationFailed)?;
 
     let claims = Claims {
         sub: user.to_string(),
         role: role.as_ref().to_string(),
-        exp: exp as usize,
+        exp: exp.timestamp() as usize,
+        impersonator: impersonator.map(str::to_string),
     };
 
     let header = Header::new(Algorithm::HS512);
     encode(&header, &claims, &KEYS.encoding)
+        .map(|token| (token, exp))
         .map_err(TokenGenerationError::from)
         .map_err(Error::from)
 }
+
+/// Issues a new refresh token of `user_id` and stores it so that it can be revoked.
+pub async fn generate_refresh_token(db: &DbPool, user_id: Uuid) -> Result<String, Error> {
+    let (refresh_token, token) = RefreshToken::generate(
+        user_id,
+        chrono::Duration::seconds(REFRESH_TOKEN_VALID_DURATION),
+    );
+    refresh_token.create(db).await?;
+    Ok(token)
+}
@@ -0,0 +1,29 @@
+//! Limits of the size of request bodies. Requests announcing a larger body in their
+//! `Content-Length` are rejected before the handler runs, bodies sent without a length are cut
+//! off by the `DefaultBodyLimit` the extractors read them with.
+
+use crate::ToAxumResponse;
+use airtifex_core::api_response::ApiResponse;
+
+use axum::{
+    extract::State,
+    http::{header, Request},
+    middleware::Next,
+    response::Response,
+};
+
+/// Responds with `413 Payload Too Large` when the request body is larger than `max` bytes.
+pub async fn body_limit<B>(State(max): State<usize>, req: Request<B>, next: Next<B>) -> Response {
+    let length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok());
+    match length {
+        Some(length) if length > max as u64 => {
+            ApiResponse::failure(format!("the request body is larger than {max} bytes"))
+                .payload_too_large()
+        }
+        _ => next.run(req).await,
+    }
+}
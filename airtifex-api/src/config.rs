@@ -1,19 +1,53 @@
-use crate::{Error, Result};
+use crate::{gen::image::SAMPLERS, rate_limit::RouteGroup, Error, Result};
 
-use airtifex_core::image::ImageModelFeatures;
+use airtifex_core::image::{ImageModelCapabilities, ImageModelFeatures};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, env, path::PathBuf};
+use std::{collections::HashMap, env, path::PathBuf, time::Duration};
 
 #[derive(Deserialize, Serialize)]
 struct RawConfig {
     listen_addr: String,
     listen_port: u16,
     db_url: String,
+    #[serde(default)]
+    database: DatabaseConfig,
     jwt_secret: String,
     #[serde(default)]
     llms: Vec<LlmConfig>,
     #[serde(default)]
     stable_diffusion: Vec<StableDiffusionConfig>,
+    #[serde(default)]
+    rate_limits: RateLimitConfig,
+    #[serde(default)]
+    login_lockout: LoginLockoutConfig,
+    #[serde(default)]
+    request_limits: RequestLimitsConfig,
+    #[serde(default)]
+    metrics: MetricsConfig,
+    #[serde(default)]
+    inference_defaults: InferenceDefaults,
+    #[serde(default)]
+    avatar: AvatarConfig,
+    #[serde(default)]
+    image_share: ShareConfig,
+    #[serde(default)]
+    chat_share: ShareConfig,
+    #[serde(default)]
+    cors: CorsConfig,
+    #[serde(default)]
+    image_metadata: ImageMetadataConfig,
+    #[serde(default)]
+    image_cancel: ImageCancelConfig,
+    #[serde(default)]
+    passwords: PasswordConfig,
+    #[serde(default)]
+    webhooks: WebhookConfig,
+    #[serde(default)]
+    body_limits: BodyLimitConfig,
+    #[serde(default)]
+    moderation: ModerationConfig,
+    #[serde(default)]
+    idempotency_keys: IdempotencyConfig,
 }
 
 fn default_num_ctx_tokens() -> usize {
@@ -37,12 +71,53 @@ fn default_top_k() -> usize {
 fn default_top_p() -> f32 {
     0.95
 }
+fn default_mirostat_tau() -> f32 {
+    5.0
+}
+fn default_mirostat_eta() -> f32 {
+    0.1
+}
+fn default_repetition_window() -> usize {
+    200
+}
+fn default_repetition_threshold() -> usize {
+    4
+}
 fn default_max_inference_sessions() -> usize {
     5
 }
+fn default_token_channel_capacity() -> usize {
+    64
+}
+fn default_queue_capacity() -> usize {
+    128
+}
 fn default_num_threads() -> usize {
     num_cpus::get_physical()
 }
+fn default_answer_prefix() -> String {
+    "Assistant: ".into()
+}
+fn default_user_prefix() -> String {
+    "User: ".into()
+}
+fn default_conversation_prompt() -> String {
+    r#"{{SYSTEM}}
+Below is a dialog between a user and you.
+Write a response to the request in the '### Request:' section that appropriately completes the request.
+
+### Conversation:
+{{HISTORY}}
+
+### Request:
+{{PROMPT}}
+
+### Response:"#
+        .into()
+}
+
+/// Markers that a conversation prompt has to contain.
+const CONVERSATION_PROMPT_MARKERS: &[&str] = &["{{HISTORY}}", "{{PROMPT}}"];
 
 #[derive(Clone, Copy, Deserialize, Serialize)]
 pub enum LlmType {
@@ -67,41 +142,267 @@ pub enum LlmType {
 pub struct LlmConfig {
     pub model_description: Option<String>,
     pub model_path: std::path::PathBuf,
+    /// Downloaded to `model_path` on start when the weights aren't there yet, an interrupted
+    /// download is continued.
+    pub model_url: Option<String>,
+    /// Hex encoded SHA-256 of the weights, weights that don't match aren't loaded.
+    pub model_sha256: Option<String>,
     #[serde(default = "default_num_ctx_tokens")]
     /// Sets the size of the context (in tokens). Allows feeding longer prompts.
     /// Note that this affects memory.
     pub num_ctx_tokens: usize,
     #[serde(default = "default_num_threads")]
+    /// Number of threads an answer is generated with, requests can ask for fewer. The sessions of
+    /// a model take turns generating a token so they share these threads however many of them
+    /// are running, every model has threads of its own though.
     pub num_threads: usize,
-    #[serde(default = "default_batch_size")]
-    pub batch_size: usize,
+    #[serde(default)]
+    /// CPU cores the inference threads of the model are pinned to, they can run on every core
+    /// when empty. Only supported on Linux.
+    pub cpu_affinity: Vec<usize>,
+    /// Overrides `inference_defaults.batch_size` for this model.
+    pub batch_size: Option<usize>,
     #[serde(default = "default_repeat_last_n")]
     pub repeat_last_n: usize,
-    #[serde(default = "default_repeat_penalty")]
-    pub repeat_penalty: f32,
-    #[serde(default = "default_temperature")]
-    pub temperature: f32,
-    #[serde(default = "default_top_k")]
-    pub top_k: usize,
-    #[serde(default = "default_top_p")]
-    pub top_p: f32,
+    pub repeat_penalty: Option<f32>,
+    pub temperature: Option<f32>,
+    pub top_k: Option<usize>,
+    pub top_p: Option<f32>,
+    /// Overrides `inference_defaults.mirostat` for this model, only `0` is supported until the
+    /// inference backend implements mirostat sampling.
+    pub mirostat: Option<u8>,
+    pub mirostat_tau: Option<f32>,
+    pub mirostat_eta: Option<f32>,
     #[serde(default)]
     pub float16: bool,
+    /// Seed of the answers of requests that don't set one, a random one is used when not set.
     pub seed: Option<u64>,
+    #[serde(default = "default_repetition_window")]
+    /// Number of trailing bytes of an answer checked for a repeating phrase, `0` disables the
+    /// check.
+    pub repetition_window: usize,
+    #[serde(default = "default_repetition_threshold")]
+    /// Answers are cut once their last `repetition_window` bytes are a phrase repeated at least
+    /// this many times.
+    pub repetition_threshold: usize,
     #[serde(default = "default_max_inference_sessions")]
     // Maximum concurent sessions for inference
     pub max_inference_sessions: usize,
+    #[serde(default = "default_token_channel_capacity")]
+    /// Number of messages of an answer that wait for a slow client, the session stops generating
+    /// while that many are waiting.
+    pub token_channel_capacity: usize,
+    #[serde(default = "default_queue_capacity")]
+    /// Number of requests that wait for a free session, further requests are refused until one
+    /// of them is started.
+    pub queue_capacity: usize,
+    #[serde(default)]
+    /// Runs a short prompt through a throwaway session after the model is loaded so that the
+    /// first request doesn't start on cold caches.
+    pub warm_up: bool,
+    #[serde(default)]
+    /// Keeps a session that was already fed the start of the chat prompt while the model is
+    /// idle, the next chat request continues from it instead of starting from scratch.
+    pub keep_warm: bool,
+    #[serde(default)]
+    /// Generates an answer that ended before anything was generated once more with a slightly
+    /// higher temperature.
+    pub retry_empty_answers: bool,
+    #[serde(default)]
+    /// Number of answers to seeded requests that are replayed when the same request comes again,
+    /// `0` disables the cache.
+    pub response_cache_size: usize,
     #[serde(rename = "type")]
     pub type_: LlmType,
+    #[serde(default = "default_answer_prefix")]
+    /// Prepended to the answers of the model in the chat history.
+    pub answer_prefix: String,
+    #[serde(default)]
+    /// Removed from the start of chat answers like the answer prefix, for models that begin
+    /// their answers with a name of their own.
+    pub stripped_answer_prefixes: Vec<String>,
+    #[serde(default = "default_user_prefix")]
+    /// Prepended to the messages of the user in the chat history and to the request.
+    pub user_prefix: String,
+    #[serde(default = "default_conversation_prompt")]
+    /// Template of chat prompts. `{{HISTORY}}` is replaced with the previous messages,
+    /// `{{PROMPT}}` with the request and `{{SYSTEM}}` with the system prompt.
+    pub conversation_prompt: String,
+    #[serde(skip)]
+    /// Global defaults of the parameters this model doesn't override.
+    pub defaults: InferenceDefaults,
+}
+
+impl LlmConfig {
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(self.defaults.batch_size)
+    }
+    pub fn repeat_penalty(&self) -> f32 {
+        self.repeat_penalty.unwrap_or(self.defaults.repeat_penalty)
+    }
+    pub fn temperature(&self) -> f32 {
+        self.temperature.unwrap_or(self.defaults.temperature)
+    }
+    pub fn top_k(&self) -> usize {
+        self.top_k.unwrap_or(self.defaults.top_k)
+    }
+    pub fn top_p(&self) -> f32 {
+        self.top_p.unwrap_or(self.defaults.top_p)
+    }
+    pub fn mirostat(&self) -> u8 {
+        self.mirostat.unwrap_or(self.defaults.mirostat)
+    }
+    pub fn mirostat_tau(&self) -> f32 {
+        self.mirostat_tau.unwrap_or(self.defaults.mirostat_tau)
+    }
+    pub fn mirostat_eta(&self) -> f32 {
+        self.mirostat_eta.unwrap_or(self.defaults.mirostat_eta)
+    }
+    /// Prefixes removed from the start of chat answers, the answer prefix among them.
+    pub fn answer_prefixes(&self) -> Vec<String> {
+        std::iter::once(&self.answer_prefix)
+            .chain(&self.stripped_answer_prefixes)
+            .map(|prefix| prefix.trim().to_string())
+            .filter(|prefix| !prefix.is_empty())
+            .collect()
+    }
+
+    /// Checks the settings of model `name` together with the defaults it uses, so that a
+    /// misconfigured model is reported before it is started.
+    pub fn validate(&self, name: &str) -> Result<()> {
+        let invalid = |field: &str, requirement: &str| {
+            Err(Error::InvalidConfig(format!(
+                "{field} of model {name} {requirement}"
+            )))
+        };
+        let is_positive = |value: f32| value.is_finite() && value > 0.0;
+
+        if let Some(marker) = CONVERSATION_PROMPT_MARKERS
+            .iter()
+            .find(|m| !self.conversation_prompt.contains(**m))
+        {
+            return Err(Error::InvalidConfig(format!(
+                "conversation prompt of model {name} is missing the {marker} marker"
+            )));
+        }
+        if self.repetition_window > 0 && self.repetition_threshold < 2 {
+            return invalid("repetition threshold", "must be at least 2");
+        }
+        if let Some(url) = &self.model_url {
+            let is_http = url.parse::<hyper::Uri>().is_ok_and(|uri| {
+                uri.host().is_some() && matches!(uri.scheme_str(), Some("http" | "https"))
+            });
+            if !is_http {
+                return invalid("model_url", "must be an http or https URL");
+            }
+        } else if !self.model_path.is_file() {
+            return Err(Error::InvalidConfig(format!(
+                "weights of model {name} don't exist at {}",
+                self.model_path.display()
+            )));
+        }
+        if let Some(sha256) = &self.model_sha256 {
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return invalid("model_sha256", "must be 64 hexadecimal digits");
+            }
+        }
+        for (field, value) in [
+            ("num_ctx_tokens", self.num_ctx_tokens),
+            ("num_threads", self.num_threads),
+            ("batch_size", self.batch_size()),
+            ("max_inference_sessions", self.max_inference_sessions),
+            ("token_channel_capacity", self.token_channel_capacity),
+            ("queue_capacity", self.queue_capacity),
+            ("top_k", self.top_k()),
+        ] {
+            if value == 0 {
+                return invalid(field, "must be at least 1");
+            }
+        }
+        if self.repeat_last_n > self.num_ctx_tokens {
+            return invalid("repeat_last_n", "can't be larger than num_ctx_tokens");
+        }
+        let temperature = self.temperature();
+        if !(temperature.is_finite() && temperature >= 0.0) {
+            return invalid("temperature", "must be a number of at least 0");
+        }
+        let top_p = self.top_p();
+        if !(top_p > 0.0 && top_p <= 1.0) {
+            return invalid("top_p", "must be larger than 0 and at most 1");
+        }
+        for (field, value) in [
+            ("repeat_penalty", self.repeat_penalty()),
+            ("mirostat_tau", self.mirostat_tau()),
+            ("mirostat_eta", self.mirostat_eta()),
+        ] {
+            if !is_positive(value) {
+                return invalid(field, "must be a number larger than 0");
+            }
+        }
+        if self.mirostat() != 0 {
+            return invalid(
+                "mirostat",
+                "must be 0, mirostat sampling isn't supported by the inference backend",
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Inference parameters used when neither the request nor the model configuration sets them.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct InferenceDefaults {
+    pub batch_size: usize,
+    pub repeat_penalty: f32,
+    pub temperature: f32,
+    pub top_k: usize,
+    pub top_p: f32,
+    /// Mirostat sampling mode, `0` disables mirostat. Only `0` is supported until the inference
+    /// backend implements mirostat sampling.
+    pub mirostat: u8,
+    pub mirostat_tau: f32,
+    pub mirostat_eta: f32,
+}
+
+impl Default for InferenceDefaults {
+    fn default() -> Self {
+        Self {
+            batch_size: default_batch_size(),
+            repeat_penalty: default_repeat_penalty(),
+            temperature: default_temperature(),
+            top_k: default_top_k(),
+            top_p: default_top_p(),
+            mirostat: 0,
+            mirostat_tau: default_mirostat_tau(),
+            mirostat_eta: default_mirostat_eta(),
+        }
+    }
 }
 
 pub struct Config {
     pub listen_addr: std::net::IpAddr,
     pub listen_port: u16,
     pub db_url: String,
+    pub database: DatabaseConfig,
     pub jwt_secret: String,
     pub llms: HashMap<String, LlmConfig>,
     pub stable_diffusion: Vec<StableDiffusionConfig>,
+    pub rate_limits: RateLimitConfig,
+    pub login_lockout: LoginLockoutConfig,
+    pub request_limits: RequestLimitsConfig,
+    pub metrics: MetricsConfig,
+    pub avatar: AvatarConfig,
+    pub image_share: ShareConfig,
+    pub chat_share: ShareConfig,
+    pub cors: CorsConfig,
+    pub image_metadata: ImageMetadataConfig,
+    pub image_cancel: ImageCancelConfig,
+    pub passwords: PasswordConfig,
+    pub webhooks: WebhookConfig,
+    pub body_limits: BodyLimitConfig,
+    pub moderation: ModerationConfig,
+    pub idempotency_keys: IdempotencyConfig,
 }
 
 impl Config {
@@ -125,36 +426,558 @@ impl Config {
             .unwrap_or(config.listen_port);
 
         let db_url = env::var("AIRTIFEX_DB_URL").ok().unwrap_or(config.db_url);
+        let mut database = config.database;
+        if let Ok(data_dir) = env::var("AIRTIFEX_DATA_DIR") {
+            database.data_dir = Some(data_dir.into());
+        }
 
         let jwt_secret = env::var("AIRTIFEX_JWT_SECRET")
             .ok()
             .unwrap_or(config.jwt_secret);
 
+        let inference_defaults = config.inference_defaults;
         let llms = config
             .llms
             .into_iter()
             .enumerate()
-            .map(|(i, cfg)| {
+            .map(|(i, mut cfg)| {
                 let name = cfg
                     .model_path
                     .file_prefix()
                     .map(|f| f.to_string_lossy().to_string())
                     .unwrap_or_else(|| format!("llm-model-{i}"));
-                (name, cfg)
+                cfg.defaults = inference_defaults.clone();
+                cfg.validate(&name)?;
+                Ok((name, cfg))
             })
-            .collect();
+            .collect::<Result<_>>()?;
+
+        for sd in &config.stable_diffusion {
+            let weights = [
+                &sd.clip_weights_path,
+                &sd.vae_weights_path,
+                &sd.unet_weights_path,
+                &sd.vocab_file,
+            ];
+            if matches!(sd.backend, ImageBackendConfig::Local)
+                && weights.iter().any(|path| path.as_os_str().is_empty())
+            {
+                return Err(Error::InvalidConfig(format!(
+                    "image model {} needs the paths of its weights and vocabulary",
+                    sd.model_name()
+                )));
+            }
+            if [sd.max_width, sd.max_height]
+                .iter()
+                .flatten()
+                .any(|max| *max < 1)
+            {
+                return Err(Error::InvalidConfig(format!(
+                    "max_width and max_height of image model {} must be at least 1",
+                    sd.model_name()
+                )));
+            }
+            if sd.queue_capacity < 1 {
+                return Err(Error::InvalidConfig(format!(
+                    "queue_capacity of image model {} must be at least 1",
+                    sd.model_name()
+                )));
+            }
+            if let Some(sampler) = sd.samplers.iter().find(|s| !SAMPLERS.contains(&s.as_str())) {
+                return Err(Error::InvalidConfig(format!(
+                    "sampler {sampler} of image model {} isn't one of {}",
+                    sd.model_name(),
+                    SAMPLERS.join(", ")
+                )));
+            }
+        }
+
+        if config.passwords.hash_iterations == 0 {
+            return Err(Error::InvalidConfig(
+                "password hash iterations must be at least 1".into(),
+            ));
+        }
+        if config.idempotency_keys.ttl_secs <= 0 {
+            return Err(Error::InvalidConfig(
+                "idempotency key ttl_secs must be at least 1".into(),
+            ));
+        }
 
         Ok(Self {
             listen_addr,
             listen_port,
             db_url,
+            database,
             jwt_secret,
             llms,
             stable_diffusion: config.stable_diffusion,
+            rate_limits: config.rate_limits,
+            login_lockout: config.login_lockout,
+            request_limits: config.request_limits,
+            metrics: config.metrics,
+            avatar: config.avatar,
+            image_share: config.image_share,
+            chat_share: config.chat_share,
+            cors: config.cors,
+            image_metadata: config.image_metadata,
+            image_cancel: config.image_cancel,
+            passwords: config.passwords,
+            webhooks: config.webhooks,
+            body_limits: config.body_limits,
+            moderation: config.moderation,
+            idempotency_keys: config.idempotency_keys,
         })
     }
 }
 
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct RateLimit {
+    /// Maximum number of requests a client can make at once.
+    pub burst: u32,
+    /// Number of requests a client regains every second.
+    pub per_second: f64,
+}
+
+/// Limits of requests per client for each group of routes, groups without a limit are unlimited.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+    /// Use the first address of the `X-Forwarded-For` header to identify unauthenticated clients.
+    /// Only enable this when running behind a reverse proxy that sets the header.
+    #[serde(default = "off")]
+    pub trust_forwarded_for: bool,
+    pub chat: Option<RateLimit>,
+    pub image: Option<RateLimit>,
+    pub users: Option<RateLimit>,
+}
+
+/// Failed logins that lock an account or a client address, see [`crate::login_lockout`].
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LoginLockoutConfig {
+    /// Failed logins of an account that lock it, `0` never locks accounts.
+    pub max_account_failures: u32,
+    /// Failed logins from a client address that lock it, `0` never locks addresses.
+    pub max_ip_failures: u32,
+    /// Wait after the first failed login in seconds, it doubles with every further failure.
+    pub backoff_secs: u64,
+    /// Duration of a lockout in seconds. Failures are forgotten once this long passed since the
+    /// last one.
+    pub lockout_secs: u64,
+}
+
+impl LoginLockoutConfig {
+    pub fn lockout(&self) -> Duration {
+        Duration::from_secs(self.lockout_secs)
+    }
+
+    /// Wait after the `failures`th failed login in a row, never longer than a lockout.
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let factor = 1u64 << failures.saturating_sub(1).min(32);
+        Duration::from_secs(
+            self.backoff_secs
+                .saturating_mul(factor)
+                .min(self.lockout_secs),
+        )
+    }
+}
+
+impl Default for LoginLockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_account_failures: 5,
+            max_ip_failures: 20,
+            backoff_secs: 1,
+            lockout_secs: 900,
+        }
+    }
+}
+
+/// Maximum size of request bodies in bytes, larger requests are rejected with
+/// `413 Payload Too Large`. Groups without a limit use `max_request_body_bytes`.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BodyLimitConfig {
+    pub max_request_body_bytes: usize,
+    pub chat: Option<usize>,
+    pub image: Option<usize>,
+    pub users: Option<usize>,
+}
+
+impl BodyLimitConfig {
+    pub fn max_for(&self, group: RouteGroup) -> usize {
+        let limit = match group {
+            RouteGroup::Chat => self.chat,
+            RouteGroup::Image => self.image,
+            RouteGroup::Users => self.users,
+        };
+        limit.unwrap_or(self.max_request_body_bytes)
+    }
+}
+
+impl Default for BodyLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_request_body_bytes: 8 * 1000 * 1000,
+            chat: None,
+            image: None,
+            users: None,
+        }
+    }
+}
+
+/// Where the unauthenticated `/metrics` endpoint is served.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct MetricsConfig {
+    /// Serve the metrics on a separate port instead of the API port, for example to keep them
+    /// reachable only from an internal network.
+    pub listen_port: Option<u16>,
+    /// Address of the separate metrics server, defaults to the API address.
+    pub listen_addr: Option<std::net::IpAddr>,
+}
+
+/// Limits of the avatars uploaded by users.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AvatarConfig {
+    /// Uploads larger than this many bytes are rejected.
+    pub max_upload_size: usize,
+    /// Avatars are cropped to a square and scaled to this width and height.
+    pub size: i64,
+}
+
+impl Default for AvatarConfig {
+    fn default() -> Self {
+        Self {
+            max_upload_size: 2 * 1000 * 1000,
+            size: 128,
+        }
+    }
+}
+
+/// Public links to image samples or chat transcripts.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ShareConfig {
+    /// Number of seconds a share link stays valid.
+    pub expiry: i64,
+    /// Address the public links point to, defaults to the listen address of the API.
+    pub base_url: Option<String>,
+}
+
+impl Default for ShareConfig {
+    fn default() -> Self {
+        Self {
+            expiry: 24 * 3600,
+            base_url: None,
+        }
+    }
+}
+
+/// Keys clients send with generation requests so that a retried request doesn't generate twice.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IdempotencyConfig {
+    /// Number of seconds a retry with the same key gets the result of the first request.
+    pub ttl_secs: i64,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            ttl_secs: 24 * 3600,
+        }
+    }
+}
+
+/// Generation parameters stored in the generated images.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ImageMetadataConfig {
+    /// Embed the prompt, seed, model and other parameters in the PNG of every new sample.
+    pub embed: bool,
+}
+
+impl Default for ImageMetadataConfig {
+    fn default() -> Self {
+        Self { embed: true }
+    }
+}
+
+/// Cancelling image generations that nobody follows anymore.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ImageCancelConfig {
+    /// Cancel a generation once the last client following its progress disconnects.
+    /// Generations that were never followed keep running.
+    pub on_disconnect: bool,
+    /// Seconds a client has to reconnect before the generation is cancelled.
+    pub grace_period_secs: u64,
+}
+
+impl ImageCancelConfig {
+    /// Grace period of disconnected clients, `None` when generations aren't cancelled.
+    pub fn grace_period(&self) -> Option<std::time::Duration> {
+        self.on_disconnect
+            .then(|| std::time::Duration::from_secs(self.grace_period_secs))
+    }
+}
+
+impl Default for ImageCancelConfig {
+    fn default() -> Self {
+        Self {
+            on_disconnect: true,
+            grace_period_secs: 10,
+        }
+    }
+}
+
+/// Delivery of the notifications users registered webhooks for.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Maximum number of webhooks of a user.
+    pub max_per_user: usize,
+    /// Attempts to deliver an event before giving up, the first one included.
+    pub max_attempts: u32,
+    /// Seconds to wait before the first retry, the wait doubles with every further retry.
+    pub retry_delay_secs: u64,
+    /// Seconds a webhook has to respond to a delivery.
+    pub timeout_secs: u64,
+    /// Allow webhooks on loopback, private and link-local addresses, for example when the
+    /// receiver runs next to the server.
+    pub allow_private_addresses: bool,
+}
+
+impl WebhookConfig {
+    /// Wait before the retry following the failed `attempt`, starting at 1.
+    pub fn retry_delay(&self, attempt: u32) -> std::time::Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        std::time::Duration::from_secs(self.retry_delay_secs.saturating_mul(factor))
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            max_per_user: 10,
+            max_attempts: 3,
+            retry_delay_secs: 5,
+            timeout_secs: 10,
+            allow_private_addresses: false,
+        }
+    }
+}
+
+/// Blocklist of the prompts sent to the models, prompts that contain a keyword or match a pattern
+/// are refused. Nothing is blocked without entries.
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ModerationConfig {
+    /// Words or phrases that are blocked anywhere in a text, ignoring case.
+    pub keywords: Vec<String>,
+    /// Regular expressions that block the texts they match, ignoring case.
+    pub patterns: Vec<String>,
+    /// Also checks the answers of the language models while they are generated, a blocked answer
+    /// ends with the `moderated` stop reason.
+    pub check_output: bool,
+}
+
+/// Location and connection settings of the sqlite database, ignored with PostgreSQL.
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    /// Directory that a relative sqlite path of `db_url` is resolved against, it is created
+    /// together with the database if missing. Without it the path is relative to the working
+    /// directory.
+    pub data_dir: Option<PathBuf>,
+    pub pragmas: SqlitePragmas,
+}
+
+/// Pragmas set on every sqlite connection.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SqlitePragmas {
+    /// WAL lets readers continue while chat entries or images are saved in the background.
+    pub journal_mode: JournalMode,
+    /// Milliseconds a connection waits for a lock held by another connection before failing with
+    /// `database is locked`.
+    pub busy_timeout_ms: u64,
+    pub synchronous: Synchronous,
+}
+
+impl Default for SqlitePragmas {
+    fn default() -> Self {
+        Self {
+            journal_mode: JournalMode::Wal,
+            busy_timeout_ms: 5000,
+            // durable enough in WAL mode, only the last commits can be lost on power loss
+            synchronous: Synchronous::Normal,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+/// Requirements of new passwords and the cost of their hashes.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PasswordConfig {
+    /// Minimum length of a password in characters.
+    pub min_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    /// Require a character that is neither a letter nor a digit.
+    pub require_symbol: bool,
+    /// PBKDF2 iterations of new password hashes. Hashes with fewer iterations are upgraded when
+    /// their user logs in.
+    pub hash_iterations: u32,
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            hash_iterations: 600_000,
+        }
+    }
+}
+
+/// Cross-origin requests to the API, needed when the web app is hosted on another origin. Without
+/// allowed origins only same-origin requests are possible.
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API like `https://airtifex.example.com`, `*` allows any origin.
+    pub allowed_origins: Vec<String>,
+    /// Allowed request methods, defaults to the methods used by the API.
+    pub allowed_methods: Vec<String>,
+    /// Allowed request headers, defaults to the headers used by the web app.
+    pub allowed_headers: Vec<String>,
+    /// Allow requests with cookies and `Authorization` headers, can't be combined with `*`.
+    pub allow_credentials: bool,
+    /// Number of seconds browsers may cache the response to a preflight request.
+    pub max_age: Option<u64>,
+}
+
+/// Inclusive range of values accepted for a request parameter.
+#[derive(Clone, Copy, Deserialize, Serialize)]
+pub struct Bounds<T> {
+    pub min: T,
+    pub max: T,
+}
+
+impl<T> Bounds<T> {
+    pub const fn new(min: T, max: T) -> Self {
+        Self { min, max }
+    }
+}
+
+/// Bounds of the parameters accepted by the generation endpoints, requests outside of them are
+/// rejected.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct RequestLimitsConfig {
+    #[serde(default)]
+    pub image: ImageRequestLimits,
+    #[serde(default)]
+    pub inference: InferenceRequestLimits,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ImageRequestLimits {
+    pub width: Bounds<i64>,
+    pub height: Bounds<i64>,
+    /// Width and height have to be a multiple of this, most diffusion models only work with
+    /// dimensions divisible by 8.
+    pub dimension_multiple: i64,
+    pub n_steps: Bounds<usize>,
+    pub num_samples: Bounds<i64>,
+    pub guidance_scale: Bounds<f64>,
+    pub strength: Bounds<f64>,
+    /// Steps between previews, every preview adds a decoding of the latents to the generation.
+    pub preview_every: Bounds<usize>,
+    /// Number of variations generated from an image at once.
+    pub variations: Bounds<i64>,
+    /// Maximum length of the prompt in characters.
+    pub max_prompt_length: usize,
+}
+
+impl Default for ImageRequestLimits {
+    fn default() -> Self {
+        Self {
+            width: Bounds::new(64, 2048),
+            height: Bounds::new(64, 2048),
+            dimension_multiple: 8,
+            n_steps: Bounds::new(1, 420),
+            num_samples: Bounds::new(1, 16),
+            guidance_scale: Bounds::new(0.0, 20.0),
+            strength: Bounds::new(0.0, 1.0),
+            preview_every: Bounds::new(1, 420),
+            variations: Bounds::new(1, 8),
+            max_prompt_length: 4096,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct InferenceRequestLimits {
+    pub num_predict: Bounds<usize>,
+    pub n_batch: Bounds<usize>,
+    pub top_k: Bounds<usize>,
+    pub top_p: Bounds<f32>,
+    pub repeat_penalty: Bounds<f32>,
+    pub temp: Bounds<f32>,
+    pub mirostat: Bounds<u8>,
+    pub mirostat_tau: Bounds<f32>,
+    pub mirostat_eta: Bounds<f32>,
+    /// Maximum length of prompts and system prompts in characters.
+    pub max_prompt_length: usize,
+    /// Maximum number of prompts of a batch request.
+    pub max_batch_size: usize,
+}
+
+impl Default for InferenceRequestLimits {
+    fn default() -> Self {
+        Self {
+            num_predict: Bounds::new(1, 4096),
+            n_batch: Bounds::new(1, 512),
+            top_k: Bounds::new(1, 1000),
+            top_p: Bounds::new(0.0, 1.0),
+            repeat_penalty: Bounds::new(0.0, 10.0),
+            temp: Bounds::new(0.0, 10.0),
+            mirostat: Bounds::new(0, 2),
+            mirostat_tau: Bounds::new(0.0, 20.0),
+            mirostat_eta: Bounds::new(0.0, 1.0),
+            max_prompt_length: 32768,
+            max_batch_size: 100,
+        }
+    }
+}
+
 fn default_is_cpu() -> bool {
     true
 }
@@ -194,14 +1017,44 @@ fn on() -> bool {
     true
 }
 
+fn default_remote_timeout_secs() -> u64 {
+    30
+}
+
+/// Where the samples of an image model are generated.
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageBackendConfig {
+    /// Stable Diffusion with the weights of the model loaded by this server.
+    #[default]
+    Local,
+    /// A diffusion server the requests are forwarded to over HTTP.
+    Remote {
+        /// Base URL of the server, requests are posted to `<url>/generate`.
+        url: String,
+        /// Seconds to wait for the server to accept a request.
+        #[serde(default = "default_remote_timeout_secs")]
+        timeout_secs: u64,
+    },
+    /// Solid color samples that only depend on the seed, for development without weights.
+    Mock,
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub struct StableDiffusionConfig {
     pub name: Option<String>,
     pub model_description: Option<String>,
     pub version: StableDiffusionVersion,
+    #[serde(default)]
+    pub backend: ImageBackendConfig,
+    // the weights are only needed by the local backend
+    #[serde(default)]
     pub clip_weights_path: PathBuf,
+    #[serde(default)]
     pub vae_weights_path: PathBuf,
+    #[serde(default)]
     pub unet_weights_path: PathBuf,
+    #[serde(default)]
     pub vocab_file: PathBuf,
     #[serde(default = "default_is_cpu")]
     pub clip_cpu: bool,
@@ -211,6 +1064,10 @@ pub struct StableDiffusionConfig {
     pub unet_cpu: bool,
     #[serde(default = "default_max_image_gen_sessions")]
     pub max_image_gen_sessions: usize,
+    #[serde(default = "default_queue_capacity")]
+    /// Number of requests that wait for a free session, further requests are refused until one
+    /// of them is started.
+    pub queue_capacity: usize,
     #[serde(default = "default_max_timesteps")]
     pub max_timesteps: usize,
     #[serde(default = "default_max_guidance_scale")]
@@ -222,9 +1079,23 @@ pub struct StableDiffusionConfig {
     pub feature_text_to_image: bool,
     #[serde(default = "on")]
     pub feature_image_to_image: bool,
+    /// Largest width of the images of the model, only the request limits apply when not set.
+    pub max_width: Option<i64>,
+    /// Largest height of the images of the model, only the request limits apply when not set.
+    pub max_height: Option<i64>,
+    #[serde(default)]
+    /// Samplers requests can choose, all of them when empty.
+    pub samplers: Vec<String>,
 }
 
 impl StableDiffusionConfig {
+    /// Name the model is registered with.
+    pub fn model_name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("stable-diffusion-{}", self.version.as_ref()))
+    }
+
     pub fn features(&self) -> ImageModelFeatures {
         ImageModelFeatures {
             inpaint: self.feature_inpaint,
@@ -232,4 +1103,146 @@ impl StableDiffusionConfig {
             image_to_image: self.feature_image_to_image,
         }
     }
+
+    pub fn capabilities(&self) -> ImageModelCapabilities {
+        ImageModelCapabilities {
+            max_width: self.max_width,
+            max_height: self.max_height,
+            samplers: self.samplers.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Reads a config with the base settings and `yaml` appended, the weights of the models are
+    /// expected at `llama.bin` in `dir`.
+    fn read(dir: &std::path::Path, yaml: &str) -> Result<Config> {
+        std::fs::write(dir.join("llama.bin"), b"").expect("weights are written");
+        let mut file = tempfile::NamedTempFile::new().expect("temporary config is created");
+        write!(
+            file,
+            "listen_addr: 127.0.0.1\nlisten_port: 6901\ndb_url: \"sqlite::memory:\"\njwt_secret: secret\n{}",
+            yaml.replace("{DIR}", &dir.display().to_string())
+        )
+        .expect("temporary config is written");
+        Config::read(file.path())
+    }
+
+    #[test]
+    fn models_override_the_global_inference_defaults() {
+        let dir = tempfile::tempdir().expect("temporary directory is created");
+        let config = read(
+            dir.path(),
+            r#"
+inference_defaults:
+  temperature: 0.5
+  top_k: 20
+  batch_size: 4
+llms:
+  - type: llama
+    model_path: "{DIR}/llama.bin"
+    temperature: 0.9
+    top_p: 0.5
+"#,
+        )
+        .expect("config is valid");
+        let llm = &config.llms["llama"];
+
+        // set by the model
+        assert_eq!(llm.temperature(), 0.9);
+        assert_eq!(llm.top_p(), 0.5);
+        // set globally only
+        assert_eq!(llm.top_k(), 20);
+        assert_eq!(llm.batch_size(), 4);
+        // set nowhere
+        assert_eq!(llm.repeat_penalty(), default_repeat_penalty());
+        assert_eq!(llm.mirostat(), 0);
+        assert_eq!(llm.mirostat_tau(), default_mirostat_tau());
+        assert_eq!(llm.mirostat_eta(), default_mirostat_eta());
+    }
+
+    /// Reason the model with the weights at `llama.bin` in `dir` and the options in `yaml` is
+    /// rejected for, `None` if it is valid.
+    fn rejection(dir: &std::path::Path, yaml: &str) -> Option<String> {
+        std::fs::write(dir.join("llama.bin"), b"").expect("weights are written");
+        let yaml = format!(
+            "type: llama\nmodel_path: \"{}\"\n{yaml}",
+            dir.join("llama.bin").display()
+        );
+        let llm: LlmConfig = serde_yaml::from_str(&yaml).expect("model config parses");
+        llm.validate("llama").err().map(|e| e.to_string())
+    }
+
+    #[test]
+    fn invalid_models_are_rejected() {
+        let dir = tempfile::tempdir().expect("temporary directory is created");
+        let dir = dir.path();
+        assert_eq!(rejection(dir, ""), None);
+
+        for field in [
+            "num_ctx_tokens",
+            "num_threads",
+            "batch_size",
+            "max_inference_sessions",
+        ] {
+            assert_eq!(
+                rejection(dir, &format!("{field}: 0")),
+                Some(format!(
+                    "invalid configuration - {field} of model llama must be at least 1"
+                ))
+            );
+        }
+
+        for top_p in ["0.0", "1.5", "-0.1", ".nan"] {
+            assert_eq!(
+                rejection(dir, &format!("top_p: {top_p}")),
+                Some(
+                    "invalid configuration - top_p of model llama must be larger than 0 and at \
+                     most 1"
+                        .to_string()
+                ),
+                "top_p {top_p}"
+            );
+        }
+        assert_eq!(rejection(dir, "top_p: 1.0"), None);
+
+        // mirostat isn't supported by the backend, 1 and 2 aren't accepted either
+        for mirostat in [1, 2, 3] {
+            let rejection = rejection(dir, &format!("mirostat: {mirostat}"));
+            assert!(
+                rejection.is_some_and(|r| r.contains("mirostat of model llama must be 0")),
+                "mirostat {mirostat}"
+            );
+        }
+        assert_eq!(rejection(dir, "mirostat: 0"), None);
+    }
+
+    #[test]
+    fn missing_weights_are_rejected() {
+        let dir = tempfile::tempdir().expect("temporary directory is created");
+        let missing = dir.path().join("missing.bin");
+        let llm: LlmConfig = serde_yaml::from_str(&format!(
+            "type: llama\nmodel_path: \"{}\"",
+            missing.display()
+        ))
+        .expect("model config parses");
+        assert_eq!(
+            llm.validate("llama").unwrap_err().to_string(),
+            format!(
+                "invalid configuration - weights of model llama don't exist at {}",
+                missing.display()
+            )
+        );
+
+        // weights that are downloaded don't have to exist yet
+        let llm = LlmConfig {
+            model_url: Some("https://example.com/llama.bin".into()),
+            ..llm
+        };
+        assert!(llm.validate("llama").is_ok());
+    }
 }
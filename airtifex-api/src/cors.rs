@@ -0,0 +1,177 @@
+//! CORS headers of the API, configured by [`CorsConfig`]. Requests from other origins are
+//! rejected by browsers unless the origin is allowed in the configuration.
+
+use crate::{config::CorsConfig, Error, Result};
+
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+const DEFAULT_METHODS: [Method; 5] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+];
+
+/// Headers sent by the web app, `Last-Event-ID` is sent when resuming a response stream.
+const DEFAULT_HEADERS: [HeaderName; 4] = [
+    header::ACCEPT,
+    header::AUTHORIZATION,
+    header::CONTENT_TYPE,
+    HeaderName::from_static("last-event-id"),
+];
+
+/// Builds the CORS layer of the API, there is none when no origin is allowed so that only
+/// same-origin requests work.
+pub fn cors_layer(config: &CorsConfig) -> Result<Option<CorsLayer>> {
+    if config.allowed_origins.is_empty() {
+        return Ok(None);
+    }
+
+    let any_origin = config.allowed_origins.iter().any(|o| o == "*");
+    if any_origin && config.allow_credentials {
+        return Err(Error::InvalidConfig(
+            "CORS credentials can't be allowed for any origin".into(),
+        ));
+    }
+    let allow_origin = if any_origin {
+        AllowOrigin::from(Any)
+    } else {
+        let origins = config
+            .allowed_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin.trim_end_matches('/'))
+                    .map_err(|_| Error::InvalidConfig(format!("invalid CORS origin `{origin}`")))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    let methods = if config.allowed_methods.is_empty() {
+        DEFAULT_METHODS.to_vec()
+    } else {
+        config
+            .allowed_methods
+            .iter()
+            .map(|method| {
+                method
+                    .to_uppercase()
+                    .parse::<Method>()
+                    .map_err(|_| Error::InvalidConfig(format!("invalid CORS method `{method}`")))
+            })
+            .collect::<Result<_>>()?
+    };
+
+    let headers = if config.allowed_headers.is_empty() {
+        DEFAULT_HEADERS.to_vec()
+    } else {
+        config
+            .allowed_headers
+            .iter()
+            .map(|name| {
+                name.parse::<HeaderName>()
+                    .map_err(|_| Error::InvalidConfig(format!("invalid CORS header `{name}`")))
+            })
+            .collect::<Result<_>>()?
+    };
+
+    let mut layer = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(config.allow_credentials)
+        // lets clients honor rate limits
+        .expose_headers([header::RETRY_AFTER]);
+    if let Some(max_age) = config.max_age {
+        layer = layer.max_age(Duration::from_secs(max_age));
+    }
+    Ok(Some(layer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::{
+        body::Body,
+        http::{Request, Response, StatusCode},
+        routing, Router,
+    };
+    use tower::ServiceExt;
+
+    async fn preflight(origin: &str) -> Response<axum::body::BoxBody> {
+        let config = CorsConfig {
+            allowed_origins: vec!["https://airtifex.example.com/".into()],
+            allow_credentials: true,
+            max_age: Some(600),
+            ..Default::default()
+        };
+        let layer = cors_layer(&config)
+            .expect("config is valid")
+            .expect("origins are allowed");
+        let router = Router::new()
+            .route("/api/v1/users", routing::get(|| async {}))
+            .layer(layer);
+        router
+            .oneshot(
+                Request::builder()
+                    .method(Method::OPTIONS)
+                    .uri("/api/v1/users")
+                    .header(header::ORIGIN, origin)
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                    .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization")
+                    .body(Body::empty())
+                    .expect("request is valid"),
+            )
+            .await
+            .expect("router doesn't fail")
+    }
+
+    #[tokio::test]
+    async fn preflight_of_an_allowed_origin_is_answered() {
+        let response = preflight("https://airtifex.example.com").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://airtifex.example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+        let methods = headers[header::ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .expect("methods are text");
+        assert!(methods.contains("POST"), "{methods}");
+        let allowed_headers = headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .expect("headers are text");
+        assert!(
+            allowed_headers.contains("authorization"),
+            "{allowed_headers}"
+        );
+    }
+
+    #[tokio::test]
+    async fn preflight_of_another_origin_isnt_allowed() {
+        let response = preflight("https://evil.example.com").await;
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[test]
+    fn credentials_arent_allowed_for_any_origin() {
+        let config = CorsConfig {
+            allowed_origins: vec!["*".into()],
+            allow_credentials: true,
+            ..Default::default()
+        };
+        assert!(cors_layer(&config).is_err());
+        assert!(cors_layer(&CorsConfig::default())
+            .expect("config is valid")
+            .is_none());
+    }
+}
@@ -4,6 +4,8 @@ use thiserror::Error;
 pub enum Error {
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
+    #[error("failed to create the data directory - {0}")]
+    DataDirCreateFailed(std::io::Error),
     #[error("Failed to initialize the server: {0}")]
     ServerInitializationError(std::io::Error),
     #[error("Error: {0}")]
@@ -34,8 +36,29 @@ pub enum Error {
     ConfigReadFailed(std::io::Error),
     #[error("failed to deserialize configuration file as yaml - {0}")]
     ConfigDeserializeFailed(serde_yaml::Error),
-    #[error("Failed to send token to receiver - {0}")]
-    InferenceSend(flume::SendError<airtifex_core::llm::ChatStreamResult>),
+    #[error("invalid configuration - {0}")]
+    InvalidConfig(String),
     #[error(transparent)]
     InferenceError(#[from] llm::InferenceError),
+    #[error("failed to find model {0}")]
+    ModelNotFound(String),
+    #[error("Failed to queue inference request - {0}")]
+    InferenceRequestSend(#[from] crate::queue::QueueError),
+    #[error(transparent)]
+    ValidationError(#[from] crate::validation::ValidationError),
+    #[error("the content was blocked - {0}")]
+    ContentBlocked(String),
+}
+
+impl Error {
+    /// Code of the failure response reporting the error.
+    pub fn code(&self) -> airtifex_core::api_response::ErrorCode {
+        use airtifex_core::api_response::ErrorCode;
+        match self {
+            Error::ModelNotFound(_) | Error::InferenceRequestSend(_) => ErrorCode::ModelUnavailable,
+            Error::ValidationError(_) => ErrorCode::ValidationFailed,
+            Error::ContentBlocked(_) => ErrorCode::ContentBlocked,
+            _ => ErrorCode::Internal,
+        }
+    }
 }
@@ -0,0 +1,209 @@
+use crate::gen::image::{
+    backend::{GeneratedSample, ImageBackend, SampleStream},
+    metadata::{write_chunk, PNG_SIGNATURE},
+    sd::sample_seed,
+    GenerateImageRequest,
+};
+use airtifex_core::image::ImageProgress;
+
+use futures_util::{stream, StreamExt};
+
+/// Largest block of a zlib stream that is stored without compression.
+const MAX_STORED_BLOCK: usize = 65535;
+
+/// Generates samples without a model. Every sample is a single color picked by its seed, so the
+/// same request always results in the same bytes.
+pub struct MockBackend;
+
+impl ImageBackend for MockBackend {
+    fn generate(&self, mut request: GenerateImageRequest) -> SampleStream {
+        let data = request.data_mut();
+        let (width, height) = (data.width as u32, data.height as u32);
+        let (seed, num_samples, n_steps) = (data.seed, data.num_samples, data.n_steps);
+        let progress = data.progress.take();
+
+        stream::iter(0..num_samples)
+            .map(move |idx| {
+                let n_sample = idx + 1;
+                if let Some(progress) = &progress {
+                    let step = ImageProgress {
+                        n_sample,
+                        num_samples,
+                        step: n_steps,
+                        n_steps,
+                    };
+                    progress.send(step, None);
+                }
+                let seed = sample_seed(seed, idx);
+                Ok(GeneratedSample {
+                    n_sample: n_sample as i32,
+                    seed,
+                    data: solid_png(width, height, seed_color(seed)),
+                    thumbnail: None,
+                })
+            })
+            .boxed()
+    }
+}
+
+fn seed_color(seed: i64) -> [u8; 3] {
+    let [r, g, b, ..] = (seed as u64)
+        .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        .to_be_bytes();
+    [r, g, b]
+}
+
+/// Encodes an RGB image of a single `color` as PNG, the pixels are stored uncompressed.
+fn solid_png(width: u32, height: u32, color: [u8; 3]) -> Vec<u8> {
+    // every row starts with its filter type, none
+    let mut row = vec![0];
+    (0..width).for_each(|_| row.extend_from_slice(&color));
+    let pixels = row.repeat(height as usize);
+
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = pixels.chunks(MAX_STORED_BLOCK).peekable();
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&pixels).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bit RGB, default compression and filters, not interlaced
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (a, b) = data.iter().fold((1, 0), |(a, b), &byte| {
+        let a = (a + byte as u32) % MOD_ADLER;
+        (a, (b + a) % MOD_ADLER)
+    });
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::image::BaseImageData;
+
+    fn request(width: i64, height: i64, seed: i64, num_samples: i64) -> GenerateImageRequest {
+        GenerateImageRequest::TextToImage(BaseImageData {
+            id: "image".to_string(),
+            prompt: "a lighthouse".to_string(),
+            width,
+            height,
+            n_steps: 20,
+            seed,
+            num_samples,
+            guidance_scale: 7.5,
+            preview_every: None,
+            progress: None,
+            queue_ticket: None,
+            reroll: None,
+            request_id: Default::default(),
+        })
+    }
+
+    async fn generate(request: GenerateImageRequest) -> Vec<GeneratedSample> {
+        MockBackend
+            .generate(request)
+            .map(|sample| sample.expect("mock backend doesn't fail"))
+            .collect()
+            .await
+    }
+
+    /// Type and data of the chunks of `png`.
+    fn chunks(png: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let mut rest = png.strip_prefix(&PNG_SIGNATURE).expect("sample is a PNG");
+        let mut chunks = vec![];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            chunks.push((&rest[4..8], &rest[8..8 + len]));
+            rest = &rest[12 + len..];
+        }
+        chunks
+    }
+
+    /// Pixels of a zlib stream made of stored blocks.
+    fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+        let mut rest = zlib.strip_prefix(&[0x78, 0x01]).expect("zlib header");
+        let mut pixels = vec![];
+        loop {
+            let is_final = rest[0] == 1;
+            let len = u16::from_le_bytes([rest[1], rest[2]]);
+            assert_eq!(u16::from_le_bytes([rest[3], rest[4]]), !len);
+            pixels.extend_from_slice(&rest[5..5 + len as usize]);
+            rest = &rest[5 + len as usize..];
+            if is_final {
+                break;
+            }
+        }
+        assert_eq!(rest, adler32(&pixels).to_be_bytes());
+        pixels
+    }
+
+    #[test]
+    fn adler32_of_known_data() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[tokio::test]
+    async fn samples_are_solid_images_of_their_seed() {
+        let samples = generate(request(2, 1, 1, 2)).await;
+        assert_eq!(samples.len(), 2);
+        assert_eq!((samples[0].n_sample, samples[0].seed), (1, 1));
+        assert_eq!((samples[1].n_sample, samples[1].seed), (2, 2));
+
+        let chunks = chunks(&samples[0].data);
+        let kinds: Vec<_> = chunks.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+        // a single row of two pixels colored by the first bytes of the scrambled seed
+        assert_eq!(
+            inflate_stored(chunks[1].1),
+            [0, 0x9e, 0x37, 0x79, 0x9e, 0x37, 0x79]
+        );
+        assert_ne!(samples[0].data, samples[1].data);
+    }
+
+    #[tokio::test]
+    async fn same_request_gives_the_same_bytes() {
+        let first = generate(request(64, 48, 1234, 3)).await;
+        let second = generate(request(64, 48, 1234, 3)).await;
+        let bytes = |samples: &[GeneratedSample]| -> Vec<Vec<u8>> {
+            samples.iter().map(|s| s.data.clone()).collect()
+        };
+        assert_eq!(bytes(&first), bytes(&second));
+
+        // the samples of a request are the ones of the following seeds
+        let shifted = generate(request(64, 48, 1235, 1)).await;
+        assert_eq!(shifted[0].data, first[1].data);
+    }
+
+    #[tokio::test]
+    async fn large_images_are_split_into_blocks() {
+        let samples = generate(request(256, 256, 7, 1)).await;
+        let chunks = chunks(&samples[0].data);
+        let pixels = inflate_stored(chunks[1].1);
+        let row = 1 + 256 * 3;
+        assert_eq!(pixels.len(), row * 256);
+        let color = seed_color(7);
+        for row in pixels.chunks(row) {
+            assert_eq!(row[0], 0);
+            assert!(row[1..].chunks(3).all(|pixel| pixel == color));
+        }
+    }
+}
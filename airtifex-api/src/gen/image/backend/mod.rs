@@ -0,0 +1,45 @@
+//! Backends generate the samples of image requests. Every model has its own backend picked in
+//! its configuration, the queue of the model and saving the samples work the same for all of
+//! them.
+
+pub mod mock;
+pub mod remote;
+
+use crate::{
+    config::{ImageBackendConfig, StableDiffusionConfig},
+    gen::image::{sd, GenerateImageRequest},
+};
+
+use futures_util::stream::BoxStream;
+use std::{sync::Arc, time::Duration};
+
+/// Sample of an image as PNG.
+#[derive(Debug)]
+pub struct GeneratedSample {
+    /// Starts at 1.
+    pub n_sample: i32,
+    pub seed: i64,
+    pub data: Vec<u8>,
+    /// Thumbnail of the sample, one is made from the sample when the backend has none.
+    pub thumbnail: Option<Vec<u8>>,
+}
+
+pub type SampleStream = BoxStream<'static, Result<GeneratedSample, String>>;
+
+pub trait ImageBackend: Send + Sync {
+    /// Generates the samples of `request` in order. The stream ends after the last sample or
+    /// the first error, the progress of the request is reported until it ends.
+    fn generate(&self, request: GenerateImageRequest) -> SampleStream;
+}
+
+/// Starts the backend configured for the model of `config`.
+pub fn from_config(config: &StableDiffusionConfig) -> Arc<dyn ImageBackend> {
+    match &config.backend {
+        ImageBackendConfig::Local => Arc::new(sd::LocalBackend::start(config.clone())),
+        ImageBackendConfig::Remote { url, timeout_secs } => Arc::new(remote::RemoteBackend::new(
+            url,
+            Duration::from_secs(*timeout_secs),
+        )),
+        ImageBackendConfig::Mock => Arc::new(mock::MockBackend),
+    }
+}
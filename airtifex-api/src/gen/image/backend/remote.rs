@@ -0,0 +1,199 @@
+//! Forwards image requests to a diffusion server over HTTP. The request is posted as JSON to
+//! `<url>/generate`, the server responds with one JSON object per line for every sample,
+//! `{"n_sample": 1, "seed": 42, "data": "<base64 encoded PNG>"}`, or `{"error": "..."}` when the
+//! generation fails.
+
+use crate::gen::image::{
+    backend::{GeneratedSample, ImageBackend, SampleStream},
+    progress::ProgressSender,
+    GenerateImageRequest,
+};
+use airtifex_core::image::ImageProgress;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::{stream, StreamExt};
+use hyper::{body::HttpBody, client::HttpConnector, header::CONTENT_TYPE, Body, Client, Request};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub struct RemoteBackend {
+    client: Client<HttpConnector>,
+    url: String,
+    /// Time to wait for the server to accept a request, generating the samples can take longer.
+    timeout: Duration,
+}
+
+impl RemoteBackend {
+    pub fn new(url: &str, timeout: Duration) -> Self {
+        Self {
+            client: Client::new(),
+            url: format!("{}/generate", url.trim_end_matches('/')),
+            timeout,
+        }
+    }
+
+    fn http_request(&self, request: &GenerateImageRequest) -> Result<Request<Body>, String> {
+        let body = serde_json::to_vec(&RemoteRequest::new(request)).map_err(|e| e.to_string())?;
+        Request::post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .map_err(|e| format!("invalid image server url - {e}"))
+    }
+}
+
+impl ImageBackend for RemoteBackend {
+    fn generate(&self, mut request: GenerateImageRequest) -> SampleStream {
+        let http_request = self.http_request(&request);
+        let data = request.data_mut();
+        let progress = data.progress.take();
+        let (num_samples, n_steps) = (data.num_samples, data.n_steps);
+        let (client, timeout) = (self.client.clone(), self.timeout);
+
+        stream::once(async move {
+            let response = tokio::time::timeout(timeout, client.request(http_request?))
+                .await
+                .map_err(|_| "the image server didn't respond in time".to_string())?
+                .map_err(|e| format!("failed to reach the image server - {e}"))?;
+            let status = response.status();
+            if !status.is_success() {
+                let body = hyper::body::to_bytes(response.into_body())
+                    .await
+                    .unwrap_or_default();
+                let body = String::from_utf8_lossy(&body);
+                return Err(format!("the image server responded with {status} - {body}"));
+            }
+            Ok(samples(
+                response.into_body(),
+                progress,
+                num_samples,
+                n_steps,
+            ))
+        })
+        .flat_map(|result| result.unwrap_or_else(|e| stream::iter([Err(e)]).boxed()))
+        .boxed()
+    }
+}
+
+#[derive(Serialize)]
+struct RemoteRequest<'a> {
+    prompt: &'a str,
+    width: i64,
+    height: i64,
+    n_steps: usize,
+    seed: i64,
+    num_samples: i64,
+    guidance_scale: f64,
+    /// Base64 encoded input image of image-to-image and inpaint requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_image: Option<String>,
+    /// Base64 encoded mask of inpaint requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mask: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    strength: Option<f64>,
+}
+
+impl<'a> RemoteRequest<'a> {
+    fn new(request: &'a GenerateImageRequest) -> Self {
+        let (input_image, mask, strength) = match request {
+            GenerateImageRequest::TextToImage(_) => (None, None, None),
+            GenerateImageRequest::ImageToImage(data) => {
+                (Some(&data.input_image), None, Some(data.strength))
+            }
+            GenerateImageRequest::Inpaint(data) => {
+                (Some(&data.input_image), Some(&data.mask), None)
+            }
+        };
+        let data = request.data();
+        Self {
+            prompt: &data.prompt,
+            width: data.width,
+            height: data.height,
+            n_steps: data.n_steps,
+            seed: data.seed,
+            num_samples: data.num_samples,
+            guidance_scale: data.guidance_scale,
+            input_image: input_image.map(|image| STANDARD.encode(image)),
+            mask: mask.map(|mask| STANDARD.encode(mask)),
+            strength,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RemoteEvent {
+    Sample {
+        n_sample: i32,
+        seed: i64,
+        data: String,
+    },
+    Error {
+        error: String,
+    },
+}
+
+/// Reads the samples from the lines of `body`, the progress is reported after every sample.
+fn samples(
+    body: Body,
+    progress: Option<ProgressSender>,
+    num_samples: i64,
+    n_steps: usize,
+) -> SampleStream {
+    stream::unfold(
+        Some((body, Vec::new(), progress)),
+        move |state| async move {
+            let (mut body, mut buffer, progress) = state?;
+            loop {
+                if let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                    let line = buffer.drain(..=end).collect::<Vec<_>>();
+                    if line.iter().all(u8::is_ascii_whitespace) {
+                        continue;
+                    }
+                    let sample = parse_sample(&line);
+                    if let (Ok(sample), Some(progress)) = (&sample, &progress) {
+                        let step = ImageProgress {
+                            n_sample: sample.n_sample as i64,
+                            num_samples,
+                            step: n_steps,
+                            n_steps,
+                        };
+                        progress.send(step, None);
+                    }
+                    let next = sample.is_ok().then_some((body, buffer, progress));
+                    return Some((sample, next));
+                }
+                match body.data().await {
+                    Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                    Some(Err(e)) => {
+                        let e = format!("lost the connection to the image server - {e}");
+                        return Some((Err(e), None));
+                    }
+                    None if buffer.iter().all(u8::is_ascii_whitespace) => return None,
+                    // the last line doesn't have to end with a line break
+                    None => buffer.push(b'\n'),
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+fn parse_sample(line: &[u8]) -> Result<GeneratedSample, String> {
+    match serde_json::from_slice(line) {
+        Ok(RemoteEvent::Sample {
+            n_sample,
+            seed,
+            data,
+        }) => Ok(GeneratedSample {
+            n_sample,
+            seed,
+            data: STANDARD
+                .decode(data)
+                .map_err(|e| format!("invalid sample from the image server - {e}"))?,
+            thumbnail: None,
+        }),
+        Ok(RemoteEvent::Error { error }) => Err(format!("the image server failed - {error}")),
+        Err(e) => Err(format!("invalid response from the image server - {e}")),
+    }
+}
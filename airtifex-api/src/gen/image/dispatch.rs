@@ -0,0 +1,332 @@
+//! Runs the queued requests of an image model on its backend and saves their samples.
+
+use crate::{
+    gen::image::{
+        backend::{GeneratedSample, ImageBackend, SampleStream},
+        metadata,
+        progress::Cancellation,
+        reroll::SampleReroll,
+        square_thumbnail, GenerateImageRequest,
+    },
+    id::Uuid,
+    models::{image::Image, image_sample::ImageSample},
+    queue::RunningSessions,
+    request_id,
+    webhook::Webhooks,
+    DbPool,
+};
+use airtifex_core::{
+    image::{ImageModelFeatures, ImageStatus},
+    webhook::{WebhookData, WebhookEvent},
+};
+
+use flume::Receiver;
+use futures_util::StreamExt;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tracing::Instrument;
+
+/// Size of the thumbnails made for backends that don't provide one.
+const THUMBNAIL_SIZE: i64 = 64;
+
+/// Starts the requests of a model in the order they were queued, at most `max_sessions` at once.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_model(
+    db: Arc<DbPool>,
+    backend: Arc<dyn ImageBackend>,
+    rx_request: Receiver<GenerateImageRequest>,
+    running: RunningSessions,
+    max_sessions: usize,
+    features: ImageModelFeatures,
+    embed_metadata: bool,
+    webhooks: Webhooks,
+) {
+    let sessions = Arc::new(Semaphore::new(max_sessions));
+    while let Ok(mut request) = rx_request.recv_async().await {
+        // the request keeps its place in the queue until a session is free
+        let Ok(session) = sessions.clone().acquire_owned().await else {
+            break;
+        };
+        request.take_queue_ticket();
+        if request.cancellation().is_some_and(|c| c.is_cancelled()) {
+            cancel(&db, request.id()).await;
+            continue;
+        }
+        running.set(max_sessions - sessions.available_permits());
+
+        let (db, backend, features) = (db.clone(), backend.clone(), features.clone());
+        let (sessions, running, webhooks) = (sessions.clone(), running.clone(), webhooks.clone());
+        let span = request_id::span(request.request_id());
+        let run = async move {
+            run_request(
+                &db,
+                backend.as_ref(),
+                request,
+                &features,
+                embed_metadata,
+                &webhooks,
+            )
+            .await;
+            drop(session);
+            running.set(max_sessions - sessions.available_permits());
+        };
+        tokio::spawn(run.instrument(span));
+    }
+}
+
+async fn run_request(
+    db: &DbPool,
+    backend: &dyn ImageBackend,
+    mut request: GenerateImageRequest,
+    features: &ImageModelFeatures,
+    embed_metadata: bool,
+    webhooks: &Webhooks,
+) {
+    if let Some(reroll) = request.take_reroll() {
+        reroll_sample(db, backend, request, features, embed_metadata, reroll).await;
+        return;
+    }
+    let id = request.id().to_string();
+    let Ok(image_id) = id.parse::<Uuid>() else {
+        log::error!("[{id}] invalid image id");
+        return;
+    };
+    update_status(db, &image_id, ImageStatus::Running, None).await;
+
+    let num_samples = request.data().num_samples;
+    let mut cancellation = request.cancellation();
+    let result = match check_feature(&request, features) {
+        Ok(()) => {
+            let samples = backend.generate(request);
+            // dropping the samples stops the backend
+            tokio::select! {
+                result = save_samples(db, &image_id, samples, num_samples, embed_metadata) => result,
+                _ = cancelled(&mut cancellation) => {
+                    cancel(db, &id).await;
+                    return;
+                }
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    let (event, status, error) = match result {
+        Ok(()) => {
+            log::debug!("[{id}] updating image status to done");
+            if let Err(e) = Image::finish(db, &image_id).await {
+                log::error!("[{id}] failed to update image status - {e}");
+            }
+            (WebhookEvent::ImageDone, ImageStatus::Done, None)
+        }
+        Err(e) => {
+            log::error!("[{id}] {e}");
+            update_status(db, &image_id, ImageStatus::Failed, Some(&e)).await;
+            (WebhookEvent::ImageFailed, ImageStatus::Failed, Some(e))
+        }
+    };
+
+    match Image::get_by_id(db, &image_id).await {
+        Ok(image) => webhooks.notify(
+            image.user_id,
+            event,
+            WebhookData::Image {
+                image_id: id,
+                status,
+                error,
+            },
+        ),
+        Err(e) => log::error!("[{id}] failed to notify webhooks - {e}"),
+    }
+}
+
+/// Generates sample `reroll.n_sample()` of a finished image again and replaces it, the other
+/// samples and the status of the image are left as they are.
+async fn reroll_sample(
+    db: &DbPool,
+    backend: &dyn ImageBackend,
+    request: GenerateImageRequest,
+    features: &ImageModelFeatures,
+    embed_metadata: bool,
+    reroll: SampleReroll,
+) {
+    let id = request.id().to_string();
+    let n_sample = reroll.n_sample();
+    let Ok(image_id) = id.parse::<Uuid>() else {
+        log::error!("[{id}] invalid image id");
+        return;
+    };
+
+    let result = match check_feature(&request, features) {
+        Ok(()) => match backend.generate(request).next().await {
+            Some(Ok(sample)) => {
+                let sample = GeneratedSample { n_sample, ..sample };
+                match prepare_sample(db, &image_id, sample, embed_metadata).await {
+                    Ok(sample) => match sample.replace(db).await {
+                        Ok(true) => Ok(sample.actual_seed),
+                        Ok(false) => Err("the sample no longer exists".to_string()),
+                        Err(e) => Err(e.to_string()),
+                    },
+                    Err(e) => Err(e),
+                }
+            }
+            Some(Err(e)) => Err(e),
+            None => Err("the generation ended without a sample".to_string()),
+        },
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(seed) => log::info!("[{id}][{n_sample}] generated the sample again with seed {seed}"),
+        Err(e) => log::error!("[{id}][{n_sample}] failed to generate the sample again - {e}"),
+    }
+    // the slot can be generated again once the sample is replaced
+    drop(reroll);
+}
+
+fn check_feature(
+    request: &GenerateImageRequest,
+    features: &ImageModelFeatures,
+) -> Result<(), String> {
+    match request {
+        GenerateImageRequest::TextToImage(_) if !features.text_to_image => {
+            Err("feature text-to-image is disabled for this model".into())
+        }
+        GenerateImageRequest::ImageToImage(_) if !features.image_to_image => {
+            Err("feature image-to-image is disabled for this model".into())
+        }
+        GenerateImageRequest::Inpaint(_) if !features.inpaint => {
+            Err("feature inpaint is disabled for this model".into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Saves the samples as they are generated, fails when the backend stops before the last one.
+async fn save_samples(
+    db: &DbPool,
+    image_id: &Uuid,
+    mut samples: SampleStream,
+    num_samples: i64,
+    embed_metadata: bool,
+) -> Result<(), String> {
+    let mut saved = 0;
+    while let Some(sample) = samples.next().await {
+        save_sample(db, image_id, sample?, embed_metadata).await?;
+        saved += 1;
+    }
+    if saved < num_samples {
+        return Err(format!(
+            "the generation ended after {saved} of {num_samples} samples"
+        ));
+    }
+    Ok(())
+}
+
+async fn save_sample(
+    db: &DbPool,
+    image_id: &Uuid,
+    sample: GeneratedSample,
+    embed_metadata: bool,
+) -> Result<(), String> {
+    let n_sample = sample.n_sample;
+    prepare_sample(db, image_id, sample, embed_metadata)
+        .await?
+        .create(db)
+        .await
+        .map_err(|e| format!("failed to save sample {n_sample} - {e}"))
+}
+
+/// Updates the thumbnail of the image from its first sample and embeds the metadata in the
+/// sample.
+async fn prepare_sample(
+    db: &DbPool,
+    image_id: &Uuid,
+    sample: GeneratedSample,
+    embed_metadata: bool,
+) -> Result<ImageSample, String> {
+    let GeneratedSample {
+        n_sample,
+        seed,
+        data,
+        thumbnail,
+    } = sample;
+    log::debug!("[{image_id}][{n_sample}] saving image to DB");
+
+    // the thumbnail of the image is made from its first sample
+    if n_sample == 1 {
+        let thumbnail = match thumbnail {
+            Some(thumbnail) => Ok(thumbnail),
+            None => {
+                let data = data.clone();
+                tokio::task::spawn_blocking(move || square_thumbnail(&data, THUMBNAIL_SIZE))
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|thumbnail| thumbnail.map_err(|e| e.to_string()))
+            }
+        };
+        let result = match thumbnail {
+            Ok(thumbnail) => Image::update_thumbnail(db, image_id, &thumbnail)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            log::error!("[{image_id}][{n_sample}] failed to update image thumbnail - {e}");
+        }
+    }
+
+    let data = if embed_metadata {
+        with_metadata(db, image_id, n_sample, seed, data).await
+    } else {
+        data
+    };
+    Ok(ImageSample::new(*image_id, n_sample, seed, data))
+}
+
+async fn cancelled(cancellation: &mut Option<Cancellation>) {
+    match cancellation {
+        Some(cancellation) => cancellation.cancelled().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn cancel(db: &DbPool, id: &str) {
+    log::info!("[{id}] generation cancelled");
+    match id.parse::<Uuid>() {
+        Ok(image_id) => update_status(db, &image_id, ImageStatus::Cancelled, None).await,
+        Err(_) => log::error!("[{id}] invalid image id"),
+    }
+}
+
+async fn update_status(db: &DbPool, id: &Uuid, status: ImageStatus, error: Option<&str>) {
+    if let Err(e) = Image::update_status(db, id, status, error).await {
+        log::error!(
+            "[{id}] failed to update image status to {} - {e}",
+            status.as_ref()
+        );
+    }
+}
+
+/// Embeds the generation parameters of the image in the PNG of a sample, the sample is saved
+/// without them when they can't be added.
+async fn with_metadata(
+    db: &DbPool,
+    image_id: &Uuid,
+    n_sample: i32,
+    seed: i64,
+    data: Vec<u8>,
+) -> Vec<u8> {
+    let result = Image::get_by_id(db, image_id)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|image| {
+            let parameters = metadata::generation_parameters(&image, seed);
+            metadata::embed_parameters(&data, &parameters).map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(with_metadata) => with_metadata,
+        Err(e) => {
+            log::error!("[{image_id}][{n_sample}] failed to embed image metadata - {e}");
+            data
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Contact sheets of the samples of an image. The samples are tiled into a single PNG on a white
+//! background, optionally with their seeds written below them in a small built-in pixel font.
+
+use crate::models::image_sample::ImageSample;
+
+use tch::{Device, Kind, Tensor};
+use thiserror::Error as ErrorType;
+
+/// Space around and between the tiles in pixels.
+const GAP: i64 = 8;
+/// Size of a pixel of the font in pixels of the grid.
+const FONT_SCALE: usize = 3;
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+/// Height of the strip below a tile the seed is written in.
+const LABEL_HEIGHT: usize = (GLYPH_HEIGHT + 2) * FONT_SCALE;
+
+#[derive(Debug, ErrorType)]
+pub enum GridError {
+    #[error("the image has no samples")]
+    Empty,
+    #[error("failed to decode sample {n} - {error}")]
+    Decode { n: i32, error: tch::TchError },
+    #[error("failed to resize sample {n} - {error}")]
+    Resize { n: i32, error: tch::TchError },
+    #[error("failed to encode the grid - {0}")]
+    Encode(tch::TchError),
+    #[error("failed to read the encoded grid - {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Number of columns and rows of a grid of `num_samples` tiles. Without `cols` the grid is as
+/// square as possible, more columns than samples are never used.
+pub fn grid_layout(num_samples: usize, cols: Option<usize>) -> (usize, usize) {
+    let num_samples = num_samples.max(1);
+    let cols = cols
+        .unwrap_or_else(|| (num_samples as f64).sqrt().ceil() as usize)
+        .clamp(1, num_samples);
+    (cols, num_samples.div_ceil(cols))
+}
+
+/// Tiles the samples in the order given into a PNG with `cols` columns, the size of the first
+/// sample is the size of every tile. This is CPU bound so it should run on a blocking thread.
+pub fn contact_sheet(
+    samples: &[ImageSample],
+    cols: usize,
+    with_seeds: bool,
+) -> Result<Vec<u8>, GridError> {
+    let first = samples.first().ok_or(GridError::Empty)?;
+    let (cols, rows) = grid_layout(samples.len(), Some(cols));
+    let decode = |sample: &ImageSample| {
+        tch::vision::image::load_from_memory(&sample.data)
+            .map_err(|error| GridError::Decode { n: sample.n, error })
+    };
+    let (_, height, width) = decode(first)?
+        .size3()
+        .map_err(|error| GridError::Decode { n: first.n, error })?;
+    let label_height = if with_seeds { LABEL_HEIGHT as i64 } else { 0 };
+    let cell_height = height + label_height;
+
+    let (cols, rows) = (cols as i64, rows as i64);
+    let grid = Tensor::ones(
+        [
+            3,
+            rows * (cell_height + GAP) + GAP,
+            cols * (width + GAP) + GAP,
+        ],
+        (Kind::Uint8, Device::Cpu),
+    ) * 255;
+    for (i, sample) in samples.iter().enumerate() {
+        let mut tile = decode(sample)?;
+        if tile.size3().ok() != Some((3, height, width)) {
+            tile = tch::vision::image::resize(&tile, width, height)
+                .map_err(|error| GridError::Resize { n: sample.n, error })?;
+        }
+        let x = GAP + (i as i64 % cols) * (width + GAP);
+        let y = GAP + (i as i64 / cols) * (cell_height + GAP);
+        grid.narrow(1, y, height).narrow(2, x, width).copy_(&tile);
+        if with_seeds {
+            let label = label(&sample.actual_seed.to_string(), width as usize);
+            let label = Tensor::from_slice(&label)
+                .view([1, label_height, width])
+                .repeat([3, 1, 1]);
+            grid.narrow(1, y + height, label_height)
+                .narrow(2, x, width)
+                .copy_(&label);
+        }
+    }
+
+    // the encoding is picked from the extension so the grid has to go through a file
+    let file = tempfile::Builder::new().suffix(".png").tempfile()?;
+    tch::vision::image::save(&grid, file.path()).map_err(GridError::Encode)?;
+    Ok(std::fs::read(file.path())?)
+}
+
+/// Grayscale strip of `width` pixels with `text` centered in black on white, characters that
+/// don't fit are cut off.
+fn label(text: &str, width: usize) -> Vec<u8> {
+    let mut strip = vec![255u8; LABEL_HEIGHT * width];
+    let advance = (GLYPH_WIDTH + 1) * FONT_SCALE;
+    let text_width = (text.chars().count() * advance).saturating_sub(FONT_SCALE);
+    let left = width.saturating_sub(text_width) / 2;
+    for (i, c) in text.chars().enumerate() {
+        let Some(rows) = glyph(c) else {
+            continue;
+        };
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                let x = left + i * advance + col * FONT_SCALE;
+                let y = FONT_SCALE + row * FONT_SCALE;
+                for dy in 0..FONT_SCALE {
+                    for dx in 0..FONT_SCALE {
+                        if x + dx < width {
+                            strip[(y + dy) * width + x + dx] = 0;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    strip
+}
+
+/// Rows of the 3x5 pixel glyph of a character of a seed, the highest bit is the left pixel.
+fn glyph(c: char) -> Option<[u8; GLYPH_HEIGHT]> {
+    Some(match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => return None,
+    })
+}
@@ -0,0 +1,173 @@
+//! Generation parameters embedded in the PNG samples so that a downloaded image describes how it
+//! was made. The parameters are stored like Automatic1111 does, as a `parameters` text chunk with
+//! the prompt followed by a line of `Key: value` pairs, so other tools can read them as well.
+
+use crate::models::image::Image;
+use airtifex_core::image::ImageSampleMetadata;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use thiserror::Error as ErrorType;
+
+pub(super) const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+const PNG_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+const PARAMETERS_KEYWORD: &str = "parameters";
+const SAMPLER: &str = super::SAMPLERS[0];
+
+#[derive(Debug, ErrorType)]
+pub enum PngMetadataError {
+    #[error("image is not a PNG")]
+    NotPng,
+    #[error("PNG chunk `{0}` is truncated")]
+    Truncated(String),
+}
+
+/// Formats the generation parameters of a sample of `image` generated with `seed`.
+pub fn generation_parameters(image: &Image, seed: i64) -> String {
+    let mut parameters = format!(
+        "{}\nSteps: {}, Sampler: {SAMPLER}, CFG scale: {}, Seed: {seed}, Size: {}x{}, Model: {}",
+        image.prompt, image.n_steps, image.guidance_scale, image.width, image.height, image.model
+    );
+    if let Some(strength) = image.strength {
+        parameters.push_str(&format!(", Denoising strength: {strength}"));
+    }
+    parameters
+}
+
+/// Returns `png` with `parameters` stored in an uncompressed `iTXt` chunk right after the header.
+/// Existing `parameters` chunks are replaced.
+pub fn embed_parameters(png: &[u8], parameters: &str) -> Result<Vec<u8>, PngMetadataError> {
+    let mut chunk_data = Vec::with_capacity(PARAMETERS_KEYWORD.len() + parameters.len() + 5);
+    chunk_data.extend_from_slice(PARAMETERS_KEYWORD.as_bytes());
+    // null separator, no compression, empty language tag and translated keyword
+    chunk_data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    chunk_data.extend_from_slice(parameters.as_bytes());
+
+    let mut out = Vec::with_capacity(png.len() + chunk_data.len() + 12);
+    out.extend_from_slice(&PNG_SIGNATURE);
+    for (i, chunk) in chunks(png)?.into_iter().enumerate() {
+        if text_chunk_keyword(&chunk).is_some_and(|k| k == PARAMETERS_KEYWORD.as_bytes()) {
+            continue;
+        }
+        out.extend_from_slice(chunk.raw);
+        // IHDR must stay the first chunk
+        if i == 0 {
+            write_chunk(&mut out, b"iTXt", &chunk_data);
+        }
+    }
+    Ok(out)
+}
+
+/// Reads the generation parameters embedded in `png`, `None` when there are none.
+pub fn read_parameters(png: &[u8]) -> Result<Option<ImageSampleMetadata>, PngMetadataError> {
+    Ok(chunks(png)?
+        .iter()
+        .find_map(|chunk| match chunk.kind {
+            b"tEXt" => {
+                let (keyword, text) = split_at_null(chunk.data)?;
+                // tEXt chunks are Latin-1
+                (keyword == PARAMETERS_KEYWORD.as_bytes())
+                    .then(|| text.iter().map(|&b| b as char).collect::<String>())
+            }
+            b"iTXt" => {
+                let (keyword, rest) = split_at_null(chunk.data)?;
+                let (&compressed, rest) = rest.split_first()?;
+                // skip the compression method
+                let (_language, rest) = split_at_null(rest.get(1..)?)?;
+                let (_translated_keyword, text) = split_at_null(rest)?;
+                (keyword == PARAMETERS_KEYWORD.as_bytes() && compressed == 0)
+                    .then(|| String::from_utf8_lossy(text).into_owned())
+            }
+            _ => None,
+        })
+        .map(|parameters| parse_parameters(&parameters)))
+}
+
+/// Parses parameters in the format of [`generation_parameters`], the values not known by this
+/// server are left out.
+pub fn parse_parameters(parameters: &str) -> ImageSampleMetadata {
+    let mut metadata = ImageSampleMetadata {
+        parameters: parameters.to_string(),
+        ..Default::default()
+    };
+    let mut prompt = vec![];
+    for line in parameters.lines() {
+        if let Some(negative_prompt) = line.strip_prefix("Negative prompt: ") {
+            metadata.negative_prompt = Some(negative_prompt.to_string());
+        } else if line.starts_with("Steps: ") {
+            for (key, value) in line.split(", ").filter_map(|kv| kv.split_once(": ")) {
+                match key {
+                    "Steps" => metadata.n_steps = value.parse().ok(),
+                    "Sampler" => metadata.sampler = Some(value.to_string()),
+                    "CFG scale" => metadata.guidance_scale = value.parse().ok(),
+                    "Seed" => metadata.seed = value.parse().ok(),
+                    "Size" => {
+                        if let Some((width, height)) = value.split_once('x') {
+                            metadata.width = width.parse().ok();
+                            metadata.height = height.parse().ok();
+                        }
+                    }
+                    "Model" => metadata.model = Some(value.to_string()),
+                    "Denoising strength" => metadata.strength = value.parse().ok(),
+                    _ => {}
+                }
+            }
+        } else {
+            prompt.push(line);
+        }
+    }
+    metadata.prompt = prompt.join("\n");
+    metadata
+}
+
+struct Chunk<'a> {
+    kind: &'a [u8],
+    data: &'a [u8],
+    /// The whole chunk including its length, type and CRC.
+    raw: &'a [u8],
+}
+
+fn chunks(png: &[u8]) -> Result<Vec<Chunk<'_>>, PngMetadataError> {
+    let mut rest = png
+        .strip_prefix(&PNG_SIGNATURE)
+        .ok_or(PngMetadataError::NotPng)?;
+    let mut chunks = vec![];
+    while rest.len() >= 8 {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let kind = &rest[4..8];
+        let end = 12 + len;
+        if rest.len() < end {
+            return Err(PngMetadataError::Truncated(
+                String::from_utf8_lossy(kind).into_owned(),
+            ));
+        }
+        chunks.push(Chunk {
+            kind,
+            data: &rest[8..8 + len],
+            raw: &rest[..end],
+        });
+        rest = &rest[end..];
+    }
+    Ok(chunks)
+}
+
+pub(super) fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut crc = PNG_CRC.digest();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+fn text_chunk_keyword<'a>(chunk: &Chunk<'a>) -> Option<&'a [u8]> {
+    match chunk.kind {
+        b"tEXt" | b"iTXt" | b"zTXt" => split_at_null(chunk.data).map(|(keyword, _)| keyword),
+        _ => None,
+    }
+}
+
+fn split_at_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let pos = data.iter().position(|&b| b == 0)?;
+    Some((&data[..pos], &data[pos + 1..]))
+}
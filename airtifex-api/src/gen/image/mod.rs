@@ -1,11 +1,60 @@
+pub mod backend;
+pub(crate) mod dispatch;
+pub mod grid;
+pub mod metadata;
+pub mod progress;
+pub mod reroll;
 pub mod sd;
 
 use std::{collections::HashMap, sync::Arc};
 
 use serde::{Deserialize, Serialize};
+use thiserror::Error as ErrorType;
 use tokio::runtime::Runtime;
 
-use crate::{config::Config, models::image_model::ImageModel, DbPool, Result};
+use crate::{
+    config::Config,
+    id::Uuid,
+    models::image_model::ImageModel,
+    queue::{self, QueueSender, QueueTicket, Queued},
+    webhook::Webhooks,
+    DbPool, Result,
+};
+use progress::{Cancellation, ProgressSender};
+use reroll::SampleReroll;
+
+/// Samplers the images can be generated with, every model uses the DDIM scheduler.
+pub const SAMPLERS: &[&str] = &["DDIM"];
+
+#[derive(Debug, ErrorType)]
+pub enum ThumbnailError {
+    #[error("failed to decode image - {0}")]
+    Decode(tch::TchError),
+    #[error("failed to resize image - {0}")]
+    Resize(tch::TchError),
+    #[error("failed to encode thumbnail - {0}")]
+    Encode(tch::TchError),
+    #[error("failed to read encoded thumbnail - {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Decodes `data`, crops it to a centered square and scales it down to a `size`x`size` PNG.
+/// This is CPU bound so it should run on a blocking thread.
+pub fn square_thumbnail(data: &[u8], size: i64) -> std::result::Result<Vec<u8>, ThumbnailError> {
+    let image = tch::vision::image::load_from_memory(data).map_err(ThumbnailError::Decode)?;
+    let (_, height, width) = image.size3().map_err(ThumbnailError::Decode)?;
+    let side = height.min(width);
+    let square = image
+        .narrow(1, (height - side) / 2, side)
+        .narrow(2, (width - side) / 2, side);
+    let thumbnail =
+        tch::vision::image::resize(&square, size, size).map_err(ThumbnailError::Resize)?;
+
+    // the encoding is picked from the extension so the thumbnail has to go through a file
+    let file = tempfile::Builder::new().suffix(".png").tempfile()?;
+    tch::vision::image::save(&thumbnail, file.path()).map_err(ThumbnailError::Encode)?;
+    Ok(std::fs::read(file.path())?)
+}
 
 pub enum GenerateImageRequest {
     TextToImage(BaseImageData),
@@ -15,12 +64,53 @@ pub enum GenerateImageRequest {
 
 impl GenerateImageRequest {
     pub fn id(&self) -> &str {
+        &self.data().id
+    }
+
+    pub fn request_id(&self) -> Uuid {
+        self.data().request_id
+    }
+
+    fn data(&self) -> &BaseImageData {
         match self {
-            Self::TextToImage(data) => &data.id,
-            Self::ImageToImage(data) => &data.data.id,
-            Self::Inpaint(data) => &data.data.id,
+            Self::TextToImage(data) => data,
+            Self::ImageToImage(data) => &data.data,
+            Self::Inpaint(data) => &data.data,
         }
     }
+
+    fn data_mut(&mut self) -> &mut BaseImageData {
+        match self {
+            Self::TextToImage(data) => data,
+            Self::ImageToImage(data) => &mut data.data,
+            Self::Inpaint(data) => &mut data.data,
+        }
+    }
+
+    /// Signal of the request being cancelled, requests without a progress stream can't be.
+    pub fn cancellation(&self) -> Option<Cancellation> {
+        self.data()
+            .progress
+            .as_ref()
+            .map(ProgressSender::cancellation)
+    }
+
+    /// Gives up the place of the request in the queue once it is started.
+    pub fn take_queue_ticket(&mut self) -> Option<QueueTicket> {
+        self.data_mut().queue_ticket.take()
+    }
+
+    /// Takes the reserved slot of a request generating a single sample of a finished image
+    /// again.
+    pub fn take_reroll(&mut self) -> Option<SampleReroll> {
+        self.data_mut().reroll.take()
+    }
+}
+
+impl Queued for GenerateImageRequest {
+    fn set_queue_ticket(&mut self, ticket: QueueTicket) {
+        self.data_mut().queue_ticket = Some(ticket);
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -33,6 +123,19 @@ pub struct BaseImageData {
     pub seed: i64,
     pub num_samples: i64,
     pub guidance_scale: f64,
+    /// Steps between previews of the sample, `None` disables them.
+    pub preview_every: Option<usize>,
+    #[serde(skip)]
+    pub progress: Option<ProgressSender>,
+    #[serde(skip)]
+    pub queue_ticket: Option<QueueTicket>,
+    /// Set when the request replaces a single sample of a finished image, the request then has
+    /// one sample generated with `seed`.
+    #[serde(skip)]
+    pub reroll: Option<SampleReroll>,
+    /// Id of the request that queued the generation.
+    #[serde(skip)]
+    pub request_id: Uuid,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -59,40 +162,55 @@ pub struct SaveImageResult {
 pub struct SaveImageFsResult {
     pub id: String,
     pub n_sample: i32,
-    pub is_last: bool,
+    pub seed: i64,
     pub path: std::path::PathBuf,
     pub thumbnail: std::path::PathBuf,
 }
 
+/// Sample saved by the local backend, or why the generation failed.
+pub type SavedSample = std::result::Result<SaveImageFsResult, String>;
+
 pub async fn initialize_models(
     db: Arc<DbPool>,
     config: &Config,
     runtime: Arc<Runtime>,
-) -> Result<HashMap<String, flume::Sender<GenerateImageRequest>>> {
+    webhooks: &Webhooks,
+) -> Result<HashMap<String, QueueSender<GenerateImageRequest>>> {
     tch::maybe_init_cuda();
     log::info!("Cuda available: {}", tch::Cuda::is_available());
     log::info!("Cudnn available: {}", tch::Cuda::cudnn_is_available());
     log::info!("MPS available: {}", tch::utils::has_mps());
     let mut txs = HashMap::new();
     for model_config in config.stable_diffusion.iter() {
-        let model = model_config
-            .name
-            .clone()
-            .unwrap_or_else(|| format!("stable-diffusion-{}", model_config.version.as_ref()));
+        let model = model_config.model_name();
         let exists = ImageModel::get_by_name(&db, &model).await.is_ok();
 
         log::info!("initializing image model {model}, exists in db: {exists}");
 
-        if !exists {
+        if exists {
+            // the limits of the model follow the configuration
+            ImageModel::update_capabilities(&db, &model, &model_config.capabilities()).await?;
+        } else {
             let image_model = ImageModel::new(
                 model.clone(),
                 model_config.model_description.clone(),
                 model_config.features(),
+                &model_config.capabilities(),
             );
             image_model.create(&db).await?;
         }
-        let tx_inference_req = sd::initialize(db.clone(), model_config.clone(), runtime.clone());
-        txs.insert(model.clone(), tx_inference_req);
+        let (tx_request, rx_request, running) = queue::queue_channel(model_config.queue_capacity);
+        runtime.spawn(dispatch::run_model(
+            db.clone(),
+            backend::from_config(model_config),
+            rx_request,
+            running,
+            model_config.max_image_gen_sessions,
+            model_config.features(),
+            config.image_metadata.embed,
+            webhooks.clone(),
+        ));
+        txs.insert(model.clone(), tx_request);
     }
     Ok(txs)
 }
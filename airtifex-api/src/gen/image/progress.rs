@@ -0,0 +1,229 @@
+use crate::{id::Uuid, queue::QueuePosition};
+use airtifex_core::image::{ImagePreview, ImageProgress};
+
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::sync::watch;
+
+/// Progress of an image generation as far as it got.
+#[derive(Clone, Debug, Default)]
+pub struct GenerationProgress {
+    pub progress: ImageProgress,
+    /// Latest preview of the generation, every new preview replaces it so that at most one is
+    /// kept in memory per image.
+    pub preview: Option<Arc<ImagePreview>>,
+}
+
+/// Progress receiver of an image together with the id of the stream it belongs to.
+#[derive(Debug)]
+struct ProgressStream {
+    stream_id: u64,
+    rx_progress: watch::Receiver<GenerationProgress>,
+    /// Position of the generation request in the queue of its model.
+    queue: Option<QueuePosition>,
+    /// Number of clients following the progress.
+    watchers: usize,
+    tx_cancel: watch::Sender<bool>,
+}
+
+type StreamMap = HashMap<Uuid, ProgressStream>;
+
+/// Progress of the images that are queued or being generated, keyed by image. A stream ends
+/// once its [`ProgressSender`] is dropped together with the generation.
+#[derive(Default)]
+pub struct ImageProgressStreams {
+    streams: Arc<Mutex<StreamMap>>,
+    next_stream_id: AtomicU64,
+    /// Time the last client following a generation has to come back before the generation is
+    /// cancelled, `None` keeps generations running without clients.
+    cancel_grace_period: Option<Duration>,
+}
+
+impl ImageProgressStreams {
+    pub fn new(cancel_grace_period: Option<Duration>) -> Self {
+        Self {
+            cancel_grace_period,
+            ..Default::default()
+        }
+    }
+
+    /// Starts the progress stream of image `id`, replacing a previous one.
+    pub fn start(&self, id: Uuid) -> ProgressSender {
+        let stream_id = self.next_stream_id.fetch_add(1, Ordering::Relaxed);
+        let (tx_progress, rx_progress) = watch::channel(GenerationProgress::default());
+        let (tx_cancel, rx_cancel) = watch::channel(false);
+        self.streams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                id,
+                ProgressStream {
+                    stream_id,
+                    rx_progress,
+                    queue: None,
+                    watchers: 0,
+                    tx_cancel,
+                },
+            );
+        ProgressSender {
+            id,
+            stream_id,
+            tx_progress,
+            rx_cancel,
+            streams: self.streams.clone(),
+        }
+    }
+
+    /// Follows the progress of image `id` if it is still being generated. Once the last client
+    /// stops following it the generation is cancelled, unless a client comes back within the
+    /// grace period.
+    pub fn watch(&self, id: &Uuid) -> Option<ProgressWatch> {
+        let mut streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        let stream = streams.get_mut(id)?;
+        stream.watchers += 1;
+        Some(ProgressWatch {
+            id: *id,
+            stream_id: stream.stream_id,
+            rx_progress: stream.rx_progress.clone(),
+            streams: self.streams.clone(),
+            cancel_grace_period: self.cancel_grace_period,
+        })
+    }
+
+    /// Remembers the queue position of the generation of image `id` for its progress stream.
+    pub fn set_queue(&self, id: &Uuid, queue: QueuePosition) {
+        if let Some(stream) = self
+            .streams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_mut(id)
+        {
+            stream.queue = Some(queue);
+        }
+    }
+
+    /// Returns the queue position of the generation of image `id` if it is still running.
+    pub fn queue(&self, id: &Uuid) -> Option<QueuePosition> {
+        self.streams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .and_then(|stream| stream.queue.clone())
+    }
+}
+
+/// Progress of an image followed by a client, see [`ImageProgressStreams::watch`].
+pub struct ProgressWatch {
+    id: Uuid,
+    stream_id: u64,
+    rx_progress: watch::Receiver<GenerationProgress>,
+    streams: Arc<Mutex<StreamMap>>,
+    cancel_grace_period: Option<Duration>,
+}
+
+impl Deref for ProgressWatch {
+    type Target = watch::Receiver<GenerationProgress>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.rx_progress
+    }
+}
+
+impl DerefMut for ProgressWatch {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.rx_progress
+    }
+}
+
+impl Drop for ProgressWatch {
+    fn drop(&mut self) {
+        let mut streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(stream) = streams
+            .get_mut(&self.id)
+            .filter(|stream| stream.stream_id == self.stream_id)
+        else {
+            return;
+        };
+        stream.watchers -= 1;
+        let (Some(grace_period), 0) = (self.cancel_grace_period, stream.watchers) else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let (id, stream_id, streams) = (self.id, self.stream_id, self.streams.clone());
+        runtime.spawn(async move {
+            tokio::time::sleep(grace_period).await;
+            let streams = streams.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(stream) = streams
+                .get(&id)
+                .filter(|stream| stream.stream_id == stream_id && stream.watchers == 0)
+            {
+                log::info!("[{id}] cancelling the generation, nobody follows its progress");
+                stream.tx_cancel.send_replace(true);
+            }
+        });
+    }
+}
+
+/// Signal of a generation that nobody follows anymore.
+#[derive(Clone, Debug)]
+pub struct Cancellation(watch::Receiver<bool>);
+
+impl Cancellation {
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Waits until the generation is cancelled, never returns once the progress stream ended.
+    pub async fn cancelled(&mut self) {
+        while !*self.0.borrow_and_update() {
+            if self.0.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// Sending side of a progress stream, removes the stream when dropped.
+#[derive(Debug)]
+pub struct ProgressSender {
+    id: Uuid,
+    stream_id: u64,
+    tx_progress: watch::Sender<GenerationProgress>,
+    rx_cancel: watch::Receiver<bool>,
+    streams: Arc<Mutex<StreamMap>>,
+}
+
+impl ProgressSender {
+    pub fn cancellation(&self) -> Cancellation {
+        Cancellation(self.rx_cancel.clone())
+    }
+
+    /// Publishes the current step, `preview` replaces the previous preview when set.
+    pub fn send(&self, progress: ImageProgress, preview: Option<ImagePreview>) {
+        self.tx_progress.send_modify(|current| {
+            current.progress = progress;
+            if let Some(preview) = preview {
+                current.preview = Some(Arc::new(preview));
+            }
+        });
+    }
+}
+
+impl Drop for ProgressSender {
+    fn drop(&mut self) {
+        let mut streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        if matches!(streams.get(&self.id), Some(current) if current.stream_id == self.stream_id) {
+            streams.remove(&self.id);
+        }
+    }
+}
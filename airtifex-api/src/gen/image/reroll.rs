@@ -0,0 +1,59 @@
+//! Samples of finished images that are generated again one at a time. A slot is reserved while
+//! its new sample is generated so that a slot is never generated twice at once, rerolls of
+//! different slots of the same image run independently.
+
+use crate::id::Uuid;
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+type Slots = Arc<Mutex<HashSet<(Uuid, i32)>>>;
+
+/// Slots of images whose sample is being generated again.
+#[derive(Clone, Debug, Default)]
+pub struct SampleRerolls {
+    slots: Slots,
+}
+
+impl SampleRerolls {
+    /// Reserves sample `n_sample` of image `image_id`, `None` when it is already being generated
+    /// again.
+    pub fn reserve(&self, image_id: Uuid, n_sample: i32) -> Option<SampleReroll> {
+        let reserved = self
+            .slots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert((image_id, n_sample));
+        reserved.then(|| SampleReroll {
+            image_id,
+            n_sample,
+            slots: self.slots.clone(),
+        })
+    }
+}
+
+/// Reservation of a sample slot, the slot is released when dropped.
+#[derive(Debug)]
+pub struct SampleReroll {
+    image_id: Uuid,
+    n_sample: i32,
+    slots: Slots,
+}
+
+impl SampleReroll {
+    /// Slot of the sample that is replaced, starting at 1.
+    pub fn n_sample(&self) -> i32 {
+        self.n_sample
+    }
+}
+
+impl Drop for SampleReroll {
+    fn drop(&mut self) {
+        self.slots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&(self.image_id, self.n_sample));
+    }
+}
@@ -2,7 +2,7 @@ use crate::{
     config::StableDiffusionConfig,
     gen::image::{
         sd::generator::{BaseImageGenerator, ImageGenerator},
-        ImageToImageData, SaveImageFsResult,
+        ImageToImageData, SavedSample,
     },
     Result,
 };
@@ -28,7 +28,7 @@ impl ImageToImageGenerator {
         clip_device: Device,
         unet_device: Device,
         vae_device: Device,
-        tx_results: Sender<SaveImageFsResult>,
+        tx_results: Sender<SavedSample>,
         save_dir: impl AsRef<Path>,
     ) -> Result<Self> {
         let ImageToImageData {
@@ -66,7 +66,7 @@ impl ImageToImageGenerator {
     }
 
     pub fn init_latents(&mut self) {
-        tch::manual_seed(self.base_generator.request.seed + self.base_generator.sample_idx());
+        tch::manual_seed(self.base_generator.sample_seed());
         let latents =
             (self.init_latent_dist.sample() * LATENTS_SCALE).to(self.base_generator.unet_device);
         let noise = latents.randn_like();
@@ -138,6 +138,8 @@ impl ImageGenerator for ImageToImageGenerator {
             .step(&noise_pred, timestep, &self.latents);
 
         self.base_generator.processed_timesteps += 1;
+        self.base_generator
+            .report_progress(self.type_(), &self.latents);
 
         true
     }
@@ -2,7 +2,7 @@ use crate::{
     config::StableDiffusionConfig,
     gen::image::{
         sd::generator::{BaseImageGenerator, GenImageError, ImageGenerator},
-        InpaintData, SaveImageFsResult,
+        InpaintData, SavedSample,
     },
     Result,
 };
@@ -29,7 +29,7 @@ impl InpaintImageGenerator {
         clip_device: Device,
         unet_device: Device,
         vae_device: Device,
-        tx_results: Sender<SaveImageFsResult>,
+        tx_results: Sender<SavedSample>,
         save_dir: impl AsRef<Path>,
     ) -> Result<Self> {
         let InpaintData {
@@ -76,7 +76,7 @@ impl InpaintImageGenerator {
     }
 
     pub fn init_latents(&mut self) {
-        tch::manual_seed(self.base_generator.request.seed + self.base_generator.sample_idx());
+        tch::manual_seed(self.base_generator.sample_seed());
         let masked_image_latents =
             (self.masked_image_dist.sample() * LATENTS_SCALE).to(self.base_generator.unet_device);
         self.masked_image_latents = Tensor::cat(&[&masked_image_latents, &masked_image_latents], 0);
@@ -160,6 +160,8 @@ impl ImageGenerator for InpaintImageGenerator {
             .step(&noise_pred, timestep, &self.latents);
 
         self.base_generator.processed_timesteps += 1;
+        self.base_generator
+            .report_progress(self.type_(), &self.latents);
 
         true
     }
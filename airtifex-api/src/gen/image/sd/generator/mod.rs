@@ -4,9 +4,12 @@ pub mod txt2img;
 
 use crate::{
     config::{StableDiffusionConfig, StableDiffusionVersion},
-    gen::image::{BaseImageData, SaveImageFsResult},
+    gen::image::{BaseImageData, SaveImageFsResult, SavedSample, ThumbnailError},
     Result,
 };
+use airtifex_core::image::{ImagePreview, ImageProgress};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
 
 use diffusers::{
     models::{unet_2d::UNet2DConditionModel, vae::AutoEncoderKL},
@@ -21,6 +24,16 @@ use tch::{nn::Module, Device, Kind, Tensor};
 
 pub const LATENTS_SCALE: f64 = 0.18215;
 
+/// Seed of the sample at `idx` of a batch generated with `seed`. Each sample uses the base seed
+/// offset by its index so that every one of them can be reproduced on its own, seeds at the end
+/// of the range wrap around to its start.
+pub fn sample_seed(seed: i64, idx: i64) -> i64 {
+    seed.wrapping_add(idx)
+}
+
+/// Previews are scaled down so that their longer side is at most this many pixels.
+const PREVIEW_MAX_SIZE: i64 = 256;
+
 #[derive(Debug, thiserror::Error)]
 pub enum GenImageError {
     #[error("failed to create CLIP Tokenizer - {0}")]
@@ -51,6 +64,10 @@ pub trait ImageGenerator {
     fn is_finished(&self) -> bool;
     fn process_next_timestep(&mut self) -> bool;
 
+    fn is_cancelled(&self) -> bool {
+        self.base_generator().is_cancelled()
+    }
+
     fn log_timestep(&self) {
         self.base_generator().log_timestep(self.type_());
     }
@@ -67,7 +84,7 @@ pub struct BaseImageGenerator {
     unet_device: Device,
     scheduler: DDIMScheduler,
     text_embeddings: Tensor,
-    tx_results: Sender<SaveImageFsResult>,
+    tx_results: Sender<SavedSample>,
     request: BaseImageData,
     sd_config: stable_diffusion::StableDiffusionConfig,
     save_dir: PathBuf,
@@ -84,7 +101,7 @@ impl BaseImageGenerator {
         clip_device: Device,
         unet_device: Device,
         vae_device: Device,
-        tx_results: Sender<SaveImageFsResult>,
+        tx_results: Sender<SavedSample>,
         save_dir: impl AsRef<Path>,
         bsize: i64,
     ) -> Result<Self> {
@@ -165,10 +182,20 @@ impl BaseImageGenerator {
         self.processed_samples as i64
     }
 
+    /// Seed used for the current sample.
+    pub fn sample_seed(&self) -> i64 {
+        sample_seed(self.request.seed, self.sample_idx())
+    }
+
     pub fn is_finished(&self) -> bool {
         self.processed_samples as i64 >= self.request.num_samples
     }
 
+    /// Nobody waits for the samples anymore, the request was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.tx_results.is_disconnected()
+    }
+
     pub fn decode_latents(&self, latents: &Tensor) -> Tensor {
         let decoded = self.vae.decode(&(latents / LATENTS_SCALE));
         let decoded = (decoded / 2 + 0.5).clamp(0., 1.).to_device(Device::Cpu);
@@ -213,13 +240,13 @@ impl BaseImageGenerator {
                 );
             }
         }
-        if let Err(e) = self.tx_results.try_send(SaveImageFsResult {
+        if let Err(e) = self.tx_results.try_send(Ok(SaveImageFsResult {
             id: self.request.id.clone(),
             n_sample: idx as i32,
-            is_last: idx == self.request.num_samples,
+            seed: self.sample_seed(),
             path,
             thumbnail: thumbnail_path,
-        }) {
+        })) {
             log::error!(
                 "[{}][{}/{}] failed to send save request - {e}",
                 self.request.id,
@@ -236,6 +263,66 @@ impl BaseImageGenerator {
         self.save_image(image);
     }
 
+    /// Publishes the progress after a timestep, every `preview_every` steps together with a
+    /// preview decoded from `latents`.
+    pub fn report_progress(&self, type_: &'static str, latents: &Tensor) {
+        let Some(progress) = &self.request.progress else {
+            return;
+        };
+        let step = self.processed_timesteps;
+        // the last step is followed by the sample itself
+        let preview = self
+            .request
+            .preview_every
+            .filter(|every| step.is_multiple_of(*every) && step < self.request.n_steps)
+            .and_then(|_| match self.encode_preview(latents) {
+                Ok(data) => Some(ImagePreview {
+                    n_sample: self.sample_idx() + 1,
+                    step,
+                    data,
+                }),
+                Err(e) => {
+                    self.log(
+                        Level::Error,
+                        type_,
+                        format!("failed to create preview - {e}"),
+                    );
+                    None
+                }
+            });
+        progress.send(
+            ImageProgress {
+                n_sample: self.sample_idx() + 1,
+                num_samples: self.request.num_samples,
+                step,
+                n_steps: self.request.n_steps,
+            },
+            preview,
+        );
+    }
+
+    /// Decodes the latents of the current sample into a base64 encoded PNG.
+    fn encode_preview(&self, latents: &Tensor) -> std::result::Result<String, ThumbnailError> {
+        let image = self.decode_latents(&latents.to(self.vae_device)).get(0);
+        let (width, height) = (self.sd_config.width, self.sd_config.height);
+        let scale = (PREVIEW_MAX_SIZE as f64 / width.max(height) as f64).min(1.);
+        let image = tch::vision::image::resize(
+            &image,
+            ((width as f64 * scale) as i64).max(1),
+            ((height as f64 * scale) as i64).max(1),
+        )
+        .map_err(ThumbnailError::Resize)?;
+
+        // the encoding is picked from the extension so the preview has to go through a file
+        let path = self
+            .save_dir
+            .join(format!("{}-preview.png", self.request.id));
+        tch::vision::image::save(&image, &path).map_err(ThumbnailError::Encode)?;
+        let data = std::fs::read(&path)?;
+        let _ = std::fs::remove_file(&path);
+        Ok(STANDARD.encode(data))
+    }
+
     pub fn log_timestep(&self, type_: &'static str) {
         self.log(
             Level::Debug,
@@ -265,3 +352,15 @@ fn generate_thumbnail(
 ) -> std::result::Result<Tensor, tch::TchError> {
     tch::vision::image::resize(image, width as i64, height as i64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_seeds_wrap_around() {
+        let seeds: Vec<_> = (0..3).map(|idx| sample_seed(i64::MAX, idx)).collect();
+        assert_eq!(seeds, [i64::MAX, i64::MIN, i64::MIN + 1]);
+        assert_eq!(sample_seed(-1, 2), 1);
+    }
+}
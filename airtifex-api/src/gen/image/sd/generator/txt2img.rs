@@ -2,7 +2,7 @@ use crate::{
     config::StableDiffusionConfig,
     gen::image::{
         sd::generator::{BaseImageGenerator, ImageGenerator},
-        BaseImageData, SaveImageFsResult,
+        BaseImageData, SavedSample,
     },
     Result,
 };
@@ -23,7 +23,7 @@ impl TextToImageGenerator {
         clip_device: Device,
         unet_device: Device,
         vae_device: Device,
-        tx_results: Sender<SaveImageFsResult>,
+        tx_results: Sender<SavedSample>,
         save_dir: impl AsRef<Path>,
     ) -> Result<Self> {
         let base_generator = BaseImageGenerator::new(
@@ -48,7 +48,7 @@ impl TextToImageGenerator {
     }
 
     pub fn init_latents(&mut self) {
-        tch::manual_seed(self.base_generator.request.seed + self.base_generator.sample_idx());
+        tch::manual_seed(self.base_generator.sample_seed());
         self.latents = Tensor::randn(
             [
                 self.base_generator.bsize,
@@ -122,6 +122,8 @@ impl ImageGenerator for TextToImageGenerator {
             .step(&noise_pred, timestep, &self.latents);
 
         self.base_generator.processed_timesteps += 1;
+        self.base_generator
+            .report_progress(self.type_(), &self.latents);
 
         true
     }
@@ -1,247 +1,161 @@
 mod generator;
 
-pub use generator::GenImageError;
+pub use generator::{sample_seed, GenImageError};
 
 use crate::{
     config::StableDiffusionConfig,
-    gen::image::{GenerateImageRequest, SaveImageFsResult},
-    models::{image::Image, image_sample::ImageSample},
-    queue,
+    gen::image::{
+        backend::{GeneratedSample, ImageBackend, SampleStream},
+        GenerateImageRequest, SaveImageFsResult, SavedSample,
+    },
 };
 use generator::{
     img2img::ImageToImageGenerator, inpaint::InpaintImageGenerator, txt2img::TextToImageGenerator,
+    ImageGenerator,
 };
 
-use flume::Sender;
-use std::sync::Arc;
-use tokio::runtime::Runtime;
-
-use self::generator::ImageGenerator;
-
-pub fn initialize(
-    db: Arc<crate::DbPool>,
-    config: StableDiffusionConfig,
-    runtime: Arc<Runtime>,
-) -> Sender<GenerateImageRequest> {
-    let request_queue = queue::empty_queue();
-    let save_data_queue = queue::empty_queue();
-
-    let queue = request_queue.clone();
-    let tx_request = queue::start_queue_thread::<GenerateImageRequest>(queue);
-
-    let queue = save_data_queue.clone();
-    let tx_results = queue::start_queue_thread::<SaveImageFsResult>(queue);
-
-    // Create thread responsible for saving images to database
-    std::thread::spawn(move || loop {
-        let save_data_request = save_data_queue
-            .write()
-            .ok()
-            .and_then(|mut queue| queue.pop_front());
-        if let Some(save_data_request) = save_data_request {
-            let db = db.clone();
-            if let Ok(data) = std::fs::read(&save_data_request.path) {
-                let thumbnail = std::fs::read(&save_data_request.thumbnail);
-                // TODO: verify the result of this
-                runtime.spawn(async move {
-                    log::debug!(
-                        "[{}][{}] saving image to DB",
-                        save_data_request.id,
-                        save_data_request.n_sample
-                    );
-                    let entry = ImageSample::new(
-                        save_data_request.id.parse().unwrap(),
-                        save_data_request.n_sample,
-                        data,
-                    );
-                    if let Err(e) = entry.create(&db).await {
-                        log::error!(
-                            "[{}][{}] failed to save image data- {e}",
-                            save_data_request.id,
-                            save_data_request.n_sample
+use flume::{unbounded, Sender};
+use futures_util::StreamExt;
+
+/// Generates the samples with the weights of the model on this server. The model is run on a
+/// thread of its own that advances all of its requests one step at a time.
+pub struct LocalBackend {
+    tx_request: Sender<(GenerateImageRequest, Sender<SavedSample>)>,
+}
+
+impl LocalBackend {
+    /// Loads the model on a new thread.
+    pub fn start(config: StableDiffusionConfig) -> Self {
+        let (tx_request, rx_request) = unbounded::<(GenerateImageRequest, Sender<SavedSample>)>();
+
+        std::thread::spawn(move || {
+            let cpu = {
+                let mut cpu = vec![];
+                if config.clip_cpu {
+                    cpu.push("clip".into())
+                }
+                if config.vae_cpu {
+                    cpu.push("vae".into())
+                }
+                if config.unet_cpu {
+                    cpu.push("unet".into())
+                }
+                cpu
+            };
+            let device_setup = diffusers::utils::DeviceSetup::new(cpu);
+
+            let clip_device = device_setup.get("clip");
+            let vae_device = device_setup.get("vae");
+            let unet_device = device_setup.get("unet");
+
+            let tmp = tempfile::TempDir::new().expect("temporary directory for images");
+
+            let mut running_sessions: Vec<Box<dyn ImageGenerator>> = Vec::new();
+
+            loop {
+                // the number of requests is limited by the queue of the model
+                while let Ok((request, tx_results)) = rx_request.try_recv() {
+                    let generator = match request {
+                        GenerateImageRequest::ImageToImage(data) => ImageToImageGenerator::new(
+                            data,
+                            &config,
+                            clip_device,
+                            unet_device,
+                            vae_device,
+                            tx_results.clone(),
+                            tmp.path(),
                         )
+                        .map(|g| Box::new(g) as Box<dyn ImageGenerator>),
+                        GenerateImageRequest::Inpaint(data) => InpaintImageGenerator::new(
+                            data,
+                            &config,
+                            clip_device,
+                            unet_device,
+                            vae_device,
+                            tx_results.clone(),
+                            tmp.path(),
+                        )
+                        .map(|g| Box::new(g) as Box<dyn ImageGenerator>),
+                        GenerateImageRequest::TextToImage(data) => TextToImageGenerator::new(
+                            data,
+                            &config,
+                            clip_device,
+                            unet_device,
+                            vae_device,
+                            tx_results.clone(),
+                            tmp.path(),
+                        )
+                        .map(|g| Box::new(g) as Box<dyn ImageGenerator>),
+                    };
+                    match generator {
+                        Ok(generator) => running_sessions.push(generator),
+                        Err(e) => {
+                            let _ = tx_results.send(Err(e.to_string()));
+                        }
                     }
+                }
 
-                    // save thumbnail from first sample
-                    if save_data_request.n_sample == 1 {
-                        if let Ok(id) = save_data_request.id.parse() {
-                            match thumbnail {
-                                Ok(thumbnail) => {
-                                    if let Err(e) =
-                                        Image::update_thumbnail(&db, &id, &thumbnail).await
-                                    {
-                                        log::error!(
-                                            "[{}][{}] failed to update image thumbnail- {e}",
-                                            save_data_request.id,
-                                            save_data_request.n_sample
-                                        )
-                                    }
-                                }
-                                Err(e) => {
-                                    log::error!(
-                                        "[{}][{}] failed to read thumbnail- {e}",
-                                        save_data_request.id,
-                                        save_data_request.n_sample
-                                    )
-                                }
-                            }
-                        }
+                for session in &mut running_sessions {
+                    session.process_next_timestep();
+                }
+
+                running_sessions.retain(|s| {
+                    if s.is_finished() {
+                        return false;
                     }
-                    // update image processing status
-                    if save_data_request.is_last {
-                        log::debug!(
-                            "[{}][{}] updating image processing to false",
-                            save_data_request.id,
-                            save_data_request.n_sample
-                        );
-                        if let Ok(id) = save_data_request.id.parse() {
-                            if let Err(e) = Image::update_is_processing(&db, &id, false).await {
-                                log::error!(
-                                    "[{}][{}] failed to update image processing status - {e}",
-                                    save_data_request.id,
-                                    save_data_request.n_sample
-                                )
-                            }
-                        }
+                    if s.is_cancelled() {
+                        s.log(log::Level::Info, "stopping cancelled generation");
+                        return false;
                     }
+                    true
                 });
-            }
-        } else {
-            std::thread::sleep(std::time::Duration::from_millis(100));
-        }
-    });
-
-    // Create a thread that will handle generating images
-    std::thread::spawn(move || {
-        let cpu = {
-            let mut cpu = vec![];
-            if config.clip_cpu {
-                cpu.push("clip".into())
-            }
-            if config.vae_cpu {
-                cpu.push("vae".into())
-            }
-            if config.unet_cpu {
-                cpu.push("unet".into())
-            }
-            cpu
-        };
-        let device_setup = diffusers::utils::DeviceSetup::new(cpu);
-
-        let clip_device = device_setup.get("clip");
-        let vae_device = device_setup.get("vae");
-        let unet_device = device_setup.get("unet");
-
-        let tmp = tempfile::TempDir::new().expect("temporary directory for images");
-
-        let mut running_sessions = Vec::new();
-
-        loop {
-            let free_spots = config.max_image_gen_sessions - running_sessions.len();
-
-            if free_spots > 0 {
-                if let Ok(mut queue) = request_queue.try_write() {
-                    'inner: while let Some(request) = queue.pop_front() {
-                        let id = request.id().to_string();
-                        let generator = match request {
-                            GenerateImageRequest::ImageToImage(data) => {
-                                if config.feature_image_to_image {
-                                    match ImageToImageGenerator::new(
-                                        data,
-                                        &config,
-                                        clip_device,
-                                        unet_device,
-                                        vae_device,
-                                        tx_results.clone(),
-                                        tmp.path(),
-                                    ) {
-                                        Ok(generator) => {
-                                            Box::new(generator) as Box<dyn ImageGenerator>
-                                        }
-                                        Err(e) => {
-                                            log::error!("[{id}] {e}");
-                                            continue 'inner;
-                                        }
-                                    }
-                                } else {
-                                    log::error!("[{id}] feature image-to-image is disabled");
-                                    continue 'inner;
-                                    // # TODO return an error somehow
-                                }
-                            }
-                            GenerateImageRequest::Inpaint(data) => {
-                                if config.feature_inpaint {
-                                    match InpaintImageGenerator::new(
-                                        data,
-                                        &config,
-                                        clip_device,
-                                        unet_device,
-                                        vae_device,
-                                        tx_results.clone(),
-                                        tmp.path(),
-                                    ) {
-                                        Ok(generator) => {
-                                            Box::new(generator) as Box<dyn ImageGenerator>
-                                        }
-                                        Err(e) => {
-                                            log::error!("[{id}] {e}");
-                                            continue;
-                                        }
-                                    }
-                                } else {
-                                    log::error!(
-                                        "[{id}] feature inpaint is disabled for this model"
-                                    );
-                                    continue 'inner;
-                                    // # TODO return an error somehow
-                                }
-                            }
-                            GenerateImageRequest::TextToImage(data) => {
-                                if config.feature_text_to_image {
-                                    match TextToImageGenerator::new(
-                                        data,
-                                        &config,
-                                        clip_device,
-                                        unet_device,
-                                        vae_device,
-                                        tx_results.clone(),
-                                        tmp.path(),
-                                    ) {
-                                        Ok(generator) => {
-                                            Box::new(generator) as Box<dyn ImageGenerator>
-                                        }
-                                        Err(e) => {
-                                            log::error!("[{id}] {e}");
-                                            continue;
-                                        }
-                                    }
-                                } else {
-                                    log::error!(
-                                        "[{id}] feature text-to-image is disabled for this model"
-                                    );
-                                    continue 'inner;
-                                    // # TODO return an error somehow
-                                }
-                            }
-                        };
-                        running_sessions.push(generator);
-
-                        if free_spots == 0 {
-                            break 'inner;
-                        }
-                    }
-                }
-            }
 
-            for session in &mut running_sessions {
-                session.process_next_timestep();
+                std::thread::sleep(std::time::Duration::from_millis(10));
             }
+        });
 
-            running_sessions.retain(|s| !s.is_finished());
+        Self { tx_request }
+    }
+}
 
-            std::thread::sleep(std::time::Duration::from_millis(10));
+impl ImageBackend for LocalBackend {
+    fn generate(&self, request: GenerateImageRequest) -> SampleStream {
+        let (tx_results, rx_results) = unbounded();
+        if self.tx_request.send((request, tx_results)).is_err() {
+            return futures_util::stream::iter([Err("the model isn't running".into())]).boxed();
         }
-    });
+        // the generator drops its sender once the last sample is saved
+        rx_results
+            .into_stream()
+            .then(|result| async move {
+                let saved = result?;
+                tokio::task::spawn_blocking(move || read_sample(saved))
+                    .await
+                    .map_err(|e| e.to_string())?
+            })
+            .boxed()
+    }
+}
 
-    tx_request
+/// Reads a sample saved by a generator.
+fn read_sample(saved: SaveImageFsResult) -> Result<GeneratedSample, String> {
+    let data = std::fs::read(&saved.path)
+        .map_err(|e| format!("failed to read sample {} - {e}", saved.n_sample))?;
+    let thumbnail = match std::fs::read(&saved.thumbnail) {
+        Ok(thumbnail) => Some(thumbnail),
+        Err(e) => {
+            log::error!(
+                "[{}][{}] failed to read thumbnail - {e}",
+                saved.id,
+                saved.n_sample
+            );
+            None
+        }
+    };
+    Ok(GeneratedSample {
+        n_sample: saved.n_sample,
+        seed: saved.seed,
+        data,
+        thumbnail,
+    })
 }
@@ -0,0 +1,82 @@
+//! Pinning of the inference threads of a model to a set of CPU cores. The model computes on
+//! threads started by the inference thread, those inherit the cores it is pinned to.
+
+use std::io;
+
+/// Pins the calling thread to `cores`, nothing changes when `cores` is empty.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cores: &[usize]) -> io::Result<()> {
+    if cores.is_empty() {
+        return Ok(());
+    }
+    let max_cores = libc::CPU_SETSIZE as usize;
+    if let Some(core) = cores.iter().find(|core| **core >= max_cores) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("core {core} is out of range, the cores are numbered below {max_cores}"),
+        ));
+    }
+    // SAFETY: `cpu_set_t` is a plain bit set that is valid zeroed and every core fits into it
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for core in cores {
+            libc::CPU_SET(*core, &mut set);
+        }
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(cores: &[usize]) -> io::Result<()> {
+    if cores.is_empty() {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "CPU affinity is only supported on Linux",
+    ))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    /// Cores the calling thread may run on.
+    fn current_cores() -> Vec<usize> {
+        // SAFETY: the set is valid zeroed and only read after the kernel filled it
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            let result =
+                libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set);
+            assert_eq!(result, 0, "{}", io::Error::last_os_error());
+            (0..libc::CPU_SETSIZE as usize)
+                .filter(|core| libc::CPU_ISSET(*core, &set))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn threads_are_pinned_to_the_cores() {
+        // pinned on a thread of its own, the thread of the test keeps its cores
+        std::thread::spawn(|| {
+            let all = current_cores();
+            pin_current_thread(&[]).unwrap();
+            assert_eq!(current_cores(), all);
+
+            pin_current_thread(&all[..1]).unwrap();
+            assert_eq!(current_cores(), &all[..1]);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn cores_out_of_range_are_refused() {
+        let err = pin_current_thread(&[libc::CPU_SETSIZE as usize]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
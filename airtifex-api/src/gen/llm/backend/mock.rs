@@ -0,0 +1,124 @@
+//! Model of the tests. Its answers are scripted instead of generated, so the tests can check how
+//! answers are streamed, stopped and saved without any weights.
+
+use super::{LanguageModel, LanguageSession};
+
+use llm::{InferenceError, InferenceParameters, InferenceSessionConfig, TokenId, Vocabulary};
+use rand::{Rng, RngCore};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// How the mock model answers every prompt.
+#[derive(Clone)]
+pub enum MockAnswer {
+    /// The tokens in order, followed by the end of the text.
+    Tokens(Vec<String>),
+    /// `len` tokens each picked from `words` by the generator of the session, followed by the
+    /// end of the text.
+    Sampled { words: Vec<String>, len: usize },
+}
+
+/// What the sessions of a mock model were fed and generated.
+#[derive(Default)]
+pub struct MockLog {
+    pub sessions: usize,
+    pub prompts: Vec<String>,
+    /// Parameters the prompts were fed with.
+    pub params: Vec<InferenceParameters>,
+    pub generated_tokens: usize,
+}
+
+pub struct MockModel {
+    answer: MockAnswer,
+    vocabulary: Vocabulary,
+    log: Arc<Mutex<MockLog>>,
+}
+
+impl MockModel {
+    pub fn new(answer: MockAnswer) -> Self {
+        Self {
+            answer,
+            vocabulary: Vocabulary::default(),
+            log: Default::default(),
+        }
+    }
+
+    /// Model answering with `tokens`.
+    pub fn answering(tokens: &[&str]) -> Self {
+        Self::new(MockAnswer::Tokens(
+            tokens.iter().map(|t| t.to_string()).collect(),
+        ))
+    }
+
+    /// Replaces the empty vocabulary of the model, each token has its index as id.
+    pub fn with_vocabulary(mut self, tokens: &[&[u8]]) -> Self {
+        self.vocabulary = Vocabulary::default();
+        for (id, token) in tokens.iter().enumerate() {
+            self.vocabulary
+                .push_token(id as TokenId, token.to_vec(), 0.0);
+        }
+        self
+    }
+
+    pub fn log(&self) -> MutexGuard<'_, MockLog> {
+        self.log.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl LanguageModel for MockModel {
+    fn start_session(&self, _config: InferenceSessionConfig) -> Box<dyn LanguageSession> {
+        self.log().sessions += 1;
+        Box::new(MockSession {
+            answer: self.answer.clone(),
+            generated: 0,
+            token: String::new(),
+            log: self.log.clone(),
+        })
+    }
+
+    fn vocabulary(&self) -> &Vocabulary {
+        &self.vocabulary
+    }
+}
+
+struct MockSession {
+    answer: MockAnswer,
+    generated: usize,
+    /// The last generated token.
+    token: String,
+    log: Arc<Mutex<MockLog>>,
+}
+
+impl LanguageSession for MockSession {
+    fn feed_prompt(
+        &mut self,
+        params: &InferenceParameters,
+        prompt: &str,
+        callback: &mut dyn FnMut(&[u8]),
+    ) -> Result<(), InferenceError> {
+        callback(prompt.as_bytes());
+        let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        log.prompts.push(prompt.to_string());
+        log.params.push(params.clone());
+        Ok(())
+    }
+
+    fn infer_next_token(
+        &mut self,
+        _params: &InferenceParameters,
+        rng: &mut dyn RngCore,
+    ) -> Result<&[u8], InferenceError> {
+        let token = match &self.answer {
+            MockAnswer::Tokens(tokens) => tokens.get(self.generated).cloned(),
+            MockAnswer::Sampled { words, len } => {
+                (self.generated < *len).then(|| words[rng.gen_range(0..words.len())].clone())
+            }
+        };
+        self.token = token.ok_or(InferenceError::EndOfText)?;
+        self.generated += 1;
+        self.log
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .generated_tokens += 1;
+        Ok(self.token.as_bytes())
+    }
+}
@@ -0,0 +1,97 @@
+//! Backends generate the tokens of answers. The server generates with the models of the
+//! inference backend, the tests with a mock model that doesn't need any weights.
+
+#[cfg(test)]
+pub mod mock;
+
+use llm::{
+    InferenceError, InferenceParameters, InferenceSession, InferenceSessionConfig, Vocabulary,
+};
+use rand::RngCore;
+use std::sync::Arc;
+
+/// Weights of a language model, sessions are started on them for every answer.
+pub trait LanguageModel: Send + Sync {
+    fn start_session(&self, config: InferenceSessionConfig) -> Box<dyn LanguageSession>;
+
+    fn vocabulary(&self) -> &Vocabulary;
+}
+
+/// Context of a single answer, it is fed the prompt before the tokens of the answer are
+/// generated.
+pub trait LanguageSession {
+    /// Feeds `prompt` to the session, `callback` gets every token of it.
+    fn feed_prompt(
+        &mut self,
+        params: &InferenceParameters,
+        prompt: &str,
+        callback: &mut dyn FnMut(&[u8]),
+    ) -> Result<(), InferenceError>;
+
+    /// Generates the next token of the answer, `InferenceError::EndOfText` once it is complete.
+    fn infer_next_token(
+        &mut self,
+        params: &InferenceParameters,
+        rng: &mut dyn RngCore,
+    ) -> Result<&[u8], InferenceError>;
+}
+
+/// Model of the inference backend.
+pub struct BackendModel(Arc<dyn llm::Model>);
+
+impl From<Box<dyn llm::Model>> for BackendModel {
+    fn from(model: Box<dyn llm::Model>) -> Self {
+        Self(model.into())
+    }
+}
+
+impl LanguageModel for BackendModel {
+    fn start_session(&self, config: InferenceSessionConfig) -> Box<dyn LanguageSession> {
+        Box::new(BackendSession {
+            session: self.0.start_session(config),
+            model: self.0.clone(),
+        })
+    }
+
+    fn vocabulary(&self) -> &Vocabulary {
+        self.0.vocabulary()
+    }
+}
+
+struct BackendSession {
+    model: Arc<dyn llm::Model>,
+    session: InferenceSession,
+}
+
+impl LanguageSession for BackendSession {
+    fn feed_prompt(
+        &mut self,
+        params: &InferenceParameters,
+        prompt: &str,
+        callback: &mut dyn FnMut(&[u8]),
+    ) -> Result<(), InferenceError> {
+        self.session.feed_prompt(
+            self.model.as_ref(),
+            params,
+            prompt,
+            &mut Default::default(),
+            |bytes| {
+                callback(bytes);
+                Ok::<(), InferenceError>(())
+            },
+        )
+    }
+
+    fn infer_next_token(
+        &mut self,
+        params: &InferenceParameters,
+        mut rng: &mut dyn RngCore,
+    ) -> Result<&[u8], InferenceError> {
+        self.session.infer_next_token(
+            self.model.as_ref(),
+            params,
+            &mut Default::default(),
+            &mut rng,
+        )
+    }
+}
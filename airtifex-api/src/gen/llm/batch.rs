@@ -0,0 +1,157 @@
+//! Batches run many prompts with the same settings. The prompts are sent to the model queue a
+//! few at a time so that a large batch doesn't hold back the requests of other users, each
+//! answer is stored as soon as it is complete.
+
+use crate::{
+    config::LlmConfig,
+    gen::llm::{token_channel, InferenceRequest},
+    models::{
+        batch::{Batch, BatchEntry},
+        user::User,
+    },
+    queue::QueueSender,
+    request_id,
+    webhook::Webhooks,
+    DbPool,
+};
+use airtifex_core::{
+    llm::{BatchEntryStatus, ChatStreamMessage, InferenceSettings},
+    webhook::{WebhookData, WebhookEvent},
+};
+
+use futures_util::{stream, StreamExt};
+use std::sync::Arc;
+
+/// Number of prompts of a batch that are queued at once for a model with `max_sessions`
+/// concurrent inference sessions. Half of the sessions are left for other requests.
+pub fn batch_concurrency(max_sessions: usize) -> usize {
+    (max_sessions / 2).max(1)
+}
+
+/// Runs the prompts of `batch` to completion, `prompts` are in the order of the batch entries.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_batch(
+    db: Arc<DbPool>,
+    llm_config: LlmConfig,
+    tx_model: QueueSender<InferenceRequest>,
+    batch: Batch,
+    prompts: Vec<String>,
+    settings: InferenceSettings,
+    concurrency: usize,
+    webhooks: Webhooks,
+) {
+    let seed = batch.seed.map(|s| s as u64);
+    stream::iter(prompts.into_iter().enumerate())
+        .for_each_concurrent(concurrency, |(n, prompt)| {
+            let request = BatchPrompt {
+                n: n as i32,
+                prompt,
+                settings: settings.clone(),
+                seed,
+            };
+            run_prompt(&db, &llm_config, &tx_model, &batch, request)
+        })
+        .await;
+    log::info!("finished batch {}", batch.id);
+    notify_done(&db, &batch, &webhooks).await;
+}
+
+async fn notify_done(db: &DbPool, batch: &Batch, webhooks: &Webhooks) {
+    let result = match BatchEntry::list(db, &batch.id).await {
+        Ok(entries) => User::get(db, &batch.username)
+            .await
+            .map(|user| (user.id, entries)),
+        Err(e) => Err(e),
+    };
+    let (user_id, entries) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            log::error!("failed to notify webhooks of batch {} - {e}", batch.id);
+            return;
+        }
+    };
+    let count = |status| entries.iter().filter(|e| e.status == status).count() as u64;
+    webhooks.notify(
+        user_id,
+        WebhookEvent::BatchDone,
+        WebhookData::Batch {
+            batch_id: batch.id.to_string(),
+            model: batch.model.clone(),
+            done: count(BatchEntryStatus::Done),
+            failed: count(BatchEntryStatus::Failed),
+        },
+    );
+}
+
+struct BatchPrompt {
+    n: i32,
+    prompt: String,
+    settings: InferenceSettings,
+    seed: Option<u64>,
+}
+
+async fn run_prompt(
+    db: &DbPool,
+    llm_config: &LlmConfig,
+    tx_model: &QueueSender<InferenceRequest>,
+    batch: &Batch,
+    request: BatchPrompt,
+) {
+    let n = request.n;
+    let (tx_tokens, rx_tokens) = token_channel(llm_config);
+    let inference_request = InferenceRequest {
+        tx_tokens,
+        user: batch.username.clone(),
+        save: false,
+        chat_data: None,
+        prompt: request.prompt,
+        settings: request.settings,
+        play_back_tokens: false,
+        template: None,
+        json_schema: None,
+        seed: request.seed,
+        repeat_last_n: None,
+        n_threads: None,
+        no_cache: false,
+        queue_ticket: None,
+        request_id: request_id::current(),
+    };
+
+    if let Err(e) = BatchEntry::start(db, &batch.id, n).await {
+        log::error!("failed to start prompt {n} of batch {} - {e}", batch.id);
+    }
+    let result = match tx_model.send(inference_request) {
+        Ok(_) => collect_answer(rx_tokens).await,
+        Err(e) => Err((String::new(), format!("failed to queue the prompt - {e}"))),
+    };
+
+    let result = match &result {
+        Ok(answer) => BatchEntry::finish(db, &batch.id, n, answer).await,
+        Err((answer, error)) => {
+            let answer = Some(answer.as_str()).filter(|a| !a.is_empty());
+            BatchEntry::fail(db, &batch.id, n, answer, error).await
+        }
+    };
+    if let Err(e) = result {
+        log::error!("failed to save prompt {n} of batch {} - {e}", batch.id);
+    }
+}
+
+/// Waits for the whole answer, the error contains the part of the answer received before it.
+async fn collect_answer(
+    rx_tokens: flume::Receiver<ChatStreamMessage>,
+) -> Result<String, (String, String)> {
+    let mut answer = String::new();
+    // the sender is dropped once the inference session ends
+    while let Ok(message) = rx_tokens.recv_async().await {
+        match message.into_result() {
+            Some(Ok(token)) => answer.push_str(&token),
+            Some(Err(e)) => return Err((answer, e)),
+            None => {}
+        }
+    }
+    if answer.is_empty() {
+        return Err((answer, "the model didn't generate an answer".into()));
+    }
+    Ok(answer)
+}
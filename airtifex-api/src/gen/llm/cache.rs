@@ -0,0 +1,136 @@
+//! Answers of seeded requests kept for later requests with the same prompt and parameters.
+//! Sampling with the same seed generates the same answer again, so the answer is replayed instead
+//! of running the model. The cache belongs to the loaded weights, a reload starts with an empty
+//! one.
+
+use airtifex_core::llm::{ChatStreamMessage, StopReason};
+
+use llm::InferenceParameters;
+use std::collections::HashMap;
+
+/// Everything the answer of a seeded request depends on besides the weights of the model.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    prompt: String,
+    seed: u64,
+    n_batch: usize,
+    top_k: usize,
+    top_p: u32,
+    repeat_penalty: u32,
+    temperature: u32,
+    repeat_last_n: usize,
+    num_predict: Option<usize>,
+    json_schema: Option<String>,
+    play_back_tokens: bool,
+}
+
+impl CacheKey {
+    /// Key of the answer to the fully rendered `prompt`.
+    pub fn new(
+        prompt: &str,
+        seed: u64,
+        params: &InferenceParameters,
+        num_predict: Option<usize>,
+        json_schema: Option<&serde_json::Value>,
+        play_back_tokens: bool,
+    ) -> Self {
+        Self {
+            prompt: prompt.to_string(),
+            seed,
+            n_batch: params.n_batch,
+            top_k: params.top_k,
+            // the bits of the floats, equal parameters generate equal answers
+            top_p: params.top_p.to_bits(),
+            repeat_penalty: params.repeat_penalty.to_bits(),
+            temperature: params.temperature.to_bits(),
+            repeat_last_n: params.repetition_penalty_last_n,
+            num_predict,
+            json_schema: json_schema.map(|schema| schema.to_string()),
+            play_back_tokens,
+        }
+    }
+}
+
+/// Answer as it was sent to the client.
+#[derive(Clone, Debug)]
+pub struct CachedAnswer {
+    /// Messages of the answer without its usage and end.
+    pub messages: Vec<ChatStreamMessage>,
+    pub answer: String,
+    pub generated_tokens: usize,
+    pub reason: StopReason,
+}
+
+impl CachedAnswer {
+    /// Whether an answer that ended for `reason` is generated the same way again. Answers that
+    /// were stopped from the outside are incomplete.
+    pub fn is_reproducible(reason: StopReason) -> bool {
+        matches!(
+            reason,
+            StopReason::EndOfText
+                | StopReason::MaxTokens
+                | StopReason::Repetition
+                | StopReason::EmptyAnswer
+        )
+    }
+
+    /// Messages that replay the answer, its usage and its end included.
+    pub fn replay(self) -> impl Iterator<Item = ChatStreamMessage> {
+        let end = [
+            ChatStreamMessage::Usage {
+                generated_tokens: self.generated_tokens,
+            },
+            ChatStreamMessage::Done {
+                reason: self.reason,
+            },
+        ];
+        self.messages.into_iter().chain(end)
+    }
+}
+
+/// The `capacity` most recently used answers, a capacity of `0` disables the cache.
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    capacity: usize,
+    entries: HashMap<CacheKey, (u64, CachedAnswer)>,
+    /// Incremented on every use, the entry with the lowest use is evicted first.
+    uses: u64,
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn get(&mut self, key: &CacheKey) -> Option<CachedAnswer> {
+        self.uses += 1;
+        let (last_use, answer) = self.entries.get_mut(key)?;
+        *last_use = self.uses;
+        Some(answer.clone())
+    }
+
+    pub fn insert(&mut self, key: CacheKey, answer: CachedAnswer) {
+        if !self.is_enabled() {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let least_recent = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (last_use, _))| *last_use)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recent) = least_recent {
+                self.entries.remove(&least_recent);
+            }
+        }
+        self.uses += 1;
+        self.entries.insert(key, (self.uses, answer));
+    }
+}
@@ -0,0 +1,212 @@
+//! Grouping of the tokens of a chat answer into the messages of its stream. Clients rendering
+//! Markdown while the answer arrives misrender text that ends inside of a word, an emphasis or a
+//! code span, so depending on the [`StreamGranularity`] the text is held back until a boundary
+//! where it renders the same way as the complete answer would.
+
+use airtifex_core::llm::StreamGranularity;
+
+/// Returns the length in bytes of the start of `text` that can be sent with the granularity,
+/// the rest has to wait for more tokens or the end of the answer. The text has to start where
+/// the previously sent text ended.
+///
+/// A line break is always a boundary. Otherwise a word ends with the whitespace after it and a
+/// sentence with the whitespace after a `.`, `!`, `?` or `…`, neither inside of an emphasis,
+/// strikethrough or code span nor on the opening or closing line of a code block.
+pub fn flush_len(text: &str, granularity: StreamGranularity) -> usize {
+    if granularity == StreamGranularity::Token {
+        return text.len();
+    }
+
+    let mut boundary = 0;
+    let mut line = LineState::default();
+    let mut prev = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\n' => {
+                boundary = i + 1;
+                line = LineState::default();
+            }
+            '`' | '*' | '~' => {
+                let mut run = 1;
+                while chars.next_if(|(_, next)| *next == c).is_some() {
+                    run += 1;
+                }
+                let next = chars.peek().map(|(_, next)| *next);
+                line.marker(c, run, prev, next);
+                line.is_start = false;
+            }
+            c if c.is_whitespace() => {
+                let ends_chunk = match granularity {
+                    StreamGranularity::Sentence => line.is_sentence_end,
+                    _ => !line.is_start,
+                };
+                if ends_chunk && line.is_closed() {
+                    boundary = i + c.len_utf8();
+                }
+                line.is_sentence_end = false;
+            }
+            c => {
+                line.is_start = false;
+                // closing quotes and brackets belong to the sentence they end
+                if !matches!(c, '"' | '\'' | ')' | ']' | '”' | '’' | '»') {
+                    line.is_sentence_end = matches!(c, '.' | '!' | '?' | '…');
+                }
+            }
+        }
+        prev = Some(c);
+    }
+    boundary
+}
+
+/// Markup opened on the current line, the line starts with the text or after a line break.
+struct LineState {
+    is_start: bool,
+    is_fence: bool,
+    /// Length of the backtick run that opened the current code span.
+    code_span: Option<usize>,
+    is_emphasis: bool,
+    is_strong: bool,
+    is_strikethrough: bool,
+    is_sentence_end: bool,
+}
+
+impl Default for LineState {
+    fn default() -> Self {
+        Self {
+            is_start: true,
+            is_fence: false,
+            code_span: None,
+            is_emphasis: false,
+            is_strong: false,
+            is_strikethrough: false,
+            is_sentence_end: false,
+        }
+    }
+}
+
+impl LineState {
+    /// Whether text ending here renders like it does once the line is complete.
+    fn is_closed(&self) -> bool {
+        !self.is_fence
+            && self.code_span.is_none()
+            && !self.is_emphasis
+            && !self.is_strong
+            && !self.is_strikethrough
+    }
+
+    /// Opens or closes the markup of a run of `run` marker characters `c` between `prev` and
+    /// `next`.
+    fn marker(&mut self, c: char, run: usize, prev: Option<char>, next: Option<char>) {
+        if c == '`' && self.is_start && run >= 3 {
+            self.is_fence = true;
+            return;
+        }
+        if let Some(open) = self.code_span {
+            if c == '`' && run == open {
+                self.code_span = None;
+            }
+            return;
+        }
+        match c {
+            '`' => self.code_span = Some(run),
+            // a run with whitespace on both sides is a list bullet or a multiplication
+            '*' if prev.is_none_or(char::is_whitespace)
+                && next.is_some_and(char::is_whitespace) => {}
+            '*' => {
+                if run % 2 == 1 {
+                    self.is_emphasis = !self.is_emphasis;
+                }
+                if run >= 2 {
+                    self.is_strong = !self.is_strong;
+                }
+            }
+            '~' if run == 2 => self.is_strikethrough = !self.is_strikethrough,
+            _ => {}
+        }
+    }
+}
+
+/// Tokens of an answer that wait for a boundary of the granularity before they are sent.
+#[derive(Debug, Default)]
+pub struct ChunkBuffer {
+    granularity: StreamGranularity,
+    pending: String,
+}
+
+impl ChunkBuffer {
+    pub fn new(granularity: StreamGranularity) -> Self {
+        Self {
+            granularity,
+            pending: String::new(),
+        }
+    }
+
+    /// Adds a token to the buffer, returns the text that can be sent now.
+    pub fn push(&mut self, token: &str) -> Option<String> {
+        self.pending.push_str(token);
+        let len = flush_len(&self.pending, self.granularity);
+        (len > 0).then(|| self.pending.drain(..len).collect())
+    }
+
+    /// Returns the text left in the buffer, used once the answer ended.
+    pub fn flush(&mut self) -> Option<String> {
+        (!self.pending.is_empty()).then(|| std::mem::take(&mut self.pending))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_flushed_at_the_boundaries_of_the_granularity() {
+        // text, flushed with word granularity, flushed with sentence granularity
+        for (text, word, sentence) in [
+            ("Hi there again", "Hi there ", ""),
+            ("Hi there. Next", "Hi there. ", "Hi there. "),
+            ("*a b*", "", ""),
+            ("*a b* c", "*a b* ", ""),
+            ("**a b** c", "**a b** ", ""),
+            ("~~a b~~ c", "~~a b~~ ", ""),
+            ("* a b", "* a ", ""),
+            ("`x y`", "", ""),
+            ("`x y` z", "`x y` ", ""),
+            ("``a ` b`` c", "``a ` b`` ", ""),
+            ("```rust code", "", ""),
+            (
+                "```rust\nfn x() {}\n``` done",
+                "```rust\nfn x() {}\n",
+                "```rust\nfn x() {}\n",
+            ),
+            ("\"Hi.\" next", "\"Hi.\" ", "\"Hi.\" "),
+            ("(Hi!) next", "(Hi!) ", "(Hi!) "),
+            ("Wait… what", "Wait… ", "Wait… "),
+            ("Hmm…", "", ""),
+            ("line\nbreak", "line\n", "line\n"),
+        ] {
+            for (granularity, flushed) in [
+                (StreamGranularity::Token, text),
+                (StreamGranularity::Word, word),
+                (StreamGranularity::Sentence, sentence),
+            ] {
+                assert_eq!(
+                    &text[..flush_len(text, granularity)],
+                    flushed,
+                    "{text:?} with {granularity:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn buffered_tokens_are_sent_in_chunks() {
+        let mut buffer = ChunkBuffer::new(StreamGranularity::Word);
+        let mut sent: Vec<_> = ["Hel", "lo *wor", "ld* a", "nd", " more"]
+            .into_iter()
+            .filter_map(|token| buffer.push(token))
+            .collect();
+        sent.extend(buffer.flush());
+        assert_eq!(sent, ["Hello ", "*world* ", "and ", "more"]);
+    }
+}
@@ -0,0 +1,538 @@
+//! Download of the weights of language models that aren't on disk yet. The weights are fetched
+//! from the `model_url` of the model into a `.part` file next to `model_path`, an interrupted
+//! download continues where it stopped with a range request, also after a restart of the server.
+//! The file is moved to `model_path` once it is complete and matches `model_sha256`.
+
+use crate::{config::LlmConfig, gen::llm::load::LoadProgressTracker, webhook::tls_connector};
+
+use hyper::{
+    body::HttpBody,
+    client::conn::SendRequest,
+    header::{CONTENT_LENGTH, CONTENT_RANGE, HOST, LOCATION, RANGE, USER_AGENT},
+    Body, Request, Response, StatusCode, Uri,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use thiserror::Error as ErrorType;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::rustls::ServerName;
+
+/// Attempts to finish a download before giving up, each one continues the previous one.
+const MAX_ATTEMPTS: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_REDIRECTS: usize = 10;
+/// Time to wait for the next part of the body before the attempt counts as failed.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, ErrorType)]
+pub enum DownloadError {
+    #[error("invalid model url {0}")]
+    InvalidUrl(String),
+    #[error("failed to download {url} - {error}")]
+    Failed { url: String, error: String },
+    #[error("downloading {url} failed with {status}")]
+    Status { url: String, status: StatusCode },
+    #[error("failed to write {path} - {error}")]
+    Io { path: String, error: std::io::Error },
+    #[error("checksum of {path} is {actual} instead of {expected}, the weights aren't loaded")]
+    ChecksumMismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl DownloadError {
+    /// Whether another attempt can get further, failures of the server or the connection are
+    /// usually temporary.
+    fn is_temporary(&self) -> bool {
+        match self {
+            DownloadError::Failed { .. } => true,
+            DownloadError::Status { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+/// Makes sure the weights of the model are at its `model_path` before they are loaded. Missing
+/// weights are downloaded from `model_url`, existing ones are never replaced. Weights that don't
+/// match `model_sha256` are refused.
+pub async fn fetch_model(
+    config: &LlmConfig,
+    progress: &LoadProgressTracker,
+) -> Result<(), DownloadError> {
+    let path = &config.model_path;
+    let expected = config.model_sha256.as_deref();
+    let Some(url) = config.model_url.as_deref().filter(|_| !path.is_file()) else {
+        return verify(path, expected);
+    };
+
+    let partial = partial_path(path);
+    log::info!("downloading {url} to {}", path.display());
+    let mut attempt = 1;
+    loop {
+        match download(url, &partial, progress).await {
+            Ok(()) => break,
+            Err(e) if attempt < MAX_ATTEMPTS && e.is_temporary() => {
+                log::warn!("attempt {attempt}/{MAX_ATTEMPTS} to download {url} failed - {e}");
+                tokio::time::sleep(RETRY_DELAY).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if let Err(e) = verify(&partial, expected) {
+        // the next start downloads the weights from scratch
+        let _ = std::fs::remove_file(&partial);
+        return Err(e);
+    }
+    std::fs::rename(&partial, path).map_err(|error| io_error(path, error))?;
+    log::info!("downloaded {url} to {}", path.display());
+    Ok(())
+}
+
+/// File the weights are downloaded to until they are complete.
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    partial.into()
+}
+
+/// Downloads the rest of the file after the bytes already in `partial`.
+async fn download(
+    url: &str,
+    partial: &Path,
+    progress: &LoadProgressTracker,
+) -> Result<(), DownloadError> {
+    let mut offset = std::fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
+    let response = get(url, offset).await?;
+    let failed = |error: String| DownloadError::Failed {
+        url: url.to_string(),
+        error,
+    };
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(partial)
+        .map_err(|error| io_error(partial, error))?;
+    let total = match response.status() {
+        StatusCode::PARTIAL_CONTENT => {
+            let (start, total) = content_range(&response)
+                .ok_or_else(|| failed("the server sent an invalid content range".into()))?;
+            if start != offset {
+                return Err(failed(format!(
+                    "the server continued at byte {start} instead of {offset}"
+                )));
+            }
+            total
+        }
+        // the server doesn't support ranges, the file is downloaded from the start again
+        StatusCode::OK => {
+            file.set_len(0).map_err(|error| io_error(partial, error))?;
+            offset = 0;
+            content_length(&response)
+        }
+        // the file was already complete, the checksum tells whether it is the right one
+        StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(()),
+        status => {
+            return Err(DownloadError::Status {
+                url: url.to_string(),
+                status,
+            })
+        }
+    };
+    progress.start_download(offset, total);
+
+    let mut body = response.into_body();
+    loop {
+        let chunk = match tokio::time::timeout(READ_TIMEOUT, body.data()).await {
+            Ok(Some(chunk)) => chunk.map_err(|e| failed(e.to_string()))?,
+            Ok(None) => break,
+            Err(_) => return Err(failed("the server stopped sending".into())),
+        };
+        file.write_all(&chunk)
+            .map_err(|error| io_error(partial, error))?;
+        progress.add_downloaded(chunk.len());
+    }
+    file.sync_all().map_err(|error| io_error(partial, error))
+}
+
+/// Sends a GET request for the file after the first `offset` bytes, following redirects.
+async fn get(url: &str, offset: u64) -> Result<Response<Body>, DownloadError> {
+    let mut uri = url
+        .parse::<Uri>()
+        .map_err(|e| DownloadError::InvalidUrl(format!("{url} - {e}")))?;
+    for _ in 0..MAX_REDIRECTS {
+        let response = send(&uri, offset)
+            .await
+            .map_err(|error| DownloadError::Failed {
+                url: url.to_string(),
+                error,
+            })?;
+        if !response.status().is_redirection() {
+            return Ok(response);
+        }
+        let Some(location) = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+        else {
+            return Ok(response);
+        };
+        uri = redirect_target(&uri, location)
+            .ok_or_else(|| DownloadError::InvalidUrl(format!("{url} redirected to {location}")))?;
+    }
+    Err(DownloadError::Failed {
+        url: url.to_string(),
+        error: format!("more than {MAX_REDIRECTS} redirects"),
+    })
+}
+
+/// Resolves the `Location` of a redirect, which can be relative to the requested URI.
+fn redirect_target(uri: &Uri, location: &str) -> Option<Uri> {
+    let target = location.parse::<Uri>().ok()?;
+    if target.scheme().is_some() {
+        return Some(target);
+    }
+    let mut parts = target.into_parts();
+    parts.scheme = uri.scheme().cloned();
+    parts.authority = uri.authority().cloned();
+    Uri::from_parts(parts).ok()
+}
+
+async fn send(uri: &Uri, offset: u64) -> Result<Response<Body>, String> {
+    let https = match uri.scheme_str() {
+        Some("https") => true,
+        Some("http") => false,
+        _ => return Err("the model url must be an http or https URL".into()),
+    };
+    let Some(authority) = uri.authority().cloned() else {
+        return Err("the model url doesn't contain a host".into());
+    };
+    let host = authority
+        .host()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = authority.port_u16().unwrap_or(if https { 443 } else { 80 });
+    let addrs = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("failed to resolve {host} - {e}"))?
+        .collect::<Vec<SocketAddr>>();
+    let stream = TcpStream::connect(addrs.as_slice())
+        .await
+        .map_err(|e| format!("failed to connect to {authority} - {e}"))?;
+
+    let mut request = Request::get(uri.path_and_query().map(|p| p.as_str()).unwrap_or("/"))
+        .header(HOST, authority.as_str())
+        .header(USER_AGENT, "airtifex");
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={offset}-"));
+    }
+    let request = request.body(Body::empty()).map_err(|e| e.to_string())?;
+
+    let mut sender = if https {
+        let server_name =
+            ServerName::try_from(host).map_err(|e| format!("invalid host {host} - {e}"))?;
+        let stream = tls_connector()
+            .connect(server_name, stream)
+            .await
+            .map_err(|e| format!("TLS handshake with {authority} failed - {e}"))?;
+        handshake(stream).await?
+    } else {
+        handshake(stream).await?
+    };
+    sender
+        .send_request(request)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn handshake<T>(io: T) -> Result<SendRequest<Body>, String>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, connection) = hyper::client::conn::handshake(io)
+        .await
+        .map_err(|e| e.to_string())?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::debug!("download connection closed - {e}");
+        }
+    });
+    Ok(sender)
+}
+
+/// First byte and total size of a `Content-Range: bytes <start>-<end>/<total>` header, the total
+/// can be unknown.
+fn content_range(response: &Response<Body>) -> Option<(u64, Option<u64>)> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    Some((start.parse().ok()?, total.parse().ok()))
+}
+
+fn content_length(response: &Response<Body>) -> Option<u64> {
+    response
+        .headers()
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Checks the file against the hex encoded SHA-256, files are accepted without one.
+fn verify(path: &Path, expected: Option<&str>) -> Result<(), DownloadError> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let actual = sha256(path).map_err(|error| io_error(path, error))?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(DownloadError::ChecksumMismatch {
+            path: path.display().to_string(),
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Hex encoded SHA-256 of the file.
+pub fn sha256(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> DownloadError {
+    DownloadError::Io {
+        path: path.display().to_string(),
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::{http::HeaderMap, routing::get, Router};
+    use std::sync::{Arc, Mutex};
+
+    const WEIGHTS: &[u8] = b"weights of the model that the tests download";
+
+    /// How the server of the tests answers requests for the weights.
+    #[derive(Clone, Copy)]
+    enum Server {
+        /// Sends the rest of the weights after the start of a range request.
+        Ranges,
+        /// Ignores range requests and sends all of the weights.
+        NoRanges,
+        Missing,
+    }
+
+    /// Serves the weights on a free port. Returns their URL and the `Range` headers of the
+    /// requests that were sent for them.
+    async fn serve(server: Server) -> (String, Arc<Mutex<Vec<Option<String>>>>) {
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let requests = ranges.clone();
+        let router = Router::new().route(
+            "/model.bin",
+            get(move |headers: HeaderMap| async move {
+                let range = headers
+                    .get(RANGE)
+                    .map(|range| range.to_str().unwrap().to_string());
+                requests.lock().unwrap().push(range.clone());
+                let start = range
+                    .and_then(|range| {
+                        range
+                            .strip_prefix("bytes=")?
+                            .strip_suffix('-')?
+                            .parse()
+                            .ok()
+                    })
+                    .unwrap_or(0);
+                let len = WEIGHTS.len();
+                match server {
+                    Server::Ranges if start >= len => {
+                        (StatusCode::RANGE_NOT_SATISFIABLE, HeaderMap::new(), vec![])
+                    }
+                    Server::Ranges if start > 0 => {
+                        let mut headers = HeaderMap::new();
+                        let range = format!("bytes {start}-{}/{len}", len - 1);
+                        headers.insert(CONTENT_RANGE, range.parse().unwrap());
+                        (
+                            StatusCode::PARTIAL_CONTENT,
+                            headers,
+                            WEIGHTS[start..].to_vec(),
+                        )
+                    }
+                    Server::Ranges | Server::NoRanges => {
+                        (StatusCode::OK, HeaderMap::new(), WEIGHTS.to_vec())
+                    }
+                    Server::Missing => (StatusCode::NOT_FOUND, HeaderMap::new(), vec![]),
+                }
+            }),
+        );
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+        let url = format!("http://{}/model.bin", server.local_addr());
+        tokio::spawn(server);
+        (url, ranges)
+    }
+
+    fn config(dir: &Path, url: Option<String>, sha256: &str) -> LlmConfig {
+        let mut config: LlmConfig =
+            serde_yaml::from_str("type: llama\nmodel_path: llama.bin").expect("config is valid");
+        config.model_path = dir.join("llama.bin");
+        config.model_url = url;
+        config.model_sha256 = Some(sha256.to_string());
+        config
+    }
+
+    fn checksum(bytes: &[u8]) -> String {
+        Sha256::digest(bytes)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    const WRONG_CHECKSUM: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+    #[tokio::test]
+    async fn existing_weights_are_verified() {
+        let dir = tempfile::tempdir().unwrap();
+        let progress = LoadProgressTracker::default();
+        std::fs::write(dir.path().join("llama.bin"), WEIGHTS).unwrap();
+
+        let config = config(dir.path(), None, &checksum(WEIGHTS));
+        fetch_model(&config, &progress).await.unwrap();
+
+        let config = self::config(dir.path(), None, WRONG_CHECKSUM);
+        let error = fetch_model(&config, &progress).await.unwrap_err();
+        assert!(
+            matches!(&error, DownloadError::ChecksumMismatch { actual, .. } if *actual == checksum(WEIGHTS)),
+            "{error}"
+        );
+        // weights that are there already are never replaced
+        assert_eq!(std::fs::read(&config.model_path).unwrap(), WEIGHTS);
+    }
+
+    #[tokio::test]
+    async fn partial_downloads_are_resumed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, ranges) = serve(Server::Ranges).await;
+        let config = config(dir.path(), Some(url), &checksum(WEIGHTS));
+        let partial = partial_path(&config.model_path);
+        std::fs::write(&partial, &WEIGHTS[..10]).unwrap();
+
+        fetch_model(&config, &LoadProgressTracker::default())
+            .await
+            .unwrap();
+
+        assert_eq!(*ranges.lock().unwrap(), [Some("bytes=10-".to_string())]);
+        assert_eq!(std::fs::read(&config.model_path).unwrap(), WEIGHTS);
+        assert!(!partial.exists());
+    }
+
+    #[tokio::test]
+    async fn complete_partial_downloads_are_verified() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, ranges) = serve(Server::Ranges).await;
+        let config = config(dir.path(), Some(url), &checksum(WEIGHTS));
+        std::fs::write(partial_path(&config.model_path), WEIGHTS).unwrap();
+
+        fetch_model(&config, &LoadProgressTracker::default())
+            .await
+            .unwrap();
+
+        let range = format!("bytes={}-", WEIGHTS.len());
+        assert_eq!(*ranges.lock().unwrap(), [Some(range)]);
+        assert_eq!(std::fs::read(&config.model_path).unwrap(), WEIGHTS);
+    }
+
+    #[tokio::test]
+    async fn downloads_restart_without_range_support() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, _) = serve(Server::NoRanges).await;
+        let config = config(dir.path(), Some(url), &checksum(WEIGHTS));
+        std::fs::write(partial_path(&config.model_path), b"stale bytes").unwrap();
+
+        fetch_model(&config, &LoadProgressTracker::default())
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&config.model_path).unwrap(), WEIGHTS);
+    }
+
+    #[tokio::test]
+    async fn mismatching_downloads_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, _) = serve(Server::Ranges).await;
+        let config = config(dir.path(), Some(url), WRONG_CHECKSUM);
+        let partial = partial_path(&config.model_path);
+        std::fs::write(&partial, &WEIGHTS[..10]).unwrap();
+
+        let error = fetch_model(&config, &LoadProgressTracker::default())
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(error, DownloadError::ChecksumMismatch { .. }),
+            "{error}"
+        );
+        // the next start downloads the weights from scratch instead of loading them
+        assert!(!config.model_path.exists());
+        assert!(!partial.exists());
+    }
+
+    #[tokio::test]
+    async fn aborted_downloads_keep_the_partial_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, ranges) = serve(Server::Missing).await;
+        let config = config(dir.path(), Some(url), &checksum(WEIGHTS));
+        let partial = partial_path(&config.model_path);
+        std::fs::write(&partial, &WEIGHTS[..10]).unwrap();
+
+        let error = fetch_model(&config, &LoadProgressTracker::default())
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(
+                error,
+                DownloadError::Status {
+                    status: StatusCode::NOT_FOUND,
+                    ..
+                }
+            ),
+            "{error}"
+        );
+        // errors of the client aren't retried
+        assert_eq!(ranges.lock().unwrap().len(), 1);
+        assert!(!config.model_path.exists());
+        assert_eq!(std::fs::read(&partial).unwrap(), &WEIGHTS[..10]);
+    }
+}
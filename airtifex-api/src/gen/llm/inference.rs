@@ -1,47 +1,90 @@
 use crate::{
-    config::{LlmConfig, LlmType},
-    gen::ModelName,
+    config::LlmConfig,
+    gen::{
+        llm::{
+            affinity,
+            backend::{LanguageModel, LanguageSession},
+            cache::{CacheKey, CachedAnswer, ResponseCache},
+            download::fetch_model,
+            json,
+            load::load_model,
+            prefix,
+            reload::ModelSwap,
+            repetition,
+            tokenize::LoadedModel,
+        },
+        ModelName,
+    },
     id::Uuid,
-    models::{chat_entry::ChatEntry, prompt::Prompt},
-    queue,
+    metrics::LlmMetrics,
+    models::{chat_entry::ChatEntry, prompt::Prompt, stats::InferenceStat},
+    moderation::{Decision, Moderator},
+    queue::{self, QueueSender, QueueTicket, Queued},
+    request_id,
+};
+use airtifex_core::llm::{
+    ChatEntryParams, ChatEntryType, ChatStreamMessage, InferenceSettings, StopReason,
 };
-use airtifex_core::llm::{ChatEntryType, ChatStreamResult, InferenceSettings};
 
 use llm::{
-    InferenceError, InferenceParameters, InferenceSession, InferenceSessionConfig, LoadProgress,
-    Model, ModelKVMemoryType, TokenBias,
+    InferenceError, InferenceParameters, InferenceSessionConfig, ModelKVMemoryType, TokenBias,
 };
-use rand::{rngs::ThreadRng, thread_rng};
-use std::{collections::VecDeque, sync::Arc};
-use tokio::runtime::Runtime;
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+use std::{
+    collections::VecDeque,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
+use tokio::{runtime::Runtime, sync::oneshot};
+
+use flume::{unbounded, Receiver, Selector, Sender, TrySendError};
 
-use flume::{unbounded, Receiver, Sender};
+const DEFAULT_SYSTEM_PROMPT: &str = r#"Your name is Assistant and you are a helpful virtual assistant.
+As Assistant, you fulfill users request in the most effective way and your answer is never empty."#;
 
-const ANSWER_PREFIX: &str = "Assistant: ";
-const USER_PREFIX: &str = "User: ";
-const CONVERSATION_PROMPT: &str = r#"Your name is Assistant and you are a helpful virtual assistant.
-As Assistant, you fulfill users request in the most effective way and your answer is never empty.
-Below is a dialog between a user and you.
-Write a response to the request in the '### Request:' section that appropriately completes the request.
+/// Prompt the model is warmed up with after loading.
+const WARM_UP_PROMPT: &str = "Hello";
 
-### Conversation:
-{{HISTORY}}
+/// Time the inference thread sleeps when all running sessions wait for their receivers.
+const BACKPRESSURE_PAUSE: Duration = Duration::from_millis(5);
 
-### Request:
-{{PROMPT}}
+/// Bytes at the end of an answer the moderator checks after every token. The text before was
+/// checked with the previous tokens, only blocked texts longer than this can slip through.
+const MODERATION_WINDOW: usize = 512;
 
-### Response:"#;
+/// Added to the temperature of an answer that is retried because it was empty.
+const EMPTY_ANSWER_TEMPERATURE_INCREASE: f32 = 0.2;
+
+/// Channel the answer of a request is streamed through. A session stops generating while the
+/// channel is full so that the answer isn't buffered without a limit for a slow receiver.
+pub fn token_channel(
+    config: &LlmConfig,
+) -> (Sender<ChatStreamMessage>, Receiver<ChatStreamMessage>) {
+    flume::bounded(config.token_channel_capacity.max(1))
+}
 
 #[derive(Debug)]
 pub struct ChatData {
     pub conversation_id: Uuid,
     pub history: Vec<ChatEntry>,
+    /// The prompt is already stored as an entry of the chat, like an edited prompt that is
+    /// answered again, so only the answer is saved.
+    pub is_prompt_saved: bool,
+    /// Number of previous turns given to the model, the whole history when `None`.
+    pub context_turns: Option<usize>,
+}
+
+/// Template a oneshot prompt was rendered from.
+#[derive(Debug)]
+pub struct PromptTemplate {
+    pub template: String,
+    pub variables: Vec<String>,
 }
 
 #[derive(Debug)]
 pub struct InferenceRequest {
-    /// The channel to send the tokens to.
-    pub tx_tokens: Sender<ChatStreamResult>,
+    /// The channel to send the tokens of the answer to, followed by its usage and how it ended.
+    pub tx_tokens: Sender<ChatStreamMessage>,
 
     pub user: String,
     pub save: bool,
@@ -49,20 +92,118 @@ pub struct InferenceRequest {
     pub prompt: String,
     pub settings: InferenceSettings,
     pub play_back_tokens: bool,
+    /// Saved in place of the rendered prompt so that it can be generated again.
+    pub template: Option<PromptTemplate>,
+    /// Schema the answer has to match. The answer is sent at once after it was validated, when
+    /// it doesn't match the raw answer is followed by the error.
+    pub json_schema: Option<serde_json::Value>,
+    /// Seeds the sampling so that the same prompt and settings generate the same answer, the
+    /// answer is sampled with a random seed when not set.
+    pub seed: Option<u64>,
+    /// Number of the last tokens the repetition penalty applies to, `repeat_last_n` of the
+    /// configuration when not set.
+    pub repeat_last_n: Option<usize>,
+    /// Number of threads the answer is generated with, capped at `num_threads` of the
+    /// configuration which is also used when not set.
+    pub n_threads: Option<usize>,
+    /// Generates the answer even if the same seeded request was answered before.
+    pub no_cache: bool,
+    /// Place of the request in the queue of the model, given up once a session is started.
+    pub queue_ticket: Option<QueueTicket>,
+    /// Id of the request that queued the inference, the session reuses it as its id.
+    pub request_id: Uuid,
+}
+
+impl Queued for InferenceRequest {
+    fn set_queue_ticket(&mut self, ticket: QueueTicket) {
+        self.queue_ticket = Some(ticket);
+    }
+}
+
+impl InferenceRequest {
+    /// Ends the answer of a request that is dropped before it was started.
+    fn abort(self) {
+        let messages = [
+            ChatStreamMessage::Error {
+                message: "the request was aborted before it was started".into(),
+            },
+            ChatStreamMessage::Usage {
+                generated_tokens: 0,
+            },
+            ChatStreamMessage::Done {
+                reason: StopReason::Aborted,
+            },
+        ];
+        for message in messages {
+            let _ = self.tx_tokens.try_send(message);
+        }
+    }
+}
+
+/// Commands handled by the inference thread of a model between tokens.
+#[allow(clippy::large_enum_variant)]
+pub enum ModelCommand {
+    /// Swaps in new weights, see [`ModelReloader`](super::ModelReloader).
+    Swap(ModelSwap),
+    ClearQueue(QueueClear),
+}
+
+/// Aborts the requests waiting for the model, with `cancel_running` its running sessions too.
+pub struct QueueClear {
+    pub cancel_running: bool,
+    pub tx_cleared: oneshot::Sender<QueueCleared>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QueueCleared {
+    /// Number of waiting requests that were aborted.
+    pub cleared: usize,
+    /// Number of running sessions that were stopped.
+    pub cancelled: usize,
+}
+
+/// Clears the queue of the model `tx_commands` belongs to, `None` when its inference thread
+/// stopped. Requests sent while the queue is cleared are either aborted or kept.
+pub async fn clear_queue(
+    tx_commands: &Sender<ModelCommand>,
+    cancel_running: bool,
+) -> Option<QueueCleared> {
+    let (tx_cleared, rx_cleared) = oneshot::channel();
+    let clear = QueueClear {
+        cancel_running,
+        tx_cleared,
+    };
+    tx_commands
+        .send_async(ModelCommand::ClearQueue(clear))
+        .await
+        .ok()?;
+    rx_cleared.await.ok()
 }
 
 #[derive(Debug)]
 pub enum SaveDataRequest {
     Chat {
         conversation_id: Uuid,
-        input: String,
+        /// `None` when the prompt is already saved.
+        input: Option<String>,
         output: String,
+        seed: u64,
+        /// Parameters the answer was generated with.
+        params: InferenceParameters,
+        num_predict: Option<usize>,
     },
     Prompt {
         input: String,
         output: String,
         username: String,
         settings: InferenceSettings,
+        variables: Vec<String>,
+    },
+    /// Timing of a finished session, every session sends it whether its results are saved or not.
+    Stats {
+        queue_wait: Duration,
+        generation_time: Duration,
+        generated_tokens: usize,
     },
 }
 
@@ -72,275 +213,834 @@ struct InferenceState {
     pub answer: String,
     pub processed_prompt: String,
     pub is_finished: bool,
+    /// The start of the answer was sent, further tokens are sent as they are generated.
+    pub is_answer_started: bool,
+}
+
+/// Event the idle inference thread waits for.
+enum Idle {
+    Request(InferenceRequest),
+    Command(ModelCommand),
 }
 
+/// Starts the inference thread of the model, returns the queue of its requests and the channel
+/// of its commands. Answers are stopped once `moderator` blocks them, the weights are published
+/// to `loaded` once they are loaded.
 pub fn initialize_model_and_handle_inferences(
     model: ModelName,
     db: Arc<crate::DbPool>,
     config: LlmConfig,
     runtime: Arc<Runtime>,
-) -> Sender<InferenceRequest> {
-    let request_queue = queue::empty_queue();
+    metrics: Arc<LlmMetrics>,
+    moderator: Option<Arc<dyn Moderator>>,
+    loaded: LoadedModel,
+) -> (QueueSender<InferenceRequest>, Sender<ModelCommand>) {
+    // Requests wait in the channel until a session is free, the inference thread is its only
+    // receiver so they are started in the order they were sent
+    let (tx_request, rx_request, running) = queue::queue_channel(config.queue_capacity);
+    let (tx_commands, rx_commands) = unbounded();
+
+    let model_name = model.clone();
+    let inference_runtime = runtime.clone();
 
     // Create a channel and thread responsible for saving chat entries to database
     let (tx_results, rx_results): (Sender<SaveDataRequest>, Receiver<SaveDataRequest>) =
         unbounded();
     std::thread::spawn(move || loop {
         if let Ok(save_data_request) = rx_results.recv() {
-            match save_data_request {
-                SaveDataRequest::Chat {
-                    conversation_id,
-                    input,
-                    output,
-                } => {
-                    let user = ChatEntry::new_user(conversation_id, input);
-                    let bot = ChatEntry::new_bot(conversation_id, output);
-                    let db = db.clone();
-                    // TODO: store the futures somewhere and await them?
-                    runtime.spawn(async move {
-                        if let Err(e) = user.create(&db).await {
-                            log::error!("failed to save user chat entry - {e}")
-                        }
-                        if let Err(e) = bot.create(&db).await {
-                            log::error!("failed to save bot chat entry - {e}")
-                        }
-                    });
-                }
-                SaveDataRequest::Prompt {
-                    input,
-                    output,
-                    username,
-                    settings,
-                } => {
-                    let db = db.clone();
-                    let prompt = Prompt::new(username, model.clone(), input, output, settings);
-                    // TODO: store the futures somewhere and await them?
-                    runtime.spawn(async move {
-                        if let Err(e) = prompt.create(&db).await {
-                            log::error!("failed to save prompt - {e}")
-                        }
-                    });
+            // the stats of all sessions that finished in the meantime are written at once
+            let mut stats = vec![];
+            for save_data_request in std::iter::once(save_data_request).chain(rx_results.try_iter())
+            {
+                match save_data_request {
+                    SaveDataRequest::Chat {
+                        conversation_id,
+                        input,
+                        output,
+                        seed,
+                        params,
+                        num_predict,
+                    } => {
+                        let params = ChatEntryParams {
+                            model: model.clone(),
+                            temp: params.temperature,
+                            top_k: params.top_k,
+                            top_p: params.top_p,
+                            repeat_penalty: params.repeat_penalty,
+                            repeat_last_n: params.repetition_penalty_last_n,
+                            num_predict,
+                        };
+                        let user = input.map(|input| ChatEntry::new_user(conversation_id, input));
+                        let bot = ChatEntry::new_bot(conversation_id, output)
+                            .with_seed(seed)
+                            .with_params(&params);
+                        let db = db.clone();
+                        // TODO: store the futures somewhere and await them?
+                        runtime.spawn(async move {
+                            if let Some(user) = user {
+                                if let Err(e) = user.create(&db).await {
+                                    log::error!("failed to save user chat entry - {e}")
+                                }
+                            }
+                            if let Err(e) = bot.create(&db).await {
+                                log::error!("failed to save bot chat entry - {e}")
+                            }
+                        });
+                    }
+                    SaveDataRequest::Prompt {
+                        input,
+                        output,
+                        username,
+                        settings,
+                        variables,
+                    } => {
+                        let db = db.clone();
+                        let prompt = Prompt::new(
+                            username,
+                            model.clone(),
+                            input,
+                            output,
+                            settings,
+                            variables,
+                        );
+                        // TODO: store the futures somewhere and await them?
+                        runtime.spawn(async move {
+                            if let Err(e) = prompt.create(&db).await {
+                                log::error!("failed to save prompt - {e}")
+                            }
+                        });
+                    }
+                    SaveDataRequest::Stats {
+                        queue_wait,
+                        generation_time,
+                        generated_tokens,
+                    } => stats.push(InferenceStat::new(
+                        model.clone(),
+                        queue_wait,
+                        generation_time,
+                        generated_tokens,
+                    )),
                 }
             }
+            if !stats.is_empty() {
+                let db = db.clone();
+                runtime.spawn(async move {
+                    if let Err(e) = InferenceStat::create_batch(&db, &stats).await {
+                        log::error!("failed to save inference stats - {e}")
+                    }
+                });
+            }
         } else {
             log::error!("all channels closed");
             break;
         }
     });
 
-    // Create a thread that'll receive InferenceRequests
-    let queue = request_queue.clone();
-    let tx_request = queue::start_queue_thread::<InferenceRequest>(queue);
-
     // Create a thread that will handle inference
     std::thread::spawn(move || {
-        let mut inference_session_manager = InferenceSessionManager::new(config);
-        let mut running_sessions = VecDeque::new();
-        let mut rng = thread_rng();
+        if let Err(e) = affinity::pin_current_thread(&config.cpu_affinity) {
+            log::error!("[{model_name}] failed to pin the inference threads - {e}");
+        }
+        if let Err(e) = inference_runtime.block_on(fetch_model(&config, &metrics.load_progress)) {
+            log::error!("[{model_name}] {e}");
+            metrics.load_progress.fail(e.to_string());
+            return;
+        }
+        let inference_session_manager = InferenceSessionManager::new(
+            model_name,
+            config,
+            metrics,
+            moderator,
+            loaded,
+            inference_runtime,
+        );
+        handle_inferences(
+            inference_session_manager,
+            rx_request,
+            rx_commands,
+            tx_results,
+            running,
+        );
+    });
 
-        loop {
-            let mut free_spots =
-                inference_session_manager.config.max_inference_sessions - running_sessions.len();
-            if free_spots > 0 {
-                if let Ok(mut queue) = request_queue.try_write() {
-                    while let Some(inference_request) = queue.pop_front() && free_spots > 0 {
-                        let mut session = inference_session_manager.get_inference_session(inference_request);
-
-                        if let Err(e) = session.feed_prompt(inference_session_manager.model.as_ref()) {
-                            log::error!("failed to initialize inference session - {e}");
-                        } else {
-                            running_sessions.push_back(session);
-                            free_spots -= 1;
-                        }
+    (tx_request, tx_commands)
+}
+
+/// Answers the requests of `rx_request` and runs the commands of `rx_commands` until both
+/// channels are closed, the number of running sessions is reported through `running`.
+fn handle_inferences(
+    mut inference_session_manager: InferenceSessionManager,
+    rx_request: Receiver<InferenceRequest>,
+    rx_commands: Receiver<ModelCommand>,
+    tx_results: Sender<SaveDataRequest>,
+    running: queue::RunningSessions,
+) {
+    let mut running_sessions = VecDeque::new();
+    // weights replaced by a reload, reloads are exclusive so there is at most one
+    let mut draining: Option<DrainingModel> = None;
+
+    loop {
+        if let Ok(command) = rx_commands.try_recv() {
+            inference_session_manager.run_command(
+                command,
+                &mut running_sessions,
+                &mut draining,
+                &rx_request,
+                &tx_results,
+            );
+        }
+        if running_sessions.is_empty() && draining.is_none() {
+            // nothing to generate, prepare the next session and wait for the next request
+            inference_session_manager.keep_warm();
+            let event = Selector::new()
+                .recv(&rx_request, |r| r.map(Idle::Request))
+                .recv(&rx_commands, |r| r.map(Idle::Command))
+                .wait();
+            match event {
+                Ok(Idle::Request(inference_request)) => {
+                    if let Some(session) =
+                        inference_session_manager.start_session(inference_request, &tx_results)
+                    {
+                        running_sessions.push_back(session);
                     }
                 }
+                Ok(Idle::Command(command)) => {
+                    inference_session_manager.run_command(
+                        command,
+                        &mut running_sessions,
+                        &mut draining,
+                        &rx_request,
+                        &tx_results,
+                    );
+                    continue;
+                }
+                Err(_) => {
+                    log::info!("inference request channel closed, stopping the inference thread");
+                    break;
+                }
             }
-            for session in &mut running_sessions {
-                if session.state.processed_tokens
-                    <= session.request.settings.num_predict.unwrap_or(usize::MAX)
-                {
-                    if let Err(e) =
-                        session.infer_next_token(&inference_session_manager, &mut rng, &tx_results)
-                    {
-                        log::error!("{e}");
-                    }
-                } else {
-                    log::debug!("already infered max number of tokens for session");
-                    session.state.is_finished = true;
+        }
+        let draining_sessions = draining.as_ref().map_or(0, |d| d.sessions.len());
+        inference_session_manager.admit(
+            &mut running_sessions,
+            draining_sessions,
+            &rx_request,
+            &tx_results,
+        );
+        let mut is_any_generating =
+            inference_session_manager.generate(&mut running_sessions, &tx_results);
+        if let Some(old) = &mut draining {
+            is_any_generating |= old.manager.generate(&mut old.sessions, &tx_results);
+            if old.sessions.is_empty() {
+                if let Some(old) = draining.take() {
+                    old.finish();
                 }
             }
+        }
+        let draining_sessions = draining.as_ref().map_or(0, |d| d.sessions.len());
+        if !is_any_generating && running_sessions.len() + draining_sessions > 0 {
+            std::thread::sleep(BACKPRESSURE_PAUSE);
+        }
 
-            running_sessions.retain(|s| !s.state.is_finished);
+        let metrics = &inference_session_manager.metrics;
+        metrics
+            .queue_depth
+            .store(rx_request.len(), Ordering::Relaxed);
+        metrics.running_sessions.store(
+            running_sessions.len() + draining_sessions,
+            Ordering::Relaxed,
+        );
+        running.set(running_sessions.len() + draining_sessions);
+    }
+}
 
-            std::thread::sleep(std::time::Duration::from_millis(5));
-        }
-    });
+/// Weights replaced by a reload, kept until the sessions that were running on them are done.
+struct DrainingModel {
+    manager: InferenceSessionManager,
+    sessions: VecDeque<RunningInferenceSession>,
+    /// Number of sessions that were running when the weights were replaced.
+    drained: usize,
+    tx_drained: oneshot::Sender<usize>,
+}
 
-    tx_request
+impl DrainingModel {
+    fn finish(self) {
+        log::info!(
+            "[{}] dropping the previous weights, {} sessions finished on them",
+            self.manager.name,
+            self.drained
+        );
+        let _ = self.tx_drained.send(self.drained);
+    }
 }
 
 struct InferenceSessionManager {
-    model: Box<dyn llm::Model>,
+    name: ModelName,
+    model: Arc<dyn LanguageModel>,
+    /// Where the weights are published for the readers of the vocabulary.
+    loaded: LoadedModel,
     config: LlmConfig,
+    metrics: Arc<LlmMetrics>,
+    /// Idle session already fed [`WarmSession::prefix`], only kept with `keep_warm`. It isn't
+    /// one of the running sessions, the next request either continues from it or drops it.
+    warm_session: Option<WarmSession>,
+    /// Answers of seeded requests, empty for new weights.
+    response_cache: ResponseCache,
+    /// Checks the answers while they are generated, unchecked without one.
+    moderator: Option<Arc<dyn Moderator>>,
+    /// Runtime the answers of the response cache are replayed on.
+    runtime: Arc<Runtime>,
+}
+
+struct WarmSession {
+    session: Box<dyn LanguageSession>,
+    prefix: String,
 }
 
 impl InferenceSessionManager {
-    fn new(config: LlmConfig) -> Self {
-        let load_callback = |progress| {
-            match progress {
-                LoadProgress::HyperparametersLoaded => {
-                    log::debug!("Loaded hyperparameters")
+    /// Loads the model of the configuration, panics when it can't be loaded.
+    fn new(
+        name: ModelName,
+        config: LlmConfig,
+        metrics: Arc<LlmMetrics>,
+        moderator: Option<Arc<dyn Moderator>>,
+        loaded: LoadedModel,
+        runtime: Arc<Runtime>,
+    ) -> Self {
+        let model = load_model(&config, &metrics.load_progress).expect("Could not load model");
+        let manager = Self::with_model(name, model, config, metrics, moderator, loaded, runtime);
+        manager.metrics.load_progress.finish();
+        manager
+    }
+
+    fn with_model(
+        name: ModelName,
+        model: Arc<dyn LanguageModel>,
+        config: LlmConfig,
+        metrics: Arc<LlmMetrics>,
+        moderator: Option<Arc<dyn Moderator>>,
+        loaded: LoadedModel,
+        runtime: Arc<Runtime>,
+    ) -> Self {
+        loaded.set(model.clone());
+        let manager = Self {
+            name,
+            model,
+            loaded,
+            response_cache: ResponseCache::new(config.response_cache_size),
+            config,
+            metrics,
+            warm_session: None,
+            moderator,
+            runtime,
+        };
+        if manager.config.warm_up {
+            manager.warm_up();
+        }
+        manager
+    }
+
+    fn run_command(
+        &mut self,
+        command: ModelCommand,
+        running_sessions: &mut VecDeque<RunningInferenceSession>,
+        draining: &mut Option<DrainingModel>,
+        rx_request: &Receiver<InferenceRequest>,
+        tx_results: &Sender<SaveDataRequest>,
+    ) {
+        match command {
+            ModelCommand::Swap(swap) => *draining = self.swap(swap, running_sessions),
+            ModelCommand::ClearQueue(clear) => {
+                let mut cleared = QueueCleared::default();
+                // dropping the requests gives up their places in the queue
+                for request in rx_request.try_iter() {
+                    request.abort();
+                    cleared.cleared += 1;
                 }
-                //LoadProgress::BadToken { index } => {
-                //log::info!("Warning: Bad token in vocab at index {index}")
-                //}
-                LoadProgress::ContextSize { bytes } => log::info!(
-                    "ggml ctx size = {:.2} MB\n",
-                    bytes as f64 / (1024.0 * 1024.0)
-                ),
-                LoadProgress::TensorLoaded {
-                    current_tensor,
-                    tensor_count,
-                    ..
-                } => {
-                    let current_tensor = current_tensor + 1;
-                    if current_tensor % 8 == 0 {
-                        log::info!("Loaded tensor {current_tensor}/{tensor_count}");
+                if clear.cancel_running {
+                    let draining_sessions = draining.iter_mut().flat_map(|d| d.sessions.iter_mut());
+                    for session in running_sessions.iter_mut().chain(draining_sessions) {
+                        if !session.state.is_finished {
+                            session.save_results(tx_results, StopReason::Aborted);
+                            cleared.cancelled += 1;
+                        }
                     }
                 }
-                LoadProgress::Loaded {
-                    file_size,
-                    tensor_count,
-                } => {
-                    log::info!(
-                        "Model size = {:.2} MB / num tensors = {}",
-                        file_size as f64 / 1024.0 / 1024.0,
-                        tensor_count
-                    );
+                log::info!(
+                    "[{}] cleared the queue, aborted {} waiting requests and {} running sessions",
+                    self.name,
+                    cleared.cleared,
+                    cleared.cancelled
+                );
+                let _ = clear.tx_cleared.send(cleared);
+            }
+        }
+    }
+
+    /// Starts new requests on the weights of `swap` from now on, the running sessions are moved
+    /// to the returned previous weights to finish on them. Without running sessions the previous
+    /// weights are dropped right away.
+    fn swap(
+        &mut self,
+        swap: ModelSwap,
+        running_sessions: &mut VecDeque<RunningInferenceSession>,
+    ) -> Option<DrainingModel> {
+        let ModelSwap {
+            model,
+            config,
+            tx_drained,
+        } = swap;
+        let manager = Self::with_model(
+            self.name.clone(),
+            model,
+            config,
+            self.metrics.clone(),
+            self.moderator.clone(),
+            self.loaded.clone(),
+            self.runtime.clone(),
+        );
+        log::info!(
+            "[{}] swapped in {}",
+            self.name,
+            manager.config.model_path.display()
+        );
+        let sessions = std::mem::take(running_sessions);
+        let old = DrainingModel {
+            manager: std::mem::replace(self, manager),
+            drained: sessions.len(),
+            sessions,
+            tx_drained,
+        };
+        if old.sessions.is_empty() {
+            old.finish();
+            return None;
+        }
+        Some(old)
+    }
+
+    /// Generates the next token of every session, returns whether any of them generated one.
+    /// Finished sessions are removed once the end of their answer is sent.
+    fn generate(
+        &mut self,
+        sessions: &mut VecDeque<RunningInferenceSession>,
+        tx_results: &Sender<SaveDataRequest>,
+    ) -> bool {
+        let mut is_any_generating = false;
+        for session in sessions.iter_mut() {
+            let _entered = session.span.clone().entered();
+            // the session waits for its receiver to catch up without holding back the others
+            if !session.flush() || session.state.is_finished {
+                continue;
+            }
+            is_any_generating = true;
+            if session.state.processed_tokens
+                <= session.request.settings.num_predict.unwrap_or(usize::MAX)
+            {
+                let result = session.infer_next_token(self, tx_results);
+                if let Err(e) = result {
+                    log::error!("{e}");
                 }
+            } else {
+                log::debug!("already infered max number of tokens for session");
+                session.release_answer_start();
+                session.send_json_output();
+                session.finish(tx_results, StopReason::MaxTokens);
+            }
+        }
+
+        for session in sessions.iter_mut() {
+            if let Some((key, answer)) = session.cached.take() {
+                self.response_cache.insert(key, answer);
+            }
+        }
+        // finished sessions are kept until the end of their answer is sent
+        sessions.retain(|s| !s.state.is_finished || !s.outbox.is_empty());
+        is_any_generating
+    }
+
+    /// Runs a short prompt through a throwaway session so that the first request doesn't pay
+    /// for the cold caches.
+    fn warm_up(&self) {
+        let start = Instant::now();
+        let params = inference_parameters(&self.config, &InferenceSettings::default(), None, None);
+        let mut session = self.model.start_session(self.session_config());
+        let result = session
+            .feed_prompt(&params, WARM_UP_PROMPT, &mut |_| {})
+            .and_then(|_| {
+                session
+                    .infer_next_token(&params, &mut thread_rng())
+                    .map(|_| ())
+            });
+        match result {
+            Ok(()) | Err(InferenceError::EndOfText) => {
+                log::info!("[{}] warmed up in {:?}", self.name, start.elapsed());
             }
+            Err(e) => log::warn!("[{}] failed to warm up - {e}", self.name),
+        }
+    }
+
+    /// Prepares the warm session if `keep_warm` is enabled and there is none yet.
+    fn keep_warm(&mut self) {
+        if !self.config.keep_warm || self.warm_session.is_some() {
+            return;
+        }
+        let Some(prefix) = warm_prefix(&self.config) else {
+            return;
         };
+        let start = Instant::now();
+        let params = inference_parameters(&self.config, &InferenceSettings::default(), None, None);
+        let mut session = self.model.start_session(self.session_config());
+        let result = session.feed_prompt(&params, &prefix, &mut |_| {});
+        match result {
+            Ok(()) => {
+                log::debug!(
+                    "[{}] prepared warm session in {:?}",
+                    self.name,
+                    start.elapsed()
+                );
+                self.warm_session = Some(WarmSession { session, prefix });
+            }
+            Err(e) => log::warn!("[{}] failed to prepare warm session - {e}", self.name),
+        }
+    }
 
-        // Load model
-        let model = match config.type_ {
-            LlmType::Bloom => Box::new(
-                llm::load::<llm::models::Bloom>(
-                    &config.model_path,
-                    Default::default(),
-                    load_callback,
-                )
-                .expect("Could not load model"),
-            ) as Box<dyn llm::Model>,
-            LlmType::Gpt2 => Box::new(
-                llm::load::<llm::models::Gpt2>(
-                    &config.model_path,
-                    Default::default(),
-                    load_callback,
-                )
-                .expect("Could not load model"),
-            ) as Box<dyn llm::Model>,
-            LlmType::GptJ => Box::new(
-                llm::load::<llm::models::GptJ>(
-                    &config.model_path,
-                    Default::default(),
-                    load_callback,
-                )
-                .expect("Could not load model"),
-            ) as Box<dyn llm::Model>,
-            LlmType::Llama => Box::new(
-                llm::load::<llm::models::Llama>(
-                    &config.model_path,
-                    Default::default(),
-                    load_callback,
-                )
-                .expect("Could not load model"),
-            ) as Box<dyn llm::Model>,
-            LlmType::Neox => Box::new(
-                llm::load::<llm::models::NeoX>(
-                    &config.model_path,
-                    Default::default(),
-                    load_callback,
-                )
-                .expect("Could not load model"),
-            ) as Box<dyn llm::Model>,
+    /// Returns the warm session if `prompt` continues its prefix together with the length of
+    /// the prefix, the warm session is dropped otherwise.
+    fn take_warm_session(
+        &mut self,
+        prompt: &str,
+        request: &InferenceRequest,
+    ) -> Option<(Box<dyn LanguageSession>, usize)> {
+        let warm = self.warm_session.take()?;
+        // seeded requests are tokenized in one piece so that they always generate the same
+        // answer
+        if request.seed.or(self.config.seed).is_some() || !prompt.starts_with(&warm.prefix) {
+            log::debug!("[{}] dropping warm session", self.name);
+            return None;
+        }
+        log::debug!("[{}] continuing warm session", self.name);
+        Some((warm.session, warm.prefix.len()))
+    }
+
+    fn session_config(&self) -> InferenceSessionConfig {
+        let mem_typ = if self.config.float16 {
+            ModelKVMemoryType::Float16
+        } else {
+            ModelKVMemoryType::Float32
         };
+        InferenceSessionConfig {
+            memory_k_type: mem_typ,
+            memory_v_type: mem_typ,
+        }
+    }
 
-        Self { model, config }
+    /// Number of queued requests that can be started next to `running` sessions. A reload can
+    /// lower `max_inference_sessions` below the sessions that are still running, then nothing is
+    /// started until enough of them are done.
+    fn free_spots(&self, running: usize) -> usize {
+        let max = self.config.max_inference_sessions;
+        if running > max {
+            log::trace!(
+                "[{}] {running} sessions running above the limit of {max}",
+                self.name
+            );
+            return 0;
+        }
+        max - running
     }
 
-    fn get_inference_session(&mut self, request: InferenceRequest) -> RunningInferenceSession {
-        let inference_session_params = {
-            let mem_typ = if self.config.float16 {
-                ModelKVMemoryType::Float16
-            } else {
-                ModelKVMemoryType::Float32
-            };
-            InferenceSessionConfig {
-                memory_k_type: mem_typ,
-                memory_v_type: mem_typ,
+    /// Starts the queued requests that fit next to the running sessions and the sessions still
+    /// draining on previous weights.
+    fn admit(
+        &mut self,
+        running_sessions: &mut VecDeque<RunningInferenceSession>,
+        draining_sessions: usize,
+        rx_request: &Receiver<InferenceRequest>,
+        tx_results: &Sender<SaveDataRequest>,
+    ) {
+        let free_spots = self.free_spots(running_sessions.len() + draining_sessions);
+        for request in rx_request.try_iter().take(free_spots) {
+            if let Some(session) = self.start_session(request, tx_results) {
+                running_sessions.push_back(session);
             }
-        };
+        }
+    }
+
+    /// Creates a session for the request and feeds it the prompt, `None` if that failed or the
+    /// answer was replayed from the response cache.
+    fn start_session(
+        &mut self,
+        mut request: InferenceRequest,
+        tx_results: &Sender<SaveDataRequest>,
+    ) -> Option<RunningInferenceSession> {
+        let queue_wait = request
+            .queue_ticket
+            .take()
+            .map(|ticket| ticket.waited())
+            .unwrap_or_default();
+        let params = inference_parameters(
+            &self.config,
+            &request.settings,
+            request.repeat_last_n,
+            request.n_threads,
+        );
+        let prompt = self.render_prompt(&request);
+        let seed = request.seed.or(self.config.seed);
 
-        let params = InferenceParameters {
-            n_threads: self.config.num_threads,
-            n_batch: request.settings.n_batch.unwrap_or(self.config.batch_size),
-            top_k: request.settings.top_k.unwrap_or(self.config.top_k),
-            top_p: request.settings.top_p.unwrap_or(self.config.top_p),
-            repeat_penalty: request
-                .settings
-                .repeat_penalty
-                .unwrap_or(self.config.repeat_penalty),
-            temperature: request.settings.temp.unwrap_or(self.config.temperature),
-            bias_tokens: TokenBias::default(),
-            repetition_penalty_last_n: 1,
+        // only seeded answers are generated the same way again
+        let cache_key = seed
+            .filter(|_| self.response_cache.is_enabled() && !request.no_cache)
+            .map(|seed| {
+                CacheKey::new(
+                    &prompt,
+                    seed,
+                    &params,
+                    request.settings.num_predict,
+                    request.json_schema.as_ref(),
+                    request.play_back_tokens,
+                )
+            });
+        let cached = cache_key
+            .as_ref()
+            .and_then(|key| self.response_cache.get(key));
+        if let (Some(cached), Some(seed)) = (cached, seed) {
+            log::debug!(
+                "[{}] replaying the answer from the response cache",
+                request.request_id
+            );
+            self.metrics.cache_hits.fetch_add(1, Ordering::Relaxed);
+            // nothing was generated for the answer
+            if let Err(e) = tx_results.try_send(SaveDataRequest::Stats {
+                queue_wait,
+                generation_time: Duration::ZERO,
+                generated_tokens: 0,
+            }) {
+                log::error!(
+                    "[{}] failed to save inference stats - {e}",
+                    request.request_id
+                );
+            }
+            // answers cut off at the maximum number of tokens aren't saved when generated either
+            if cached.reason != StopReason::MaxTokens {
+                save_answer(&request, cached.answer.clone(), seed, &params, tx_results);
+            }
+            let tx_tokens = request.tx_tokens;
+            // the receiver reads the answer at its own pace without holding back the sessions
+            self.runtime.spawn(async move {
+                for message in cached.replay() {
+                    if tx_tokens.send_async(message).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            return None;
+        }
+
+        let seed = seed.unwrap_or_else(|| thread_rng().gen());
+        let mut session = self.get_inference_session(request, prompt, params, seed);
+        session.queue_wait = queue_wait;
+        session.recording = cache_key.map(|key| (key, vec![]));
+        let _entered = session.span.clone().entered();
+        if let Err(e) = session.feed_prompt() {
+            log::error!("failed to initialize inference session - {e}");
+            return None;
+        }
+        Some(session)
+    }
+
+    fn get_inference_session(
+        &mut self,
+        request: InferenceRequest,
+        prompt: String,
+        params: InferenceParameters,
+        seed: u64,
+    ) -> RunningInferenceSession {
+        log::debug!(
+            "inference session of {}: n_batch = {}, top_k = {}, top_p = {}, repeat_penalty = {}, temperature = {}",
+            request.user,
+            params.n_batch,
+            params.top_k,
+            params.top_p,
+            params.repeat_penalty,
+            params.temperature
+        );
+
+        let (session, fed_prompt_len) = match self.take_warm_session(&prompt, &request) {
+            Some(warm) => warm,
+            None => (self.model.start_session(self.session_config()), 0),
         };
 
-        let prompt = if let Some(chat) = &request.chat_data {
-            let history = chat.history.iter().fold(String::new(), |mut acc, x| {
+        let answer_prefixes = request
+            .chat_data
+            .is_some()
+            .then(|| self.config.answer_prefixes());
+        RunningInferenceSession {
+            id: request.request_id,
+            span: request_id::span(request.request_id),
+            session,
+            fed_prompt_len,
+            params,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            queue_wait: Duration::ZERO,
+            started_at: Instant::now(),
+            request,
+            state: InferenceState {
+                processed_prompt: prompt,
+                ..Default::default()
+            },
+            outbox: VecDeque::new(),
+            recording: None,
+            cached: None,
+            answer_prefixes,
+            is_empty_answer_retry: false,
+        }
+    }
+
+    /// Prompt the model is fed for the request, the history and system prompt of a chat included.
+    fn render_prompt(&self, request: &InferenceRequest) -> String {
+        let user_prompt = match &request.json_schema {
+            Some(schema) => format!("{}\n{}", request.prompt, json::schema_instruction(schema)),
+            None => request.prompt.clone(),
+        };
+        if let Some(chat) = &request.chat_data {
+            let history = recent_turns(&chat.history, chat.context_turns);
+            let history = history.iter().fold(String::new(), |mut acc, x| {
                 let prefix = match x.entry_type {
-                    ChatEntryType::Bot => ANSWER_PREFIX,
-                    ChatEntryType::User => USER_PREFIX,
+                    ChatEntryType::Bot => &self.config.answer_prefix,
+                    ChatEntryType::User => &self.config.user_prefix,
                 };
                 acc.push_str(prefix);
                 acc.push_str(&x.content);
                 acc.push('\n');
                 acc
             });
-            let user_prompt = format!("{USER_PREFIX}{}", request.prompt);
-            request
-                .settings
-                .system_prompt
-                .as_deref()
-                .unwrap_or(CONVERSATION_PROMPT)
+            let user_prompt = format!("{}{user_prompt}", self.config.user_prefix);
+            // A system prompt that contains the `{{PROMPT}}` marker is treated as a complete
+            // conversation template, otherwise it only replaces the default persona.
+            let template = match request.settings.system_prompt.as_deref() {
+                Some(system_prompt) if system_prompt.contains("{{PROMPT}}") => {
+                    system_prompt.to_string()
+                }
+                Some(system_prompt) => self
+                    .config
+                    .conversation_prompt
+                    .replace("{{SYSTEM}}", system_prompt),
+                None => self
+                    .config
+                    .conversation_prompt
+                    .replace("{{SYSTEM}}", DEFAULT_SYSTEM_PROMPT),
+            };
+            template
                 .replace("{{HISTORY}}", &history)
                 .replace("{{PROMPT}}", &user_prompt)
         } else {
-            request.prompt.clone()
-        };
-
-        RunningInferenceSession {
-            id: Uuid::new_v4(),
-            session: self.model.start_session(inference_session_params),
-            params,
-            request,
-            state: InferenceState {
-                processed_prompt: prompt,
-                ..Default::default()
-            },
+            user_prompt
         }
     }
 }
 
+/// Start of the chat prompts that use the default system prompt, it ends where the history or
+/// the request is inserted.
+fn warm_prefix(config: &LlmConfig) -> Option<String> {
+    let template = &config.conversation_prompt;
+    let end = ["{{HISTORY}}", "{{PROMPT}}"]
+        .iter()
+        .filter_map(|marker| template.find(marker))
+        .min()?;
+    let prefix = template[..end].replace("{{SYSTEM}}", DEFAULT_SYSTEM_PROMPT);
+    (!prefix.is_empty()).then_some(prefix)
+}
+
+/// Parameters of a session, values missing from `settings`, `repeat_last_n` and `n_threads`
+/// fall back to the configuration of the model, which falls back to the global defaults.
+fn inference_parameters(
+    config: &LlmConfig,
+    settings: &InferenceSettings,
+    repeat_last_n: Option<usize>,
+    n_threads: Option<usize>,
+) -> InferenceParameters {
+    InferenceParameters {
+        n_threads: clamp_threads(n_threads, config.num_threads),
+        n_batch: settings.n_batch.unwrap_or(config.batch_size()),
+        top_k: settings.top_k.unwrap_or(config.top_k()),
+        top_p: settings.top_p.unwrap_or(config.top_p()),
+        repeat_penalty: settings.repeat_penalty.unwrap_or(config.repeat_penalty()),
+        temperature: settings.temp.unwrap_or(config.temperature()),
+        bias_tokens: TokenBias::default(),
+        repetition_penalty_last_n: repeat_last_n.unwrap_or(config.repeat_last_n),
+    }
+}
+
+/// The last `len` bytes of `answer`, fewer if they would start in the middle of a character.
+fn answer_tail(answer: &str, len: usize) -> &str {
+    let mut start = answer.len().saturating_sub(len);
+    while !answer.is_char_boundary(start) {
+        start += 1;
+    }
+    &answer[start..]
+}
+
+/// Threads of a session, requests can use fewer threads than `num_threads` but never more.
+fn clamp_threads(n_threads: Option<usize>, num_threads: usize) -> usize {
+    n_threads
+        .unwrap_or(num_threads)
+        .clamp(1, num_threads.max(1))
+}
+
+/// The last `turns` turns of `history`, a turn starts with a prompt of the user. Independent of
+/// this the whole prompt still has to fit the context of the model.
+fn recent_turns(history: &[ChatEntry], turns: Option<usize>) -> &[ChatEntry] {
+    let Some(turns) = turns else {
+        return history;
+    };
+    if turns == 0 {
+        return &[];
+    }
+    let start = history
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, entry)| entry.entry_type == ChatEntryType::User)
+        .nth(turns - 1)
+        .map(|(i, _)| i)
+        .unwrap_or_default();
+    &history[start..]
+}
+
 struct RunningInferenceSession {
     pub id: Uuid,
-    pub session: InferenceSession,
+    /// Span the log lines of the session are written in.
+    pub span: tracing::Span,
+    pub session: Box<dyn LanguageSession>,
+    /// Length of the start of the prompt the session was already fed.
+    pub fed_prompt_len: usize,
     pub params: InferenceParameters,
+    /// Seed of the generator the answer is sampled with, saved with the answer so that it can
+    /// be generated again.
+    pub seed: u64,
+    pub rng: StdRng,
+    /// Time the request waited in the queue of the model.
+    pub queue_wait: Duration,
+    pub started_at: Instant,
     pub request: InferenceRequest,
     pub state: InferenceState,
+    /// Messages that didn't fit into the full token channel yet, nothing is generated until they
+    /// are sent.
+    pub outbox: VecDeque<ChatStreamMessage>,
+    /// Messages of the answer so far when it can be cached, under the key it is cached with.
+    pub recording: Option<(CacheKey, Vec<ChatStreamMessage>)>,
+    /// Answer to add to the response cache once the session is finished.
+    pub cached: Option<(CacheKey, CachedAnswer)>,
+    /// Prefixes removed from the start of the answer of a chat, `None` when the answer is sent
+    /// as it is generated.
+    pub answer_prefixes: Option<Vec<String>>,
+    /// The answer is generated once more because the first one was empty.
+    pub is_empty_answer_retry: bool,
 }
 
 impl RunningInferenceSession {
-    fn feed_prompt(&mut self, model: &dyn Model) -> Result<(), crate::Error> {
+    fn feed_prompt(&mut self) -> Result<(), crate::Error> {
         log::trace!(
             "[{}] Feeding prompt `{}`",
             self.id,
@@ -349,78 +1049,216 @@ impl RunningInferenceSession {
         let id = self.id;
         self.session
             .feed_prompt(
-                model,
                 &self.params,
-                &self.state.processed_prompt,
-                &mut Default::default(),
-                move |b| {
-                    log::trace!("[{}] prompt part: {}", id, String::from_utf8_lossy(b));
-                    Ok::<(), InferenceError>(())
-                },
+                &self.state.processed_prompt[self.fed_prompt_len..],
+                &mut |b| log::trace!("[{}] prompt part: {}", id, String::from_utf8_lossy(b)),
             )
             .map_err(crate::Error::from)
     }
 
-    fn save_results(&mut self, tx_results: &Sender<SaveDataRequest>) {
-        self.state.is_finished = true;
-        if self.request.save {
-            if let Some(chat) = &self.request.chat_data {
-                log::trace!("saving chat data {}", &chat.conversation_id);
-                let output = self.state.answer.clone();
-                if !output.is_empty() {
-                    if let Err(e) = tx_results.try_send(SaveDataRequest::Chat {
-                        conversation_id: chat.conversation_id,
-                        input: self.request.prompt.clone(),
-                        output,
-                    }) {
-                        log::error!(
-                            "failed to save chat entries for {} - {e}",
-                            chat.conversation_id
-                        );
-                    }
+    /// Queues the message after the ones waiting in the outbox and sends what fits into the
+    /// token channel.
+    fn send(&mut self, message: ChatStreamMessage) {
+        if let Some((_, messages)) = &mut self.recording {
+            if !self.state.is_finished {
+                messages.push(message.clone());
+            }
+        }
+        self.outbox.push_back(message);
+        self.flush();
+    }
+
+    /// Sends the messages of the outbox until the token channel is full, returns whether all of
+    /// them were sent. Messages for a dropped receiver are discarded.
+    fn flush(&mut self) -> bool {
+        while let Some(message) = self.outbox.pop_front() {
+            match self.request.tx_tokens.try_send(message) {
+                Ok(()) => {}
+                Err(TrySendError::Full(message)) => {
+                    self.outbox.push_front(message);
+                    return false;
                 }
-            } else {
-                log::trace!("[{}] saving inference results", self.id);
-                if let Err(e) = tx_results.try_send(SaveDataRequest::Prompt {
-                    input: self.request.prompt.clone(),
-                    output: self.state.answer.clone(),
-                    username: self.request.user.clone(),
-                    settings: InferenceSettings {
-                        num_predict: self.request.settings.num_predict,
-                        system_prompt: self.request.settings.system_prompt.clone(),
-                        n_batch: Some(self.params.n_batch),
-                        top_k: Some(self.params.top_k),
-                        top_p: Some(self.params.top_p),
-                        repeat_penalty: Some(self.params.repeat_penalty),
-                        temp: Some(self.params.temperature),
-                    },
-                }) {
-                    log::error!("failed to save inference results - {e}");
+                Err(TrySendError::Disconnected(_)) => {
+                    log::debug!(
+                        "[{}] failed to send the answer, the receiver was dropped",
+                        self.id
+                    );
+                    self.outbox.clear();
                 }
             }
         }
+        true
+    }
+
+    /// Ends the answer, its usage and the reason it ended are sent after the tokens.
+    fn finish(&mut self, tx_results: &Sender<SaveDataRequest>, reason: StopReason) {
+        self.state.is_finished = true;
+        if let Err(e) = tx_results.try_send(SaveDataRequest::Stats {
+            queue_wait: self.queue_wait,
+            generation_time: self.started_at.elapsed(),
+            generated_tokens: self.state.processed_tokens,
+        }) {
+            log::error!("[{}] failed to save inference stats - {e}", self.id);
+        }
+        if let Some((key, messages)) = self.recording.take() {
+            if CachedAnswer::is_reproducible(reason) {
+                let answer = CachedAnswer {
+                    messages,
+                    answer: self.state.answer.clone(),
+                    generated_tokens: self.state.processed_tokens,
+                    reason,
+                };
+                self.cached = Some((key, answer));
+            }
+        }
+        self.send(ChatStreamMessage::Usage {
+            generated_tokens: self.state.processed_tokens,
+        });
+        self.send(ChatStreamMessage::Done { reason });
+    }
+
+    /// Returns the part of the answer to send after a token was added to it. The start of a chat
+    /// answer is held back until it is clear whether it begins with one of the answer prefixes,
+    /// the prefix isn't part of the answer then.
+    fn answer_update(&mut self, token: String) -> Option<String> {
+        if self.state.is_answer_started {
+            return Some(token);
+        }
+        if let Some(prefixes) = &self.answer_prefixes {
+            if prefix::is_undecided(&self.state.answer, prefixes) {
+                return None;
+            }
+        }
+        self.start_answer();
+        Some(self.state.answer.clone()).filter(|answer| !answer.is_empty())
+    }
+
+    fn start_answer(&mut self) {
+        self.state.is_answer_started = true;
+        if let Some(prefixes) = &self.answer_prefixes {
+            let start = self.state.answer.len()
+                - prefix::strip_answer_prefix(&self.state.answer, prefixes).len();
+            self.state.answer.drain(..start);
+        }
+    }
+
+    /// Sends the start of the answer that is still held back once the answer ends.
+    fn release_answer_start(&mut self) {
+        if self.state.is_answer_started {
+            return;
+        }
+        self.start_answer();
+        if self.request.json_schema.is_none() && !self.state.answer.is_empty() {
+            self.send(ChatStreamMessage::Token {
+                content: self.state.answer.clone(),
+            });
+        }
+    }
+
+    fn save_results(&mut self, tx_results: &Sender<SaveDataRequest>, reason: StopReason) {
+        self.release_answer_start();
+        self.finish(tx_results, reason);
+        save_answer(
+            &self.request,
+            self.state.answer.clone(),
+            self.seed,
+            &self.params,
+            tx_results,
+        );
+    }
+
+    /// Sends the buffered answer of a request with a JSON schema, repaired if it had to be.
+    fn send_json_output(&mut self) {
+        let Some(schema) = &self.request.json_schema else {
+            return;
+        };
+        match json::parse_output(&self.state.answer, schema) {
+            Ok(value) => {
+                self.state.answer = value.to_string();
+                self.send(ChatStreamMessage::Token {
+                    content: self.state.answer.clone(),
+                });
+            }
+            Err(e) => {
+                log::debug!("[{}] invalid JSON output - {e}", self.id);
+                self.send(ChatStreamMessage::Token {
+                    content: self.state.answer.clone(),
+                });
+                self.send(ChatStreamMessage::Error {
+                    message: e.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Starts the answer over with a fresh session and a higher temperature when the model
+    /// retries empty answers and this isn't the retry already, returns whether it did. Nothing
+    /// of an empty answer was sent yet so the client only gets the retry.
+    fn retry_empty_answer(&mut self, inference_session_manager: &InferenceSessionManager) -> bool {
+        let metrics = &inference_session_manager.metrics;
+        metrics.empty_answers.fetch_add(1, Ordering::Relaxed);
+        if self.is_empty_answer_retry || !inference_session_manager.config.retry_empty_answers {
+            log::warn!(
+                "[{}] the model returned an empty answer after {} tokens",
+                self.id,
+                self.state.processed_tokens
+            );
+            return false;
+        }
+        metrics.empty_answer_retries.fetch_add(1, Ordering::Relaxed);
+        let temperature = self.params.temperature + EMPTY_ANSWER_TEMPERATURE_INCREASE;
+        log::warn!(
+            "[{}] the model returned an empty answer after {} tokens, retrying with temperature {temperature}",
+            self.id,
+            self.state.processed_tokens
+        );
+
+        // the answer is saved with the parameters of the retry, the seed generates it again
+        self.is_empty_answer_retry = true;
+        self.params.temperature = temperature;
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.session = inference_session_manager
+            .model
+            .start_session(inference_session_manager.session_config());
+        self.fed_prompt_len = 0;
+        self.state.answer.clear();
+        self.state.processed_tokens = 0;
+        self.state.is_answer_started = false;
+        if let Err(e) = self.feed_prompt() {
+            log::error!("[{}] failed to feed the prompt of the retry - {e}", self.id);
+            return false;
+        }
+        true
     }
 
     fn infer_next_token(
         &mut self,
         inference_session_manager: &InferenceSessionManager,
-        rng: &mut ThreadRng,
         tx_results: &Sender<SaveDataRequest>,
     ) -> Result<(), crate::Error> {
+        if self.request.tx_tokens.is_disconnected() {
+            // nobody reads the answer anymore, keep what was generated and free the slot
+            log::debug!("[{}] the receiver of the answer was dropped", self.id);
+            self.save_results(tx_results, StopReason::Cancelled);
+            return Ok(());
+        }
         log::trace!("[{}] infering next valid utf-8 token", self.id);
         let mut buf = llm::TokenUtf8Buffer::new();
 
         loop {
-            let token = match self.session.infer_next_token(
-                inference_session_manager.model.as_ref(),
-                &self.params,
-                &mut Default::default(),
-                rng,
-            ) {
+            let token = match self.session.infer_next_token(&self.params, &mut self.rng) {
                 Ok(token) => token,
                 Err(InferenceError::EndOfText) => {
                     log::debug!("[{}] end of inference", self.id);
-                    self.save_results(tx_results);
+                    self.release_answer_start();
+                    if self.state.answer.is_empty() {
+                        if !self.retry_empty_answer(inference_session_manager) {
+                            self.save_results(tx_results, StopReason::EmptyAnswer);
+                        }
+                        break;
+                    }
+                    self.send_json_output();
+                    self.save_results(tx_results, StopReason::EndOfText);
                     break;
                 }
                 Err(e) => return Err(e.into()),
@@ -429,20 +1267,956 @@ impl RunningInferenceSession {
             if let Some(valid_token) = buf.push(token) {
                 self.state.answer.push_str(&valid_token);
                 self.state.processed_tokens += 1;
-                log::trace!("[{}] Sending token {} to receiver.", self.id, valid_token);
-                match self.request.tx_tokens.send(Ok(valid_token)) {
-                    Ok(_) => {
+                inference_session_manager
+                    .metrics
+                    .generated_tokens
+                    .fetch_add(1, Ordering::Relaxed);
+                let content = self.answer_update(valid_token);
+                if let Some(moderator) = &inference_session_manager.moderator {
+                    // the token that got the answer blocked isn't sent, nor is the answer saved
+                    let tail = answer_tail(&self.state.answer, MODERATION_WINDOW);
+                    if let Decision::Block { reason } = moderator.check(tail) {
+                        log::info!(
+                            "[{}] stopping the answer, it was blocked - {reason}",
+                            self.id
+                        );
+                        self.finish(tx_results, StopReason::Moderated);
                         break;
                     }
-                    Err(e) => {
-                        // The receiver has been dropped.
-                        self.save_results(tx_results);
-                        return Err(crate::Error::InferenceSend(e));
-                    }
                 }
+                if self.request.json_schema.is_some() {
+                    // the answer is only sent once it is complete
+                    break;
+                }
+                if let Some(content) = content {
+                    log::trace!("[{}] Sending token {} to receiver.", self.id, content);
+                    // a dropped receiver is noticed before the next token
+                    self.send(ChatStreamMessage::Token { content });
+                }
+                break;
             }
         }
 
+        let config = &inference_session_manager.config;
+        if !self.state.is_finished
+            && repetition::is_repeating(
+                &self.state.answer,
+                config.repetition_window,
+                config.repetition_threshold,
+            )
+        {
+            log::debug!(
+                "[{}] stopping the answer, it keeps repeating itself",
+                self.id
+            );
+            self.release_answer_start();
+            self.send_json_output();
+            self.save_results(tx_results, StopReason::Repetition);
+        }
+
         Ok(())
     }
 }
+
+/// Saves the answer to the request, to its chat or as a prompt.
+fn save_answer(
+    request: &InferenceRequest,
+    answer: String,
+    seed: u64,
+    params: &InferenceParameters,
+    tx_results: &Sender<SaveDataRequest>,
+) {
+    if request.save {
+        if let Some(chat) = &request.chat_data {
+            log::trace!("saving chat data {}", &chat.conversation_id);
+            if !answer.is_empty() {
+                if let Err(e) = tx_results.try_send(SaveDataRequest::Chat {
+                    conversation_id: chat.conversation_id,
+                    input: Some(request.prompt.clone()).filter(|_| !chat.is_prompt_saved),
+                    output: answer,
+                    seed,
+                    params: params.clone(),
+                    num_predict: request.settings.num_predict,
+                }) {
+                    log::error!(
+                        "failed to save chat entries for {} - {e}",
+                        chat.conversation_id
+                    );
+                }
+            }
+        } else {
+            log::trace!("[{}] saving inference results", request.request_id);
+            let (input, variables) = match &request.template {
+                Some(template) => (template.template.clone(), template.variables.clone()),
+                None => (request.prompt.clone(), vec![]),
+            };
+            if let Err(e) = tx_results.try_send(SaveDataRequest::Prompt {
+                input,
+                output: answer,
+                username: request.user.clone(),
+                settings: InferenceSettings {
+                    num_predict: request.settings.num_predict,
+                    system_prompt: request.settings.system_prompt.clone(),
+                    n_batch: Some(params.n_batch),
+                    top_k: Some(params.top_k),
+                    top_p: Some(params.top_p),
+                    repeat_penalty: Some(params.repeat_penalty),
+                    temp: Some(params.temperature),
+                    mirostat: request.settings.mirostat,
+                    mirostat_tau: request.settings.mirostat_tau,
+                    mirostat_eta: request.settings.mirostat_eta,
+                },
+                variables,
+            }) {
+                log::error!("failed to save inference results - {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::InferenceDefaults,
+        gen::llm::backend::mock::{MockAnswer, MockModel},
+        moderation::BlocklistModerator,
+    };
+
+    /// Configuration of a model that only sets the options in `yaml`.
+    fn config(yaml: &str) -> LlmConfig {
+        serde_yaml::from_str(&format!("type: llama\nmodel_path: llama.bin\n{yaml}"))
+            .expect("model config is valid")
+    }
+
+    fn manager(model: &Arc<MockModel>, config: LlmConfig) -> InferenceSessionManager {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .build()
+            .expect("runtime starts");
+        InferenceSessionManager::with_model(
+            "mock".into(),
+            model.clone(),
+            config,
+            Default::default(),
+            None,
+            Default::default(),
+            Arc::new(runtime),
+        )
+    }
+
+    /// Request of a oneshot prompt, its answer is read from the returned receiver.
+    fn request(prompt: &str) -> (InferenceRequest, Receiver<ChatStreamMessage>) {
+        let (tx_tokens, rx_tokens) = unbounded();
+        let request = InferenceRequest {
+            tx_tokens,
+            user: "alice".into(),
+            save: true,
+            chat_data: None,
+            prompt: prompt.into(),
+            settings: Default::default(),
+            play_back_tokens: false,
+            template: None,
+            json_schema: None,
+            seed: None,
+            repeat_last_n: None,
+            n_threads: None,
+            no_cache: false,
+            queue_ticket: None,
+            request_id: Uuid::new_v4(),
+        };
+        (request, rx_tokens)
+    }
+
+    /// Chat of alternating prompts and answers, `turns` of each.
+    fn history(turns: usize) -> Vec<ChatEntry> {
+        let chat_id = Uuid::new_v4();
+        (1..=turns)
+            .flat_map(|turn| {
+                [
+                    ChatEntry::new_user(chat_id, format!("question {turn}")),
+                    ChatEntry::new_bot(chat_id, format!("answer {turn}")),
+                ]
+            })
+            .collect()
+    }
+
+    /// Generates until every session is finished and the end of its answer was sent.
+    fn run(
+        manager: &mut InferenceSessionManager,
+        sessions: &mut VecDeque<RunningInferenceSession>,
+        tx_results: &Sender<SaveDataRequest>,
+    ) {
+        for _ in 0..10_000 {
+            if sessions.is_empty() {
+                return;
+            }
+            manager.generate(sessions, tx_results);
+        }
+        panic!("sessions didn't finish");
+    }
+
+    /// Answers `request` on its own, returns the streamed answer and why it ended.
+    fn answer(
+        manager: &mut InferenceSessionManager,
+        request: (InferenceRequest, Receiver<ChatStreamMessage>),
+        tx_results: &Sender<SaveDataRequest>,
+    ) -> (String, StopReason) {
+        let (request, rx_tokens) = request;
+        let mut sessions = manager
+            .start_session(request, tx_results)
+            .into_iter()
+            .collect();
+        run(manager, &mut sessions, tx_results);
+        streamed(&rx_tokens)
+    }
+
+    /// The tokens received so far together with why the answer ended.
+    fn streamed(rx_tokens: &Receiver<ChatStreamMessage>) -> (String, StopReason) {
+        let mut answer = String::new();
+        let mut reason = None;
+        for message in rx_tokens.try_iter() {
+            match message {
+                ChatStreamMessage::Token { content } => answer.push_str(&content),
+                ChatStreamMessage::Done { reason: done } => reason = Some(done),
+                _ => {}
+            }
+        }
+        (answer, reason.expect("answer is done"))
+    }
+
+    #[test]
+    fn request_settings_override_the_model_and_the_defaults() {
+        let mut config = config("temperature: 0.9\ntop_p: 0.5\nnum_threads: 8");
+        config.defaults = InferenceDefaults {
+            temperature: 0.5,
+            top_k: 20,
+            top_p: 0.8,
+            batch_size: 4,
+            ..Default::default()
+        };
+
+        let params = inference_parameters(&config, &InferenceSettings::default(), None, None);
+        assert_eq!(params.temperature, 0.9);
+        assert_eq!(params.top_p, 0.5);
+        assert_eq!(params.top_k, 20);
+        assert_eq!(params.n_batch, 4);
+        assert_eq!(
+            params.repeat_penalty,
+            InferenceDefaults::default().repeat_penalty
+        );
+
+        let settings = InferenceSettings {
+            temp: Some(0.1),
+            top_k: Some(5),
+            n_batch: Some(2),
+            repeat_penalty: Some(1.1),
+            ..Default::default()
+        };
+        let params = inference_parameters(&config, &settings, None, None);
+        assert_eq!(params.temperature, 0.1);
+        assert_eq!(params.top_k, 5);
+        assert_eq!(params.n_batch, 2);
+        assert_eq!(params.repeat_penalty, 1.1);
+        // not part of the request
+        assert_eq!(params.top_p, 0.5);
+    }
+
+    #[test]
+    fn recent_turns_start_at_a_prompt() {
+        let contents = |entries: &[ChatEntry]| -> Vec<String> {
+            entries.iter().map(|e| e.content.clone()).collect()
+        };
+        let history = history(3);
+        assert_eq!(recent_turns(&history, None).len(), 6);
+        assert!(recent_turns(&history, Some(0)).is_empty());
+        assert_eq!(
+            contents(recent_turns(&history, Some(2))),
+            ["question 2", "answer 2", "question 3", "answer 3"]
+        );
+        assert_eq!(recent_turns(&history, Some(3)).len(), 6);
+        assert_eq!(recent_turns(&history, Some(10)).len(), 6);
+
+        // an answer without its prompt belongs to no turn of its own
+        assert_eq!(
+            contents(recent_turns(&history[1..], Some(2))),
+            ["question 2", "answer 2", "question 3", "answer 3"]
+        );
+        assert_eq!(recent_turns(&history[1..], Some(3)).len(), 5);
+    }
+
+    #[test]
+    fn only_the_last_turns_reach_the_prompt() {
+        let model = Arc::new(MockModel::answering(&["ok"]));
+        let mut manager = manager(&model, config(""));
+        let (tx_results, _rx_results) = unbounded();
+
+        for context_turns in [Some(2), None] {
+            let (mut request, rx_tokens) = request("question 5");
+            request.chat_data = Some(ChatData {
+                conversation_id: Uuid::new_v4(),
+                history: history(4),
+                is_prompt_saved: false,
+                context_turns,
+            });
+            answer(&mut manager, (request, rx_tokens), &tx_results);
+        }
+
+        let log = model.log();
+        let user = &manager.config.user_prefix;
+        let bot = &manager.config.answer_prefix;
+        let [limited, whole] = &log.prompts[..] else {
+            panic!("two prompts were fed");
+        };
+        for turn in 1..=4 {
+            let question = format!("{user}question {turn}\n");
+            let answer = format!("{bot}answer {turn}\n");
+            assert!(
+                whole.contains(&question) && whole.contains(&answer),
+                "{whole}"
+            );
+            assert_eq!(limited.contains(&question), turn > 2, "{limited}");
+            assert_eq!(limited.contains(&answer), turn > 2, "{limited}");
+        }
+        assert!(limited.contains(&format!("{user}question 5")));
+    }
+
+    /// Request of a chat without history, the answer prefixes are stripped from its answers.
+    fn chat_request(prompt: &str) -> (InferenceRequest, Receiver<ChatStreamMessage>) {
+        let (mut request, rx_tokens) = request(prompt);
+        request.chat_data = Some(ChatData {
+            conversation_id: Uuid::new_v4(),
+            history: vec![],
+            is_prompt_saved: false,
+            context_turns: None,
+        });
+        (request, rx_tokens)
+    }
+
+    /// The answer that was saved for a chat.
+    fn saved_answer(rx_results: &Receiver<SaveDataRequest>) -> String {
+        rx_results
+            .try_iter()
+            .find_map(|result| match result {
+                SaveDataRequest::Chat { output, .. } => Some(output),
+                _ => None,
+            })
+            .expect("answer is saved")
+    }
+
+    #[test]
+    fn answer_prefix_split_across_tokens_is_stripped() {
+        let model = Arc::new(MockModel::answering(&[
+            "\n", "Assi", "stant", ":", " Hel", "lo", " there",
+        ]));
+        let mut manager = manager(&model, config(""));
+        let (tx_results, rx_results) = unbounded();
+
+        let (chat, rx_tokens) = chat_request("Greet");
+        let mut sessions = manager
+            .start_session(chat, &tx_results)
+            .into_iter()
+            .collect();
+        run(&mut manager, &mut sessions, &tx_results);
+        let tokens: Vec<_> = rx_tokens
+            .try_iter()
+            .filter_map(|message| match message {
+                ChatStreamMessage::Token { content } => Some(content),
+                _ => None,
+            })
+            .collect();
+        // the start is held back until the prefix is complete
+        assert_eq!(tokens, ["Hel", "lo", " there"]);
+        assert_eq!(saved_answer(&rx_results), "Hello there");
+
+        // only the start of chat answers is stripped
+        let (answer, _) = answer(&mut manager, request("Greet"), &tx_results);
+        assert_eq!(answer, "\nAssistant: Hello there");
+    }
+
+    #[test]
+    fn start_resembling_the_prefix_is_kept() {
+        let model = Arc::new(MockModel::answering(&["Assi", "stance", " is here"]));
+        let mut manager = manager(&model, config(""));
+        let (tx_results, rx_results) = unbounded();
+
+        let (answer, _) = answer(&mut manager, chat_request("Help"), &tx_results);
+        assert_eq!(answer, "Assistance is here");
+        assert_eq!(saved_answer(&rx_results), answer);
+    }
+
+    #[test]
+    fn answer_without_tokens_is_empty() {
+        let model = Arc::new(MockModel::answering(&[]));
+        let mut manager = manager(&model, config(""));
+        let (tx_results, rx_results) = unbounded();
+
+        let (answer, reason) = answer(&mut manager, chat_request("Hello?"), &tx_results);
+        assert_eq!((answer.as_str(), reason), ("", StopReason::EmptyAnswer));
+        // empty answers aren't added to the chat
+        assert!(!rx_results
+            .try_iter()
+            .any(|result| matches!(result, SaveDataRequest::Chat { .. })));
+        assert_eq!(manager.metrics.empty_answers.load(Ordering::Relaxed), 1);
+        assert_eq!(model.log().sessions, 1);
+    }
+
+    #[test]
+    fn answer_of_only_the_prefix_is_empty() {
+        let model = Arc::new(MockModel::answering(&["\n", "Assistant", ":", " "]));
+        let mut manager = manager(&model, config("retry_empty_answers: true"));
+        let (tx_results, _rx_results) = unbounded();
+
+        let (chat, rx_tokens) = chat_request("Hello?");
+        let mut sessions = manager
+            .start_session(chat, &tx_results)
+            .into_iter()
+            .collect();
+        run(&mut manager, &mut sessions, &tx_results);
+        let messages: Vec<_> = rx_tokens.try_iter().collect();
+        // the stripped prefix is never sent, not even of the answer that was retried
+        assert!(!messages
+            .iter()
+            .any(|message| matches!(message, ChatStreamMessage::Token { .. })));
+        assert!(matches!(
+            messages.last(),
+            Some(ChatStreamMessage::Done {
+                reason: StopReason::EmptyAnswer
+            })
+        ));
+        assert_eq!(model.log().sessions, 2);
+        let metrics = &manager.metrics;
+        assert_eq!(metrics.empty_answers.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.empty_answer_retries.load(Ordering::Relaxed), 1);
+    }
+
+    /// Answers a chat prompt sampled with `seed`, returns the answer together with the seed it
+    /// was saved with.
+    fn seeded_answer(manager: &mut InferenceSessionManager, seed: Option<u64>) -> (String, u64) {
+        let (tx_results, rx_results) = unbounded();
+        let (mut request, rx_tokens) = chat_request("Tell me a story");
+        request.seed = seed;
+        request.no_cache = true;
+        let (answer, _) = answer(manager, (request, rx_tokens), &tx_results);
+        let saved = rx_results.try_iter().find_map(|result| match result {
+            SaveDataRequest::Chat { output, seed, .. } => Some((output, seed)),
+            _ => None,
+        });
+        let (output, seed) = saved.expect("answer is saved");
+        assert_eq!(output, answer);
+        (answer, seed)
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_answer() {
+        let words = ["a", " b", " c", " d", " e", " f", " g", " h"];
+        let model = Arc::new(MockModel::new(MockAnswer::Sampled {
+            words: words.iter().map(|w| w.to_string()).collect(),
+            len: 32,
+        }));
+        let mut manager = manager(&model, config(""));
+
+        let (first, seed) = seeded_answer(&mut manager, Some(42));
+        assert_eq!(seed, 42);
+        assert_eq!(seeded_answer(&mut manager, Some(42)), (first.clone(), 42));
+        assert_ne!(seeded_answer(&mut manager, Some(43)).0, first);
+
+        // the random seed of an unseeded answer is saved, it generates the answer again
+        let (random, seed) = seeded_answer(&mut manager, None);
+        assert_eq!(seeded_answer(&mut manager, Some(seed)), (random, seed));
+        assert_eq!(model.log().sessions, 5);
+    }
+
+    /// Model answering with `len` tokens that never repeat.
+    fn counting_model(len: usize) -> Arc<MockModel> {
+        Arc::new(MockModel::new(MockAnswer::Tokens(
+            (0..len).map(|i| format!(" {i}")).collect(),
+        )))
+    }
+
+    #[test]
+    fn dropped_receiver_finishes_the_session() {
+        let model = counting_model(1000);
+        let mut manager = manager(&model, config("max_inference_sessions: 1"));
+        let (tx_results, rx_results) = unbounded();
+
+        let (counting, rx_tokens) = request("Count");
+        let mut sessions: VecDeque<_> = manager
+            .start_session(counting, &tx_results)
+            .into_iter()
+            .collect();
+        for _ in 0..10 {
+            manager.generate(&mut sessions, &tx_results);
+        }
+        assert_eq!(manager.free_spots(sessions.len()), 0);
+        assert_eq!(rx_tokens.try_iter().count(), 10);
+
+        drop(rx_tokens);
+        manager.generate(&mut sessions, &tx_results);
+        assert!(sessions.is_empty());
+        assert_eq!(manager.free_spots(sessions.len()), 1);
+        assert_eq!(model.log().generated_tokens, 10);
+        // what was generated is kept
+        let saved = rx_results.try_iter().find_map(|result| match result {
+            SaveDataRequest::Prompt { output, .. } => Some(output),
+            _ => None,
+        });
+        assert_eq!(saved.as_deref(), Some(" 0 1 2 3 4 5 6 7 8 9"));
+
+        // the free slot answers the next request
+        let (answer, reason) = answer(&mut manager, request("Count again"), &tx_results);
+        assert_eq!(reason, StopReason::EndOfText);
+        assert!(answer.ends_with(" 998 999"));
+    }
+
+    #[test]
+    fn slow_receiver_pauses_the_session() {
+        let model = counting_model(100);
+        let mut manager = manager(&model, config("token_channel_capacity: 4"));
+        let (tx_results, _rx_results) = unbounded();
+
+        let (mut slow, _) = request("Count");
+        let (tx_tokens, rx_tokens) = token_channel(&manager.config);
+        slow.tx_tokens = tx_tokens;
+        let mut sessions: VecDeque<_> = manager
+            .start_session(slow, &tx_results)
+            .into_iter()
+            .collect();
+        let mut is_generating = true;
+        for _ in 0..50 {
+            is_generating = manager.generate(&mut sessions, &tx_results);
+        }
+        // the full channel and the token that didn't fit are all that is buffered
+        assert!(!is_generating);
+        assert_eq!(rx_tokens.len(), 4);
+        assert_eq!(sessions[0].outbox.len(), 1);
+        assert_eq!(model.log().generated_tokens, 5);
+
+        // reading the answer lets the session continue where it paused
+        let mut answer = String::new();
+        let mut reason = None;
+        for _ in 0..10_000 {
+            if reason.is_some() {
+                break;
+            }
+            manager.generate(&mut sessions, &tx_results);
+            assert!(rx_tokens.len() <= 4);
+            if let Ok(message) = rx_tokens.try_recv() {
+                match message {
+                    ChatStreamMessage::Token { content } => answer.push_str(&content),
+                    ChatStreamMessage::Done { reason: done } => reason = Some(done),
+                    _ => {}
+                }
+            }
+        }
+        assert_eq!(reason, Some(StopReason::EndOfText));
+        let expected: String = (0..100).map(|i| format!(" {i}")).collect();
+        assert_eq!(answer, expected);
+        assert!(sessions.is_empty());
+    }
+
+    #[test]
+    fn reload_finishes_running_sessions_on_the_previous_weights() {
+        let old_model = counting_model(50);
+        let new_model = Arc::new(MockModel::answering(&["new", " weights"]));
+        let mut manager = manager(&old_model, config(""));
+        let (tx_results, _rx_results) = unbounded();
+        let (_tx_request, rx_request) = unbounded();
+
+        let (running, rx_running) = request("Count");
+        let mut sessions: VecDeque<_> = manager
+            .start_session(running, &tx_results)
+            .into_iter()
+            .collect();
+        for _ in 0..10 {
+            manager.generate(&mut sessions, &tx_results);
+        }
+
+        let (tx_drained, mut rx_drained) = oneshot::channel();
+        let mut new_config = config("");
+        new_config.model_path = "new.bin".into();
+        let swap = ModelSwap {
+            model: new_model.clone(),
+            config: new_config,
+            tx_drained,
+        };
+        let mut draining = None;
+        manager.run_command(
+            ModelCommand::Swap(swap),
+            &mut sessions,
+            &mut draining,
+            &rx_request,
+            &tx_results,
+        );
+        assert!(sessions.is_empty());
+        assert_eq!(manager.config.model_path, std::path::Path::new("new.bin"));
+        let mut old = draining.expect("running session is drained");
+        assert_eq!(old.sessions.len(), 1);
+
+        // requests started after the swap are answered by the new weights next to the old session
+        let (next, rx_next) = request("Greet");
+        sessions.extend(manager.start_session(next, &tx_results));
+        for _ in 0..10_000 {
+            if sessions.is_empty() && old.sessions.is_empty() {
+                break;
+            }
+            manager.generate(&mut sessions, &tx_results);
+            old.manager.generate(&mut old.sessions, &tx_results);
+        }
+        assert!(rx_drained.try_recv().is_err());
+        old.finish();
+        assert_eq!(rx_drained.try_recv(), Ok(1));
+
+        let counted: String = (0..50).map(|i| format!(" {i}")).collect();
+        assert_eq!(streamed(&rx_running), (counted, StopReason::EndOfText));
+        assert_eq!(
+            streamed(&rx_next),
+            ("new weights".to_string(), StopReason::EndOfText)
+        );
+        assert_eq!(old_model.log().sessions, 1);
+        assert_eq!(new_model.log().sessions, 1);
+    }
+
+    #[test]
+    fn repeat_last_n_reaches_the_session() {
+        let model = Arc::new(MockModel::answering(&["ok"]));
+        let mut manager = manager(&model, config("repeat_last_n: 32"));
+        let (tx_results, _rx_results) = unbounded();
+
+        for repeat_last_n in [Some(128), None, Some(0)] {
+            let (mut request, rx_tokens) = request("Say ok");
+            request.repeat_last_n = repeat_last_n;
+            answer(&mut manager, (request, rx_tokens), &tx_results);
+        }
+        let fed: Vec<_> = model
+            .log()
+            .params
+            .iter()
+            .map(|params| params.repetition_penalty_last_n)
+            .collect();
+        assert_eq!(fed, [128, 32, 0]);
+    }
+
+    #[test]
+    fn n_threads_reaches_the_session() {
+        let model = Arc::new(MockModel::answering(&["ok"]));
+        let mut manager = manager(&model, config("num_threads: 4"));
+        let (tx_results, _rx_results) = unbounded();
+
+        for n_threads in [Some(2), None, Some(16)] {
+            let (mut request, rx_tokens) = request("Say ok");
+            request.n_threads = n_threads;
+            answer(&mut manager, (request, rx_tokens), &tx_results);
+        }
+        let fed: Vec<_> = model
+            .log()
+            .params
+            .iter()
+            .map(|params| params.n_threads)
+            .collect();
+        assert_eq!(fed, [2, 4, 4]);
+    }
+
+    #[test]
+    fn threads_are_clamped_to_the_model() {
+        for (n_threads, num_threads, expected) in [
+            (None, 8, 8),
+            (Some(2), 8, 2),
+            (Some(8), 8, 8),
+            (Some(9), 8, 8),
+            (Some(0), 8, 1),
+            (None, 0, 1),
+        ] {
+            assert_eq!(
+                clamp_threads(n_threads, num_threads),
+                expected,
+                "{n_threads:?} of {num_threads}"
+            );
+        }
+    }
+
+    /// Runs a [`ModelCommand::ClearQueue`] on the manager.
+    fn clear_queue(
+        manager: &mut InferenceSessionManager,
+        cancel_running: bool,
+        sessions: &mut VecDeque<RunningInferenceSession>,
+        rx_request: &Receiver<InferenceRequest>,
+        tx_results: &Sender<SaveDataRequest>,
+    ) -> QueueCleared {
+        let (tx_cleared, mut rx_cleared) = oneshot::channel();
+        let clear = QueueClear {
+            cancel_running,
+            tx_cleared,
+        };
+        manager.run_command(
+            ModelCommand::ClearQueue(clear),
+            sessions,
+            &mut None,
+            rx_request,
+            tx_results,
+        );
+        rx_cleared.try_recv().expect("clear is answered")
+    }
+
+    #[test]
+    fn clearing_the_queue_aborts_only_the_waiting_requests() {
+        let model = counting_model(20);
+        let mut manager = manager(&model, config(""));
+        let (tx_results, _rx_results) = unbounded();
+        let (tx_request, rx_request) = unbounded();
+
+        let (running, rx_running) = request("Count");
+        let mut sessions: VecDeque<_> = manager
+            .start_session(running, &tx_results)
+            .into_iter()
+            .collect();
+        manager.generate(&mut sessions, &tx_results);
+        let waiting: Vec<_> = ["Wait", "Wait too"]
+            .into_iter()
+            .map(|prompt| {
+                let (waiting, rx_waiting) = request(prompt);
+                tx_request.send(waiting).unwrap();
+                rx_waiting
+            })
+            .collect();
+
+        let cleared = clear_queue(&mut manager, false, &mut sessions, &rx_request, &tx_results);
+        assert_eq!((cleared.cleared, cleared.cancelled), (2, 0));
+        assert!(rx_request.is_empty());
+        for rx_waiting in &waiting {
+            assert_eq!(streamed(rx_waiting), (String::new(), StopReason::Aborted));
+        }
+        run(&mut manager, &mut sessions, &tx_results);
+        let counted: String = (0..20).map(|i| format!(" {i}")).collect();
+        assert_eq!(streamed(&rx_running), (counted, StopReason::EndOfText));
+
+        // cancelling the running sessions stops them too
+        let (running, rx_running) = request("Count");
+        sessions.extend(manager.start_session(running, &tx_results));
+        manager.generate(&mut sessions, &tx_results);
+        let cleared = clear_queue(&mut manager, true, &mut sessions, &rx_request, &tx_results);
+        assert_eq!((cleared.cleared, cleared.cancelled), (0, 1));
+        run(&mut manager, &mut sessions, &tx_results);
+        assert_eq!(
+            streamed(&rx_running),
+            (" 0".to_string(), StopReason::Aborted)
+        );
+    }
+
+    #[test]
+    fn nothing_is_admitted_above_the_session_limit() {
+        let model = counting_model(5);
+        let mut manager = manager(&model, config("max_inference_sessions: 3"));
+        let (tx_results, _rx_results) = unbounded();
+        let (tx_request, rx_request) = unbounded();
+        let mut receivers = vec![];
+        let mut queue = |count: usize| {
+            for _ in 0..count {
+                let (request, rx_tokens) = request("Count");
+                tx_request.send(request).unwrap();
+                receivers.push(rx_tokens);
+            }
+        };
+
+        queue(3);
+        let mut sessions = VecDeque::new();
+        manager.admit(&mut sessions, 0, &rx_request, &tx_results);
+        assert_eq!(sessions.len(), 3);
+
+        // a reload lowered the limit below the running sessions
+        manager.config.max_inference_sessions = 2;
+        queue(2);
+        for draining_sessions in [0, 1] {
+            manager.admit(&mut sessions, draining_sessions, &rx_request, &tx_results);
+            assert_eq!(sessions.len(), 3);
+            assert_eq!(rx_request.len(), 2);
+        }
+        assert_eq!(manager.free_spots(4), 0);
+        assert_eq!(model.log().sessions, 3);
+
+        // sessions draining on the previous weights take up slots too
+        run(&mut manager, &mut sessions, &tx_results);
+        manager.admit(&mut sessions, 1, &rx_request, &tx_results);
+        assert_eq!(sessions.len(), 1);
+        manager.admit(&mut sessions, 0, &rx_request, &tx_results);
+        assert_eq!(sessions.len(), 2);
+        assert!(rx_request.is_empty());
+    }
+
+    #[test]
+    fn identical_seeded_request_is_replayed_from_the_cache() {
+        let model = counting_model(8);
+        let mut manager = manager(&model, config("response_cache_size: 4"));
+        let (tx_results, rx_results) = unbounded();
+        let seeded = || {
+            let (mut request, rx_tokens) = request("Count");
+            request.seed = Some(7);
+            (request, rx_tokens)
+        };
+
+        let (generated, reason) = answer(&mut manager, seeded(), &tx_results);
+        assert_eq!(reason, StopReason::EndOfText);
+        let generated_tokens = model.log().generated_tokens;
+        rx_results.drain();
+
+        // the answer is replayed while the inference thread carries on
+        let (request, rx_tokens) = seeded();
+        assert!(manager.start_session(request, &tx_results).is_none());
+        let mut replayed = String::new();
+        loop {
+            let message = rx_tokens
+                .recv_timeout(Duration::from_secs(5))
+                .expect("answer is replayed");
+            match message {
+                ChatStreamMessage::Token { content } => replayed.push_str(&content),
+                ChatStreamMessage::Done { reason } => {
+                    assert_eq!(reason, StopReason::EndOfText);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(replayed, generated);
+        assert_eq!(model.log().sessions, 1);
+        assert_eq!(model.log().generated_tokens, generated_tokens);
+        assert_eq!(manager.metrics.cache_hits.load(Ordering::Relaxed), 1);
+
+        // the replay is saved and counted like a generated answer without tokens
+        let results: Vec<_> = rx_results.try_iter().collect();
+        assert!(results.iter().any(|result| matches!(
+            result,
+            SaveDataRequest::Stats {
+                generated_tokens: 0,
+                ..
+            }
+        )));
+        assert!(results.iter().any(|result| matches!(
+            result,
+            SaveDataRequest::Prompt { output, .. } if *output == generated
+        )));
+
+        // unless the request opts out
+        let (mut request, rx_tokens) = seeded();
+        request.no_cache = true;
+        answer(&mut manager, (request, rx_tokens), &tx_results);
+        assert_eq!(model.log().sessions, 2);
+    }
+
+    #[test]
+    fn answer_tail_keeps_whole_characters() {
+        assert_eq!(answer_tail("hello", 3), "llo");
+        assert_eq!(answer_tail("hello", 10), "hello");
+        // `…` takes three bytes
+        assert_eq!(answer_tail("a…b", 3), "b");
+        assert_eq!(answer_tail("a…b", 4), "…b");
+    }
+
+    #[test]
+    fn blocked_answer_is_stopped_and_not_saved() {
+        // the blocked word comes long after the start of the answer
+        let mut tokens: Vec<_> = (0..200).map(|i| format!(" {i}")).collect();
+        tokens.extend([" Forbidden".to_string(), " secrets".to_string()]);
+        let model = Arc::new(MockModel::new(MockAnswer::Tokens(tokens.clone())));
+        let mut manager = manager(&model, config(""));
+        let moderator = BlocklistModerator::new(&["forbidden".into()], &[]).unwrap();
+        manager.moderator = Some(Arc::new(moderator));
+        let (tx_results, rx_results) = unbounded();
+
+        let (answer, reason) = answer(&mut manager, request("Tell me"), &tx_results);
+        assert_eq!(reason, StopReason::Moderated);
+        // the token that got the answer blocked isn't sent
+        assert_eq!(answer, tokens[..200].concat());
+        assert_eq!(model.log().generated_tokens, 201);
+        let saved = rx_results.try_iter().find(|result| {
+            matches!(
+                result,
+                SaveDataRequest::Prompt { .. } | SaveDataRequest::Chat { .. }
+            )
+        });
+        assert!(saved.is_none());
+    }
+
+    #[test]
+    fn repeating_answer_is_stopped() {
+        let model = Arc::new(MockModel::answering(&[" again"; 1000]));
+        let mut manager = manager(&model, config(""));
+        let (tx_results, _rx_results) = unbounded();
+
+        let (answer, reason) = answer(&mut manager, request("Say it"), &tx_results);
+        assert_eq!(reason, StopReason::Repetition);
+        assert!(answer.starts_with(" again again"), "{answer}");
+        // stopped once the phrase filled the window of the check
+        let window = manager.config.repetition_window;
+        assert_eq!(
+            answer.len(),
+            window.div_ceil(" again".len()) * " again".len()
+        );
+        assert_eq!(model.log().generated_tokens, answer.len() / " again".len());
+    }
+
+    #[test]
+    fn answer_ending_on_its_own_isnt_cut() {
+        let model = Arc::new(MockModel::answering(&["Hello", ",", " world"]));
+        let mut manager = manager(&model, config(""));
+        let (tx_results, _rx_results) = unbounded();
+
+        let (answer, reason) = answer(&mut manager, request("Greet"), &tx_results);
+        assert_eq!(
+            (answer.as_str(), reason),
+            ("Hello, world", StopReason::EndOfText)
+        );
+    }
+
+    #[test]
+    fn waiting_requests_are_scraped_as_the_queue_depth() {
+        let registry = crate::metrics::Metrics::default();
+        let model = Arc::new(MockModel::answering(&["Hello", ",", " world"]));
+        let metrics = registry.llm("mock");
+        let (tx_request, rx_request, running) = queue::queue_channel(8);
+        let (tx_commands, rx_commands) = unbounded();
+        let (tx_results, _rx_results) = unbounded();
+        let inferences = std::thread::spawn(move || {
+            let mut manager = manager(&model, config("max_inference_sessions: 1"));
+            manager.metrics = metrics;
+            handle_inferences(manager, rx_request, rx_commands, tx_results, running)
+        });
+
+        // the first request takes the only session and waits for its unread answer, the others
+        // wait for that session to end
+        let answers: Vec<_> = (1..=4)
+            .map(|i| {
+                let (tx_tokens, rx_tokens) = flume::bounded(1);
+                let (inference, _) = request(&format!("prompt {i}"));
+                let inference = InferenceRequest {
+                    tx_tokens,
+                    ..inference
+                };
+                tx_request.send(inference).expect("request is queued");
+                rx_tokens
+            })
+            .collect();
+        let scraped = |series: &str| {
+            registry
+                .render()
+                .lines()
+                .find_map(|line| line.strip_prefix(series)?.strip_prefix(' ')?.parse().ok())
+                .unwrap_or(f64::NAN)
+        };
+        for _ in 0..500 {
+            if scraped("airtifex_llm_queue_depth{model=\"mock\"}") == 3.0 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(scraped("airtifex_llm_queue_depth{model=\"mock\"}"), 3.0);
+        assert_eq!(
+            scraped("airtifex_llm_running_sessions{model=\"mock\"}"),
+            1.0
+        );
+
+        // nobody reads the answers anymore, so the sessions end and the thread stops
+        drop(answers);
+        drop((tx_request, tx_commands));
+        inferences.join().expect("inference thread doesn't panic");
+    }
+}
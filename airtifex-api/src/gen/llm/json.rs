@@ -0,0 +1,215 @@
+//! JSON output of language models. The inference backend can't constrain sampling, so the
+//! model is instructed to answer with JSON matching a schema and the answer is repaired and
+//! validated once it is complete.
+
+use serde_json::{Map, Value};
+use thiserror::Error as ErrorType;
+
+#[derive(Debug, ErrorType)]
+pub enum JsonOutputError {
+    #[error("response is not valid JSON - {0}")]
+    Parse(serde_json::Error),
+    #[error("response doesn't match the JSON schema - {0}")]
+    Schema(String),
+}
+
+/// Instruction appended to the prompt of requests with a JSON schema.
+pub fn schema_instruction(schema: &Value) -> String {
+    format!(
+        "Respond only with a JSON value that matches the following JSON schema, without any \
+         explanation or formatting around it.\nJSON schema: {schema}"
+    )
+}
+
+/// Parses the answer of a model and validates it against `schema`. Answers wrapped in a code
+/// fence or surrounded by text are repaired by extracting the outermost JSON value.
+pub fn parse_output(answer: &str, schema: &Value) -> Result<Value, JsonOutputError> {
+    let value = match serde_json::from_str(answer.trim()) {
+        Ok(value) => value,
+        Err(e) => extract_json(answer).ok_or(JsonOutputError::Parse(e))?,
+    };
+    validate(&value, schema, "$").map_err(JsonOutputError::Schema)?;
+    Ok(value)
+}
+
+/// Returns the first object or array of `text` that parses.
+fn extract_json(text: &str) -> Option<Value> {
+    let start = text.find(['{', '['])?;
+    let close = if text[start..].starts_with('{') {
+        '}'
+    } else {
+        ']'
+    };
+    // the value ends at one of the closing brackets, the last one that parses is picked so that
+    // text after the value is ignored
+    text.rmatch_indices(close)
+        .take_while(|(end, _)| *end > start)
+        .find_map(|(end, _)| serde_json::from_str(&text[start..=end]).ok())
+}
+
+/// Validates `value` against the subset of JSON schema models are realistically asked for:
+/// `type`, `enum`, `const`, `properties`, `required`, `additionalProperties` and `items`.
+pub fn validate(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    let schema = match schema {
+        Value::Bool(true) => return Ok(()),
+        Value::Bool(false) => return Err(format!("{path} is not allowed")),
+        Value::Object(schema) => schema,
+        _ => return Err("schema must be an object or a boolean".into()),
+    };
+
+    if let Some(types) = schema.get("type") {
+        let types = match types {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            Value::String(ty) => vec![ty.as_str()],
+            _ => vec![],
+        };
+        if !types.is_empty() && !types.iter().any(|ty| has_type(value, ty)) {
+            return Err(format!("{path} must be of type {}", types.join(" or ")));
+        }
+    }
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        if !values.contains(value) {
+            return Err(format!(
+                "{path} must be one of {}",
+                Value::from(values.clone())
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            return Err(format!("{path} must be {expected}"));
+        }
+    }
+
+    match value {
+        Value::Object(object) => validate_object(object, schema, path),
+        Value::Array(items) => match schema.get("items") {
+            Some(items_schema) => items
+                .iter()
+                .enumerate()
+                .try_for_each(|(i, item)| validate(item, items_schema, &format!("{path}[{i}]"))),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn validate_object(
+    object: &Map<String, Value>,
+    schema: &Map<String, Value>,
+    path: &str,
+) -> Result<(), String> {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        if let Some(missing) = required
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|key| !object.contains_key(*key))
+        {
+            return Err(format!(
+                "{path} is missing the required property `{missing}`"
+            ));
+        }
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (key, property) in object {
+        let property_path = format!("{path}.{key}");
+        match properties.and_then(|p| p.get(key)) {
+            Some(property_schema) => validate(property, property_schema, &property_path)?,
+            None => {
+                if let Some(additional) = schema.get("additionalProperties") {
+                    validate(property, additional, &property_path)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn has_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "age": {"type": "integer"},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}}
+            },
+            "required": ["name", "age"],
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn valid_output_is_parsed() {
+        let value = parse_output(r#" {"name": "Ada", "age": 36, "tags": ["a"]} "#, &schema())
+            .expect("output is valid");
+        assert_eq!(value, json!({"name": "Ada", "age": 36, "tags": ["a"]}));
+    }
+
+    #[test]
+    fn output_around_the_value_is_repaired() {
+        let expected = json!({"name": "Ada", "age": 36});
+        for answer in [
+            "```json\n{\"name\": \"Ada\", \"age\": 36}\n```",
+            "Sure, here it is: {\"name\": \"Ada\", \"age\": 36} Anything else? {}",
+        ] {
+            let value = parse_output(answer, &schema()).expect("output is repaired");
+            assert_eq!(value, expected, "{answer}");
+        }
+    }
+
+    #[test]
+    fn output_that_isnt_json_fails_to_parse() {
+        for answer in ["", "Ada is 36", "{\"name\": \"Ada\", \"age\": "] {
+            let err = parse_output(answer, &schema()).unwrap_err();
+            assert!(matches!(err, JsonOutputError::Parse(_)), "{answer}");
+        }
+    }
+
+    #[test]
+    fn output_that_doesnt_match_the_schema_fails() {
+        for (answer, reason) in [
+            (
+                json!({"name": "Ada"}),
+                "$ is missing the required property `age`",
+            ),
+            (
+                json!({"name": "Ada", "age": 36.5}),
+                "$.age must be of type integer",
+            ),
+            (
+                json!({"name": "Ada", "age": 36, "tags": ["c"]}),
+                "$.tags[0] must be one of [\"a\",\"b\"]",
+            ),
+            (
+                json!({"name": "Ada", "age": 36, "email": ""}),
+                "$.email is not allowed",
+            ),
+            (json!(["Ada", 36]), "$ must be of type object"),
+        ] {
+            match parse_output(&answer.to_string(), &schema()) {
+                Err(JsonOutputError::Schema(e)) => assert_eq!(e, reason),
+                result => panic!("{answer} is refused by the schema, got {result:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Loading of language models and its progress. Models are loaded by their inference thread
+//! after the server started, until then the progress is reported by `/ready` and the load status
+//! route. The progress of a reload is reported separately since the model keeps serving requests
+//! meanwhile.
+
+use crate::{
+    config::{LlmConfig, LlmType},
+    gen::llm::backend::{BackendModel, LanguageModel},
+    SharedAppState,
+};
+use airtifex_core::llm::{LlmLoadStatus, ModelLoadStage, ModelLoadStatus};
+
+use llm::{LoadError, LoadProgress};
+use std::sync::{
+    atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+/// Loads the weights of the configuration, reporting the progress to `progress`.
+pub fn load_model(
+    config: &LlmConfig,
+    progress: &LoadProgressTracker,
+) -> Result<Arc<dyn LanguageModel>, LoadError> {
+    let load_callback = |load_progress| {
+        match load_progress {
+            LoadProgress::HyperparametersLoaded => {
+                log::debug!("Loaded hyperparameters")
+            }
+            //LoadProgress::BadToken { index } => {
+            //log::info!("Warning: Bad token in vocab at index {index}")
+            //}
+            LoadProgress::ContextSize { bytes } => log::info!(
+                "ggml ctx size = {:.2} MB\n",
+                bytes as f64 / (1024.0 * 1024.0)
+            ),
+            LoadProgress::TensorLoaded {
+                current_tensor,
+                tensor_count,
+                ..
+            } => {
+                let current_tensor = current_tensor + 1;
+                if current_tensor % 8 == 0 {
+                    log::info!("Loaded tensor {current_tensor}/{tensor_count}");
+                }
+            }
+            LoadProgress::Loaded {
+                file_size,
+                tensor_count,
+            } => {
+                log::info!(
+                    "Model size = {:.2} MB / num tensors = {}",
+                    file_size as f64 / 1024.0 / 1024.0,
+                    tensor_count
+                );
+            }
+        }
+        progress.update(&load_progress);
+    };
+
+    let path = &config.model_path;
+    let model = match config.type_ {
+        LlmType::Bloom => Box::new(llm::load::<llm::models::Bloom>(
+            path,
+            Default::default(),
+            load_callback,
+        )?) as Box<dyn llm::Model>,
+        LlmType::Gpt2 => Box::new(llm::load::<llm::models::Gpt2>(
+            path,
+            Default::default(),
+            load_callback,
+        )?) as Box<dyn llm::Model>,
+        LlmType::GptJ => Box::new(llm::load::<llm::models::GptJ>(
+            path,
+            Default::default(),
+            load_callback,
+        )?) as Box<dyn llm::Model>,
+        LlmType::Llama => Box::new(llm::load::<llm::models::Llama>(
+            path,
+            Default::default(),
+            load_callback,
+        )?) as Box<dyn llm::Model>,
+        LlmType::Neox => Box::new(llm::load::<llm::models::NeoX>(
+            path,
+            Default::default(),
+            load_callback,
+        )?) as Box<dyn llm::Model>,
+    };
+    Ok(Arc::new(BackendModel::from(model)))
+}
+
+/// Progress of loading a model, updated by the load callback of its inference thread.
+#[derive(Default)]
+pub struct LoadProgressTracker {
+    stage: AtomicU8,
+    tensors_loaded: AtomicUsize,
+    tensor_count: AtomicUsize,
+    bytes_downloaded: AtomicU64,
+    /// `0` while the size of the download is unknown.
+    bytes_total: AtomicU64,
+    error: Mutex<Option<String>>,
+}
+
+impl LoadProgressTracker {
+    pub fn update(&self, progress: &LoadProgress) {
+        match progress {
+            LoadProgress::HyperparametersLoaded | LoadProgress::ContextSize { .. } => {
+                self.set_stage(ModelLoadStage::Hyperparameters)
+            }
+            LoadProgress::TensorLoaded {
+                current_tensor,
+                tensor_count,
+                ..
+            } => {
+                self.tensors_loaded
+                    .store(current_tensor + 1, Ordering::Relaxed);
+                self.tensor_count.store(*tensor_count, Ordering::Relaxed);
+                self.set_stage(ModelLoadStage::Tensors);
+            }
+            LoadProgress::Loaded { tensor_count, .. } => {
+                self.tensors_loaded.store(*tensor_count, Ordering::Relaxed);
+                self.tensor_count.store(*tensor_count, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Starts tracking a download that continues after `downloaded` bytes.
+    pub fn start_download(&self, downloaded: u64, total: Option<u64>) {
+        self.bytes_downloaded.store(downloaded, Ordering::Relaxed);
+        self.bytes_total
+            .store(total.unwrap_or(0), Ordering::Relaxed);
+        self.set_stage(ModelLoadStage::Downloading);
+    }
+
+    pub fn add_downloaded(&self, bytes: usize) {
+        self.bytes_downloaded
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Marks the model as ready to serve requests, called once the inference thread set it up.
+    pub fn finish(&self) {
+        self.set_stage(ModelLoadStage::Loaded);
+    }
+
+    /// Marks the model as not loaded for good because of `error`.
+    pub fn fail(&self, error: String) {
+        *self.error.lock().unwrap_or_else(|e| e.into_inner()) = Some(error);
+        self.set_stage(ModelLoadStage::Failed);
+    }
+
+    /// Starts tracking a new load from the beginning.
+    pub fn reset(&self) {
+        self.tensors_loaded.store(0, Ordering::Relaxed);
+        self.tensor_count.store(0, Ordering::Relaxed);
+        self.bytes_downloaded.store(0, Ordering::Relaxed);
+        self.bytes_total.store(0, Ordering::Relaxed);
+        *self.error.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        self.set_stage(ModelLoadStage::Pending);
+    }
+
+    fn set_stage(&self, stage: ModelLoadStage) {
+        self.stage.store(stage as u8, Ordering::Release);
+    }
+
+    pub fn stage(&self) -> ModelLoadStage {
+        match self.stage.load(Ordering::Acquire) {
+            1 => ModelLoadStage::Downloading,
+            2 => ModelLoadStage::Hyperparameters,
+            3 => ModelLoadStage::Tensors,
+            4 => ModelLoadStage::Loaded,
+            5 => ModelLoadStage::Failed,
+            _ => ModelLoadStage::Pending,
+        }
+    }
+
+    pub fn status(&self, model: &str) -> ModelLoadStatus {
+        let stage = self.stage();
+        let tensors_loaded = self.tensors_loaded.load(Ordering::Relaxed);
+        let tensor_count = self.tensor_count.load(Ordering::Relaxed);
+        // 100% is only reported once the model is ready
+        let percentage = match stage {
+            ModelLoadStage::Loaded => 100.0,
+            _ if tensor_count > 0 => {
+                (tensors_loaded as f32 * 100.0 / tensor_count as f32).min(99.0)
+            }
+            _ => 0.0,
+        };
+        ModelLoadStatus {
+            model: model.to_string(),
+            stage,
+            tensors_loaded,
+            tensor_count,
+            percentage,
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::Relaxed),
+            bytes_total: Some(self.bytes_total.load(Ordering::Relaxed)).filter(|total| *total > 0),
+            error: self.error.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        }
+    }
+}
+
+/// Load status of all configured language models. The server is ready once every model is
+/// loaded, servers without language models are ready right away.
+pub fn llm_load_status(state: &SharedAppState) -> LlmLoadStatus {
+    let mut models = state
+        .config
+        .llms
+        .keys()
+        .map(|model| state.metrics.llm(model).load_progress.status(model))
+        .collect::<Vec<_>>();
+    models.sort_by(|a, b| a.model.cmp(&b.model));
+
+    let ready = models.iter().all(|m| m.stage == ModelLoadStage::Loaded);
+    let percentage = if models.is_empty() {
+        100.0
+    } else {
+        models.iter().map(|m| m.percentage).sum::<f32>() / models.len() as f32
+    };
+    let mut reloading = state
+        .config
+        .llms
+        .keys()
+        .filter_map(|model| {
+            let metrics = state.metrics.llm(model);
+            metrics
+                .is_reloading
+                .load(Ordering::Acquire)
+                .then(|| metrics.reload_progress.status(model))
+        })
+        .collect::<Vec<_>>();
+    reloading.sort_by(|a, b| a.model.cmp(&b.model));
+
+    LlmLoadStatus {
+        ready,
+        percentage,
+        models,
+        reloading,
+    }
+}
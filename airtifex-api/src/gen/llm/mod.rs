@@ -1,23 +1,65 @@
 use crate::{
     config::{Config, LlmConfig},
     gen::ModelName,
+    metrics::Metrics,
     models::llm::LargeLanguageModel,
+    moderation::Moderator,
+    queue::QueueSender,
     DbPool, Result,
 };
 
+use flume::Sender;
 use std::{collections::HashMap, sync::Arc};
 use tokio::runtime::Runtime;
 
+pub mod affinity;
+pub mod backend;
+pub mod batch;
+pub mod cache;
+pub mod chunk;
+pub mod download;
 pub mod inference;
+pub mod json;
+pub mod load;
+pub mod prefix;
+pub mod reload;
+pub mod repetition;
+pub mod stream;
+pub mod tokenize;
 
 pub use inference::*;
+pub use reload::ModelReloader;
+pub use stream::*;
+pub use tokenize::LoadedModel;
 
+/// Queues of the language models with the configuration of each model.
+pub type LlmQueues = HashMap<ModelName, (LlmConfig, QueueSender<InferenceRequest>)>;
+
+/// Command channels of the inference threads of the language models.
+pub type LlmCommands = HashMap<ModelName, Sender<ModelCommand>>;
+
+/// Weights of the language models for reading their vocabularies.
+pub type LlmModels = HashMap<ModelName, LoadedModel>;
+
+/// Starts the inference threads of the configured models, returns their queues together with
+/// the reloaders of their weights, their command channels and their loaded weights. The answers
+/// are checked with `output_moderator` while they are generated.
 pub async fn initialize_models(
     db: Arc<DbPool>,
     config: &Config,
     runtime: Arc<Runtime>,
-) -> Result<HashMap<ModelName, (LlmConfig, flume::Sender<InferenceRequest>)>> {
+    metrics: &Metrics,
+    output_moderator: Option<Arc<dyn Moderator>>,
+) -> Result<(
+    LlmQueues,
+    HashMap<ModelName, ModelReloader>,
+    LlmCommands,
+    LlmModels,
+)> {
     let mut txs = HashMap::new();
+    let mut reloaders = HashMap::new();
+    let mut commands = HashMap::new();
+    let mut models = HashMap::new();
     for (model, llm_config) in config.llms.iter() {
         let exists = LargeLanguageModel::get_by_name(&db, model).await.is_ok();
 
@@ -28,13 +70,28 @@ pub async fn initialize_models(
                 LargeLanguageModel::new(model.to_owned(), llm_config.model_description.clone());
             llm.create(&db).await?;
         }
-        let tx_inference_req = inference::initialize_model_and_handle_inferences(
+        let loaded = LoadedModel::default();
+        let (tx_inference_req, tx_commands) = inference::initialize_model_and_handle_inferences(
             model.to_owned(),
             db.clone(),
             llm_config.clone(),
             runtime.clone(),
+            metrics.llm(model),
+            output_moderator.clone(),
+            loaded.clone(),
         );
         txs.insert(model.clone(), (llm_config.clone(), tx_inference_req));
+        reloaders.insert(
+            model.clone(),
+            ModelReloader::new(
+                model.clone(),
+                llm_config.clone(),
+                metrics.llm(model),
+                tx_commands.clone(),
+            ),
+        );
+        commands.insert(model.clone(), tx_commands);
+        models.insert(model.clone(), loaded);
     }
-    Ok(txs)
+    Ok((txs, reloaders, commands, models))
 }
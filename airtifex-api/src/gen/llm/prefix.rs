@@ -0,0 +1,83 @@
+//! Cleanup of the start of chat answers. Following the conversation template a model sometimes
+//! begins its answer with the prefix the history marks its answers with, or with the line breaks
+//! after the end of the template. Such a start is removed before the answer is sent or saved.
+
+/// Removes leading whitespace and one of the `prefixes` together with the whitespace after it
+/// from the start of the answer.
+pub fn strip_answer_prefix<'a>(answer: &'a str, prefixes: &[String]) -> &'a str {
+    let answer = answer.trim_start();
+    prefixes
+        .iter()
+        .find_map(|prefix| answer.strip_prefix(prefix.as_str()))
+        .unwrap_or(answer)
+        .trim_start()
+}
+
+/// Whether the start of the answer generated so far could still turn out to be removed, it can't
+/// be sent until that is decided.
+pub fn is_undecided(answer: &str, prefixes: &[String]) -> bool {
+    let start = answer.trim_start();
+    strip_answer_prefix(answer, prefixes).is_empty()
+        || prefixes
+            .iter()
+            .any(|prefix| prefix.len() > start.len() && prefix.starts_with(start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefixes() -> Vec<String> {
+        vec!["Assistant:".into(), "Bot:".into()]
+    }
+
+    #[test]
+    fn prefixes_and_leading_whitespace_are_stripped() {
+        for (answer, stripped) in [
+            ("Hello", "Hello"),
+            ("\n\n  Hello", "Hello"),
+            ("Assistant: Hello", "Hello"),
+            ("\nBot:\n Hello", "Hello"),
+            ("Assistant:Bot: Hello", "Bot: Hello"),
+            ("Hello Assistant:", "Hello Assistant:"),
+            ("Assistance", "Assistance"),
+            ("Assistant:", ""),
+            (" \n", ""),
+        ] {
+            assert_eq!(
+                strip_answer_prefix(answer, &prefixes()),
+                stripped,
+                "{answer:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn prefixes_split_across_tokens_are_undecided_until_complete() {
+        let mut answer = String::new();
+        for (token, undecided) in [
+            ("\n", true),
+            ("Assi", true),
+            ("stant", true),
+            (":", true),
+            (" ", true),
+            ("Hel", false),
+            ("lo", false),
+        ] {
+            answer.push_str(token);
+            assert_eq!(is_undecided(&answer, &prefixes()), undecided, "{answer:?}");
+        }
+        assert_eq!(strip_answer_prefix(&answer, &prefixes()), "Hello");
+    }
+
+    #[test]
+    fn answers_that_diverge_from_the_prefixes_are_decided() {
+        for answer in ["Assistance", "Bo b", "Hi"] {
+            assert!(!is_undecided(answer, &prefixes()), "{answer:?}");
+        }
+        assert!(is_undecided("Bo", &prefixes()));
+        // without prefixes only whitespace is held back
+        assert!(is_undecided("\n ", &[]));
+        assert!(!is_undecided("A", &[]));
+    }
+}
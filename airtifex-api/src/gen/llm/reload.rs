@@ -0,0 +1,143 @@
+//! Hot reload of language models. New weights are loaded next to the running model, once they
+//! are loaded new requests are started on them while the sessions running on the previous weights
+//! finish on those. The previous weights are dropped once their last session is done, weights
+//! that fail to load leave the running model in place.
+
+use crate::{
+    config::LlmConfig,
+    gen::{
+        llm::{backend::LanguageModel, load::load_model, ModelCommand},
+        ModelName,
+    },
+    metrics::LlmMetrics,
+};
+
+use flume::Sender;
+use std::{
+    path::PathBuf,
+    sync::{atomic::Ordering, Arc},
+};
+use thiserror::Error as ErrorType;
+use tokio::sync::oneshot;
+
+#[derive(Debug, ErrorType)]
+pub enum ReloadError {
+    #[error("model {0} is already being reloaded")]
+    InProgress(ModelName),
+    #[error("failed to load {path}, the current weights are kept - {error}")]
+    LoadFailed { path: String, error: String },
+    #[error("the inference thread of model {0} stopped")]
+    Stopped(ModelName),
+}
+
+/// New weights sent to the inference thread of a model.
+pub struct ModelSwap {
+    pub model: Arc<dyn LanguageModel>,
+    pub config: LlmConfig,
+    /// Receives the number of sessions that finished on the previous weights once they are done.
+    pub tx_drained: oneshot::Sender<usize>,
+}
+
+/// Reloads the weights of a model from another path.
+#[derive(Clone)]
+pub struct ModelReloader {
+    model: ModelName,
+    config: LlmConfig,
+    metrics: Arc<LlmMetrics>,
+    tx_commands: Sender<ModelCommand>,
+}
+
+/// Marks the model as reloading until dropped.
+struct ReloadGuard(Arc<LlmMetrics>);
+
+impl Drop for ReloadGuard {
+    fn drop(&mut self) {
+        self.0.is_reloading.store(false, Ordering::Release);
+    }
+}
+
+impl ModelReloader {
+    pub fn new(
+        model: ModelName,
+        config: LlmConfig,
+        metrics: Arc<LlmMetrics>,
+        tx_commands: Sender<ModelCommand>,
+    ) -> Self {
+        Self {
+            model,
+            config,
+            metrics,
+            tx_commands,
+        }
+    }
+
+    /// Loads the weights at `model_path` and swaps them in, returns the number of sessions that
+    /// finished on the previous weights. The reload carries on when the caller stops waiting for
+    /// it, the model can't be reloaded again until it is done.
+    pub async fn reload(&self, model_path: PathBuf) -> Result<usize, ReloadError> {
+        if self.metrics.is_reloading.swap(true, Ordering::AcqRel) {
+            return Err(ReloadError::InProgress(self.model.clone()));
+        }
+        let guard = ReloadGuard(self.metrics.clone());
+        let reloader = self.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            reloader.swap_in(model_path).await
+        })
+        .await
+        .unwrap_or_else(|_| Err(ReloadError::Stopped(self.model.clone())))
+    }
+
+    async fn swap_in(&self, model_path: PathBuf) -> Result<usize, ReloadError> {
+        let config = LlmConfig {
+            model_path,
+            ..self.config.clone()
+        };
+        let path = config.model_path.display().to_string();
+        log::info!("[{}] reloading from {path}", self.model);
+        self.metrics.reload_progress.reset();
+
+        // loading takes a while, it runs on its own thread like the initial load
+        let (tx_loaded, rx_loaded) = oneshot::channel();
+        let metrics = self.metrics.clone();
+        let load_config = config.clone();
+        std::thread::spawn(move || {
+            let _ = tx_loaded.send(load_model(&load_config, &metrics.reload_progress));
+        });
+        let model = match rx_loaded.await {
+            Ok(Ok(model)) => model,
+            Ok(Err(e)) => {
+                log::error!("[{}] failed to reload from {path} - {e}", self.model);
+                return Err(ReloadError::LoadFailed {
+                    path,
+                    error: e.to_string(),
+                });
+            }
+            Err(_) => {
+                return Err(ReloadError::LoadFailed {
+                    path,
+                    error: "the loading thread panicked".into(),
+                })
+            }
+        };
+        self.metrics.reload_progress.finish();
+
+        let (tx_drained, rx_drained) = oneshot::channel();
+        self.tx_commands
+            .send_async(ModelCommand::Swap(ModelSwap {
+                model,
+                config,
+                tx_drained,
+            }))
+            .await
+            .map_err(|_| ReloadError::Stopped(self.model.clone()))?;
+        let drained = rx_drained
+            .await
+            .map_err(|_| ReloadError::Stopped(self.model.clone()))?;
+        log::info!(
+            "[{}] reloaded from {path}, {drained} sessions finished on the previous weights",
+            self.model
+        );
+        Ok(drained)
+    }
+}
@@ -0,0 +1,13 @@
+//! Detection of degenerate answers. Small quantized models sometimes fall into a loop repeating
+//! the same phrase until the token limit is hit, such answers are cut short.
+
+/// Whether the last `window` bytes of `answer` consist of a phrase repeated at least `threshold`
+/// times. A `window` of `0` disables the check.
+pub fn is_repeating(answer: &str, window: usize, threshold: usize) -> bool {
+    if window == 0 || threshold < 2 || answer.len() < window {
+        return false;
+    }
+    let tail = &answer.as_bytes()[answer.len() - window..];
+    // the tail repeats a phrase of `period` bytes if it equals itself shifted by that period
+    (1..=window / threshold).any(|period| tail[period..] == tail[..window - period])
+}
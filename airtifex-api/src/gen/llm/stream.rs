@@ -0,0 +1,129 @@
+use crate::{gen::llm::chunk::flush_len, id::Uuid, queue::QueuePosition};
+use airtifex_core::llm::{ChatStreamMessage, StopReason, StreamGranularity};
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::sync::watch;
+
+/// How long the answer of a finished response is kept in memory for reconnecting clients, after
+/// that only the saved chat entry is left.
+const FINISHED_RESPONSE_RETENTION: Duration = Duration::from_secs(60);
+
+/// Answer of a chat response as far as it was generated.
+#[derive(Clone, Debug, Default)]
+pub struct ResponseAnswer {
+    pub content: String,
+    pub is_finished: bool,
+    pub error: Option<String>,
+    /// Number of tokens the model generated, known once the answer is finished.
+    pub generated_tokens: Option<usize>,
+    pub stop_reason: StopReason,
+    /// Place of the request in the queue of the model until the answer is started.
+    pub queue: Option<QueuePosition>,
+}
+
+impl ResponseAnswer {
+    /// Returns the part of the answer after the first `offset` characters together with the
+    /// offset after it.
+    pub fn since(&self, offset: usize) -> (String, usize) {
+        let rest = self.content.chars().skip(offset).collect::<String>();
+        let end = offset + rest.chars().count();
+        (rest, end)
+    }
+
+    /// Like [`since`](Self::since) but ends the part at the last boundary of the granularity
+    /// until the answer is finished.
+    pub fn chunk_since(&self, offset: usize, granularity: StreamGranularity) -> (String, usize) {
+        let (mut rest, end) = self.since(offset);
+        if self.is_finished {
+            return (rest, end);
+        }
+        rest.truncate(flush_len(&rest, granularity));
+        let end = offset + rest.chars().count();
+        (rest, end)
+    }
+}
+
+/// Answer receivers keyed by chat together with the id of the stream they belong to.
+type StreamMap = HashMap<Uuid, (u64, watch::Receiver<ResponseAnswer>)>;
+
+/// Answers of chat responses that are being generated or finished recently, keyed by chat.
+/// The tokens are collected independently of the client that requested the response so that a
+/// client can reconnect and resume the stream from the last character it received.
+#[derive(Default)]
+pub struct ChatResponseStreams {
+    streams: Arc<Mutex<StreamMap>>,
+    next_stream_id: AtomicU64,
+}
+
+impl ChatResponseStreams {
+    /// Starts collecting the tokens of a new response of chat `id`, replacing the previous one.
+    pub fn start(
+        &self,
+        id: Uuid,
+        rx_tokens: flume::Receiver<ChatStreamMessage>,
+        queue: QueuePosition,
+    ) -> watch::Receiver<ResponseAnswer> {
+        let stream_id = self.next_stream_id.fetch_add(1, Ordering::Relaxed);
+        let (tx_answer, rx_answer) = watch::channel(ResponseAnswer {
+            queue: Some(queue),
+            ..Default::default()
+        });
+        self.streams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, (stream_id, rx_answer.clone()));
+
+        let streams = self.streams.clone();
+        tokio::spawn(async move {
+            // the sender is dropped once the inference session ends
+            while let Ok(message) = rx_tokens.recv_async().await {
+                match message {
+                    ChatStreamMessage::Token { content } => {
+                        tx_answer.send_modify(|answer| answer.content.push_str(&content))
+                    }
+                    ChatStreamMessage::Usage { generated_tokens } => tx_answer
+                        .send_modify(|answer| answer.generated_tokens = Some(generated_tokens)),
+                    ChatStreamMessage::Done { reason } => {
+                        tx_answer.send_modify(|answer| answer.stop_reason = reason)
+                    }
+                    ChatStreamMessage::Error { message } => {
+                        tx_answer.send_modify(|answer| answer.error = Some(message))
+                    }
+                }
+            }
+            tx_answer.send_modify(|answer| answer.is_finished = true);
+
+            tokio::time::sleep(FINISHED_RESPONSE_RETENTION).await;
+            let mut streams = streams.lock().unwrap_or_else(|e| e.into_inner());
+            if matches!(streams.get(&id), Some((current, _)) if *current == stream_id) {
+                streams.remove(&id);
+            }
+        });
+
+        rx_answer
+    }
+
+    /// Drops the answer of the latest response of chat `id`, used once the answer was deleted.
+    pub fn forget(&self, id: &Uuid) {
+        self.streams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id);
+    }
+
+    /// Returns the answer of the latest response of chat `id` if it is still kept in memory.
+    pub fn get(&self, id: &Uuid) -> Option<watch::Receiver<ResponseAnswer>> {
+        self.streams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .map(|(_, rx_answer)| rx_answer.clone())
+    }
+}
@@ -0,0 +1,140 @@
+//! Read-only access to the vocabulary of a loaded model. Text is tokenized and tokens are turned
+//! back into text without starting an inference session or waiting in the queue of the model.
+
+use crate::gen::llm::backend::LanguageModel;
+use airtifex_core::llm::TokenPiece;
+
+use llm::TokenId;
+use std::sync::{Arc, RwLock};
+use thiserror::Error as ErrorType;
+
+#[derive(Debug, ErrorType)]
+pub enum TokenizeError {
+    #[error("the model isn't loaded yet")]
+    NotLoaded,
+    #[error("failed to tokenize the text - {0}")]
+    Tokenize(llm::TokenizationError),
+    #[error("token {0} isn't part of the vocabulary of the model")]
+    UnknownToken(TokenId),
+}
+
+/// Weights a model currently generates with, shared by its inference thread with the routes that
+/// only read the vocabulary. Empty until the weights are loaded, a reload replaces them.
+#[derive(Clone, Default)]
+pub struct LoadedModel(Arc<RwLock<Option<Arc<dyn LanguageModel>>>>);
+
+impl LoadedModel {
+    pub fn set(&self, model: Arc<dyn LanguageModel>) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Some(model);
+    }
+
+    pub fn get(&self) -> Result<Arc<dyn LanguageModel>, TokenizeError> {
+        self.0
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .ok_or(TokenizeError::NotLoaded)
+    }
+}
+
+/// Splits `text` into the tokens of the vocabulary of the model, the way a prompt that continues
+/// a session is split. A prompt starting a session is preceded by the beginning of text token.
+pub fn tokenize(model: &dyn LanguageModel, text: &str) -> Result<Vec<TokenPiece>, TokenizeError> {
+    let tokens = model
+        .vocabulary()
+        .tokenize(text, false)
+        .map_err(TokenizeError::Tokenize)?;
+    Ok(tokens
+        .into_iter()
+        .map(|(piece, id)| token_piece(id, piece))
+        .collect())
+}
+
+/// Looks up the tokens in the vocabulary of the model, returns the text they make up together
+/// with every token.
+pub fn detokenize(
+    model: &dyn LanguageModel,
+    ids: &[TokenId],
+) -> Result<(String, Vec<TokenPiece>), TokenizeError> {
+    let vocabulary = model.vocabulary();
+    let mut text = vec![];
+    let mut tokens = Vec::with_capacity(ids.len());
+    for id in ids {
+        let piece = usize::try_from(*id)
+            .ok()
+            .filter(|idx| *idx < vocabulary.id_to_token.len())
+            .map(|idx| vocabulary.token(idx))
+            .ok_or(TokenizeError::UnknownToken(*id))?;
+        text.extend_from_slice(piece);
+        tokens.push(token_piece(*id, piece));
+    }
+    // a character can be split across tokens so the text is decoded as a whole
+    Ok((String::from_utf8_lossy(&text).into_owned(), tokens))
+}
+
+/// A token that is only part of a character is shown with the replacement character.
+fn token_piece(id: TokenId, piece: &[u8]) -> TokenPiece {
+    TokenPiece {
+        id,
+        piece: String::from_utf8_lossy(piece).into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::llm::backend::mock::MockModel;
+
+    /// Model with a small vocabulary. Like in the vocabularies of real models the first tokens
+    /// are special, the text is split into the others. The last two tokens are the bytes of "é".
+    fn model() -> MockModel {
+        MockModel::answering(&[]).with_vocabulary(&[
+            b"<unk>", b"<s>", b"</s>", b"Hello", b" world", b" wor", b"ld", b"!", b" ", b"\xc3",
+            b"\xa9",
+        ])
+    }
+
+    fn ids(tokens: &[TokenPiece]) -> Vec<TokenId> {
+        tokens.iter().map(|token| token.id).collect()
+    }
+
+    #[test]
+    fn detokenized_tokens_are_the_text() {
+        let model = model();
+        for text in ["Hello world!", "Hello  Hello!", "ld wor"] {
+            let tokens = tokenize(&model, text).unwrap();
+            let (detokenized, pieces) = detokenize(&model, &ids(&tokens)).unwrap();
+            assert_eq!(detokenized, text);
+            assert_eq!(pieces, tokens);
+        }
+        let tokens = tokenize(&model, "Hello world!").unwrap();
+        assert_eq!(ids(&tokens), [3, 4, 7]);
+    }
+
+    #[test]
+    fn characters_split_across_tokens_are_decoded_as_a_whole() {
+        let model = model();
+        let tokens = tokenize(&model, "é!").unwrap();
+        assert_eq!(ids(&tokens), [9, 10, 7]);
+        assert_eq!(tokens[0].piece, "\u{fffd}");
+
+        let (text, pieces) = detokenize(&model, &ids(&tokens)).unwrap();
+        assert_eq!(text, "é!");
+        assert_eq!(pieces, tokens);
+    }
+
+    #[test]
+    fn unknown_tokens_are_refused() {
+        let model = model();
+        for id in [11, -1] {
+            assert!(matches!(
+                detokenize(&model, &[3, id]),
+                Err(TokenizeError::UnknownToken(unknown)) if unknown == id
+            ));
+        }
+        assert!(matches!(
+            tokenize(&model, "Hallo"),
+            Err(TokenizeError::Tokenize(_))
+        ));
+    }
+}
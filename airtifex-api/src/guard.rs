@@ -1,16 +1,15 @@
 use crate::{
     auth::Claims,
-    models::user::{account_type_from_str, AuthenticationError, User},
+    models::{
+        audit::AuditEntry,
+        user::{account_type_from_str, AuthenticationError, User},
+    },
     permissions::Acl,
-    DbPool,
+    DbPool, ToAxumResponse,
 };
-use airtifex_core::{api_response::ApiResponse, user::AuthenticatedUser};
+use airtifex_core::{api_response::ApiResponse, audit::AuditAction, user::AuthenticatedUser};
 
-use axum::{
-    http::StatusCode,
-    response::{IntoResponse, Response},
-    Json,
-};
+use axum::response::Response;
 
 #[allow(unused_macros)]
 macro_rules! with_optional_guard {
@@ -84,6 +83,15 @@ macro_rules! with_service_guard {
     };
 }
 
+/// Returns the refusal of `action` when an admin takes it while impersonating a user.
+macro_rules! without_impersonation {
+    ($claims:ident, $db:ident, $action:expr) => {
+        if let Some(response) = crate::guard::refuse_impersonation(&$claims, &$db, $action).await {
+            return response;
+        }
+    };
+}
+
 macro_rules! with_user_guard {
     ($req:ident, $db:ident) => {
         with_guard!(
@@ -110,6 +118,7 @@ pub async fn auth_guard_err(
     let account_type = account_type_from_str(&claims.role)?;
 
     if !acl.has_account_type(account_type) {
+        AuditEntry::record(db, claims, AuditAction::AuthorizationFailed, None).await;
         return Err(AuthenticationError::Unauthorized.into());
     }
 
@@ -120,6 +129,7 @@ pub async fn auth_guard_err(
             account_type,
             registration_date: user.registration_date,
             email: user.email,
+            impersonator: claims.impersonator.clone(),
         }),
         Err(e) => Err(e),
     }
@@ -131,13 +141,26 @@ pub async fn auth_guard(
     acl: Acl,
 ) -> Result<AuthenticatedUser, Response> {
     auth_guard_err(claims, db, acl).await.map_err(|e| match e {
-        crate::models::Error::AuthenticationError(e) => {
-            (StatusCode::UNAUTHORIZED, Json(ApiResponse::failure(e))).into_response()
+        // a valid session that lacks permissions must not send the client back to the login
+        crate::models::Error::AuthenticationError(e @ AuthenticationError::Unauthorized) => {
+            ApiResponse::failure(e).forbidden()
         }
-        e => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ApiResponse::failure(e)),
-        )
-            .into_response(),
+        crate::models::Error::AuthenticationError(e) => ApiResponse::failure(e).unauthorized(),
+        e => ApiResponse::failure(e).internal_server_error(),
     })
 }
+
+/// Refuses actions an admin must not take on behalf of an impersonated user, the attempt is
+/// recorded in the audit log.
+pub async fn refuse_impersonation(claims: &Claims, db: &DbPool, action: &str) -> Option<Response> {
+    let impersonator = claims.impersonator.as_deref()?;
+    log::warn!(
+        "{impersonator} tried {action} while acting as {}",
+        claims.sub
+    );
+    AuditEntry::record(db, claims, AuditAction::AuthorizationFailed, Some(action)).await;
+    Some(
+        ApiResponse::failure(format!("{action} isn't allowed while impersonating a user"))
+            .forbidden(),
+    )
+}
@@ -1,28 +1,45 @@
 #![feature(path_file_prefix)]
 #![feature(let_chains)]
-pub use airtifex_core::api_response::{ApiResponse, ApiVersion};
+pub use airtifex_core::api_response::{ApiResponse, ApiVersion, ErrorCode};
 use config::LlmConfig;
 pub use errors::Error;
 
 use axum::{extract::FromRef, http::StatusCode, response::Response};
 use axum_extra::extract::cookie::Key;
-use flume::Sender;
 use std::{collections::HashMap, ops::Deref};
 
 #[macro_use]
 mod guard;
 
 pub mod auth;
+pub mod body_limit;
 pub mod config;
+pub mod cors;
 pub mod errors;
 pub mod gen;
 pub mod id;
+pub mod login_lockout;
+pub mod metrics;
 pub mod models;
+pub mod moderation;
+pub mod password;
 pub mod permissions;
 pub mod queue;
+pub mod rate_limit;
+pub mod request_id;
 pub mod routes;
-
-use gen::{image::GenerateImageRequest, llm::InferenceRequest, ModelName};
+pub mod share;
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod testing;
+pub mod validation;
+pub mod webhook;
+
+use gen::{
+    image::{progress::ImageProgressStreams, reroll::SampleRerolls, GenerateImageRequest},
+    llm::{ChatResponseStreams, InferenceRequest, LlmCommands, LlmModels, ModelReloader},
+    ModelName,
+};
+use queue::QueueSender;
 
 #[cfg(all(feature = "postgres", not(feature = "sqlite")))]
 pub type DbPool = sqlx::PgPool;
@@ -31,6 +48,11 @@ pub type DbPool = sqlx::SqlitePool;
 #[cfg(all(not(feature = "sqlite"), not(feature = "postgres")))]
 pub type DbPool = ();
 
+#[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+pub type Db = sqlx::Postgres;
+#[cfg(all(feature = "sqlite", not(feature = "postgres")))]
+pub type Db = sqlx::Sqlite;
+
 pub type Result<T> = core::result::Result<T, errors::Error>;
 
 pub struct InnerAppState {
@@ -38,8 +60,21 @@ pub struct InnerAppState {
     pub db: std::sync::Arc<crate::DbPool>,
     pub key: Key,
     pub config: config::Config,
-    pub tx_inference_req: HashMap<ModelName, (LlmConfig, Sender<InferenceRequest>)>,
-    pub tx_image_gen_req: HashMap<ModelName, Sender<GenerateImageRequest>>,
+    pub tx_inference_req: HashMap<ModelName, (LlmConfig, QueueSender<InferenceRequest>)>,
+    pub llm_reloaders: HashMap<ModelName, ModelReloader>,
+    pub llm_commands: LlmCommands,
+    /// Loaded weights of the language models, only their vocabularies are read through them.
+    pub llm_models: LlmModels,
+    pub tx_image_gen_req: HashMap<ModelName, QueueSender<GenerateImageRequest>>,
+    pub rate_limiter: rate_limit::RateLimiter,
+    pub login_lockout: login_lockout::LoginLockout,
+    pub chat_streams: ChatResponseStreams,
+    pub image_progress: ImageProgressStreams,
+    pub sample_rerolls: SampleRerolls,
+    pub metrics: std::sync::Arc<metrics::Metrics>,
+    pub webhooks: webhook::Webhooks,
+    /// Checks the prompts of chats, inferences and images before they are queued.
+    pub moderator: std::sync::Arc<dyn moderation::Moderator>,
 }
 
 #[derive(Clone)]
@@ -83,14 +118,68 @@ pub trait ToAxumResponse: Sized {
         self.into_response(StatusCode::BAD_REQUEST)
     }
 
+    fn not_found(self) -> Response {
+        self.into_response(StatusCode::NOT_FOUND)
+    }
+
+    fn forbidden(self) -> Response {
+        self.into_response(StatusCode::FORBIDDEN)
+    }
+
+    fn conflict(self) -> Response {
+        self.into_response(StatusCode::CONFLICT)
+    }
+
+    fn payload_too_large(self) -> Response {
+        self.into_response(StatusCode::PAYLOAD_TOO_LARGE)
+    }
+
+    fn unprocessable_entity(self) -> Response {
+        self.into_response(StatusCode::UNPROCESSABLE_ENTITY)
+    }
+
+    fn too_many_requests(self) -> Response {
+        self.into_response(StatusCode::TOO_MANY_REQUESTS)
+    }
+
     fn internal_server_error(self) -> Response {
         self.into_response(StatusCode::INTERNAL_SERVER_ERROR)
     }
+
+    fn service_unavailable(self) -> Response {
+        self.into_response(StatusCode::SERVICE_UNAVAILABLE)
+    }
 }
 
 impl ToAxumResponse for ApiResponse {
     fn into_response(self, code: StatusCode) -> Response {
         use axum::response::IntoResponse;
-        (code, axum::Json(self)).into_response()
+        // failures without an explicit code get the one matching their status
+        let response = match (self.code(), default_error_code(code)) {
+            (None, Some(error_code)) => self.with_code(error_code),
+            _ => self,
+        };
+        let response = match request_id::try_current() {
+            Some(id) => response.with_request_id(id.to_string()),
+            None => response,
+        };
+        (code, axum::Json(response)).into_response()
     }
 }
+
+fn default_error_code(status: StatusCode) -> Option<ErrorCode> {
+    let code = match status {
+        StatusCode::BAD_REQUEST => ErrorCode::ValidationFailed,
+        StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+        StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+        StatusCode::NOT_FOUND => ErrorCode::NotFound,
+        StatusCode::CONFLICT => ErrorCode::Conflict,
+        StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooLarge,
+        StatusCode::UNPROCESSABLE_ENTITY => ErrorCode::ContentBlocked,
+        StatusCode::TOO_MANY_REQUESTS => ErrorCode::QuotaExceeded,
+        StatusCode::SERVICE_UNAVAILABLE => ErrorCode::ModelUnavailable,
+        status if status.is_server_error() => ErrorCode::Internal,
+        _ => return None,
+    };
+    Some(code)
+}
@@ -0,0 +1,207 @@
+//! Protection of the login against password guessing. Failed logins are counted per account and
+//! per client address, every failure makes the next attempt wait twice as long and too many of
+//! them lock the account or address for a while. Attempts are refused while waiting, even with
+//! the correct password, so that guesses can't be confirmed during a lockout.
+
+use crate::config::LoginLockoutConfig;
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Number of tracked accounts and addresses after which the forgotten ones get dropped.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// What failed logins are counted for.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LoginKey {
+    Account(String),
+    Ip(String),
+}
+
+impl LoginKey {
+    /// Audit target of a lockout of the key.
+    pub fn target(&self) -> String {
+        match self {
+            LoginKey::Account(username) => username.clone(),
+            LoginKey::Ip(ip) => format!("ip:{ip}"),
+        }
+    }
+}
+
+struct Failures {
+    count: u32,
+    last: Instant,
+    /// No login is attempted before this.
+    blocked_until: Instant,
+}
+
+#[derive(Default)]
+pub struct LoginLockout {
+    failures: Mutex<HashMap<LoginKey, Failures>>,
+}
+
+impl LoginLockout {
+    /// Returns how long the login has to wait when one of the keys is blocked.
+    pub fn check(&self, config: &LoginLockoutConfig, keys: &[LoginKey]) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        let mut wait = Duration::ZERO;
+        for key in keys {
+            let Some(entry) = failures.get(key) else {
+                continue;
+            };
+            if is_forgotten(config, entry, now) {
+                failures.remove(key);
+                continue;
+            }
+            wait = wait.max(entry.blocked_until.saturating_duration_since(now));
+        }
+        if wait.is_zero() {
+            Ok(())
+        } else {
+            Err(wait)
+        }
+    }
+
+    /// Counts a failed login for the keys and blocks them, returns the keys that are locked out
+    /// by this failure.
+    pub fn record_failure(&self, config: &LoginLockoutConfig, keys: &[LoginKey]) -> Vec<LoginKey> {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        if failures.len() >= MAX_TRACKED_KEYS {
+            failures.retain(|_, entry| !is_forgotten(config, entry, now));
+        }
+
+        let mut locked = vec![];
+        for key in keys {
+            let max_failures = match key {
+                LoginKey::Account(_) => config.max_account_failures,
+                LoginKey::Ip(_) => config.max_ip_failures,
+            };
+            if max_failures == 0 {
+                continue;
+            }
+            let entry = failures.entry(key.clone()).or_insert(Failures {
+                count: 0,
+                last: now,
+                blocked_until: now,
+            });
+            if is_forgotten(config, entry, now) {
+                entry.count = 0;
+            }
+            entry.count += 1;
+            entry.last = now;
+            if entry.count >= max_failures {
+                // the failures are counted from the start once the lockout is over
+                entry.count = 0;
+                entry.blocked_until = now + config.lockout();
+                locked.push(key.clone());
+            } else {
+                entry.blocked_until = now + config.backoff(entry.count);
+            }
+        }
+        locked
+    }
+
+    /// Forgets the failures of the key after a successful login.
+    pub fn reset(&self, key: &LoginKey) {
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        failures.remove(key);
+    }
+}
+
+/// Failures are forgotten once a lockout passed since the last one and the key isn't blocked.
+fn is_forgotten(config: &LoginLockoutConfig, entry: &Failures, now: Instant) -> bool {
+    entry.blocked_until <= now && now.duration_since(entry.last) >= config.lockout()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(backoff_secs: u64) -> LoginLockoutConfig {
+        LoginLockoutConfig {
+            max_account_failures: 3,
+            max_ip_failures: 0,
+            backoff_secs,
+            lockout_secs: 60,
+        }
+    }
+
+    fn account(username: &str) -> LoginKey {
+        LoginKey::Account(username.to_string())
+    }
+
+    /// Moves the failures `duration` into the past.
+    fn elapse(lockout: &LoginLockout, duration: Duration) {
+        for entry in lockout.failures.lock().unwrap().values_mut() {
+            entry.last -= duration;
+            entry.blocked_until -= duration;
+        }
+    }
+
+    #[test]
+    fn accounts_are_locked_after_max_failures() {
+        let (lockout, config) = (LoginLockout::default(), config(0));
+        let keys = [account("alice"), LoginKey::Ip("127.0.0.1".into())];
+
+        for _ in 0..2 {
+            assert!(lockout.record_failure(&config, &keys).is_empty());
+            assert_eq!(lockout.check(&config, &keys), Ok(()));
+        }
+        // addresses are never locked with `max_ip_failures: 0`
+        assert_eq!(lockout.record_failure(&config, &keys), [account("alice")]);
+
+        let wait = lockout.check(&config, &keys).unwrap_err();
+        assert!(wait > Duration::from_secs(59) && wait <= config.lockout());
+        assert_eq!(lockout.check(&config, &[account("bob")]), Ok(()));
+    }
+
+    #[test]
+    fn failures_wait_twice_as_long_each_time() {
+        let (lockout, config) = (LoginLockout::default(), config(1));
+        let keys = [account("alice")];
+
+        for expected in [1, 2] {
+            lockout.record_failure(&config, &keys);
+            let wait = lockout.check(&config, &keys).unwrap_err();
+            assert!(wait > Duration::from_secs(expected) - Duration::from_millis(500));
+            assert!(wait <= Duration::from_secs(expected));
+        }
+    }
+
+    #[test]
+    fn successful_logins_reset_the_failures() {
+        let (lockout, config) = (LoginLockout::default(), config(0));
+        let keys = [account("alice")];
+
+        lockout.record_failure(&config, &keys);
+        lockout.record_failure(&config, &keys);
+        lockout.reset(&keys[0]);
+
+        assert!(lockout.record_failure(&config, &keys).is_empty());
+        assert!(lockout.record_failure(&config, &keys).is_empty());
+        assert_eq!(lockout.check(&config, &keys), Ok(()));
+    }
+
+    #[test]
+    fn lockouts_end_after_the_window() {
+        let (lockout, config) = (LoginLockout::default(), config(0));
+        let keys = [account("alice")];
+        for _ in 0..3 {
+            lockout.record_failure(&config, &keys);
+        }
+
+        elapse(&lockout, config.lockout() - Duration::from_secs(1));
+        assert!(lockout.check(&config, &keys).is_err());
+
+        elapse(&lockout, Duration::from_secs(1));
+        assert_eq!(lockout.check(&config, &keys), Ok(()));
+        // the failures are counted from the start again
+        assert!(lockout.record_failure(&config, &keys).is_empty());
+        assert_eq!(lockout.check(&config, &keys), Ok(()));
+    }
+}
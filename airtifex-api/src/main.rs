@@ -1,20 +1,26 @@
 use airtifex_api::{
     config::Config,
-    gen,
+    cors,
+    gen::{self, image::progress::ImageProgressStreams},
     id::V1Context as ClockContext,
-    models::user::User,
-    routes::{api, r#static},
-    DbPool, Error, InnerAppState, Result, SharedAppState,
+    metrics::{self, Metrics},
+    models::{self, batch::BatchEntry, image::Image, user::User},
+    moderation,
+    password::hash_password_blocking,
+    request_id::{self, RequestId},
+    routes::{api, public, r#static},
+    webhook::Webhooks,
+    InnerAppState, Result, SharedAppState,
 };
 use airtifex_core::user::AccountType;
 
 use axum::{extract::DefaultBodyLimit, Router};
 use axum_extra::extract::cookie::Key;
 use clap::Parser;
-use std::{path::PathBuf, sync::Arc, time::Duration};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 use tokio::runtime::Runtime;
 use tower_http::classify::ServerErrorsFailureClass;
-use tracing::{Level, Span};
+use tracing::Span;
 use tracing_subscriber::{field::MakeExt, layer::SubscriberExt, util::SubscriberInitExt};
 
 #[cfg(all(feature = "postgres", feature = "sqlite"))]
@@ -58,15 +64,11 @@ async fn inner(runtime: Arc<Runtime>) -> Result<()> {
 
     match opts.command {
         Command::Serve => {
-            let db_pool = Arc::new(
-                DbPool::connect(&config.db_url)
-                    .await
-                    .map_err(Error::DatabaseError)?,
-            );
+            let cors = cors::cors_layer(&config.cors)?;
+            let db_pool = Arc::new(models::connect(&config.db_url, &config.database).await?);
 
             #[cfg(all(feature = "sqlite", not(feature = "postgres")))]
             {
-                airtifex_api::models::run_pragma(&db_pool).await?;
                 sqlx::migrate!("migrations/sqlite").run(&*db_pool).await?;
             }
 
@@ -75,37 +77,112 @@ async fn inner(runtime: Arc<Runtime>) -> Result<()> {
                 sqlx::migrate!("migrations/postgres").run(&*db_pool).await?;
             }
 
+            match Image::fail_interrupted(&db_pool).await {
+                Ok(0) => {}
+                Ok(n) => tracing::warn!("marked {n} interrupted image generations as failed"),
+                Err(e) => tracing::error!("failed to mark interrupted image generations - {e}"),
+            }
+            match BatchEntry::fail_interrupted(&db_pool).await {
+                Ok(0) => {}
+                Ok(n) => tracing::warn!("marked {n} interrupted batch prompts as failed"),
+                Err(e) => tracing::error!("failed to mark interrupted batch prompts - {e}"),
+            }
+
             let context = ClockContext::new(0);
 
-            let user = User::new("admin", "admin", "", AccountType::Admin);
+            let password =
+                hash_password_blocking("admin".into(), config.passwords.hash_iterations).await;
+            let user = User::new("admin", password, "", AccountType::Admin);
             let _ = user.create(&db_pool).await;
 
             let listen = (config.listen_addr, config.listen_port);
 
-            let tx_inference_req =
-                gen::llm::initialize_models(db_pool.clone(), &config, runtime.clone()).await?;
+            let metrics = Arc::new(Metrics::default());
+            let webhooks = Webhooks::new(db_pool.clone(), config.webhooks.clone());
+            let moderator = moderation::from_config(&config.moderation)?;
+            let output_moderator = config.moderation.check_output.then(|| moderator.clone());
+            let (tx_inference_req, llm_reloaders, llm_commands, llm_models) =
+                gen::llm::initialize_models(
+                    db_pool.clone(),
+                    &config,
+                    runtime.clone(),
+                    &metrics,
+                    output_moderator,
+                )
+                .await?;
             let tx_image_gen_req =
-                gen::image::initialize_models(db_pool.clone(), &config, runtime.clone()).await?;
+                gen::image::initialize_models(db_pool.clone(), &config, runtime.clone(), &webhooks)
+                    .await?;
+
+            let image_progress = ImageProgressStreams::new(config.image_cancel.grace_period());
 
             std::env::set_var("JWT_SECRET", &config.jwt_secret);
 
-            let app = Router::new()
-                .merge(api::router())
-                .merge(r#static::router())
-                .with_state(SharedAppState::from(Arc::new(InnerAppState {
-                    db: db_pool,
-                    uuid_context: context,
-                    key: Key::generate(),
-                    config,
-                    tx_inference_req,
-                    tx_image_gen_req,
-                })))
-                .layer(DefaultBodyLimit::max(8 * 1000 * 1000))
+            let state = SharedAppState::from(Arc::new(InnerAppState {
+                db: db_pool,
+                uuid_context: context,
+                key: Key::generate(),
+                config,
+                tx_inference_req,
+                llm_reloaders,
+                llm_commands,
+                llm_models,
+                tx_image_gen_req,
+                rate_limiter: Default::default(),
+                login_lockout: Default::default(),
+                chat_streams: Default::default(),
+                image_progress,
+                sample_rerolls: Default::default(),
+                metrics,
+                webhooks,
+                moderator,
+            }));
+
+            let mut app = Router::new()
+                .merge(api::router(state.clone()))
+                .merge(public::router(state.clone()))
+                .merge(r#static::router());
+            if let Some(port) = state.config.metrics.listen_port {
+                let addr = state
+                    .config
+                    .metrics
+                    .listen_addr
+                    .unwrap_or(state.config.listen_addr);
+                let metrics_app = metrics::router().with_state(state.clone());
+                tracing::info!("serving metrics on {addr}:{port}");
+                tokio::spawn(async move {
+                    if let Err(e) = axum::Server::bind(&(addr, port).into())
+                        .serve(metrics_app.into_make_service())
+                        .await
+                    {
+                        tracing::error!("metrics server failed - {e}");
+                    }
+                });
+            } else {
+                app = app.merge(metrics::router());
+            }
+            // applied to the whole router so that preflight requests and streamed responses carry
+            // the headers as well
+            if let Some(cors) = cors {
+                app = app.layer(cors);
+            }
+
+            // routes of the API override it with the limit of their group
+            let max_body = state.config.body_limits.max_request_body_bytes;
+            let app = app
+                .with_state(state)
+                .layer(DefaultBodyLimit::max(max_body))
                 .layer(
                     tower_http::trace::TraceLayer::new_for_http()
-                        .make_span_with(
-                            tower_http::trace::DefaultMakeSpan::new().level(Level::INFO),
-                        )
+                        .make_span_with(|req: &axum::http::Request<axum::body::Body>| {
+                            let id = req.extensions().get::<RequestId>().map(|id| id.0);
+                            tracing::info_span!(
+                                "request",
+                                id = %id.unwrap_or_default(),
+                                method = %req.method(),
+                                uri = %req.uri(),
+                            )
+                        })
                         .on_failure(
                             |_error: ServerErrorsFailureClass, _latency: Duration, _span: &Span| {},
                         )
@@ -114,11 +191,12 @@ async fn inner(runtime: Arc<Runtime>) -> Result<()> {
                                 tracing::info!("{} {}ms", rsp.status(), latency.as_millis());
                             },
                         ),
-                );
+                )
+                .layer(axum::middleware::from_fn(request_id::request_id));
 
             tracing::info!("listening on {}:{}", listen.0, listen.1);
             Ok(axum::Server::bind(&listen.into())
-                .serve(app.into_make_service())
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                 .await?)
         }
     }
@@ -136,6 +214,7 @@ fn main() {
     runtime.block_on(async move {
         if let Err(e) = inner(rt).await {
             eprintln!("Execution failed - {}", e);
+            std::process::exit(1);
         }
     })
 }
@@ -0,0 +1,377 @@
+use crate::{gen::llm::load::LoadProgressTracker, rate_limit::RouteGroup, SharedAppState};
+use airtifex_core::llm::ModelLoadStage;
+
+use axum::{
+    extract::State,
+    http::{header, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing, Router,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+/// Upper bounds of the request duration histogram buckets in seconds.
+const DURATION_BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// Gauges and counters of a single language model, updated by its inference thread.
+#[derive(Default)]
+pub struct LlmMetrics {
+    /// Number of requests waiting for a free inference session.
+    pub queue_depth: AtomicUsize,
+    pub running_sessions: AtomicUsize,
+    pub generated_tokens: AtomicU64,
+    /// Answers the model ended before generating anything, retries included.
+    pub empty_answers: AtomicU64,
+    pub empty_answer_retries: AtomicU64,
+    /// Answers replayed from the response cache instead of being generated.
+    pub cache_hits: AtomicU64,
+    pub load_progress: LoadProgressTracker,
+    /// Whether new weights of the model are being loaded, only one reload runs at a time.
+    pub is_reloading: AtomicBool,
+    pub reload_progress: LoadProgressTracker,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// Metrics of the server exposed in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    llms: Mutex<BTreeMap<String, Arc<LlmMetrics>>>,
+    image_generations: Mutex<BTreeMap<String, u64>>,
+    request_durations: Mutex<BTreeMap<RouteGroup, Histogram>>,
+}
+
+impl Metrics {
+    /// Returns the metrics of language model `model`, registering it on first use.
+    pub fn llm(&self, model: &str) -> Arc<LlmMetrics> {
+        self.llms
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(model.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Returns the number of queued requests and running sessions summed over all models.
+    pub fn inference_load(&self) -> (usize, usize) {
+        self.llms
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .fold((0, 0), |(queued, running), m| {
+                (
+                    queued + m.queue_depth.load(Ordering::Relaxed),
+                    running + m.running_sessions.load(Ordering::Relaxed),
+                )
+            })
+    }
+
+    pub fn count_image_generation(&self, model: &str) {
+        *self
+            .image_generations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(model.to_string())
+            .or_default() += 1;
+    }
+
+    fn observe_request_duration(&self, group: RouteGroup, seconds: f64) {
+        self.request_durations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(group)
+            .or_default()
+            .observe(seconds);
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let llms = self.llms.lock().unwrap_or_else(|e| e.into_inner());
+        let mut write_llm_metric = |name, kind, help, value: fn(&LlmMetrics) -> u64| {
+            write_header(&mut out, name, kind, help);
+            for (model, metrics) in llms.iter() {
+                let _ = writeln!(
+                    out,
+                    "{name}{{model=\"{}\"}} {}",
+                    escape_label(model),
+                    value(metrics)
+                );
+            }
+        };
+        write_llm_metric(
+            "airtifex_llm_queue_depth",
+            "gauge",
+            "Number of inference requests waiting for a free session.",
+            |m| m.queue_depth.load(Ordering::Relaxed) as u64,
+        );
+        write_llm_metric(
+            "airtifex_llm_running_sessions",
+            "gauge",
+            "Number of inference sessions generating tokens.",
+            |m| m.running_sessions.load(Ordering::Relaxed) as u64,
+        );
+        write_llm_metric(
+            "airtifex_llm_generated_tokens_total",
+            "counter",
+            "Number of tokens generated.",
+            |m| m.generated_tokens.load(Ordering::Relaxed),
+        );
+        write_llm_metric(
+            "airtifex_llm_empty_answers_total",
+            "counter",
+            "Number of answers that ended before anything was generated.",
+            |m| m.empty_answers.load(Ordering::Relaxed),
+        );
+        write_llm_metric(
+            "airtifex_llm_empty_answer_retries_total",
+            "counter",
+            "Number of empty answers that were generated once more.",
+            |m| m.empty_answer_retries.load(Ordering::Relaxed),
+        );
+        write_llm_metric(
+            "airtifex_llm_cache_hits_total",
+            "counter",
+            "Number of answers replayed from the response cache.",
+            |m| m.cache_hits.load(Ordering::Relaxed),
+        );
+        write_llm_metric(
+            "airtifex_llm_loaded",
+            "gauge",
+            "Whether the model finished loading and serves requests.",
+            |m| (m.load_progress.stage() == ModelLoadStage::Loaded) as u64,
+        );
+        drop(llms);
+
+        let name = "airtifex_image_generations_total";
+        write_header(
+            &mut out,
+            name,
+            "counter",
+            "Number of image generations dispatched to the models.",
+        );
+        for (model, count) in self
+            .image_generations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let _ = writeln!(out, "{name}{{model=\"{}\"}} {count}", escape_label(model));
+        }
+
+        let name = "airtifex_http_request_duration_seconds";
+        write_header(
+            &mut out,
+            name,
+            "histogram",
+            "Duration of API requests until the response starts.",
+        );
+        for (group, histogram) in self
+            .request_durations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let group = group.as_ref();
+            for (count, bound) in histogram.buckets.iter().zip(DURATION_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "{name}_bucket{{group=\"{group}\",le=\"{bound}\"}} {count}"
+                );
+            }
+            let count = histogram.count;
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{group=\"{group}\",le=\"+Inf\"}} {count}"
+            );
+            let _ = writeln!(out, "{name}_sum{{group=\"{group}\"}} {}", histogram.sum);
+            let _ = writeln!(out, "{name}_count{{group=\"{group}\"}} {count}");
+        }
+
+        out
+    }
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Middleware recording the duration of requests of a route group.
+pub async fn track_duration<B>(
+    State((state, group)): State<(SharedAppState, RouteGroup)>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let start = Instant::now();
+    let response = next.run(req).await;
+    state
+        .metrics
+        .observe_request_duration(group, start.elapsed().as_secs_f64());
+    response
+}
+
+async fn metrics(State(state): State<SharedAppState>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+        .into_response()
+}
+
+/// Unauthenticated `GET /metrics` route, served either by the API server or on its own address.
+pub fn router() -> Router<SharedAppState> {
+    Router::new().route("/metrics", routing::get(metrics))
+}
+
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use airtifex_core::user::AccountType;
+
+    use axum::{
+        body::Body,
+        http::{Method, StatusCode},
+    };
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    /// Splits a sample line into its name with labels and its value.
+    fn sample(line: &str) -> (&str, f64) {
+        let (series, value) = line.rsplit_once(' ').expect("sample has a value");
+        let value = match value {
+            "+Inf" => f64::INFINITY,
+            value => value.parse().expect("value is a number"),
+        };
+        (series, value)
+    }
+
+    #[tokio::test]
+    async fn scrape_is_in_the_prometheus_text_format() {
+        let db = testing::db().await;
+        testing::user(&db, "alice", AccountType::User).await;
+        let state = testing::state(db, testing::config(""));
+        let router = testing::router(state.clone()).merge(router().with_state(state.clone()));
+
+        let llama = state.metrics.llm("llama");
+        llama.generated_tokens.store(7, Ordering::Relaxed);
+        state.metrics.count_image_generation("stable \"diffusion\"");
+        let (status, _) = testing::send(
+            &router,
+            Method::POST,
+            "/api/v1/users/login",
+            None,
+            Some(json!({"username": "alice", "password": testing::PASSWORD})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let response = router
+            .oneshot(
+                Request::get("/metrics")
+                    .body(Body::empty())
+                    .expect("request is valid"),
+            )
+            .await
+            .expect("router doesn't fail");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/plain; version=0.0.4"
+        );
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("body is read");
+        let body = String::from_utf8(body.to_vec()).expect("body is text");
+
+        // every family starts with its help and type, followed by samples of that family only
+        let mut family = None;
+        let mut lines = body.lines().peekable();
+        while let Some(line) = lines.next() {
+            if let Some(help) = line.strip_prefix("# HELP ") {
+                let (name, _) = help.split_once(' ').expect("help has a text");
+                let kind = lines.next().expect("type follows the help");
+                let kind = kind
+                    .strip_prefix(&format!("# TYPE {name} "))
+                    .expect("type is of the same family");
+                assert!(["gauge", "counter", "histogram"].contains(&kind));
+                family = Some(name);
+                continue;
+            }
+            let name = family.expect("samples follow a header");
+            let (series, _) = sample(line);
+            assert!(series.starts_with(name), "{series} belongs to {name}");
+            assert!(series.ends_with('}'), "{series} has labels");
+        }
+
+        let value = |series: &str| {
+            body.lines()
+                .filter(|l| !l.starts_with('#'))
+                .map(sample)
+                .find(|(s, _)| *s == series)
+                .map(|(_, v)| v)
+                .unwrap_or_else(|| panic!("{series} is scraped"))
+        };
+        assert_eq!(
+            value("airtifex_llm_generated_tokens_total{model=\"llama\"}"),
+            7.0
+        );
+        assert_eq!(value("airtifex_llm_loaded{model=\"llama\"}"), 0.0);
+        assert_eq!(
+            value("airtifex_image_generations_total{model=\"stable \\\"diffusion\\\"\"}"),
+            1.0
+        );
+
+        // buckets of the histogram are cumulative and end with all observations
+        let name = "airtifex_http_request_duration_seconds";
+        let buckets: Vec<_> = body
+            .lines()
+            .filter(|l| l.starts_with(&format!("{name}_bucket{{group=\"users\"")))
+            .map(|l| sample(l).1)
+            .collect();
+        assert_eq!(buckets.len(), DURATION_BUCKETS.len() + 1);
+        assert!(buckets.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(
+            value(&format!("{name}_bucket{{group=\"users\",le=\"+Inf\"}}")),
+            1.0
+        );
+        assert_eq!(value(&format!("{name}_count{{group=\"users\"}}")), 1.0);
+        assert!(value(&format!("{name}_sum{{group=\"users\"}}")) >= 0.0);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{
+    auth::Claims,
+    id::Uuid,
+    models::{user::User, Error, Result},
+    DbPool,
+};
+use airtifex_core::audit::AuditAction;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ErrorType;
+
+#[derive(Debug, ErrorType)]
+pub enum AuditError {
+    #[error("failed to create an audit entry - {0}")]
+    CreateError(sqlx::Error),
+    #[error("failed to list audit entries - {0}")]
+    ListError(sqlx::Error),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub actor_user_id: Option<Uuid>,
+    /// Admin acting as the actor when the action was taken during an impersonation.
+    pub impersonator_user_id: Option<Uuid>,
+    pub action: AuditAction,
+    pub target: Option<String>,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+impl AuditEntry {
+    pub fn new(actor_user_id: Option<Uuid>, action: AuditAction, target: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            actor_user_id,
+            impersonator_user_id: None,
+            action,
+            target,
+            timestamp: chrono::Utc::now(),
+        }
+    }
+}
+
+impl AuditEntry {
+    /// Records `action` of the user of the claims on `target`, actions taken during an
+    /// impersonation record the admin as well. The audit log must not break the action itself
+    /// so failures are only logged.
+    pub async fn record(db: &DbPool, claims: &Claims, action: AuditAction, target: Option<&str>) {
+        let actor = &claims.sub;
+        let actor_user_id = User::get(db, actor).await.map(|user| user.id).ok();
+        let mut entry = Self::new(actor_user_id, action, target.map(str::to_string));
+        if let Some(impersonator) = &claims.impersonator {
+            entry.impersonator_user_id = User::get(db, impersonator).await.map(|user| user.id).ok();
+        }
+        if let Err(e) = entry.create(db).await {
+            log::error!("failed to record {} of {actor} - {e}", action.as_ref());
+        }
+    }
+
+    pub async fn create(&self, db: &DbPool) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log
+                    (id, actor_user_id, impersonator_user_id, action, target, timestamp)
+            VALUES  ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(self.id)
+        .bind(self.actor_user_id)
+        .bind(self.impersonator_user_id)
+        .bind(self.action)
+        .bind(&self.target)
+        .bind(self.timestamp)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(AuditError::CreateError)
+        .map_err(Error::from)
+    }
+
+    /// Lists the entries newest first, optionally only those of `action`.
+    pub async fn list(
+        db: &DbPool,
+        page: Option<u32>,
+        page_size: Option<u32>,
+        action: Option<AuditAction>,
+    ) -> Result<Vec<Self>> {
+        let page = page.unwrap_or(1).max(1);
+        let page_size = page_size.unwrap_or(25);
+        let offset = (page - 1) * page_size;
+        sqlx::query_as(
+            r#"
+            SELECT id, actor_user_id, impersonator_user_id, action, target, timestamp
+            FROM audit_log
+            WHERE $1 IS NULL OR action = $1
+            ORDER BY timestamp DESC
+            LIMIT $2
+            OFFSET $3
+            "#,
+        )
+        .bind(action)
+        .bind(page_size as i32)
+        .bind(offset as i32)
+        .fetch_all(db)
+        .await
+        .map_err(AuditError::ListError)
+        .map_err(Error::from)
+    }
+}
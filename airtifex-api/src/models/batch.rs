@@ -0,0 +1,215 @@
+use crate::{
+    id::Uuid,
+    models::{Error, Result},
+    DbPool,
+};
+use airtifex_core::llm::BatchEntryStatus;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ErrorType;
+
+#[derive(Debug, ErrorType)]
+pub enum BatchError {
+    #[error("failed to create a batch - {0}")]
+    Create(sqlx::Error),
+    #[error("failed to inspect a batch - {0}")]
+    Inspect(sqlx::Error),
+    #[error("failed to update a batch entry - {0}")]
+    Update(sqlx::Error),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Batch {
+    pub id: Uuid,
+    pub username: String,
+    pub model: String,
+    pub seed: Option<i64>,
+    pub create_date: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BatchEntry {
+    pub batch_id: Uuid,
+    pub n: i32,
+    pub prompt: String,
+    pub status: BatchEntryStatus,
+    pub response: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Batch {
+    pub fn new(username: String, model: String, seed: Option<u64>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            username,
+            model,
+            seed: seed.map(|s| s as i64),
+            create_date: chrono::Utc::now(),
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Stores the batch with one queued entry per prompt.
+    pub async fn create(&self, db: &DbPool, prompts: &[String]) -> Result<()> {
+        let mut tx = db.begin().await.map_err(BatchError::Create)?;
+        sqlx::query(
+            r#"
+            INSERT INTO llm_batches
+                    (id, username, model, seed, create_date)
+            VALUES  ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(self.id)
+        .bind(&self.username)
+        .bind(&self.model)
+        .bind(self.seed)
+        .bind(self.create_date)
+        .execute(&mut tx)
+        .await
+        .map_err(BatchError::Create)?;
+
+        for (n, prompt) in prompts.iter().enumerate() {
+            sqlx::query(
+                r#"
+                INSERT INTO llm_batch_entries
+                        (batch_id, n, prompt, status)
+                VALUES  ($1, $2, $3, $4)
+                "#,
+            )
+            .bind(self.id)
+            .bind(n as i32)
+            .bind(prompt)
+            .bind(BatchEntryStatus::Queued)
+            .execute(&mut tx)
+            .await
+            .map_err(BatchError::Create)?;
+        }
+
+        tx.commit()
+            .await
+            .map(|_| ())
+            .map_err(BatchError::Create)
+            .map_err(Error::from)
+    }
+
+    pub async fn get_batch_for_user(db: &DbPool, username: &str, id: &Uuid) -> Result<Self> {
+        sqlx::query_as(
+            r#"
+            SELECT id, username, model, seed, create_date
+            FROM llm_batches
+            WHERE id = $1 AND username = $2
+            "#,
+        )
+        .bind(id)
+        .bind(username)
+        .fetch_one(db)
+        .await
+        .map_err(BatchError::Inspect)
+        .map_err(Error::from)
+    }
+}
+
+impl BatchEntry {
+    pub async fn list(db: &DbPool, batch_id: &Uuid) -> Result<Vec<Self>> {
+        sqlx::query_as(
+            r#"
+            SELECT batch_id, n, prompt, status, response, error
+            FROM llm_batch_entries
+            WHERE batch_id = $1
+            ORDER BY n
+            "#,
+        )
+        .bind(batch_id)
+        .fetch_all(db)
+        .await
+        .map_err(BatchError::Inspect)
+        .map_err(Error::from)
+    }
+
+    pub async fn start(db: &DbPool, batch_id: &Uuid, n: i32) -> Result<()> {
+        Self::update(db, batch_id, n, BatchEntryStatus::Running, None, None).await
+    }
+
+    pub async fn finish(db: &DbPool, batch_id: &Uuid, n: i32, response: &str) -> Result<()> {
+        Self::update(
+            db,
+            batch_id,
+            n,
+            BatchEntryStatus::Done,
+            Some(response),
+            None,
+        )
+        .await
+    }
+
+    /// Marks the entry as failed, `response` is the part of the answer generated before the
+    /// failure.
+    pub async fn fail(
+        db: &DbPool,
+        batch_id: &Uuid,
+        n: i32,
+        response: Option<&str>,
+        error: &str,
+    ) -> Result<()> {
+        Self::update(
+            db,
+            batch_id,
+            n,
+            BatchEntryStatus::Failed,
+            response,
+            Some(error),
+        )
+        .await
+    }
+
+    async fn update(
+        db: &DbPool,
+        batch_id: &Uuid,
+        n: i32,
+        status: BatchEntryStatus,
+        response: Option<&str>,
+        error: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE llm_batch_entries
+            SET status = $1, response = $2, error = $3
+            WHERE batch_id = $4 AND n = $5
+            "#,
+        )
+        .bind(status)
+        .bind(response)
+        .bind(error)
+        .bind(batch_id)
+        .bind(n)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(BatchError::Update)
+        .map_err(Error::from)
+    }
+
+    /// Marks entries left queued or running by a previous run of the server as failed, their
+    /// requests were lost together with the in-memory queues. Returns the number of such entries.
+    pub async fn fail_interrupted(db: &DbPool) -> Result<u64> {
+        sqlx::query(
+            r#"
+            UPDATE llm_batch_entries
+            SET status = $1, error = $2
+            WHERE status = $3 OR status = $4
+            "#,
+        )
+        .bind(BatchEntryStatus::Failed)
+        .bind("interrupted by a server restart")
+        .bind(BatchEntryStatus::Queued)
+        .bind(BatchEntryStatus::Running)
+        .execute(db)
+        .await
+        .map(|r| r.rows_affected())
+        .map_err(BatchError::Update)
+        .map_err(Error::from)
+    }
+}
@@ -3,7 +3,7 @@ use crate::{
     models::{chat_entry::ChatEntry, Error, Result},
     DbPool,
 };
-use airtifex_core::llm::{InferenceSettings, UserChatCounters};
+use airtifex_core::llm::{ChatEntryType, InferenceSettings, UserChatCounters};
 
 use serde::{Deserialize, Serialize};
 use sqlx::Row;
@@ -40,6 +40,39 @@ pub struct Chat {
     pub top_p: Option<f32>,
     pub repeat_penalty: Option<f32>,
     pub temp: Option<f32>,
+    pub mirostat: Option<i32>,
+    pub mirostat_tau: Option<f32>,
+    pub mirostat_eta: Option<f32>,
+    /// Number of previous turns the model is given, all of them when `None`.
+    pub context_turns: Option<i32>,
+}
+
+/// A chat together with its latest entry, as shown in the list of chats.
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct ChatSummary {
+    pub id: Uuid,
+    pub title: String,
+    pub model: String,
+    pub start_date: chrono::DateTime<chrono::Utc>,
+    pub last_read_date: Option<chrono::DateTime<chrono::Utc>>,
+    pub message_count: i64,
+    pub last_entry_type: Option<ChatEntryType>,
+    pub last_entry_content: Option<String>,
+    pub last_entry_date: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl ChatSummary {
+    pub fn last_updated(&self) -> chrono::DateTime<chrono::Utc> {
+        self.last_entry_date.unwrap_or(self.start_date)
+    }
+
+    /// The latest entry is an answer of the bot that came after the user last read the chat.
+    pub fn is_unread(&self) -> bool {
+        match (self.last_entry_type, self.last_entry_date) {
+            (Some(ChatEntryType::Bot), Some(date)) => self.last_read_date.is_none_or(|r| date > r),
+            _ => false,
+        }
+    }
 }
 
 impl Chat {
@@ -62,6 +95,10 @@ impl Chat {
             top_p: settings.top_p,
             repeat_penalty: settings.repeat_penalty,
             temp: settings.temp,
+            mirostat: settings.mirostat.map(|m| m as i32),
+            mirostat_tau: settings.mirostat_tau,
+            mirostat_eta: settings.mirostat_eta,
+            context_turns: None,
         }
     }
 
@@ -78,8 +115,8 @@ impl Chat {
         sqlx::query(
             r#"
             INSERT INTO chats
-                    (id, username, title, start_date, model, num_predict, system_prompt, n_batch, top_k, top_p, repeat_penalty, temp)
-            VALUES  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                    (id, username, title, start_date, model, num_predict, system_prompt, n_batch, top_k, top_p, repeat_penalty, temp, mirostat, mirostat_tau, mirostat_eta, context_turns)
+            VALUES  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
             "#,
         )
         .bind(self.id)
@@ -94,6 +131,10 @@ impl Chat {
         .bind(self.top_p)
         .bind(self.repeat_penalty)
         .bind(self.temp)
+        .bind(self.mirostat)
+        .bind(self.mirostat_tau)
+        .bind(self.mirostat_eta)
+        .bind(self.context_turns)
         .execute(db)
         .await
         .map(|_| ())
@@ -101,8 +142,75 @@ impl Chat {
         .map_err(Error::from)
     }
 
-    pub async fn delete(db: &DbPool, id: &Uuid) -> Result<()> {
-        let tx = db.begin().await.map_err(ChatError::DeleteError)?;
+    /// Copies the chat with its settings into a new chat titled `title` containing copies of the
+    /// entries `entry_ids`. The copies get new ids so that both chats continue independently.
+    pub async fn fork(&self, db: &DbPool, title: &str, entry_ids: &[Uuid]) -> Result<Self> {
+        let fork = Self {
+            id: Uuid::new_v4(),
+            title: title.to_string(),
+            start_date: chrono::Utc::now(),
+            ..self.clone()
+        };
+
+        let mut tx = db.begin().await.map_err(ChatError::CreateError)?;
+        sqlx::query(
+            r#"
+            INSERT INTO chats
+                    (id, username, title, start_date, model, num_predict, system_prompt, n_batch, top_k, top_p, repeat_penalty, temp, mirostat, mirostat_tau, mirostat_eta, context_turns)
+            SELECT  $1, username, $2, $3, model, num_predict, system_prompt, n_batch, top_k, top_p, repeat_penalty, temp, mirostat, mirostat_tau, mirostat_eta, context_turns
+            FROM chats
+            WHERE id = $4
+            "#,
+        )
+        .bind(fork.id)
+        .bind(&fork.title)
+        .bind(fork.start_date)
+        .bind(self.id)
+        .execute(&mut tx)
+        .await
+        .map_err(ChatError::CreateError)?;
+
+        for entry_id in entry_ids {
+            // the original entry dates are kept so that the history keeps its order
+            sqlx::query(
+                r#"
+                INSERT INTO chat_entries
+                        (entry_id, chat_id, entry_type, content, entry_date, seed, params)
+                SELECT  $1, $2, entry_type, content, entry_date, seed, params
+                FROM chat_entries
+                WHERE entry_id = $3
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(fork.id)
+            .bind(entry_id)
+            .execute(&mut tx)
+            .await
+            .map_err(ChatError::CreateError)?;
+        }
+
+        tx.commit()
+            .await
+            .map(|_| fork)
+            .map_err(ChatError::CreateError)
+            .map_err(Error::from)
+    }
+
+    /// Deletes the chat together with its entries, returns the number of deleted entries.
+    pub async fn delete(db: &DbPool, id: &Uuid) -> Result<u64> {
+        let mut tx = db.begin().await.map_err(ChatError::DeleteError)?;
+        let deleted_entries = sqlx::query(
+            r#"
+            DELETE FROM chat_entries
+            WHERE chat_id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await
+        .map_err(ChatError::DeleteError)?
+        .rows_affected();
+
         sqlx::query(
             r#"
             DELETE FROM chats
@@ -110,21 +218,36 @@ impl Chat {
             "#,
         )
         .bind(id)
-        .execute(db)
+        .execute(&mut tx)
         .await
         .map_err(ChatError::DeleteError)?;
 
         tx.commit()
             .await
-            .map(|_| ())
+            .map(|_| deleted_entries)
             .map_err(ChatError::DeleteError)
             .map_err(Error::from)
     }
 
+    pub async fn get(db: &DbPool, chat_id: &Uuid) -> Result<Self> {
+        sqlx::query_as(
+            r#"
+                    SELECT id, username, title, start_date, model, num_predict, system_prompt, n_batch, top_k, top_p, repeat_penalty, temp, mirostat, mirostat_tau, mirostat_eta, context_turns
+                    FROM chats
+                    WHERE id = $1
+                "#,
+        )
+        .bind(chat_id)
+        .fetch_one(db)
+        .await
+        .map_err(ChatError::InspectError)
+        .map_err(Error::from)
+    }
+
     pub async fn get_chat_for_user(db: &DbPool, username: &str, chat_id: &Uuid) -> Result<Self> {
         sqlx::query_as(
             r#"
-                    SELECT id, username, title, start_date, model, num_predict, system_prompt, n_batch, top_k, top_p, repeat_penalty, temp
+                    SELECT id, username, title, start_date, model, num_predict, system_prompt, n_batch, top_k, top_p, repeat_penalty, temp, mirostat, mirostat_tau, mirostat_eta, context_turns
                     FROM chats
                     WHERE id = $1 AND username = $2
                 "#,
@@ -137,22 +260,81 @@ impl Chat {
         .map_err(Error::from)
     }
 
-    pub async fn list_chats_of_user(db: &DbPool, username: &str) -> Result<Vec<Self>> {
+    /// Returns a page of the chats of the user with their latest entries, the chat with the most
+    /// recent entry comes first. Chats without entries count as updated when they were started.
+    pub async fn list_summaries_of_user(
+        db: &DbPool,
+        username: &str,
+        page: Option<u32>,
+        page_size: Option<u32>,
+    ) -> Result<Vec<ChatSummary>> {
+        let page = page.unwrap_or(1).max(1);
+        let page_size = page_size.unwrap_or(25);
+        let offset = (page - 1) * page_size;
         sqlx::query_as(
             r#"
-                    SELECT id, username, title, start_date, model, num_predict, system_prompt, n_batch, top_k, top_p, repeat_penalty, temp
-                    FROM chats
-                    WHERE username = $1
-                    ORDER BY start_date
-                "#,
+            SELECT c.id, c.title, c.model, c.start_date, c.last_read_date,
+                   (SELECT COUNT(*) FROM chat_entries e WHERE e.chat_id = c.id) AS message_count,
+                   l.entry_type AS last_entry_type,
+                   l.content AS last_entry_content,
+                   l.entry_date AS last_entry_date
+            FROM chats c
+            LEFT JOIN chat_entries l ON l.entry_id = (
+                SELECT e.entry_id
+                FROM chat_entries e
+                WHERE e.chat_id = c.id AND e.entry_date IS NOT NULL
+                ORDER BY e.entry_date DESC, e.entry_type DESC
+                LIMIT 1
+            )
+            WHERE c.username = $1
+            ORDER BY COALESCE(l.entry_date, c.start_date) DESC, c.id
+            LIMIT $2
+            OFFSET $3
+            "#,
         )
         .bind(username)
+        .bind(page_size as i32)
+        .bind(offset as i32)
         .fetch_all(db)
         .await
         .map_err(ChatError::ListChatsError)
         .map_err(Error::from)
     }
 
+    pub async fn count_chats_of_user(db: &DbPool, username: &str) -> Result<i64> {
+        sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*)
+            FROM chats
+            WHERE username = $1
+            "#,
+        )
+        .bind(username)
+        .fetch_one(db)
+        .await
+        .map_err(ChatError::ListChatsError)
+        .map_err(Error::from)
+    }
+
+    /// Marks the answers in the chat as read by its owner.
+    pub async fn mark_read(db: &DbPool, id: &Uuid, username: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE chats
+            SET last_read_date = $1
+            WHERE id = $2 AND username = $3
+            "#,
+        )
+        .bind(chrono::Utc::now())
+        .bind(id)
+        .bind(username)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(ChatError::UpdateError)
+        .map_err(Error::from)
+    }
+
     pub async fn list_entries(db: &DbPool, id: &Uuid, username: &str) -> Result<Vec<ChatEntry>> {
         ChatEntry::get_chat_entries(db, id, username).await
     }
@@ -174,6 +356,87 @@ impl Chat {
         .map_err(Error::from)
     }
 
+    pub async fn update_system_prompt(
+        db: &DbPool,
+        id: &Uuid,
+        username: &str,
+        system_prompt: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE chats
+            SET system_prompt = $1
+            WHERE id = $2 AND username = $3
+            "#,
+        )
+        .bind(system_prompt)
+        .bind(id)
+        .bind(username)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(ChatError::UpdateError)
+        .map_err(Error::from)
+    }
+
+    pub async fn update_context_turns(
+        db: &DbPool,
+        id: &Uuid,
+        username: &str,
+        context_turns: Option<i32>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE chats
+            SET context_turns = $1
+            WHERE id = $2 AND username = $3
+            "#,
+        )
+        .bind(context_turns)
+        .bind(id)
+        .bind(username)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(ChatError::UpdateError)
+        .map_err(Error::from)
+    }
+
+    /// Share links are only valid while they are signed with the current generation of the
+    /// chat.
+    pub async fn share_generation(db: &DbPool, id: &Uuid) -> Result<i64> {
+        sqlx::query_scalar(
+            r#"
+            SELECT share_generation
+            FROM chats
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_one(db)
+        .await
+        .map_err(ChatError::InspectError)
+        .map_err(Error::from)
+    }
+
+    /// Invalidates all share links of the chat.
+    pub async fn revoke_shares(db: &DbPool, id: &Uuid, username: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE chats
+            SET share_generation = share_generation + 1
+            WHERE id = $1 AND username = $2
+            "#,
+        )
+        .bind(id)
+        .bind(username)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(ChatError::UpdateError)
+        .map_err(Error::from)
+    }
+
     pub async fn counters(db: &DbPool, username: &str) -> Result<UserChatCounters> {
         sqlx::query(
             r#"
@@ -1,9 +1,9 @@
 use crate::{
     id::Uuid,
-    models::{Error, Result},
+    models::{contains_pattern, Error, Result},
     DbPool,
 };
-use airtifex_core::llm::ChatEntryType;
+use airtifex_core::llm::{ChatEntryParams, ChatEntryType};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error as ErrorType;
@@ -16,10 +16,17 @@ pub enum ChatEntryError {
     InspectError(sqlx::Error),
     #[error("failed to delete a chat entry - {0}")]
     DeleteError(sqlx::Error),
+    #[error("failed to edit a chat entry - {0}")]
+    EditError(sqlx::Error),
     #[error("failed to list chat entries - {0}")]
     ListChatsError(sqlx::Error),
+    #[error("failed to search chat entries - {0}")]
+    SearchError(sqlx::Error),
 }
 
+/// Maximum number of entries returned by a single search.
+const MAX_SEARCH_RESULTS: i64 = 50;
+
 #[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct ChatEntry {
     #[serde(default)]
@@ -28,6 +35,20 @@ pub struct ChatEntry {
     pub entry_type: ChatEntryType,
     pub content: String,
     pub entry_date: chrono::DateTime<chrono::Utc>,
+    /// Seed the answer of the bot was sampled with.
+    pub seed: Option<i64>,
+    /// [`ChatEntryParams`] the answer of the bot was generated with as compact JSON.
+    pub params: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ChatEntryMatch {
+    pub entry_id: Uuid,
+    pub chat_id: Uuid,
+    pub chat_title: String,
+    pub entry_type: ChatEntryType,
+    pub content: String,
+    pub entry_date: chrono::DateTime<chrono::Utc>,
 }
 
 impl ChatEntry {
@@ -38,6 +59,8 @@ impl ChatEntry {
             entry_type: ChatEntryType::User,
             content,
             entry_date: chrono::Utc::now(),
+            seed: None,
+            params: None,
         }
     }
     pub fn new_bot(chat_id: Uuid, content: String) -> Self {
@@ -47,8 +70,28 @@ impl ChatEntry {
             entry_type: ChatEntryType::Bot,
             content,
             entry_date: chrono::Utc::now(),
+            seed: None,
+            params: None,
         }
     }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed as i64);
+        self
+    }
+
+    pub fn with_params(mut self, params: &ChatEntryParams) -> Self {
+        self.params = serde_json::to_string(params).ok();
+        self
+    }
+
+    /// Parameters the answer was generated with, `None` for prompts and older answers.
+    pub fn params(&self) -> Option<ChatEntryParams> {
+        let params = self.params.as_deref()?;
+        serde_json::from_str(params)
+            .map_err(|e| log::warn!("invalid parameters of chat entry {} - {e}", self.entry_id))
+            .ok()
+    }
 }
 
 impl ChatEntry {
@@ -56,8 +99,8 @@ impl ChatEntry {
         sqlx::query(
             r#"
             INSERT INTO chat_entries
-                    (entry_id, chat_id, entry_type, content, entry_date)
-            VALUES  ($1, $2, $3, $4, $5)
+                    (entry_id, chat_id, entry_type, content, entry_date, seed, params)
+            VALUES  ($1, $2, $3, $4, $5, $6, $7)
             "#,
         )
         .bind(self.entry_id)
@@ -65,6 +108,8 @@ impl ChatEntry {
         .bind(self.entry_type)
         .bind(&self.content)
         .bind(self.entry_date)
+        .bind(self.seed)
+        .bind(&self.params)
         .execute(db)
         .await
         .map(|_| ())
@@ -72,23 +117,67 @@ impl ChatEntry {
         .map_err(Error::from)
     }
 
-    pub async fn delete(db: &DbPool, id: &Uuid) -> Result<()> {
+    /// Deletes the entries `ids` of chat `chat_id` in a single transaction, returns the number of
+    /// deleted entries.
+    pub async fn delete(db: &DbPool, chat_id: &Uuid, ids: &[Uuid]) -> Result<u64> {
         let mut tx = db.begin().await.map_err(ChatEntryError::DeleteError)?;
+        let mut deleted = 0;
+        for id in ids {
+            deleted += sqlx::query(
+                r#"
+                DELETE FROM chat_entries
+                WHERE entry_id = $1 AND chat_id = $2
+                "#,
+            )
+            .bind(id)
+            .bind(chat_id)
+            .execute(&mut tx)
+            .await
+            .map_err(ChatEntryError::DeleteError)?
+            .rows_affected();
+        }
+
+        tx.commit()
+            .await
+            .map(|_| deleted)
+            .map_err(ChatEntryError::DeleteError)
+            .map_err(Error::from)
+    }
+
+    /// Replaces the content of `entry` and removes all entries of its chat that came after it in
+    /// a single transaction.
+    pub async fn edit(db: &DbPool, entry: &Self, content: &str) -> Result<()> {
+        let mut tx = db.begin().await.map_err(ChatEntryError::EditError)?;
+        sqlx::query(
+            r#"
+            UPDATE chat_entries
+            SET content = $1
+            WHERE entry_id = $2
+            "#,
+        )
+        .bind(content)
+        .bind(entry.entry_id)
+        .execute(&mut tx)
+        .await
+        .map_err(ChatEntryError::EditError)?;
+
         sqlx::query(
             r#"
             DELETE FROM chat_entries
-            WHERE entry_id = $1
+            WHERE chat_id = $1 AND entry_date >= $2 AND entry_id <> $3
             "#,
         )
-        .bind(id)
+        .bind(entry.chat_id)
+        .bind(entry.entry_date)
+        .bind(entry.entry_id)
         .execute(&mut tx)
         .await
-        .map_err(ChatEntryError::DeleteError)?;
+        .map_err(ChatEntryError::EditError)?;
 
         tx.commit()
             .await
             .map(|_| ())
-            .map_err(ChatEntryError::DeleteError)
+            .map_err(ChatEntryError::EditError)
             .map_err(Error::from)
     }
 
@@ -99,7 +188,7 @@ impl ChatEntry {
     ) -> Result<Vec<Self>> {
         sqlx::query_as(
             r#"
-            SELECT entry_id, chat_id, entry_type, content, entry_date
+            SELECT entry_id, chat_id, entry_type, content, entry_date, seed, params
             FROM chat_entries
             INNER JOIN chats c ON c.id = $1
             WHERE chat_id = $1 AND c.username = $2
@@ -113,4 +202,51 @@ impl ChatEntry {
         .map_err(ChatEntryError::ListChatsError)
         .map_err(Error::from)
     }
+
+    /// Returns the latest answer of the bot in chat `chat_id`.
+    pub async fn get_last_bot_entry(
+        db: &DbPool,
+        chat_id: &Uuid,
+        username: &str,
+    ) -> Result<Option<Self>> {
+        sqlx::query_as(
+            r#"
+            SELECT entry_id, chat_id, entry_type, content, entry_date, seed, params
+            FROM chat_entries
+            INNER JOIN chats c ON c.id = $1
+            WHERE chat_id = $1 AND c.username = $2 AND entry_type = $3
+            ORDER BY entry_date DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(chat_id)
+        .bind(username)
+        .bind(ChatEntryType::Bot)
+        .fetch_optional(db)
+        .await
+        .map_err(ChatEntryError::InspectError)
+        .map_err(Error::from)
+    }
+
+    /// Case insensitive search of `query` in the entries of all chats of `username`, newest
+    /// entries first.
+    pub async fn search(db: &DbPool, username: &str, query: &str) -> Result<Vec<ChatEntryMatch>> {
+        sqlx::query_as(
+            r#"
+            SELECT e.entry_id, e.chat_id, c.title AS chat_title, e.entry_type, e.content, e.entry_date
+            FROM chat_entries e
+            INNER JOIN chats c ON c.id = e.chat_id
+            WHERE c.username = $1 AND LOWER(e.content) LIKE LOWER($2) ESCAPE '\'
+            ORDER BY e.entry_date DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(username)
+        .bind(contains_pattern(query))
+        .bind(MAX_SEARCH_RESULTS)
+        .fetch_all(db)
+        .await
+        .map_err(ChatEntryError::SearchError)
+        .map_err(Error::from)
+    }
 }
@@ -0,0 +1,230 @@
+use crate::{
+    id::Uuid,
+    models::{Error, Result},
+    DbPool,
+};
+
+use chrono::{DateTime, Utc};
+use thiserror::Error as ErrorType;
+
+#[derive(Debug, ErrorType)]
+pub enum IdempotencyKeyError {
+    #[error("failed to claim an idempotency key - {0}")]
+    Claim(sqlx::Error),
+    #[error("failed to inspect an idempotency key - {0}")]
+    Inspect(sqlx::Error),
+    #[error("failed to complete an idempotency key - {0}")]
+    Complete(sqlx::Error),
+    #[error("failed to release an idempotency key - {0}")]
+    Release(sqlx::Error),
+}
+
+/// Key a client sent with a generation request so that retrying the request doesn't generate
+/// again. Keys are scoped to the user, only a digest of the request is kept.
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct IdempotencyKey {
+    pub user_id: Uuid,
+    pub request_key: String,
+    pub request_digest: Vec<u8>,
+    /// Resource the request created, unset while the request is being processed.
+    pub resource_id: Option<Uuid>,
+    pub create_date: DateTime<Utc>,
+    pub expiration_date: DateTime<Utc>,
+}
+
+/// Outcome of claiming a key for a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyClaim {
+    /// The key wasn't used before, the request has to be processed.
+    Claimed,
+    /// The same request was already processed and created the resource.
+    Done(Uuid),
+    /// The same request is still being processed.
+    InProgress,
+    /// The key was used for a different request.
+    Mismatch,
+}
+
+impl IdempotencyKey {
+    pub fn new(
+        user_id: Uuid,
+        request_key: String,
+        request_digest: Vec<u8>,
+        valid_for: chrono::Duration,
+    ) -> Self {
+        let create_date = Utc::now();
+        Self {
+            user_id,
+            request_key,
+            request_digest,
+            resource_id: None,
+            create_date,
+            expiration_date: create_date + valid_for,
+        }
+    }
+
+    /// Claims the key for the request unless the user used it before, keys that expired are
+    /// removed first.
+    pub async fn claim(&self, db: &DbPool) -> Result<KeyClaim> {
+        sqlx::query(
+            r#"
+            DELETE FROM idempotency_keys
+            WHERE expiration_date <= $1
+            "#,
+        )
+        .bind(self.create_date)
+        .execute(db)
+        .await
+        .map_err(IdempotencyKeyError::Claim)?;
+
+        let claimed = sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys
+                    (user_id, request_key, request_digest, resource_id, create_date, expiration_date)
+            VALUES  ($1, $2, $3, NULL, $4, $5)
+            ON CONFLICT (user_id, request_key) DO NOTHING
+            "#,
+        )
+        .bind(self.user_id)
+        .bind(&self.request_key)
+        .bind(&self.request_digest)
+        .bind(self.create_date)
+        .bind(self.expiration_date)
+        .execute(db)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .map_err(IdempotencyKeyError::Claim)?;
+        if claimed {
+            return Ok(KeyClaim::Claimed);
+        }
+
+        let existing: Self = sqlx::query_as(
+            r#"
+            SELECT *
+            FROM idempotency_keys
+            WHERE user_id = $1 AND request_key = $2
+            "#,
+        )
+        .bind(self.user_id)
+        .bind(&self.request_key)
+        .fetch_one(db)
+        .await
+        .map_err(IdempotencyKeyError::Inspect)?;
+        Ok(if existing.request_digest != self.request_digest {
+            KeyClaim::Mismatch
+        } else if let Some(resource_id) = existing.resource_id {
+            KeyClaim::Done(resource_id)
+        } else {
+            KeyClaim::InProgress
+        })
+    }
+
+    /// Records the resource the request created, retries of the request get it from now on.
+    pub async fn complete(&self, db: &DbPool, resource_id: &Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE idempotency_keys
+            SET resource_id = $3
+            WHERE user_id = $1 AND request_key = $2
+            "#,
+        )
+        .bind(self.user_id)
+        .bind(&self.request_key)
+        .bind(resource_id)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(IdempotencyKeyError::Complete)
+        .map_err(Error::from)
+    }
+
+    /// Gives up the key of a request that failed before creating anything so that it can be
+    /// retried.
+    pub async fn release(&self, db: &DbPool) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM idempotency_keys
+            WHERE user_id = $1 AND request_key = $2 AND resource_id IS NULL
+            "#,
+        )
+        .bind(self.user_id)
+        .bind(&self.request_key)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(IdempotencyKeyError::Release)
+        .map_err(Error::from)
+    }
+}
+
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use airtifex_core::user::AccountType;
+
+    fn key(user_id: Uuid, digest: &[u8]) -> IdempotencyKey {
+        IdempotencyKey::new(
+            user_id,
+            "retry-1".into(),
+            digest.to_vec(),
+            chrono::Duration::hours(1),
+        )
+    }
+
+    #[tokio::test]
+    async fn keys_are_claimed_once_per_request() {
+        let db = testing::db().await;
+        let alice = testing::user(&db, "alice", AccountType::User).await;
+        let bob = testing::user(&db, "bob", AccountType::User).await;
+        let resource_id = Uuid::new_v4();
+
+        let first = key(alice.id, b"request");
+        assert_eq!(first.claim(&db).await.unwrap(), KeyClaim::Claimed);
+        assert_eq!(
+            key(alice.id, b"request").claim(&db).await.unwrap(),
+            KeyClaim::InProgress
+        );
+        first.complete(&db, &resource_id).await.unwrap();
+        assert_eq!(
+            key(alice.id, b"request").claim(&db).await.unwrap(),
+            KeyClaim::Done(resource_id)
+        );
+        assert_eq!(
+            key(alice.id, b"other request").claim(&db).await.unwrap(),
+            KeyClaim::Mismatch
+        );
+        // keys are scoped to the user
+        assert_eq!(
+            key(bob.id, b"other request").claim(&db).await.unwrap(),
+            KeyClaim::Claimed
+        );
+    }
+
+    #[tokio::test]
+    async fn released_and_expired_keys_are_claimed_again() {
+        let db = testing::db().await;
+        let alice = testing::user(&db, "alice", AccountType::User).await;
+
+        let failed = key(alice.id, b"request");
+        failed.claim(&db).await.unwrap();
+        failed.release(&db).await.unwrap();
+        let retried = key(alice.id, b"other request");
+        assert_eq!(retried.claim(&db).await.unwrap(), KeyClaim::Claimed);
+        retried.complete(&db, &Uuid::new_v4()).await.unwrap();
+        // completed keys aren't released
+        retried.release(&db).await.unwrap();
+        assert_eq!(
+            key(alice.id, b"request").claim(&db).await.unwrap(),
+            KeyClaim::Mismatch
+        );
+
+        let mut expired = key(alice.id, b"expired");
+        expired.request_key = "retry-2".into();
+        expired.expiration_date = expired.create_date;
+        expired.claim(&db).await.unwrap();
+        let mut reused = key(alice.id, b"request");
+        reused.request_key = "retry-2".into();
+        assert_eq!(reused.claim(&db).await.unwrap(), KeyClaim::Claimed);
+    }
+}
@@ -1,8 +1,9 @@
 use crate::{
     id::Uuid,
     models::{Error, Result},
-    DbPool,
+    Db, DbPool,
 };
+use airtifex_core::image::{ImageDeleteStatus, ImageStatus};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error as ErrorType;
@@ -21,6 +22,127 @@ pub enum ImageError {
     UpdateError(sqlx::Error),
 }
 
+/// Position in the image feed, points at the last image of a page.
+#[derive(Clone, Copy, Debug)]
+pub struct FeedCursor {
+    pub create_date: chrono::DateTime<chrono::Utc>,
+    pub id: Uuid,
+    /// Whether the image is a favorite of the user, only set in feeds that list the favorites
+    /// first.
+    pub is_favorite: Option<bool>,
+}
+
+/// Limits a listing to the images of `owner`, with `favorites_only` to their favorites and to
+/// the images tagged with all of `tags`, the tags must be distinct.
+pub struct ImageFilter<'a> {
+    pub owner: Uuid,
+    pub tags: &'a [String],
+    pub favorites_only: bool,
+}
+
+/// Images of `owner` that are exported, with `model` only the images of that model and with
+/// `from` and `to` only the images created within them.
+#[derive(Clone, Debug)]
+pub struct ImageExportFilter {
+    pub owner: Uuid,
+    pub model: Option<String>,
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+type QueryAs<'q, O> =
+    sqlx::query::QueryAs<'q, Db, O, <Db as sqlx::database::HasArguments<'q>>::Arguments>;
+
+impl<'a> ImageFilter<'a> {
+    /// SQL condition of the filter with its parameters numbered from `first`.
+    fn condition(&self, first: usize) -> String {
+        let mut condition = format!("user_id = ${first}");
+        if self.favorites_only {
+            condition.push_str(" AND is_favorite");
+        }
+        if !self.tags.is_empty() {
+            let tags = (0..self.tags.len())
+                .map(|n| format!("${}", first + 1 + n))
+                .collect::<Vec<_>>()
+                .join(", ");
+            condition.push_str(&format!(
+                " AND id IN (
+                    SELECT image_id
+                    FROM image_tags
+                    WHERE tag IN ({tags})
+                    GROUP BY image_id
+                    HAVING COUNT(*) = ${}
+                )",
+                first + 1 + self.tags.len()
+            ));
+        }
+        condition
+    }
+
+    fn param_count(&self) -> usize {
+        if self.tags.is_empty() {
+            1
+        } else {
+            self.tags.len() + 2
+        }
+    }
+
+    /// Binds the parameters of [`condition`](Self::condition) in order.
+    fn bind<'q, O>(&self, mut query: QueryAs<'q, O>) -> QueryAs<'q, O>
+    where
+        'a: 'q,
+    {
+        query = query.bind(self.owner);
+        if self.tags.is_empty() {
+            return query;
+        }
+        for tag in self.tags {
+            query = query.bind(tag);
+        }
+        query.bind(self.tags.len() as i64)
+    }
+}
+
+/// SQL expression that is true for the favorites of the user bound to parameter `param`, the
+/// favorites of other users don't count.
+fn favorite_of(param: usize) -> String {
+    format!("(is_favorite AND user_id = ${param})")
+}
+
+impl FeedCursor {
+    pub fn encode(&self) -> String {
+        let cursor = format!(
+            "{}_{}",
+            self.create_date
+                .to_rfc3339_opts(chrono::SecondsFormat::Nanos, true),
+            self.id
+        );
+        match self.is_favorite {
+            Some(is_favorite) => format!("{cursor}_{}", is_favorite as u8),
+            None => cursor,
+        }
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let mut parts = cursor.splitn(3, '_');
+        let create_date = parts.next()?;
+        let id = parts.next()?;
+        let is_favorite = match parts.next() {
+            None => None,
+            Some("1") => Some(true),
+            Some("0") => Some(false),
+            Some(_) => return None,
+        };
+        Some(Self {
+            create_date: chrono::DateTime::parse_from_rfc3339(create_date)
+                .ok()?
+                .with_timezone(&chrono::Utc),
+            id: id.parse().ok()?,
+            is_favorite,
+        })
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Image {
     pub id: Uuid,
@@ -37,8 +159,12 @@ pub struct Image {
     pub seed: i64,
     pub num_samples: i64,
     pub guidance_scale: f64,
-    pub processing: bool,
+    pub status: ImageStatus,
+    pub error: Option<String>,
     pub create_date: chrono::DateTime<chrono::Utc>,
+    pub is_favorite: bool,
+    /// Image this one is a variation of.
+    pub source_image_id: Option<Uuid>,
 }
 
 impl Image {
@@ -73,10 +199,19 @@ impl Image {
             seed,
             num_samples,
             guidance_scale,
-            processing: true,
+            status: ImageStatus::Queued,
+            error: None,
             create_date: chrono::Utc::now(),
+            is_favorite: false,
+            source_image_id: None,
         }
     }
+
+    /// Marks the image as a variation of `source`.
+    pub fn with_source_image(mut self, source: Uuid) -> Self {
+        self.source_image_id = Some(source);
+        self
+    }
 }
 
 impl Image {
@@ -84,8 +219,8 @@ impl Image {
         sqlx::query(
             r#"
             INSERT INTO images
-                    (id, user_id, model, width, height, prompt, input_image, mask, thumbnail, strength, n_steps, seed, num_samples, guidance_scale, processing, create_date)
-            VALUES  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+                    (id, user_id, model, width, height, prompt, input_image, mask, thumbnail, strength, n_steps, seed, num_samples, guidance_scale, status, error, create_date, is_favorite, source_image_id)
+            VALUES  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19)
             "#,
         )
         .bind(self.id)
@@ -102,8 +237,11 @@ impl Image {
         .bind(self.seed)
         .bind(self.num_samples)
         .bind(self.guidance_scale)
-        .bind(self.processing)
+        .bind(self.status)
+        .bind(&self.error)
         .bind(self.create_date)
+        .bind(self.is_favorite)
+        .bind(self.source_image_id)
         .execute(db)
         .await
         .map(|_| ())
@@ -111,23 +249,188 @@ impl Image {
         .map_err(Error::from)
     }
 
-    pub async fn list(db: &DbPool) -> Result<Vec<Self>> {
-        sqlx::query_as(
+    /// Lists the images, with `favorites_of` the favorites of that user come first and the
+    /// images are ordered newest first.
+    pub async fn list(
+        db: &DbPool,
+        filter: Option<&ImageFilter<'_>>,
+        favorites_of: Option<&Uuid>,
+    ) -> Result<Vec<Self>> {
+        let mut next_param = 1;
+        let where_clause = filter
+            .map(|filter| {
+                next_param += filter.param_count();
+                format!("WHERE {}", filter.condition(1))
+            })
+            .unwrap_or_default();
+        let order_clause = favorites_of
+            .map(|_| {
+                format!(
+                    "ORDER BY {} DESC, create_date DESC, id DESC",
+                    favorite_of(next_param)
+                )
+            })
+            .unwrap_or_default();
+        let sql = format!(
             r#"
-            SELECT id, user_id, model, width, height, prompt, input_image, mask, thumbnail, strength, n_steps, seed, num_samples, guidance_scale, processing, create_date
+            SELECT id, user_id, model, width, height, prompt, input_image, mask, thumbnail, strength, n_steps, seed, num_samples, guidance_scale, status, error, create_date, is_favorite, source_image_id
             FROM images
+            {where_clause}
+            {order_clause}
+            "#
+        );
+        let mut query = sqlx::query_as(&sql);
+        if let Some(filter) = filter {
+            query = filter.bind(query);
+        }
+        if let Some(user_id) = favorites_of {
+            query = query.bind(user_id);
+        }
+        query
+            .fetch_all(db)
+            .await
+            .map_err(ImageError::ListImagesError)
+            .map_err(Error::from)
+    }
+
+    /// Returns up to `limit` images created before the image at `cursor`, newest first. Images
+    /// created at the same time are ordered by their id so that no image is skipped or repeated.
+    /// With `favorites_of` the favorites of that user come first, the cursor must then say
+    /// whether the image it points at is one of them.
+    pub async fn list_feed(
+        db: &DbPool,
+        cursor: Option<FeedCursor>,
+        filter: Option<&ImageFilter<'_>>,
+        favorites_of: Option<&Uuid>,
+        limit: u32,
+    ) -> Result<Vec<Self>> {
+        let mut conditions = Vec::new();
+        let favorite = favorites_of.map(|_| favorite_of(1));
+        let mut next_param = if favorite.is_some() { 2 } else { 1 };
+        if let Some(cursor) = cursor {
+            let (date, id) = (next_param, next_param + 1);
+            let after_date =
+                format!("(create_date < ${date} OR (create_date = ${date} AND id < ${id}))");
+            next_param += 2;
+            match (&favorite, cursor.is_favorite) {
+                (Some(favorite), Some(_)) => {
+                    let is_favorite = next_param;
+                    next_param += 1;
+                    conditions.push(format!(
+                        "({favorite} < ${is_favorite} OR ({favorite} = ${is_favorite} AND {after_date}))"
+                    ));
+                }
+                _ => conditions.push(after_date),
+            }
+        }
+        if let Some(filter) = filter {
+            conditions.push(filter.condition(next_param));
+            next_param += filter.param_count();
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let favorite_order = favorite
+            .map(|favorite| format!("{favorite} DESC, "))
+            .unwrap_or_default();
+        let sql = format!(
+            r#"
+            SELECT id, user_id, model, width, height, prompt, input_image, mask, thumbnail, strength, n_steps, seed, num_samples, guidance_scale, status, error, create_date, is_favorite, source_image_id
+            FROM images
+            {where_clause}
+            ORDER BY {favorite_order}create_date DESC, id DESC
+            LIMIT ${next_param}
+            "#
+        );
+
+        let mut query = sqlx::query_as(&sql);
+        if let Some(user_id) = favorites_of {
+            query = query.bind(user_id);
+        }
+        if let Some(cursor) = cursor {
+            query = query.bind(cursor.create_date).bind(cursor.id);
+            if let (Some(_), Some(is_favorite)) = (favorites_of, cursor.is_favorite) {
+                query = query.bind(is_favorite);
+            }
+        }
+        if let Some(filter) = filter {
+            query = filter.bind(query);
+        }
+        query
+            .bind(limit as i64)
+            .fetch_all(db)
+            .await
+            .map_err(ImageError::ListImagesError)
+            .map_err(Error::from)
+    }
+
+    /// Returns up to `limit` images of the export created after the image at `after`, oldest
+    /// first. The input images, masks and thumbnails are left out.
+    pub async fn list_export(
+        db: &DbPool,
+        filter: &ImageExportFilter,
+        after: Option<&FeedCursor>,
+        limit: u32,
+    ) -> Result<Vec<Self>> {
+        let mut conditions = vec!["user_id = $1".to_string()];
+        let mut next_param = 2;
+        if filter.model.is_some() {
+            conditions.push(format!("model = ${next_param}"));
+            next_param += 1;
+        }
+        if filter.from.is_some() {
+            conditions.push(format!("create_date >= ${next_param}"));
+            next_param += 1;
+        }
+        if filter.to.is_some() {
+            conditions.push(format!("create_date <= ${next_param}"));
+            next_param += 1;
+        }
+        if after.is_some() {
+            let (date, id) = (next_param, next_param + 1);
+            conditions.push(format!(
+                "(create_date > ${date} OR (create_date = ${date} AND id > ${id}))"
+            ));
+            next_param += 2;
+        }
+        let sql = format!(
+            r#"
+            SELECT id, user_id, model, width, height, prompt, NULL AS input_image, NULL AS mask, NULL AS thumbnail, strength, n_steps, seed, num_samples, guidance_scale, status, error, create_date, is_favorite, source_image_id
+            FROM images
+            WHERE {}
+            ORDER BY create_date, id
+            LIMIT ${next_param}
             "#,
-        )
-        .fetch_all(db)
-        .await
-        .map_err(ImageError::ListImagesError)
-        .map_err(Error::from)
+            conditions.join(" AND ")
+        );
+
+        let mut query = sqlx::query_as(&sql).bind(filter.owner);
+        if let Some(model) = &filter.model {
+            query = query.bind(model);
+        }
+        if let Some(from) = filter.from {
+            query = query.bind(from);
+        }
+        if let Some(to) = filter.to {
+            query = query.bind(to);
+        }
+        if let Some(after) = after {
+            query = query.bind(after.create_date).bind(after.id);
+        }
+        query
+            .bind(limit as i64)
+            .fetch_all(db)
+            .await
+            .map_err(ImageError::ListImagesError)
+            .map_err(Error::from)
     }
 
     pub async fn get_by_id(db: &DbPool, id: &Uuid) -> Result<Self> {
         sqlx::query_as(
             r#"
-            SELECT id, user_id, model, width, height, prompt, input_image, mask, thumbnail, strength, n_steps, seed, num_samples, guidance_scale, processing, create_date
+            SELECT id, user_id, model, width, height, prompt, input_image, mask, thumbnail, strength, n_steps, seed, num_samples, guidance_scale, status, error, create_date, is_favorite, source_image_id
             FROM images
             WHERE id = $1
             "#,
@@ -139,6 +442,29 @@ impl Image {
         .map_err(Error::from)
     }
 
+    /// Stars the image of `user_id` or removes its star, returns whether the image is a favorite
+    /// now or `None` when the user has no such image.
+    pub async fn toggle_favorite_for_user(
+        db: &DbPool,
+        user_id: &Uuid,
+        id: &Uuid,
+    ) -> Result<Option<bool>> {
+        sqlx::query_scalar(
+            r#"
+            UPDATE images
+            SET is_favorite = NOT is_favorite
+            WHERE id = $1 AND user_id = $2
+            RETURNING is_favorite
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(db)
+        .await
+        .map_err(ImageError::UpdateError)
+        .map_err(Error::from)
+    }
+
     pub async fn delete(db: &DbPool, id: &Uuid) -> Result<()> {
         let tx = db.begin().await.map_err(ImageError::DeleteError)?;
         sqlx::query(
@@ -159,15 +485,74 @@ impl Image {
             .map_err(Error::from)
     }
 
-    pub async fn update_is_processing(db: &DbPool, id: &Uuid, processing: bool) -> Result<()> {
+    /// Deletes the images `ids` in a single transaction, when `owner` is set images of other
+    /// users are skipped. Nothing is deleted if any of the deletions fails.
+    pub async fn delete_batch(
+        db: &DbPool,
+        ids: &[Uuid],
+        owner: Option<&Uuid>,
+    ) -> Result<Vec<(Uuid, ImageDeleteStatus)>> {
+        let mut tx = db.begin().await.map_err(ImageError::DeleteError)?;
+        let mut results = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            let user_id: Option<Uuid> = sqlx::query_scalar(
+                r#"
+                SELECT user_id
+                FROM images
+                WHERE id = $1
+                "#,
+            )
+            .bind(id)
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(ImageError::DeleteError)?;
+
+            let status = match user_id {
+                None => ImageDeleteStatus::NotFound,
+                Some(user_id) if owner.map(|owner| *owner != user_id).unwrap_or(false) => {
+                    ImageDeleteStatus::NotOwned
+                }
+                Some(_) => {
+                    sqlx::query(
+                        r#"
+                        DELETE FROM images
+                        WHERE id = $1
+                        "#,
+                    )
+                    .bind(id)
+                    .execute(&mut tx)
+                    .await
+                    .map_err(ImageError::DeleteError)?;
+                    ImageDeleteStatus::Deleted
+                }
+            };
+            results.push((*id, status));
+        }
+
+        tx.commit()
+            .await
+            .map(|_| results)
+            .map_err(ImageError::DeleteError)
+            .map_err(Error::from)
+    }
+
+    /// Moves the image to `status`, the error is cleared unless given.
+    pub async fn update_status(
+        db: &DbPool,
+        id: &Uuid,
+        status: ImageStatus,
+        error: Option<&str>,
+    ) -> Result<()> {
         sqlx::query(
             r#"
             UPDATE images
-            SET processing = $1
-            WHERE id = $2
+            SET status = $1, error = $2
+            WHERE id = $3
             "#,
         )
-        .bind(processing)
+        .bind(status)
+        .bind(error)
         .bind(id)
         .execute(db)
         .await
@@ -176,6 +561,46 @@ impl Image {
         .map_err(Error::from)
     }
 
+    /// Marks a running image as done, images that failed in the meantime stay failed.
+    pub async fn finish(db: &DbPool, id: &Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE images
+            SET status = $1
+            WHERE id = $2 AND status = $3
+            "#,
+        )
+        .bind(ImageStatus::Done)
+        .bind(id)
+        .bind(ImageStatus::Running)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(ImageError::UpdateError)
+        .map_err(Error::from)
+    }
+
+    /// Marks images left queued or running by a previous run of the server as failed, their
+    /// requests were lost together with the in-memory queues. Returns the number of such images.
+    pub async fn fail_interrupted(db: &DbPool) -> Result<u64> {
+        sqlx::query(
+            r#"
+            UPDATE images
+            SET status = $1, error = $2
+            WHERE status = $3 OR status = $4
+            "#,
+        )
+        .bind(ImageStatus::Failed)
+        .bind("interrupted by a server restart")
+        .bind(ImageStatus::Queued)
+        .bind(ImageStatus::Running)
+        .execute(db)
+        .await
+        .map(|r| r.rows_affected())
+        .map_err(ImageError::UpdateError)
+        .map_err(Error::from)
+    }
+
     pub async fn update_thumbnail(db: &DbPool, id: &Uuid, thumbnail: &[u8]) -> Result<()> {
         sqlx::query(
             r#"
@@ -192,4 +617,38 @@ impl Image {
         .map_err(ImageError::UpdateError)
         .map_err(Error::from)
     }
+
+    /// Share links are only valid while they are signed with the current generation of the
+    /// image.
+    pub async fn share_generation(db: &DbPool, id: &Uuid) -> Result<i64> {
+        sqlx::query_scalar(
+            r#"
+            SELECT share_generation
+            FROM images
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_one(db)
+        .await
+        .map_err(ImageError::InspectError)
+        .map_err(Error::from)
+    }
+
+    /// Invalidates all share links of the image.
+    pub async fn revoke_shares(db: &DbPool, id: &Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE images
+            SET share_generation = share_generation + 1
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(ImageError::UpdateError)
+        .map_err(Error::from)
+    }
 }
@@ -4,7 +4,7 @@ use crate::{
     DbPool,
 };
 
-use airtifex_core::image::ImageModelFeatures;
+use airtifex_core::image::{ImageModelCapabilities, ImageModelFeatures};
 use serde::{Deserialize, Serialize};
 use thiserror::Error as ErrorType;
 
@@ -32,10 +32,17 @@ pub struct ImageModel {
     pub feature_inpaint: bool,
     pub feature_text_to_image: bool,
     pub feature_image_to_image: bool,
+    /// [`ImageModelCapabilities`] of the model as compact JSON.
+    pub capabilities: Option<String>,
 }
 
 impl ImageModel {
-    pub fn new(name: String, description: Option<String>, features: ImageModelFeatures) -> Self {
+    pub fn new(
+        name: String,
+        description: Option<String>,
+        features: ImageModelFeatures,
+        capabilities: &ImageModelCapabilities,
+    ) -> Self {
         Self {
             model_id: Uuid::new_v4(),
             name,
@@ -43,12 +50,24 @@ impl ImageModel {
             feature_inpaint: features.inpaint,
             feature_text_to_image: features.text_to_image,
             feature_image_to_image: features.image_to_image,
+            capabilities: serde_json::to_string(capabilities).ok(),
         }
     }
 
     pub fn id(&self) -> Uuid {
         self.model_id
     }
+
+    /// Limits of the requests the model accepts, models created before they were recorded
+    /// accept every request.
+    pub fn capabilities(&self) -> ImageModelCapabilities {
+        let Some(capabilities) = self.capabilities.as_deref() else {
+            return ImageModelCapabilities::default();
+        };
+        serde_json::from_str(capabilities)
+            .map_err(|e| log::warn!("invalid capabilities of image model {} - {e}", self.name))
+            .unwrap_or_default()
+    }
 }
 
 impl ImageModel {
@@ -56,8 +75,8 @@ impl ImageModel {
         sqlx::query(
             r#"
             INSERT INTO image_models
-                    (model_id, name, description, feature_inpaint, feature_text_to_image, feature_image_to_image)
-            VALUES  ($1, $2, $3, $4, $5, $6)
+                    (model_id, name, description, feature_inpaint, feature_text_to_image, feature_image_to_image, capabilities)
+            VALUES  ($1, $2, $3, $4, $5, $6, $7)
             "#,
         )
         .bind(self.model_id)
@@ -66,6 +85,7 @@ impl ImageModel {
         .bind(self.feature_inpaint)
         .bind(self.feature_text_to_image)
         .bind(self.feature_image_to_image)
+        .bind(&self.capabilities)
         .execute(db)
         .await
         .map(|_| ())
@@ -78,7 +98,8 @@ impl ImageModel {
         let deleted = sqlx::query_as(
             r#"
             DELETE FROM image_models
-            WHERE id = $1
+            WHERE model_id = $1
+            RETURNING model_id, name, description, feature_inpaint, feature_text_to_image, feature_image_to_image, capabilities
             "#,
         )
         .bind(id)
@@ -96,7 +117,7 @@ impl ImageModel {
     pub async fn list(db: &DbPool) -> Result<Vec<Self>> {
         sqlx::query_as(
             r#"
-                    SELECT model_id, name, description, feature_inpaint, feature_text_to_image, feature_image_to_image
+                    SELECT model_id, name, description, feature_inpaint, feature_text_to_image, feature_image_to_image, capabilities
                     FROM image_models
                     ORDER BY name
                 "#,
@@ -107,10 +128,32 @@ impl ImageModel {
         .map_err(Error::from)
     }
 
+    /// Replaces the capabilities of the model named `name`.
+    pub async fn update_capabilities(
+        db: &DbPool,
+        name: &str,
+        capabilities: &ImageModelCapabilities,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE image_models
+            SET capabilities = $1
+            WHERE name = $2
+            "#,
+        )
+        .bind(serde_json::to_string(capabilities).ok())
+        .bind(name)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(ImageModelError::UpdateError)
+        .map_err(Error::from)
+    }
+
     pub async fn get_by_name(db: &DbPool, name: &str) -> Result<Self> {
         sqlx::query_as(
             r#"
-                    SELECT model_id, name, description, feature_inpaint, feature_text_to_image, feature_image_to_image
+                    SELECT model_id, name, description, feature_inpaint, feature_text_to_image, feature_image_to_image, capabilities
                     FROM image_models
                     WHERE name = $1
                 "#,
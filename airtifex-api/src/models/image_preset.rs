@@ -0,0 +1,224 @@
+use crate::{
+    id::Uuid,
+    models::{Error, Result},
+    DbPool,
+};
+use airtifex_core::image::{ImagePresetInspect, ImagePresetRequest, ImageSettings};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ErrorType;
+
+#[derive(Debug, ErrorType)]
+pub enum ImagePresetError {
+    #[error("failed to create an image preset - {0}")]
+    Create(sqlx::Error),
+    #[error("failed to inspect an image preset - {0}")]
+    Inspect(sqlx::Error),
+    #[error("failed to update an image preset - {0}")]
+    Update(sqlx::Error),
+    #[error("failed to delete an image preset - {0}")]
+    Delete(sqlx::Error),
+    #[error("failed to list image presets - {0}")]
+    List(sqlx::Error),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ImagePreset {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub prompt: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub n_steps: Option<i64>,
+    pub seed: Option<i64>,
+    pub num_samples: Option<i64>,
+    pub guidance_scale: Option<f64>,
+    pub strength: Option<f64>,
+    pub create_date: chrono::DateTime<chrono::Utc>,
+}
+
+impl ImagePreset {
+    pub fn new(user_id: Uuid, request: ImagePresetRequest) -> Self {
+        let mut preset = Self {
+            id: Uuid::new_v4(),
+            user_id,
+            name: String::new(),
+            prompt: None,
+            width: None,
+            height: None,
+            n_steps: None,
+            seed: None,
+            num_samples: None,
+            guidance_scale: None,
+            strength: None,
+            create_date: chrono::Utc::now(),
+        };
+        preset.set(request);
+        preset
+    }
+
+    /// Replaces the name and the parameters of the preset with the ones of `request`.
+    pub fn set(&mut self, request: ImagePresetRequest) {
+        let ImagePresetRequest {
+            name,
+            prompt,
+            seed,
+            settings,
+        } = request;
+        self.name = name.trim().to_string();
+        self.prompt = prompt;
+        self.width = settings.width;
+        self.height = settings.height;
+        self.n_steps = settings.n_steps.map(|n| n as i64);
+        self.seed = seed;
+        self.num_samples = settings.num_samples;
+        self.guidance_scale = settings.guidance_scale;
+        self.strength = settings.strength;
+    }
+
+    pub fn inspect(self) -> ImagePresetInspect {
+        ImagePresetInspect {
+            id: self.id.to_string(),
+            name: self.name,
+            prompt: self.prompt,
+            seed: self.seed,
+            settings: ImageSettings {
+                width: self.width,
+                height: self.height,
+                n_steps: self.n_steps.map(|n| n as usize),
+                num_samples: self.num_samples,
+                guidance_scale: self.guidance_scale,
+                strength: self.strength,
+            },
+            create_date: self.create_date,
+        }
+    }
+
+    pub async fn create(&self, db: &DbPool) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO image_presets
+                    (id, user_id, name, prompt, width, height, n_steps, seed, num_samples, guidance_scale, strength, create_date)
+            VALUES  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            "#,
+        )
+        .bind(self.id)
+        .bind(self.user_id)
+        .bind(&self.name)
+        .bind(&self.prompt)
+        .bind(self.width)
+        .bind(self.height)
+        .bind(self.n_steps)
+        .bind(self.seed)
+        .bind(self.num_samples)
+        .bind(self.guidance_scale)
+        .bind(self.strength)
+        .bind(self.create_date)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(ImagePresetError::Create)
+        .map_err(Error::from)
+    }
+
+    pub async fn update(&self, db: &DbPool) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE image_presets
+            SET name = $3, prompt = $4, width = $5, height = $6, n_steps = $7, seed = $8,
+                num_samples = $9, guidance_scale = $10, strength = $11
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(self.id)
+        .bind(self.user_id)
+        .bind(&self.name)
+        .bind(&self.prompt)
+        .bind(self.width)
+        .bind(self.height)
+        .bind(self.n_steps)
+        .bind(self.seed)
+        .bind(self.num_samples)
+        .bind(self.guidance_scale)
+        .bind(self.strength)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(ImagePresetError::Update)
+        .map_err(Error::from)
+    }
+
+    pub async fn get_for_user(db: &DbPool, user_id: &Uuid, id: &Uuid) -> Result<Self> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM image_presets
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+        .map_err(ImagePresetError::Inspect)
+        .map_err(Error::from)
+    }
+
+    /// Returns the presets of the user sorted by name.
+    pub async fn list_for_user(db: &DbPool, user_id: &Uuid) -> Result<Vec<Self>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM image_presets
+            WHERE user_id = $1
+            ORDER BY name
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(db)
+        .await
+        .map_err(ImagePresetError::List)
+        .map_err(Error::from)
+    }
+
+    /// Returns whether the user has a preset named `name` other than `except`.
+    pub async fn name_taken(
+        db: &DbPool,
+        user_id: &Uuid,
+        name: &str,
+        except: Option<&Uuid>,
+    ) -> Result<bool> {
+        sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT id
+            FROM image_presets
+            WHERE user_id = $1 AND name = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(name)
+        .fetch_optional(db)
+        .await
+        .map(|id| id.is_some_and(|id| Some(&id) != except))
+        .map_err(ImagePresetError::Inspect)
+        .map_err(Error::from)
+    }
+
+    /// Deletes the preset, returns whether the user had it.
+    pub async fn delete_for_user(db: &DbPool, user_id: &Uuid, id: &Uuid) -> Result<bool> {
+        sqlx::query(
+            r#"
+            DELETE FROM image_presets
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(db)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .map_err(ImagePresetError::Delete)
+        .map_err(Error::from)
+    }
+}
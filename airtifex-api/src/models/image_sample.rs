@@ -13,6 +13,8 @@ pub enum ImageSampleError {
     CreateError(sqlx::Error),
     #[error("failed to inspect a image sample - {0}")]
     InspectError(sqlx::Error),
+    #[error("failed to replace a image sample - {0}")]
+    ReplaceError(sqlx::Error),
     #[error("failed to delete a image sample - {0}")]
     DeleteError(sqlx::Error),
     #[error("failed to get image sample - {0}")]
@@ -27,15 +29,17 @@ pub struct ImageSample {
     pub sample_id: Uuid,
     pub image_id: Uuid,
     pub n: i32,
+    pub actual_seed: i64,
     pub data: Vec<u8>,
 }
 
 impl ImageSample {
-    pub fn new(image_id: Uuid, n: i32, data: Vec<u8>) -> Self {
+    pub fn new(image_id: Uuid, n: i32, actual_seed: i64, data: Vec<u8>) -> Self {
         Self {
             sample_id: Uuid::new_v4(),
             image_id,
             n,
+            actual_seed,
             data,
         }
     }
@@ -46,13 +50,14 @@ impl ImageSample {
         sqlx::query(
             r#"
             INSERT INTO image_samples
-                    (sample_id, image_id, n, data)
-            VALUES  ($1, $2, $3, $4)
+                    (sample_id, image_id, n, actual_seed, data)
+            VALUES  ($1, $2, $3, $4, $5)
             "#,
         )
         .bind(self.sample_id)
         .bind(self.image_id)
         .bind(self.n)
+        .bind(self.actual_seed)
         .bind(&self.data)
         .execute(db)
         .await
@@ -61,6 +66,27 @@ impl ImageSample {
         .map_err(Error::from)
     }
 
+    /// Replaces the seed and data of the sample in the same slot of the image, returns whether
+    /// the image still had the sample.
+    pub async fn replace(&self, db: &DbPool) -> Result<bool> {
+        sqlx::query(
+            r#"
+            UPDATE image_samples
+            SET actual_seed = $3, data = $4
+            WHERE image_id = $1 AND n = $2
+            "#,
+        )
+        .bind(self.image_id)
+        .bind(self.n)
+        .bind(self.actual_seed)
+        .bind(&self.data)
+        .execute(db)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .map_err(ImageSampleError::ReplaceError)
+        .map_err(Error::from)
+    }
+
     pub async fn delete(db: &DbPool, id: &Uuid) -> Result<()> {
         let mut tx = db.begin().await.map_err(ImageSampleError::DeleteError)?;
         sqlx::query(
@@ -81,16 +107,75 @@ impl ImageSample {
             .map_err(Error::from)
     }
 
+    pub async fn delete_image_samples(db: &DbPool, image_id: &Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM image_samples
+            WHERE image_id = $1
+            "#,
+        )
+        .bind(image_id)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(ImageSampleError::DeleteError)
+        .map_err(Error::from)
+    }
+
     pub async fn get_sample(db: &DbPool, image_id: &Uuid, n: i32) -> Result<Self> {
         sqlx::query_as(
             r#"
-            SELECT sample_id, image_id, n, data
+            SELECT sample_id, image_id, n, actual_seed, data
+            FROM image_samples
+            WHERE image_id = $1 AND n = $2
+            "#,
+        )
+        .bind(image_id)
+        .bind(n)
+        .fetch_one(db)
+        .await
+        .map_err(ImageSampleError::GetImageError)
+        .map_err(Error::from)
+    }
+
+    /// Size of the PNG of a sample in bytes.
+    pub async fn data_length(db: &DbPool, image_id: &Uuid, n: i32) -> Result<i64> {
+        sqlx::query_scalar(
+            r#"
+            SELECT CAST(LENGTH(data) AS BIGINT)
+            FROM image_samples
+            WHERE image_id = $1 AND n = $2
+            "#,
+        )
+        .bind(image_id)
+        .bind(n)
+        .fetch_one(db)
+        .await
+        .map_err(ImageSampleError::GetImageError)
+        .map_err(Error::from)
+    }
+
+    /// Reads `len` bytes of the PNG of a sample starting at `offset`, so that large samples
+    /// don't have to be loaded at once.
+    pub async fn data_chunk(
+        db: &DbPool,
+        image_id: &Uuid,
+        n: i32,
+        offset: i64,
+        len: i64,
+    ) -> Result<Vec<u8>> {
+        sqlx::query_scalar(
+            r#"
+            SELECT SUBSTR(data, $3, $4)
             FROM image_samples
             WHERE image_id = $1 AND n = $2
             "#,
         )
         .bind(image_id)
         .bind(n)
+        // positions of SUBSTR start at 1
+        .bind((offset + 1) as i32)
+        .bind(len as i32)
         .fetch_one(db)
         .await
         .map_err(ImageSampleError::GetImageError)
@@ -100,7 +185,7 @@ impl ImageSample {
     pub async fn get_image_samples(db: &DbPool, image_id: &Uuid) -> Result<Vec<Self>> {
         sqlx::query_as(
             r#"
-            SELECT sample_id, image_id, n, data
+            SELECT sample_id, image_id, n, actual_seed, data
             FROM image_samples
             INNER JOIN images i ON i.id = $1
             WHERE image_id = $1
@@ -0,0 +1,140 @@
+use crate::{
+    id::Uuid,
+    models::{Error, Result},
+    DbPool,
+};
+use airtifex_core::image::ImageTagCount;
+
+use sqlx::Row;
+use std::collections::HashMap;
+use thiserror::Error as ErrorType;
+
+/// Number of images whose tags are fetched with a single query.
+const TAGS_QUERY_CHUNK: usize = 500;
+
+#[derive(Debug, ErrorType)]
+pub enum ImageTagError {
+    #[error("failed to tag an image - {0}")]
+    Add(sqlx::Error),
+    #[error("failed to remove a tag of an image - {0}")]
+    Remove(sqlx::Error),
+    #[error("failed to list image tags - {0}")]
+    List(sqlx::Error),
+}
+
+pub struct ImageTag;
+
+impl ImageTag {
+    /// Tags the image, tagging it again with the same tag does nothing.
+    pub async fn add(db: &DbPool, image_id: &Uuid, tag: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO image_tags (image_id, tag)
+            VALUES ($1, $2)
+            ON CONFLICT (image_id, tag) DO NOTHING
+            "#,
+        )
+        .bind(image_id)
+        .bind(tag)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(ImageTagError::Add)
+        .map_err(Error::from)
+    }
+
+    /// Removes the tag from the image, returns whether the image had it.
+    pub async fn remove(db: &DbPool, image_id: &Uuid, tag: &str) -> Result<bool> {
+        sqlx::query(
+            r#"
+            DELETE FROM image_tags
+            WHERE image_id = $1 AND tag = $2
+            "#,
+        )
+        .bind(image_id)
+        .bind(tag)
+        .execute(db)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .map_err(ImageTagError::Remove)
+        .map_err(Error::from)
+    }
+
+    pub async fn list(db: &DbPool, image_id: &Uuid) -> Result<Vec<String>> {
+        sqlx::query_scalar(
+            r#"
+            SELECT tag
+            FROM image_tags
+            WHERE image_id = $1
+            ORDER BY tag
+            "#,
+        )
+        .bind(image_id)
+        .fetch_all(db)
+        .await
+        .map_err(ImageTagError::List)
+        .map_err(Error::from)
+    }
+
+    /// Returns the sorted tags of every image of `image_ids` that has any.
+    pub async fn list_for_images(
+        db: &DbPool,
+        image_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Vec<String>>> {
+        let mut tags: HashMap<Uuid, Vec<String>> = HashMap::new();
+        for ids in image_ids.chunks(TAGS_QUERY_CHUNK) {
+            let params = (1..=ids.len())
+                .map(|n| format!("${n}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let sql = format!(
+                r#"
+                SELECT image_id, tag
+                FROM image_tags
+                WHERE image_id IN ({params})
+                ORDER BY tag
+                "#
+            );
+            let mut query = sqlx::query(&sql);
+            for id in ids {
+                query = query.bind(id);
+            }
+            for row in query.fetch_all(db).await.map_err(ImageTagError::List)? {
+                let image_id: Uuid = row.try_get("image_id").map_err(ImageTagError::List)?;
+                let tag: String = row.try_get("tag").map_err(ImageTagError::List)?;
+                tags.entry(image_id).or_default().push(tag);
+            }
+        }
+        Ok(tags)
+    }
+
+    /// Returns the distinct tags of the images of `user_id` with the number of images tagged with
+    /// each.
+    pub async fn counts(db: &DbPool, user_id: &Uuid) -> Result<Vec<ImageTagCount>> {
+        sqlx::query(
+            r#"
+            SELECT t.tag AS tag, COUNT(*) AS count
+            FROM image_tags t
+            INNER JOIN images i ON i.id = t.image_id
+            WHERE i.user_id = $1
+            GROUP BY t.tag
+            ORDER BY t.tag
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(db)
+        .await
+        .and_then(|rows| {
+            rows.into_iter()
+                .map(|row| {
+                    Ok(ImageTagCount {
+                        tag: row.try_get("tag")?,
+                        count: row.try_get::<i64, _>("count")? as u64,
+                    })
+                })
+                .collect()
+        })
+        .map_err(ImageTagError::List)
+        .map_err(Error::from)
+    }
+}
@@ -1,11 +1,20 @@
+pub mod audit;
+pub mod batch;
 pub mod chat;
 pub mod chat_entry;
+pub mod idempotency_key;
 pub mod image;
 pub mod image_model;
+pub mod image_preset;
 pub mod image_sample;
+pub mod image_tag;
 pub mod llm;
 pub mod prompt;
+pub mod refresh_token;
+pub mod stats;
+pub mod system_prompt;
 pub mod user;
+pub mod webhook;
 
 use thiserror::Error;
 
@@ -13,34 +22,187 @@ pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error(transparent)]
+    AuditError(#[from] audit::AuditError),
     #[error(transparent)]
     AuthenticationError(#[from] user::AuthenticationError),
     #[error(transparent)]
     UserError(#[from] user::UserError),
     #[error(transparent)]
+    BatchError(#[from] batch::BatchError),
+    #[error(transparent)]
     ChatError(#[from] chat::ChatError),
     #[error(transparent)]
     ImageError(#[from] image::ImageError),
     #[error(transparent)]
     ImageModelError(#[from] image_model::ImageModelError),
     #[error(transparent)]
+    ImagePresetError(#[from] image_preset::ImagePresetError),
+    #[error(transparent)]
     LlmError(#[from] llm::LlmError),
     #[error(transparent)]
     PromptError(#[from] prompt::PromptError),
     #[error(transparent)]
+    RefreshTokenError(#[from] refresh_token::RefreshTokenError),
+    #[error(transparent)]
+    StatsError(#[from] stats::StatsError),
+    #[error(transparent)]
+    SystemPromptError(#[from] system_prompt::SystemPromptError),
+    #[error(transparent)]
     ChatEntryError(#[from] chat_entry::ChatEntryError),
     #[error(transparent)]
     ImageSampleError(#[from] image_sample::ImageSampleError),
+    #[error(transparent)]
+    ImageTagError(#[from] image_tag::ImageTagError),
+    #[error(transparent)]
+    IdempotencyKeyError(#[from] idempotency_key::IdempotencyKeyError),
+    #[error(transparent)]
+    WebhookError(#[from] webhook::WebhookError),
+}
+
+/// Opens the database pool. Every sqlite connection is opened with the configured pragmas, a
+/// relative database path is resolved against the data directory.
+#[cfg(all(feature = "sqlite", not(feature = "postgres")))]
+pub async fn connect(
+    db_url: &str,
+    config: &crate::config::DatabaseConfig,
+) -> crate::Result<crate::DbPool> {
+    use crate::config::{JournalMode, Synchronous};
+    use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
+    use std::{str::FromStr, time::Duration};
+
+    let pragmas = &config.pragmas;
+    let journal_mode = match pragmas.journal_mode {
+        JournalMode::Delete => SqliteJournalMode::Delete,
+        JournalMode::Truncate => SqliteJournalMode::Truncate,
+        JournalMode::Persist => SqliteJournalMode::Persist,
+        JournalMode::Memory => SqliteJournalMode::Memory,
+        JournalMode::Wal => SqliteJournalMode::Wal,
+        JournalMode::Off => SqliteJournalMode::Off,
+    };
+    let synchronous = match pragmas.synchronous {
+        Synchronous::Off => SqliteSynchronous::Off,
+        Synchronous::Normal => SqliteSynchronous::Normal,
+        Synchronous::Full => SqliteSynchronous::Full,
+        Synchronous::Extra => SqliteSynchronous::Extra,
+    };
+    let mut options = SqliteConnectOptions::from_str(db_url)?
+        .foreign_keys(true)
+        .journal_mode(journal_mode)
+        .synchronous(synchronous)
+        .busy_timeout(Duration::from_millis(pragmas.busy_timeout_ms));
+
+    if let (Some(data_dir), Some(path)) = (&config.data_dir, sqlite_path(db_url)) {
+        if path.is_relative() {
+            std::fs::create_dir_all(data_dir).map_err(crate::Error::DataDirCreateFailed)?;
+            options = options
+                .filename(data_dir.join(path))
+                .create_if_missing(true);
+        }
+    }
+
+    crate::DbPool::connect_with(options)
+        .await
+        .map_err(crate::Error::from)
+}
+
+#[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+pub async fn connect(
+    db_url: &str,
+    _config: &crate::config::DatabaseConfig,
+) -> crate::Result<crate::DbPool> {
+    crate::DbPool::connect(db_url)
+        .await
+        .map_err(crate::Error::from)
+}
+
+/// Path of the database file of a sqlite url, `None` for in-memory databases.
+#[cfg(all(feature = "sqlite", not(feature = "postgres")))]
+fn sqlite_path(db_url: &str) -> Option<&std::path::Path> {
+    let path = db_url
+        .strip_prefix("sqlite://")
+        .or_else(|| db_url.strip_prefix("sqlite:"))?;
+    let path = path.split('?').next().unwrap_or_default();
+    (!path.is_empty() && path != ":memory:").then(|| std::path::Path::new(path))
 }
 
-pub async fn run_pragma(db: &crate::DbPool) -> crate::Result<()> {
-    sqlx::query(
-        r#"
-            PRAGMA foreign_keys = ON;
-            "#,
+/// `LIKE` pattern of the values containing `text`. The wildcards in the text are escaped with
+/// `\`, queries using the pattern declare it with `ESCAPE '\'`.
+pub fn contains_pattern(text: &str) -> String {
+    format!(
+        "%{}%",
+        text.replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
     )
-    .execute(db)
-    .await
-    .map(|_| ())
-    .map_err(crate::Error::from)
+}
+
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod tests {
+    use super::*;
+    use crate::config::{DatabaseConfig, JournalMode, SqlitePragmas, Synchronous};
+
+    #[test]
+    fn wildcards_are_escaped_in_patterns() {
+        assert_eq!(contains_pattern("50%_off\\"), "%50\\%\\_off\\\\%");
+    }
+
+    #[test]
+    fn sqlite_paths_of_urls() {
+        assert_eq!(
+            sqlite_path("sqlite://data/airtifex.db?mode=rwc"),
+            Some(std::path::Path::new("data/airtifex.db"))
+        );
+        assert_eq!(
+            sqlite_path("sqlite:airtifex.db"),
+            Some(std::path::Path::new("airtifex.db"))
+        );
+        assert_eq!(sqlite_path("sqlite::memory:"), None);
+        assert_eq!(sqlite_path("postgres://localhost/airtifex"), None);
+    }
+
+    #[tokio::test]
+    async fn every_connection_has_the_configured_pragmas() {
+        let data_dir = tempfile::tempdir().expect("temporary directory is created");
+        let config = DatabaseConfig {
+            data_dir: Some(data_dir.path().join("data")),
+            pragmas: SqlitePragmas {
+                journal_mode: JournalMode::Wal,
+                busy_timeout_ms: 1234,
+                synchronous: Synchronous::Full,
+            },
+        };
+        let db = connect("sqlite://airtifex.db", &config)
+            .await
+            .expect("database opens");
+        assert!(data_dir.path().join("data/airtifex.db").exists());
+
+        // hold both connections so that the second one is opened as well
+        let mut first = db.acquire().await.expect("connection opens");
+        let mut second = db.acquire().await.expect("connection opens");
+        for connection in [&mut first, &mut second] {
+            let pragma = |name| format!("PRAGMA {name}");
+            let (journal_mode,): (String,) = sqlx::query_as(&pragma("journal_mode"))
+                .fetch_one(&mut **connection)
+                .await
+                .unwrap();
+            let (busy_timeout,): (i64,) = sqlx::query_as(&pragma("busy_timeout"))
+                .fetch_one(&mut **connection)
+                .await
+                .unwrap();
+            let (synchronous,): (i64,) = sqlx::query_as(&pragma("synchronous"))
+                .fetch_one(&mut **connection)
+                .await
+                .unwrap();
+            let (foreign_keys,): (i64,) = sqlx::query_as(&pragma("foreign_keys"))
+                .fetch_one(&mut **connection)
+                .await
+                .unwrap();
+            assert_eq!(journal_mode, "wal");
+            assert_eq!(busy_timeout, 1234);
+            // FULL
+            assert_eq!(synchronous, 2);
+            assert_eq!(foreign_keys, 1);
+        }
+    }
 }
@@ -1,9 +1,9 @@
 use crate::{
     id::Uuid,
     models::{Error, Result},
-    DbPool,
+    Db, DbPool,
 };
-use airtifex_core::llm::InferenceSettings;
+use airtifex_core::llm::{InferenceSettings, PromptDuplicateAction, PromptImportResponse};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error as ErrorType;
@@ -20,6 +20,10 @@ pub enum PromptError {
     List(sqlx::Error),
     #[error("failed to update a prompt - {0}")]
     Update(sqlx::Error),
+    #[error("failed to reorder prompts - {0}")]
+    Reorder(sqlx::Error),
+    #[error("failed to import prompts - {0}")]
+    Import(sqlx::Error),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -37,6 +41,11 @@ pub struct Prompt {
     pub top_p: Option<f32>,
     pub repeat_penalty: Option<f32>,
     pub temp: Option<f32>,
+    /// Comma separated names of the variables declared by a prompt template.
+    pub variables: Option<String>,
+    pub is_favorite: bool,
+    /// Position of the prompt in the order chosen by its owner.
+    pub sort_order: i32,
 }
 
 impl Prompt {
@@ -46,6 +55,7 @@ impl Prompt {
         prompt: String,
         response: String,
         settings: InferenceSettings,
+        variables: Vec<String>,
     ) -> Self {
         Self {
             id: Uuid::new_v4(),
@@ -60,21 +70,48 @@ impl Prompt {
             top_p: settings.top_p,
             repeat_penalty: settings.repeat_penalty,
             temp: settings.temp,
+            variables: if variables.is_empty() {
+                None
+            } else {
+                Some(variables.join(","))
+            },
+            is_favorite: false,
+            sort_order: 0,
         }
     }
 
     pub fn id(&self) -> Uuid {
         self.id
     }
+
+    pub fn variables(&self) -> Vec<String> {
+        self.variables
+            .as_deref()
+            .map(|v| v.split(',').map(String::from).collect())
+            .unwrap_or_default()
+    }
 }
 
 impl Prompt {
     pub async fn create(&self, db: &DbPool) -> Result<()> {
+        self.insert(db)
+            .await
+            .map_err(PromptError::Create)
+            .map_err(Error::from)
+    }
+
+    /// Adds the prompt after the other prompts of its owner.
+    async fn insert<'c, E>(&self, executor: E) -> std::result::Result<(), sqlx::Error>
+    where
+        E: sqlx::Executor<'c, Database = Db>,
+    {
         sqlx::query(
             r#"
             INSERT INTO prompts
-                    (id, username, prompt, response, date, model, num_predict, n_batch, top_k, top_p, repeat_penalty, temp)
-            VALUES  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                    (id, username, prompt, response, date, model, num_predict, n_batch, top_k, top_p, repeat_penalty, temp, variables, is_favorite, sort_order)
+            SELECT  $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, COALESCE(MAX(sort_order) + 1, 0)
+            FROM prompts
+            WHERE username = $2
             "#,
         )
         .bind(self.id)
@@ -89,11 +126,11 @@ impl Prompt {
         .bind(self.top_p)
         .bind(self.repeat_penalty)
         .bind(self.temp)
-        .execute(db)
+        .bind(&self.variables)
+        .bind(self.is_favorite)
+        .execute(executor)
         .await
         .map(|_| ())
-        .map_err(PromptError::Create)
-        .map_err(Error::from)
     }
 
     pub async fn delete_prompt_for_user(db: &DbPool, username: &str, id: &Uuid) -> Result<()> {
@@ -120,7 +157,7 @@ impl Prompt {
     pub async fn get_prompt_for_user(db: &DbPool, username: &str, chat_id: &Uuid) -> Result<Self> {
         sqlx::query_as(
             r#"
-                    SELECT id, username, prompt, response, date, model, num_predict, n_batch, top_k, top_p, repeat_penalty, temp
+                    SELECT id, username, prompt, response, date, model, num_predict, n_batch, top_k, top_p, repeat_penalty, temp, variables, is_favorite, sort_order
                     FROM prompts
                     WHERE id = $1 AND username = $2
                 "#,
@@ -133,19 +170,154 @@ impl Prompt {
         .map_err(Error::from)
     }
 
-    pub async fn list_prompts_of_user(db: &DbPool, username: &str) -> Result<Vec<Self>> {
+    /// Lists the prompts of a user, favorites first and then in the order chosen by the user.
+    pub async fn list_prompts_of_user(
+        db: &DbPool,
+        username: &str,
+        favorites_only: bool,
+    ) -> Result<Vec<Self>> {
         sqlx::query_as(
             r#"
-                    SELECT id, username, prompt, response, date, model, num_predict, n_batch, top_k, top_p, repeat_penalty, temp
+                    SELECT id, username, prompt, response, date, model, num_predict, n_batch, top_k, top_p, repeat_penalty, temp, variables, is_favorite, sort_order
                     FROM prompts
-                    WHERE username = $1
-                    ORDER BY date
+                    WHERE username = $1 AND (is_favorite OR NOT $2)
+                    ORDER BY is_favorite DESC, sort_order, date
                 "#,
         )
         .bind(username)
+        .bind(favorites_only)
         .fetch_all(db)
         .await
         .map_err(PromptError::List)
         .map_err(Error::from)
     }
+
+    pub async fn set_favorite_for_user(
+        db: &DbPool,
+        username: &str,
+        id: &Uuid,
+        is_favorite: bool,
+    ) -> Result<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE prompts
+            SET is_favorite = $1
+            WHERE id = $2 AND username = $3
+            "#,
+        )
+        .bind(is_favorite)
+        .bind(id)
+        .bind(username)
+        .execute(db)
+        .await
+        .map_err(PromptError::Update)?;
+
+        if result.rows_affected() == 0 {
+            return Err(PromptError::Update(sqlx::Error::RowNotFound).into());
+        }
+        Ok(())
+    }
+
+    /// Stores `ids` as the new order of the prompts of a user. Nothing is updated when one of the
+    /// ids isn't a prompt of `username`.
+    pub async fn reorder_prompts_of_user(db: &DbPool, username: &str, ids: &[Uuid]) -> Result<()> {
+        let mut tx = db.begin().await.map_err(PromptError::Reorder)?;
+        for (position, id) in ids.iter().enumerate() {
+            let result = sqlx::query(
+                r#"
+                UPDATE prompts
+                SET sort_order = $1
+                WHERE id = $2 AND username = $3
+                "#,
+            )
+            .bind(position as i32)
+            .bind(id)
+            .bind(username)
+            .execute(&mut tx)
+            .await
+            .map_err(PromptError::Reorder)?;
+
+            if result.rows_affected() == 0 {
+                return Err(PromptError::Reorder(sqlx::Error::RowNotFound).into());
+            }
+        }
+
+        tx.commit()
+            .await
+            .map(|_| ())
+            .map_err(PromptError::Reorder)
+            .map_err(Error::from)
+    }
+
+    /// Adds `prompts` to the prompts of `username` in a single transaction. A prompt with the
+    /// same text as a saved prompt of the user is skipped or merged into it, nothing is imported
+    /// if any of the prompts fails.
+    pub async fn import_for_user(
+        db: &DbPool,
+        username: &str,
+        prompts: &[Prompt],
+        on_duplicate: PromptDuplicateAction,
+    ) -> Result<PromptImportResponse> {
+        let mut tx = db.begin().await.map_err(PromptError::Import)?;
+        let mut response = PromptImportResponse::default();
+
+        for prompt in prompts {
+            let existing: Option<Uuid> = sqlx::query_scalar(
+                r#"
+                SELECT id
+                FROM prompts
+                WHERE username = $1 AND prompt = $2
+                ORDER BY sort_order, date
+                LIMIT 1
+                "#,
+            )
+            .bind(username)
+            .bind(&prompt.prompt)
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(PromptError::Import)?;
+
+            match (existing, on_duplicate) {
+                (None, _) => {
+                    let prompt = Prompt {
+                        username: username.to_string(),
+                        ..prompt.clone()
+                    };
+                    prompt.insert(&mut tx).await.map_err(PromptError::Import)?;
+                    response.created += 1;
+                }
+                (Some(_), PromptDuplicateAction::Skip) => response.skipped += 1,
+                (Some(id), PromptDuplicateAction::Merge) => {
+                    sqlx::query(
+                        r#"
+                        UPDATE prompts
+                        SET response = $1, model = $2, num_predict = $3, n_batch = $4, top_k = $5,
+                            top_p = $6, repeat_penalty = $7, temp = $8, variables = $9,
+                            is_favorite = is_favorite OR $10
+                        WHERE id = $11 AND username = $12
+                        "#,
+                    )
+                    .bind(&prompt.response)
+                    .bind(&prompt.model)
+                    .bind(prompt.num_predict)
+                    .bind(prompt.n_batch)
+                    .bind(prompt.top_k)
+                    .bind(prompt.top_p)
+                    .bind(prompt.repeat_penalty)
+                    .bind(prompt.temp)
+                    .bind(&prompt.variables)
+                    .bind(prompt.is_favorite)
+                    .bind(id)
+                    .bind(username)
+                    .execute(&mut tx)
+                    .await
+                    .map_err(PromptError::Import)?;
+                    response.merged += 1;
+                }
+            }
+        }
+
+        tx.commit().await.map_err(PromptError::Import)?;
+        Ok(response)
+    }
 }
@@ -0,0 +1,164 @@
+use crate::{
+    id::Uuid,
+    models::{Error, Result},
+    DbPool,
+};
+use airtifex_core::{auth::hash_pass, user::AccountType};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use thiserror::Error as ErrorType;
+
+#[derive(Debug, ErrorType)]
+pub enum RefreshTokenError {
+    #[error("Failed to create a refresh token - {0}")]
+    CreateError(sqlx::Error),
+    #[error("Failed to get a refresh token - {0}")]
+    GetError(sqlx::Error),
+    #[error("Failed to revoke refresh tokens - {0}")]
+    DeleteError(sqlx::Error),
+}
+
+/// Refresh token as stored in the database, the token itself is only known to the client.
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct RefreshToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub token_digest: Vec<u8>,
+    pub create_date: DateTime<Utc>,
+    pub expiration_date: DateTime<Utc>,
+}
+
+/// Refresh token together with the user it was issued to.
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct RefreshTokenOwner {
+    pub id: Uuid,
+    pub expiration_date: DateTime<Utc>,
+    pub username: String,
+    pub account_type: AccountType,
+}
+
+impl RefreshToken {
+    /// Generates a new random token for `user_id` valid for `valid_for`. Returns the model
+    /// together with the token that has to be handed out to the client.
+    pub fn generate(user_id: Uuid, valid_for: chrono::Duration) -> (Self, String) {
+        let mut rng = rand::thread_rng();
+        let token = format!("{:032x}{:032x}", rng.gen::<u128>(), rng.gen::<u128>());
+        let create_date = Utc::now();
+        let refresh_token = Self {
+            id: Uuid::new_v4(),
+            user_id,
+            token_digest: hash_pass(token.clone()),
+            create_date,
+            expiration_date: create_date + valid_for,
+        };
+        (refresh_token, token)
+    }
+}
+
+impl RefreshTokenOwner {
+    pub fn is_expired(&self) -> bool {
+        self.expiration_date <= Utc::now()
+    }
+}
+
+impl RefreshToken {
+    pub async fn create(&self, db: &DbPool) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO refresh_tokens
+                    (id, user_id, token_digest, create_date, expiration_date)
+            VALUES  ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(self.id)
+        .bind(self.user_id)
+        .bind(&self.token_digest)
+        .bind(self.create_date)
+        .bind(self.expiration_date)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(RefreshTokenError::CreateError)
+        .map_err(Error::from)
+    }
+
+    /// Looks up the stored refresh token matching `token` and the user it belongs to.
+    pub async fn get_owner(db: &DbPool, token: &str) -> Result<RefreshTokenOwner> {
+        sqlx::query_as(
+            r#"
+            SELECT t.id, t.expiration_date, u.username, u.account_type
+            FROM refresh_tokens t
+            INNER JOIN users u ON u.id = t.user_id
+            WHERE t.token_digest = $1
+            "#,
+        )
+        .bind(hash_pass(token.to_string()))
+        .fetch_one(db)
+        .await
+        .map_err(RefreshTokenError::GetError)
+        .map_err(Error::from)
+    }
+
+    pub async fn delete(db: &DbPool, id: &Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM refresh_tokens
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(RefreshTokenError::DeleteError)
+        .map_err(Error::from)
+    }
+
+    /// Revokes the refresh token matching `token` if it exists.
+    pub async fn delete_by_token(db: &DbPool, token: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM refresh_tokens
+            WHERE token_digest = $1
+            "#,
+        )
+        .bind(hash_pass(token.to_string()))
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(RefreshTokenError::DeleteError)
+        .map_err(Error::from)
+    }
+
+    /// Revokes all refresh tokens of `username`.
+    pub async fn delete_by_username(db: &DbPool, username: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM refresh_tokens
+            WHERE user_id IN (SELECT id FROM users WHERE username = $1)
+            "#,
+        )
+        .bind(username)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(RefreshTokenError::DeleteError)
+        .map_err(Error::from)
+    }
+
+    pub async fn delete_expired(db: &DbPool) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM refresh_tokens
+            WHERE expiration_date <= $1
+            "#,
+        )
+        .bind(Utc::now())
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(RefreshTokenError::DeleteError)
+        .map_err(Error::from)
+    }
+}
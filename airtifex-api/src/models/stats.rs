@@ -0,0 +1,246 @@
+use crate::{
+    id::Uuid,
+    models::{Error, Result},
+    DbPool,
+};
+use airtifex_core::admin::DurationPercentiles;
+
+use sqlx::Row;
+use std::time::Duration;
+use thiserror::Error as ErrorType;
+
+#[derive(Debug, ErrorType)]
+pub enum StatsError {
+    #[error("failed to count records - {0}")]
+    Count(sqlx::Error),
+    #[error("failed to save inference stats - {0}")]
+    CreateInferenceStats(sqlx::Error),
+    #[error("failed to list inference stats - {0}")]
+    ListInferenceStats(sqlx::Error),
+}
+
+/// Record counts of the whole database.
+#[derive(Clone, Debug, Default)]
+pub struct RecordCounts {
+    pub users: u64,
+    pub images: u64,
+    pub chat_messages: u64,
+    pub images_since: u64,
+}
+
+impl RecordCounts {
+    /// Counts all records, `since` limits `images_since` to images created after it.
+    pub async fn get(db: &DbPool, since: chrono::DateTime<chrono::Utc>) -> Result<Self> {
+        sqlx::query(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM users) AS users,
+                (SELECT COUNT(*) FROM images) AS images,
+                (SELECT COUNT(*) FROM chat_entries) AS chat_messages,
+                (SELECT COUNT(*) FROM images WHERE create_date >= $1) AS images_since
+            "#,
+        )
+        .bind(since)
+        .fetch_one(db)
+        .await
+        .and_then(|row| {
+            let count = |column| row.try_get::<i64, _>(column).map(|c| c as u64);
+            Ok(Self {
+                users: count("users")?,
+                images: count("images")?,
+                chat_messages: count("chat_messages")?,
+                images_since: count("images_since")?,
+            })
+        })
+        .map_err(StatsError::Count)
+        .map_err(Error::from)
+    }
+}
+
+/// Timing of a finished inference session.
+#[derive(Clone, Debug)]
+pub struct InferenceStat {
+    pub id: Uuid,
+    pub model: String,
+    /// Time the request waited in the queue before its session was started.
+    pub queue_wait_ms: i64,
+    /// Time from the start of the session to the end of the answer, feeding the prompt included.
+    pub generation_ms: i64,
+    pub generated_tokens: i64,
+    pub finish_date: chrono::DateTime<chrono::Utc>,
+}
+
+impl InferenceStat {
+    pub fn new(
+        model: String,
+        queue_wait: Duration,
+        generation_time: Duration,
+        generated_tokens: usize,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            model,
+            queue_wait_ms: queue_wait.as_millis() as i64,
+            generation_ms: generation_time.as_millis() as i64,
+            generated_tokens: generated_tokens as i64,
+            finish_date: chrono::Utc::now(),
+        }
+    }
+
+    /// Stores all of `stats` in one transaction.
+    pub async fn create_batch(db: &DbPool, stats: &[InferenceStat]) -> Result<()> {
+        let mut tx = db.begin().await.map_err(StatsError::CreateInferenceStats)?;
+        for stat in stats {
+            sqlx::query(
+                r#"
+                INSERT INTO inference_stats
+                        (id, model, queue_wait_ms, generation_ms, generated_tokens, finish_date)
+                VALUES  ($1, $2, $3, $4, $5, $6)
+                "#,
+            )
+            .bind(stat.id)
+            .bind(&stat.model)
+            .bind(stat.queue_wait_ms)
+            .bind(stat.generation_ms)
+            .bind(stat.generated_tokens)
+            .bind(stat.finish_date)
+            .execute(&mut tx)
+            .await
+            .map_err(StatsError::CreateInferenceStats)?;
+        }
+        tx.commit()
+            .await
+            .map_err(StatsError::CreateInferenceStats)
+            .map_err(Error::from)
+    }
+
+    /// Returns the queue waits and generation times in milliseconds of the sessions that
+    /// finished after `since`, optionally only those of `model`.
+    pub async fn list_durations(
+        db: &DbPool,
+        model: Option<&str>,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<(i64, i64)>> {
+        sqlx::query_as(
+            r#"
+            SELECT queue_wait_ms, generation_ms
+            FROM inference_stats
+            WHERE finish_date >= $1
+            AND ($2 IS NULL OR model = $2)
+            "#,
+        )
+        .bind(since)
+        .bind(model)
+        .fetch_all(db)
+        .await
+        .map_err(StatsError::ListInferenceStats)
+        .map_err(Error::from)
+    }
+}
+
+/// Nearest-rank percentiles of `durations` in milliseconds, all zero without durations.
+pub fn duration_percentiles(durations: &mut [i64]) -> DurationPercentiles {
+    durations.sort_unstable();
+    let percentile = |p: usize| {
+        if durations.is_empty() {
+            return 0;
+        }
+        let rank = (p * durations.len()).div_ceil(100);
+        durations[rank.max(1) - 1].max(0) as u64
+    };
+    DurationPercentiles {
+        p50_ms: percentile(50),
+        p95_ms: percentile(95),
+        p99_ms: percentile(99),
+    }
+}
+
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn stat(model: &str, queue_wait_ms: u64, generation_ms: u64) -> InferenceStat {
+        InferenceStat::new(
+            model.to_string(),
+            Duration::from_millis(queue_wait_ms),
+            Duration::from_millis(generation_ms),
+            16,
+        )
+    }
+
+    #[test]
+    fn percentiles_are_nearest_ranks() {
+        let mut durations: Vec<i64> = (1..=100).rev().collect();
+        assert_eq!(
+            duration_percentiles(&mut durations),
+            DurationPercentiles {
+                p50_ms: 50,
+                p95_ms: 95,
+                p99_ms: 99,
+            }
+        );
+        let mut durations = [30, 10, 20];
+        assert_eq!(
+            duration_percentiles(&mut durations),
+            DurationPercentiles {
+                p50_ms: 20,
+                p95_ms: 30,
+                p99_ms: 30,
+            }
+        );
+        assert_eq!(
+            duration_percentiles(&mut []),
+            DurationPercentiles::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn durations_of_the_window_are_aggregated() {
+        let db = testing::db().await;
+        let mut old = stat("llama", 5000, 5000);
+        old.finish_date = chrono::Utc::now() - chrono::Duration::hours(2);
+        let stats = [
+            stat("llama", 10, 100),
+            stat("llama", 30, 300),
+            stat("llama", 20, 200),
+            stat("alpaca", 1000, 2000),
+            old,
+        ];
+        InferenceStat::create_batch(&db, &stats)
+            .await
+            .expect("stats are saved");
+
+        let since = chrono::Utc::now() - chrono::Duration::hours(1);
+        let durations = InferenceStat::list_durations(&db, Some("llama"), since)
+            .await
+            .expect("stats are listed");
+        let (mut queue_waits, mut generation_times): (Vec<_>, Vec<_>) =
+            durations.into_iter().unzip();
+        assert_eq!(queue_waits.len(), 3);
+        assert_eq!(
+            duration_percentiles(&mut queue_waits),
+            DurationPercentiles {
+                p50_ms: 20,
+                p95_ms: 30,
+                p99_ms: 30,
+            }
+        );
+        assert_eq!(duration_percentiles(&mut generation_times).p50_ms, 200);
+
+        // all models, the older window includes the old session
+        let all = InferenceStat::list_durations(&db, None, since)
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 4);
+        let since = chrono::Utc::now() - chrono::Duration::hours(3);
+        let (mut queue_waits, _): (Vec<_>, Vec<_>) =
+            InferenceStat::list_durations(&db, None, since)
+                .await
+                .unwrap()
+                .into_iter()
+                .unzip();
+        assert_eq!(queue_waits.len(), 5);
+        assert_eq!(duration_percentiles(&mut queue_waits).p99_ms, 5000);
+    }
+}
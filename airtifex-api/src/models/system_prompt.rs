@@ -0,0 +1,198 @@
+use crate::{
+    id::Uuid,
+    models::{Error, Result},
+    DbPool,
+};
+use airtifex_core::llm::{SystemPromptInspect, SystemPromptRequest};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ErrorType;
+
+#[derive(Debug, ErrorType)]
+pub enum SystemPromptError {
+    #[error("failed to create a system prompt - {0}")]
+    Create(sqlx::Error),
+    #[error("failed to inspect a system prompt - {0}")]
+    Inspect(sqlx::Error),
+    #[error("failed to update a system prompt - {0}")]
+    Update(sqlx::Error),
+    #[error("failed to delete a system prompt - {0}")]
+    Delete(sqlx::Error),
+    #[error("failed to list system prompts - {0}")]
+    List(sqlx::Error),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SystemPrompt {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub content: String,
+    pub shared: bool,
+    pub create_date: chrono::DateTime<chrono::Utc>,
+}
+
+impl SystemPrompt {
+    pub fn new(user_id: Uuid, request: SystemPromptRequest) -> Self {
+        let mut prompt = Self {
+            id: Uuid::new_v4(),
+            user_id,
+            name: String::new(),
+            content: String::new(),
+            shared: false,
+            create_date: chrono::Utc::now(),
+        };
+        prompt.set(request);
+        prompt
+    }
+
+    pub fn set(&mut self, request: SystemPromptRequest) {
+        self.name = request.name.trim().to_string();
+        self.content = request.content;
+        self.shared = request.shared;
+    }
+
+    /// Returns the system prompt as seen by the user `user_id`.
+    pub fn inspect(self, user_id: &Uuid) -> SystemPromptInspect {
+        SystemPromptInspect {
+            id: self.id.to_string(),
+            owned: &self.user_id == user_id,
+            name: self.name,
+            content: self.content,
+            shared: self.shared,
+            create_date: self.create_date,
+        }
+    }
+
+    pub async fn create(&self, db: &DbPool) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO system_prompts
+                    (id, user_id, name, content, shared, create_date)
+            VALUES  ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(self.id)
+        .bind(self.user_id)
+        .bind(&self.name)
+        .bind(&self.content)
+        .bind(self.shared)
+        .bind(self.create_date)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(SystemPromptError::Create)
+        .map_err(Error::from)
+    }
+
+    pub async fn update(&self, db: &DbPool) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE system_prompts
+            SET name = $3, content = $4, shared = $5
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(self.id)
+        .bind(self.user_id)
+        .bind(&self.name)
+        .bind(&self.content)
+        .bind(self.shared)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(SystemPromptError::Update)
+        .map_err(Error::from)
+    }
+
+    /// Returns the system prompt if it belongs to the user.
+    pub async fn get_for_user(db: &DbPool, user_id: &Uuid, id: &Uuid) -> Result<Self> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM system_prompts
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+        .map_err(SystemPromptError::Inspect)
+        .map_err(Error::from)
+    }
+
+    /// Returns the system prompt if it belongs to the user or is shared.
+    pub async fn get_visible(db: &DbPool, user_id: &Uuid, id: &Uuid) -> Result<Self> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM system_prompts
+            WHERE id = $1 AND (user_id = $2 OR shared)
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+        .map_err(SystemPromptError::Inspect)
+        .map_err(Error::from)
+    }
+
+    /// Returns the system prompts of the user and the shared ones sorted by name.
+    pub async fn list_visible(db: &DbPool, user_id: &Uuid) -> Result<Vec<Self>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM system_prompts
+            WHERE user_id = $1 OR shared
+            ORDER BY name
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(db)
+        .await
+        .map_err(SystemPromptError::List)
+        .map_err(Error::from)
+    }
+
+    /// Returns whether the user has a system prompt named `name` other than `except`.
+    pub async fn name_taken(
+        db: &DbPool,
+        user_id: &Uuid,
+        name: &str,
+        except: Option<&Uuid>,
+    ) -> Result<bool> {
+        sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT id
+            FROM system_prompts
+            WHERE user_id = $1 AND name = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(name)
+        .fetch_optional(db)
+        .await
+        .map(|id| id.is_some_and(|id| Some(&id) != except))
+        .map_err(SystemPromptError::Inspect)
+        .map_err(Error::from)
+    }
+
+    /// Deletes the system prompt, returns whether the user had it.
+    pub async fn delete_for_user(db: &DbPool, user_id: &Uuid, id: &Uuid) -> Result<bool> {
+        sqlx::query(
+            r#"
+            DELETE FROM system_prompts
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(db)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .map_err(SystemPromptError::Delete)
+        .map_err(Error::from)
+    }
+}
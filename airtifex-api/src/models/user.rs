@@ -1,10 +1,11 @@
 use crate::{
     id::Uuid,
-    models::{Error, Result},
+    models::{contains_pattern, Error, Result},
+    password::{hash_password_blocking, needs_rehash, verify_password_blocking},
 };
 use airtifex_core::{
-    auth::{hash_pass, Credentials},
-    user::{AccountType, ListOrder, UserRegisterRequest},
+    auth::Credentials,
+    user::{AccountType, ListOrder, ListQuery, UiPreferences, UserSettings},
 };
 
 use chrono::{DateTime, Utc};
@@ -26,6 +27,16 @@ pub enum UserError {
     CreateError(sqlx::Error),
     #[error("Failed to list users - {0}")]
     ListError(sqlx::Error),
+    #[error("Failed to update avatar - {0}")]
+    AvatarUpdateError(sqlx::Error),
+    #[error("Failed to get avatar - {0}")]
+    GetAvatarError(sqlx::Error),
+    #[error("Failed to update settings - {0}")]
+    SettingsUpdateError(sqlx::Error),
+    #[error("Failed to get settings - {0}")]
+    GetSettingsError(sqlx::Error),
+    #[error("Invalid stored settings - {0}")]
+    InvalidSettings(serde_json::Error),
     #[error("Invalid account type `{0}`")]
     InvalidAccountType(String),
 }
@@ -40,13 +51,6 @@ pub enum AuthenticationError {
     UserDoesntExist,
 }
 
-impl From<Credentials> for User {
-    fn from(credentials: Credentials) -> Self {
-        let (user, pass) = credentials.consume();
-        User::new(user, pass, "", AccountType::default())
-    }
-}
-
 pub fn account_type_from_str(s: &str) -> core::result::Result<AccountType, UserError> {
     match &s.to_lowercase()[..] {
         "admin" => Ok(AccountType::Admin),
@@ -67,22 +71,25 @@ pub struct User {
     pub email: String,
     pub account_type: AccountType,
     pub registration_date: DateTime<Utc>,
+    pub last_login: Option<DateTime<Utc>>,
 }
 
 impl User {
+    /// Creates a user with a password hash from [`crate::password::hash_password`].
     pub fn new(
         username: impl Into<String>,
-        password: impl Into<String>,
+        password_hash: Vec<u8>,
         email: impl Into<String>,
         account_type: AccountType,
     ) -> Self {
         Self {
             id: Uuid::new_v4(),
             username: username.into(),
-            password: hash_pass(password.into()),
+            password: password_hash,
             email: email.into(),
             account_type,
             registration_date: Utc::now(),
+            last_login: None,
         }
     }
 
@@ -107,6 +114,22 @@ impl User {
     }
 }
 
+/// Filter of [`User::list`] and [`User::count`], `$1` is the search pattern and `$2` the account
+/// type.
+const LIST_CONDITION: &str = r#"
+                ($1 IS NULL OR LOWER(username) LIKE $1 ESCAPE '\' OR LOWER(email) LIKE $1 ESCAPE '\')
+                AND ($2 IS NULL OR account_type = $2)
+"#;
+
+/// `LIKE` pattern of the search of the query, `None` when it is empty.
+fn search_pattern(query: &ListQuery) -> Option<String> {
+    let search = query.search.as_deref().map(str::trim).unwrap_or_default();
+    if search.is_empty() {
+        return None;
+    }
+    Some(contains_pattern(&search.to_lowercase()))
+}
+
 impl User {
     pub async fn create(&self, db: &DbPool) -> Result<()> {
         sqlx::query(
@@ -149,44 +172,84 @@ impl User {
         .ok()
     }
 
-    pub async fn list(
-        db: &DbPool,
-        page: Option<u32>,
-        page_size: Option<u32>,
-        order_by: Option<ListOrder>,
-    ) -> Result<Vec<User>> {
-        let page = page.unwrap_or(1);
-        let page_size = page_size.unwrap_or(25);
-        let order_by = order_by.unwrap_or(ListOrder::AccountType);
-        let order_by = match order_by {
-            ListOrder::AccountType => "account_type",
-            ListOrder::Email => "email",
-            ListOrder::RegistrationDate => "registration_date",
-            ListOrder::Username => "username",
+    /// Returns a page of the users matching the search and account type of the query.
+    pub async fn list(db: &DbPool, query: &ListQuery) -> Result<Vec<User>> {
+        let page = query.page.unwrap_or(1).max(1);
+        let page_size = query.page_size.unwrap_or(25);
+        let direction = if query.descending.unwrap_or_default() {
+            "DESC"
+        } else {
+            "ASC"
+        };
+        let order_by = match query.order_by.unwrap_or(ListOrder::AccountType) {
+            ListOrder::AccountType => format!("account_type {direction}"),
+            ListOrder::Email => format!("email {direction}"),
+            ListOrder::RegistrationDate => format!("registration_date {direction}"),
+            ListOrder::Username => format!("username {direction}"),
+            ListOrder::LastLogin => format!("last_login IS NULL, last_login {direction}"),
         };
         let offset = (page - 1) * page_size;
-        sqlx::query_as(
+        let sql = format!(
             r#"
-            SELECT id, username, email, password, account_type, registration_date
+            SELECT id, username, email, password, account_type, registration_date, last_login
             FROM users
-            ORDER BY $1
-            LIMIT $2
-            OFFSET $3;
+            WHERE {LIST_CONDITION}
+            ORDER BY {order_by}, username
+            LIMIT $3
+            OFFSET $4
+            "#
+        );
+        sqlx::query_as(&sql)
+            .bind(search_pattern(query))
+            .bind(query.account_type)
+            .bind(page_size as i32)
+            .bind(offset as i32)
+            .fetch_all(db)
+            .await
+            .map_err(UserError::ListError)
+            .map_err(Error::from)
+    }
+
+    /// Returns the number of users matching the search and account type of the query.
+    pub async fn count(db: &DbPool, query: &ListQuery) -> Result<i64> {
+        let sql = format!(
+            r#"
+            SELECT COUNT(*)
+            FROM users
+            WHERE {LIST_CONDITION}
+            "#
+        );
+        sqlx::query_scalar(&sql)
+            .bind(search_pattern(query))
+            .bind(query.account_type)
+            .fetch_one(db)
+            .await
+            .map_err(UserError::ListError)
+            .map_err(Error::from)
+    }
+
+    /// Records a successful login.
+    pub async fn update_last_login(db: &DbPool, id: &Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET last_login = $1
+            WHERE id = $2
             "#,
         )
-        .bind(order_by)
-        .bind(page_size as i32)
-        .bind(offset as i32)
-        .fetch_all(db)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(db)
         .await
-        .map_err(UserError::ListError)
+        .map(|_| ())
+        .map_err(UserError::UpdateError)
         .map_err(Error::from)
     }
 
     pub async fn get(db: &DbPool, username: &str) -> Result<Self> {
         sqlx::query_as(
             r#"
-            SELECT id, username, email, password, account_type, registration_date
+            SELECT id, username, email, password, account_type, registration_date, last_login
             FROM users
             WHERE username = $1
             "#,
@@ -201,7 +264,7 @@ impl User {
     pub async fn get_by_id(db: &DbPool, id: &Uuid) -> Result<Self> {
         sqlx::query_as(
             r#"
-            SELECT id, username, email, password, account_type, registration_date
+            SELECT id, username, email, password, account_type, registration_date, last_login
             FROM users
             WHERE id = $1
             "#,
@@ -266,45 +329,197 @@ impl User {
         .map_err(Error::from)
     }
 
-    pub async fn authenticate(db: &DbPool, credentials: Credentials) -> Result<Self> {
-        let pass = credentials.password_digest();
-        sqlx::query_as(
+    /// Sets the avatar of `username`, `None` removes it.
+    pub async fn set_avatar(db: &DbPool, username: &str, avatar: Option<&[u8]>) -> Result<()> {
+        sqlx::query(
             r#"
-            SELECT id, username, email, password, account_type, registration_date
+            UPDATE users
+            SET avatar = $1
+            WHERE username = $2
+            "#,
+        )
+        .bind(avatar)
+        .bind(username)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(UserError::AvatarUpdateError)
+        .map_err(Error::from)
+    }
+
+    pub async fn get_avatar(db: &DbPool, username: &str) -> Result<Option<Vec<u8>>> {
+        sqlx::query_scalar(
+            r#"
+            SELECT avatar
             FROM users
-            WHERE username = $1 AND password = $2
+            WHERE username = $1
             "#,
         )
-        .bind(credentials.username())
-        .bind(pass)
+        .bind(username)
         .fetch_one(db)
         .await
-        .map_err(|_| AuthenticationError::AuthenticationFailed)
+        .map_err(UserError::GetAvatarError)
         .map_err(Error::from)
     }
 
-    pub async fn change_pasword(db: &DbPool, user_id: &Uuid, new_password: String) -> Result<()> {
+    /// Returns the generation defaults of `username`, empty if the user never saved any.
+    pub async fn get_settings(db: &DbPool, username: &str) -> Result<UserSettings> {
+        let settings: Option<String> = sqlx::query_scalar(
+            r#"
+            SELECT s.settings
+            FROM user_settings s
+            INNER JOIN users u ON u.id = s.user_id
+            WHERE u.username = $1
+            "#,
+        )
+        .bind(username)
+        .fetch_optional(db)
+        .await
+        .map_err(UserError::GetSettingsError)?;
+
+        match settings {
+            Some(settings) => serde_json::from_str(&settings)
+                .map_err(UserError::InvalidSettings)
+                .map_err(Error::from),
+            None => Ok(UserSettings::default()),
+        }
+    }
+
+    pub async fn set_settings(db: &DbPool, username: &str, settings: &UserSettings) -> Result<()> {
+        let settings = serde_json::to_string(settings).map_err(UserError::InvalidSettings)?;
         sqlx::query(
             r#"
-            UPDATE users
-            SET password = $1
-            WHERE id = $2
+            INSERT INTO user_settings (user_id, settings)
+            SELECT id, $1
+            FROM users
+            WHERE username = $2
+            ON CONFLICT (user_id) DO UPDATE SET settings = excluded.settings
             "#,
         )
-        .bind(hash_pass(new_password))
-        .bind(user_id)
+        .bind(settings)
+        .bind(username)
         .execute(db)
         .await
         .map(|_| ())
-        .map_err(UserError::PasswordUpdateError)
+        .map_err(UserError::SettingsUpdateError)
         .map_err(Error::from)
     }
 
+    /// Returns the web app preferences of `username`, empty if the user never saved any.
+    pub async fn get_preferences(db: &DbPool, username: &str) -> Result<UiPreferences> {
+        let preferences: Option<String> = sqlx::query_scalar(
+            r#"
+            SELECT p.preferences
+            FROM user_preferences p
+            INNER JOIN users u ON u.id = p.user_id
+            WHERE u.username = $1
+            "#,
+        )
+        .bind(username)
+        .fetch_optional(db)
+        .await
+        .map_err(UserError::GetSettingsError)?;
+
+        match preferences {
+            Some(preferences) => serde_json::from_str(&preferences)
+                .map_err(UserError::InvalidSettings)
+                .map_err(Error::from),
+            None => Ok(UiPreferences::default()),
+        }
+    }
+
+    /// Stores the web app preferences of `username` unless the stored ones were changed later,
+    /// returns the preferences that are stored afterwards.
+    pub async fn set_preferences(
+        db: &DbPool,
+        username: &str,
+        preferences: &UiPreferences,
+        updated_at: DateTime<Utc>,
+    ) -> Result<UiPreferences> {
+        let preferences = UiPreferences {
+            updated_at: Some(updated_at),
+            ..preferences.clone()
+        };
+        let serialized = serde_json::to_string(&preferences).map_err(UserError::InvalidSettings)?;
+        sqlx::query(
+            r#"
+            INSERT INTO user_preferences (user_id, preferences, updated_at)
+            SELECT id, $1, $2
+            FROM users
+            WHERE username = $3
+            ON CONFLICT (user_id) DO UPDATE
+            SET preferences = excluded.preferences, updated_at = excluded.updated_at
+            WHERE user_preferences.updated_at <= excluded.updated_at
+            "#,
+        )
+        .bind(serialized)
+        .bind(updated_at)
+        .bind(username)
+        .execute(db)
+        .await
+        .map_err(UserError::SettingsUpdateError)?;
+
+        Self::get_preferences(db, username).await
+    }
+
+    /// Checks the credentials and replaces the stored password hash if it is weaker than new
+    /// hashes with `hash_iterations`.
+    pub async fn authenticate(
+        db: &DbPool,
+        credentials: Credentials,
+        hash_iterations: u32,
+    ) -> Result<Self> {
+        let user: Option<Self> = sqlx::query_as(
+            r#"
+            SELECT id, username, email, password, account_type, registration_date, last_login
+            FROM users
+            WHERE username = $1
+            "#,
+        )
+        .bind(credentials.username())
+        .fetch_optional(db)
+        .await
+        .map_err(|_| AuthenticationError::AuthenticationFailed)?;
+
+        let (_, password) = credentials.consume();
+        let Some(user) = user else {
+            // take as long as a wrong password so that response times don't reveal usernames
+            hash_password_blocking(password, hash_iterations).await;
+            return Err(AuthenticationError::AuthenticationFailed.into());
+        };
+        if !verify_password_blocking(password.clone(), user.password.clone()).await {
+            return Err(AuthenticationError::AuthenticationFailed.into());
+        }
+
+        if needs_rehash(&user.password, hash_iterations) {
+            let hash = hash_password_blocking(password, hash_iterations).await;
+            if let Err(e) = Self::set_password_hash(db, &user.id, hash).await {
+                log::error!(
+                    "failed to upgrade the password hash of {} - {e}",
+                    user.username
+                );
+            }
+        }
+        Ok(user)
+    }
+
+    pub async fn change_pasword(
+        db: &DbPool,
+        user_id: &Uuid,
+        new_password: String,
+        hash_iterations: u32,
+    ) -> Result<()> {
+        let hash = hash_password_blocking(new_password, hash_iterations).await;
+        Self::set_password_hash(db, user_id, hash).await
+    }
+
     pub async fn change_pasword_by_username(
         db: &DbPool,
         username: &str,
         new_password: String,
+        hash_iterations: u32,
     ) -> Result<()> {
+        let hash = hash_password_blocking(new_password, hash_iterations).await;
         sqlx::query(
             r#"
             UPDATE users
@@ -312,7 +527,7 @@ impl User {
             WHERE username = $2
             "#,
         )
-        .bind(hash_pass(new_password))
+        .bind(hash)
         .bind(username)
         .execute(db)
         .await
@@ -320,10 +535,21 @@ impl User {
         .map_err(UserError::PasswordUpdateError)
         .map_err(Error::from)
     }
-}
 
-impl From<UserRegisterRequest> for User {
-    fn from(req: UserRegisterRequest) -> Self {
-        User::new(req.username, req.password, req.email, req.account_type)
+    async fn set_password_hash(db: &DbPool, user_id: &Uuid, hash: Vec<u8>) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET password = $1
+            WHERE id = $2
+            "#,
+        )
+        .bind(hash)
+        .bind(user_id)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(UserError::PasswordUpdateError)
+        .map_err(Error::from)
     }
 }
@@ -0,0 +1,186 @@
+use crate::{
+    id::Uuid,
+    models::{Error, Result},
+    DbPool,
+};
+use airtifex_core::webhook::{WebhookCreateRequest, WebhookEvent, WebhookInspect};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ErrorType;
+
+#[derive(Debug, ErrorType)]
+pub enum WebhookError {
+    #[error("failed to create a webhook - {0}")]
+    Create(sqlx::Error),
+    #[error("failed to inspect a webhook - {0}")]
+    Inspect(sqlx::Error),
+    #[error("failed to update a webhook - {0}")]
+    Update(sqlx::Error),
+    #[error("failed to delete a webhook - {0}")]
+    Delete(sqlx::Error),
+    #[error("failed to list webhooks - {0}")]
+    List(sqlx::Error),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    /// Comma separated names of the events, see [`events`](Self::events).
+    pub events: String,
+    pub create_date: chrono::DateTime<chrono::Utc>,
+    pub last_status: Option<String>,
+    pub last_delivery_date: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Webhook {
+    pub fn new(user_id: Uuid, request: WebhookCreateRequest) -> Self {
+        let mut events: Vec<&str> = Vec::new();
+        for event in &request.events {
+            if !events.contains(&event.as_ref()) {
+                events.push(event.as_ref());
+            }
+        }
+        Self {
+            id: Uuid::new_v4(),
+            user_id,
+            url: request.url.trim().to_string(),
+            secret: request.secret,
+            events: events.join(","),
+            create_date: chrono::Utc::now(),
+            last_status: None,
+            last_delivery_date: None,
+        }
+    }
+
+    /// Events the webhook is subscribed to, unknown names are skipped.
+    pub fn events(&self) -> Vec<WebhookEvent> {
+        self.events
+            .split(',')
+            .filter_map(WebhookEvent::parse_str)
+            .collect()
+    }
+
+    pub fn is_subscribed(&self, event: WebhookEvent) -> bool {
+        self.events.split(',').any(|e| e == event.as_ref())
+    }
+
+    /// The secret is left out.
+    pub fn inspect(self) -> WebhookInspect {
+        WebhookInspect {
+            id: self.id.to_string(),
+            events: self.events(),
+            url: self.url,
+            create_date: self.create_date,
+            last_status: self.last_status,
+            last_delivery_date: self.last_delivery_date,
+        }
+    }
+
+    pub async fn create(&self, db: &DbPool) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO webhooks
+                    (id, user_id, url, secret, events, create_date)
+            VALUES  ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(self.id)
+        .bind(self.user_id)
+        .bind(&self.url)
+        .bind(&self.secret)
+        .bind(&self.events)
+        .bind(self.create_date)
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(WebhookError::Create)
+        .map_err(Error::from)
+    }
+
+    pub async fn get_for_user(db: &DbPool, user_id: &Uuid, id: &Uuid) -> Result<Self> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM webhooks
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+        .map_err(WebhookError::Inspect)
+        .map_err(Error::from)
+    }
+
+    /// Returns the webhooks of the user, oldest first.
+    pub async fn list_for_user(db: &DbPool, user_id: &Uuid) -> Result<Vec<Self>> {
+        sqlx::query_as(
+            r#"
+            SELECT *
+            FROM webhooks
+            WHERE user_id = $1
+            ORDER BY create_date
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(db)
+        .await
+        .map_err(WebhookError::List)
+        .map_err(Error::from)
+    }
+
+    pub async fn count_for_user(db: &DbPool, user_id: &Uuid) -> Result<i64> {
+        sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*)
+            FROM webhooks
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+        .map_err(WebhookError::Inspect)
+        .map_err(Error::from)
+    }
+
+    /// Records the outcome of the last delivery.
+    pub async fn update_last_delivery(db: &DbPool, id: &Uuid, status: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE webhooks
+            SET last_status = $2, last_delivery_date = $3
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(status)
+        .bind(chrono::Utc::now())
+        .execute(db)
+        .await
+        .map(|_| ())
+        .map_err(WebhookError::Update)
+        .map_err(Error::from)
+    }
+
+    /// Deletes the webhook, returns whether the user had it.
+    pub async fn delete_for_user(db: &DbPool, user_id: &Uuid, id: &Uuid) -> Result<bool> {
+        sqlx::query(
+            r#"
+            DELETE FROM webhooks
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(db)
+        .await
+        .map(|r| r.rows_affected() > 0)
+        .map_err(WebhookError::Delete)
+        .map_err(Error::from)
+    }
+}
@@ -0,0 +1,168 @@
+//! Content-safety gate of the prompts sent to the models and, when enabled, of the answers they
+//! generate. The moderator of the app state is built from the config, deployments that need
+//! another check, for example one calling an external API, put their own [`Moderator`] there.
+
+use crate::{config::ModerationConfig, Error, Result};
+
+use regex::{Regex, RegexBuilder};
+use std::sync::Arc;
+
+/// Whether a text can be passed on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    /// The text is refused, the reason is returned to the client.
+    Block {
+        reason: String,
+    },
+}
+
+/// Checks prompts before they are queued and answers while they are generated.
+pub trait Moderator: Send + Sync {
+    fn check(&self, text: &str) -> Decision;
+}
+
+/// Allows every text, used when the config doesn't block anything.
+pub struct NoopModerator;
+
+impl Moderator for NoopModerator {
+    fn check(&self, _text: &str) -> Decision {
+        Decision::Allow
+    }
+}
+
+/// Blocks texts that contain one of the keywords or match one of the patterns, both ignoring
+/// case.
+pub struct BlocklistModerator {
+    keywords: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl BlocklistModerator {
+    pub fn new(keywords: &[String], patterns: &[String]) -> Result<Self> {
+        let keywords = keywords
+            .iter()
+            .map(|keyword| keyword.trim().to_lowercase())
+            .filter(|keyword| !keyword.is_empty())
+            .collect();
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| {
+                        Error::InvalidConfig(format!(
+                            "invalid moderation pattern `{pattern}` - {e}"
+                        ))
+                    })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { keywords, patterns })
+    }
+}
+
+impl Moderator for BlocklistModerator {
+    fn check(&self, text: &str) -> Decision {
+        let lowercase = text.to_lowercase();
+        if self
+            .keywords
+            .iter()
+            .any(|keyword| lowercase.contains(keyword.as_str()))
+        {
+            return Decision::Block {
+                reason: "the text contains a blocked word".into(),
+            };
+        }
+        if self.patterns.iter().any(|pattern| pattern.is_match(text)) {
+            return Decision::Block {
+                reason: "the text matches a blocked pattern".into(),
+            };
+        }
+        Decision::Allow
+    }
+}
+
+/// Moderator of the config, the blocklist when it has any entries.
+pub fn from_config(config: &ModerationConfig) -> Result<Arc<dyn Moderator>> {
+    if config.keywords.is_empty() && config.patterns.is_empty() {
+        return Ok(Arc::new(NoopModerator));
+    }
+    Ok(Arc::new(BlocklistModerator::new(
+        &config.keywords,
+        &config.patterns,
+    )?))
+}
+
+/// Fails with the reason of the first of the texts the moderator blocks.
+pub fn check_all<'a>(
+    moderator: &dyn Moderator,
+    texts: impl IntoIterator<Item = &'a str>,
+) -> Result<()> {
+    for text in texts {
+        if let Decision::Block { reason } = moderator.check(text) {
+            return Err(Error::ContentBlocked(reason));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod tests {
+    use super::*;
+    use crate::{queue::queue_channel, testing, SharedAppState};
+    use airtifex_core::user::AccountType;
+
+    use axum::http::{Method, StatusCode};
+
+    #[test]
+    fn blocklist_ignores_case() {
+        let moderator =
+            BlocklistModerator::new(&[" Secret ".into()], &[r"\bpass(word)?\b".into()]).unwrap();
+        assert_eq!(moderator.check("a harmless prompt"), Decision::Allow);
+        assert_eq!(
+            moderator.check("tell me the SECRET"),
+            Decision::Block {
+                reason: "the text contains a blocked word".into()
+            }
+        );
+        assert_eq!(
+            moderator.check("what is the Password"),
+            Decision::Block {
+                reason: "the text matches a blocked pattern".into()
+            }
+        );
+        assert_eq!(moderator.check("passage"), Decision::Allow);
+        assert!(BlocklistModerator::new(&[], &["(".into()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn blocked_prompt_is_refused() {
+        let db = testing::db().await;
+        let alice = testing::user(&db, "alice", AccountType::User).await;
+        let config = testing::config("moderation:\n  keywords: [forbidden]\n");
+        let llm_config = serde_yaml::from_str("type: llama\nmodel_path: llama.bin\n")
+            .expect("model config is valid");
+        let mut state = testing::inner_state(db, config);
+        let (tx_request, rx_request, _) = queue_channel(1);
+        state
+            .tx_inference_req
+            .insert("llama".into(), (llm_config, tx_request));
+        let router = testing::router(SharedAppState::from(std::sync::Arc::new(state)));
+
+        let (status, body) = testing::send(
+            &router,
+            Method::POST,
+            "/api/v1/llm/inference",
+            Some(&testing::token(&alice)),
+            Some(serde_json::json!({
+                "prompt": "Something Forbidden",
+                "model": "llama",
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "ContentBlocked");
+        assert!(rx_request.is_empty());
+    }
+}
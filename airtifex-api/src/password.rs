@@ -0,0 +1,265 @@
+//! Password hashes stored in the `password` column of users. New hashes are PBKDF2-HMAC-SHA256
+//! with a random salt encoded as `$pbkdf2-sha256$i=<iterations>$<salt>$<hash>`. Older accounts
+//! still have an unsalted SHA3-224 digest, it is replaced on their next login like hashes with
+//! fewer iterations than configured.
+
+use airtifex_core::auth::hash_pass;
+
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SCHEME: &str = "pbkdf2-sha256";
+const SALT_LENGTH: usize = 16;
+
+/// Parameters and digest of a stored password hash.
+enum StoredHash {
+    /// Unsalted SHA3-224 digest of the password.
+    Legacy(Vec<u8>),
+    Pbkdf2 {
+        iterations: u32,
+        salt: Vec<u8>,
+        hash: Vec<u8>,
+    },
+}
+
+impl StoredHash {
+    fn parse(stored: &[u8]) -> Option<Self> {
+        let Ok(encoded) = std::str::from_utf8(stored) else {
+            return Self::legacy(stored);
+        };
+        let mut parts = encoded.split('$');
+        let (Some(""), Some(SCHEME), Some(iterations), Some(salt), Some(hash), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            return Self::legacy(stored);
+        };
+        Some(Self::Pbkdf2 {
+            iterations: iterations.strip_prefix("i=")?.parse().ok()?,
+            salt: STANDARD_NO_PAD.decode(salt).ok()?,
+            hash: STANDARD_NO_PAD.decode(hash).ok()?,
+        })
+    }
+
+    fn legacy(stored: &[u8]) -> Option<Self> {
+        // length of a SHA3-224 digest
+        (stored.len() == 28).then(|| Self::Legacy(stored.to_vec()))
+    }
+}
+
+/// Hashes `password` with a new random salt.
+pub fn hash_password(password: &str, iterations: u32) -> Vec<u8> {
+    let mut salt = [0; SALT_LENGTH];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let hash = pbkdf2_sha256(password.as_bytes(), &salt, iterations);
+    format!(
+        "${SCHEME}$i={iterations}${}${}",
+        STANDARD_NO_PAD.encode(salt),
+        STANDARD_NO_PAD.encode(hash)
+    )
+    .into_bytes()
+}
+
+/// Checks `password` against a stored hash in constant time, malformed hashes never match.
+pub fn verify_password(password: &str, stored: &[u8]) -> bool {
+    match StoredHash::parse(stored) {
+        Some(StoredHash::Legacy(digest)) => hash_pass(password.to_string()).ct_eq(&digest).into(),
+        Some(StoredHash::Pbkdf2 {
+            iterations,
+            salt,
+            hash,
+        }) => pbkdf2_sha256(password.as_bytes(), &salt, iterations)
+            .as_slice()
+            .ct_eq(&hash)
+            .into(),
+        None => false,
+    }
+}
+
+/// Whether the stored hash is weaker than new hashes with `iterations` and should be replaced
+/// once the password is known.
+pub fn needs_rehash(stored: &[u8], iterations: u32) -> bool {
+    match StoredHash::parse(stored) {
+        Some(StoredHash::Pbkdf2 {
+            iterations: stored, ..
+        }) => stored < iterations,
+        _ => true,
+    }
+}
+
+/// PBKDF2 with HMAC-SHA256 as defined in RFC 8018, derives a single block of 32 bytes.
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let prf = HmacSha256::new_from_slice(password).expect("HMAC accepts keys of any size");
+
+    let mut mac = prf.clone();
+    mac.update(salt);
+    mac.update(&1u32.to_be_bytes());
+    let mut block = mac.finalize().into_bytes();
+    let mut derived: [u8; 32] = block.into();
+
+    for _ in 1..iterations {
+        let mut mac = prf.clone();
+        mac.update(&block);
+        block = mac.finalize().into_bytes();
+        derived
+            .iter_mut()
+            .zip(block.iter())
+            .for_each(|(d, b)| *d ^= b);
+    }
+    derived
+}
+
+/// Runs [`hash_password`] on the blocking thread pool, hashing takes long enough to hold up the
+/// other requests of an async worker.
+pub async fn hash_password_blocking(password: String, iterations: u32) -> Vec<u8> {
+    tokio::task::spawn_blocking(move || hash_password(&password, iterations))
+        .await
+        .expect("password hashing doesn't panic")
+}
+
+/// Runs [`verify_password`] on the blocking thread pool.
+pub async fn verify_password_blocking(password: String, stored: Vec<u8>) -> bool {
+    tokio::task::spawn_blocking(move || verify_password(&password, &stored))
+        .await
+        .expect("password verification doesn't panic")
+}
+
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod tests {
+    use super::*;
+    use crate::{models::user::User, testing, DbPool};
+    use airtifex_core::{auth::Credentials, user::AccountType};
+
+    use axum::http::{Method, StatusCode};
+
+    #[test]
+    fn hashes_only_match_their_password() {
+        let stored = hash_password("hunter22", 3);
+        assert!(stored.starts_with(b"$pbkdf2-sha256$i=3$"));
+        assert!(verify_password("hunter22", &stored));
+        assert!(!verify_password("hunter23", &stored));
+        // every hash gets a salt of its own
+        assert_ne!(stored, hash_password("hunter22", 3));
+
+        let legacy = hash_pass("hunter22".to_string());
+        assert!(verify_password("hunter22", &legacy));
+        assert!(!verify_password("hunter23", &legacy));
+
+        assert!(!verify_password(
+            "hunter22",
+            b"$pbkdf2-sha256$i=x$AAAA$AAAA"
+        ));
+        assert!(!verify_password("", b""));
+    }
+
+    #[test]
+    fn weaker_hashes_need_a_rehash() {
+        assert!(needs_rehash(&hash_pass("hunter22".to_string()), 1));
+        assert!(needs_rehash(&hash_password("hunter22", 2), 3));
+        assert!(!needs_rehash(&hash_password("hunter22", 3), 3));
+        assert!(!needs_rehash(&hash_password("hunter22", 4), 3));
+    }
+
+    async fn login(db: &DbPool, username: &str, hash_iterations: u32) -> Vec<u8> {
+        let credentials = Credentials::new(username, testing::PASSWORD);
+        User::authenticate(db, credentials, hash_iterations)
+            .await
+            .expect("credentials are valid");
+        User::get(db, username).await.expect("user exists").password
+    }
+
+    #[tokio::test]
+    async fn login_upgrades_weaker_hashes() {
+        let db = testing::db().await;
+        let legacy = User::new(
+            "legacy",
+            hash_pass(testing::PASSWORD.to_string()),
+            "legacy@example.com".to_string(),
+            AccountType::User,
+        );
+        legacy.create(&db).await.expect("user is created");
+        let stored = login(&db, "legacy", 2).await;
+        assert!(stored.starts_with(b"$pbkdf2-sha256$i=2$"));
+        assert!(verify_password(testing::PASSWORD, &stored));
+
+        // hashes with fewer iterations than configured are replaced, others are kept
+        let stored = login(&db, "legacy", 3).await;
+        assert!(stored.starts_with(b"$pbkdf2-sha256$i=3$"));
+        assert_eq!(login(&db, "legacy", 3).await, stored);
+        assert_eq!(login(&db, "legacy", 2).await, stored);
+
+        // a wrong password leaves the hash alone
+        let credentials = Credentials::new("legacy", "wrong");
+        assert!(User::authenticate(&db, credentials, 4).await.is_err());
+        assert_eq!(User::get(&db, "legacy").await.unwrap().password, stored);
+    }
+
+    #[tokio::test]
+    async fn weak_passwords_are_refused_with_the_missing_requirements() {
+        let db = testing::db().await;
+        let admin = testing::user(&db, "admin", AccountType::Admin).await;
+        let mut config = testing::config("");
+        config.passwords.min_length = 10;
+        config.passwords.require_uppercase = true;
+        config.passwords.require_digit = true;
+        let router = testing::router(testing::state(db.clone(), config));
+        let token = testing::token(&admin);
+
+        let register = |password: &str| {
+            serde_json::json!({
+                "username": "bob",
+                "password": password,
+                "email": "bob@example.com",
+                "account_type": "user",
+            })
+        };
+        let (status, body) = testing::send(
+            &router,
+            Method::POST,
+            "/api/v1/users",
+            Some(&token),
+            Some(register("short")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["data"],
+            "invalid `password` - must be at least 10 characters long, got 5, contain an \
+             uppercase letter, contain a digit"
+        );
+        assert!(User::get(&db, "bob").await.is_err());
+
+        let (status, _) = testing::send(
+            &router,
+            Method::POST,
+            "/api/v1/users",
+            Some(&token),
+            Some(register("Long enough 1")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = testing::send(
+            &router,
+            Method::POST,
+            "/api/v1/users/bob/password",
+            Some(&token),
+            Some(serde_json::json!({ "new_password": "no digits here" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["data"],
+            "invalid `new_password` - must contain an uppercase letter, contain a digit"
+        );
+    }
+}
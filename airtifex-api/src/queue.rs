@@ -1,35 +1,265 @@
-use flume::{unbounded, Sender};
+use airtifex_core::QueueStatus;
+
+use flume::{Receiver, Sender, TrySendError};
+use futures_util::Stream;
 use std::{
     collections::VecDeque,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
+use thiserror::Error as ErrorType;
+use tokio::sync::watch;
 
-pub type Queue<T> = Arc<RwLock<VecDeque<T>>>;
+#[derive(Debug, ErrorType)]
+pub enum QueueError {
+    #[error("the queue of the model is full, try again later")]
+    Full,
+    #[error("the model stopped taking requests")]
+    Stopped,
+}
 
-pub fn empty_queue<T: Send + Sync + 'static>() -> Queue<T> {
-    Default::default()
+/// Requests waiting for a model in the order they were sent, together with the number of
+/// requests the model is working on.
+#[derive(Debug, Default)]
+struct Waiting {
+    ids: VecDeque<u64>,
+    running_sessions: usize,
 }
 
-pub fn start_queue_thread<T: Send + Sync + 'static>(queue: Queue<T>) -> Sender<T> {
-    let (tx_request, rx_request) = unbounded();
-    std::thread::spawn(move || {
-        let mut temp_queue = VecDeque::new();
-        loop {
-            if !temp_queue.is_empty() {
-                if let Ok(mut queue) = queue.try_write() {
-                    queue.append(&mut temp_queue);
-                }
+/// Requests that keep their place in the queue of a model until they are started.
+pub trait Queued {
+    fn set_queue_ticket(&mut self, ticket: QueueTicket);
+}
+
+/// Sends requests to a model and keeps track of their place in its queue.
+#[derive(Debug)]
+pub struct QueueSender<T> {
+    tx_request: Sender<T>,
+    waiting: Arc<watch::Sender<Waiting>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl<T> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx_request: self.tx_request.clone(),
+            waiting: self.waiting.clone(),
+            next_id: self.next_id.clone(),
+        }
+    }
+}
+
+impl<T: Queued> QueueSender<T> {
+    /// Sends `request` to the end of the queue and returns its place, a full queue refuses it
+    /// instead of waiting for room.
+    pub fn send(&self, mut request: T) -> Result<QueuePosition, QueueError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        request.set_queue_ticket(QueueTicket {
+            id,
+            waiting: self.waiting.clone(),
+            queued_at: Instant::now(),
+        });
+        let mut result = Ok(());
+        // the list is locked while sending so that its order matches the order of the channel
+        self.waiting.send_modify(|waiting| {
+            result = self.tx_request.try_send(request);
+            if result.is_ok() {
+                waiting.ids.push_back(id);
             }
-            if let Ok(gen_image_request) = rx_request.try_recv() {
-                if let Ok(mut queue) = queue.try_write() {
-                    queue.push_back(gen_image_request);
-                } else {
-                    temp_queue.push_back(gen_image_request);
+        });
+        // a request that wasn't sent drops its ticket here, outside of the lock
+        match result {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => return Err(QueueError::Full),
+            Err(TrySendError::Disconnected(_)) => return Err(QueueError::Stopped),
+        }
+        Ok(QueuePosition {
+            id,
+            rx_waiting: self.waiting.subscribe(),
+        })
+    }
+}
+
+/// Creates the queue of a model that holds up to `capacity` waiting requests. The model reports
+/// the number of requests it is working on through the returned [`RunningSessions`].
+pub fn queue_channel<T>(capacity: usize) -> (QueueSender<T>, Receiver<T>, RunningSessions) {
+    let (tx_request, rx_request) = flume::bounded(capacity);
+    let waiting = Arc::new(watch::channel(Waiting::default()).0);
+    let sender = QueueSender {
+        tx_request,
+        waiting: waiting.clone(),
+        next_id: Default::default(),
+    };
+    (sender, rx_request, RunningSessions { waiting })
+}
+
+/// Place of a request in the queue, it is given up when the ticket is dropped. Models drop the
+/// ticket once they start the request.
+#[derive(Debug)]
+pub struct QueueTicket {
+    id: u64,
+    waiting: Arc<watch::Sender<Waiting>>,
+    queued_at: Instant,
+}
+
+impl QueueTicket {
+    /// Time since the request was sent.
+    pub fn waited(&self) -> Duration {
+        self.queued_at.elapsed()
+    }
+}
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        self.waiting.send_if_modified(|waiting| {
+            let len = waiting.ids.len();
+            waiting.ids.retain(|id| *id != self.id);
+            waiting.ids.len() != len
+        });
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RunningSessions {
+    waiting: Arc<watch::Sender<Waiting>>,
+}
+
+impl RunningSessions {
+    pub fn set(&self, running_sessions: usize) {
+        self.waiting.send_if_modified(|waiting| {
+            let is_modified = waiting.running_sessions != running_sessions;
+            waiting.running_sessions = running_sessions;
+            is_modified
+        });
+    }
+}
+
+/// Follows the place of a sent request in the queue.
+#[derive(Clone, Debug)]
+pub struct QueuePosition {
+    id: u64,
+    rx_waiting: watch::Receiver<Waiting>,
+}
+
+impl QueuePosition {
+    /// Current place of the request, `None` once it was started.
+    pub fn status(&self) -> Option<QueueStatus> {
+        let waiting = self.rx_waiting.borrow();
+        let position = waiting.ids.iter().position(|id| *id == self.id)?;
+        Some(QueueStatus {
+            position: position + 1,
+            running_sessions: waiting.running_sessions,
+        })
+    }
+
+    /// Streams the place of the request whenever it moves closer to the front, the stream ends
+    /// with `None` once the request is started.
+    pub fn updates(self) -> impl Stream<Item = Option<QueueStatus>> {
+        futures_util::stream::unfold(Some((self, usize::MAX)), |state| async move {
+            let (mut queue, last_position) = state?;
+            loop {
+                queue.rx_waiting.borrow_and_update();
+                let Some(status) = queue.status() else {
+                    return Some((None, None));
+                };
+                // the position only ever decreases, later requests are queued behind it
+                if status.position < last_position {
+                    let position = status.position;
+                    return Some((Some(status), Some((queue, position))));
+                }
+                if queue.rx_waiting.changed().await.is_err() {
+                    return Some((None, None));
                 }
             }
-            std::thread::sleep(std::time::Duration::from_millis(5));
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Request {
+        ticket: Option<QueueTicket>,
+    }
+
+    impl Queued for Request {
+        fn set_queue_ticket(&mut self, ticket: QueueTicket) {
+            self.ticket = Some(ticket);
         }
-    });
+    }
+
+    impl Request {
+        fn id(&self) -> u64 {
+            self.ticket.as_ref().expect("request was queued").id
+        }
+    }
+
+    #[test]
+    fn concurrent_requests_keep_the_order_of_the_channel() {
+        let (tx_request, rx_request, _) = queue_channel(64);
+        let positions = std::thread::scope(|scope| {
+            let senders: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..8)
+                            .map(|_| tx_request.send(Request::default()).expect("queue has room"))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            senders
+                .into_iter()
+                .flat_map(|sender| sender.join().expect("sender doesn't panic"))
+                .collect::<Vec<_>>()
+        });
+
+        let mut requests: Vec<_> = rx_request.try_iter().collect();
+        let received: Vec<_> = requests.iter().map(Request::id).collect();
+        let waiting: Vec<_> = tx_request.waiting.borrow().ids.iter().copied().collect();
+        assert_eq!(received.len(), 64);
+        assert_eq!(received, waiting);
+        for position in &positions {
+            let place = received.iter().position(|id| *id == position.id);
+            assert_eq!(position.status().map(|s| s.position), place.map(|p| p + 1));
+        }
+
+        // starting the first request moves everyone else up
+        drop(requests.remove(0));
+        for position in &positions {
+            let status = position.status().map(|s| s.position);
+            match received.iter().position(|id| *id == position.id) {
+                Some(0) => assert_eq!(status, None),
+                place => assert_eq!(status, place),
+            }
+        }
+    }
+
+    #[test]
+    fn full_queue_refuses_requests() {
+        let (tx_request, rx_request, _) = queue_channel(2);
+        let first = tx_request.send(Request::default()).expect("queue has room");
+        tx_request.send(Request::default()).expect("queue has room");
+        assert!(matches!(
+            tx_request.send(Request::default()),
+            Err(QueueError::Full)
+        ));
+        assert_eq!(tx_request.waiting.borrow().ids.len(), 2);
+
+        // a started request makes room for another one
+        drop(rx_request.try_recv());
+        assert!(first.status().is_none());
+        let last = tx_request.send(Request::default()).expect("queue has room");
+        assert_eq!(last.status().map(|s| s.position), Some(2));
 
-    tx_request
+        drop(rx_request);
+        assert!(matches!(
+            tx_request.send(Request::default()),
+            Err(QueueError::Stopped)
+        ));
+    }
 }
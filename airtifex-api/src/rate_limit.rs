@@ -0,0 +1,221 @@
+use crate::{auth::Claims, config::RateLimit, SharedAppState, ToAxumResponse};
+use airtifex_core::api_response::ApiResponse;
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, State},
+    http::{header, request::Parts, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Number of tracked clients after which buckets that are full again get dropped.
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RouteGroup {
+    Chat,
+    Image,
+    Users,
+}
+
+impl AsRef<str> for RouteGroup {
+    fn as_ref(&self) -> &str {
+        match self {
+            RouteGroup::Chat => "chat",
+            RouteGroup::Image => "image",
+            RouteGroup::Users => "users",
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    /// Time at which the bucket is full again, after that it can be forgotten.
+    full_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(limit.burst as f64);
+        self.last_refill = now;
+    }
+}
+
+/// Token bucket rate limiter keyed by route group and client.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<(RouteGroup, String), Bucket>>,
+}
+
+impl RateLimiter {
+    /// Takes a token from the bucket of `client`. When the bucket is empty returns how long the
+    /// client has to wait for the next token.
+    pub fn check(
+        &self,
+        group: RouteGroup,
+        client: &str,
+        limit: &RateLimit,
+    ) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= MAX_TRACKED_BUCKETS {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let bucket = buckets
+            .entry((group, client.to_string()))
+            .or_insert_with(|| Bucket {
+                tokens: limit.burst as f64,
+                last_refill: now,
+                full_at: now,
+            });
+        bucket.refill(limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            let missing = limit.burst as f64 - bucket.tokens;
+            bucket.full_at = if limit.per_second > 0.0 {
+                now + Duration::from_secs_f64(missing / limit.per_second)
+            } else {
+                // the bucket never refills so it has to be remembered
+                now + Duration::from_secs(u32::MAX as u64)
+            };
+            Ok(())
+        } else if limit.per_second > 0.0 {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / limit.per_second,
+            ))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+}
+
+/// Middleware that limits requests of a route group per authenticated user, falling back to the
+/// client address for unauthenticated requests.
+pub async fn rate_limit<B>(
+    State((state, group)): State<(SharedAppState, RouteGroup)>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let limit = match group {
+        RouteGroup::Chat => state.config.rate_limits.chat,
+        RouteGroup::Image => state.config.rate_limits.image,
+        RouteGroup::Users => state.config.rate_limits.users,
+    };
+    let Some(limit) = limit else {
+        return next.run(req).await;
+    };
+
+    let (mut parts, body) = req.into_parts();
+    // most requests without a valid token are refused by their route anyway, they are only
+    // logged there
+    let client = match Claims::decode_from_parts(&mut parts, &state).await {
+        Ok(claims) => format!("user:{}", claims.sub),
+        Err(_) => format!("ip:{}", client_ip(&parts, &state)),
+    };
+    let req = Request::from_parts(parts, body);
+
+    match state.rate_limiter.check(group, &client, &limit) {
+        Ok(_) => next.run(req).await,
+        Err(retry_after) => {
+            log::debug!("rate limit of {group:?} routes exceeded by {client}");
+            retry_later("too many requests", retry_after)
+        }
+    }
+}
+
+/// `429 Too Many Requests` response telling the client to retry after `retry_after`.
+pub fn retry_later(message: &str, retry_after: Duration) -> Response {
+    let retry_after = retry_after.as_secs_f64().ceil().min(u32::MAX as f64) as u32;
+    let mut response = ApiResponse::failure(message).too_many_requests();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after.max(1)));
+    response
+}
+
+/// Address of the client of a request, the same one its rate limit is kept for. Empty when the
+/// server doesn't know it.
+pub struct ClientIp(pub String);
+
+#[async_trait]
+impl FromRequestParts<SharedAppState> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &SharedAppState,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(ClientIp(client_ip(parts, state)))
+    }
+}
+
+fn client_ip(parts: &Parts, state: &SharedAppState) -> String {
+    if state.config.rate_limits.trust_forwarded_for {
+        if let Some(ip) = parts
+            .headers
+            .get("x-forwarded-for")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.split(',').next())
+        {
+            return ip.trim().to_string();
+        }
+    }
+    parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod tests {
+    use crate::testing;
+    use airtifex_core::user::AccountType;
+
+    use axum::http::{Method, StatusCode};
+
+    #[tokio::test]
+    async fn login_is_refused_once_the_burst_is_used_up() {
+        let db = testing::db().await;
+        testing::user(&db, "alice", AccountType::User).await;
+        let config = testing::config("rate_limits:\n  users: { burst: 3, per_second: 0.0 }\n");
+        let router = testing::router(testing::state(db, config));
+        let credentials = serde_json::json!({ "username": "alice", "password": testing::PASSWORD });
+
+        let mut statuses = vec![];
+        for _ in 0..5 {
+            let (status, _) = testing::send(
+                &router,
+                Method::POST,
+                "/api/v1/users/login",
+                None,
+                Some(credentials.clone()),
+            )
+            .await;
+            statuses.push(status);
+        }
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::OK,
+                StatusCode::OK,
+                StatusCode::OK,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::TOO_MANY_REQUESTS,
+            ]
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Ids that correlate the log lines of a request. Every HTTP request gets a new id that is
+//! returned in the `X-Request-Id` header and in the body of failure responses, the log lines of
+//! the request are written in a `request{id=...}` span. Inference and image generation requests
+//! carry the id over to the threads running them.
+
+use crate::id::Uuid;
+
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+tokio::task_local! {
+    static REQUEST_ID: Uuid;
+}
+
+/// Id of the request, stored in the request extensions by [`request_id`].
+#[derive(Copy, Clone, Debug)]
+pub struct RequestId(pub Uuid);
+
+/// Id of the HTTP request being handled by the current task, a new id when the task isn't
+/// handling one like the tasks of WebSockets and batches.
+pub fn current() -> Uuid {
+    REQUEST_ID
+        .try_with(|id| *id)
+        .unwrap_or_else(|_| Uuid::new_v4())
+}
+
+/// Id of the HTTP request being handled by the current task, if any.
+pub fn try_current() -> Option<Uuid> {
+    REQUEST_ID.try_with(|id| *id).ok()
+}
+
+/// Span the log lines of the request with `id` are written in.
+pub fn span(id: Uuid) -> tracing::Span {
+    tracing::info_span!("request", id = %id)
+}
+
+/// Assigns a new id to the request, it has to wrap the tracing layer so that the span of the
+/// request includes the id.
+pub async fn request_id<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let id = Uuid::new_v4();
+    req.extensions_mut().insert(RequestId(id));
+    let mut response = REQUEST_ID.scope(id, next.run(req)).await;
+    if let Ok(value) = HeaderValue::from_str(&id.to_string()) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
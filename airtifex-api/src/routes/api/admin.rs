@@ -0,0 +1,288 @@
+use crate::{
+    auth::{generate_impersonation_jwt, Claims},
+    gen::llm::{self, reload::ReloadError},
+    models::{
+        audit::AuditEntry,
+        stats::{duration_percentiles, InferenceStat, RecordCounts},
+        user::User,
+    },
+    SharedAppState, ToAxumResponse,
+};
+use airtifex_core::{
+    admin::{
+        ImpersonationResponse, InferenceStatsQuery, InferenceStatsResponse, LlmQueueClearRequest,
+        LlmQueueClearResponse, LlmReloadRequest, LlmReloadResponse, SystemStats,
+    },
+    api_response::ApiResponse,
+    audit::AuditAction,
+    user::AccountType,
+};
+
+use axum::{
+    extract::{Json, Path, Query, State},
+    response::Response,
+    routing, Router,
+};
+use std::path::PathBuf;
+
+pub fn router() -> Router<SharedAppState> {
+    Router::new()
+        .route("/stats", routing::get(stats))
+        .route("/llm/reload", routing::post(reload_llm))
+        .route("/llm/queue/clear", routing::post(clear_llm_queue))
+        .route("/llm/stats", routing::get(llm_stats))
+        .route("/users/:username/impersonate", routing::post(impersonate))
+}
+
+/// Window of the inference stats when the query doesn't set one.
+const DEFAULT_STATS_HOURS: u32 = 24;
+/// Longest window of the inference stats, a year.
+const MAX_STATS_HOURS: u32 = 24 * 366;
+
+/// Returns the record counts of the database together with the current inference load.
+async fn stats(claims: Claims, state: State<SharedAppState>) -> Response {
+    let db = &state.db;
+    with_admin_guard!(claims, db);
+
+    let since = chrono::Utc::now() - chrono::Duration::hours(24);
+    let counts = match RecordCounts::get(db, since).await {
+        Ok(counts) => counts,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let (inference_queue_depth, running_sessions) = state.metrics.inference_load();
+
+    ApiResponse::success(SystemStats {
+        total_users: counts.users,
+        total_images: counts.images,
+        total_chat_messages: counts.chat_messages,
+        images_last_day: counts.images_since,
+        inference_queue_depth,
+        running_sessions,
+    })
+    .ok()
+}
+
+/// Replaces the weights of a language model without a restart. New requests use the new weights
+/// once they are loaded, the response is sent after the sessions running on the previous weights
+/// finished. The load progress is reported by the load status route.
+async fn reload_llm(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Json(request): Json<LlmReloadRequest>,
+) -> Response {
+    let db = &state.db;
+    with_admin_guard!(claims, db);
+
+    let Some(reloader) = state.llm_reloaders.get(&request.model) else {
+        return ApiResponse::failure(format!("model {} doesn't exist", request.model)).not_found();
+    };
+    let model_path = PathBuf::from(&request.model_path);
+    if !model_path.is_file() {
+        return ApiResponse::failure(format!("{} is not a file", request.model_path)).bad_request();
+    }
+
+    match reloader.reload(model_path).await {
+        Ok(drained_sessions) => {
+            let target = format!("{} from {}", request.model, request.model_path);
+            AuditEntry::record(db, &claims, AuditAction::LlmReloaded, Some(&target)).await;
+            ApiResponse::success(LlmReloadResponse {
+                model: request.model,
+                model_path: request.model_path,
+                drained_sessions,
+            })
+            .ok()
+        }
+        Err(e @ ReloadError::InProgress(_)) => ApiResponse::failure(e).conflict(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+/// Aborts the requests waiting for any of the language models, every waiting client receives an
+/// `aborted` end of its answer. With `cancel_running` the answers that are being generated are
+/// stopped too.
+async fn clear_llm_queue(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Json(request): Json<LlmQueueClearRequest>,
+) -> Response {
+    let db = &state.db;
+    with_admin_guard!(claims, db);
+
+    let mut response = LlmQueueClearResponse::default();
+    for (model, tx_commands) in &state.llm_commands {
+        match llm::clear_queue(tx_commands, request.cancel_running).await {
+            Some(cleared) => {
+                response.cleared += cleared.cleared;
+                response.cancelled += cleared.cancelled;
+            }
+            None => {
+                log::warn!("failed to clear the queue of {model}, its inference thread stopped")
+            }
+        }
+    }
+
+    let target = format!(
+        "{} waiting requests, {} running sessions",
+        response.cleared, response.cancelled
+    );
+    AuditEntry::record(db, &claims, AuditAction::LlmQueueCleared, Some(&target)).await;
+    ApiResponse::success(response).ok()
+}
+
+/// Returns the percentiles of the time inference requests waited in the queue and took to
+/// generate over the last `hours`.
+async fn llm_stats(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Query(query): Query<InferenceStatsQuery>,
+) -> Response {
+    let db = &state.db;
+    with_admin_guard!(claims, db);
+
+    let hours = query.hours.unwrap_or(DEFAULT_STATS_HOURS);
+    if !(1..=MAX_STATS_HOURS).contains(&hours) {
+        return ApiResponse::failure(format!("hours must be between 1 and {MAX_STATS_HOURS}"))
+            .bad_request();
+    }
+    let since = chrono::Utc::now() - chrono::Duration::hours(hours.into());
+    let durations = match InferenceStat::list_durations(db, query.model.as_deref(), since).await {
+        Ok(durations) => durations,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let (mut queue_waits, mut generation_times): (Vec<_>, Vec<_>) = durations.into_iter().unzip();
+
+    ApiResponse::success(InferenceStatsResponse {
+        requests: queue_waits.len(),
+        queue_wait: duration_percentiles(&mut queue_waits),
+        generation: duration_percentiles(&mut generation_times),
+    })
+    .ok()
+}
+
+/// Issues a short lived access token acting as the user so that an admin can see what the user
+/// sees. The token marks the admin as the impersonator, the audit entries of the actions taken
+/// with it name the admin as well. Admins can't be impersonated.
+async fn impersonate(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(username): Path<String>,
+) -> Response {
+    let db = &state.db;
+    without_impersonation!(claims, db, "impersonation");
+    with_admin_guard!(claims, db);
+
+    let target = match User::get(db, &username).await {
+        Ok(target) => target,
+        Err(e) => return ApiResponse::failure(e).not_found(),
+    };
+    if target.account_type == AccountType::Admin {
+        return ApiResponse::failure("admins can't be impersonated").forbidden();
+    }
+
+    match generate_impersonation_jwt(&target.username, target.account_type, &claims.sub) {
+        Ok((token, expires_at)) => {
+            log::warn!("{} started acting as {}", claims.sub, target.username);
+            AuditEntry::record(
+                db,
+                &claims,
+                AuditAction::ImpersonationStarted,
+                Some(&target.username),
+            )
+            .await;
+            ApiResponse::success(ImpersonationResponse {
+                token,
+                username: target.username,
+                expires_at,
+            })
+            .ok()
+        }
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    use axum::http::{header, Method, Request, StatusCode};
+
+    /// Claims of `token` the way the routes decode them.
+    async fn claims(state: &SharedAppState, token: &str) -> Claims {
+        let (mut parts, _) = Request::builder()
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(())
+            .unwrap()
+            .into_parts();
+        Claims::decode_from_parts(&mut parts, state).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn impersonation_tokens_name_the_admin() {
+        let db = testing::db().await;
+        let alice = testing::user(&db, "alice", AccountType::Admin).await;
+        let bob = testing::user(&db, "bob", AccountType::User).await;
+        testing::user(&db, "carol", AccountType::Admin).await;
+        let state = testing::state(db, testing::config(""));
+        let router = testing::router(state.clone());
+        let impersonate = |username: &str| format!("/api/v1/admin/users/{username}/impersonate");
+
+        let (status, body) = testing::send(
+            &router,
+            Method::POST,
+            &impersonate("carol"),
+            Some(&testing::token(&alice)),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["data"], "admins can't be impersonated");
+
+        let (status, body) = testing::send(
+            &router,
+            Method::POST,
+            &impersonate("bob"),
+            Some(&testing::token(&alice)),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["username"], "bob");
+        let token = body["data"]["token"].as_str().unwrap().to_string();
+        let claims = claims(&state, &token).await;
+        assert_eq!(
+            (claims.sub.as_str(), claims.impersonator.as_deref()),
+            ("bob", Some("alice"))
+        );
+        assert_eq!(claims.role, AccountType::User.as_ref());
+
+        // the impersonated user can't impersonate, neither on its own nor with the admin
+        for token in [testing::token(&bob), token] {
+            let (status, _) = testing::send(
+                &router,
+                Method::POST,
+                &impersonate("bob"),
+                Some(&token),
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::FORBIDDEN);
+        }
+
+        let entries = AuditEntry::list(&state.db, None, None, None).await.unwrap();
+        let has_entry = |action, actor, impersonator| {
+            entries.iter().any(|entry| {
+                entry.action == action
+                    && entry.actor_user_id == Some(actor)
+                    && entry.impersonator_user_id == impersonator
+            })
+        };
+        assert!(has_entry(AuditAction::ImpersonationStarted, alice.id, None));
+        assert!(has_entry(AuditAction::AuthorizationFailed, bob.id, None));
+        assert!(has_entry(
+            AuditAction::AuthorizationFailed,
+            bob.id,
+            Some(alice.id)
+        ));
+    }
+}
@@ -0,0 +1,43 @@
+use crate::{
+    auth::Claims, errors::Error, models::audit::AuditEntry, routes::handle_db_result_as_json,
+    SharedAppState,
+};
+use airtifex_core::audit::{AuditEntryInspect, AuditListQuery};
+
+use axum::{
+    extract::{Query, State},
+    response::Response,
+    routing, Router,
+};
+
+pub fn router() -> Router<SharedAppState> {
+    Router::new().route("/", routing::get(list))
+}
+
+async fn list(
+    claims: Claims,
+    state: State<SharedAppState>,
+    query: Query<AuditListQuery>,
+) -> Response {
+    let db = &state.db;
+    with_admin_guard!(claims, db);
+
+    handle_db_result_as_json(
+        AuditEntry::list(db, query.page, query.page_size, query.action)
+            .await
+            .map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|entry| AuditEntryInspect {
+                        id: entry.id.to_string(),
+                        actor_user_id: entry.actor_user_id.map(|id| id.to_string()),
+                        impersonator_user_id: entry.impersonator_user_id.map(|id| id.to_string()),
+                        action: entry.action,
+                        target: entry.target,
+                        timestamp: entry.timestamp,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .map_err(Error::from),
+    )
+}
@@ -0,0 +1,125 @@
+use crate::{
+    auth::Claims,
+    gen::llm::batch::{batch_concurrency, run_batch},
+    id::Uuid,
+    models::{
+        batch::{Batch, BatchEntry},
+        user::User,
+    },
+    moderation,
+    routes::handle_db_result_as_json,
+    validation::validate_batch_request,
+    Error, SharedAppState, ToAxumResponse,
+};
+use airtifex_core::{
+    api_response::{ApiResponse, ErrorCode},
+    llm::{BatchEntryInspect, BatchInspect, BatchRequest, BatchStartResponse},
+};
+
+use axum::{
+    extract::{Json, Path, State},
+    response::Response,
+    routing, Router,
+};
+use rand::Rng;
+
+pub fn router() -> Router<SharedAppState> {
+    Router::new()
+        .route("/batch", routing::post(start_batch))
+        .route("/batch/:id", routing::get(get_batch))
+}
+
+/// Queues the prompts of the request in the background, the answers are collected in the
+/// returned batch.
+async fn start_batch(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Json(mut request): Json<BatchRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    match User::get_settings(db, &claims.sub).await {
+        Ok(settings) => request.params = request.params.with_defaults(&settings.chat),
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    }
+
+    if let Err(e) = validate_batch_request(&state.config.request_limits.inference, &request) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let prompts = request.prompts.iter().map(String::as_str);
+    if let Err(e) = moderation::check_all(state.moderator.as_ref(), prompts) {
+        return ApiResponse::failure(&e)
+            .with_code(e.code())
+            .unprocessable_entity();
+    }
+    let Some((llm_config, tx_model)) = state.tx_inference_req.get(&request.model) else {
+        return ApiResponse::failure(format!("failed to find model {}", request.model))
+            .with_code(ErrorCode::ModelUnavailable)
+            .bad_request();
+    };
+
+    let seed = request
+        .identical_seeds
+        .then(|| request.seed.unwrap_or_else(|| rand::thread_rng().gen()));
+    let batch = Batch::new(claims.sub, request.model, seed);
+    if let Err(e) = batch.create(db, &request.prompts).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+    let batch_id = batch.id().to_string();
+
+    tokio::spawn(run_batch(
+        db.clone(),
+        llm_config.clone(),
+        tx_model.clone(),
+        batch,
+        request.prompts,
+        request.params,
+        batch_concurrency(llm_config.max_inference_sessions),
+        state.webhooks.clone(),
+    ));
+
+    ApiResponse::success(BatchStartResponse { batch_id }).ok()
+}
+
+/// Returns the state of every prompt of the batch, answers are available as soon as their
+/// prompt is done.
+async fn get_batch(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let batch = match Batch::get_batch_for_user(db, &claims.sub, &id).await {
+        Ok(batch) => batch,
+        Err(e) => return ApiResponse::failure(e).not_found(),
+    };
+
+    handle_db_result_as_json(
+        BatchEntry::list(db, &id)
+            .await
+            .map(|entries| {
+                let entries = entries
+                    .into_iter()
+                    .map(|e| BatchEntryInspect {
+                        n: e.n,
+                        prompt: e.prompt,
+                        status: e.status,
+                        response: e.response,
+                        error: e.error,
+                    })
+                    .collect::<Vec<_>>();
+                BatchInspect {
+                    id: batch.id.to_string(),
+                    model: batch.model,
+                    seed: batch.seed.map(|s| s as u64),
+                    create_date: batch.create_date,
+                    finished: entries.iter().filter(|e| !e.status.is_processing()).count(),
+                    entries,
+                }
+            })
+            .map_err(Error::from),
+    )
+}
@@ -1,105 +1,572 @@
 use crate::{
     auth::Claims,
-    gen::llm::{ChatData, InferenceRequest},
+    gen::llm::{
+        chunk::ChunkBuffer, load::llm_load_status, token_channel, ChatData, InferenceRequest,
+        ResponseAnswer,
+    },
     id::Uuid,
-    models::{chat::Chat, chat_entry::ChatEntry, llm::LargeLanguageModel},
-    routes::handle_db_result_as_json,
-    Error, SharedAppState, ToAxumResponse,
+    models::{
+        chat::{Chat, ChatSummary},
+        chat_entry::ChatEntry,
+        llm::LargeLanguageModel,
+        user::User,
+    },
+    moderation,
+    queue::{QueueError, QueuePosition},
+    request_id,
+    routes::{
+        api::{claim_idempotency_key, finish_idempotency_key, queue_events, user_id, Idempotency},
+        handle_db_result_as_json, share_url,
+    },
+    share::ChatShareToken,
+    validation::{
+        validate_chat_list_query, validate_chat_prompt, validate_inference_settings,
+        validate_n_threads, validate_repeat_last_n, validate_system_prompt_update,
+    },
+    DbPool, Error, SharedAppState, ToAxumResponse,
 };
 use airtifex_core::{
     api_response::ApiResponse,
     llm::{
-        ChatEntryListEntry, ChatListEntry, ChatResponseRequest, ChatStartRequest,
-        ChatStartResponse, ChatStreamResult, InferenceSettings, LlmListEntry,
+        ChatContextTurnsUpdateRequest, ChatDeleteResponse, ChatEntryEditRequest,
+        ChatEntryListEntry, ChatEntryType, ChatForkQuery, ChatListEntry, ChatListPage,
+        ChatListQuery, ChatOverview, ChatResponseRequest, ChatSearchQuery, ChatSearchResult,
+        ChatShareRequest, ChatShareResponse, ChatStartRequest, ChatStartResponse,
+        ChatStreamMessage, ChatStreamQuery, ChatSystemPromptUpdateRequest, ChatWsClientMessage,
+        ChatWsQuery, ChatWsServerMessage, InferenceSettings, LlmListEntry, StreamGranularity,
     },
+    user::{AccountType, AuthenticatedUser},
 };
 
 use axum::{
-    body::StreamBody,
-    extract::{Json, Path, State},
-    response::{IntoResponse, Response},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Json, Path, Query, State,
+    },
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     routing, Router,
 };
+use futures_util::StreamExt;
+use std::collections::VecDeque;
+use tokio::sync::watch;
 
 pub fn router() -> Router<SharedAppState> {
     Router::new()
         .route("/models", routing::get(list_models))
+        .route("/load-status", routing::get(load_status))
         .route("/chat", routing::post(start_chat).get(list))
         .route("/chat/counters", routing::get(counters))
+        .route("/chat/search", routing::get(search))
         .route(
             "/chat/:id",
             routing::get(get_chat).delete(delete_chat).post(inference),
         )
         .route("/chat/:id/history", routing::get(get_chat_history))
+        .route(
+            "/chat/:id/entries/:entry_id",
+            routing::patch(edit_entry).delete(delete_entry),
+        )
+        .route("/chat/:id/fork", routing::post(fork_chat))
+        .route(
+            "/chat/:id/share",
+            routing::post(share_chat).delete(revoke_chat_shares),
+        )
+        .route("/chat/:id/stream", routing::get(resume_stream))
+        .route("/chat/:id/ws", routing::get(chat_ws))
+        .route(
+            "/chat/:id/system_prompt",
+            routing::post(update_system_prompt),
+        )
+        .route(
+            "/chat/:id/context_turns",
+            routing::post(update_context_turns),
+        )
 }
 
+/// Answers a prompt sent to the chat. A retry with the `Idempotency-Key` of a previous request
+/// streams the latest answer of the chat instead of sending the prompt again.
 async fn inference(
     claims: Claims,
     State(state): State<SharedAppState>,
     Path(id): Path<Uuid>,
+    headers: HeaderMap,
     Json(request): Json<ChatResponseRequest>,
 ) -> Response {
     let db = &state.db;
     with_user_guard!(claims, db);
 
-    let (tx_tokens, rx_tokens): (
-        flume::Sender<ChatStreamResult>,
-        flume::Receiver<ChatStreamResult>,
-    ) = flume::unbounded();
-
-    let history = match Chat::list_entries(db, &id, &claims.sub).await {
-        Ok(chat) => chat,
-        Err(e) => {
-            return ApiResponse::failure(e).internal_server_error();
+    if let Err(e) = validate_chat_prompt(&state.config.request_limits.inference, &request) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let granularity = request.stream_granularity;
+    let scope = format!("chat/{id}");
+    let key = match claim_idempotency_key(&state, &headers, user_id, &scope, &request).await {
+        Ok(Idempotency::Process(key)) => key,
+        Ok(Idempotency::Replay(_)) => {
+            return latest_answer_events(&state, &claims.sub, &id, 0, granularity).await;
         }
+        Err(response) => return response,
     };
 
-    let chat = match Chat::get_chat_for_user(db, &claims.sub, &id).await {
-        Ok(chat) => chat,
-        Err(e) => {
-            return ApiResponse::failure(e).internal_server_error();
+    let (queue, rx_tokens) =
+        match send_chat_inference_request(&state, &claims.sub, &id, request, None).await {
+            Ok(sent) => sent,
+            Err(e) => {
+                finish_idempotency_key(db, key, None).await;
+                let response = ApiResponse::failure(&e).with_code(e.code());
+                return match e {
+                    Error::ValidationError(_) => response.bad_request(),
+                    Error::ContentBlocked(_) => response.unprocessable_entity(),
+                    Error::InferenceRequestSend(QueueError::Full) => response.service_unavailable(),
+                    _ => response.internal_server_error(),
+                };
+            }
+        };
+
+    // the answer is collected independently of this response so that the generation continues
+    // when the client disconnects and can be resumed with `resume_stream`
+    let rx_answer = state.chat_streams.start(id, rx_tokens, queue);
+    finish_idempotency_key(db, key, Some(&id)).await;
+    answer_events(rx_answer, 0, granularity)
+}
+
+/// Replaces the content of a prompt of the chat and answers it again, the entries after the
+/// prompt are removed. The answer is streamed like the one of [`inference`].
+async fn edit_entry(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path((id, entry_id)): Path<(Uuid, Uuid)>,
+    Json(request): Json<ChatEntryEditRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let request = ChatResponseRequest {
+        prompt: request.content,
+        stream_granularity: request.stream_granularity,
+        ..Default::default()
+    };
+    let granularity = request.stream_granularity;
+    if let Err(e) = validate_chat_prompt(&state.config.request_limits.inference, &request) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    // the edited prompt is saved before it is answered
+    if let Err(e) = moderation::check_all(state.moderator.as_ref(), [request.prompt.as_str()]) {
+        return ApiResponse::failure(&e)
+            .with_code(e.code())
+            .unprocessable_entity();
+    }
+    // the running response would be saved after the removed entries
+    if is_answering(&state, &id) {
+        return ApiResponse::failure("a response of the chat is still being generated").conflict();
+    }
+
+    let entries = match ChatEntry::get_chat_entries(db, &id, &claims.sub).await {
+        Ok(entries) => entries,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let Some(entry) = entries.into_iter().find(|e| e.entry_id == entry_id) else {
+        return ApiResponse::failure(format!("entry {entry_id} doesn't belong to chat {id}"))
+            .not_found();
+    };
+    if entry.entry_type != ChatEntryType::User {
+        return ApiResponse::failure("only prompts of the user can be edited").bad_request();
+    }
+    if let Err(e) = ChatEntry::edit(db, &entry, &request.prompt).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+
+    let (queue, rx_tokens) =
+        match send_chat_inference_request(&state, &claims.sub, &id, request, Some(&entry_id)).await
+        {
+            Ok(sent) => sent,
+            Err(e) => {
+                return ApiResponse::failure(&e)
+                    .with_code(e.code())
+                    .internal_server_error()
+            }
+        };
+
+    let rx_answer = state.chat_streams.start(id, rx_tokens, queue);
+    answer_events(rx_answer, 0, granularity)
+}
+
+/// Resumes the stream of the latest response of a chat after the number of characters given by
+/// the `Last-Event-ID` header or the `offset` query. When the response isn't kept in memory
+/// anymore the saved answer is replayed instead.
+async fn resume_stream(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ChatStreamQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(e) = Chat::get_chat_for_user(db, &claims.sub, &id).await {
+        return ApiResponse::failure(e).bad_request();
+    }
+
+    let offset = headers
+        .get("last-event-id")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.trim().parse().ok())
+        .or(query.offset)
+        .unwrap_or_default();
+
+    latest_answer_events(&state, &claims.sub, &id, offset, query.stream_granularity).await
+}
+
+/// Streams the latest answer of a chat after `offset` characters, the answer being generated or
+/// else the saved one.
+async fn latest_answer_events(
+    state: &SharedAppState,
+    username: &str,
+    id: &Uuid,
+    offset: usize,
+    granularity: StreamGranularity,
+) -> Response {
+    if let Some(rx_answer) = state.chat_streams.get(id) {
+        return answer_events(rx_answer, offset, granularity);
+    }
+
+    match ChatEntry::get_last_bot_entry(&state.db, id, username).await {
+        Ok(Some(entry)) => {
+            let (_, rx_answer) = watch::channel(ResponseAnswer {
+                content: entry.content,
+                is_finished: true,
+                ..Default::default()
+            });
+            answer_events(rx_answer, offset, granularity)
         }
+        Ok(None) => ApiResponse::failure("chat has no response to resume").bad_request(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+/// Streams an answer as server-sent events starting after the first `offset` characters. The
+/// answer is preceded by the [`queue_events`] of the request. The data of the events is a
+/// [`ChatStreamMessage`], the id of each event is the number of characters sent up to and
+/// including it. The tokens are grouped into events with the granularity, the stream ends with
+/// the rest of the answer, its `usage` and a `done` event, or with an `error` event.
+fn answer_events(
+    rx_answer: watch::Receiver<ResponseAnswer>,
+    offset: usize,
+    granularity: StreamGranularity,
+) -> Response {
+    let queue = rx_answer.borrow().queue.clone();
+    let events = futures_util::stream::unfold(Some((rx_answer, offset)), move |state| async move {
+        let (mut rx_answer, offset) = state?;
+        loop {
+            let (content, end, last_messages) = {
+                let answer = rx_answer.borrow_and_update();
+                let (content, end) = answer.chunk_since(offset, granularity);
+                (
+                    content,
+                    end,
+                    answer.is_finished.then(|| last_messages(&answer)),
+                )
+            };
+            if !content.is_empty() {
+                let event = answer_event(ChatStreamMessage::Token { content }, end);
+                return Some((vec![event], Some((rx_answer, end))));
+            }
+            if let Some(messages) = last_messages {
+                let events = messages
+                    .into_iter()
+                    .map(|message| answer_event(message, end))
+                    .collect();
+                return Some((events, None));
+            }
+            if rx_answer.changed().await.is_err() {
+                return None;
+            }
+        }
+    })
+    .flat_map(futures_util::stream::iter);
+
+    Sse::new(queue_events(queue).chain(events))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Messages that end a finished answer.
+fn last_messages(answer: &ResponseAnswer) -> Vec<ChatStreamMessage> {
+    if let Some(message) = &answer.error {
+        return vec![ChatStreamMessage::Error {
+            message: message.clone(),
+        }];
+    }
+    answer
+        .generated_tokens
+        .map(|generated_tokens| ChatStreamMessage::Usage { generated_tokens })
+        .into_iter()
+        .chain([ChatStreamMessage::Done {
+            reason: answer.stop_reason,
+        }])
+        .collect()
+}
+
+/// Tokens are sent as unnamed events, the other messages as events named after their type.
+fn answer_event(message: ChatStreamMessage, id: usize) -> Result<Event, serde_json::Error> {
+    let event = match &message {
+        ChatStreamMessage::Token { .. } => Event::default(),
+        ChatStreamMessage::Usage { .. } => Event::default().event("usage"),
+        ChatStreamMessage::Done { .. } => Event::default().event("done"),
+        ChatStreamMessage::Error { .. } => Event::default().event("error"),
     };
+    event.id(id.to_string()).json_data(message)
+}
 
+/// Queues the next turn of a chat for inference. The chat settings and history are loaded on
+/// every call so that changes made in the meantime only apply to the following responses.
+/// `saved_prompt` is the entry of a prompt that is answered again, the history ends before it.
+async fn send_chat_inference_request(
+    state: &SharedAppState,
+    username: &str,
+    id: &Uuid,
+    request: ChatResponseRequest,
+    saved_prompt: Option<&Uuid>,
+) -> Result<(QueuePosition, flume::Receiver<ChatStreamMessage>), Error> {
+    let db = &state.db;
+    let mut history = Chat::list_entries(db, id, username).await?;
+    if let Some(position) =
+        saved_prompt.and_then(|entry_id| history.iter().position(|e| e.entry_id == *entry_id))
+    {
+        history.truncate(position);
+    }
+    let chat = Chat::get_chat_for_user(db, username, id).await?;
+    let Some((llm_config, tx_model)) = state.tx_inference_req.get(&chat.model) else {
+        return Err(Error::ModelNotFound(chat.model));
+    };
+    validate_repeat_last_n(request.repeat_last_n, llm_config.num_ctx_tokens)?;
+    validate_n_threads(request.n_threads, llm_config.num_threads)?;
+    let system_prompt = request
+        .system_prompt
+        .as_deref()
+        .filter(|p| !p.trim().is_empty())
+        .or(chat.system_prompt.as_deref());
+    moderation::check_all(
+        state.moderator.as_ref(),
+        std::iter::once(request.prompt.as_str()).chain(system_prompt),
+    )?;
+
+    let (tx_tokens, rx_tokens) = token_channel(llm_config);
     let request = InferenceRequest {
         tx_tokens,
-        user: claims.sub,
+        user: username.to_string(),
         save: true,
         chat_data: Some(ChatData {
-            conversation_id: id,
+            conversation_id: *id,
             history,
+            is_prompt_saved: saved_prompt.is_some(),
+            context_turns: chat.context_turns.map(|n| n as usize),
         }),
         prompt: request.prompt,
         settings: InferenceSettings {
             num_predict: chat.num_predict.map(|k| k as usize),
-            system_prompt: chat.system_prompt,
+            system_prompt: request
+                .system_prompt
+                .filter(|p| !p.trim().is_empty())
+                .or(chat.system_prompt),
             n_batch: chat.n_batch.map(|k| k as usize),
             top_k: chat.top_k.map(|k| k as usize),
             top_p: chat.top_p,
             repeat_penalty: chat.repeat_penalty,
             temp: chat.temp,
+            mirostat: chat.mirostat.map(|m| m as u8),
+            mirostat_tau: chat.mirostat_tau,
+            mirostat_eta: chat.mirostat_eta,
         },
         play_back_tokens: false,
+        template: None,
+        json_schema: request.json_schema,
+        seed: request.seed,
+        repeat_last_n: request.repeat_last_n,
+        n_threads: request.n_threads,
+        no_cache: request.no_cache,
+        queue_ticket: None,
+        request_id: request_id::current(),
     };
     log::info!("{request:?}");
 
-    if let Some((_, model)) = state.tx_inference_req.get(&chat.model) {
-        if let Err(e) = model.send_async(request).await {
-            return ApiResponse::failure(e).internal_server_error();
+    tx_model
+        .send(request)
+        .map(|queue| (queue, rx_tokens))
+        .map_err(Error::from)
+}
+
+async fn chat_ws(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ChatWsQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(e) = Chat::get_chat_for_user(db, &claims.sub, &id).await {
+        return ApiResponse::failure(e).bad_request();
+    }
+
+    ws.on_upgrade(move |socket| handle_chat_socket(socket, state, claims.sub, id, query.queue))
+}
+
+async fn send_ws_message(socket: &mut WebSocket, message: &ChatWsServerMessage) -> bool {
+    match serde_json::to_string(message) {
+        Ok(text) => socket.send(Message::Text(text)).await.is_ok(),
+        Err(e) => {
+            log::error!("failed to serialize websocket message - {e}");
+            false
         }
-    } else {
-        return ApiResponse::failure(format!("failed to find model {}", &chat.model))
-            .internal_server_error();
     }
+}
 
-    (
-        [
-            (axum::http::header::CONTENT_TYPE, "text/event-stream"),
-            (axum::http::header::TRANSFER_ENCODING, "chunked"),
-        ],
-        StreamBody::new(rx_tokens.into_stream()),
-    )
-        .into_response()
+/// Drives a chat WebSocket. Prompts are answered one at a time, a prompt received while a response
+/// is being generated is either queued or rejected depending on `queue_prompts`. Dropping the
+/// token receiver on disconnect makes the inference session stop and save what it generated.
+async fn handle_chat_socket(
+    mut socket: WebSocket,
+    state: SharedAppState,
+    username: String,
+    id: Uuid,
+    queue_prompts: bool,
+) {
+    let mut pending_prompts = VecDeque::<ChatResponseRequest>::new();
+    let mut running: Option<flume::Receiver<ChatStreamMessage>> = None;
+    let mut chunks = ChunkBuffer::default();
+
+    loop {
+        if running.is_none() {
+            if let Some(request) = pending_prompts.pop_front() {
+                let granularity = request.stream_granularity;
+                match send_chat_inference_request(&state, &username, &id, request, None).await {
+                    Ok((_, rx_tokens)) => {
+                        running = Some(rx_tokens);
+                        chunks = ChunkBuffer::new(granularity);
+                    }
+                    Err(e) => {
+                        let message = ChatWsServerMessage::Error {
+                            message: e.to_string(),
+                        };
+                        if !send_ws_message(&mut socket, &message).await {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+            }
+        }
+
+        let next_message = async {
+            match &running {
+                Some(rx_tokens) => rx_tokens.recv_async().await.ok(),
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    match serde_json::from_str::<ChatWsClientMessage>(&text) {
+                        Ok(ChatWsClientMessage::Prompt {
+                            prompt,
+                            system_prompt,
+                            json_schema,
+                            seed,
+                            repeat_last_n,
+                            n_threads,
+                            no_cache,
+                            stream_granularity,
+                        }) => {
+                            let request = ChatResponseRequest {
+                                prompt,
+                                system_prompt,
+                                json_schema,
+                                seed,
+                                repeat_last_n,
+                                n_threads,
+                                no_cache,
+                                stream_granularity,
+                            };
+                            let limits = &state.config.request_limits.inference;
+                            let error = if running.is_some() && !queue_prompts {
+                                Some("a response is still being generated".to_string())
+                            } else {
+                                validate_chat_prompt(limits, &request).err().map(|e| e.to_string())
+                            };
+                            if let Some(message) = error {
+                                let message = ChatWsServerMessage::Error { message };
+                                if !send_ws_message(&mut socket, &message).await {
+                                    break;
+                                }
+                            } else {
+                                pending_prompts.push_back(request);
+                            }
+                        }
+                        Err(e) => {
+                            let message = ChatWsServerMessage::Error {
+                                message: format!("invalid message - {e}"),
+                            };
+                            if !send_ws_message(&mut socket, &message).await {
+                                break;
+                            }
+                        }
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            message = next_message => {
+                let message = match message {
+                    Some(ChatWsServerMessage::Token { content }) => match chunks.push(&content) {
+                        Some(content) => ChatWsServerMessage::Token { content },
+                        None => continue,
+                    },
+                    Some(message) => {
+                        if matches!(message, ChatWsServerMessage::Done { .. }) {
+                            running = None;
+                        }
+                        message
+                    }
+                    None => {
+                        // the inference session dropped its sender without ending the answer
+                        running = None;
+                        ChatWsServerMessage::Error {
+                            message: "the model stopped answering".to_string(),
+                        }
+                    }
+                };
+                // the held back tokens go out before the answer ends
+                if !matches!(message, ChatWsServerMessage::Token { .. }) {
+                    if let Some(content) = chunks.flush() {
+                        let rest = ChatWsServerMessage::Token { content };
+                        if !send_ws_message(&mut socket, &rest).await {
+                            break;
+                        }
+                    }
+                }
+                if !send_ws_message(&mut socket, &message).await {
+                    break;
+                }
+            }
+        }
+    }
+
+    if running.is_some() {
+        log::debug!("chat {id} websocket closed while a response was being generated");
+    }
+    let _ = socket.close().await;
 }
 
 async fn start_chat(
@@ -122,23 +589,41 @@ async fn start_chat(
             .unwrap_or_default()
     };
 
-    let mut chat = Chat::new(claims.sub, model.clone(), request.title, request.settings);
+    let user_settings = match User::get_settings(db, &claims.sub).await {
+        Ok(settings) => settings,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let mut settings = request.settings.with_defaults(&user_settings.chat);
+    settings.system_prompt = settings.system_prompt.filter(|p| !p.trim().is_empty());
+    if let Err(e) = validate_inference_settings(&state.config.request_limits.inference, &settings) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let mut chat = Chat::new(claims.sub, model.clone(), request.title, settings);
 
     if let Some((config, _)) = state.tx_inference_req.get(&model) {
         if chat.n_batch.is_none() {
-            chat.n_batch = Some(config.batch_size as i32);
+            chat.n_batch = Some(config.batch_size() as i32);
         }
         if chat.top_k.is_none() {
-            chat.top_k = Some(config.top_k as i32);
+            chat.top_k = Some(config.top_k() as i32);
         }
         if chat.top_p.is_none() {
-            chat.top_p = Some(config.top_p);
+            chat.top_p = Some(config.top_p());
         }
         if chat.repeat_penalty.is_none() {
-            chat.repeat_penalty = Some(config.repeat_penalty);
+            chat.repeat_penalty = Some(config.repeat_penalty());
         }
         if chat.temp.is_none() {
-            chat.temp = Some(config.temperature);
+            chat.temp = Some(config.temperature());
+        }
+        if chat.mirostat.is_none() {
+            chat.mirostat = Some(config.mirostat() as i32);
+        }
+        if chat.mirostat_tau.is_none() {
+            chat.mirostat_tau = Some(config.mirostat_tau());
+        }
+        if chat.mirostat_eta.is_none() {
+            chat.mirostat_eta = Some(config.mirostat_eta());
         }
     }
 
@@ -152,38 +637,61 @@ async fn start_chat(
     )
 }
 
-async fn list(claims: Claims, state: State<SharedAppState>) -> Response {
+/// Returns a page of the chats of the user, the most recently updated chat first.
+async fn list(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Query(query): Query<ChatListQuery>,
+) -> Response {
     let db = &state.db;
     with_user_guard!(claims, db);
 
+    if let Err(e) = validate_chat_list_query(&query) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let total = match Chat::count_chats_of_user(db, &claims.sub).await {
+        Ok(total) => total as u64,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
     handle_db_result_as_json(
-        Chat::list_chats_of_user(db, &claims.sub)
+        Chat::list_summaries_of_user(db, &claims.sub, query.page, query.page_size)
             .await
-            .map(|entries| {
-                entries
-                    .into_iter()
-                    .map(|chat| ChatListEntry {
-                        id: chat.id.to_string(),
-                        title: chat.title,
-                        username: chat.username,
-                        start_date: chat.start_date,
-                        model: chat.model,
-                        settings: airtifex_core::llm::InferenceSettings {
-                            num_predict: chat.num_predict.map(|n| n as usize),
-                            system_prompt: chat.system_prompt,
-                            n_batch: chat.n_batch.map(|n| n as usize),
-                            top_k: chat.top_k.map(|n| n as usize),
-                            top_p: chat.top_p,
-                            repeat_penalty: chat.repeat_penalty,
-                            temp: chat.temp,
-                        },
-                    })
-                    .collect::<Vec<_>>()
+            .map(|chats| ChatListPage {
+                chats: chats.into_iter().map(chat_overview).collect(),
+                total,
             })
             .map_err(Error::from),
     )
 }
 
+/// Number of characters of the latest message shown in the list of chats.
+const PREVIEW_LENGTH: usize = 120;
+
+fn chat_overview(chat: ChatSummary) -> ChatOverview {
+    ChatOverview {
+        id: chat.id.to_string(),
+        last_updated: chat.last_updated(),
+        unread: chat.is_unread(),
+        message_count: chat.message_count as u64,
+        last_entry_type: chat.last_entry_type,
+        last_message: chat.last_entry_content.as_deref().map(message_preview),
+        title: chat.title,
+        model: chat.model,
+        start_date: chat.start_date,
+    }
+}
+
+/// The beginning of the message on a single line.
+fn message_preview(content: &str) -> String {
+    let line = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() <= PREVIEW_LENGTH {
+        return line;
+    }
+    let mut preview: String = line.chars().take(PREVIEW_LENGTH).collect();
+    preview.push_str("...");
+    preview
+}
+
 async fn get_chat(claims: Claims, state: State<SharedAppState>, Path(id): Path<Uuid>) -> Response {
     let db = &state.db;
     with_user_guard!(claims, db);
@@ -205,7 +713,11 @@ async fn get_chat(claims: Claims, state: State<SharedAppState>, Path(id): Path<U
                     top_p: chat.top_p,
                     repeat_penalty: chat.repeat_penalty,
                     temp: chat.temp,
+                    mirostat: chat.mirostat.map(|m| m as u8),
+                    mirostat_tau: chat.mirostat_tau,
+                    mirostat_eta: chat.mirostat_eta,
                 },
+                context_turns: chat.context_turns.map(|n| n as usize),
             })
             .map_err(Error::from),
     )
@@ -219,17 +731,24 @@ async fn get_chat_history(
     let db = &state.db;
     with_user_guard!(claims, db);
 
+    let entries = ChatEntry::get_chat_entries(db, &id, &claims.sub).await;
+    if entries.is_ok() {
+        if let Err(e) = Chat::mark_read(db, &id, &claims.sub).await {
+            log::error!("[{id}] failed to mark the chat as read - {e}");
+        }
+    }
     handle_db_result_as_json(
-        ChatEntry::get_chat_entries(db, &id, &claims.sub)
-            .await
+        entries
             .map(|entries| {
                 entries
                     .into_iter()
                     .map(|e| ChatEntryListEntry {
                         id: e.entry_id.to_string(),
                         chat_id: e.chat_id.to_string(),
+                        params: e.params(),
                         content: e.content,
                         entry_type: e.entry_type,
+                        seed: e.seed.map(|s| s as u64),
                     })
                     .collect::<Vec<_>>()
             })
@@ -237,6 +756,210 @@ async fn get_chat_history(
     )
 }
 
+/// Starts a new chat from the history of chat `:id` up to and including entry `at`.
+async fn fork_chat(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ChatForkQuery>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let Ok(entry_id) = query.at.parse::<Uuid>() else {
+        return ApiResponse::failure(format!("invalid entry id `{}`", query.at)).bad_request();
+    };
+
+    let chat = match Chat::get_chat_for_user(db, &claims.sub, &id).await {
+        Ok(chat) => chat,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let entries = match ChatEntry::get_chat_entries(db, &id, &claims.sub).await {
+        Ok(entries) => entries,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let Some(position) = entries.iter().position(|e| e.entry_id == entry_id) else {
+        return ApiResponse::failure(format!("entry {entry_id} doesn't belong to chat {id}"))
+            .bad_request();
+    };
+    let entry_ids = entries[..=position]
+        .iter()
+        .map(|e| e.entry_id)
+        .collect::<Vec<_>>();
+
+    let title = format!("{} (branch)", chat.title);
+    handle_db_result_as_json(
+        chat.fork(db, &title, &entry_ids)
+            .await
+            .map(|fork| ChatStartResponse {
+                chat_id: fork.id().to_string(),
+            })
+            .map_err(Error::from),
+    )
+}
+
+/// Creates a link to a read-only transcript of the chat that can be opened without an account
+/// until it expires or the links of the chat are revoked.
+async fn share_chat(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<ChatShareRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(e) = Chat::get_chat_for_user(db, &claims.sub, &id).await {
+        return ApiResponse::failure(e).not_found();
+    }
+    let generation = match Chat::share_generation(db, &id).await {
+        Ok(generation) => generation,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+
+    let config = &state.config;
+    let expires = chrono::Utc::now() + chrono::Duration::seconds(config.chat_share.expiry);
+    let token = ChatShareToken::new(id, request.include_system_prompt, expires.timestamp())
+        .sign(&config.jwt_secret, generation);
+
+    ApiResponse::success(ChatShareResponse {
+        url: share_url(config, &config.chat_share, &format!("chat/{token}")),
+        expires,
+    })
+    .ok()
+}
+
+/// Invalidates all outstanding share links of the chat.
+async fn revoke_chat_shares(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(e) = Chat::get_chat_for_user(db, &claims.sub, &id).await {
+        return ApiResponse::failure(e).not_found();
+    }
+    handle_db_result_as_json(
+        Chat::revoke_shares(db, &id, &claims.sub)
+            .await
+            .map_err(Error::from),
+    )
+}
+
+async fn update_system_prompt(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<ChatSystemPromptUpdateRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(e) = validate_system_prompt_update(&state.config.request_limits.inference, &request)
+    {
+        return ApiResponse::failure(e).bad_request();
+    }
+    if let Err(e) = Chat::get_chat_for_user(db, &claims.sub, &id).await {
+        return ApiResponse::failure(e).not_found();
+    }
+    let system_prompt = request
+        .system_prompt
+        .as_deref()
+        .filter(|p| !p.trim().is_empty());
+
+    handle_db_result_as_json(
+        Chat::update_system_prompt(db, &id, &claims.sub, system_prompt)
+            .await
+            .map_err(Error::from),
+    )
+}
+
+async fn update_context_turns(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<ChatContextTurnsUpdateRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let Ok(context_turns) = request.context_turns.map(i32::try_from).transpose() else {
+        return ApiResponse::failure("context turns are out of range").bad_request();
+    };
+
+    handle_db_result_as_json(
+        Chat::update_context_turns(db, &id, &claims.sub, context_turns)
+            .await
+            .map_err(Error::from),
+    )
+}
+
+/// Number of characters of context displayed around a search match.
+const SEARCH_SNIPPET_CONTEXT: usize = 60;
+
+fn search_snippet(content: &str, query: &str) -> String {
+    let content_lower = content.to_lowercase();
+    let query_lower = query.to_lowercase();
+    // lowercasing can change byte offsets of non-ascii text, so count characters instead
+    let match_start = content_lower
+        .find(&query_lower)
+        .map(|i| content_lower[..i].chars().count())
+        .unwrap_or_default();
+    let match_len = query.chars().count();
+    let start = match_start.saturating_sub(SEARCH_SNIPPET_CONTEXT);
+    let end = match_start + match_len + SEARCH_SNIPPET_CONTEXT;
+
+    let mut snippet: String = content.chars().skip(start).take(end - start).collect();
+    if start > 0 {
+        snippet.insert_str(0, "...");
+    }
+    if content.chars().count() > end {
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+async fn search(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Query(query): Query<ChatSearchQuery>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let q = query.q.trim();
+    if q.is_empty() {
+        return ApiResponse::failure("search query can't be empty").bad_request();
+    }
+
+    handle_db_result_as_json(
+        ChatEntry::search(db, &claims.sub, q)
+            .await
+            .map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|e| ChatSearchResult {
+                        chat_id: e.chat_id.to_string(),
+                        chat_title: e.chat_title,
+                        entry_id: e.entry_id.to_string(),
+                        entry_type: e.entry_type,
+                        snippet: search_snippet(&e.content, q),
+                        entry_date: e.entry_date,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .map_err(Error::from),
+    )
+}
+
+/// Loading progress of the models, available without an account so that the login page can
+/// tell users to wait.
+async fn load_status(State(state): State<SharedAppState>) -> Response {
+    ApiResponse::success(llm_load_status(&state)).ok()
+}
+
 async fn list_models(claims: Claims, state: State<SharedAppState>) -> Response {
     let db = &state.db;
     with_user_guard!(claims, db);
@@ -258,15 +981,109 @@ async fn list_models(claims: Claims, state: State<SharedAppState>) -> Response {
     )
 }
 
+/// Deletes a chat together with its entries.
 async fn delete_chat(
     claims: Claims,
-    state: State<SharedAppState>,
+    State(state): State<SharedAppState>,
     Path(id): Path<Uuid>,
 ) -> Response {
     let db = &state.db;
-    with_user_guard!(claims, db);
+    let user = with_user_guard!(claims, db);
+
+    if let Err(response) = chat_to_delete(db, &user, &id).await {
+        return response;
+    }
+    if is_answering(&state, &id) {
+        return ApiResponse::failure("a response of the chat is still being generated").conflict();
+    }
+
+    match Chat::delete(db, &id).await {
+        Ok(deleted_entries) => {
+            state.chat_streams.forget(&id);
+            ApiResponse::success(ChatDeleteResponse { deleted_entries }).ok()
+        }
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+/// Deletes a prompt of a chat together with its answer, or an answer together with its prompt,
+/// so that the remaining history keeps alternating between prompts and answers.
+async fn delete_entry(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path((id, entry_id)): Path<(Uuid, Uuid)>,
+) -> Response {
+    let db = &state.db;
+    let user = with_user_guard!(claims, db);
+
+    let chat = match chat_to_delete(db, &user, &id).await {
+        Ok(chat) => chat,
+        Err(response) => return response,
+    };
+    // the running response would be saved without its prompt
+    if is_answering(&state, &id) {
+        return ApiResponse::failure("a response of the chat is still being generated").conflict();
+    }
+
+    let entries = match ChatEntry::get_chat_entries(db, &id, &chat.username).await {
+        Ok(entries) => entries,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let Some(position) = entries.iter().position(|e| e.entry_id == entry_id) else {
+        return ApiResponse::failure(format!("entry {entry_id} doesn't belong to chat {id}"))
+            .not_found();
+    };
+    let ids = entry_pair(&entries, position)
+        .iter()
+        .map(|e| e.entry_id)
+        .collect::<Vec<_>>();
+
+    match ChatEntry::delete(db, &id, &ids).await {
+        Ok(deleted_entries) => {
+            state.chat_streams.forget(&id);
+            ApiResponse::success(ChatDeleteResponse { deleted_entries }).ok()
+        }
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+/// Returns chat `id` when the user owns it or is an admin.
+async fn chat_to_delete(
+    db: &DbPool,
+    user: &AuthenticatedUser,
+    id: &Uuid,
+) -> Result<Chat, Response> {
+    let chat = match user.account_type {
+        AccountType::Admin => Chat::get(db, id).await,
+        _ => Chat::get_chat_for_user(db, &user.username, id).await,
+    };
+    chat.map_err(|e| ApiResponse::failure(e).not_found())
+}
+
+/// The prompt and answer the entry at `position` belongs to, a prompt that wasn't answered or an
+/// answer without a prompt stands alone.
+fn entry_pair(entries: &[ChatEntry], position: usize) -> &[ChatEntry] {
+    let is_prompt = |i: usize| entries[i].entry_type == ChatEntryType::User;
+    let start = if !is_prompt(position) && position > 0 && is_prompt(position - 1) {
+        position - 1
+    } else {
+        position
+    };
+    let end = if is_prompt(start) && start + 1 < entries.len() && !is_prompt(start + 1) {
+        start + 2
+    } else {
+        start + 1
+    };
+    &entries[start..end]
+}
 
-    handle_db_result_as_json(Chat::delete(db, &id).await.map_err(Error::from))
+/// Whether a response of chat `id` is still being generated.
+fn is_answering(state: &SharedAppState, id: &Uuid) -> bool {
+    state
+        .chat_streams
+        .get(id)
+        .map(|rx_answer| !rx_answer.borrow().is_finished)
+        .unwrap_or_default()
 }
 
 async fn counters(claims: Claims, State(state): State<SharedAppState>) -> Response {
@@ -1,57 +1,193 @@
 use crate::{
     auth::Claims,
-    gen::image::{BaseImageData, GenerateImageRequest, ImageToImageData, InpaintData},
+    gen::image::{
+        grid::{contact_sheet, grid_layout},
+        metadata,
+        progress::{GenerationProgress, ProgressSender},
+        reroll::SampleReroll,
+        sd::sample_seed,
+        BaseImageData, GenerateImageRequest, ImageToImageData, InpaintData,
+    },
     id::Uuid,
-    models::{image::Image, image_model::ImageModel, image_sample::ImageSample, user::User},
-    routes::handle_db_result_as_json,
-    Error, SharedAppState, ToAxumResponse,
+    models::{
+        audit::AuditEntry,
+        image::{FeedCursor, Image, ImageExportFilter, ImageFilter},
+        image_model::ImageModel,
+        image_preset::ImagePreset,
+        image_sample::ImageSample,
+        image_tag::ImageTag,
+        user::User,
+    },
+    moderation,
+    queue::QueuePosition,
+    request_id,
+    routes::{
+        api::{claim_idempotency_key, finish_idempotency_key, queue_events, user_id, Idempotency},
+        handle_db_result_as_json, share_url, stream_sample,
+    },
+    share::ShareToken,
+    validation::{
+        normalize_image_tag, validate_image_model_request, validate_image_preset,
+        validate_image_request, ValidationError,
+    },
+    DbPool, Error, SharedAppState, ToAxumResponse,
 };
 use airtifex_core::{
     api_response::ApiResponse,
+    audit::AuditAction,
     image::{
-        ImageGenerateRequest, ImageInspect, ImageModelFeatures, ImageModelListEntry,
-        ImageSampleInspect, TextToImageResponse,
+        tags_from_query, ImageDeleteBatchRequest, ImageDeleteBatchResponse, ImageDeleteResult,
+        ImageDeleteStatus, ImageExportFormat, ImageExportQuery, ImageFavoriteResponse,
+        ImageFeedPage, ImageFeedQuery, ImageGenerateRequest, ImageGridQuery, ImageInspect,
+        ImageListQuery, ImageModelCreateRequest, ImageModelCreateResponse, ImageModelFeatures,
+        ImageModelListEntry, ImagePresetRequest, ImageSampleInspect, ImageSampleRegenerateRequest,
+        ImageSampleRegenerateResponse, ImageShareRequest, ImageShareResponse, ImageStatus,
+        ImageTagRequest, ImageVariationsQuery, InputImage, TextToImageResponse,
     },
+    user::AccountType,
+    QueueStatus,
 };
 
 use axum::{
-    extract::{Json, Path, State},
-    response::Response,
+    body::StreamBody,
+    extract::{Json, Path, Query, RawQuery, State},
+    http::{header, HeaderMap},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     routing, Router,
 };
+use futures_util::StreamExt;
 use rand::Rng;
+use std::{borrow::Cow, sync::Arc};
 
 pub fn router() -> Router<SharedAppState> {
     Router::new()
         .route("/generate", routing::post(generate_image))
         .route("/", routing::get(list_images))
-        .route("/models", routing::get(list_models))
+        .route("/feed", routing::get(image_feed))
+        .route("/export", routing::get(export_images))
+        .route("/tags", routing::get(list_tags))
+        .route("/delete-batch", routing::post(delete_images))
+        .route("/models", routing::get(list_models).post(create_model))
+        .route("/models/:id", routing::delete(delete_model))
+        .route("/presets", routing::get(list_presets).post(create_preset))
+        .route(
+            "/presets/:id",
+            routing::get(get_preset)
+                .put(update_preset)
+                .delete(delete_preset),
+        )
         .route(
             "/:id",
             routing::get(get_image_metadata).delete(delete_image),
         )
+        .route("/:id/progress", routing::get(image_progress))
+        .route("/:id/retry", routing::post(retry_image))
+        .route("/:id/favorite", routing::post(toggle_favorite))
+        .route("/:id/reproduce", routing::post(reproduce_image))
+        .route("/:id/variations", routing::post(create_variations))
+        .route(
+            "/:id/share",
+            routing::post(share_image).delete(revoke_image_shares),
+        )
+        .route("/:id/tags", routing::post(add_tag))
+        .route("/:id/tags/:tag", routing::delete(remove_tag))
         .route("/:id/samples", routing::get(list_image_entries))
+        .route("/:id/grid", routing::get(get_image_grid))
         .route("/:id/samples/:n", routing::get(get_image_entry))
+        .route("/:id/samples/:n/data", routing::get(get_image_entry_data))
+        .route(
+            "/:id/samples/:n/metadata",
+            routing::get(get_image_entry_metadata),
+        )
+        .route(
+            "/:id/samples/:n/regenerate",
+            routing::post(regenerate_image_entry),
+        )
 }
 
+/// Queues the generation of an image. A retry with the `Idempotency-Key` of a previous request
+/// gets the image that request created.
 async fn generate_image(
     claims: Claims,
     State(state): State<SharedAppState>,
+    headers: HeaderMap,
     Json(request): Json<ImageGenerateRequest>,
 ) -> Response {
     let db = &state.db;
     with_user_guard!(claims, db);
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
 
-    log::info!("{request:?}");
+    let key =
+        match claim_idempotency_key(&state, &headers, user_id, "image/generate", &request).await {
+            Ok(Idempotency::Process(key)) => key,
+            Ok(Idempotency::Replay(image_id)) => {
+                let queue = state
+                    .image_progress
+                    .queue(&image_id)
+                    .and_then(|queue| queue.status());
+                let image_id = image_id.to_string();
+                return ApiResponse::success(TextToImageResponse { image_id, queue }).ok();
+            }
+            Err(response) => return response,
+        };
+    let (image_id, response) = create_image(&state, &claims.sub, user_id, request).await;
+    finish_idempotency_key(db, key, image_id.as_ref()).await;
+    response
+}
 
-    let user_id = match User::get(db, &claims.sub).await.map(|u| u.id) {
-        Ok(id) => id,
-        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+/// Width and height of images that don't set them.
+const DEFAULT_IMAGE_SIZE: i64 = 512;
+
+/// Creates and queues the image of a generation request, returns the id of the image unless the
+/// request failed before it was created.
+async fn create_image(
+    state: &SharedAppState,
+    username: &str,
+    user_id: Uuid,
+    request: ImageGenerateRequest,
+) -> (Option<Uuid>, Response) {
+    let db = &state.db;
+
+    // parameters left out of the request fall back to the defaults of the user
+    let mut request = match User::get_settings(db, username).await {
+        Ok(settings) => request.with_defaults(&settings.image),
+        Err(e) => return (None, ApiResponse::failure(e).internal_server_error()),
     };
 
-    let guidance_scale = request.guidance_scale.unwrap_or(7.5).min(20.0);
-    let num_samples = request.num_samples.unwrap_or(1).min(16);
-    let n_steps = request.n_steps.unwrap_or(25).min(420) as i64;
+    log::info!("{request:?}");
+
+    if let Err(e) = validate_image_request(&state.config.request_limits.image, &request) {
+        return (None, ApiResponse::failure(e).bad_request());
+    }
+    request.width.get_or_insert(DEFAULT_IMAGE_SIZE);
+    request.height.get_or_insert(DEFAULT_IMAGE_SIZE);
+    if let Some(config) = state
+        .config
+        .stable_diffusion
+        .iter()
+        .find(|config| config.model_name() == request.model)
+    {
+        let capabilities = config.capabilities();
+        if let Err(e) = validate_image_model_request(&config.features(), &capabilities, &request) {
+            return (None, ApiResponse::failure(e).bad_request());
+        }
+    }
+    if let Err(e) = moderation::check_all(state.moderator.as_ref(), [request.prompt.as_str()]) {
+        let response = ApiResponse::failure(&e)
+            .with_code(e.code())
+            .unprocessable_entity();
+        return (None, response);
+    }
+
+    let guidance_scale = request.guidance_scale.unwrap_or(7.5);
+    let num_samples = request.num_samples.unwrap_or(1);
+    let n_steps = request.n_steps.unwrap_or(25) as i64;
 
     let (data, mask, strength) = request
         .input_image
@@ -61,8 +197,8 @@ async fn generate_image(
     let image = Image::new(
         user_id,
         request.model,
-        request.width.unwrap_or(512),
-        request.height.unwrap_or(512),
+        request.width.unwrap_or(DEFAULT_IMAGE_SIZE),
+        request.height.unwrap_or(DEFAULT_IMAGE_SIZE),
         request.prompt,
         data,
         mask,
@@ -75,22 +211,75 @@ async fn generate_image(
     );
 
     if let Err(e) = image.create(db).await {
-        return ApiResponse::failure(e).internal_server_error();
+        return (None, ApiResponse::failure(e).internal_server_error());
+    }
+
+    // the image exists even when it can't be queued, a retry gets it marked as failed
+    let id = image.id;
+    let image_id = id.to_string();
+    let response = match dispatch_image(state, image, request.preview_every).await {
+        Ok(queue) => ApiResponse::success(TextToImageResponse { image_id, queue }).ok(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    };
+    (Some(id), response)
+}
+
+/// Sends the generation request of a queued image to its model, marking it failed when that
+/// isn't possible. The progress stream of the image sends a preview every `preview_every` steps.
+/// Returns the position of the request in the queue unless the model already started it.
+async fn dispatch_image(
+    state: &SharedAppState,
+    image: Image,
+    preview_every: Option<usize>,
+) -> Result<Option<QueueStatus>, String> {
+    let (id, model) = (image.id, image.model.clone());
+    let progress = state.image_progress.start(id);
+    let request = generation_request(image, preview_every, Some(progress), None);
+
+    let error = match send_generation_request(state, &model, request) {
+        Ok(queue) => {
+            let status = queue.status();
+            state.image_progress.set_queue(&id, queue);
+            return Ok(status);
+        }
+        Err(e) => e,
+    };
+
+    // nothing will pick the image up so it would stay queued forever
+    if let Err(e) = Image::update_status(&state.db, &id, ImageStatus::Failed, Some(&error)).await {
+        log::error!("[{id}] failed to update image status - {e}");
     }
+    Err(error)
+}
 
+/// Builds the request generating the samples of `image`, a request with a `reroll` generates
+/// only the reserved sample with the seed of the image.
+fn generation_request(
+    image: Image,
+    preview_every: Option<usize>,
+    progress: Option<ProgressSender>,
+    reroll: Option<SampleReroll>,
+) -> GenerateImageRequest {
     let data = BaseImageData {
         id: image.id.to_string(),
         prompt: image.prompt,
-        // input_image: image.input_image,
-        // mask: image.mask,
         width: image.width,
         height: image.height,
         n_steps: image.n_steps as usize,
         seed: image.seed,
-        num_samples: image.num_samples,
+        num_samples: if reroll.is_some() {
+            1
+        } else {
+            image.num_samples
+        },
         guidance_scale: image.guidance_scale,
+        preview_every,
+        progress,
+        queue_ticket: None,
+        reroll,
+        request_id: request_id::current(),
     };
-    let request = match (image.input_image, image.mask) {
+    match (image.input_image, image.mask) {
         (Some(input_image), Some(mask)) => GenerateImageRequest::Inpaint(InpaintData {
             data,
             input_image,
@@ -102,53 +291,632 @@ async fn generate_image(
             strength: image.strength.unwrap_or(0.7),
         }),
         (None, None) | (None, Some(_)) => GenerateImageRequest::TextToImage(data),
+    }
+}
+
+/// Queues the request on the model, returns its position in the queue.
+fn send_generation_request(
+    state: &SharedAppState,
+    model: &str,
+    request: GenerateImageRequest,
+) -> Result<QueuePosition, String> {
+    let Some(tx_gen_req) = state.tx_image_gen_req.get(model) else {
+        return Err("Image generation from text is disabled".to_string());
     };
+    let queue = tx_gen_req.send(request).map_err(|e| e.to_string())?;
+    state.metrics.count_image_generation(model);
+    Ok(queue)
+}
 
-    if let Some(tx_gen_req) = state.tx_image_gen_req.get(&image.model) {
-        if let Err(e) = tx_gen_req.send_async(request).await {
-            return ApiResponse::failure(e).internal_server_error();
-        }
-    } else {
-        return ApiResponse::failure("Image generation from text is disabled")
-            .internal_server_error();
+/// Streams the progress of an image that is queued or being generated as server-sent events.
+/// The [`queue_events`] of the generation request come first, then `progress` events carry an [`ImageProgress`](airtifex_core::image::ImageProgress) after
+/// every step, `preview` events the latest
+/// [`ImagePreview`](airtifex_core::image::ImagePreview) when previews were requested. The
+/// stream ends with a `done` event once the generation finishes, right away when it isn't
+/// running anymore. The generation is cancelled when its last stream closes and no client
+/// reconnects within the configured grace period.
+async fn image_progress(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let events = futures_util::stream::unfold(
+        Some((state.image_progress.watch(&id), None, None)),
+        |stream| async move {
+            let (mut rx_progress, mut sent_progress, mut sent_preview) = stream?;
+            let done = Ok(Event::default().event("done").data(""));
+            let Some(rx) = rx_progress.as_mut() else {
+                return Some((done, None));
+            };
+            loop {
+                let GenerationProgress { progress, preview } = rx.borrow_and_update().clone();
+                let is_new = |p: &Arc<_>| !matches!(&sent_preview, Some(s) if Arc::ptr_eq(s, p));
+                if let Some(preview) = preview.filter(is_new) {
+                    let event = Event::default().event("preview").json_data(&*preview);
+                    sent_preview = Some(preview);
+                    return Some((event, Some((rx_progress, sent_progress, sent_preview))));
+                }
+                if sent_progress.as_ref() != Some(&progress) && progress.n_steps > 0 {
+                    let event = Event::default().event("progress").json_data(&progress);
+                    sent_progress = Some(progress);
+                    return Some((event, Some((rx_progress, sent_progress, sent_preview))));
+                }
+                // the sender is dropped once the generation ends
+                if rx.changed().await.is_err() {
+                    return Some((done, None));
+                }
+            }
+        },
+    );
+
+    Sse::new(queue_events(state.image_progress.queue(&id)).chain(events))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+async fn retry_image(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let image = match get_own_image(&state, &claims.sub, &id).await {
+        Ok(image) => image,
+        Err(response) => return response,
+    };
+    if !image.status.can_retry() {
+        return ApiResponse::failure(format!(
+            "only failed and cancelled images can be retried, image is {}",
+            image.status.as_ref()
+        ))
+        .bad_request();
     }
 
-    ApiResponse::success(TextToImageResponse {
-        image_id: image.id.to_string(),
-    })
-    .ok()
+    // samples that were generated before the failure are generated again
+    if let Err(e) = ImageSample::delete_image_samples(db, &id).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+    if let Err(e) = Image::update_status(db, &id, ImageStatus::Queued, None).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+
+    match dispatch_image(&state, image, None).await {
+        Ok(queue) => ApiResponse::success(TextToImageResponse {
+            image_id: id.to_string(),
+            queue,
+        })
+        .ok(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+/// Generates `:id` of the caller again with the same parameters and seed as a new image.
+async fn reproduce_image(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let original = match get_own_image(&state, &claims.sub, &id).await {
+        Ok(image) => image,
+        Err(response) => return response,
+    };
+    let samples = match ImageSample::get_image_samples(db, &id).await {
+        Ok(samples) => samples,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    if let Err(e) = check_reproducible(&state, &original, &samples) {
+        return ApiResponse::failure(format!("image {id} can't be reproduced - {e}")).conflict();
+    }
+
+    let image = Image::new(
+        original.user_id,
+        original.model,
+        original.width,
+        original.height,
+        original.prompt,
+        original.input_image,
+        original.mask,
+        None,
+        original.strength,
+        original.n_steps,
+        original.seed,
+        original.num_samples,
+        original.guidance_scale,
+    );
+
+    if let Err(e) = image.create(db).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+
+    let image_id = image.id.to_string();
+    match dispatch_image(&state, image, None).await {
+        Ok(queue) => ApiResponse::success(TextToImageResponse { image_id, queue }).ok(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
 }
 
-async fn list_images(claims: Claims, state: State<SharedAppState>) -> Response {
+/// Number of variations generated when the request doesn't specify it.
+const DEFAULT_VARIATIONS: i64 = 4;
+
+/// Generates variations of the image `:id` of the caller as the samples of a new image, they use
+/// the prompt and parameters of the image with a new seed. Responds with the id of the new image.
+async fn create_variations(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ImageVariationsQuery>,
+) -> Response {
     let db = &state.db;
     with_user_guard!(claims, db);
 
+    let limits = &state.config.request_limits.image;
+    let count = query
+        .count
+        .unwrap_or_else(|| DEFAULT_VARIATIONS.min(limits.variations.max));
+    if let Err(e) = limits
+        .variations
+        .check("count", Some(count))
+        .and_then(|_| limits.num_samples.check("count", Some(count)))
+    {
+        return ApiResponse::failure(e).bad_request();
+    }
+
+    let source = match get_own_image(&state, &claims.sub, &id).await {
+        Ok(image) => image,
+        Err(response) => return response,
+    };
+    if let Err(e) = check_model_supports(&state, &source) {
+        return ApiResponse::failure(format!("can't generate variations of image {id} - {e}"))
+            .conflict();
+    }
+
+    let image = Image::new(
+        source.user_id,
+        source.model,
+        source.width,
+        source.height,
+        source.prompt,
+        source.input_image,
+        source.mask,
+        None,
+        source.strength,
+        source.n_steps,
+        rand::thread_rng().gen(),
+        count,
+        source.guidance_scale,
+    )
+    .with_source_image(source.id);
+
+    if let Err(e) = image.create(db).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+
+    let image_id = image.id.to_string();
+    match dispatch_image(&state, image, None).await {
+        Ok(queue) => ApiResponse::success(TextToImageResponse { image_id, queue }).ok(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+/// Checks that the model of `image` is still available and supports the kind of generation.
+fn check_model_supports(state: &SharedAppState, image: &Image) -> Result<(), String> {
+    let config = state
+        .config
+        .stable_diffusion
+        .iter()
+        .find(|config| config.model_name() == image.model)
+        .filter(|_| state.tx_image_gen_req.contains_key(&image.model))
+        .ok_or_else(|| format!("model {} is no longer available", image.model))?;
+
+    let features = config.features();
+    let (supported, kind) = match (&image.input_image, &image.mask) {
+        (Some(_), Some(_)) => (features.inpaint, "inpainting"),
+        (Some(_), None) => (features.image_to_image, "image to image generation"),
+        (None, _) => (features.text_to_image, "text to image generation"),
+    };
+    if !supported {
+        return Err(format!("model {} no longer supports {kind}", image.model));
+    }
+    let capabilities = config.capabilities();
+    if capabilities.max_width.is_some_and(|max| image.width > max)
+        || capabilities
+            .max_height
+            .is_some_and(|max| image.height > max)
+    {
+        return Err(format!(
+            "model {} no longer generates images of {}x{}",
+            image.model, image.width, image.height
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the currently configured backend can generate `image` exactly like it was
+/// generated originally.
+fn check_reproducible(
+    state: &SharedAppState,
+    image: &Image,
+    samples: &[ImageSample],
+) -> Result<(), String> {
+    check_model_supports(state, image)?;
+
+    // samples are seeded with the seed of the image offset by their index, anything else
+    // was generated by a backend seeding them differently
+    if let Some(sample) = samples
+        .iter()
+        .find(|s| s.actual_seed != sample_seed(image.seed, s.n as i64 - 1))
+    {
+        return Err(format!(
+            "sample {} was generated with seed {} which the backend no longer derives from \
+             seed {}",
+            sample.n, sample.actual_seed, image.seed
+        ));
+    }
+
+    let request = ImageGenerateRequest {
+        prompt: image.prompt.clone(),
+        model: image.model.clone(),
+        input_image: image.input_image.as_ref().map(|_| InputImage {
+            strength: image.strength,
+            ..Default::default()
+        }),
+        width: Some(image.width),
+        height: Some(image.height),
+        n_steps: Some(image.n_steps as usize),
+        seed: Some(image.seed),
+        num_samples: Some(image.num_samples),
+        guidance_scale: Some(image.guidance_scale),
+        preview_every: None,
+        sampler: None,
+    };
+    validate_image_request(&state.config.request_limits.image, &request)
+        .map_err(|e| format!("parameters exceed the current limits, {e}"))
+}
+
+/// Maximum number of tags of a single image.
+const MAX_IMAGE_TAGS: usize = 32;
+
+/// Returns the normalized distinct tags of the `tag` parameters of the query.
+fn query_tags(query: Option<&str>) -> std::result::Result<Vec<String>, ValidationError> {
+    let mut tags = Vec::new();
+    for tag in tags_from_query(query.unwrap_or_default()) {
+        let tag = normalize_image_tag(&tag)?;
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    Ok(tags)
+}
+
+/// Converts the images to their API representation together with their tags, only the
+/// favorites of `owner` are marked as such.
+async fn inspect_with_tags(
+    db: &DbPool,
+    owner: &Uuid,
+    images: crate::models::Result<Vec<Image>>,
+) -> crate::models::Result<Vec<ImageInspect>> {
+    let images = images?;
+    let ids = images.iter().map(|image| image.id).collect::<Vec<_>>();
+    let mut tags = ImageTag::list_for_images(db, &ids).await?;
+    Ok(images
+        .into_iter()
+        .map(|image| ImageInspect {
+            tags: tags.remove(&image.id).unwrap_or_default(),
+            is_favorite: image.is_favorite && image.user_id == *owner,
+            ..image_inspect(image)
+        })
+        .collect())
+}
+
+/// Lists all images, with `tag` parameters or `favorites_only` only the images of the user that
+/// have all of the tags or are their favorites.
+async fn list_images(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Query(list_query): Query<ImageListQuery>,
+    RawQuery(query): RawQuery,
+) -> Response {
+    let db = &state.db;
+    let user = with_user_guard!(claims, db);
+
+    let tags = match query_tags(query.as_deref()) {
+        Ok(tags) => tags,
+        Err(e) => return ApiResponse::failure(e).bad_request(),
+    };
+    let owner = match user.id.parse::<Uuid>() {
+        Ok(owner) => owner,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let favorites_only = list_query.favorites_only.unwrap_or_default();
+    let filter = (!tags.is_empty() || favorites_only).then_some(ImageFilter {
+        owner,
+        tags: &tags,
+        favorites_only,
+    });
+    let favorites_of = list_query
+        .favorites_first
+        .unwrap_or_default()
+        .then_some(&owner);
+
     handle_db_result_as_json(
-        Image::list(db)
-            .await
-            .map(|e| {
-                e.into_iter()
-                    .map(|e| ImageInspect {
-                        id: e.id.to_string(),
-                        user_id: e.user_id.to_string(),
-                        model: e.model,
-                        width: e.width,
-                        height: e.height,
-                        prompt: e.prompt,
-                        input_image: e.input_image,
-                        mask: e.mask,
-                        thumbnail: e.thumbnail,
-                        n_steps: e.n_steps,
-                        seed: e.seed,
-                        num_samples: e.num_samples,
-                        processing: e.processing,
-                        create_date: e.create_date,
-                        guidance_scale: e.guidance_scale,
-                    })
-                    .collect::<Vec<_>>()
+        inspect_with_tags(
+            db,
+            &owner,
+            Image::list(db, filter.as_ref(), favorites_of).await,
+        )
+        .await
+        .map_err(Error::from),
+    )
+}
+
+/// Number of images in a feed page when the request doesn't specify it.
+const DEFAULT_FEED_LIMIT: u32 = 20;
+/// Maximum number of images in a feed page.
+const MAX_FEED_LIMIT: u32 = 100;
+
+async fn image_feed(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Query(mut query): Query<ImageFeedQuery>,
+    RawQuery(raw_query): RawQuery,
+) -> Response {
+    let db = &state.db;
+    let user = with_user_guard!(claims, db);
+
+    query.tags = match query_tags(raw_query.as_deref()) {
+        Ok(tags) => tags,
+        Err(e) => return ApiResponse::failure(e).bad_request(),
+    };
+    let owner = match user.id.parse::<Uuid>() {
+        Ok(owner) => owner,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let favorites_only = query.favorites_only.unwrap_or_default();
+    let filter = (!query.tags.is_empty() || favorites_only).then_some(ImageFilter {
+        owner,
+        tags: &query.tags,
+        favorites_only,
+    });
+    let favorites_first = query.favorites_first.unwrap_or_default();
+
+    let cursor = match query.cursor.as_deref().filter(|c| !c.is_empty()) {
+        // cursors of feeds with the favorites first say whether they point at one
+        Some(cursor) => match FeedCursor::decode(cursor) {
+            Some(cursor) if cursor.is_favorite.is_some() == favorites_first => Some(cursor),
+            _ => return ApiResponse::failure("invalid feed cursor").bad_request(),
+        },
+        None => None,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_FEED_LIMIT)
+        .clamp(1, MAX_FEED_LIMIT);
+
+    // one more image is fetched to know whether there is a next page
+    let favorites_of = favorites_first.then_some(&owner);
+    let images = Image::list_feed(db, cursor, filter.as_ref(), favorites_of, limit + 1).await;
+    let page = images.map(|mut images| {
+        let next_cursor = if images.len() > limit as usize {
+            images.truncate(limit as usize);
+            images.last().map(|image| {
+                FeedCursor {
+                    create_date: image.create_date,
+                    id: image.id,
+                    is_favorite: favorites_first
+                        .then_some(image.is_favorite && image.user_id == owner),
+                }
+                .encode()
             })
-            .map_err(Error::from),
+        } else {
+            None
+        };
+        (images, next_cursor)
+    });
+    let result = match page {
+        Ok((images, next_cursor)) => {
+            inspect_with_tags(db, &owner, Ok(images))
+                .await
+                .map(|images| ImageFeedPage {
+                    images,
+                    next_cursor,
+                })
+        }
+        Err(e) => Err(e),
+    };
+    handle_db_result_as_json(result.map_err(Error::from))
+}
+
+/// Number of images read at once when exporting the images of a user.
+const EXPORT_PAGE_SIZE: u32 = 100;
+
+const EXPORT_CSV_HEADER: &str = "id,create_date,model,prompt,width,height,n_steps,seed,num_samples,guidance_scale,status,error,tags,is_favorite,source_image_id\r\n";
+
+/// Exports the images of the user as a CSV or JSON file, oldest first. The images are read and
+/// sent a page at a time so that long histories aren't loaded at once.
+async fn export_images(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Query(query): Query<ImageExportQuery>,
+) -> Response {
+    let db = &state.db;
+    let user = with_user_guard!(claims, db);
+
+    let owner = match user.id.parse::<Uuid>() {
+        Ok(owner) => owner,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return ApiResponse::failure("`from` can't be after `to`").bad_request();
+        }
+    }
+    let format = query.format.unwrap_or_default();
+    let filter = ImageExportFilter {
+        owner,
+        model: query.model.filter(|model| !model.is_empty()),
+        from: query.from,
+        to: query.to,
+    };
+
+    let db = state.db.clone();
+    // the state is the cursor of the next page and whether it is the first one, `None` after the
+    // last page
+    let chunks = futures_util::stream::unfold(Some((None, true)), move |next| {
+        let db = db.clone();
+        let filter = filter.clone();
+        async move {
+            let (after, is_first): (Option<FeedCursor>, bool) = next?;
+            let images = Image::list_export(&db, &filter, after.as_ref(), EXPORT_PAGE_SIZE).await;
+            let after = images.as_ref().ok().and_then(|images| {
+                let last = images
+                    .last()
+                    .filter(|_| images.len() == EXPORT_PAGE_SIZE as usize)?;
+                Some(FeedCursor {
+                    create_date: last.create_date,
+                    id: last.id,
+                    is_favorite: None,
+                })
+            });
+            match inspect_with_tags(&db, &filter.owner, images).await {
+                Ok(images) => {
+                    let is_last = after.is_none();
+                    let chunk = export_chunk(format, &images, is_first, is_last);
+                    Some((Ok(chunk), (!is_last).then_some((after, false))))
+                }
+                // the response is already started, ending the body early is all that is left
+                Err(e) => Some((Err(e), None)),
+            }
+        }
+    });
+
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                export_content_type(format).to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"images.{}\"", format.as_ref()),
+            ),
+        ],
+        StreamBody::new(chunks),
     )
+        .into_response()
+}
+
+fn export_content_type(format: ImageExportFormat) -> &'static str {
+    match format {
+        ImageExportFormat::Json => "application/json",
+        ImageExportFormat::Csv => "text/csv; charset=utf-8",
+    }
+}
+
+/// Part of an export with a page of images. JSON exports are a single array of the images, CSV
+/// exports start with a header.
+fn export_chunk(
+    format: ImageExportFormat,
+    images: &[ImageInspect],
+    is_first: bool,
+    is_last: bool,
+) -> String {
+    let mut chunk = String::new();
+    match format {
+        ImageExportFormat::Json => {
+            if is_first {
+                chunk.push('[');
+            }
+            for (n, image) in images.iter().enumerate() {
+                if !is_first || n > 0 {
+                    chunk.push(',');
+                }
+                if let Ok(json) = serde_json::to_string(image) {
+                    chunk.push_str(&json);
+                }
+            }
+            if is_last {
+                chunk.push(']');
+            }
+        }
+        ImageExportFormat::Csv => {
+            if is_first {
+                chunk.push_str(EXPORT_CSV_HEADER);
+            }
+            for image in images {
+                chunk.push_str(&csv_row(image));
+            }
+        }
+    }
+    chunk
+}
+
+fn csv_row(image: &ImageInspect) -> String {
+    let fields = [
+        image.id.clone(),
+        image.create_date.to_rfc3339(),
+        image.model.clone(),
+        image.prompt.clone(),
+        image.width.to_string(),
+        image.height.to_string(),
+        image.n_steps.to_string(),
+        image.seed.to_string(),
+        image.num_samples.to_string(),
+        image.guidance_scale.to_string(),
+        image.status.as_ref().to_string(),
+        image.error.clone().unwrap_or_default(),
+        image.tags.join(";"),
+        image.is_favorite.to_string(),
+        image.source_image_id.clone().unwrap_or_default(),
+    ];
+    let mut row = fields
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push_str("\r\n");
+    row
+}
+
+/// Quotes `value` when it contains a separator, a quote or a line break, quotes in it are
+/// doubled.
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+fn image_inspect(image: Image) -> ImageInspect {
+    ImageInspect {
+        id: image.id.to_string(),
+        user_id: image.user_id.to_string(),
+        model: image.model,
+        width: image.width,
+        height: image.height,
+        prompt: image.prompt,
+        input_image: image.input_image,
+        mask: image.mask,
+        thumbnail: image.thumbnail,
+        n_steps: image.n_steps,
+        seed: image.seed,
+        num_samples: image.num_samples,
+        status: image.status,
+        error: image.error,
+        create_date: image.create_date,
+        guidance_scale: image.guidance_scale,
+        tags: Vec::new(),
+        is_favorite: image.is_favorite,
+        source_image_id: image.source_image_id.map(|id| id.to_string()),
+    }
 }
 
 async fn list_image_entries(
@@ -168,6 +936,7 @@ async fn list_image_entries(
                         sample_id: e.sample_id.to_string(),
                         image_id: e.image_id.to_string(),
                         n_sample: e.n,
+                        actual_seed: e.actual_seed,
                         data: e.data,
                     })
                     .collect::<Vec<_>>()
@@ -191,71 +960,378 @@ async fn get_image_entry(
                 sample_id: e.sample_id.to_string(),
                 image_id: e.image_id.to_string(),
                 n_sample: e.n,
+                actual_seed: e.actual_seed,
                 data: e.data,
             })
             .map_err(Error::from),
     )
 }
 
-async fn get_image_metadata(
+/// The PNG of a sample as is, streamed instead of embedded in JSON.
+async fn get_image_entry_data(
     claims: Claims,
-    state: State<SharedAppState>,
+    State(state): State<SharedAppState>,
+    Path((id, n)): Path<(Uuid, i32)>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(response) = get_own_image(&state, &claims.sub, &id).await {
+        return response;
+    }
+    stream_sample(state.db.clone(), id, n).await
+}
+
+/// Contact sheet of the samples of an image as a PNG download. Samples that weren't generated,
+/// for example because the generation failed, are left out.
+async fn get_image_grid(
+    claims: Claims,
+    State(state): State<SharedAppState>,
     Path(id): Path<Uuid>,
+    Query(query): Query<ImageGridQuery>,
 ) -> Response {
     let db = &state.db;
     with_user_guard!(claims, db);
 
-    let user_id = match User::get(db, &claims.sub).await.map(|u| u.id) {
-        Ok(id) => id.to_string(),
+    if query.cols == Some(0) {
+        return ApiResponse::failure("`cols` must be at least 1").bad_request();
+    }
+    let image = match get_own_image(&state, &claims.sub, &id).await {
+        Ok(image) => image,
+        Err(response) => return response,
+    };
+    if image.status.is_processing() {
+        return ApiResponse::failure(format!(
+            "the grid can be made once all samples are generated, image is {}",
+            image.status.as_ref()
+        ))
+        .conflict();
+    }
+    let samples = match ImageSample::get_image_samples(db, &id).await {
+        Ok(samples) if samples.is_empty() => {
+            return ApiResponse::failure(format!("image {id} has no samples")).not_found()
+        }
+        Ok(samples) => samples,
         Err(e) => return ApiResponse::failure(e).internal_server_error(),
     };
 
-    handle_db_result_as_json(
-        Image::get_by_id(db, &id)
-            .await
-            .map(|image| ImageInspect {
-                id: image.id.to_string(),
-                user_id,
-                model: image.model,
-                width: image.width,
-                height: image.height,
-                prompt: image.prompt,
-                input_image: image.input_image,
-                mask: image.mask,
-                thumbnail: image.thumbnail,
-                n_steps: image.n_steps,
-                seed: image.seed,
-                num_samples: image.num_samples,
-                processing: image.processing,
-                create_date: image.create_date,
-                guidance_scale: image.guidance_scale,
-            })
-            .map_err(Error::from),
+    let (cols, _) = grid_layout(image.num_samples as usize, query.cols);
+    let with_seeds = query.seeds.unwrap_or(false);
+    let grid = match tokio::task::spawn_blocking(move || contact_sheet(&samples, cols, with_seeds))
+        .await
+    {
+        Ok(Ok(grid)) => grid,
+        Ok(Err(e)) => return ApiResponse::failure(e).internal_server_error(),
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    (
+        [
+            (header::CONTENT_TYPE, "image/png".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{id}-grid.png\""),
+            ),
+        ],
+        grid,
     )
+        .into_response()
 }
 
-async fn delete_image(
+/// Generation parameters embedded in the PNG of a sample.
+async fn get_image_entry_metadata(
     claims: Claims,
     state: State<SharedAppState>,
-    Path(id): Path<Uuid>,
+    Path((id, n)): Path<(Uuid, i32)>,
 ) -> Response {
     let db = &state.db;
     with_user_guard!(claims, db);
 
-    handle_db_result_as_json(Image::delete(db, &id).await.map_err(Error::from))
+    let sample = match ImageSample::get_sample(db, &id, n).await {
+        Ok(sample) => sample,
+        Err(e) => return ApiResponse::failure(e).not_found(),
+    };
+    match metadata::read_parameters(&sample.data) {
+        Ok(Some(metadata)) => ApiResponse::success(metadata).ok(),
+        Ok(None) => {
+            ApiResponse::failure("the sample has no embedded generation parameters").not_found()
+        }
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
 }
 
-async fn list_models(claims: Claims, state: State<SharedAppState>) -> Response {
+/// Generates sample `:n` of a finished image again with a new seed, the other samples are kept.
+/// The sample is replaced once it is generated, a slot can't be generated again while its
+/// previous reroll is running.
+async fn regenerate_image_entry(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path((id, n)): Path<(Uuid, i32)>,
+    Json(request): Json<ImageSampleRegenerateRequest>,
+) -> Response {
     let db = &state.db;
     with_user_guard!(claims, db);
 
-    handle_db_result_as_json(
-        ImageModel::list(db)
-            .await
-            .map(|entries| {
-                entries
-                    .into_iter()
+    let mut image = match get_own_image(&state, &claims.sub, &id).await {
+        Ok(image) => image,
+        Err(response) => return response,
+    };
+    if image.status != ImageStatus::Done {
+        return ApiResponse::failure(format!(
+            "only samples of finished images can be generated again, image is {}",
+            image.status.as_ref()
+        ))
+        .conflict();
+    }
+    if n < 1 || n as i64 > image.num_samples {
+        return ApiResponse::failure(format!("image {id} has no sample {n}")).not_found();
+    }
+    let Some(reroll) = state.sample_rerolls.reserve(id, n) else {
+        return ApiResponse::failure(format!(
+            "sample {n} of image {id} is already being generated again"
+        ))
+        .conflict();
+    };
+
+    let seed = request.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    image.seed = seed;
+    let model = image.model.clone();
+    let generation = generation_request(image, None, None, Some(reroll));
+    match send_generation_request(&state, &model, generation) {
+        Ok(queue) => ApiResponse::success(ImageSampleRegenerateResponse {
+            image_id: id.to_string(),
+            n_sample: n,
+            seed,
+            queue: queue.status(),
+        })
+        .ok(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+async fn get_image_metadata(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let user_id = match User::get(db, &claims.sub).await.map(|u| u.id) {
+        Ok(id) => id.to_string(),
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+
+    let result = match Image::get_by_id(db, &id).await {
+        Ok(image) => ImageTag::list(db, &image.id)
+            .await
+            .map(|tags| (image, tags)),
+        Err(e) => Err(e),
+    };
+    handle_db_result_as_json(
+        result
+            .map(|(image, tags)| {
+                let is_favorite = image.is_favorite && image.user_id.to_string() == user_id;
+                ImageInspect {
+                    id: image.id.to_string(),
+                    user_id,
+                    model: image.model,
+                    width: image.width,
+                    height: image.height,
+                    prompt: image.prompt,
+                    input_image: image.input_image,
+                    mask: image.mask,
+                    thumbnail: image.thumbnail,
+                    n_steps: image.n_steps,
+                    seed: image.seed,
+                    num_samples: image.num_samples,
+                    status: image.status,
+                    error: image.error,
+                    create_date: image.create_date,
+                    guidance_scale: image.guidance_scale,
+                    tags,
+                    is_favorite,
+                    source_image_id: image.source_image_id.map(|id| id.to_string()),
+                }
+            })
+            .map_err(Error::from),
+    )
+}
+
+/// Stars the image or removes its star, only the owner of an image can star it.
+async fn toggle_favorite(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match Image::toggle_favorite_for_user(db, &user_id, &id).await {
+        Ok(Some(is_favorite)) => ApiResponse::success(ImageFavoriteResponse { is_favorite }).ok(),
+        Ok(None) => ApiResponse::failure(format!("image {id} doesn't exist")).not_found(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+async fn delete_image(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    handle_db_result_as_json(Image::delete(db, &id).await.map_err(Error::from))
+}
+
+/// Returns the image if it belongs to `username`, the response to send otherwise.
+async fn get_own_image(
+    state: &SharedAppState,
+    username: &str,
+    id: &Uuid,
+) -> std::result::Result<Image, Response> {
+    let db = &state.db;
+    let user_id = User::get(db, username)
+        .await
+        .map(|u| u.id)
+        .map_err(|e| ApiResponse::failure(e).internal_server_error())?;
+    let image = Image::get_by_id(db, id)
+        .await
+        .map_err(|e| ApiResponse::failure(e).not_found())?;
+    if image.user_id != user_id {
+        return Err(ApiResponse::failure("only the owner of an image can change it").forbidden());
+    }
+    Ok(image)
+}
+
+/// Creates a link giving access to a sample of the image without an account until it expires
+/// or the links of the image are revoked.
+async fn share_image(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<ImageShareRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let image = match get_own_image(&state, &claims.sub, &id).await {
+        Ok(image) => image,
+        Err(response) => return response,
+    };
+    let n_sample = request.n_sample.unwrap_or(1);
+    if n_sample < 1 || n_sample as i64 > image.num_samples {
+        return ApiResponse::failure(format!("image {id} has no sample {n_sample}")).bad_request();
+    }
+    let generation = match Image::share_generation(db, &id).await {
+        Ok(generation) => generation,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+
+    let config = &state.config;
+    let expires = chrono::Utc::now() + chrono::Duration::seconds(config.image_share.expiry);
+    let token =
+        ShareToken::new(id, n_sample, expires.timestamp()).sign(&config.jwt_secret, generation);
+
+    ApiResponse::success(ImageShareResponse {
+        url: share_url(config, &config.image_share, &format!("image/{token}")),
+        expires,
+    })
+    .ok()
+}
+
+/// Invalidates all outstanding share links of the image.
+async fn revoke_image_shares(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(response) = get_own_image(&state, &claims.sub, &id).await {
+        return response;
+    }
+    handle_db_result_as_json(Image::revoke_shares(db, &id).await.map_err(Error::from))
+}
+
+/// Maximum number of images deleted by a single request.
+const MAX_BATCH_DELETE: usize = 500;
+
+/// Deletes the listed images, users other than admins can only delete their own images.
+async fn delete_images(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Json(request): Json<ImageDeleteBatchRequest>,
+) -> Response {
+    let db = &state.db;
+    let user = with_user_guard!(claims, db);
+
+    if request.ids.len() > MAX_BATCH_DELETE {
+        return ApiResponse::failure(format!(
+            "at most {MAX_BATCH_DELETE} images can be deleted at once"
+        ))
+        .bad_request();
+    }
+    let mut ids = Vec::with_capacity(request.ids.len());
+    for id in &request.ids {
+        match id.parse::<Uuid>() {
+            Ok(id) if !ids.contains(&id) => ids.push(id),
+            Ok(_) => {}
+            Err(e) => {
+                return ApiResponse::failure(format!("invalid image id {id} - {e}")).bad_request()
+            }
+        }
+    }
+
+    let owner = match user.account_type {
+        AccountType::Admin => None,
+        _ => match user.id.parse::<Uuid>() {
+            Ok(id) => Some(id),
+            Err(e) => return ApiResponse::failure(e).internal_server_error(),
+        },
+    };
+
+    handle_db_result_as_json(
+        Image::delete_batch(db, &ids, owner.as_ref())
+            .await
+            .map(|results| {
+                let deleted = results
+                    .iter()
+                    .filter(|(_, status)| *status == ImageDeleteStatus::Deleted)
+                    .count();
+                ImageDeleteBatchResponse {
+                    deleted,
+                    skipped: results.len() - deleted,
+                    results: results
+                        .into_iter()
+                        .map(|(id, status)| ImageDeleteResult {
+                            id: id.to_string(),
+                            status,
+                        })
+                        .collect(),
+                }
+            })
+            .map_err(Error::from),
+    )
+}
+
+async fn list_models(claims: Claims, state: State<SharedAppState>) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    handle_db_result_as_json(
+        ImageModel::list(db)
+            .await
+            .map(|entries| {
+                entries
+                    .into_iter()
                     .map(|model| ImageModelListEntry {
+                        capabilities: model.capabilities(),
                         model_id: model.model_id.to_string(),
                         name: model.name,
                         description: model.description,
@@ -270,3 +1346,571 @@ async fn list_models(claims: Claims, state: State<SharedAppState>) -> Response {
             .map_err(Error::from),
     )
 }
+
+async fn create_model(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Json(request): Json<ImageModelCreateRequest>,
+) -> Response {
+    let db = &state.db;
+    with_admin_guard!(claims, db);
+
+    let name = request.name.trim();
+    if name.is_empty() {
+        return ApiResponse::failure("model name can't be empty").bad_request();
+    }
+    if ImageModel::get_by_name(db, name).await.is_ok() {
+        return ApiResponse::failure(format!("image model {name} already exists")).bad_request();
+    }
+
+    let model = ImageModel::new(
+        name.to_string(),
+        request.description,
+        request.features,
+        &request.capabilities,
+    );
+    let result = model.create(db).await;
+    if result.is_ok() {
+        AuditEntry::record(db, &claims, AuditAction::ImageModelCreated, Some(name)).await;
+    }
+    handle_db_result_as_json(
+        result
+            .map(|_| ImageModelCreateResponse {
+                model_id: model.id().to_string(),
+            })
+            .map_err(Error::from),
+    )
+}
+
+/// Models listed in the configuration are registered again on the next start.
+async fn delete_model(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_admin_guard!(claims, db);
+
+    let result = ImageModel::delete(db, &id).await;
+    if let Ok(model) = &result {
+        log::info!("deleted image model {}", model.name);
+        AuditEntry::record(
+            db,
+            &claims,
+            AuditAction::ImageModelDeleted,
+            Some(&model.name),
+        )
+        .await;
+    }
+    handle_db_result_as_json(result.map(|_| ()).map_err(Error::from))
+}
+
+/// Returns the distinct tags of the images of the user with the number of images tagged with
+/// each.
+async fn list_tags(claims: Claims, state: State<SharedAppState>) -> Response {
+    let db = &state.db;
+    let user = with_user_guard!(claims, db);
+    let owner = match user.id.parse::<Uuid>() {
+        Ok(owner) => owner,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+
+    handle_db_result_as_json(ImageTag::counts(db, &owner).await.map_err(Error::from))
+}
+
+/// Tags an image of the user, responds with all tags of the image.
+async fn add_tag(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<ImageTagRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(response) = get_own_image(&state, &claims.sub, &id).await {
+        return response;
+    }
+    let tag = match normalize_image_tag(&request.tag) {
+        Ok(tag) => tag,
+        Err(e) => return ApiResponse::failure(e).bad_request(),
+    };
+    let tags = match ImageTag::list(db, &id).await {
+        Ok(tags) => tags,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    if !tags.contains(&tag) && tags.len() >= MAX_IMAGE_TAGS {
+        return ApiResponse::failure(format!(
+            "an image can't have more than {MAX_IMAGE_TAGS} tags"
+        ))
+        .bad_request();
+    }
+
+    if let Err(e) = ImageTag::add(db, &id, &tag).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+    handle_db_result_as_json(ImageTag::list(db, &id).await.map_err(Error::from))
+}
+
+/// Removes a tag of an image of the user, responds with the remaining tags of the image.
+async fn remove_tag(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path((id, tag)): Path<(Uuid, String)>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(response) = get_own_image(&state, &claims.sub, &id).await {
+        return response;
+    }
+    let tag = match normalize_image_tag(&tag) {
+        Ok(tag) => tag,
+        Err(e) => return ApiResponse::failure(e).bad_request(),
+    };
+
+    match ImageTag::remove(db, &id, &tag).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return ApiResponse::failure(format!("image {id} isn't tagged with `{tag}`"))
+                .not_found()
+        }
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    }
+    handle_db_result_as_json(ImageTag::list(db, &id).await.map_err(Error::from))
+}
+
+/// Returns the presets of the user sorted by name.
+async fn list_presets(claims: Claims, state: State<SharedAppState>) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    handle_db_result_as_json(
+        ImagePreset::list_for_user(db, &user_id)
+            .await
+            .map(|presets| {
+                presets
+                    .into_iter()
+                    .map(ImagePreset::inspect)
+                    .collect::<Vec<_>>()
+            })
+            .map_err(Error::from),
+    )
+}
+
+async fn create_preset(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Json(request): Json<ImagePresetRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(e) = validate_image_preset(&state.config.request_limits.image, &request) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let preset = ImagePreset::new(user_id, request);
+    if let Err(response) = check_preset_name(db, &preset).await {
+        return response;
+    }
+    if let Err(e) = preset.create(db).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+    ApiResponse::success(preset.inspect()).ok()
+}
+
+async fn get_preset(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match ImagePreset::get_for_user(db, &user_id, &id).await {
+        Ok(preset) => ApiResponse::success(preset.inspect()).ok(),
+        Err(e) => ApiResponse::failure(e).not_found(),
+    }
+}
+
+/// Replaces the name and the parameters of a preset of the user.
+async fn update_preset(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<ImagePresetRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(e) = validate_image_preset(&state.config.request_limits.image, &request) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let mut preset = match ImagePreset::get_for_user(db, &user_id, &id).await {
+        Ok(preset) => preset,
+        Err(e) => return ApiResponse::failure(e).not_found(),
+    };
+    preset.set(request);
+    if let Err(response) = check_preset_name(db, &preset).await {
+        return response;
+    }
+    if let Err(e) = preset.update(db).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+    ApiResponse::success(preset.inspect()).ok()
+}
+
+async fn delete_preset(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match ImagePreset::delete_for_user(db, &user_id, &id).await {
+        Ok(true) => ApiResponse::success(()).ok(),
+        Ok(false) => ApiResponse::failure(format!("preset {id} doesn't exist")).not_found(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+/// Fails when another preset of the owner has the same name.
+async fn check_preset_name(db: &DbPool, preset: &ImagePreset) -> std::result::Result<(), Response> {
+    match ImagePreset::name_taken(db, &preset.user_id, &preset.name, Some(&preset.id)).await {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(ApiResponse::failure(format!(
+            "a preset named `{}` already exists",
+            preset.name
+        ))
+        .conflict()),
+        Err(e) => Err(ApiResponse::failure(e).internal_server_error()),
+    }
+}
+
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod tests {
+    use super::*;
+    use crate::{queue::queue_channel, testing};
+
+    use axum::http::{Method, StatusCode};
+
+    async fn state() -> SharedAppState {
+        let config = testing::config(
+            r#"
+stable_diffusion:
+  - name: mock
+    version: v2.1
+    backend:
+      type: mock
+    max_width: 1024
+  - name: unloaded
+    version: v2.1
+    backend:
+      type: mock
+"#,
+        );
+        let mut state = testing::inner_state(testing::db().await, config);
+        let (tx, _, _) = queue_channel(1);
+        state.tx_image_gen_req.insert("mock".into(), tx);
+        SharedAppState::from(std::sync::Arc::new(state))
+    }
+
+    fn generated() -> Image {
+        Image::new(
+            Uuid::new_v4(),
+            "mock".into(),
+            512,
+            512,
+            "a lighthouse".into(),
+            None,
+            None,
+            None,
+            None,
+            30,
+            42,
+            2,
+            7.5,
+        )
+    }
+
+    fn samples(image: &Image) -> Vec<ImageSample> {
+        (1..=image.num_samples as i32)
+            .map(|n| ImageSample::new(image.id, n, image.seed + n as i64 - 1, vec![]))
+            .collect()
+    }
+
+    fn conflict(state: &SharedAppState, image: &Image, samples: &[ImageSample]) -> String {
+        check_reproducible(state, image, samples).expect_err("image isn't reproducible")
+    }
+
+    #[tokio::test]
+    async fn image_of_an_unchanged_model_is_reproducible() {
+        let state = state().await;
+        let image = generated();
+        assert_eq!(check_reproducible(&state, &image, &samples(&image)), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn image_of_a_missing_model_isnt_reproducible() {
+        let state = state().await;
+        for model in ["removed", "unloaded"] {
+            let image = Image {
+                model: model.into(),
+                ..generated()
+            };
+            assert_eq!(
+                conflict(&state, &image, &samples(&image)),
+                format!("model {model} is no longer available")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn image_of_an_unsupported_feature_isnt_reproducible() {
+        let state = state().await;
+        let image = Image {
+            input_image: Some(vec![1]),
+            mask: Some(vec![1]),
+            strength: Some(0.5),
+            ..generated()
+        };
+        assert_eq!(
+            conflict(&state, &image, &samples(&image)),
+            "model mock no longer supports inpainting"
+        );
+
+        let image = Image {
+            width: 2048,
+            ..generated()
+        };
+        assert_eq!(
+            conflict(&state, &image, &samples(&image)),
+            "model mock no longer generates images of 2048x512"
+        );
+    }
+
+    #[tokio::test]
+    async fn image_with_differently_seeded_samples_isnt_reproducible() {
+        let state = state().await;
+        let image = generated();
+        let mut samples = samples(&image);
+        samples[1].actual_seed = 99;
+        assert_eq!(
+            conflict(&state, &image, &samples),
+            "sample 2 was generated with seed 99 which the backend no longer derives from seed 42"
+        );
+    }
+
+    /// State of a server generating the images of its model on the mock backend.
+    async fn generating_state(db: DbPool) -> SharedAppState {
+        let config = testing::config(
+            r#"
+stable_diffusion:
+  - name: mock
+    version: v2.1
+    backend:
+      type: mock
+"#,
+        );
+        let mut state = testing::inner_state(db, config);
+        testing::start_image_models(&mut state).await;
+        SharedAppState::from(Arc::new(state))
+    }
+
+    #[tokio::test]
+    async fn samples_are_stored_with_their_wrapped_seeds() {
+        let db = testing::db().await;
+        let alice = testing::user(&db, "alice", AccountType::User).await;
+        let state = generating_state(db).await;
+        let router = testing::router(state.clone());
+
+        let (status, body) = testing::send(
+            &router,
+            Method::POST,
+            "/api/v1/image/generate",
+            Some(&testing::token(&alice)),
+            Some(serde_json::json!({
+                "prompt": "a lighthouse",
+                "model": "mock",
+                "width": 64,
+                "height": 64,
+                "seed": i64::MAX,
+                "num_samples": 3,
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let id = body["data"]["image_id"].as_str().unwrap().parse().unwrap();
+        let image = testing::generated_image(&state.db, &id).await;
+        assert_eq!(image.status, ImageStatus::Done);
+
+        let samples = ImageSample::get_image_samples(&state.db, &id)
+            .await
+            .unwrap();
+        let seeds: Vec<_> = samples.iter().map(|s| (s.n, s.actual_seed)).collect();
+        assert_eq!(seeds, [(1, i64::MAX), (2, i64::MIN), (3, i64::MIN + 1)]);
+    }
+
+    /// Tokens of alice and bob and a router whose mock model has generated an image of alice.
+    async fn image_of_alice() -> (SharedAppState, axum::Router, String, String, Uuid) {
+        let db = testing::db().await;
+        let alice = testing::token(&testing::user(&db, "alice", AccountType::User).await);
+        let bob = testing::token(&testing::user(&db, "bob", AccountType::User).await);
+        let state = generating_state(db).await;
+        let router = testing::router(state.clone());
+
+        let (status, body) = testing::send(
+            &router,
+            Method::POST,
+            "/api/v1/image/generate",
+            Some(&alice),
+            Some(serde_json::json!({
+                "prompt": "a lighthouse",
+                "model": "mock",
+                "width": 64,
+                "height": 64,
+                "seed": 42,
+                "num_samples": 2,
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let id = body["data"]["image_id"].as_str().unwrap().parse().unwrap();
+        let image = testing::generated_image(&state.db, &id).await;
+        assert_eq!(image.status, ImageStatus::Done);
+        (state, router, alice, bob, id)
+    }
+
+    /// Asserts that `bob` is refused the `method` request of `uri` on the image `id` of alice and
+    /// that the request of a missing image isn't found.
+    async fn assert_only_the_owner_can(
+        router: &axum::Router,
+        method: Method,
+        uri: impl Fn(&Uuid) -> String,
+        alice: &str,
+        bob: &str,
+        id: &Uuid,
+    ) {
+        let (status, body) = testing::send(router, method.clone(), &uri(id), Some(bob), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{body}");
+        let (status, body) =
+            testing::send(router, method, &uri(&Uuid::new_v4()), Some(alice), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{body}");
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_can_retry_an_image() {
+        let (state, router, alice, bob, id) = image_of_alice().await;
+        Image::update_status(&state.db, &id, ImageStatus::Failed, None)
+            .await
+            .unwrap();
+        let retry = |id: &Uuid| format!("/api/v1/image/{id}/retry");
+
+        assert_only_the_owner_can(&router, Method::POST, retry, &alice, &bob, &id).await;
+        assert_eq!(
+            ImageSample::get_image_samples(&state.db, &id)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let (status, body) =
+            testing::send(&router, Method::POST, &retry(&id), Some(&alice), None).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let image = testing::generated_image(&state.db, &id).await;
+        assert_eq!(image.status, ImageStatus::Done);
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_can_download_the_samples() {
+        let (_, router, alice, bob, id) = image_of_alice().await;
+        let data = |id: &Uuid| format!("/api/v1/image/{id}/samples/1/data");
+        let grid = |id: &Uuid| format!("/api/v1/image/{id}/grid");
+        assert_only_the_owner_can(&router, Method::GET, data, &alice, &bob, &id).await;
+        assert_only_the_owner_can(&router, Method::GET, grid, &alice, &bob, &id).await;
+
+        let (status, _) = testing::send(&router, Method::GET, &data(&id), Some(&alice), None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_can_vary_an_image() {
+        let (state, router, alice, bob, id) = image_of_alice().await;
+        let variations = |id: &Uuid| format!("/api/v1/image/{id}/variations?count=2");
+        assert_only_the_owner_can(&router, Method::POST, variations, &alice, &bob, &id).await;
+
+        let (status, body) =
+            testing::send(&router, Method::POST, &variations(&id), Some(&alice), None).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let varied = body["data"]["image_id"].as_str().unwrap().parse().unwrap();
+        let image = testing::generated_image(&state.db, &varied).await;
+        assert_eq!(image.source_image_id, Some(id));
+        assert_eq!(image.num_samples, 2);
+    }
+
+    #[tokio::test]
+    async fn reproduced_image_has_the_same_samples() {
+        let (state, router, alice, bob, id) = image_of_alice().await;
+        let reproduce = |id: &Uuid| format!("/api/v1/image/{id}/reproduce");
+        assert_only_the_owner_can(&router, Method::POST, reproduce, &alice, &bob, &id).await;
+
+        let (status, body) =
+            testing::send(&router, Method::POST, &reproduce(&id), Some(&alice), None).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let copy = body["data"]["image_id"].as_str().unwrap().parse().unwrap();
+        assert_ne!(copy, id);
+        let image = testing::generated_image(&state.db, &copy).await;
+        assert_eq!(image.status, ImageStatus::Done);
+
+        let data = |samples: Vec<ImageSample>| -> Vec<_> {
+            samples.into_iter().map(|s| (s.n, s.data)).collect()
+        };
+        let original = ImageSample::get_image_samples(&state.db, &id)
+            .await
+            .unwrap();
+        let reproduced = ImageSample::get_image_samples(&state.db, &copy)
+            .await
+            .unwrap();
+        assert_eq!(original.len(), 2);
+        assert!(original.iter().all(|s| !s.data.is_empty()));
+        assert_eq!(data(reproduced), data(original));
+    }
+
+    #[tokio::test]
+    async fn image_exceeding_the_limits_isnt_reproducible() {
+        let state = state().await;
+        let image = Image {
+            n_steps: 1000,
+            ..generated()
+        };
+        let conflict = conflict(&state, &image, &samples(&image));
+        assert!(
+            conflict.starts_with("parameters exceed the current limits, invalid `n_steps`"),
+            "{conflict}"
+        );
+    }
+}
@@ -1,17 +1,322 @@
+pub mod admin;
+pub mod audit;
+pub mod batch;
 pub mod chat;
 pub mod image;
 pub mod prompt;
+pub mod system_prompts;
+pub mod tokenize;
 pub mod users;
+pub mod webhooks;
 
-use crate::ApiVersion;
+use crate::{
+    body_limit::body_limit,
+    id::Uuid,
+    metrics::track_duration,
+    models::{
+        idempotency_key::{IdempotencyKey, KeyClaim},
+        user::User,
+    },
+    queue::QueuePosition,
+    rate_limit::{rate_limit, RouteGroup},
+    validation::validate_idempotency_key,
+    ApiResponse, ApiVersion, DbPool, SharedAppState, ToAxumResponse,
+};
 
-use axum::Router;
+use axum::{
+    extract::DefaultBodyLimit,
+    http::HeaderMap,
+    middleware,
+    response::{sse::Event, Response},
+    Router,
+};
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 
-pub fn router() -> Router<crate::SharedAppState> {
+/// Header of the key a client sends so that retrying a generation request doesn't generate twice.
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+pub fn router(state: SharedAppState) -> Router<SharedAppState> {
+    let body_limits = state.config.body_limits.clone();
+    let group = move |router: Router<SharedAppState>, group| {
+        let max_body = body_limits.max_for(group);
+        router
+            .route_layer(DefaultBodyLimit::max(max_body))
+            .route_layer(middleware::from_fn_with_state(max_body, body_limit))
+            .route_layer(middleware::from_fn_with_state(
+                (state.clone(), group),
+                rate_limit,
+            ))
+            .route_layer(middleware::from_fn_with_state(
+                (state.clone(), group),
+                track_duration,
+            ))
+    };
     let base = Router::new()
-        .nest("/users", users::router())
-        .nest("/llm", chat::router().merge(prompt::router()))
-        .nest("/image", image::router());
+        .nest("/users", group(users::router(), RouteGroup::Users))
+        .nest(
+            "/llm",
+            group(
+                chat::router()
+                    .merge(prompt::router())
+                    .merge(batch::router())
+                    .merge(system_prompts::router())
+                    .merge(tokenize::router()),
+                RouteGroup::Chat,
+            ),
+        )
+        .nest("/image", group(image::router(), RouteGroup::Image))
+        .nest("/webhooks", group(webhooks::router(), RouteGroup::Users))
+        .nest("/admin", group(admin::router(), RouteGroup::Users))
+        .nest("/audit", group(audit::router(), RouteGroup::Users));
 
     Router::new().nest(&format!("/api/{}", ApiVersion::V1.as_ref()), base)
 }
+
+/// Server-sent events of the place of a request in the queue of its model. A `queue` event with
+/// a [`QueueStatus`](airtifex_core::QueueStatus) is sent whenever the request moves closer to the
+/// front, followed by a `started` event once the model starts it.
+fn queue_events(
+    queue: Option<QueuePosition>,
+) -> impl Stream<Item = Result<Event, serde_json::Error>> {
+    futures_util::stream::iter(queue)
+        .flat_map(QueuePosition::updates)
+        .map(|status| match status {
+            Some(status) => Event::default().event("queue").json_data(status),
+            None => Ok(Event::default().event("started").data("")),
+        })
+}
+
+/// Returns the id of the user `username`, the response to send otherwise.
+async fn user_id(db: &DbPool, username: &str) -> std::result::Result<Uuid, Response> {
+    User::get(db, username)
+        .await
+        .map(|u| u.id)
+        .map_err(|e| ApiResponse::failure(e).internal_server_error())
+}
+
+/// What to do with a generation request according to its `Idempotency-Key` header.
+enum Idempotency {
+    /// The request has to be processed, with the key that was claimed for it if it has one.
+    Process(Option<IdempotencyKey>),
+    /// The same request was processed before and created this resource.
+    Replay(Uuid),
+}
+
+/// Claims the `Idempotency-Key` of a request of the user, returns the response to send when the
+/// key can't be used for it. The key is bound to `scope` and the request so that reusing it for
+/// a different request fails with `409 Conflict`.
+async fn claim_idempotency_key(
+    state: &SharedAppState,
+    headers: &HeaderMap,
+    user_id: Uuid,
+    scope: &str,
+    request: &impl Serialize,
+) -> std::result::Result<Idempotency, Response> {
+    let Some(key) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(Idempotency::Process(None));
+    };
+    let key = String::from_utf8_lossy(key.as_bytes()).into_owned();
+    if let Err(e) = validate_idempotency_key(&key) {
+        return Err(ApiResponse::failure(e).bad_request());
+    }
+    let body = match serde_json::to_vec(request) {
+        Ok(body) => body,
+        Err(e) => return Err(ApiResponse::failure(e).internal_server_error()),
+    };
+    let digest = Sha256::new()
+        .chain_update(scope)
+        .chain_update(b"\n")
+        .chain_update(body)
+        .finalize()
+        .to_vec();
+
+    let ttl = chrono::Duration::seconds(state.config.idempotency_keys.ttl_secs);
+    let key = IdempotencyKey::new(user_id, key, digest, ttl);
+    match key.claim(&state.db).await {
+        Ok(KeyClaim::Claimed) => Ok(Idempotency::Process(Some(key))),
+        Ok(KeyClaim::Done(resource_id)) => Ok(Idempotency::Replay(resource_id)),
+        Ok(KeyClaim::InProgress) => Err(ApiResponse::failure(
+            "a request with this idempotency key is still being processed",
+        )
+        .conflict()),
+        Ok(KeyClaim::Mismatch) => Err(ApiResponse::failure(
+            "the idempotency key was already used for a different request",
+        )
+        .conflict()),
+        Err(e) => Err(ApiResponse::failure(e).internal_server_error()),
+    }
+}
+
+/// Records the resource a request created for its idempotency key. Without a resource the key
+/// is released so that the failed request can be retried with it.
+async fn finish_idempotency_key(
+    db: &DbPool,
+    key: Option<IdempotencyKey>,
+    resource_id: Option<&Uuid>,
+) {
+    let Some(key) = key else {
+        return;
+    };
+    let result = match resource_id {
+        Some(resource_id) => key.complete(db, resource_id).await,
+        None => key.release(db).await,
+    };
+    if let Err(e) = result {
+        log::error!("{e}");
+    }
+}
+
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod tests {
+    use crate::{
+        id::Uuid,
+        models::{
+            image::{Image, ImageFilter},
+            image_model::ImageModel,
+        },
+        queue::queue_channel,
+        testing, DbPool, SharedAppState,
+    };
+    use airtifex_core::{
+        image::{ImageModelCapabilities, ImageModelFeatures},
+        user::AccountType,
+    };
+
+    use axum::{
+        body::Body,
+        http::{header, Method, Request, StatusCode},
+        Router,
+    };
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    /// Requests an image of `prompt` with the idempotency key `key`.
+    async fn generate(
+        router: &Router,
+        token: &str,
+        key: &str,
+        prompt: &str,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/v1/image/generate")
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(super::IDEMPOTENCY_KEY_HEADER, key)
+            .body(Body::from(
+                json!({ "prompt": prompt, "model": "sd" }).to_string(),
+            ))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    /// Number of images of `owner`.
+    async fn images_of(db: &DbPool, owner: Uuid) -> usize {
+        let filter = ImageFilter {
+            owner,
+            tags: &[],
+            favorites_only: false,
+        };
+        Image::list(db, Some(&filter), None).await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn retries_get_the_same_image() {
+        let db = testing::db().await;
+        let alice_user = testing::user(&db, "alice", AccountType::User).await;
+        let alice = testing::token(&alice_user);
+        let bob = testing::token(&testing::user(&db, "bob", AccountType::User).await);
+        let features = ImageModelFeatures {
+            inpaint: false,
+            text_to_image: true,
+            image_to_image: false,
+        };
+        ImageModel::new(
+            "sd".into(),
+            None,
+            features,
+            &ImageModelCapabilities::default(),
+        )
+        .create(&db)
+        .await
+        .unwrap();
+        let mut state = testing::inner_state(db.clone(), testing::config(""));
+        let (tx_request, rx_request, _) = queue_channel(4);
+        state.tx_image_gen_req.insert("sd".into(), tx_request);
+        let router = testing::router(SharedAppState::from(std::sync::Arc::new(state)));
+
+        let (status, body) = generate(&router, &alice, "retry-1", "a lighthouse").await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        let image_id = body["data"]["image_id"].clone();
+        assert!(image_id.is_string());
+
+        let (status, body) = generate(&router, &alice, "retry-1", "a lighthouse").await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["data"]["image_id"], image_id);
+        assert_eq!(rx_request.len(), 1);
+        assert_eq!(images_of(&db, alice_user.id).await, 1);
+
+        let (status, body) = generate(&router, &alice, "retry-1", "a windmill").await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(
+            body["data"],
+            "the idempotency key was already used for a different request"
+        );
+
+        // keys of other users and other keys generate again
+        let (_, body) = generate(&router, &bob, "retry-1", "a lighthouse").await;
+        assert_ne!(body["data"]["image_id"], image_id);
+        let (_, body) = generate(&router, &alice, "retry-2", "a lighthouse").await;
+        assert_ne!(body["data"]["image_id"], image_id);
+        assert_eq!(rx_request.len(), 3);
+        assert_eq!(images_of(&db, alice_user.id).await, 2);
+    }
+
+    #[tokio::test]
+    async fn bodies_above_the_limit_of_the_group_are_too_large() {
+        let config = testing::config("body_limits:\n  users: 64\n");
+        let router = testing::router(testing::state(testing::db().await, config));
+        let body = json!({ "username": "alice", "password": "x".repeat(64) }).to_string();
+        let login = || {
+            Request::builder()
+                .method(Method::POST)
+                .uri("/api/v1/users/login")
+                .header(header::CONTENT_TYPE, "application/json")
+        };
+
+        // the announced length is rejected before the handler runs
+        let request = login()
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body.clone()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // a body without a length is cut off while it is read
+        let chunks = body
+            .as_bytes()
+            .chunks(16)
+            .map(|chunk| Ok::<_, std::io::Error>(chunk.to_vec()))
+            .collect::<Vec<_>>();
+        let request = login()
+            .header(header::TRANSFER_ENCODING, "chunked")
+            .body(Body::wrap_stream(futures_util::stream::iter(chunks)))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // bodies within the limit reach the handler
+        let body = json!({ "username": "alice", "password": "x" }).to_string();
+        let request = login()
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
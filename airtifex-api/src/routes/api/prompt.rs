@@ -1,27 +1,134 @@
 use crate::{
-    auth::Claims, gen::llm::InferenceRequest, id::Uuid, models::prompt::Prompt,
-    routes::handle_db_result_as_json, Error, SharedAppState, ToAxumResponse,
+    auth::Claims,
+    gen::llm::{token_channel, InferenceRequest, PromptTemplate},
+    id::Uuid,
+    models::{llm::LargeLanguageModel, prompt::Prompt, user::User},
+    moderation,
+    queue::{QueueError, QueueSender},
+    request_id,
+    routes::handle_db_result_as_json,
+    validation::{validate_inference_settings, validate_prompt, validate_prompt_bundle},
+    Error, SharedAppState, ToAxumResponse,
 };
 use airtifex_core::{
-    api_response::ApiResponse,
-    llm::{ChatStreamResult, InferenceSettings, OneshotInferenceRequest, PromptInspect},
+    api_response::{ApiResponse, ErrorCode},
+    llm::{
+        is_valid_template_variable, render_template, ChatStreamMessage, InferenceSettings,
+        OneshotInferenceRequest, PromptBundle, PromptBundleEntry, PromptFavoriteRequest,
+        PromptGenerateRequest, PromptImportQuery, PromptInspect, PromptListQuery,
+        PromptReorderRequest, PROMPT_BUNDLE_VERSION,
+    },
 };
+use std::collections::HashMap;
 
 use axum::{
     body::StreamBody,
-    extract::{Json, Path, State},
+    extract::{Json, Path, Query, State},
     response::{IntoResponse, Response},
     routing, Router,
 };
+use futures_util::{future, StreamExt};
 
 pub fn router() -> Router<SharedAppState> {
     Router::new()
         .route("/inference", routing::post(oneshot_inference))
         .route("/prompt", routing::get(list))
+        .route("/prompt/order", routing::patch(reorder))
+        .route("/prompt/export", routing::get(export))
+        .route("/prompt/import", routing::post(import))
         .route(
             "/prompt/:id",
             routing::get(get_prompt).delete(delete_prompt),
         )
+        .route("/prompt/:id/generate", routing::post(generate))
+        .route("/prompt/:id/favorite", routing::patch(set_favorite))
+}
+
+fn prompt_inspect(prompt: Prompt) -> PromptInspect {
+    PromptInspect {
+        variables: prompt.variables(),
+        id: prompt.id.to_string(),
+        prompt: prompt.prompt,
+        date: prompt.date,
+        username: prompt.username,
+        response: prompt.response,
+        model: prompt.model,
+        n_batch: prompt.n_batch.map(|v| v as usize),
+        num_predict: prompt.num_predict.map(|v| v as usize),
+        top_k: prompt.top_k.map(|v| v as usize),
+        top_p: prompt.top_p,
+        repeat_penalty: prompt.repeat_penalty,
+        temp: prompt.temp,
+        is_favorite: prompt.is_favorite,
+    }
+}
+
+fn bundle_entry(prompt: Prompt) -> PromptBundleEntry {
+    PromptBundleEntry {
+        variables: prompt.variables(),
+        prompt: prompt.prompt,
+        response: prompt.response,
+        model: prompt.model,
+        num_predict: prompt.num_predict.map(|v| v as usize),
+        n_batch: prompt.n_batch.map(|v| v as usize),
+        top_k: prompt.top_k.map(|v| v as usize),
+        top_p: prompt.top_p,
+        repeat_penalty: prompt.repeat_penalty,
+        temp: prompt.temp,
+        is_favorite: prompt.is_favorite,
+    }
+}
+
+/// Renders the prompt template, the error lists the variables without a value.
+fn render_prompt(
+    template: &str,
+    variables: &[String],
+    values: &HashMap<String, String>,
+) -> Result<String, String> {
+    if let Some(invalid) = variables.iter().find(|v| !is_valid_template_variable(v)) {
+        return Err(format!(
+            "invalid variable name `{invalid}`, only letters, digits and underscores are allowed"
+        ));
+    }
+    render_template(template, variables, values)
+        .map_err(|missing| format!("missing values for variables: {}", missing.join(", ")))
+}
+
+/// Response to a request for a model that doesn't exist.
+fn model_not_found(model: &str) -> Response {
+    ApiResponse::failure(format!("failed to find model {model}"))
+        .with_code(ErrorCode::ModelUnavailable)
+        .internal_server_error()
+}
+
+async fn stream_inference(
+    tx_model: &QueueSender<InferenceRequest>,
+    inference_request: InferenceRequest,
+    rx_tokens: flume::Receiver<ChatStreamMessage>,
+) -> Response {
+    log::info!("{inference_request:?}");
+
+    if let Err(e) = tx_model.send(inference_request) {
+        let response = ApiResponse::failure(&e).with_code(ErrorCode::ModelUnavailable);
+        return match e {
+            QueueError::Full => response.service_unavailable(),
+            QueueError::Stopped => response.internal_server_error(),
+        };
+    }
+
+    (
+        [
+            (axum::http::header::CONTENT_TYPE, "text/event-stream"),
+            (axum::http::header::TRANSFER_ENCODING, "chunked"),
+        ],
+        // the answer is streamed as raw tokens, the usage and end of the answer are left out
+        StreamBody::new(
+            rx_tokens
+                .into_stream()
+                .filter_map(|message| future::ready(message.into_result())),
+        ),
+    )
+        .into_response()
 }
 
 async fn oneshot_inference(
@@ -32,74 +139,156 @@ async fn oneshot_inference(
     let db = &state.db;
     with_user_guard!(claims, db);
 
-    let (tx_tokens, rx_tokens): (
-        flume::Sender<ChatStreamResult>,
-        flume::Receiver<ChatStreamResult>,
-    ) = flume::unbounded();
+    let Some((llm_config, tx_model)) = state.tx_inference_req.get(&request.model) else {
+        return model_not_found(&request.model);
+    };
+    let (tx_tokens, rx_tokens) = token_channel(llm_config);
+
+    let (prompt, template) = if request.variables.is_empty() {
+        (request.prompt, None)
+    } else {
+        match render_prompt(&request.prompt, &request.variables, &request.values) {
+            Ok(rendered) => (
+                rendered,
+                Some(PromptTemplate {
+                    template: request.prompt,
+                    variables: request.variables,
+                }),
+            ),
+            Err(e) => return ApiResponse::failure(e).bad_request(),
+        }
+    };
+
+    let user_settings = match User::get_settings(db, &claims.sub).await {
+        Ok(settings) => settings,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let settings = InferenceSettings {
+        num_predict: request.num_predict,
+        system_prompt: None,
+        n_batch: request.n_batch,
+        top_k: request.top_k,
+        top_p: request.top_p,
+        repeat_penalty: request.repeat_penalty,
+        temp: request.temp,
+        mirostat: request.mirostat,
+        mirostat_tau: request.mirostat_tau,
+        mirostat_eta: request.mirostat_eta,
+    }
+    .with_defaults(&InferenceSettings {
+        // the system prompt only applies to chats
+        system_prompt: None,
+        ..user_settings.chat
+    });
+    let limits = &state.config.request_limits.inference;
+    if let Err(e) = validate_prompt("prompt", &prompt, limits.max_prompt_length)
+        .and_then(|_| validate_inference_settings(limits, &settings))
+    {
+        return ApiResponse::failure(e).bad_request();
+    }
+    if let Err(e) = moderation::check_all(state.moderator.as_ref(), [prompt.as_str()]) {
+        return ApiResponse::failure(&e)
+            .with_code(e.code())
+            .unprocessable_entity();
+    }
+
+    let inference_request = InferenceRequest {
+        tx_tokens,
+        save: request.save,
+        user: claims.sub,
+        chat_data: None,
+        prompt,
+        settings,
+        play_back_tokens: request.play_back_tokens,
+        template,
+        json_schema: None,
+        seed: None,
+        repeat_last_n: None,
+        n_threads: None,
+        no_cache: request.no_cache,
+        queue_ticket: None,
+        request_id: request_id::current(),
+    };
+
+    stream_inference(tx_model, inference_request, rx_tokens).await
+}
+
+async fn generate(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<PromptGenerateRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let saved = match Prompt::get_prompt_for_user(db, &claims.sub, &id).await {
+        Ok(prompt) => prompt,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let variables = saved.variables();
+    let prompt = match render_prompt(&saved.prompt, &variables, &request.values) {
+        Ok(rendered) => rendered,
+        Err(e) => return ApiResponse::failure(e).bad_request(),
+    };
+    if let Err(e) = moderation::check_all(state.moderator.as_ref(), [prompt.as_str()]) {
+        return ApiResponse::failure(&e)
+            .with_code(e.code())
+            .unprocessable_entity();
+    }
+
+    let Some((llm_config, tx_model)) = state.tx_inference_req.get(&saved.model) else {
+        return model_not_found(&saved.model);
+    };
+    let (tx_tokens, rx_tokens) = token_channel(llm_config);
 
     let inference_request = InferenceRequest {
         tx_tokens,
         save: request.save,
         user: claims.sub,
         chat_data: None,
-        prompt: request.prompt,
+        prompt,
         settings: InferenceSettings {
-            num_predict: request.num_predict,
+            num_predict: saved.num_predict.map(|v| v as usize),
             system_prompt: None,
-            n_batch: request.n_batch,
-            top_k: request.top_k,
-            top_p: request.top_p,
-            repeat_penalty: request.repeat_penalty,
-            temp: request.temp,
+            n_batch: saved.n_batch.map(|v| v as usize),
+            top_k: saved.top_k.map(|v| v as usize),
+            top_p: saved.top_p,
+            repeat_penalty: saved.repeat_penalty,
+            temp: saved.temp,
+            mirostat: None,
+            mirostat_tau: None,
+            mirostat_eta: None,
         },
         play_back_tokens: request.play_back_tokens,
+        template: Some(PromptTemplate {
+            template: saved.prompt,
+            variables,
+        }),
+        json_schema: None,
+        seed: None,
+        repeat_last_n: None,
+        n_threads: None,
+        no_cache: request.no_cache,
+        queue_ticket: None,
+        request_id: request_id::current(),
     };
-    log::info!("{inference_request:?}");
-
-    if let Some((_, model)) = state.tx_inference_req.get(&request.model) {
-        if let Err(e) = model.send_async(inference_request).await {
-            return ApiResponse::failure(e).internal_server_error();
-        }
-    } else {
-        return ApiResponse::failure(format!("failed to find model {}", &request.model))
-            .internal_server_error();
-    }
 
-    (
-        [
-            (axum::http::header::CONTENT_TYPE, "text/event-stream"),
-            (axum::http::header::TRANSFER_ENCODING, "chunked"),
-        ],
-        StreamBody::new(rx_tokens.into_stream()),
-    )
-        .into_response()
+    stream_inference(tx_model, inference_request, rx_tokens).await
 }
 
-async fn list(claims: Claims, State(state): State<SharedAppState>) -> Response {
+async fn list(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Query(query): Query<PromptListQuery>,
+) -> Response {
     let db = &state.db;
     with_user_guard!(claims, db);
 
     handle_db_result_as_json(
-        Prompt::list_prompts_of_user(db, &claims.sub)
+        Prompt::list_prompts_of_user(db, &claims.sub, query.favorites_only.unwrap_or_default())
             .await
-            .map(|p| {
-                p.into_iter()
-                    .map(|p| PromptInspect {
-                        id: p.id.to_string(),
-                        prompt: p.prompt,
-                        date: p.date,
-                        username: p.username,
-                        response: p.response,
-                        model: p.model,
-                        n_batch: p.n_batch.map(|v| v as usize),
-                        num_predict: p.num_predict.map(|v| v as usize),
-                        top_k: p.top_k.map(|v| v as usize),
-                        top_p: p.top_p,
-                        repeat_penalty: p.repeat_penalty,
-                        temp: p.temp,
-                    })
-                    .collect::<Vec<_>>()
-            })
+            .map(|p| p.into_iter().map(prompt_inspect).collect::<Vec<_>>())
             .map_err(Error::from),
     )
 }
@@ -115,24 +304,141 @@ async fn get_prompt(
     handle_db_result_as_json(
         Prompt::get_prompt_for_user(db, &claims.sub, &id)
             .await
-            .map(|p| PromptInspect {
-                id: p.id.to_string(),
-                prompt: p.prompt,
-                date: p.date,
-                username: p.username,
-                response: p.response,
-                model: p.model,
-                n_batch: p.n_batch.map(|v| v as usize),
-                num_predict: p.num_predict.map(|v| v as usize),
-                top_k: p.top_k.map(|v| v as usize),
-                top_p: p.top_p,
-                repeat_penalty: p.repeat_penalty,
-                temp: p.temp,
+            .map(prompt_inspect)
+            .map_err(Error::from),
+    )
+}
+
+async fn set_favorite(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<PromptFavoriteRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    handle_db_result_as_json(
+        Prompt::set_favorite_for_user(db, &claims.sub, &id, request.is_favorite)
+            .await
+            .map_err(Error::from),
+    )
+}
+
+/// Stores the order of the prompts of the user, every id must be one of their prompts.
+async fn reorder(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Json(request): Json<PromptReorderRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let mut ids = Vec::with_capacity(request.ids.len());
+    for id in &request.ids {
+        match id.parse::<Uuid>() {
+            Ok(id) => ids.push(id),
+            Err(_) => {
+                return ApiResponse::failure(format!("invalid prompt id `{id}`")).bad_request()
+            }
+        }
+    }
+
+    let prompts = match Prompt::list_prompts_of_user(db, &claims.sub, false).await {
+        Ok(prompts) => prompts,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    if let Some(unknown) = ids.iter().find(|id| !prompts.iter().any(|p| p.id == **id)) {
+        return ApiResponse::failure(format!("prompt {unknown} doesn't exist")).bad_request();
+    }
+
+    handle_db_result_as_json(
+        Prompt::reorder_prompts_of_user(db, &claims.sub, &ids)
+            .await
+            .map_err(Error::from),
+    )
+}
+
+/// Responds with all prompts of the user in the order of the prompt list.
+async fn export(claims: Claims, State(state): State<SharedAppState>) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    handle_db_result_as_json(
+        Prompt::list_prompts_of_user(db, &claims.sub, false)
+            .await
+            .map(|prompts| PromptBundle {
+                version: PROMPT_BUNDLE_VERSION,
+                prompts: prompts.into_iter().map(bundle_entry).collect(),
             })
             .map_err(Error::from),
     )
 }
 
+/// Adds the prompts of an exported bundle to the prompts of the user, prompts with the same text
+/// as a saved prompt are skipped unless `on_duplicate=merge` is set.
+async fn import(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Query(query): Query<PromptImportQuery>,
+    Json(bundle): Json<PromptBundle>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(e) = validate_prompt_bundle(&state.config.request_limits.inference, &bundle) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let models = match LargeLanguageModel::list(db).await {
+        Ok(models) => models,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let unknown = bundle
+        .prompts
+        .iter()
+        .enumerate()
+        .find(|(_, entry)| !models.iter().any(|m| m.name == entry.model));
+    if let Some((n, entry)) = unknown {
+        return ApiResponse::failure(format!(
+            "prompt {} uses model {} which doesn't exist",
+            n + 1,
+            entry.model
+        ))
+        .with_code(ErrorCode::ModelUnavailable)
+        .bad_request();
+    }
+
+    let prompts = bundle
+        .prompts
+        .into_iter()
+        .map(|entry| {
+            let settings = entry.settings();
+            Prompt {
+                is_favorite: entry.is_favorite,
+                ..Prompt::new(
+                    claims.sub.clone(),
+                    entry.model,
+                    entry.prompt,
+                    entry.response,
+                    settings,
+                    entry.variables,
+                )
+            }
+        })
+        .collect::<Vec<_>>();
+
+    handle_db_result_as_json(
+        Prompt::import_for_user(
+            db,
+            &claims.sub,
+            &prompts,
+            query.on_duplicate.unwrap_or_default(),
+        )
+        .await
+        .map_err(Error::from),
+    )
+}
+
 async fn delete_prompt(
     claims: Claims,
     State(state): State<SharedAppState>,
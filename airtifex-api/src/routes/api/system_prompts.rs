@@ -0,0 +1,196 @@
+use crate::{
+    auth::Claims,
+    id::Uuid,
+    models::{audit::AuditEntry, system_prompt::SystemPrompt},
+    routes::{api::user_id, handle_db_result_as_json},
+    validation::validate_system_prompt,
+    DbPool, Error, SharedAppState, ToAxumResponse,
+};
+use airtifex_core::{
+    api_response::ApiResponse,
+    audit::AuditAction,
+    llm::SystemPromptRequest,
+    user::{AccountType, AuthenticatedUser},
+};
+
+use axum::{
+    extract::{Json, Path, State},
+    response::Response,
+    routing, Router,
+};
+
+pub fn router() -> Router<SharedAppState> {
+    Router::new()
+        .route(
+            "/system_prompts",
+            routing::get(list_system_prompts).post(create_system_prompt),
+        )
+        .route(
+            "/system_prompts/:id",
+            routing::get(get_system_prompt)
+                .put(update_system_prompt)
+                .delete(delete_system_prompt),
+        )
+}
+
+/// Returns the system prompts of the user together with the shared ones sorted by name.
+async fn list_system_prompts(claims: Claims, state: State<SharedAppState>) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    handle_db_result_as_json(
+        SystemPrompt::list_visible(db, &user_id)
+            .await
+            .map(|prompts| {
+                prompts
+                    .into_iter()
+                    .map(|prompt| prompt.inspect(&user_id))
+                    .collect::<Vec<_>>()
+            })
+            .map_err(Error::from),
+    )
+}
+
+async fn create_system_prompt(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Json(request): Json<SystemPromptRequest>,
+) -> Response {
+    let db = &state.db;
+    let user = with_user_guard!(claims, db);
+
+    if let Err(e) = validate_system_prompt(&state.config.request_limits.inference, &request) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    if let Err(response) = check_share_permission(db, &claims, &user, &request).await {
+        return response;
+    }
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let prompt = SystemPrompt::new(user_id, request);
+    if let Err(response) = check_system_prompt_name(db, &prompt).await {
+        return response;
+    }
+    if let Err(e) = prompt.create(db).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+    ApiResponse::success(prompt.inspect(&user_id)).ok()
+}
+
+async fn get_system_prompt(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match SystemPrompt::get_visible(db, &user_id, &id).await {
+        Ok(prompt) => ApiResponse::success(prompt.inspect(&user_id)).ok(),
+        Err(e) => ApiResponse::failure(e).not_found(),
+    }
+}
+
+/// Replaces a system prompt of the user, shared system prompts of other users can't be changed.
+async fn update_system_prompt(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<SystemPromptRequest>,
+) -> Response {
+    let db = &state.db;
+    let user = with_user_guard!(claims, db);
+
+    if let Err(e) = validate_system_prompt(&state.config.request_limits.inference, &request) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let mut prompt = match SystemPrompt::get_for_user(db, &user_id, &id).await {
+        Ok(prompt) => prompt,
+        Err(e) => return ApiResponse::failure(e).not_found(),
+    };
+    // a prompt that an admin shared before stays shared until an admin changes that
+    if request.shared != prompt.shared {
+        if let Err(response) = check_share_permission(db, &claims, &user, &request).await {
+            return response;
+        }
+    }
+    prompt.set(request);
+    if let Err(response) = check_system_prompt_name(db, &prompt).await {
+        return response;
+    }
+    if let Err(e) = prompt.update(db).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+    ApiResponse::success(prompt.inspect(&user_id)).ok()
+}
+
+async fn delete_system_prompt(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match SystemPrompt::delete_for_user(db, &user_id, &id).await {
+        Ok(true) => ApiResponse::success(()).ok(),
+        Ok(false) => ApiResponse::failure(format!("system prompt {id} doesn't exist")).not_found(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+/// Fails unless the user is an admin when the request shares the system prompt.
+async fn check_share_permission(
+    db: &DbPool,
+    claims: &Claims,
+    user: &AuthenticatedUser,
+    request: &SystemPromptRequest,
+) -> std::result::Result<(), Response> {
+    if !request.shared || user.account_type == AccountType::Admin {
+        return Ok(());
+    }
+    AuditEntry::record(
+        db,
+        claims,
+        AuditAction::AuthorizationFailed,
+        Some("system prompt share"),
+    )
+    .await;
+    Err(ApiResponse::failure("Only admins can share system prompts").forbidden())
+}
+
+/// Fails when another system prompt of the owner has the same name.
+async fn check_system_prompt_name(
+    db: &DbPool,
+    prompt: &SystemPrompt,
+) -> std::result::Result<(), Response> {
+    match SystemPrompt::name_taken(db, &prompt.user_id, &prompt.name, Some(&prompt.id)).await {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(ApiResponse::failure(format!(
+            "a system prompt named `{}` already exists",
+            prompt.name
+        ))
+        .conflict()),
+        Err(e) => Err(ApiResponse::failure(e).internal_server_error()),
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    auth::Claims,
+    gen::llm::tokenize::{detokenize, tokenize, TokenizeError},
+    validation::{validate_detokenize_request, validate_tokenize_request},
+    SharedAppState, ToAxumResponse,
+};
+use airtifex_core::{
+    api_response::{ApiResponse, ErrorCode},
+    llm::{DetokenizeRequest, DetokenizeResponse, TokenizeRequest, TokenizeResponse},
+};
+
+use axum::{
+    extract::{Json, State},
+    response::Response,
+    routing, Router,
+};
+
+pub fn router() -> Router<SharedAppState> {
+    Router::new()
+        .route("/tokenize", routing::post(tokenize_text))
+        .route("/detokenize", routing::post(detokenize_tokens))
+}
+
+/// Splits the text into the tokens of the model without generating anything.
+async fn tokenize_text(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Json(request): Json<TokenizeRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(e) = validate_tokenize_request(&state.config.request_limits.inference, &request) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let Some(model) = state.llm_models.get(&request.model) else {
+        return model_not_found(&request.model);
+    };
+
+    match model
+        .get()
+        .and_then(|model| tokenize(model.as_ref(), &request.text))
+    {
+        Ok(tokens) => ApiResponse::success(TokenizeResponse {
+            count: tokens.len(),
+            tokens,
+        })
+        .ok(),
+        Err(e) => tokenize_failure(e),
+    }
+}
+
+/// Turns token ids of the model back into text.
+async fn detokenize_tokens(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Json(request): Json<DetokenizeRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    if let Err(e) = validate_detokenize_request(&state.config.request_limits.inference, &request) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let Some(model) = state.llm_models.get(&request.model) else {
+        return model_not_found(&request.model);
+    };
+
+    match model
+        .get()
+        .and_then(|model| detokenize(model.as_ref(), &request.tokens))
+    {
+        Ok((text, tokens)) => ApiResponse::success(DetokenizeResponse {
+            text,
+            count: tokens.len(),
+            tokens,
+        })
+        .ok(),
+        Err(e) => tokenize_failure(e),
+    }
+}
+
+/// Response to a request for a model that doesn't exist.
+fn model_not_found(model: &str) -> Response {
+    ApiResponse::failure(format!("failed to find model {model}"))
+        .with_code(ErrorCode::ModelUnavailable)
+        .not_found()
+}
+
+fn tokenize_failure(e: TokenizeError) -> Response {
+    match e {
+        TokenizeError::NotLoaded => ApiResponse::failure(e)
+            .with_code(ErrorCode::ModelUnavailable)
+            .service_unavailable(),
+        TokenizeError::Tokenize(_) | TokenizeError::UnknownToken(_) => {
+            ApiResponse::failure(e).bad_request()
+        }
+    }
+}
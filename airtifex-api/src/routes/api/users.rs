@@ -1,32 +1,64 @@
 use crate::{
-    auth::{generate_jwt, Claims, JsonWebToken},
+    auth::{generate_jwt, generate_refresh_token, Claims, JsonWebToken},
     errors::Error,
-    models::user::User,
+    gen::image::square_thumbnail,
+    login_lockout::LoginKey,
+    models::{
+        audit::AuditEntry,
+        refresh_token::{RefreshToken, RefreshTokenError},
+        user::User,
+        Error as ModelError,
+    },
+    password::hash_password_blocking,
+    rate_limit::{retry_later, ClientIp},
     routes::handle_db_result_as_json,
+    validation::{validate_password, validate_ui_preferences, validate_user_settings},
     SharedAppState, ToAxumResponse,
 };
 use airtifex_core::{
     api_response::ApiResponse,
-    auth::Credentials,
+    audit::AuditAction,
+    auth::{Credentials, RefreshTokenRequest, REFRESH_TOKEN_EXPIRED},
     user::{
-        GetUserEntry, ListQuery, ListUserEntry, PasswordChangeRequest, UserEditRequest,
-        UserRegisterRequest,
+        ListQuery, ListUserEntry, PasswordChangeRequest, UiPreferences, UserEditRequest,
+        UserListPage, UserRegisterRequest, UserSettings,
     },
 };
 
 use axum::{
-    extract::{Path, Query, State},
-    response::Response,
+    extract::{Multipart, Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
     routing, Json, Router,
 };
 
+/// Content types of the images accepted as avatars.
+const AVATAR_CONTENT_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/bmp"];
+
 pub fn router() -> Router<SharedAppState> {
     Router::new()
         .route("/", routing::get(list).post(register))
         .route("/me", routing::get(me))
         .route("/login", routing::post(auth))
+        .route("/refresh", routing::post(refresh))
+        .route("/logout", routing::post(logout))
+        .route(
+            "/profile/avatar",
+            routing::get(own_avatar)
+                .post(upload_avatar)
+                .delete(remove_avatar),
+        )
+        .route(
+            "/profile/settings",
+            routing::get(own_settings).put(update_settings),
+        )
+        .route(
+            "/profile/preferences",
+            routing::get(own_preferences).put(update_preferences),
+        )
         .route("/:user", routing::get(info).post(update).delete(remove))
         .route("/:user/password", routing::post(change_password))
+        .route("/:user/avatar", routing::get(avatar))
 }
 
 async fn me(claims: Claims, state: State<SharedAppState>) -> Response {
@@ -43,68 +75,195 @@ async fn info(
     let db = &state.db;
     with_user_guard!(claims, db);
     if username != claims.sub && claims.role != "admin" {
-        return ApiResponse::failure("Unauthorized to access user data").unauthorized();
+        AuditEntry::record(
+            db,
+            &claims,
+            AuditAction::AuthorizationFailed,
+            Some(&username),
+        )
+        .await;
+        return ApiResponse::failure("Unauthorized to access user data").forbidden();
     }
     handle_db_result_as_json(
         User::get(db, &username)
             .await
-            .map(|user| GetUserEntry {
-                id: user.id.to_string(),
-                username: user.username,
-                email: user.email,
-                registration_date: user.registration_date,
-                account_type: user.account_type,
-            })
+            .map(list_entry)
             .map_err(Error::from),
     )
 }
 
+/// Returns a page of the users matching the query along with the number of matching users.
 async fn list(claims: Claims, state: State<SharedAppState>, query: Query<ListQuery>) -> Response {
     let db = &state.db;
     with_admin_guard!(claims, db);
 
+    let total = match User::count(db, &query).await {
+        Ok(total) => total as u64,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
     handle_db_result_as_json(
-        User::list(db, query.page, query.page_size, query.order_by)
+        User::list(db, &query)
             .await
             .map_err(Error::from)
-            .map(|users| {
-                users
-                    .into_iter()
-                    .map(|user| ListUserEntry {
-                        id: user.id.to_string(),
-                        username: user.username,
-                        email: user.email,
-                        account_type: user.account_type,
-                        registration_date: user.registration_date,
-                    })
-                    .collect::<Vec<_>>()
+            .map(|users| UserListPage {
+                users: users.into_iter().map(list_entry).collect(),
+                total,
             }),
     )
 }
 
+fn list_entry(user: User) -> ListUserEntry {
+    ListUserEntry {
+        id: user.id.to_string(),
+        username: user.username,
+        email: user.email,
+        account_type: user.account_type,
+        registration_date: user.registration_date,
+        last_login: user.last_login,
+    }
+}
+
 async fn register(
     claims: Claims,
     state: State<SharedAppState>,
-    user: Json<UserRegisterRequest>,
+    Json(request): Json<UserRegisterRequest>,
 ) -> Response {
     let db = &state.db;
     with_admin_guard!(claims, db);
-    let user: User = user.0.into();
-    handle_db_result_as_json(user.create(db).await.map(|_| user.id).map_err(Error::from))
+    let policy = &state.config.passwords;
+    if let Err(e) = validate_password("password", policy, &request.password) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let password = hash_password_blocking(request.password, policy.hash_iterations).await;
+    let user = User::new(
+        request.username,
+        password,
+        request.email,
+        request.account_type,
+    );
+    let result = user.create(db).await;
+    if result.is_ok() {
+        AuditEntry::record(db, &claims, AuditAction::UserCreated, Some(&user.username)).await;
+    }
+    handle_db_result_as_json(result.map(|_| user.id).map_err(Error::from))
 }
 
-async fn auth(state: State<SharedAppState>, credentials: Json<Credentials>) -> Response {
-    match User::authenticate(&state.db, credentials.0).await {
+/// Logs the user in. Failed logins are counted for the account and the address of the client,
+/// while either is locked logins are refused with `429 Too Many Requests`.
+async fn auth(
+    state: State<SharedAppState>,
+    ClientIp(ip): ClientIp,
+    credentials: Json<Credentials>,
+) -> Response {
+    let username = credentials.username().to_string();
+    let lockout = &state.config.login_lockout;
+    let account = LoginKey::Account(username.clone());
+    let mut keys = vec![account.clone()];
+    if !ip.is_empty() {
+        keys.push(LoginKey::Ip(ip));
+    }
+    if let Err(retry_after) = state.login_lockout.check(lockout, &keys) {
+        return retry_later("too many failed logins, try again later", retry_after);
+    }
+
+    let hash_iterations = state.config.passwords.hash_iterations;
+    match User::authenticate(&state.db, credentials.0, hash_iterations).await {
         Ok(user) => {
+            // the failures of the address are kept, one known password doesn't clear the guesses
+            // at other accounts
+            state.login_lockout.reset(&account);
             let token = match generate_jwt(&user.username, user.account_type) {
                 Ok(token) => token,
                 Err(e) => return ApiResponse::failure(e).unauthorized(),
             };
+            if let Err(e) = User::update_last_login(&state.db, &user.id).await {
+                log::error!("failed to record the login of {} - {e}", user.username);
+            }
+            if let Err(e) = RefreshToken::delete_expired(&state.db).await {
+                log::error!("failed to remove expired refresh tokens - {e}");
+            }
+            let refresh_token = match generate_refresh_token(&state.db, user.id).await {
+                Ok(token) => token,
+                Err(e) => return ApiResponse::failure(e).internal_server_error(),
+            };
+
+            ApiResponse::success(JsonWebToken {
+                token,
+                refresh_token,
+            })
+            .ok()
+        }
+        Err(e) => {
+            if matches!(e, ModelError::AuthenticationError(_)) {
+                // the actor is unknown, the attempted username is the target
+                let entry =
+                    AuditEntry::new(None, AuditAction::AuthenticationFailed, Some(username));
+                if let Err(e) = entry.create(&state.db).await {
+                    log::error!("failed to record failed login - {e}");
+                }
+                for key in state.login_lockout.record_failure(lockout, &keys) {
+                    let target = key.target();
+                    log::warn!("locked the login of {target} after too many failures");
+                    let entry = AuditEntry::new(None, AuditAction::LoginLocked, Some(target));
+                    if let Err(e) = entry.create(&state.db).await {
+                        log::error!("failed to record login lockout - {e}");
+                    }
+                }
+            }
+            ApiResponse::failure(e).unauthorized()
+        }
+    }
+}
 
-            ApiResponse::success(JsonWebToken { token }).ok()
+/// Issues a new access token from a valid refresh token. Fails with [`REFRESH_TOKEN_EXPIRED`]
+/// if the refresh token is expired or was revoked.
+async fn refresh(
+    state: State<SharedAppState>,
+    Json(request): Json<RefreshTokenRequest>,
+) -> Response {
+    let db = &state.db;
+    let owner = match RefreshToken::get_owner(db, &request.refresh_token).await {
+        Ok(owner) => owner,
+        Err(ModelError::RefreshTokenError(RefreshTokenError::GetError(
+            sqlx::Error::RowNotFound,
+        ))) => {
+            return ApiResponse::failure(format!(
+                "{REFRESH_TOKEN_EXPIRED} - the refresh token is invalid or was revoked"
+            ))
+            .unauthorized()
         }
-        Err(e) => ApiResponse::failure(e).unauthorized(),
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    if owner.is_expired() {
+        if let Err(e) = RefreshToken::delete(db, &owner.id).await {
+            log::error!("failed to remove expired refresh token - {e}");
+        }
+        return ApiResponse::failure(format!(
+            "{REFRESH_TOKEN_EXPIRED} - the refresh token has expired"
+        ))
+        .unauthorized();
     }
+
+    match generate_jwt(&owner.username, owner.account_type) {
+        Ok(token) => ApiResponse::success(JsonWebToken {
+            token,
+            refresh_token: request.refresh_token,
+        })
+        .ok(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+/// Revokes the refresh token, the access token stays valid until it expires.
+async fn logout(
+    state: State<SharedAppState>,
+    Json(request): Json<RefreshTokenRequest>,
+) -> Response {
+    handle_db_result_as_json(
+        RefreshToken::delete_by_token(&state.db, &request.refresh_token)
+            .await
+            .map_err(Error::from),
+    )
 }
 
 async fn change_password(
@@ -115,14 +274,26 @@ async fn change_password(
 ) -> Response {
     let db = &state.db;
     with_admin_guard!(claims, db);
-    if request.new_password.is_empty() {
-        return ApiResponse::failure("Password cannot be empty").bad_request();
+    without_impersonation!(claims, db, "password change");
+    let policy = &state.config.passwords;
+    if let Err(e) = validate_password("new_password", policy, &request.new_password) {
+        return ApiResponse::failure(e).bad_request();
     }
-    handle_db_result_as_json(
-        User::change_pasword_by_username(db, &username, request.new_password.clone())
-            .await
-            .map_err(Error::from),
+    let result = User::change_pasword_by_username(
+        db,
+        &username,
+        request.new_password.clone(),
+        policy.hash_iterations,
     )
+    .await;
+    if result.is_ok() {
+        // sessions started with the old password have to log in again
+        if let Err(e) = RefreshToken::delete_by_username(db, &username).await {
+            log::error!("failed to revoke refresh tokens of {username} - {e}");
+        }
+        AuditEntry::record(db, &claims, AuditAction::PasswordChanged, Some(&username)).await;
+    }
+    handle_db_result_as_json(result.map_err(Error::from))
 }
 
 async fn remove(
@@ -132,11 +303,11 @@ async fn remove(
 ) -> Response {
     let db = &state.db;
     with_admin_guard!(claims, db);
-    handle_db_result_as_json(
-        User::delete_by_name(db, &username)
-            .await
-            .map_err(Error::from),
-    )
+    let result = User::delete_by_name(db, &username).await;
+    if result.is_ok() {
+        AuditEntry::record(db, &claims, AuditAction::UserDeleted, Some(&username)).await;
+    }
+    handle_db_result_as_json(result.map_err(Error::from))
 }
 
 async fn update(
@@ -147,9 +318,243 @@ async fn update(
 ) -> Response {
     let db = &state.db;
     with_admin_guard!(claims, db);
+    let result = User::update_by_name(db, &username, request.email, request.account_type).await;
+    if result.is_ok() {
+        AuditEntry::record(db, &claims, AuditAction::UserEdited, Some(&username)).await;
+    }
+    handle_db_result_as_json(result.map_err(Error::from))
+}
+
+async fn avatar_response(state: &SharedAppState, username: &str) -> Response {
+    match User::get_avatar(&state.db, username).await {
+        Ok(Some(avatar)) => ([(header::CONTENT_TYPE, "image/png")], avatar).into_response(),
+        Ok(None) => ApiResponse::failure("User has no avatar").not_found(),
+        Err(e) => ApiResponse::failure(e).not_found(),
+    }
+}
+
+async fn own_avatar(claims: Claims, state: State<SharedAppState>) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    avatar_response(&state, &claims.sub).await
+}
+
+async fn avatar(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Path(username): Path<String>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    avatar_response(&state, &username).await
+}
+
+/// Sets the avatar of the user from the first field of a multipart upload. The image is cropped
+/// and scaled down to a thumbnail before it is saved.
+async fn upload_avatar(
+    claims: Claims,
+    state: State<SharedAppState>,
+    mut multipart: Multipart,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    let config = &state.config.avatar;
+
+    let mut field = match multipart.next_field().await {
+        Ok(Some(field)) => field,
+        Ok(None) => return ApiResponse::failure("Missing avatar image").bad_request(),
+        Err(e) => return ApiResponse::failure(e).bad_request(),
+    };
+    match field.content_type() {
+        Some(content_type) if AVATAR_CONTENT_TYPES.contains(&content_type) => {}
+        content_type => {
+            return ApiResponse::failure(format!(
+                "Unsupported avatar type `{}`, expected one of {}",
+                content_type.unwrap_or_default(),
+                AVATAR_CONTENT_TYPES.join(", ")
+            ))
+            .bad_request()
+        }
+    }
+
+    let mut data = Vec::new();
+    loop {
+        match field.chunk().await {
+            Ok(Some(chunk)) => {
+                if data.len() + chunk.len() > config.max_upload_size {
+                    return ApiResponse::failure(format!(
+                        "Avatar can't be larger than {} bytes",
+                        config.max_upload_size
+                    ))
+                    .payload_too_large();
+                }
+                data.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            Err(e) => return ApiResponse::failure(e).bad_request(),
+        }
+    }
+
+    let size = config.size;
+    let thumbnail = match tokio::task::spawn_blocking(move || square_thumbnail(&data, size)).await {
+        Ok(Ok(thumbnail)) => thumbnail,
+        Ok(Err(e)) => return ApiResponse::failure(e).bad_request(),
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    handle_db_result_as_json(
+        User::set_avatar(db, &claims.sub, Some(&thumbnail))
+            .await
+            .map_err(Error::from),
+    )
+}
+
+async fn remove_avatar(claims: Claims, state: State<SharedAppState>) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    handle_db_result_as_json(
+        User::set_avatar(db, &claims.sub, None)
+            .await
+            .map_err(Error::from),
+    )
+}
+
+/// Returns the defaults used for the generation parameters left out of requests.
+async fn own_settings(claims: Claims, state: State<SharedAppState>) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
     handle_db_result_as_json(
-        User::update_by_name(db, &username, request.email, request.account_type)
+        User::get_settings(db, &claims.sub)
             .await
             .map_err(Error::from),
     )
 }
+
+async fn update_settings(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Json(settings): Json<UserSettings>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    if let Err(e) = validate_user_settings(&state.config.request_limits, &settings) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    handle_db_result_as_json(
+        User::set_settings(db, &claims.sub, &settings)
+            .await
+            .map_err(Error::from),
+    )
+}
+
+/// Returns the web app preferences synced between the devices of the user.
+async fn own_preferences(claims: Claims, state: State<SharedAppState>) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    handle_db_result_as_json(
+        User::get_preferences(db, &claims.sub)
+            .await
+            .map_err(Error::from),
+    )
+}
+
+/// Replaces the web app preferences unless the stored ones were changed later and returns the
+/// stored preferences either way. Preferences without `updated_at` and those changed in the
+/// future of the server are saved with the current time.
+async fn update_preferences(
+    claims: Claims,
+    state: State<SharedAppState>,
+    Json(preferences): Json<UiPreferences>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    if let Err(e) = validate_ui_preferences(&preferences) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let now = chrono::Utc::now();
+    let updated_at = preferences.updated_at.map_or(now, |t| t.min(now));
+    handle_db_result_as_json(
+        User::set_preferences(db, &claims.sub, &preferences, updated_at)
+            .await
+            .map_err(Error::from),
+    )
+}
+
+#[cfg(all(test, feature = "sqlite", not(feature = "postgres")))]
+mod tests {
+    use crate::{
+        auth::generate_impersonation_jwt,
+        models::{audit::AuditEntry, user::User},
+        testing,
+    };
+    use airtifex_core::{audit::AuditAction, auth::Credentials, user::AccountType};
+
+    use axum::{
+        body::Body,
+        http::{header, Method, Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn passwords_cant_be_changed_while_impersonating() {
+        let db = testing::db().await;
+        testing::user(&db, "alice", AccountType::Admin).await;
+        testing::user(&db, "carol", AccountType::Admin).await;
+        let state = testing::state(db, testing::config(""));
+        let router = testing::router(state.clone());
+        let (token, _) = generate_impersonation_jwt("carol", AccountType::Admin, "alice").unwrap();
+
+        let (status, body) = testing::send(
+            &router,
+            Method::POST,
+            "/api/v1/users/carol/password",
+            Some(&token),
+            Some(serde_json::json!({ "new_password": "Another-password-42" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            body["data"],
+            "password change isn't allowed while impersonating a user"
+        );
+        let credentials = Credentials::new("carol", testing::PASSWORD);
+        assert!(User::authenticate(&state.db, credentials, 1).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn locked_account_refuses_the_correct_password() {
+        let db = testing::db().await;
+        testing::user(&db, "alice", AccountType::User).await;
+        let config = testing::config(
+            "login_lockout:\n  max_account_failures: 2\n  backoff_secs: 0\n  lockout_secs: 60\n",
+        );
+        let state = testing::state(db, config);
+        let router = testing::router(state.clone());
+        let login = |password: &str| {
+            let credentials = serde_json::json!({ "username": "alice", "password": password });
+            Request::builder()
+                .method(Method::POST)
+                .uri("/api/v1/users/login")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(credentials.to_string()))
+                .unwrap()
+        };
+
+        for _ in 0..2 {
+            let response = router.clone().oneshot(login("wrong")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = router.oneshot(login(testing::PASSWORD)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after = response.headers()[header::RETRY_AFTER].to_str().unwrap();
+        assert!(
+            (1..=60).contains(&retry_after.parse::<u32>().unwrap()),
+            "{retry_after}"
+        );
+
+        let locks = AuditEntry::list(&state.db, None, None, Some(AuditAction::LoginLocked))
+            .await
+            .unwrap();
+        let targets: Vec<_> = locks.iter().map(|e| e.target.as_deref()).collect();
+        assert_eq!(targets, [Some("alice")]);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{
+    auth::Claims,
+    id::Uuid,
+    models::webhook::Webhook,
+    routes::{api::user_id, handle_db_result_as_json},
+    validation::validate_webhook_request,
+    webhook, Error, SharedAppState, ToAxumResponse,
+};
+use airtifex_core::{
+    api_response::ApiResponse,
+    webhook::{
+        WebhookCreateRequest, WebhookCreateResponse, WebhookData, WebhookEvent, WebhookTestResponse,
+    },
+};
+
+use axum::{
+    extract::{Json, Path, State},
+    response::Response,
+    routing, Router,
+};
+
+pub fn router() -> Router<SharedAppState> {
+    Router::new()
+        .route("/", routing::get(list).post(create))
+        .route("/:id", routing::delete(remove))
+        .route("/:id/test", routing::post(test))
+}
+
+/// Returns the webhooks of the user without their secrets.
+async fn list(claims: Claims, State(state): State<SharedAppState>) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    handle_db_result_as_json(
+        Webhook::list_for_user(db, &user_id)
+            .await
+            .map(|webhooks| {
+                webhooks
+                    .into_iter()
+                    .map(Webhook::inspect)
+                    .collect::<Vec<_>>()
+            })
+            .map_err(Error::from),
+    )
+}
+
+async fn create(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Json(request): Json<WebhookCreateRequest>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+
+    let config = &state.config.webhooks;
+    if let Err(e) = validate_webhook_request(config, &request) {
+        return ApiResponse::failure(e).bad_request();
+    }
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match Webhook::count_for_user(db, &user_id).await {
+        Ok(count) if count as usize >= config.max_per_user => {
+            return ApiResponse::failure(format!(
+                "a user can't have more than {} webhooks",
+                config.max_per_user
+            ))
+            .bad_request()
+        }
+        Ok(_) => {}
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    }
+
+    let webhook = Webhook::new(user_id, request);
+    if let Err(e) = webhook.create(db).await {
+        return ApiResponse::failure(e).internal_server_error();
+    }
+    ApiResponse::success(WebhookCreateResponse {
+        webhook_id: webhook.id.to_string(),
+    })
+    .ok()
+}
+
+async fn remove(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match Webhook::delete_for_user(db, &user_id, &id).await {
+        Ok(true) => ApiResponse::success(()).ok(),
+        Ok(false) => ApiResponse::failure(format!("webhook {id} doesn't exist")).not_found(),
+        Err(e) => ApiResponse::failure(e).internal_server_error(),
+    }
+}
+
+/// Sends a `test` event to the webhook once and reports how the webhook responded.
+async fn test(
+    claims: Claims,
+    State(state): State<SharedAppState>,
+    Path(id): Path<Uuid>,
+) -> Response {
+    let db = &state.db;
+    with_user_guard!(claims, db);
+    let user_id = match user_id(db, &claims.sub).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let webhook = match Webhook::get_for_user(db, &user_id, &id).await {
+        Ok(webhook) => webhook,
+        Err(e) => return ApiResponse::failure(e).not_found(),
+    };
+
+    let payload = webhook::payload(WebhookEvent::Test, WebhookData::Test {});
+    let result = state.webhooks.attempt(&webhook, &payload).await;
+    state.webhooks.record(&webhook.id, &result).await;
+    let response = match result {
+        Ok(status) => WebhookTestResponse {
+            delivered: status.is_success(),
+            status: Some(status.as_u16()),
+            error: None,
+        },
+        Err(e) => WebhookTestResponse {
+            delivered: false,
+            status: None,
+            error: Some(e),
+        },
+    };
+    ApiResponse::success(response).ok()
+}
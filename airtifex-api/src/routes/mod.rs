@@ -1,15 +1,70 @@
 pub mod api;
+pub mod public;
 pub mod r#static;
 
-use crate::ToAxumResponse;
+use crate::{
+    config::{Config, ShareConfig},
+    id::Uuid,
+    models::image_sample::ImageSample,
+    DbPool, ToAxumResponse,
+};
 use airtifex_core::api_response::ApiResponse;
 
-use axum::response::Response;
+use axum::{
+    body::StreamBody,
+    http::header,
+    response::{IntoResponse, Response},
+};
 use serde::Serialize;
+use std::sync::Arc;
+
+/// Bytes of the PNG of a sample read at once when streaming it.
+const SAMPLE_CHUNK_SIZE: i64 = 256 * 1024;
 
 fn handle_db_result_as_json<T: Serialize>(result: crate::Result<T>) -> Response {
     match result {
         Ok(data) => ApiResponse::success(&data).ok(),
-        Err(e) => ApiResponse::failure(e).internal_server_error(),
+        Err(e) => ApiResponse::failure(&e)
+            .with_code(e.code())
+            .internal_server_error(),
     }
 }
+
+/// Public URL of a share link, `path` is relative to `/public`.
+fn share_url(config: &Config, share: &ShareConfig, path: &str) -> String {
+    let base_url = share
+        .base_url
+        .clone()
+        .unwrap_or_else(|| format!("http://{}:{}", config.listen_addr, config.listen_port));
+    format!("{}/public/{path}", base_url.trim_end_matches('/'))
+}
+
+/// Streams the PNG of a sample in chunks so that large samples aren't loaded at once.
+async fn stream_sample(db: Arc<DbPool>, image_id: Uuid, n: i32) -> Response {
+    let length = match ImageSample::data_length(&db, &image_id, n).await {
+        Ok(length) => length,
+        Err(e) => return ApiResponse::failure(e).not_found(),
+    };
+    let chunks = futures_util::stream::unfold(0, move |offset| {
+        let db = db.clone();
+        async move {
+            if offset >= length {
+                return None;
+            }
+            let len = SAMPLE_CHUNK_SIZE.min(length - offset);
+            match ImageSample::data_chunk(&db, &image_id, n, offset, len).await {
+                Ok(chunk) => Some((Ok(chunk), offset + len)),
+                // the response is already started, ending the body early is all that is left
+                Err(e) => Some((Err(e), length)),
+            }
+        }
+    });
+    (
+        [
+            (header::CONTENT_TYPE, "image/png".to_string()),
+            (header::CONTENT_LENGTH, length.to_string()),
+        ],
+        StreamBody::new(chunks),
+    )
+        .into_response()
+}
@@ -0,0 +1,172 @@
+use crate::{
+    gen::llm::load::llm_load_status,
+    models::{chat::Chat, chat_entry::ChatEntry, image::Image},
+    rate_limit::{rate_limit, RouteGroup},
+    routes::stream_sample,
+    share::{ChatShareToken, ShareToken},
+    SharedAppState, ToAxumResponse,
+};
+use airtifex_core::{api_response::ApiResponse, llm::ChatEntryType};
+
+use axum::{
+    extract::{Path, State},
+    http::header,
+    middleware,
+    response::{Html, IntoResponse, Response},
+    routing, Router,
+};
+use std::fmt::Write;
+
+/// Routes that can be used without an account.
+pub fn router(state: SharedAppState) -> Router<SharedAppState> {
+    Router::new()
+        .route("/public/image/:token", routing::get(shared_image))
+        .route_layer(middleware::from_fn_with_state(
+            (state.clone(), RouteGroup::Image),
+            rate_limit,
+        ))
+        .merge(
+            Router::new()
+                .route("/public/chat/:token", routing::get(shared_chat))
+                .route_layer(middleware::from_fn_with_state(
+                    (state, RouteGroup::Chat),
+                    rate_limit,
+                )),
+        )
+        .route("/ready", routing::get(ready))
+}
+
+/// Readiness probe, responds with `503 Service Unavailable` until every model is loaded.
+async fn ready(State(state): State<SharedAppState>) -> Response {
+    let status = llm_load_status(&state);
+    if status.ready {
+        ApiResponse::success(status).ok()
+    } else {
+        ApiResponse::success(status).service_unavailable()
+    }
+}
+
+/// Serves the image sample of a share link.
+async fn shared_image(State(state): State<SharedAppState>, Path(token): Path<String>) -> Response {
+    let db = &state.db;
+    let (token, signature) = match ShareToken::decode(&token) {
+        Ok(decoded) => decoded,
+        Err(e) => return ApiResponse::failure(e).forbidden(),
+    };
+    // the image might have been deleted, which also invalidates its links
+    let Ok(generation) = Image::share_generation(db, &token.image_id).await else {
+        return ApiResponse::failure("invalid share link").forbidden();
+    };
+    let now = chrono::Utc::now().timestamp();
+    if let Err(e) = token.verify(&signature, &state.config.jwt_secret, generation, now) {
+        return ApiResponse::failure(e).forbidden();
+    }
+
+    stream_sample(state.db.clone(), token.image_id, token.n_sample).await
+}
+
+/// Serves the read-only transcript of the chat of a share link. The page has no way to continue
+/// the chat and isn't cached so that revoking the links takes effect immediately.
+async fn shared_chat(State(state): State<SharedAppState>, Path(token): Path<String>) -> Response {
+    let db = &state.db;
+    let (token, signature) = match ChatShareToken::decode(&token) {
+        Ok(decoded) => decoded,
+        Err(e) => return ApiResponse::failure(e).forbidden(),
+    };
+    // the chat might have been deleted, which also invalidates its links
+    let Ok(generation) = Chat::share_generation(db, &token.chat_id).await else {
+        return ApiResponse::failure("invalid share link").forbidden();
+    };
+    let now = chrono::Utc::now().timestamp();
+    if let Err(e) = token.verify(&signature, &state.config.jwt_secret, generation, now) {
+        return ApiResponse::failure(e).forbidden();
+    }
+
+    let chat = match Chat::get(db, &token.chat_id).await {
+        Ok(chat) => chat,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let entries = match ChatEntry::get_chat_entries(db, &chat.id, &chat.username).await {
+        Ok(entries) => entries,
+        Err(e) => return ApiResponse::failure(e).internal_server_error(),
+    };
+    let system_prompt = if token.include_system_prompt {
+        chat.system_prompt.as_deref()
+    } else {
+        None
+    };
+
+    (
+        [(header::CACHE_CONTROL, "no-store")],
+        Html(transcript_page(&chat, system_prompt, &entries)),
+    )
+        .into_response()
+}
+
+/// Renders the transcript of a chat as a standalone HTML page.
+fn transcript_page(chat: &Chat, system_prompt: Option<&str>, entries: &[ChatEntry]) -> String {
+    let title = escape_html(&chat.title);
+    let mut page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body {{ background: #212529; color: #f8f9fa; font-family: sans-serif; margin: 0; }}
+main {{ max-width: 48rem; margin: 0 auto; padding: 1rem; }}
+.meta {{ color: #adb5bd; }}
+.entry {{ border-radius: 0.5rem; margin: 1rem 0; padding: 0.5rem 1rem; }}
+.entry h2 {{ font-size: 0.875rem; margin: 0.25rem 0; color: #adb5bd; }}
+.entry p {{ white-space: pre-wrap; margin: 0.5rem 0; }}
+.system {{ border: 1px dashed #6c757d; }}
+.user {{ background: #343a40; }}
+.bot {{ background: #2b3035; }}
+</style>
+</head>
+<body>
+<main>
+<h1>{title}</h1>
+<p class="meta">{model} &middot; {date}</p>
+"#,
+        model = escape_html(&chat.model),
+        date = chat.start_date.format("%a, %d %b %Y %H:%M:%S UTC"),
+    );
+    if let Some(system_prompt) = system_prompt {
+        let _ = writeln!(
+            page,
+            "<section class=\"entry system\"><h2>System prompt</h2><p>{}</p></section>",
+            escape_html(system_prompt)
+        );
+    }
+    for entry in entries {
+        let author = match entry.entry_type {
+            ChatEntryType::User => "User",
+            ChatEntryType::Bot => "Assistant",
+        };
+        let _ = writeln!(
+            page,
+            "<section class=\"entry {}\"><h2>{author}</h2><p>{}</p></section>",
+            entry.entry_type.to_str(),
+            escape_html(&entry.content)
+        );
+    }
+    page.push_str("</main>\n</body>\n</html>\n");
+    page
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,193 @@
+//! Share links of image samples and chat transcripts. A link carries a token signed with the JWT
+//! secret so that it can be checked without storing it, revoking the links of an image or chat
+//! bumps its share generation which is part of the signed data.
+
+use crate::id::Uuid;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use thiserror::Error as ErrorType;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, ErrorType)]
+pub enum ShareTokenError {
+    #[error("malformed share token")]
+    Malformed,
+    #[error("invalid share token signature")]
+    InvalidSignature,
+    #[error("share link expired")]
+    Expired,
+}
+
+/// Token of a link to an image sample.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShareToken {
+    pub image_id: Uuid,
+    pub n_sample: i32,
+    /// Unix timestamp after which the token is rejected.
+    pub expires: i64,
+}
+
+impl ShareToken {
+    pub fn new(image_id: Uuid, n_sample: i32, expires: i64) -> Self {
+        Self {
+            image_id,
+            n_sample,
+            expires,
+        }
+    }
+
+    /// Encodes the token as `<image id>.<sample>.<expiry>.<signature>`.
+    pub fn sign(&self, secret: &str, generation: i64) -> String {
+        let signature = self.mac(secret, generation).finalize().into_bytes();
+        format!(
+            "{}.{}.{}.{}",
+            self.image_id,
+            self.n_sample,
+            self.expires,
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// Splits an encoded token into its claims and signature without checking the latter, see
+    /// [`verify`](Self::verify).
+    pub fn decode(token: &str) -> Result<(Self, Vec<u8>), ShareTokenError> {
+        let ([image_id, n_sample, expires], signature) = split(token)?;
+        let token = Self {
+            image_id: image_id.parse().map_err(|_| ShareTokenError::Malformed)?,
+            n_sample: n_sample.parse().map_err(|_| ShareTokenError::Malformed)?,
+            expires: expires.parse().map_err(|_| ShareTokenError::Malformed)?,
+        };
+        Ok((token, signature))
+    }
+
+    /// Checks that `signature` was created for these claims and the current share generation of
+    /// the image and that the token didn't expire at `now`.
+    pub fn verify(
+        &self,
+        signature: &[u8],
+        secret: &str,
+        generation: i64,
+        now: i64,
+    ) -> Result<(), ShareTokenError> {
+        check(self.mac(secret, generation), signature, self.expires, now)
+    }
+
+    fn mac(&self, secret: &str, generation: i64) -> HmacSha256 {
+        mac(
+            secret,
+            &format!(
+                "{}:{}:{}:{generation}",
+                self.image_id, self.n_sample, self.expires
+            ),
+        )
+    }
+}
+
+/// Token of a link to the read-only transcript of a chat.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatShareToken {
+    pub chat_id: Uuid,
+    /// Whether the transcript shows the system prompt of the chat.
+    pub include_system_prompt: bool,
+    /// Unix timestamp after which the token is rejected.
+    pub expires: i64,
+}
+
+impl ChatShareToken {
+    pub fn new(chat_id: Uuid, include_system_prompt: bool, expires: i64) -> Self {
+        Self {
+            chat_id,
+            include_system_prompt,
+            expires,
+        }
+    }
+
+    /// Encodes the token as `<chat id>.<0 or 1>.<expiry>.<signature>`.
+    pub fn sign(&self, secret: &str, generation: i64) -> String {
+        let signature = self.mac(secret, generation).finalize().into_bytes();
+        format!(
+            "{}.{}.{}.{}",
+            self.chat_id,
+            u8::from(self.include_system_prompt),
+            self.expires,
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// Splits an encoded token into its claims and signature without checking the latter, see
+    /// [`verify`](Self::verify).
+    pub fn decode(token: &str) -> Result<(Self, Vec<u8>), ShareTokenError> {
+        let ([chat_id, include_system_prompt, expires], signature) = split(token)?;
+        let include_system_prompt = match include_system_prompt {
+            "0" => false,
+            "1" => true,
+            _ => return Err(ShareTokenError::Malformed),
+        };
+        let token = Self {
+            chat_id: chat_id.parse().map_err(|_| ShareTokenError::Malformed)?,
+            include_system_prompt,
+            expires: expires.parse().map_err(|_| ShareTokenError::Malformed)?,
+        };
+        Ok((token, signature))
+    }
+
+    /// Checks that `signature` was created for these claims and the current share generation of
+    /// the chat and that the token didn't expire at `now`.
+    pub fn verify(
+        &self,
+        signature: &[u8],
+        secret: &str,
+        generation: i64,
+        now: i64,
+    ) -> Result<(), ShareTokenError> {
+        check(self.mac(secret, generation), signature, self.expires, now)
+    }
+
+    fn mac(&self, secret: &str, generation: i64) -> HmacSha256 {
+        // prefixed so that a chat token is never a valid image token
+        mac(
+            secret,
+            &format!(
+                "chat:{}:{}:{}:{generation}",
+                self.chat_id, self.include_system_prompt, self.expires
+            ),
+        )
+    }
+}
+
+/// Splits a token into its three claims and the decoded signature.
+fn split(token: &str) -> Result<([&str; 3], Vec<u8>), ShareTokenError> {
+    let mut parts = token.split('.');
+    let (Some(first), Some(second), Some(third), Some(signature), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return Err(ShareTokenError::Malformed);
+    };
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| ShareTokenError::Malformed)?;
+    Ok(([first, second, third], signature))
+}
+
+fn mac(secret: &str, data: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    mac
+}
+
+fn check(mac: HmacSha256, signature: &[u8], expires: i64, now: i64) -> Result<(), ShareTokenError> {
+    mac.verify_slice(signature)
+        .map_err(|_| ShareTokenError::InvalidSignature)?;
+    if now > expires {
+        return Err(ShareTokenError::Expired);
+    }
+    Ok(())
+}
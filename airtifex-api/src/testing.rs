@@ -0,0 +1,196 @@
+//! Helpers of the tests, an in-memory database and the state of the server around it.
+
+use crate::{
+    auth::generate_jwt,
+    config::Config,
+    gen::image::{backend, dispatch, progress::ImageProgressStreams},
+    id::{Uuid, V1Context},
+    models::{image::Image, image_model::ImageModel, user::User},
+    moderation,
+    password::hash_password,
+    queue::queue_channel,
+    routes::{api, public},
+    webhook::Webhooks,
+    DbPool, InnerAppState, SharedAppState,
+};
+use airtifex_core::{image::ImageStatus, user::AccountType};
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use axum_extra::extract::cookie::Key;
+use std::{io::Write, sync::Arc, time::Duration};
+use tower::ServiceExt;
+
+/// Password of the users created by [`user`].
+pub const PASSWORD: &str = "correct horse battery staple";
+
+/// Settings every test config starts with, the server isn't bound to the address.
+const BASE_CONFIG: &str = r#"
+listen_addr: 127.0.0.1
+listen_port: 6901
+db_url: "sqlite::memory:"
+jwt_secret: secret-of-the-tests
+passwords:
+  hash_iterations: 1
+"#;
+
+/// Migrated database that only lives as long as the pool, it has a single connection because
+/// every connection to `sqlite::memory:` opens a database of its own.
+pub async fn db() -> DbPool {
+    let db = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("in-memory database opens");
+    sqlx::migrate!("migrations/sqlite")
+        .run(&db)
+        .await
+        .expect("migrations run");
+    db
+}
+
+/// Reads the config of the tests with `yaml` appended to the base settings.
+pub fn config(yaml: &str) -> Config {
+    let mut file = tempfile::NamedTempFile::new().expect("temporary config is created");
+    write!(file, "{BASE_CONFIG}{yaml}").expect("temporary config is written");
+    Config::read(file.path()).expect("config of the test is valid")
+}
+
+/// State of a server without models.
+pub fn state(db: DbPool, config: Config) -> SharedAppState {
+    SharedAppState::from(Arc::new(inner_state(db, config)))
+}
+
+/// State of a server without models that tests can add models to.
+pub fn inner_state(db: DbPool, config: Config) -> InnerAppState {
+    set_jwt_secret();
+    let db = Arc::new(db);
+    InnerAppState {
+        uuid_context: V1Context::new(0),
+        key: Key::generate(),
+        tx_inference_req: Default::default(),
+        llm_reloaders: Default::default(),
+        llm_commands: Default::default(),
+        llm_models: Default::default(),
+        tx_image_gen_req: Default::default(),
+        rate_limiter: Default::default(),
+        login_lockout: Default::default(),
+        chat_streams: Default::default(),
+        image_progress: ImageProgressStreams::new(config.image_cancel.grace_period()),
+        sample_rerolls: Default::default(),
+        metrics: Default::default(),
+        webhooks: Webhooks::new(db.clone(), config.webhooks.clone()),
+        moderator: moderation::from_config(&config.moderation).expect("moderation config is valid"),
+        db,
+        config,
+    }
+}
+
+/// Creates the image models of the config like the server does on start and generates their
+/// images with the configured backends on the runtime of the test.
+pub async fn start_image_models(state: &mut InnerAppState) {
+    for config in &state.config.stable_diffusion {
+        let model = config.model_name();
+        ImageModel::new(
+            model.clone(),
+            None,
+            config.features(),
+            &config.capabilities(),
+        )
+        .create(&state.db)
+        .await
+        .expect("image model is created");
+        let (tx_request, rx_request, running) = queue_channel(config.queue_capacity);
+        tokio::spawn(dispatch::run_model(
+            state.db.clone(),
+            backend::from_config(config),
+            rx_request,
+            running,
+            config.max_image_gen_sessions,
+            config.features(),
+            false,
+            state.webhooks.clone(),
+        ));
+        state.tx_image_gen_req.insert(model, tx_request);
+    }
+}
+
+/// Waits until the generation of the image ended and returns it.
+pub async fn generated_image(db: &DbPool, id: &Uuid) -> Image {
+    for _ in 0..500 {
+        let image = Image::get_by_id(db, id).await.expect("image exists");
+        if !matches!(image.status, ImageStatus::Queued | ImageStatus::Running) {
+            return image;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("image {id} wasn't generated in time");
+}
+
+/// Routes of the API and the public routes on `state`.
+pub fn router(state: SharedAppState) -> Router {
+    Router::new()
+        .merge(api::router(state.clone()))
+        .merge(public::router(state.clone()))
+        .with_state(state)
+}
+
+/// Creates a user with [`PASSWORD`].
+pub async fn user(db: &DbPool, username: &str, account_type: AccountType) -> User {
+    let user = User::new(
+        username,
+        hash_password(PASSWORD, 1),
+        format!("{username}@example.com"),
+        account_type,
+    );
+    user.create(db).await.expect("user is created");
+    user
+}
+
+/// Access token of `user`.
+pub fn token(user: &User) -> String {
+    set_jwt_secret();
+    generate_jwt(&user.username, user.account_type).expect("token is signed")
+}
+
+/// The keys of the tokens are read from the environment once, like the server all tests set the
+/// same secret before the first token is signed or checked.
+fn set_jwt_secret() {
+    std::env::set_var("JWT_SECRET", "secret-of-the-tests");
+}
+
+/// Sends a request with a JSON body, authorized with `token` if there is one. Returns the status
+/// of the response together with its body.
+pub async fn send(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    token: Option<&str>,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let body = match body {
+        Some(body) => {
+            request = request.header(header::CONTENT_TYPE, "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    let response = router
+        .clone()
+        .oneshot(request.body(body).expect("request is valid"))
+        .await
+        .expect("router doesn't fail");
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .expect("body is read");
+    let body = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+    (status, body)
+}
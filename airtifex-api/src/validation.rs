@@ -0,0 +1,891 @@
+use crate::{
+    config::{
+        Bounds, ImageRequestLimits, InferenceRequestLimits, PasswordConfig, RequestLimitsConfig,
+        WebhookConfig,
+    },
+    gen::image::SAMPLERS,
+};
+use airtifex_core::{
+    image::{
+        ImageGenerateRequest, ImageModelCapabilities, ImageModelFeatures, ImagePresetRequest,
+        ImageSettings,
+    },
+    llm::{
+        is_valid_template_variable, BatchRequest, ChatListQuery, ChatResponseRequest,
+        ChatSystemPromptUpdateRequest, DetokenizeRequest, InferenceSettings, PromptBundle,
+        PromptBundleEntry, SystemPromptRequest, TokenizeRequest, PROMPT_BUNDLE_VERSION,
+    },
+    user::{UiPreferences, UserSettings},
+    webhook::{WebhookCreateRequest, WebhookEvent},
+};
+
+use std::{fmt::Display, net::IpAddr};
+use thiserror::Error as ErrorType;
+
+/// Request parameter that is outside of the configured limits.
+#[derive(Debug, ErrorType)]
+#[error("invalid `{field}` - {reason}")]
+pub struct ValidationError {
+    pub field: &'static str,
+    pub reason: String,
+}
+
+impl ValidationError {
+    fn new(field: &'static str, reason: impl Into<String>) -> Self {
+        Self {
+            field,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl<T: PartialOrd + Display + Copy> Bounds<T> {
+    /// Checks that `value` is within the bounds, `None` means the default value is used.
+    pub fn check(&self, field: &'static str, value: Option<T>) -> Result<(), ValidationError> {
+        match value {
+            // NaN isn't comparable so it never passes
+            Some(value) if !(value >= self.min && value <= self.max) => Err(ValidationError::new(
+                field,
+                format!("must be between {} and {}, got {value}", self.min, self.max),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn check_length(field: &'static str, text: &str, max_length: usize) -> Result<(), ValidationError> {
+    let length = text.chars().count();
+    if length > max_length {
+        return Err(ValidationError::new(
+            field,
+            format!("can't be longer than {max_length} characters, got {length}"),
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_prompt(
+    field: &'static str,
+    prompt: &str,
+    max_length: usize,
+) -> Result<(), ValidationError> {
+    if prompt.trim().is_empty() {
+        return Err(ValidationError::new(field, "can't be empty"));
+    }
+    check_length(field, prompt, max_length)
+}
+
+/// Validates a prompt sent to a chat together with the system prompt overriding the one of the
+/// chat.
+pub fn validate_chat_prompt(
+    limits: &InferenceRequestLimits,
+    request: &ChatResponseRequest,
+) -> Result<(), ValidationError> {
+    validate_prompt("prompt", &request.prompt, limits.max_prompt_length)?;
+    if let Some(system_prompt) = &request.system_prompt {
+        check_length("system_prompt", system_prompt, limits.max_prompt_length)?;
+    }
+    if let Some(schema) = &request.json_schema {
+        if !(schema.is_object() || schema.is_boolean()) {
+            return Err(ValidationError::new(
+                "json_schema",
+                "must be an object or a boolean",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Validates the new system prompt of a chat against the limits of the prompts.
+pub fn validate_system_prompt_update(
+    limits: &InferenceRequestLimits,
+    request: &ChatSystemPromptUpdateRequest,
+) -> Result<(), ValidationError> {
+    match &request.system_prompt {
+        Some(system_prompt) => {
+            check_length("system_prompt", system_prompt, limits.max_prompt_length)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Validates the number of the last tokens the repetition penalty of a request applies to against
+/// the context of the model.
+pub fn validate_repeat_last_n(
+    repeat_last_n: Option<usize>,
+    num_ctx_tokens: usize,
+) -> Result<(), ValidationError> {
+    match repeat_last_n {
+        Some(n) if n > num_ctx_tokens => Err(ValidationError::new(
+            "repeat_last_n",
+            format!(
+                "can't be larger than the context of the model, {num_ctx_tokens} tokens, got {n}"
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Validates the number of threads a request asks for against the `num_threads` of the model.
+pub fn validate_n_threads(
+    n_threads: Option<usize>,
+    num_threads: usize,
+) -> Result<(), ValidationError> {
+    match n_threads {
+        Some(0) => Err(ValidationError::new("n_threads", "must be at least 1")),
+        Some(n) if n > num_threads => Err(ValidationError::new(
+            "n_threads",
+            format!("can't be larger than the {num_threads} threads of the model, got {n}"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Validates the text of a tokenize request, it can be as long as a prompt.
+pub fn validate_tokenize_request(
+    limits: &InferenceRequestLimits,
+    request: &TokenizeRequest,
+) -> Result<(), ValidationError> {
+    check_length("text", &request.text, limits.max_prompt_length)
+}
+
+/// Validates the tokens of a detokenize request, a prompt has at most one token per character.
+pub fn validate_detokenize_request(
+    limits: &InferenceRequestLimits,
+    request: &DetokenizeRequest,
+) -> Result<(), ValidationError> {
+    if request.tokens.len() > limits.max_prompt_length {
+        return Err(ValidationError::new(
+            "tokens",
+            format!(
+                "can't contain more than {} tokens, got {}",
+                limits.max_prompt_length,
+                request.tokens.len()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// Validates the `Idempotency-Key` header of a request, keys are printable ASCII like other
+/// header values.
+pub fn validate_idempotency_key(key: &str) -> Result<(), ValidationError> {
+    if key.is_empty() {
+        return Err(ValidationError::new("Idempotency-Key", "can't be empty"));
+    }
+    if !key.chars().all(|c| c.is_ascii_graphic()) {
+        return Err(ValidationError::new(
+            "Idempotency-Key",
+            "can only contain printable ASCII characters without spaces",
+        ));
+    }
+    check_length("Idempotency-Key", key, MAX_IDEMPOTENCY_KEY_LENGTH)
+}
+
+pub fn validate_batch_request(
+    limits: &InferenceRequestLimits,
+    request: &BatchRequest,
+) -> Result<(), ValidationError> {
+    if request.prompts.is_empty() {
+        return Err(ValidationError::new("prompts", "can't be empty"));
+    }
+    if request.prompts.len() > limits.max_batch_size {
+        return Err(ValidationError::new(
+            "prompts",
+            format!(
+                "can't contain more than {} prompts, got {}",
+                limits.max_batch_size,
+                request.prompts.len()
+            ),
+        ));
+    }
+    for prompt in &request.prompts {
+        validate_prompt("prompts", prompt, limits.max_prompt_length)?;
+    }
+    validate_inference_settings(limits, &request.params)
+}
+
+/// Validates every prompt of an imported bundle, the error names the position of the first
+/// invalid prompt.
+pub fn validate_prompt_bundle(
+    limits: &InferenceRequestLimits,
+    bundle: &PromptBundle,
+) -> Result<(), ValidationError> {
+    if bundle.version != PROMPT_BUNDLE_VERSION {
+        return Err(ValidationError::new(
+            "version",
+            format!(
+                "only version {PROMPT_BUNDLE_VERSION} is supported, got {}",
+                bundle.version
+            ),
+        ));
+    }
+    for (n, entry) in bundle.prompts.iter().enumerate() {
+        validate_bundle_entry(limits, entry)
+            .map_err(|e| ValidationError::new("prompts", format!("prompt {} - {e}", n + 1)))?;
+    }
+    Ok(())
+}
+
+fn validate_bundle_entry(
+    limits: &InferenceRequestLimits,
+    entry: &PromptBundleEntry,
+) -> Result<(), ValidationError> {
+    validate_prompt("prompt", &entry.prompt, limits.max_prompt_length)?;
+    if let Some(invalid) = entry
+        .variables
+        .iter()
+        .find(|v| !is_valid_template_variable(v))
+    {
+        return Err(ValidationError::new(
+            "variables",
+            format!("invalid variable name `{invalid}`"),
+        ));
+    }
+    validate_inference_settings(limits, &entry.settings())
+}
+
+pub fn validate_image_request(
+    limits: &ImageRequestLimits,
+    request: &ImageGenerateRequest,
+) -> Result<(), ValidationError> {
+    validate_prompt("prompt", &request.prompt, limits.max_prompt_length)?;
+    limits
+        .preview_every
+        .check("preview_every", request.preview_every)?;
+    validate_image_settings(
+        limits,
+        &ImageSettings {
+            width: request.width,
+            height: request.height,
+            n_steps: request.n_steps,
+            num_samples: request.num_samples,
+            guidance_scale: request.guidance_scale,
+            strength: request.input_image.as_ref().and_then(|i| i.strength),
+        },
+    )
+}
+
+/// Checks that the model supports the kind of generation, size and sampler of the request.
+pub fn validate_image_model_request(
+    features: &ImageModelFeatures,
+    capabilities: &ImageModelCapabilities,
+    request: &ImageGenerateRequest,
+) -> Result<(), ValidationError> {
+    let model = &request.model;
+    let (field, supported, kind) = match &request.input_image {
+        Some(input) if input.mask.is_some() => ("mask", features.inpaint, "inpainting"),
+        Some(_) => (
+            "input_image",
+            features.image_to_image,
+            "image to image generation",
+        ),
+        None => (
+            "input_image",
+            features.text_to_image,
+            "text to image generation",
+        ),
+    };
+    if !supported {
+        return Err(ValidationError::new(
+            field,
+            format!("model {model} doesn't support {kind}"),
+        ));
+    }
+    for (field, max, value) in [
+        ("width", capabilities.max_width, request.width),
+        ("height", capabilities.max_height, request.height),
+    ] {
+        if let (Some(max), Some(value)) = (max, value) {
+            if value > max {
+                return Err(ValidationError::new(
+                    field,
+                    format!("model {model} allows at most {max}, got {value}"),
+                ));
+            }
+        }
+    }
+    if let Some(sampler) = &request.sampler {
+        let samplers = if capabilities.samplers.is_empty() {
+            SAMPLERS.iter().map(|s| s.to_string()).collect()
+        } else {
+            capabilities.samplers.clone()
+        };
+        if !samplers.iter().any(|s| s.eq_ignore_ascii_case(sampler)) {
+            return Err(ValidationError::new(
+                "sampler",
+                format!("model {model} supports only {}", samplers.join(", ")),
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_image_settings(
+    limits: &ImageRequestLimits,
+    settings: &ImageSettings,
+) -> Result<(), ValidationError> {
+    for (field, bounds, value) in [
+        ("width", &limits.width, settings.width),
+        ("height", &limits.height, settings.height),
+    ] {
+        bounds.check(field, value)?;
+        if let Some(value) = value {
+            if limits.dimension_multiple > 1 && value % limits.dimension_multiple != 0 {
+                return Err(ValidationError::new(
+                    field,
+                    format!(
+                        "must be a multiple of {}, got {value}",
+                        limits.dimension_multiple
+                    ),
+                ));
+            }
+        }
+    }
+
+    limits.n_steps.check("n_steps", settings.n_steps)?;
+    limits
+        .num_samples
+        .check("num_samples", settings.num_samples)?;
+    limits
+        .guidance_scale
+        .check("guidance_scale", settings.guidance_scale)?;
+    limits.strength.check("strength", settings.strength)?;
+
+    Ok(())
+}
+
+/// Maximum length of the name of an image preset in characters.
+const MAX_IMAGE_PRESET_NAME_LENGTH: usize = 64;
+
+/// Validates a preset like the requests generated from it.
+pub fn validate_image_preset(
+    limits: &ImageRequestLimits,
+    preset: &ImagePresetRequest,
+) -> Result<(), ValidationError> {
+    let name = preset.name.trim();
+    if name.is_empty() {
+        return Err(ValidationError::new("name", "can't be empty"));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(ValidationError::new(
+            "name",
+            "can't contain control characters",
+        ));
+    }
+    check_length("name", name, MAX_IMAGE_PRESET_NAME_LENGTH)?;
+    if let Some(prompt) = &preset.prompt {
+        validate_prompt("prompt", prompt, limits.max_prompt_length)?;
+    }
+    validate_image_settings(limits, &preset.settings)
+}
+
+/// Maximum length of the name of a system prompt in characters.
+const MAX_SYSTEM_PROMPT_NAME_LENGTH: usize = 64;
+
+/// Validates a system prompt of the library. They only fill the `{{SYSTEM}}` marker of the
+/// conversation prompt of the model, the markers of the history and the request stay in there.
+pub fn validate_system_prompt(
+    limits: &InferenceRequestLimits,
+    prompt: &SystemPromptRequest,
+) -> Result<(), ValidationError> {
+    let name = prompt.name.trim();
+    if name.is_empty() {
+        return Err(ValidationError::new("name", "can't be empty"));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(ValidationError::new(
+            "name",
+            "can't contain control characters",
+        ));
+    }
+    check_length("name", name, MAX_SYSTEM_PROMPT_NAME_LENGTH)?;
+    validate_prompt("content", &prompt.content, limits.max_prompt_length)?;
+    if let Some(marker) = ["{{HISTORY}}", "{{PROMPT}}", "{{SYSTEM}}"]
+        .into_iter()
+        .find(|marker| prompt.content.contains(marker))
+    {
+        return Err(ValidationError::new(
+            "content",
+            format!("can't contain the {marker} marker, it is filled in by the conversation prompt of the model"),
+        ));
+    }
+    Ok(())
+}
+
+/// Validates stored generation defaults against the limits of the requests they're used in.
+pub fn validate_user_settings(
+    limits: &RequestLimitsConfig,
+    settings: &UserSettings,
+) -> Result<(), ValidationError> {
+    validate_image_settings(&limits.image, &settings.image)?;
+    validate_inference_settings(&limits.inference, &settings.chat)
+}
+
+/// Largest number of entries on each page of lists that can be chosen in the web app
+/// preferences.
+const MAX_ITEMS_PER_PAGE: u32 = 100;
+
+pub fn validate_ui_preferences(preferences: &UiPreferences) -> Result<(), ValidationError> {
+    match preferences.items_per_page {
+        Some(items) if !(1..=MAX_ITEMS_PER_PAGE).contains(&items) => Err(ValidationError::new(
+            "items_per_page",
+            format!("must be between 1 and {MAX_ITEMS_PER_PAGE}"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub fn validate_chat_list_query(query: &ChatListQuery) -> Result<(), ValidationError> {
+    match query.page_size {
+        Some(size) if !(1..=MAX_ITEMS_PER_PAGE).contains(&size) => Err(ValidationError::new(
+            "page_size",
+            format!("must be between 1 and {MAX_ITEMS_PER_PAGE}"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Maximum length of an image tag in characters.
+const MAX_IMAGE_TAG_LENGTH: usize = 32;
+
+/// Returns the tag trimmed and lowercased so that tags differing only in case are the same.
+pub fn normalize_image_tag(tag: &str) -> Result<String, ValidationError> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err(ValidationError::new("tag", "can't be empty"));
+    }
+    if tag.chars().any(char::is_control) {
+        return Err(ValidationError::new(
+            "tag",
+            "can't contain control characters",
+        ));
+    }
+    check_length("tag", &tag, MAX_IMAGE_TAG_LENGTH)?;
+    Ok(tag)
+}
+
+/// Checks a new password against the policy, the error lists every requirement it misses.
+pub fn validate_password(
+    field: &'static str,
+    policy: &PasswordConfig,
+    password: &str,
+) -> Result<(), ValidationError> {
+    if password.is_empty() {
+        return Err(ValidationError::new(field, "can't be empty"));
+    }
+    let mut missing = vec![];
+    let length = password.chars().count();
+    if length < policy.min_length {
+        missing.push(format!(
+            "be at least {} characters long, got {length}",
+            policy.min_length
+        ));
+    }
+    let has = |is_class: fn(char) -> bool| password.chars().any(is_class);
+    if policy.require_lowercase && !has(char::is_lowercase) {
+        missing.push("contain a lowercase letter".to_string());
+    }
+    if policy.require_uppercase && !has(char::is_uppercase) {
+        missing.push("contain an uppercase letter".to_string());
+    }
+    if policy.require_digit && !has(|c| c.is_ascii_digit()) {
+        missing.push("contain a digit".to_string());
+    }
+    if policy.require_symbol && !has(|c| !c.is_alphanumeric()) {
+        missing.push("contain a symbol".to_string());
+    }
+    if !missing.is_empty() {
+        return Err(ValidationError::new(
+            field,
+            format!("must {}", missing.join(", ")),
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_inference_settings(
+    limits: &InferenceRequestLimits,
+    settings: &InferenceSettings,
+) -> Result<(), ValidationError> {
+    if let Some(system_prompt) = settings.system_prompt.as_deref() {
+        check_length("system_prompt", system_prompt, limits.max_prompt_length)?;
+    }
+
+    limits
+        .num_predict
+        .check("num_predict", settings.num_predict)?;
+    limits.n_batch.check("n_batch", settings.n_batch)?;
+    limits.top_k.check("top_k", settings.top_k)?;
+    limits.top_p.check("top_p", settings.top_p)?;
+    limits
+        .repeat_penalty
+        .check("repeat_penalty", settings.repeat_penalty)?;
+    limits.temp.check("temp", settings.temp)?;
+    limits.mirostat.check("mirostat", settings.mirostat)?;
+    // `llm` doesn't implement a mirostat sampler yet, the answer would silently be sampled with
+    // top-k/top-p instead
+    if settings.mirostat.is_some_and(|mirostat| mirostat != 0) {
+        return Err(ValidationError::new(
+            "mirostat",
+            "must be 0, mirostat sampling isn't supported by the inference backend",
+        ));
+    }
+    limits
+        .mirostat_tau
+        .check("mirostat_tau", settings.mirostat_tau)?;
+    limits
+        .mirostat_eta
+        .check("mirostat_eta", settings.mirostat_eta)?;
+
+    Ok(())
+}
+
+/// Maximum length of a webhook URL in characters.
+const MAX_WEBHOOK_URL_LENGTH: usize = 2048;
+const MIN_WEBHOOK_SECRET_LENGTH: usize = 16;
+const MAX_WEBHOOK_SECRET_LENGTH: usize = 256;
+
+/// Checks that the webhook posts to an `http` or `https` URL and that the URL doesn't point to
+/// the server or its network unless the config allows it. Host names are checked again once
+/// resolved before every delivery.
+pub fn validate_webhook_request(
+    config: &WebhookConfig,
+    request: &WebhookCreateRequest,
+) -> Result<(), ValidationError> {
+    let url = request.url.trim();
+    check_length("url", url, MAX_WEBHOOK_URL_LENGTH)?;
+    let uri = url
+        .parse::<hyper::Uri>()
+        .map_err(|e| ValidationError::new("url", e.to_string()))?;
+    if !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return Err(ValidationError::new("url", "must be an http or https URL"));
+    }
+    let Some(authority) = uri.authority() else {
+        return Err(ValidationError::new("url", "must contain a host"));
+    };
+    if authority.as_str().contains('@') {
+        return Err(ValidationError::new("url", "can't contain credentials"));
+    }
+    if !config.allow_private_addresses {
+        check_webhook_host(authority.host())?;
+    }
+
+    if request.events.is_empty() {
+        return Err(ValidationError::new("events", "can't be empty"));
+    }
+    if let Some(event) = request
+        .events
+        .iter()
+        .find(|e| !WebhookEvent::SUBSCRIBABLE.contains(e))
+    {
+        return Err(ValidationError::new(
+            "events",
+            format!("can't subscribe to `{}`", event.as_ref()),
+        ));
+    }
+
+    let secret_length = request.secret.chars().count();
+    if secret_length < MIN_WEBHOOK_SECRET_LENGTH {
+        return Err(ValidationError::new(
+            "secret",
+            format!("must be at least {MIN_WEBHOOK_SECRET_LENGTH} characters long"),
+        ));
+    }
+    check_length("secret", &request.secret, MAX_WEBHOOK_SECRET_LENGTH)
+}
+
+fn check_webhook_host(host: &str) -> Result<(), ValidationError> {
+    let host = host.trim_end_matches('.').to_lowercase();
+    let ip = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(&host)
+        .parse::<IpAddr>();
+    let internal = match ip {
+        Ok(ip) => !is_public_address(ip),
+        Err(_) => {
+            host == "localhost"
+                || [".localhost", ".local", ".internal"]
+                    .iter()
+                    .any(|suffix| host.ends_with(suffix))
+        }
+    };
+    if internal {
+        return Err(ValidationError::new(
+            "url",
+            "can't point to a local or private address",
+        ));
+    }
+    Ok(())
+}
+
+/// Returns whether the address can be reached on the internet, loopback, private, link-local,
+/// shared, documentation, multicast and unspecified addresses aren't.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // shared address space of carrier-grade NATs, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+                // "this network", 0.0.0.0/8
+                || a == 0)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // unique local, fc00::/7
+                || (first & 0xfe00) == 0xfc00
+                // link-local, fe80::/10
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use airtifex_core::image::InputImage;
+
+    #[test]
+    fn bounds_are_inclusive() {
+        let bounds = Bounds::new(64, 2048);
+        assert!(bounds.check("width", Some(64)).is_ok());
+        assert!(bounds.check("width", Some(2048)).is_ok());
+        assert!(bounds.check("width", None).is_ok());
+
+        let err = bounds.check("width", Some(63)).unwrap_err();
+        assert_eq!(err.field, "width");
+        assert_eq!(err.reason, "must be between 64 and 2048, got 63");
+        assert!(bounds.check("width", Some(2049)).is_err());
+    }
+
+    #[test]
+    fn float_bounds_refuse_nan() {
+        let bounds = Bounds::new(0.0, 1.0);
+        assert!(bounds.check("strength", Some(0.0)).is_ok());
+        assert!(bounds.check("strength", Some(1.0)).is_ok());
+        assert!(bounds.check("strength", Some(1.01)).is_err());
+        assert!(bounds.check("strength", Some(f64::NAN)).is_err());
+    }
+
+    #[test]
+    fn n_threads_is_limited_by_the_model() {
+        assert!(validate_n_threads(None, 4).is_ok());
+        assert!(validate_n_threads(Some(1), 4).is_ok());
+        assert!(validate_n_threads(Some(4), 4).is_ok());
+
+        let err = validate_n_threads(Some(0), 4).unwrap_err();
+        assert_eq!(
+            (err.field, err.reason.as_str()),
+            ("n_threads", "must be at least 1")
+        );
+        let err = validate_n_threads(Some(5), 4).unwrap_err();
+        assert_eq!(
+            err.reason,
+            "can't be larger than the 4 threads of the model, got 5"
+        );
+    }
+
+    fn settings(width: i64, height: i64) -> ImageSettings {
+        ImageSettings {
+            width: Some(width),
+            height: Some(height),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn image_requests_are_checked_against_the_model() {
+        let features = ImageModelFeatures {
+            inpaint: false,
+            text_to_image: true,
+            image_to_image: true,
+        };
+        let capabilities = ImageModelCapabilities {
+            max_width: Some(768),
+            max_height: Some(512),
+            samplers: vec!["DDIM".into()],
+        };
+        let request = ImageGenerateRequest {
+            model: "sd".into(),
+            width: Some(768),
+            height: Some(512),
+            sampler: Some("ddim".into()),
+            ..Default::default()
+        };
+        assert!(validate_image_model_request(&features, &capabilities, &request).is_ok());
+
+        let input_image = |mask: Option<Vec<u8>>| InputImage {
+            data: vec![0],
+            mask,
+            strength: None,
+        };
+        for (request, field, reason) in [
+            (
+                ImageGenerateRequest {
+                    width: Some(776),
+                    ..request.clone()
+                },
+                "width",
+                "model sd allows at most 768, got 776",
+            ),
+            (
+                ImageGenerateRequest {
+                    height: Some(520),
+                    ..request.clone()
+                },
+                "height",
+                "model sd allows at most 512, got 520",
+            ),
+            (
+                ImageGenerateRequest {
+                    sampler: Some("Euler".into()),
+                    ..request.clone()
+                },
+                "sampler",
+                "model sd supports only DDIM",
+            ),
+            (
+                ImageGenerateRequest {
+                    input_image: Some(input_image(Some(vec![0]))),
+                    ..request.clone()
+                },
+                "mask",
+                "model sd doesn't support inpainting",
+            ),
+        ] {
+            let err = validate_image_model_request(&features, &capabilities, &request).unwrap_err();
+            assert_eq!((err.field, err.reason.as_str()), (field, reason));
+        }
+
+        let request = ImageGenerateRequest {
+            input_image: Some(input_image(None)),
+            ..request
+        };
+        assert!(validate_image_model_request(&features, &capabilities, &request).is_ok());
+        let features = ImageModelFeatures {
+            image_to_image: false,
+            ..features
+        };
+        let err = validate_image_model_request(&features, &capabilities, &request).unwrap_err();
+        assert_eq!(
+            err.reason,
+            "model sd doesn't support image to image generation"
+        );
+        // models without capabilities accept every size and sampler of the backend
+        let request = ImageGenerateRequest {
+            input_image: None,
+            width: Some(2048),
+            ..request
+        };
+        let capabilities = ImageModelCapabilities::default();
+        assert!(validate_image_model_request(&features, &capabilities, &request).is_ok());
+    }
+
+    #[test]
+    fn image_dimensions_are_checked_at_the_limits() {
+        let limits = ImageRequestLimits::default();
+        for (width, height, field) in [
+            (64, 2048, None),
+            (2048, 64, None),
+            (63, 512, Some("width")),
+            (2049, 512, Some("width")),
+            (512, 63, Some("height")),
+            (512, 2049, Some("height")),
+        ] {
+            let result = validate_image_settings(&limits, &settings(width, height));
+            assert_eq!(
+                result.err().map(|e| e.field),
+                field,
+                "{width}x{height} is checked"
+            );
+        }
+    }
+
+    #[test]
+    fn image_dimensions_have_to_be_a_multiple() {
+        let limits = ImageRequestLimits::default();
+        assert!(validate_image_settings(&limits, &settings(512, 512)).is_ok());
+
+        let err = validate_image_settings(&limits, &settings(516, 512)).unwrap_err();
+        assert_eq!(err.field, "width");
+        assert_eq!(err.reason, "must be a multiple of 8, got 516");
+        let err = validate_image_settings(&limits, &settings(512, 511)).unwrap_err();
+        assert_eq!(err.field, "height");
+
+        let limits = ImageRequestLimits {
+            dimension_multiple: 1,
+            ..Default::default()
+        };
+        assert!(validate_image_settings(&limits, &settings(515, 511)).is_ok());
+    }
+
+    #[test]
+    fn image_settings_are_checked_at_the_limits() {
+        let limits = ImageRequestLimits::default();
+        let valid = ImageSettings {
+            n_steps: Some(420),
+            num_samples: Some(1),
+            guidance_scale: Some(20.0),
+            strength: Some(0.0),
+            ..Default::default()
+        };
+        assert!(validate_image_settings(&limits, &valid).is_ok());
+
+        for (settings, field) in [
+            (
+                ImageSettings {
+                    n_steps: Some(0),
+                    ..valid.clone()
+                },
+                "n_steps",
+            ),
+            (
+                ImageSettings {
+                    n_steps: Some(421),
+                    ..valid.clone()
+                },
+                "n_steps",
+            ),
+            (
+                ImageSettings {
+                    num_samples: Some(17),
+                    ..valid.clone()
+                },
+                "num_samples",
+            ),
+            (
+                ImageSettings {
+                    guidance_scale: Some(-0.1),
+                    ..valid.clone()
+                },
+                "guidance_scale",
+            ),
+            (
+                ImageSettings {
+                    strength: Some(1.1),
+                    ..valid.clone()
+                },
+                "strength",
+            ),
+        ] {
+            let err = validate_image_settings(&limits, &settings).unwrap_err();
+            assert_eq!(err.field, field);
+        }
+    }
+}
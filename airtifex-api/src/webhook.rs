@@ -0,0 +1,242 @@
+//! Delivery of webhook events. Payloads are posted as JSON and signed with the secret of the
+//! webhook, the hex encoded HMAC-SHA256 of the body is sent as `X-Airtifex-Signature:
+//! sha256=<signature>`. Deliveries that don't get a 2xx response are retried with a growing
+//! delay, the host of a webhook is resolved before every attempt and private addresses are
+//! refused unless the config allows them.
+
+use crate::{
+    config::WebhookConfig, id::Uuid, models::webhook::Webhook, validation::is_public_address,
+    DbPool,
+};
+use airtifex_core::webhook::{WebhookData, WebhookEvent, WebhookPayload};
+
+use hmac::{Hmac, Mac};
+use hyper::{
+    header::{CONTENT_TYPE, HOST, USER_AGENT},
+    Body, Request, StatusCode, Uri,
+};
+use sha2::Sha256;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName},
+    TlsConnector,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const EVENT_HEADER: &str = "X-Airtifex-Event";
+pub const SIGNATURE_HEADER: &str = "X-Airtifex-Signature";
+
+#[derive(Clone)]
+pub struct Webhooks {
+    db: Arc<DbPool>,
+    config: WebhookConfig,
+    tls: TlsConnector,
+}
+
+impl Webhooks {
+    pub fn new(db: Arc<DbPool>, config: WebhookConfig) -> Self {
+        Self {
+            db,
+            config,
+            tls: tls_connector(),
+        }
+    }
+
+    /// Sends the event to the webhooks of the user subscribed to it in the background.
+    pub fn notify(&self, user_id: Uuid, event: WebhookEvent, data: WebhookData) {
+        let webhooks = self.clone();
+        tokio::spawn(async move {
+            let subscribed = match Webhook::list_for_user(&webhooks.db, &user_id).await {
+                Ok(subscribed) => subscribed,
+                Err(e) => {
+                    log::error!("[{user_id}] failed to list webhooks - {e}");
+                    return;
+                }
+            };
+            for webhook in subscribed.into_iter().filter(|w| w.is_subscribed(event)) {
+                let payload = payload(event, data.clone());
+                tokio::spawn(webhooks.clone().deliver(webhook, payload));
+            }
+        });
+    }
+
+    /// Posts the payload until the webhook accepts it or the attempts run out.
+    async fn deliver(self, webhook: Webhook, payload: WebhookPayload) {
+        let id = webhook.id;
+        let event = payload.event.as_ref();
+        let max_attempts = self.config.max_attempts.max(1);
+        for attempt in 1..=max_attempts {
+            let result = self.attempt(&webhook, &payload).await;
+            self.record(&id, &result).await;
+            match result {
+                Ok(status) if status.is_success() => {
+                    log::info!("[webhook {id}] delivered {event} - {status}");
+                    return;
+                }
+                Ok(status) => log::warn!(
+                    "[webhook {id}] attempt {attempt}/{max_attempts} to deliver {event} got {status}"
+                ),
+                Err(e) => log::warn!(
+                    "[webhook {id}] attempt {attempt}/{max_attempts} to deliver {event} failed - {e}"
+                ),
+            }
+            if attempt < max_attempts {
+                tokio::time::sleep(self.config.retry_delay(attempt)).await;
+            }
+        }
+        log::error!("[webhook {id}] giving up on delivering {event} after {max_attempts} attempts");
+    }
+
+    /// Posts the payload once, returns the status the webhook responded with.
+    pub async fn attempt(
+        &self,
+        webhook: &Webhook,
+        payload: &WebhookPayload,
+    ) -> Result<StatusCode, String> {
+        let body = serde_json::to_vec(payload).map_err(|e| e.to_string())?;
+        let request = Request::post("/")
+            .header(CONTENT_TYPE, "application/json")
+            .header(USER_AGENT, "airtifex-webhook")
+            .header(EVENT_HEADER, payload.event.as_ref())
+            .header(SIGNATURE_HEADER, sign(&webhook.secret, &body))
+            .body(Body::from(body))
+            .map_err(|e| e.to_string())?;
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        tokio::time::timeout(timeout, self.send(&webhook.url, request))
+            .await
+            .map_err(|_| "the webhook didn't respond in time".to_string())?
+    }
+
+    /// Records the outcome of an attempt on the webhook.
+    pub async fn record(&self, id: &Uuid, result: &Result<StatusCode, String>) {
+        let status = match result {
+            Ok(status) => status.to_string(),
+            Err(e) => e.clone(),
+        };
+        if let Err(e) = Webhook::update_last_delivery(&self.db, id, &status).await {
+            log::error!("[webhook {id}] failed to save the delivery status - {e}");
+        }
+    }
+
+    async fn send(&self, url: &str, mut request: Request<Body>) -> Result<StatusCode, String> {
+        let uri = url
+            .parse::<Uri>()
+            .map_err(|e| format!("invalid webhook url - {e}"))?;
+        let https = match uri.scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => return Err("the webhook url must be an http or https URL".into()),
+        };
+        let Some(authority) = uri.authority().cloned() else {
+            return Err("the webhook url doesn't contain a host".into());
+        };
+        let host = authority
+            .host()
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let port = authority.port_u16().unwrap_or(if https { 443 } else { 80 });
+
+        let addrs = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| format!("failed to resolve {host} - {e}"))?
+            .collect::<Vec<SocketAddr>>();
+        if !self.config.allow_private_addresses
+            && addrs.iter().any(|addr| !is_public_address(addr.ip()))
+        {
+            return Err(format!("{host} resolves to a private address"));
+        }
+        let stream = TcpStream::connect(addrs.as_slice())
+            .await
+            .map_err(|e| format!("failed to connect to {authority} - {e}"))?;
+
+        *request.uri_mut() = uri
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("/")
+            .parse()
+            .map_err(|e| format!("invalid webhook url - {e}"))?;
+        let host_header = authority
+            .as_str()
+            .parse()
+            .map_err(|e| format!("invalid webhook host - {e}"))?;
+        request.headers_mut().insert(HOST, host_header);
+
+        if https {
+            let server_name = ServerName::try_from(host)
+                .map_err(|e| format!("invalid webhook host {host} - {e}"))?;
+            let stream = self
+                .tls
+                .connect(server_name, stream)
+                .await
+                .map_err(|e| format!("TLS handshake with {authority} failed - {e}"))?;
+            post(stream, request).await
+        } else {
+            post(stream, request).await
+        }
+    }
+}
+
+/// Connector of outgoing TLS connections, trusting the Mozilla root certificates.
+pub fn tls_connector() -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|anchor| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    let tls = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    TlsConnector::from(Arc::new(tls))
+}
+
+async fn post<T>(io: T, request: Request<Body>) -> Result<StatusCode, String>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::handshake(io)
+        .await
+        .map_err(|e| e.to_string())?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::debug!("webhook connection closed - {e}");
+        }
+    });
+    sender
+        .send_request(request)
+        .await
+        .map(|response| response.status())
+        .map_err(|e| e.to_string())
+}
+
+/// Creates the payload of a new delivery.
+pub fn payload(event: WebhookEvent, data: WebhookData) -> WebhookPayload {
+    WebhookPayload {
+        id: Uuid::new_v4().to_string(),
+        event,
+        timestamp: chrono::Utc::now(),
+        data,
+    }
+}
+
+/// Returns the value of the signature header of a body, `sha256=` followed by the hex encoded
+/// HMAC-SHA256 of the body.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    let signature = mac.finalize().into_bytes();
+    let hex = signature
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    format!("sha256={hex}")
+}
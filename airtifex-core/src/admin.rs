@@ -0,0 +1,98 @@
+use crate::query::UrlQuery;
+
+use serde::{Deserialize, Serialize};
+
+/// Usage of the server shown to admins.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SystemStats {
+    pub total_users: u64,
+    pub total_images: u64,
+    pub total_chat_messages: u64,
+    /// Number of images requested in the last 24 hours.
+    pub images_last_day: u64,
+    /// Number of inference requests waiting for a free session of any model.
+    pub inference_queue_depth: usize,
+    pub running_sessions: usize,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LlmReloadRequest {
+    /// Name of the configured model whose weights are replaced.
+    pub model: String,
+    /// Path of the new weights on the server, they have to be of the configured model type.
+    pub model_path: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LlmReloadResponse {
+    pub model: String,
+    pub model_path: String,
+    /// Number of sessions that were running when the weights were swapped, they finished on the
+    /// previous weights before the response was sent.
+    pub drained_sessions: usize,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LlmQueueClearRequest {
+    /// Also stops the answers that are being generated, what was generated of them is kept.
+    #[serde(default)]
+    pub cancel_running: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LlmQueueClearResponse {
+    /// Number of requests that were waiting for a model, they were never started.
+    pub cleared: usize,
+    /// Number of running sessions that were stopped.
+    pub cancelled: usize,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct InferenceStatsQuery {
+    /// Only includes the requests to this model.
+    pub model: Option<String>,
+    /// Length of the window in hours that ends now, 24 hours when not set.
+    pub hours: Option<u32>,
+}
+
+impl UrlQuery for InferenceStatsQuery {
+    fn as_query(&self) -> String {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(model) = &self.model {
+            serializer.append_pair("model", model);
+        }
+        if let Some(hours) = self.hours {
+            serializer.append_pair("hours", &hours.to_string());
+        }
+        serializer.finish()
+    }
+}
+
+/// Percentiles of durations in milliseconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DurationPercentiles {
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+}
+
+/// Times of the inference requests that finished in a window.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct InferenceStatsResponse {
+    /// Number of requests that finished in the window.
+    pub requests: usize,
+    /// Time the requests waited in the queue before their session was started.
+    pub queue_wait: DurationPercentiles,
+    /// Time from the start of the sessions to the end of their answers.
+    pub generation: DurationPercentiles,
+}
+
+/// Access token of an admin acting as another user. It can't be refreshed, once it expires the
+/// admin continues with their own session.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ImpersonationResponse {
+    pub token: String,
+    /// User the token acts as.
+    pub username: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
@@ -30,12 +30,44 @@ impl AsRef<str> for ApiVersion {
     }
 }
 
+/// Machine readable reason of a failure response, the `data` of the response holds a message for
+/// display. Codes are stable, new ones are only ever added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ErrorCode {
+    /// The credentials are missing, invalid or expired, the client has to log in again.
+    Unauthorized,
+    /// The user isn't allowed to perform the request.
+    Forbidden,
+    /// The requested resource doesn't exist or belongs to another user.
+    NotFound,
+    /// The request is malformed or one of its values is out of bounds.
+    ValidationFailed,
+    /// The request conflicts with the current state of the resource.
+    Conflict,
+    /// An uploaded file exceeds the size limit.
+    PayloadTooLarge,
+    /// The client made too many requests, it can retry after the `Retry-After` header.
+    QuotaExceeded,
+    /// The requested model doesn't exist or isn't accepting requests.
+    ModelUnavailable,
+    /// The prompt was refused by the content moderation of the server.
+    ContentBlocked,
+    /// The server failed to handle the request.
+    Internal,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ApiResponse {
     status: ResponseStatus,
     api_version: ApiVersion,
     timestamp: DateTime<Utc>,
     data: serde_json::Value,
+    /// Only set on failures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    code: Option<ErrorCode>,
+    /// Id of the request the failure happened in, it can be found in the logs of the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 impl ApiResponse {
@@ -46,6 +78,8 @@ impl ApiResponse {
                 api_version: ApiVersion::V1,
                 timestamp: Utc::now(),
                 data,
+                code: None,
+                request_id: None,
             },
             Err(e) => Self::failure(e),
         }
@@ -58,25 +92,54 @@ impl ApiResponse {
             api_version: ApiVersion::V1,
             timestamp: Utc::now(),
             data: serde_json::Value::String(format!("{}", error)),
+            code: None,
+            request_id: None,
+        }
+    }
+
+    /// Sets the code of a failure response, successful responses are left unchanged.
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        if !self.is_success() {
+            self.code = Some(code);
+        }
+        self
+    }
+
+    /// Sets the request id of a failure response, successful responses are left unchanged.
+    pub fn with_request_id(mut self, id: impl Into<String>) -> Self {
+        if !self.is_success() {
+            self.request_id = Some(id.into());
         }
+        self
     }
 
     pub fn is_success(&self) -> bool {
         matches!(self.status, ResponseStatus::Success)
     }
 
+    pub fn code(&self) -> Option<ErrorCode> {
+        self.code
+    }
+
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
     pub fn into_data(self) -> serde_json::Value {
         self.data
     }
 
+    /// Deserializes the data of a successful response, failures are passed to `f` with their code
+    /// and message.
     pub fn into_result<T: DeserializeOwned, E: From<ResponseError>>(
         self,
-        f: impl FnOnce(String) -> E,
+        f: impl FnOnce(Option<ErrorCode>, String) -> E,
     ) -> Result<T, E> {
         if self.is_success() {
             self.deserialize_as().map_err(E::from)
         } else {
-            Err(f(self.into_data().as_str().unwrap().to_string()))
+            let code = self.code;
+            Err(f(code, self.into_data().as_str().unwrap().to_string()))
         }
     }
 
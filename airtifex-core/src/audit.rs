@@ -0,0 +1,85 @@
+use crate::query::UrlQuery;
+
+use serde::{Deserialize, Serialize};
+
+/// Administrative action or security relevant event recorded in the audit log.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[repr(i32)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "sql", derive(sqlx::Type))]
+pub enum AuditAction {
+    UserCreated = 1,
+    UserEdited = 2,
+    UserDeleted = 3,
+    PasswordChanged = 4,
+    ImageModelCreated = 5,
+    ImageModelDeleted = 6,
+    /// An authenticated user tried to access something it has no permissions for.
+    AuthorizationFailed = 7,
+    /// Login with invalid credentials.
+    AuthenticationFailed = 8,
+    /// The weights of a language model were replaced without a restart.
+    LlmReloaded = 9,
+    /// The requests waiting for the language models were aborted.
+    LlmQueueCleared = 10,
+    /// Logins of an account or from a client address were locked after too many failures.
+    LoginLocked = 11,
+    /// An admin started acting as another user.
+    ImpersonationStarted = 12,
+}
+
+impl AsRef<str> for AuditAction {
+    fn as_ref(&self) -> &str {
+        match self {
+            AuditAction::UserCreated => "user_created",
+            AuditAction::UserEdited => "user_edited",
+            AuditAction::UserDeleted => "user_deleted",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::ImageModelCreated => "image_model_created",
+            AuditAction::ImageModelDeleted => "image_model_deleted",
+            AuditAction::AuthorizationFailed => "authorization_failed",
+            AuditAction::AuthenticationFailed => "authentication_failed",
+            AuditAction::LlmReloaded => "llm_reloaded",
+            AuditAction::LlmQueueCleared => "llm_queue_cleared",
+            AuditAction::LoginLocked => "login_locked",
+            AuditAction::ImpersonationStarted => "impersonation_started",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AuditListQuery {
+    pub page: Option<u32>,
+    pub page_size: Option<u32>,
+    /// Only list entries of this action.
+    pub action: Option<AuditAction>,
+}
+
+impl UrlQuery for AuditListQuery {
+    fn as_query(&self) -> String {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(page) = self.page {
+            serializer.append_pair("page", &page.to_string());
+        }
+        if let Some(page_size) = self.page_size {
+            serializer.append_pair("page_size", &page_size.to_string());
+        }
+        if let Some(action) = self.action {
+            serializer.append_pair("action", action.as_ref());
+        }
+        serializer.finish()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditEntryInspect {
+    pub id: String,
+    /// `None` when the actor isn't a known user, like for failed logins.
+    pub actor_user_id: Option<String>,
+    /// Admin that acted as the actor when the entry was recorded during an impersonation.
+    #[serde(default)]
+    pub impersonator_user_id: Option<String>,
+    pub action: AuditAction,
+    pub target: Option<String>,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
@@ -7,6 +7,10 @@ pub fn hash_pass(password: String) -> Vec<u8> {
     hasher.finalize().as_slice().to_vec()
 }
 
+/// Error returned by the refresh route when the refresh token is expired or was revoked, the user
+/// has to log in again.
+pub const REFRESH_TOKEN_EXPIRED: &str = "RefreshTokenExpired";
+
 pub type Username = String;
 pub type Password = String;
 
@@ -40,3 +44,8 @@ impl Credentials {
         hash_pass(self.password.clone())
     }
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
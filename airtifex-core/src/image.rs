@@ -1,3 +1,5 @@
+use crate::{query::UrlQuery, QueueStatus};
+
 use debug_stub_derive::DebugStub;
 use serde::{Deserialize, Serialize};
 
@@ -12,6 +14,62 @@ pub struct ImageGenerateRequest {
     pub seed: Option<i64>,
     pub num_samples: Option<i64>,
     pub guidance_scale: Option<f64>,
+    /// Send a decoded preview of the sample to the progress stream every this many steps,
+    /// `None` disables previews.
+    pub preview_every: Option<usize>,
+    /// Sampler the image is generated with, has to be one of the samplers of the model.
+    #[serde(default)]
+    pub sampler: Option<String>,
+}
+
+impl ImageGenerateRequest {
+    /// Fills the parameters that weren't set in the request from `defaults`.
+    pub fn with_defaults(mut self, defaults: &ImageSettings) -> Self {
+        self.width = self.width.or(defaults.width);
+        self.height = self.height.or(defaults.height);
+        self.n_steps = self.n_steps.or(defaults.n_steps);
+        self.num_samples = self.num_samples.or(defaults.num_samples);
+        self.guidance_scale = self.guidance_scale.or(defaults.guidance_scale);
+        if let Some(input_image) = self.input_image.as_mut() {
+            input_image.strength = input_image.strength.or(defaults.strength);
+        }
+        self
+    }
+}
+
+/// Default image generation parameters of a user.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageSettings {
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub n_steps: Option<usize>,
+    pub num_samples: Option<i64>,
+    pub guidance_scale: Option<f64>,
+    /// Strength used when generating from an input image.
+    pub strength: Option<f64>,
+}
+
+/// Named generation parameters a user saved to fill the image form with, names are unique per
+/// user.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImagePresetRequest {
+    pub name: String,
+    /// Prompt the form starts with, it can be completed before generating.
+    pub prompt: Option<String>,
+    pub seed: Option<i64>,
+    #[serde(flatten)]
+    pub settings: ImageSettings,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImagePresetInspect {
+    pub id: String,
+    pub name: String,
+    pub prompt: Option<String>,
+    pub seed: Option<i64>,
+    #[serde(flatten)]
+    pub settings: ImageSettings,
+    pub create_date: chrono::DateTime<chrono::Utc>,
 }
 
 #[derive(Clone, Default, Deserialize, Serialize, DebugStub)]
@@ -26,6 +84,28 @@ pub struct InputImage {
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct TextToImageResponse {
     pub image_id: String,
+    /// Position of the generation in the queue of the model, unset when it started right away.
+    #[serde(default)]
+    pub queue: Option<QueueStatus>,
+}
+
+/// Step of a running image generation, sent as `progress` events by the progress stream.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ImageProgress {
+    /// Sample being generated, starting at 1.
+    pub n_sample: i64,
+    pub num_samples: i64,
+    pub step: usize,
+    pub n_steps: usize,
+}
+
+/// Intermediate image of a sample, sent as `preview` events by the progress stream.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImagePreview {
+    pub n_sample: i64,
+    pub step: usize,
+    /// Base64 encoded PNG, smaller than the final sample.
+    pub data: String,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -43,8 +123,248 @@ pub struct ImageInspect {
     pub seed: i64,
     pub num_samples: i64,
     pub guidance_scale: f64,
-    pub processing: bool,
+    pub status: ImageStatus,
+    /// Reason of the failure when the status is `failed`.
+    pub error: Option<String>,
     pub create_date: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Whether the owner starred the image, always `false` for images of other users.
+    #[serde(default)]
+    pub is_favorite: bool,
+    /// Image this one is a variation of.
+    #[serde(default)]
+    pub source_image_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageVariationsQuery {
+    /// Number of variations, they are generated as the samples of a single new image.
+    pub count: Option<i64>,
+}
+
+impl UrlQuery for ImageVariationsQuery {
+    fn as_query(&self) -> String {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(count) = self.count {
+            serializer.append_pair("count", &count.to_string());
+        }
+        serializer.finish()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageGridQuery {
+    /// Number of columns, by default the grid is as square as the number of samples allows.
+    pub cols: Option<usize>,
+    /// Whether the seed of each sample is written below it.
+    pub seeds: Option<bool>,
+}
+
+impl UrlQuery for ImageGridQuery {
+    fn as_query(&self) -> String {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(cols) = self.cols {
+            serializer.append_pair("cols", &cols.to_string());
+        }
+        if let Some(seeds) = self.seeds {
+            serializer.append_pair("seeds", &seeds.to_string());
+        }
+        serializer.finish()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageFavoriteResponse {
+    pub is_favorite: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageListQuery {
+    /// Only list the favorites of the user.
+    pub favorites_only: Option<bool>,
+    /// List the favorites of the user before the other images.
+    pub favorites_first: Option<bool>,
+}
+
+impl UrlQuery for ImageListQuery {
+    fn as_query(&self) -> String {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(favorites_only) = self.favorites_only {
+            serializer.append_pair("favorites_only", &favorites_only.to_string());
+        }
+        if let Some(favorites_first) = self.favorites_first {
+            serializer.append_pair("favorites_first", &favorites_first.to_string());
+        }
+        serializer.finish()
+    }
+}
+
+/// Format of an export of the images of a user.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl AsRef<str> for ImageExportFormat {
+    fn as_ref(&self) -> &str {
+        match self {
+            ImageExportFormat::Json => "json",
+            ImageExportFormat::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageExportQuery {
+    pub format: Option<ImageExportFormat>,
+    /// Only export the images of this model.
+    pub model: Option<String>,
+    /// Only export the images created at or after this date.
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only export the images created at or before this date.
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl UrlQuery for ImageExportQuery {
+    fn as_query(&self) -> String {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(format) = &self.format {
+            serializer.append_pair("format", format.as_ref());
+        }
+        if let Some(model) = &self.model {
+            serializer.append_pair("model", model);
+        }
+        if let Some(from) = &self.from {
+            serializer.append_pair("from", &from.to_rfc3339());
+        }
+        if let Some(to) = &self.to {
+            serializer.append_pair("to", &to.to_rfc3339());
+        }
+        serializer.finish()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageFeedQuery {
+    /// `next_cursor` of the previous page, the first page is returned without it.
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+    /// Only list the favorites of the user.
+    pub favorites_only: Option<bool>,
+    /// List the favorites of the user before the other images, the cursor of a page is only
+    /// valid with the same value.
+    pub favorites_first: Option<bool>,
+    /// Only images of the user with all of these tags are listed, sent as repeated `tag`
+    /// parameters, see [`tags_from_query`].
+    #[serde(skip)]
+    pub tags: Vec<String>,
+}
+
+impl UrlQuery for ImageFeedQuery {
+    fn as_query(&self) -> String {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(cursor) = &self.cursor {
+            serializer.append_pair("cursor", cursor);
+        }
+        if let Some(limit) = self.limit {
+            serializer.append_pair("limit", &limit.to_string());
+        }
+        if let Some(favorites_only) = self.favorites_only {
+            serializer.append_pair("favorites_only", &favorites_only.to_string());
+        }
+        if let Some(favorites_first) = self.favorites_first {
+            serializer.append_pair("favorites_first", &favorites_first.to_string());
+        }
+        for tag in &self.tags {
+            serializer.append_pair("tag", tag);
+        }
+        serializer.finish()
+    }
+}
+
+/// Returns the values of the repeated `tag` parameters of a URL query.
+pub fn tags_from_query(query: &str) -> Vec<String> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .filter(|(key, _)| key == "tag")
+        .map(|(_, value)| value.into_owned())
+        .collect()
+}
+
+/// Page of images, newest first, after the favorites when they are listed first.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageFeedPage {
+    pub images: Vec<ImageInspect>,
+    /// Cursor of the next page, `None` when there are no more images.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageDeleteBatchRequest {
+    pub ids: Vec<String>,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageDeleteStatus {
+    Deleted,
+    NotFound,
+    /// The image belongs to another user.
+    NotOwned,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ImageDeleteResult {
+    pub id: String,
+    pub status: ImageDeleteStatus,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageDeleteBatchResponse {
+    pub deleted: usize,
+    pub skipped: usize,
+    pub results: Vec<ImageDeleteResult>,
+}
+
+/// Generation state of an image, images move from `queued` to `running` and end up either
+/// `done`, `failed` or `cancelled` when nobody followed their progress anymore. Failed and
+/// cancelled images can be queued again.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[repr(i32)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "sql", derive(sqlx::Type))]
+pub enum ImageStatus {
+    #[default]
+    Queued = 1,
+    Running = 2,
+    Failed = 3,
+    Done = 4,
+    Cancelled = 5,
+}
+
+impl ImageStatus {
+    pub fn is_processing(self) -> bool {
+        matches!(self, ImageStatus::Queued | ImageStatus::Running)
+    }
+
+    pub fn can_retry(self) -> bool {
+        matches!(self, ImageStatus::Failed | ImageStatus::Cancelled)
+    }
+}
+
+impl AsRef<str> for ImageStatus {
+    fn as_ref(&self) -> &str {
+        match self {
+            ImageStatus::Queued => "queued",
+            ImageStatus::Running => "running",
+            ImageStatus::Failed => "failed",
+            ImageStatus::Done => "done",
+            ImageStatus::Cancelled => "cancelled",
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -52,15 +372,79 @@ pub struct ImageSampleInspect {
     pub sample_id: String,
     pub image_id: String,
     pub n_sample: i32,
+    /// The exact seed this sample was generated with.
+    pub actual_seed: i64,
     pub data: Vec<u8>,
 }
 
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageSampleRegenerateRequest {
+    /// Seed of the new sample, a random one when not set.
+    pub seed: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageSampleRegenerateResponse {
+    pub image_id: String,
+    pub n_sample: i32,
+    /// Seed the sample is generated with, the sample is replaced once its `actual_seed` is this
+    /// one.
+    pub seed: i64,
+    /// Position of the generation in the queue of the model, unset when it started right away.
+    #[serde(default)]
+    pub queue: Option<QueueStatus>,
+}
+
+/// Generation parameters embedded in a sample, values missing from the embedded block are `None`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageSampleMetadata {
+    /// The embedded block as is.
+    pub parameters: String,
+    pub prompt: String,
+    pub negative_prompt: Option<String>,
+    pub model: Option<String>,
+    pub n_steps: Option<i64>,
+    pub sampler: Option<String>,
+    pub guidance_scale: Option<f64>,
+    pub seed: Option<i64>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub strength: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageTagRequest {
+    pub tag: String,
+}
+
+/// Tag of the images of a user together with the number of images that have it.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageTagCount {
+    pub tag: String,
+    pub count: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageShareRequest {
+    /// Sample the link gives access to, the first one when not set.
+    pub n_sample: Option<i32>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ImageShareResponse {
+    /// Public URL of the sample, it can be opened without an account.
+    pub url: String,
+    pub expires: chrono::DateTime<chrono::Utc>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ImageModelListEntry {
     pub model_id: String,
     pub name: String,
     pub description: Option<String>,
     pub features: ImageModelFeatures,
+    #[serde(default)]
+    pub capabilities: ImageModelCapabilities,
 }
 
 fn on() -> bool {
@@ -68,7 +452,7 @@ fn on() -> bool {
 }
 
 fn off() -> bool {
-    true
+    false
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -80,3 +464,45 @@ pub struct ImageModelFeatures {
     #[serde(default = "on")]
     pub image_to_image: bool,
 }
+
+impl Default for ImageModelFeatures {
+    fn default() -> Self {
+        Self {
+            inpaint: off(),
+            text_to_image: on(),
+            image_to_image: on(),
+        }
+    }
+}
+
+/// Limits of the requests a model accepts besides the kinds of generation it supports. The
+/// default limits nothing.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImageModelCapabilities {
+    /// Largest width of the generated images in pixels, any width allowed by the request limits
+    /// when `None`.
+    #[serde(default)]
+    pub max_width: Option<i64>,
+    /// Largest height of the generated images in pixels, any height allowed by the request
+    /// limits when `None`.
+    #[serde(default)]
+    pub max_height: Option<i64>,
+    /// Samplers the images can be generated with, every sampler of the backend when empty.
+    #[serde(default)]
+    pub samplers: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImageModelCreateRequest {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub features: ImageModelFeatures,
+    #[serde(default)]
+    pub capabilities: ImageModelCapabilities,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ImageModelCreateResponse {
+    pub model_id: String,
+}
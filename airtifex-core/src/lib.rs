@@ -1,13 +1,29 @@
 use serde::{Deserialize, Serialize};
 
+pub mod admin;
 pub mod api_response;
+pub mod audit;
 pub mod auth;
 pub mod image;
 pub mod llm;
 pub mod query;
 pub mod user;
+pub mod webhook;
+
+/// Place of a request waiting for a model, the request is generated once it leaves the queue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueStatus {
+    /// Starts at 1 for the next request to be started.
+    pub position: usize,
+    /// Number of requests the model is working on.
+    pub running_sessions: usize,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct JsonWebToken {
     pub token: String,
+    /// Long lived token used to obtain a new `token` once it expires. Tokens saved before refresh
+    /// tokens were introduced don't have one.
+    #[serde(default)]
+    pub refresh_token: String,
 }
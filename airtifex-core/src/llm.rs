@@ -1,10 +1,181 @@
+use crate::query::UrlQuery;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ChatResponseRequest {
     pub prompt: String,
+    /// Overrides the system prompt of the conversation for this response only.
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// JSON schema the response has to match. The response is sent at once when it is complete,
+    /// when it doesn't match the schema the raw response is followed by an error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<serde_json::Value>,
+    /// Seeds the sampling so that the same prompt and settings generate the same response, a
+    /// random seed is used when not set. The seed of every response is saved with it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Number of the last tokens the repetition penalty applies to, the model configuration
+    /// decides when not set. Can't be larger than the context of the model.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_last_n: Option<usize>,
+    /// Number of threads the response is generated with, the `num_threads` of the model when not
+    /// set. Can't be larger than that.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_threads: Option<usize>,
+    /// Generates the response even if the model answered the same seeded request before.
+    #[serde(default)]
+    pub no_cache: bool,
+    /// How the tokens of the response are grouped into the messages of the stream.
+    #[serde(default)]
+    pub stream_granularity: StreamGranularity,
+}
+
+/// Where the answer stream of a chat may be split into messages. Rendering Markdown while it
+/// arrives flickers when a message ends inside of a word or a `**bold**` span, with `word` or
+/// `sentence` the tokens are held back until such a boundary or a line break. The end of the
+/// answer always sends what is left.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamGranularity {
+    /// Every token is sent as soon as it is generated.
+    #[default]
+    Token,
+    /// Text is sent up to the whitespace after a word, outside of emphasis and inline code.
+    Word,
+    /// Text is sent up to the whitespace after the end of a sentence, outside of emphasis and
+    /// inline code.
+    Sentence,
+}
+
+/// Replaces the content of a prompt of a chat, the entries after it are removed and the prompt
+/// is answered again.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ChatEntryEditRequest {
+    pub content: String,
+    /// How the tokens of the new answer are grouped into the messages of the stream.
+    #[serde(default)]
+    pub stream_granularity: StreamGranularity,
+}
+
+/// Response of deleting a chat or a prompt of it together with its answer.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ChatDeleteResponse {
+    /// Number of chat entries that were removed.
+    pub deleted_entries: u64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ChatSystemPromptUpdateRequest {
+    /// New system prompt of the conversation, `None` restores the default one.
+    pub system_prompt: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ChatContextTurnsUpdateRequest {
+    /// Number of previous turns the model is given, `None` gives it the whole conversation.
+    pub context_turns: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SystemPromptRequest {
+    pub name: String,
+    /// Persona the conversation prompt of the model is filled with.
+    pub content: String,
+    /// Whether every user can pick the system prompt, only admins can share them.
+    #[serde(default)]
+    pub shared: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SystemPromptInspect {
+    pub id: String,
+    pub name: String,
+    pub content: String,
+    pub shared: bool,
+    /// Whether the system prompt belongs to the user, only those can be changed.
+    pub owned: bool,
+    pub create_date: chrono::DateTime<chrono::Utc>,
 }
 
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TokenizeRequest {
+    pub model: String,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TokenizeResponse {
+    pub tokens: Vec<TokenPiece>,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DetokenizeRequest {
+    pub model: String,
+    pub tokens: Vec<i32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DetokenizeResponse {
+    pub text: String,
+    pub tokens: Vec<TokenPiece>,
+    pub count: usize,
+}
+
+/// Token of the vocabulary of a model.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct TokenPiece {
+    pub id: i32,
+    /// Text of the token, a token that is only part of a character holds the replacement
+    /// character.
+    pub piece: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ChatStreamQuery {
+    /// Number of answer characters already received, the `Last-Event-ID` header takes precedence.
+    pub offset: Option<usize>,
+    /// How the rest of the answer is grouped into the messages of the stream.
+    #[serde(default)]
+    pub stream_granularity: StreamGranularity,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ChatWsQuery {
+    /// Queue prompts sent while a response is still being generated instead of rejecting them.
+    #[serde(default)]
+    pub queue: bool,
+}
+
+/// Message sent by the client over the chat WebSocket.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatWsClientMessage {
+    Prompt {
+        prompt: String,
+        #[serde(default)]
+        system_prompt: Option<String>,
+        #[serde(default)]
+        json_schema: Option<serde_json::Value>,
+        #[serde(default)]
+        seed: Option<u64>,
+        #[serde(default)]
+        repeat_last_n: Option<usize>,
+        #[serde(default)]
+        n_threads: Option<usize>,
+        #[serde(default)]
+        no_cache: bool,
+        #[serde(default)]
+        stream_granularity: StreamGranularity,
+    },
+}
+
+/// Frame sent by the server over the chat WebSocket, the messages of an answer are forwarded as
+/// they are sent by the model.
+pub type ChatWsServerMessage = ChatStreamMessage;
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ChatStartResponse {
     pub chat_id: String,
@@ -53,6 +224,106 @@ pub struct ChatListEntry {
     pub start_date: chrono::DateTime<chrono::Utc>,
     pub model: String,
     pub settings: InferenceSettings,
+    /// Number of previous turns the model is given, all of them when `None`.
+    #[serde(default)]
+    pub context_turns: Option<usize>,
+}
+
+/// A chat in the list of conversations with a preview of its latest message.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatOverview {
+    pub id: String,
+    pub title: String,
+    pub model: String,
+    pub start_date: chrono::DateTime<chrono::Utc>,
+    /// Date of the latest message, the start date when the chat has no messages.
+    pub last_updated: chrono::DateTime<chrono::Utc>,
+    pub message_count: u64,
+    /// Author of the latest message, `None` when the chat has no messages.
+    pub last_entry_type: Option<ChatEntryType>,
+    /// Beginning of the latest message.
+    pub last_message: Option<String>,
+    /// The latest message is an answer the user didn't read yet.
+    pub unread: bool,
+}
+
+/// A page of the chats of the user along with the number of chats on all pages.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ChatListPage {
+    pub chats: Vec<ChatOverview>,
+    pub total: u64,
+}
+
+/// Chats are listed by recent activity, the most recently updated chat comes first.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ChatListQuery {
+    pub page: Option<u32>,
+    pub page_size: Option<u32>,
+}
+
+impl UrlQuery for ChatListQuery {
+    fn as_query(&self) -> String {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(page) = self.page {
+            serializer.append_pair("page", &page.to_string());
+        }
+        if let Some(page_size) = self.page_size {
+            serializer.append_pair("page_size", &page_size.to_string());
+        }
+        serializer.finish()
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ChatSearchQuery {
+    pub q: String,
+}
+
+impl UrlQuery for ChatSearchQuery {
+    fn as_query(&self) -> String {
+        url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("q", &self.q)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ChatForkQuery {
+    /// Id of the last entry copied to the new chat.
+    pub at: String,
+}
+
+impl UrlQuery for ChatForkQuery {
+    fn as_query(&self) -> String {
+        url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("at", &self.at)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ChatShareRequest {
+    /// Shows the system prompt of the chat in the transcript, it is left out by default.
+    #[serde(default)]
+    pub include_system_prompt: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChatShareResponse {
+    /// Public URL of the read-only transcript, it can be opened without an account.
+    pub url: String,
+    pub expires: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatSearchResult {
+    pub chat_id: String,
+    pub chat_title: String,
+    pub entry_id: String,
+    pub entry_type: ChatEntryType,
+    /// Part of the entry content surrounding the first match.
+    pub snippet: String,
+    pub entry_date: chrono::DateTime<chrono::Utc>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -61,6 +332,28 @@ pub struct ChatEntryListEntry {
     pub chat_id: String,
     pub entry_type: ChatEntryType,
     pub content: String,
+    /// Seed an answer of the bot was sampled with, the same prompt and settings generate the
+    /// same answer again with it.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Parameters an answer of the bot was generated with, not set for prompts and for answers
+    /// saved before they were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<ChatEntryParams>,
+}
+
+/// Model and sampling parameters an answer of a chat was generated with, after the defaults of
+/// the model were applied.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChatEntryParams {
+    pub model: String,
+    pub temp: f32,
+    pub top_k: usize,
+    pub top_p: f32,
+    pub repeat_penalty: f32,
+    pub repeat_last_n: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<usize>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -87,6 +380,33 @@ pub struct InferenceSettings {
     pub top_p: Option<f32>,
     pub repeat_penalty: Option<f32>,
     pub temp: Option<f32>,
+    /// Mirostat sampling mode, `0` disables it while `1` and `2` select Mirostat v1 and v2.
+    /// Requests enabling mirostat are refused until the inference backend supports it.
+    pub mirostat: Option<u8>,
+    /// Target entropy (perplexity) for mirostat sampling.
+    pub mirostat_tau: Option<f32>,
+    /// Learning rate of mirostat sampling.
+    pub mirostat_eta: Option<f32>,
+}
+
+impl InferenceSettings {
+    /// Fills the settings that aren't set from `defaults`.
+    pub fn with_defaults(self, defaults: &InferenceSettings) -> Self {
+        Self {
+            num_predict: self.num_predict.or(defaults.num_predict),
+            system_prompt: self
+                .system_prompt
+                .or_else(|| defaults.system_prompt.clone()),
+            n_batch: self.n_batch.or(defaults.n_batch),
+            top_k: self.top_k.or(defaults.top_k),
+            top_p: self.top_p.or(defaults.top_p),
+            repeat_penalty: self.repeat_penalty.or(defaults.repeat_penalty),
+            temp: self.temp.or(defaults.temp),
+            mirostat: self.mirostat.or(defaults.mirostat),
+            mirostat_tau: self.mirostat_tau.or(defaults.mirostat_tau),
+            mirostat_eta: self.mirostat_eta.or(defaults.mirostat_eta),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -99,10 +419,96 @@ pub struct OneshotInferenceRequest {
     pub top_p: Option<f32>,
     pub repeat_penalty: Option<f32>,
     pub temp: Option<f32>,
+    pub mirostat: Option<u8>,
+    pub mirostat_tau: Option<f32>,
+    pub mirostat_eta: Option<f32>,
+    /// Names of the `{{NAME}}` variables declared by the prompt, when not empty the prompt is
+    /// treated as a template.
+    #[serde(default)]
+    pub variables: Vec<String>,
+    /// Values substituted for the declared variables.
+    #[serde(default)]
+    pub values: HashMap<String, String>,
+    #[serde(default = "default_play_back_tokens")]
+    pub play_back_tokens: bool,
+    #[serde(default = "default_save_inference_request")]
+    pub save: bool,
+    /// Generates the answer even if the model answered the same seeded request before.
+    #[serde(default)]
+    pub no_cache: bool,
+}
+
+/// Runs inference of a saved prompt template with the given variable values.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PromptGenerateRequest {
+    #[serde(default)]
+    pub values: HashMap<String, String>,
     #[serde(default = "default_play_back_tokens")]
     pub play_back_tokens: bool,
     #[serde(default = "default_save_inference_request")]
     pub save: bool,
+    /// Generates the answer even if the model answered the same seeded request before.
+    #[serde(default)]
+    pub no_cache: bool,
+}
+
+/// Returns whether `name` can be used as a prompt template variable.
+pub fn is_valid_template_variable(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns names of all `{{NAME}}` placeholders in the template in the order of appearance.
+pub fn template_variables(template: &str) -> Vec<String> {
+    let mut variables: Vec<String> = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("}}") else {
+            break;
+        };
+        let name = &rest[..end];
+        if is_valid_template_variable(name) && !variables.iter().any(|v| v == name) {
+            variables.push(name.to_string());
+        }
+        rest = &rest[end + 2..];
+    }
+    variables
+}
+
+/// Substitutes the values of declared `variables` into the template. Fails with the names of
+/// the declared variables that have no value.
+pub fn render_template(
+    template: &str,
+    variables: &[String],
+    values: &HashMap<String, String>,
+) -> Result<String, Vec<String>> {
+    let missing = variables
+        .iter()
+        .filter(|v| !values.contains_key(*v))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+
+    // substitute in a single pass so that values containing placeholders are left untouched
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find("}}") else {
+            break;
+        };
+        let name = &rest[2..end];
+        match values.get(name) {
+            Some(value) if variables.iter().any(|v| v == name) => rendered.push_str(value),
+            _ => rendered.push_str(&rest[..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
 }
 
 fn default_play_back_tokens() -> bool {
@@ -113,6 +519,62 @@ fn default_save_inference_request() -> bool {
     false
 }
 
+/// Why the model stopped answering.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The model ended the answer.
+    #[default]
+    EndOfText,
+    /// The answer reached the `num_predict` limit.
+    MaxTokens,
+    /// The answer was cut because it kept repeating itself.
+    Repetition,
+    /// The receiver of the answer went away before it was finished.
+    Cancelled,
+    /// An admin cleared the queue of the model.
+    Aborted,
+    /// The answer was stopped by the content moderation of the server.
+    Moderated,
+    /// The model ended the answer before generating anything, after a retry when the model
+    /// retries empty answers.
+    EmptyAnswer,
+}
+
+/// Message of an answer stream. The tokens of the answer are followed by its usage and a `done`
+/// message, an `error` comes before them when the answer failed.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatStreamMessage {
+    Token { content: String },
+    Usage { generated_tokens: usize },
+    Done { reason: StopReason },
+    Error { message: String },
+}
+
+impl ChatStreamMessage {
+    /// Converts the message to the raw token or error of [`ChatStreamResult`] consumers, control
+    /// messages are `None`.
+    pub fn into_result(self) -> Option<ChatStreamResult> {
+        match self {
+            ChatStreamMessage::Token { content } => Some(Ok(content)),
+            ChatStreamMessage::Error { message } => Some(Err(message)),
+            ChatStreamMessage::Usage { .. } | ChatStreamMessage::Done { .. } => None,
+        }
+    }
+}
+
+impl From<ChatStreamResult> for ChatStreamMessage {
+    fn from(result: ChatStreamResult) -> Self {
+        match result {
+            Ok(content) => ChatStreamMessage::Token { content },
+            Err(message) => ChatStreamMessage::Error { message },
+        }
+    }
+}
+
+/// Raw token or error of an answer, kept for consumers that only care about the content of
+/// [`ChatStreamMessage`].
 pub type ChatStreamResult = Result<String, String>;
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -134,4 +596,239 @@ pub struct PromptInspect {
     pub top_p: Option<f32>,
     pub repeat_penalty: Option<f32>,
     pub temp: Option<f32>,
+    /// Variables declared by the prompt when it is a template.
+    #[serde(default)]
+    pub variables: Vec<String>,
+    #[serde(default)]
+    pub is_favorite: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PromptListQuery {
+    /// Only list the prompts marked as favorite.
+    pub favorites_only: Option<bool>,
+}
+
+impl UrlQuery for PromptListQuery {
+    fn as_query(&self) -> String {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(favorites_only) = self.favorites_only {
+            serializer.append_pair("favorites_only", &favorites_only.to_string());
+        }
+        serializer.finish()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PromptFavoriteRequest {
+    pub is_favorite: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PromptReorderRequest {
+    /// Ids of the prompts in their new order, prompts that aren't listed keep their position.
+    pub ids: Vec<String>,
+}
+
+/// Version of the prompt bundle format written by the export.
+pub const PROMPT_BUNDLE_VERSION: u32 = 1;
+
+/// Saved prompts of a user in a form that can be imported by another user.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PromptBundle {
+    pub version: u32,
+    pub prompts: Vec<PromptBundleEntry>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PromptBundleEntry {
+    pub prompt: String,
+    #[serde(default)]
+    pub response: String,
+    pub model: String,
+    #[serde(default)]
+    pub num_predict: Option<usize>,
+    #[serde(default)]
+    pub n_batch: Option<usize>,
+    #[serde(default)]
+    pub top_k: Option<usize>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub repeat_penalty: Option<f32>,
+    #[serde(default)]
+    pub temp: Option<f32>,
+    /// Variables declared by the prompt when it is a template.
+    #[serde(default)]
+    pub variables: Vec<String>,
+    #[serde(default)]
+    pub is_favorite: bool,
+}
+
+impl PromptBundleEntry {
+    pub fn settings(&self) -> InferenceSettings {
+        InferenceSettings {
+            num_predict: self.num_predict,
+            n_batch: self.n_batch,
+            top_k: self.top_k,
+            top_p: self.top_p,
+            repeat_penalty: self.repeat_penalty,
+            temp: self.temp,
+            ..Default::default()
+        }
+    }
+}
+
+/// What happens to an imported prompt when the user already saved a prompt with the same text.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptDuplicateAction {
+    /// Keeps the saved prompt as it is.
+    #[default]
+    Skip,
+    /// Updates the saved prompt with the settings of the imported one, a prompt stays a
+    /// favorite once either of them is.
+    Merge,
+}
+
+impl AsRef<str> for PromptDuplicateAction {
+    fn as_ref(&self) -> &str {
+        match self {
+            PromptDuplicateAction::Skip => "skip",
+            PromptDuplicateAction::Merge => "merge",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PromptImportQuery {
+    pub on_duplicate: Option<PromptDuplicateAction>,
+}
+
+impl UrlQuery for PromptImportQuery {
+    fn as_query(&self) -> String {
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        if let Some(on_duplicate) = self.on_duplicate {
+            serializer.append_pair("on_duplicate", on_duplicate.as_ref());
+        }
+        serializer.finish()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PromptImportResponse {
+    pub created: usize,
+    pub merged: usize,
+    pub skipped: usize,
+}
+
+/// Runs every prompt with the same settings, the answers are collected in a batch.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BatchRequest {
+    pub model: String,
+    pub prompts: Vec<String>,
+    #[serde(default)]
+    pub params: InferenceSettings,
+    /// Generates every answer with the same seed so that the answers only differ by their
+    /// prompt.
+    #[serde(default)]
+    pub identical_seeds: bool,
+    /// Seed used with `identical_seeds`, a random one is picked when not set.
+    pub seed: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BatchStartResponse {
+    pub batch_id: String,
+}
+
+/// Generation state of a batch prompt, prompts move from `queued` to `running` and end up
+/// either `done` or `failed`.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[repr(i32)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "sql", derive(sqlx::Type))]
+pub enum BatchEntryStatus {
+    #[default]
+    Queued = 1,
+    Running = 2,
+    Failed = 3,
+    Done = 4,
+}
+
+impl BatchEntryStatus {
+    pub fn is_processing(self) -> bool {
+        matches!(self, BatchEntryStatus::Queued | BatchEntryStatus::Running)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BatchEntryInspect {
+    /// Position of the prompt in the batch request.
+    pub n: i32,
+    pub prompt: String,
+    pub status: BatchEntryStatus,
+    /// Answer of the model, set once the prompt is done. Failed prompts keep the answer
+    /// generated before the failure.
+    pub response: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BatchInspect {
+    pub id: String,
+    pub model: String,
+    /// Seed shared by all prompts of the batch.
+    pub seed: Option<u64>,
+    pub create_date: chrono::DateTime<chrono::Utc>,
+    /// Number of prompts that are done or failed.
+    pub finished: usize,
+    pub entries: Vec<BatchEntryInspect>,
+}
+
+/// Stage of loading a language model, models are loaded in the background after the server
+/// started.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelLoadStage {
+    #[default]
+    Pending,
+    /// The weights are downloaded from the `model_url` of the model.
+    Downloading,
+    Hyperparameters,
+    Tensors,
+    Loaded,
+    /// The weights couldn't be downloaded or don't match their checksum, the model isn't loaded.
+    Failed,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ModelLoadStatus {
+    pub model: String,
+    pub stage: ModelLoadStage,
+    pub tensors_loaded: usize,
+    pub tensor_count: usize,
+    pub percentage: f32,
+    /// Bytes of the weights that are downloaded, partial downloads of earlier starts included.
+    #[serde(default)]
+    pub bytes_downloaded: u64,
+    /// Size of the weights being downloaded, unknown until the server announced it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes_total: Option<u64>,
+    /// Why the model failed to load.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LlmLoadStatus {
+    /// Whether every model finished loading, `/ready` responds with `200 OK` from then on.
+    pub ready: bool,
+    /// Loading progress of all models together.
+    pub percentage: f32,
+    pub models: Vec<ModelLoadStatus>,
+    /// Loading progress of the new weights of models that are being reloaded, the models keep
+    /// serving requests with their current weights meanwhile.
+    #[serde(default)]
+    pub reloading: Vec<ModelLoadStatus>,
 }
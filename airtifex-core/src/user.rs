@@ -1,4 +1,4 @@
-use crate::query::UrlQuery;
+use crate::{image::ImageSettings, llm::InferenceSettings, query::UrlQuery};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,6 +21,103 @@ pub struct UserRegisterRequest {
     pub account_type: AccountType,
 }
 
+/// Defaults used for the generation parameters a request leaves out.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct UserSettings {
+    #[serde(default)]
+    pub image: ImageSettings,
+    #[serde(default)]
+    pub chat: InferenceSettings,
+}
+
+/// Appearance of the web app, synced between the devices of a user. Settings left out keep the
+/// choice of the device.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UiPreferences {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<UiTheme>,
+    /// Page opened after logging in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub landing_page: Option<LandingPage>,
+    /// Number of entries shown on each page of lists.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items_per_page: Option<u32>,
+    /// Time of the last change, the most recently changed copy of the preferences wins when the
+    /// server and a device disagree. `None` until the preferences are first saved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl UiPreferences {
+    /// Whether these preferences were changed after `other`.
+    pub fn is_newer_than(&self, other: &UiPreferences) -> bool {
+        self.updated_at > other.updated_at
+    }
+
+    /// Marks the preferences as changed now.
+    pub fn touch(&mut self) {
+        self.updated_at = Some(chrono::Utc::now());
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UiTheme {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl UiTheme {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            UiTheme::Dark => "dark",
+            UiTheme::Light => "light",
+        }
+    }
+    pub fn parse_str(s: impl AsRef<str>) -> Option<Self> {
+        match s.as_ref() {
+            "dark" => Some(UiTheme::Dark),
+            "light" => Some(UiTheme::Light),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LandingPage {
+    #[default]
+    Home,
+    Chat,
+    Prompt,
+    Image,
+}
+
+impl LandingPage {
+    pub const ALL: &'static [LandingPage] = &[
+        LandingPage::Home,
+        LandingPage::Chat,
+        LandingPage::Prompt,
+        LandingPage::Image,
+    ];
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            LandingPage::Home => "home",
+            LandingPage::Chat => "chat",
+            LandingPage::Prompt => "prompt",
+            LandingPage::Image => "image",
+        }
+    }
+    pub fn parse_str(s: impl AsRef<str>) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|page| page.to_str() == s.as_ref())
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AuthenticatedUser {
     pub id: String,
@@ -28,6 +125,9 @@ pub struct AuthenticatedUser {
     pub email: String,
     pub account_type: AccountType,
     pub registration_date: chrono::DateTime<chrono::Utc>,
+    /// Admin acting as the user when the session is an impersonation.
+    #[serde(default)]
+    pub impersonator: Option<String>,
 }
 
 impl AuthenticatedUser {
@@ -93,9 +193,19 @@ pub struct ListUserEntry {
     #[serde(default)]
     pub account_type: AccountType,
     pub registration_date: chrono::DateTime<chrono::Utc>,
+    /// `None` when the user never logged in.
+    #[serde(default)]
+    pub last_login: Option<chrono::DateTime<chrono::Utc>>,
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+/// A page of the user list along with the number of users matching the query on all pages.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct UserListPage {
+    pub users: Vec<ListUserEntry>,
+    pub total: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ListOrder {
     #[serde(rename = "username")]
     Username,
@@ -105,6 +215,9 @@ pub enum ListOrder {
     AccountType,
     #[serde(rename = "registration_date")]
     RegistrationDate,
+    /// Users that never logged in come last.
+    #[serde(rename = "last_login")]
+    LastLogin,
 }
 
 impl AsRef<str> for ListOrder {
@@ -114,15 +227,22 @@ impl AsRef<str> for ListOrder {
             Self::Email => "email",
             Self::Username => "username",
             Self::RegistrationDate => "registration_date",
+            Self::LastLogin => "last_login",
         }
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 pub struct ListQuery {
     pub page: Option<u32>,
     pub page_size: Option<u32>,
     pub order_by: Option<ListOrder>,
+    /// Sort in descending order instead of ascending.
+    pub descending: Option<bool>,
+    /// Only list users whose username or email contains this text, ignoring case.
+    pub search: Option<String>,
+    /// Only list users of this account type.
+    pub account_type: Option<AccountType>,
 }
 
 impl UrlQuery for ListQuery {
@@ -137,6 +257,15 @@ impl UrlQuery for ListQuery {
         if let Some(order_by) = self.order_by {
             serializer.append_pair("order_by", order_by.as_ref());
         }
+        if let Some(descending) = self.descending {
+            serializer.append_pair("descending", &descending.to_string());
+        }
+        if let Some(search) = &self.search {
+            serializer.append_pair("search", search);
+        }
+        if let Some(account_type) = self.account_type {
+            serializer.append_pair("account_type", account_type.as_ref());
+        }
         serializer.finish()
     }
 }
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+/// Events a webhook can be notified of.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// All samples of an image were generated.
+    ImageDone,
+    ImageFailed,
+    /// All entries of a batch were processed, successfully or not.
+    BatchDone,
+    /// Sent on demand to check that the webhook is reachable, webhooks don't subscribe to it.
+    Test,
+}
+
+impl WebhookEvent {
+    /// Events webhooks can subscribe to.
+    pub const SUBSCRIBABLE: &'static [WebhookEvent] = &[
+        WebhookEvent::ImageDone,
+        WebhookEvent::ImageFailed,
+        WebhookEvent::BatchDone,
+    ];
+
+    pub fn parse_str(s: impl AsRef<str>) -> Option<Self> {
+        match s.as_ref() {
+            "image_done" => Some(WebhookEvent::ImageDone),
+            "image_failed" => Some(WebhookEvent::ImageFailed),
+            "batch_done" => Some(WebhookEvent::BatchDone),
+            "test" => Some(WebhookEvent::Test),
+            _ => None,
+        }
+    }
+}
+
+impl AsRef<str> for WebhookEvent {
+    fn as_ref(&self) -> &str {
+        match self {
+            WebhookEvent::ImageDone => "image_done",
+            WebhookEvent::ImageFailed => "image_failed",
+            WebhookEvent::BatchDone => "batch_done",
+            WebhookEvent::Test => "test",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct WebhookCreateRequest {
+    /// `http` or `https` URL the events are posted to.
+    pub url: String,
+    /// Key of the HMAC-SHA256 signature of the payloads, it is never returned by the API.
+    pub secret: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookInspect {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub create_date: chrono::DateTime<chrono::Utc>,
+    /// Outcome of the last delivery, either the HTTP status of the response or the reason it
+    /// failed.
+    pub last_status: Option<String>,
+    pub last_delivery_date: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct WebhookCreateResponse {
+    pub webhook_id: String,
+}
+
+/// Body posted to a webhook, signed with the secret of the webhook in the
+/// `X-Airtifex-Signature` header.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookPayload {
+    /// Unique id of the delivery, retries of a delivery keep it.
+    pub id: String,
+    pub event: WebhookEvent,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub data: WebhookData,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum WebhookData {
+    Image {
+        image_id: String,
+        status: crate::image::ImageStatus,
+        error: Option<String>,
+    },
+    Batch {
+        batch_id: String,
+        model: String,
+        done: u64,
+        failed: u64,
+    },
+    Test {},
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct WebhookTestResponse {
+    pub delivered: bool,
+    /// HTTP status the webhook responded with.
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
@@ -1,24 +1,36 @@
 use airtifex_core::{
-    api_response::ApiResponse,
-    auth::Credentials,
+    admin::{ImpersonationResponse, SystemStats},
+    api_response::{ApiResponse, ErrorCode},
+    auth::{Credentials, RefreshTokenRequest},
     image::{
-        ImageGenerateRequest, ImageInspect, ImageModelListEntry, ImageSampleInspect,
-        TextToImageResponse,
+        ImageDeleteBatchRequest, ImageDeleteBatchResponse, ImageFavoriteResponse, ImageFeedPage,
+        ImageFeedQuery, ImageGenerateRequest, ImageGridQuery, ImageInspect, ImageModelListEntry,
+        ImagePresetInspect, ImagePresetRequest, ImageSampleInspect, ImageSampleRegenerateRequest,
+        ImageSampleRegenerateResponse, ImageShareRequest, ImageShareResponse, ImageTagCount,
+        ImageTagRequest, ImageVariationsQuery, TextToImageResponse,
     },
     llm::{
-        ChatEntryListEntry, ChatListEntry, ChatResponseRequest, ChatStartRequest,
-        ChatStartResponse, LlmListEntry, OneshotInferenceRequest, PromptInspect, UserChatCounters,
+        ChatContextTurnsUpdateRequest, ChatDeleteResponse, ChatEntryEditRequest,
+        ChatEntryListEntry, ChatForkQuery, ChatListEntry, ChatListPage, ChatListQuery,
+        ChatResponseRequest, ChatSearchQuery, ChatSearchResult, ChatShareRequest,
+        ChatShareResponse, ChatStartRequest, ChatStartResponse, ChatSystemPromptUpdateRequest,
+        LlmListEntry, LlmLoadStatus, OneshotInferenceRequest, PromptBundle, PromptFavoriteRequest,
+        PromptGenerateRequest, PromptImportQuery, PromptImportResponse, PromptInspect,
+        PromptListQuery, PromptReorderRequest, SystemPromptInspect, SystemPromptRequest,
+        UserChatCounters,
     },
     query::{append_query, UrlQuery},
     user::{
-        self, AuthenticatedUser, GetUserEntry, ListUserEntry, PasswordChangeRequest,
-        UserEditRequest, UserRegisterRequest,
+        self, AuthenticatedUser, GetUserEntry, PasswordChangeRequest, UiPreferences,
+        UserEditRequest, UserListPage, UserRegisterRequest, UserSettings,
     },
     JsonWebToken,
 };
 
 use gloo_net::http::{Request, Response};
+use gloo_storage::{LocalStorage, Storage};
 use serde::de::DeserializeOwned;
+use std::{cell::RefCell, rc::Rc};
 use thiserror::Error;
 
 #[derive(Clone, Copy)]
@@ -29,7 +41,8 @@ pub struct UnauthorizedApi {
 #[derive(Clone)]
 pub struct AuthorizedApi {
     url: &'static str,
-    token: JsonWebToken,
+    /// Shared by all clones so that a refreshed token is used everywhere.
+    token: Rc<RefCell<JsonWebToken>>,
 }
 
 impl UnauthorizedApi {
@@ -43,52 +56,100 @@ impl UnauthorizedApi {
         let token = into_json(response).await?;
         Ok(AuthorizedApi::new(self.url, token))
     }
+
+    pub async fn llm_load_status(&self) -> Result<LlmLoadStatus> {
+        let url = format!("{}/llm/load-status", self.url);
+        let response = Request::get(&url).send().await?;
+        into_json(response).await
+    }
 }
 
 impl AuthorizedApi {
-    pub const fn new(url: &'static str, token: JsonWebToken) -> Self {
-        Self { url, token }
+    pub fn new(url: &'static str, token: JsonWebToken) -> Self {
+        Self {
+            url,
+            token: Rc::new(RefCell::new(token)),
+        }
     }
     fn auth_header_value(&self) -> String {
-        format!("Bearer {}", self.token.token)
+        format!("Bearer {}", self.token.borrow().token)
     }
-    async fn send_json<T>(&self, req: Request) -> Result<T>
+    async fn send_json<T>(&self, req: impl Fn() -> Result<Request>) -> Result<T>
     where
         T: DeserializeOwned,
     {
         let response = self.send(req).await?;
         into_json(response).await
     }
-    async fn send(&self, req: Request) -> Result<Response> {
+    /// Sends the request built by `req`. If the access token was rejected it is refreshed and the
+    /// request is sent once more, failing only if the refresh token itself is expired.
+    async fn send(&self, req: impl Fn() -> Result<Request>) -> Result<Response> {
+        let response = self.send_authorized(req()?).await?;
+        if response.status() != 401 {
+            return Ok(response);
+        }
+        self.refresh().await?;
+        self.send_authorized(req()?).await
+    }
+    async fn send_authorized(&self, req: Request) -> Result<Response> {
         req.header("Authorization", &self.auth_header_value())
             .send()
             .await
             .map_err(Error::from)
         // log::info!("got response {response:?}");
     }
+    async fn refresh(&self) -> Result<()> {
+        let url = format!("{}/users/refresh", self.url);
+        let request = RefreshTokenRequest {
+            refresh_token: self.token.borrow().refresh_token.clone(),
+        };
+        let response = Request::post(&url).json(&request)?.send().await?;
+        let token: JsonWebToken = into_json(response).await?;
+        log::debug!("refreshed access token: save token in LocalStorage");
+        if let Err(e) = LocalStorage::set(crate::API_TOKEN_STORAGE_KEY, &token) {
+            log::error!("failed to save refreshed token: {e}");
+        }
+        *self.token.borrow_mut() = token;
+        Ok(())
+    }
+    /// Revokes the refresh token of this session.
+    pub async fn logout(&self) -> Result<()> {
+        let url = format!("{}/users/logout", self.url);
+        let request = RefreshTokenRequest {
+            refresh_token: self.token.borrow().refresh_token.clone(),
+        };
+        let response = Request::post(&url).json(&request)?.send().await?;
+        into_json(response).await
+    }
     pub async fn me(&self) -> Result<AuthenticatedUser> {
         let url = format!("{}/users/me", self.url);
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
     }
     pub async fn user_info(&self, username: &str) -> Result<GetUserEntry> {
         let url = format!("{}/users/{}", self.url, username);
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
     }
-    pub async fn user_list(&self, query: user::ListQuery) -> Result<Vec<ListUserEntry>> {
+    pub async fn user_list(&self, query: user::ListQuery) -> Result<UserListPage> {
         let url = append_query(format!("{}/users", self.url), query.as_query());
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
     }
     pub async fn user_add(&self, request: UserRegisterRequest) -> Result<String> {
         let url = format!("{}/users", self.url);
-        self.send_json(Request::post(&url).json(&request)?).await
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
     }
     pub async fn user_edit(&self, username: &str, request: UserEditRequest) -> Result<()> {
         let url = format!("{}/users/{}", self.url, username);
-        self.send_json(Request::post(&url).json(&request)?).await
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
     }
     pub async fn user_remove(&self, username: &str) -> Result<()> {
         let url = format!("{}/users/{}", self.url, username);
-        self.send_json(Request::delete(&url)).await
+        self.send_json(|| Ok(Request::delete(&url))).await
+    }
+    pub async fn user_impersonate(&self, username: &str) -> Result<ImpersonationResponse> {
+        let url = format!("{}/admin/users/{}/impersonate", self.url, username);
+        self.send_json(|| Ok(Request::post(&url))).await
     }
     pub async fn user_change_password(
         &self,
@@ -96,7 +157,55 @@ impl AuthorizedApi {
         request: PasswordChangeRequest,
     ) -> Result<()> {
         let url = format!("{}/users/{}/password", self.url, username);
-        self.send_json(Request::post(&url).json(&request)?).await
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
+    }
+    /// Returns the avatar of `username` as a data URL, `None` if the user didn't upload one.
+    pub async fn user_avatar(&self, username: &str) -> Result<Option<String>> {
+        let url = format!("{}/users/{}/avatar", self.url, username);
+        let response = self.send(|| Ok(Request::get(&url))).await?;
+        match response.status() {
+            200 => Ok(Some(crate::web_util::encode_image_base64(
+                &response.binary().await?,
+            ))),
+            404 => Ok(None),
+            _ => into_json(response).await,
+        }
+    }
+    pub async fn avatar_upload(&self, file: web_sys::File) -> Result<()> {
+        let url = format!("{}/users/profile/avatar", self.url);
+        let form_data = web_sys::FormData::new().map_err(js_error)?;
+        form_data
+            .append_with_blob("avatar", &file)
+            .map_err(js_error)?;
+        self.send_json(|| Ok(Request::post(&url).body(form_data.clone())))
+            .await
+    }
+    pub async fn avatar_remove(&self) -> Result<()> {
+        let url = format!("{}/users/profile/avatar", self.url);
+        self.send_json(|| Ok(Request::delete(&url))).await
+    }
+    pub async fn user_settings(&self) -> Result<UserSettings> {
+        let url = format!("{}/users/profile/settings", self.url);
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn user_settings_update(&self, settings: &UserSettings) -> Result<()> {
+        let url = format!("{}/users/profile/settings", self.url);
+        self.send_json(|| Ok(Request::put(&url).json(settings)?))
+            .await
+    }
+    pub async fn user_preferences(&self) -> Result<UiPreferences> {
+        let url = format!("{}/users/profile/preferences", self.url);
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    /// Returns the preferences stored afterwards, those of the server if they were changed later.
+    pub async fn user_preferences_update(
+        &self,
+        preferences: &UiPreferences,
+    ) -> Result<UiPreferences> {
+        let url = format!("{}/users/profile/preferences", self.url);
+        self.send_json(|| Ok(Request::put(&url).json(preferences)?))
+            .await
     }
     pub async fn chat_get_response(
         &self,
@@ -104,77 +213,312 @@ impl AuthorizedApi {
         id: &str,
     ) -> Result<Response> {
         let url = format!("{}/llm/chat/{id}", self.url);
-        self.send(Request::post(&url).json(&request)?).await
+        self.send(|| Ok(Request::post(&url).json(&request)?)).await
+    }
+    pub async fn chat_edit_entry(
+        &self,
+        id: &str,
+        entry_id: &str,
+        request: ChatEntryEditRequest,
+    ) -> Result<Response> {
+        let url = format!("{}/llm/chat/{id}/entries/{entry_id}", self.url);
+        self.send(|| Ok(Request::patch(&url).json(&request)?)).await
+    }
+    pub async fn chat_resume_stream(&self, id: &str, last_event_id: usize) -> Result<Response> {
+        let url = format!("{}/llm/chat/{id}/stream", self.url);
+        self.send(|| Ok(Request::get(&url).header("Last-Event-ID", &last_event_id.to_string())))
+            .await
     }
     pub async fn oneshot_inference(&self, request: OneshotInferenceRequest) -> Result<Response> {
         let url = format!("{}/llm/inference", self.url);
-        self.send(Request::post(&url).json(&request)?).await
+        self.send(|| Ok(Request::post(&url).json(&request)?)).await
     }
-    pub async fn prompt_list(&self) -> Result<Vec<PromptInspect>> {
-        let url = format!("{}/llm/prompt", self.url);
-        self.send_json(Request::get(&url)).await
+    pub async fn prompt_list(&self, query: PromptListQuery) -> Result<Vec<PromptInspect>> {
+        let url = append_query(format!("{}/llm/prompt", self.url), query.as_query());
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn prompt_set_favorite(&self, id: &str, is_favorite: bool) -> Result<()> {
+        let url = format!("{}/llm/prompt/{id}/favorite", self.url);
+        let request = PromptFavoriteRequest { is_favorite };
+        self.send_json(|| Ok(Request::patch(&url).json(&request)?))
+            .await
+    }
+    pub async fn prompt_reorder(&self, ids: Vec<String>) -> Result<()> {
+        let url = format!("{}/llm/prompt/order", self.url);
+        let request = PromptReorderRequest { ids };
+        self.send_json(|| Ok(Request::patch(&url).json(&request)?))
+            .await
+    }
+    pub async fn prompt_export(&self) -> Result<PromptBundle> {
+        let url = format!("{}/llm/prompt/export", self.url);
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn prompt_import(
+        &self,
+        bundle: &PromptBundle,
+        query: PromptImportQuery,
+    ) -> Result<PromptImportResponse> {
+        let url = append_query(format!("{}/llm/prompt/import", self.url), query.as_query());
+        self.send_json(|| Ok(Request::post(&url).json(bundle)?))
+            .await
     }
     pub async fn prompt_inspect(&self, id: &str) -> Result<PromptInspect> {
         let url = format!("{}/llm/prompt/{id}", self.url);
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn prompt_generate(
+        &self,
+        request: PromptGenerateRequest,
+        id: &str,
+    ) -> Result<Response> {
+        let url = format!("{}/llm/prompt/{id}/generate", self.url);
+        self.send(|| Ok(Request::post(&url).json(&request)?)).await
     }
     pub async fn chat_start_new(&self, request: ChatStartRequest) -> Result<ChatStartResponse> {
         let url = format!("{}/llm/chat", self.url);
-        self.send_json(Request::post(&url).json(&request)?).await
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
     }
     pub async fn chat_history(&self, id: &str) -> Result<Vec<ChatEntryListEntry>> {
         let url = format!("{}/llm/chat/{id}/history", self.url);
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn chat_fork(&self, id: &str, query: ChatForkQuery) -> Result<ChatStartResponse> {
+        let url = append_query(format!("{}/llm/chat/{id}/fork", self.url), query.as_query());
+        self.send_json(|| Ok(Request::post(&url))).await
     }
     pub async fn chat(&self, id: &str) -> Result<ChatListEntry> {
         let url = format!("{}/llm/chat/{id}", self.url);
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn chat_update_system_prompt(
+        &self,
+        id: &str,
+        request: ChatSystemPromptUpdateRequest,
+    ) -> Result<()> {
+        let url = format!("{}/llm/chat/{id}/system_prompt", self.url);
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
+    }
+    pub async fn system_prompts(&self) -> Result<Vec<SystemPromptInspect>> {
+        let url = format!("{}/llm/system_prompts", self.url);
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn system_prompt_create(
+        &self,
+        request: SystemPromptRequest,
+    ) -> Result<SystemPromptInspect> {
+        let url = format!("{}/llm/system_prompts", self.url);
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
+    }
+    pub async fn system_prompt_update(
+        &self,
+        id: &str,
+        request: SystemPromptRequest,
+    ) -> Result<SystemPromptInspect> {
+        let url = format!("{}/llm/system_prompts/{id}", self.url);
+        self.send_json(|| Ok(Request::put(&url).json(&request)?))
+            .await
+    }
+    pub async fn system_prompt_delete(&self, id: &str) -> Result<()> {
+        let url = format!("{}/llm/system_prompts/{id}", self.url);
+        self.send_json(|| Ok(Request::delete(&url))).await
     }
-    pub async fn chat_remove(&self, id: &str) -> Result<()> {
+    pub async fn chat_update_context_turns(
+        &self,
+        id: &str,
+        request: ChatContextTurnsUpdateRequest,
+    ) -> Result<()> {
+        let url = format!("{}/llm/chat/{id}/context_turns", self.url);
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
+    }
+    pub async fn chat_share(
+        &self,
+        id: &str,
+        request: ChatShareRequest,
+    ) -> Result<ChatShareResponse> {
+        let url = format!("{}/llm/chat/{id}/share", self.url);
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
+    }
+    pub async fn chat_revoke_shares(&self, id: &str) -> Result<()> {
+        let url = format!("{}/llm/chat/{id}/share", self.url);
+        self.send_json(|| Ok(Request::delete(&url))).await
+    }
+    pub async fn chat_remove(&self, id: &str) -> Result<ChatDeleteResponse> {
         let url = format!("{}/llm/chat/{id}", self.url);
-        self.send_json(Request::delete(&url)).await
+        self.send_json(|| Ok(Request::delete(&url))).await
     }
-    pub async fn chat_list(&self) -> Result<Vec<ChatListEntry>> {
-        let url = format!("{}/llm/chat", self.url);
-        self.send_json(Request::get(&url)).await
+    pub async fn chat_remove_entry(&self, id: &str, entry_id: &str) -> Result<ChatDeleteResponse> {
+        let url = format!("{}/llm/chat/{id}/entries/{entry_id}", self.url);
+        self.send_json(|| Ok(Request::delete(&url))).await
+    }
+    pub async fn chat_list(&self, query: ChatListQuery) -> Result<ChatListPage> {
+        let url = append_query(format!("{}/llm/chat", self.url), query.as_query());
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn chat_search(&self, query: ChatSearchQuery) -> Result<Vec<ChatSearchResult>> {
+        let url = append_query(format!("{}/llm/chat/search", self.url), query.as_query());
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn admin_stats(&self) -> Result<SystemStats> {
+        let url = format!("{}/admin/stats", self.url);
+        self.send_json(|| Ok(Request::get(&url))).await
     }
     pub async fn user_chat_counters(&self) -> Result<UserChatCounters> {
         let url = format!("{}/llm/chat/counters", self.url);
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
     }
     pub async fn image_list(&self) -> Result<Vec<ImageInspect>> {
         let url = format!("{}/image", self.url);
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn image_feed(&self, query: ImageFeedQuery) -> Result<ImageFeedPage> {
+        let url = append_query(format!("{}/image/feed", self.url), query.as_query());
+        self.send_json(|| Ok(Request::get(&url))).await
     }
     pub async fn image_delete(&self, id: &str) -> Result<()> {
         let url = format!("{}/image/{id}", self.url);
-        self.send_json(Request::delete(&url)).await
+        self.send_json(|| Ok(Request::delete(&url))).await
+    }
+    pub async fn image_delete_batch(
+        &self,
+        request: ImageDeleteBatchRequest,
+    ) -> Result<ImageDeleteBatchResponse> {
+        let url = format!("{}/image/delete-batch", self.url);
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
     }
     pub async fn image_info(&self, id: &str) -> Result<ImageInspect> {
         let url = format!("{}/image/{id}", self.url);
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
     }
     pub async fn image_samples(&self, id: &str) -> Result<Vec<ImageSampleInspect>> {
         let url = format!("{}/image/{id}/samples", self.url);
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn image_sample(&self, id: &str, n_sample: i32) -> Result<ImageSampleInspect> {
+        let url = format!("{}/image/{id}/samples/{n_sample}", self.url);
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    /// Returns the samples of the image tiled into a single PNG.
+    pub async fn image_grid(&self, id: &str, query: ImageGridQuery) -> Result<Vec<u8>> {
+        let url = append_query(format!("{}/image/{id}/grid", self.url), query.as_query());
+        let response = self.send(|| Ok(Request::get(&url))).await?;
+        match response.status() {
+            200 => Ok(response.binary().await?),
+            _ => into_json(response).await,
+        }
+    }
+    pub async fn image_sample_regenerate(
+        &self,
+        id: &str,
+        n_sample: i32,
+    ) -> Result<ImageSampleRegenerateResponse> {
+        let url = format!("{}/image/{id}/samples/{n_sample}/regenerate", self.url);
+        let request = ImageSampleRegenerateRequest { seed: None };
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
+    }
+    pub async fn image_progress_stream(&self, id: &str) -> Result<Response> {
+        let url = format!("{}/image/{id}/progress", self.url);
+        self.send(|| Ok(Request::get(&url))).await
+    }
+    pub async fn image_retry(&self, id: &str) -> Result<TextToImageResponse> {
+        let url = format!("{}/image/{id}/retry", self.url);
+        self.send_json(|| Ok(Request::post(&url))).await
+    }
+    pub async fn image_toggle_favorite(&self, id: &str) -> Result<ImageFavoriteResponse> {
+        let url = format!("{}/image/{id}/favorite", self.url);
+        self.send_json(|| Ok(Request::post(&url))).await
+    }
+    pub async fn image_reproduce(&self, id: &str) -> Result<TextToImageResponse> {
+        let url = format!("{}/image/{id}/reproduce", self.url);
+        self.send_json(|| Ok(Request::post(&url))).await
+    }
+    pub async fn image_variations(
+        &self,
+        id: &str,
+        query: ImageVariationsQuery,
+    ) -> Result<TextToImageResponse> {
+        let url = append_query(
+            format!("{}/image/{id}/variations", self.url),
+            query.as_query(),
+        );
+        self.send_json(|| Ok(Request::post(&url))).await
+    }
+    pub async fn image_share(&self, id: &str, n_sample: i32) -> Result<ImageShareResponse> {
+        let url = format!("{}/image/{id}/share", self.url);
+        let request = ImageShareRequest {
+            n_sample: Some(n_sample),
+        };
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
+    }
+    pub async fn image_revoke_shares(&self, id: &str) -> Result<()> {
+        let url = format!("{}/image/{id}/share", self.url);
+        self.send_json(|| Ok(Request::delete(&url))).await
+    }
+    pub async fn image_tags(&self) -> Result<Vec<ImageTagCount>> {
+        let url = format!("{}/image/tags", self.url);
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn image_tag_add(&self, id: &str, tag: &str) -> Result<Vec<String>> {
+        let url = format!("{}/image/{id}/tags", self.url);
+        let request = ImageTagRequest { tag: tag.into() };
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
+    }
+    pub async fn image_tag_remove(&self, id: &str, tag: &str) -> Result<Vec<String>> {
+        let tag = String::from(js_sys::encode_uri_component(tag));
+        let url = format!("{}/image/{id}/tags/{tag}", self.url);
+        self.send_json(|| Ok(Request::delete(&url))).await
     }
     pub async fn image_generate(
         &self,
         request: ImageGenerateRequest,
     ) -> Result<TextToImageResponse> {
         let url = format!("{}/image/generate", self.url);
-        self.send_json(Request::post(&url).json(&request)?).await
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
+    }
+    pub async fn image_presets(&self) -> Result<Vec<ImagePresetInspect>> {
+        let url = format!("{}/image/presets", self.url);
+        self.send_json(|| Ok(Request::get(&url))).await
+    }
+    pub async fn image_preset_create(
+        &self,
+        request: ImagePresetRequest,
+    ) -> Result<ImagePresetInspect> {
+        let url = format!("{}/image/presets", self.url);
+        self.send_json(|| Ok(Request::post(&url).json(&request)?))
+            .await
+    }
+    pub async fn image_preset_update(
+        &self,
+        id: &str,
+        request: ImagePresetRequest,
+    ) -> Result<ImagePresetInspect> {
+        let url = format!("{}/image/presets/{id}", self.url);
+        self.send_json(|| Ok(Request::put(&url).json(&request)?))
+            .await
+    }
+    pub async fn image_preset_delete(&self, id: &str) -> Result<()> {
+        let url = format!("{}/image/presets/{id}", self.url);
+        self.send_json(|| Ok(Request::delete(&url))).await
     }
     pub async fn large_language_models(&self) -> Result<Vec<LlmListEntry>> {
         let url = format!("{}/llm/models", self.url);
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
     }
     pub async fn image_models(&self) -> Result<Vec<ImageModelListEntry>> {
         let url = format!("{}/image/models", self.url);
-        self.send_json(Request::get(&url)).await
+        self.send_json(|| Ok(Request::get(&url))).await
     }
-    pub fn token(&self) -> &JsonWebToken {
-        &self.token
+    pub fn token(&self) -> JsonWebToken {
+        self.token.borrow().clone()
     }
 }
 
@@ -190,6 +534,25 @@ pub enum Error {
     ApiResponseError(#[from] airtifex_core::api_response::ResponseError),
     #[error("{0}")]
     ApiError(String),
+    /// Failure response of the API.
+    #[error("{message}")]
+    Failure {
+        code: Option<ErrorCode>,
+        message: String,
+    },
+}
+
+impl Error {
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Error::Failure { code, .. } => *code,
+            _ => None,
+        }
+    }
+}
+
+fn js_error(e: wasm_bindgen::JsValue) -> Error {
+    Error::ApiError(format!("{e:?}"))
 }
 
 async fn into_json<T>(response: Response) -> Result<T>
@@ -198,5 +561,13 @@ where
 {
     let json = response.json::<ApiResponse>().await?;
     // log::info!("got json {json:?}");
-    json.into_result(Error::ApiError)
+    // the id lets the failure be found in the logs of the server
+    let request_id = json.request_id().map(|id| id.to_string());
+    json.into_result(|code, message| {
+        let message = match request_id {
+            Some(id) => format!("{message} (request {id})"),
+            None => message,
+        };
+        Error::Failure { code, message }
+    })
 }
@@ -0,0 +1,43 @@
+use leptos::*;
+
+/// Avatar of `username` as a circle of `size` pixels, shows the initials of the username while
+/// `src` is empty.
+#[component]
+pub fn Avatar(
+    cx: Scope,
+    username: String,
+    src: Signal<Option<String>>,
+    size: u32,
+) -> impl IntoView {
+    let initials = initials(&username);
+    let style = format!(
+        "width: {size}px; height: {size}px; font-size: {}px;",
+        size * 2 / 5
+    );
+
+    view! { cx,
+        {move || match src.get() {
+            Some(src) => view! { cx,
+                <img class="avatar" style=style.clone() src=src alt=username.clone() />
+            }.into_view(cx),
+            None => view! { cx,
+                <span class="avatar" style=style.clone()>{initials.clone()}</span>
+            }.into_view(cx),
+        }}
+    }
+}
+
+/// First letters of up to two words of the username, `john.doe` becomes `JD`.
+fn initials(username: &str) -> String {
+    let initials: String = username
+        .split(|c: char| !c.is_alphanumeric())
+        .filter_map(|word| word.chars().next())
+        .take(2)
+        .flat_map(char::to_uppercase)
+        .collect();
+    if initials.is_empty() {
+        "?".into()
+    } else {
+        initials
+    }
+}
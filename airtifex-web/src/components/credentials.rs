@@ -1,4 +1,4 @@
-use crate::components::status_message::*;
+use crate::{components::status_message::*, i18n::t};
 
 use leptos::{ev, *};
 
@@ -28,7 +28,7 @@ pub fn CredentialsForm(
           class = "form-control"
           type = "text"
           required
-          placeholder = "Username"
+          placeholder = move || t(cx, "login.username")
           prop:disabled = move || disabled.get()
           on:keyup = move |ev: ev::KeyboardEvent| {
             let val = event_target_value(&ev);
@@ -44,7 +44,7 @@ pub fn CredentialsForm(
           class = "form-control"
           type = "password"
           required
-          placeholder = "Password"
+          placeholder = move || t(cx, "login.password")
           prop:disabled = move || disabled.get()
           on:keyup = move |ev: ev::KeyboardEvent| {
             match &*ev.key() {
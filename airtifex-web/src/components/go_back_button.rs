@@ -1,4 +1,4 @@
-use crate::pages::PageStack;
+use crate::{i18n::t, pages::PageStack};
 
 use leptos::*;
 
@@ -8,7 +8,7 @@ pub fn GoBackButton(cx: Scope) -> impl IntoView {
     view! { cx,
       <a class="btn btn-outline-lighter me-auto ms-2" href=href>
           <img class="fill-airtifex me-2" src="/icons/arrow-left-circle.svg" />
-          "Go back"
+          {move || t(cx, "button.go_back")}
       </a>
     }
 }
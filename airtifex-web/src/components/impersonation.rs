@@ -0,0 +1,39 @@
+use crate::pages::{goto, Page};
+use airtifex_core::user::AuthenticatedUser;
+
+use leptos::*;
+
+/// Shows who the session acts as while an admin impersonates a user. The banner stays until the
+/// admin returns to their own session with `on_return`, the home page is opened afterwards.
+#[component]
+pub fn ImpersonationBanner<F>(
+    cx: Scope,
+    user_info: RwSignal<Option<AuthenticatedUser>>,
+    on_return: F,
+) -> impl IntoView
+where
+    F: Fn() + 'static + Copy,
+{
+    view! { cx, {move || {
+        match user_info.get() {
+            Some(AuthenticatedUser { username, impersonator: Some(admin), .. }) => view! { cx,
+                <div
+                  class="alert alert-warning position-fixed bottom-0 start-50 translate-middle-x mb-3 shadow d-flex align-items-center"
+                  style="z-index: 1100;"
+                  role="status"
+                >
+                  <span>"Acting as "<strong>{username}</strong>" on behalf of "{admin}</span>
+                  <button class="btn btn-sm btn-outline-dark ms-3" on:click=move |_| {
+                      on_return();
+                      if let Err(e) = goto(cx, Page::Home.raw_path()) {
+                          log::error!("failed to open the home page - {e}");
+                      }
+                  }>
+                    "Return to admin"
+                  </button>
+                </div>
+            }.into_view(cx),
+            _ => view! { cx, <></> }.into_view(cx),
+        }
+    }}}
+}
@@ -0,0 +1,26 @@
+use crate::i18n::{t, use_lang, Lang};
+
+use leptos::*;
+
+#[component]
+pub fn LangSelect(cx: Scope) -> impl IntoView {
+    let lang = use_lang(cx);
+    view! { cx,
+      <div class="input-group input-group-sm mt-2">
+          <label class="input-group-text" for="langSelector">{move || t(cx, "lang.label")}</label>
+          <select
+            class="form-select"
+            id="langSelector"
+            on:change=move |ev| {
+                if let Some(selected) = Lang::parse_str(&event_target_value(&ev)) {
+                    lang.update(|l| *l = selected);
+                }
+            }
+          >
+          {Lang::ALL.iter().map(|l| view! { cx,
+              <option value=l.as_str() selected=move || lang.get() == *l>{l.name()}</option>
+          }).collect::<Vec<_>>()}
+          </select>
+      </div>
+    }
+}
@@ -6,7 +6,22 @@ pub fn ListPageControl(
     current_list_page: RwSignal<u32>,
     elem_count: Signal<usize>,
     page_size: ReadSignal<usize>,
+    /// Number of elements on all pages, when it isn't known the last page is the first one that
+    /// isn't full.
+    #[prop(optional)]
+    total: Option<Signal<usize>>,
 ) -> impl IntoView {
+    let page_count = move || {
+        total.map(|total| {
+            let page_size = page_size.get().max(1);
+            ((total.get() + page_size - 1) / page_size).max(1) as u32
+        })
+    };
+    let has_next_page = move || match page_count() {
+        Some(page_count) => current_list_page.get() < page_count,
+        None => elem_count.get() == page_size.get(),
+    };
+
     let back_page_btn = move || {
         if current_list_page.get() > 1 {
             view! { cx,
@@ -24,7 +39,9 @@ pub fn ListPageControl(
     };
 
     let page = move || {
-        if elem_count.get() == page_size.get() {
+        if let Some(page_count) = page_count() {
+            format!("{}/{page_count}", current_list_page.get())
+        } else if has_next_page() {
             format!("{}/...", current_list_page.get())
         } else {
             format!("{0}/{0}", current_list_page.get())
@@ -32,7 +49,7 @@ pub fn ListPageControl(
     };
 
     let forward_page_btn = move || {
-        if elem_count.get() == page_size.get() {
+        if has_next_page() {
             view! { cx,
               <button
                   class="ps-3"
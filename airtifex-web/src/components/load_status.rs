@@ -0,0 +1,49 @@
+use crate::{api::UnauthorizedApi, web_util};
+use airtifex_core::llm::LlmLoadStatus;
+
+use leptos::*;
+
+/// Interval between two requests of the load status while models are loading.
+const POLL_INTERVAL_MS: i32 = 2000;
+
+/// Shows a banner while the server is still loading its language models. The banner disappears
+/// once the load status reports the server as ready, the same moment `/ready` responds with
+/// `200 OK`.
+#[component]
+pub fn LoadStatusBanner(cx: Scope, api: UnauthorizedApi) -> impl IntoView {
+    let load_status = create_rw_signal(cx, None::<LlmLoadStatus>);
+
+    let poll_load_status = create_action(cx, move |_: &()| async move {
+        loop {
+            match api.llm_load_status().await {
+                Ok(status) => {
+                    let ready = status.ready;
+                    load_status.update(|s| *s = Some(status));
+                    if ready {
+                        break;
+                    }
+                }
+                Err(e) => log::error!("failed to fetch the model load status - {e}"),
+            }
+            let _ = web_util::sleep(POLL_INTERVAL_MS).await;
+        }
+    });
+    poll_load_status.dispatch(());
+
+    view! { cx, {move || {
+        match load_status.get() {
+            Some(status) if !status.ready => view! { cx,
+                <div
+                  class="alert alert-info position-fixed top-0 start-50 translate-middle-x mt-3 shadow"
+                  style="z-index: 1100;"
+                  role="status"
+                >
+                  <strong>"Model loading… "</strong>
+                  {format!("{:.0}%", status.percentage)}
+                  <span class="ms-2 text-muted">"Chats will be available once the models are ready."</span>
+                </div>
+            }.into_view(cx),
+            _ => view! { cx, <></> }.into_view(cx),
+        }
+    }}}
+}
@@ -1,3 +1,6 @@
+use crate::i18n::t;
+use airtifex_core::QueueStatus;
+
 use leptos::*;
 
 #[component]
@@ -18,3 +21,19 @@ pub fn Dots(cx: Scope, is_loading: ReadSignal<bool>) -> impl IntoView {
         }
     }}}
 }
+
+/// Place of a request in the queue of its model, shown until the model starts it.
+#[component]
+pub fn QueuePosition(cx: Scope, status: ReadSignal<Option<QueueStatus>>) -> impl IntoView {
+    view! { cx, {move || {
+        if let Some(status) = status.get() {
+            view!{cx,
+                <p class="ms-3 text-airtifex-light font-monospace">
+                    {t(cx, "queue.position").replace("{position}", &status.position.to_string())}
+                </p>
+            }.into_view(cx)
+        } else {
+            view!{cx, <></>}.into_view(cx)
+        }
+    }}}
+}
@@ -0,0 +1,127 @@
+use crate::{
+    markdown::{self, Block, Inline, MarkdownStream},
+    web_util,
+};
+
+use leptos::*;
+use std::{cell::RefCell, rc::Rc};
+
+/// Renders `text` as Markdown. When the new value of `text` extends the previous one only the
+/// blocks at its end are parsed and rendered again, so streamed responses stay cheap to render.
+#[component]
+pub fn Markdown(cx: Scope, text: Signal<String>) -> impl IntoView {
+    let stream = Rc::new(RefCell::new(MarkdownStream::default()));
+    let completed = create_rw_signal(cx, Vec::<Block>::new());
+    let tail = create_rw_signal(cx, Vec::<Block>::new());
+
+    create_effect(cx, move |_| {
+        text.with(|text| {
+            let mut stream = stream.borrow_mut();
+            if !text.starts_with(stream.text()) {
+                *stream = MarkdownStream::default();
+                completed.update(|c| c.clear());
+            }
+            let parsed_len = stream.text().len();
+            let new_blocks = stream.push_str(&text[parsed_len..]);
+            if !new_blocks.is_empty() {
+                completed.update(|c| c.extend(new_blocks));
+            }
+            tail.update(|t| *t = stream.tail().to_vec());
+        })
+    });
+
+    view! { cx,
+        <div class="markdown">
+            {move || completed.with(|blocks| {
+                blocks.iter().map(|block| block_view(cx, block)).collect::<Vec<_>>()
+            })}
+            {move || tail.with(|blocks| {
+                blocks.iter().map(|block| block_view(cx, block)).collect::<Vec<_>>()
+            })}
+        </div>
+    }
+}
+
+fn block_view(cx: Scope, block: &Block) -> View {
+    match block {
+        Block::Paragraph(text) => view! { cx, <p>{inline_view(cx, text)}</p> }.into_view(cx),
+        Block::Heading(1, text) => view! { cx, <h4>{inline_view(cx, text)}</h4> }.into_view(cx),
+        Block::Heading(2, text) => view! { cx, <h5>{inline_view(cx, text)}</h5> }.into_view(cx),
+        Block::Heading(_, text) => view! { cx, <h6>{inline_view(cx, text)}</h6> }.into_view(cx),
+        Block::Code { lang, code } => view! { cx,
+            <CodeBlock lang=lang.clone() code=code.clone() />
+        }
+        .into_view(cx),
+        Block::List { ordered, items } => {
+            let items = items
+                .iter()
+                .map(|item| view! { cx, <li>{inline_view(cx, item)}</li> })
+                .collect::<Vec<_>>();
+            if *ordered {
+                view! { cx, <ol>{items}</ol> }.into_view(cx)
+            } else {
+                view! { cx, <ul>{items}</ul> }.into_view(cx)
+            }
+        }
+        Block::Quote(text) => {
+            view! { cx, <blockquote>{inline_view(cx, text)}</blockquote> }.into_view(cx)
+        }
+        Block::Rule => view! { cx, <hr /> }.into_view(cx),
+    }
+}
+
+fn inline_view(cx: Scope, text: &str) -> Vec<View> {
+    markdown::parse_inline(text)
+        .into_iter()
+        .map(|inline| match inline {
+            Inline::Text(text) => text.into_view(cx),
+            Inline::Code(code) => view! { cx, <code>{code}</code> }.into_view(cx),
+            Inline::Strong(text) => view! { cx, <strong>{text}</strong> }.into_view(cx),
+            Inline::Emphasis(text) => view! { cx, <em>{text}</em> }.into_view(cx),
+            Inline::Link { text, url } => view! { cx,
+                <a href=url target="_blank" rel="noopener noreferrer">{text}</a>
+            }
+            .into_view(cx),
+        })
+        .collect()
+}
+
+/// Highlighted code with a button copying it to the clipboard.
+#[component]
+fn CodeBlock(cx: Scope, lang: String, code: String) -> impl IntoView {
+    let copied = create_rw_signal(cx, false);
+    let tokens = markdown::highlight(&lang, &code)
+        .into_iter()
+        .map(|(kind, text)| view! { cx, <span class=kind.class()>{text}</span> })
+        .collect::<Vec<_>>();
+
+    let copy_action = create_action(cx, move |code: &String| {
+        let code = code.clone();
+        async move {
+            match web_util::copy_to_clipboard(&code).await {
+                Ok(()) => {
+                    // the block might have been rendered again in the meantime
+                    let _ = copied.try_update(|c| *c = true);
+                    let _ = web_util::sleep(2000).await;
+                    let _ = copied.try_update(|c| *c = false);
+                }
+                Err(e) => log::error!("failed to copy code - {e:?}"),
+            }
+        }
+    });
+
+    view! { cx,
+        <div class="code-block">
+            <div class="code-block-header">
+                <span class="font-monospace">{if lang.is_empty() { "code".into() } else { lang }}</span>
+                <button
+                    class="btn btn-sm btn-outline-lighter py-0"
+                    on:click=move |_| copy_action.dispatch(code.clone())
+                >
+                    {move || if copied.get() { "Copied" } else { "Copy" }}
+                </button>
+            </div>
+            <pre><code>{tokens}</code></pre>
+        </div>
+    }
+}
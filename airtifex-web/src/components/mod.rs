@@ -1,16 +1,25 @@
+pub mod avatar;
 pub mod credentials;
 pub mod email_validation;
 pub mod go_back_button;
+pub mod impersonation;
+pub mod lang_select;
 pub mod list_page_control;
+pub mod load_status;
 pub mod loading;
+pub mod markdown;
 pub mod modal;
 pub mod navbar;
 pub mod password_validation;
 pub mod status_message;
+pub mod system_prompt_picker;
+pub mod theme_toggle;
 pub mod titled_child_page;
 pub mod users;
 
 pub use self::{
-    credentials::*, email_validation::*, go_back_button::*, list_page_control::*, loading::*,
-    modal::*, navbar::*, password_validation::*, status_message::*, titled_child_page::*, users::*,
+    avatar::*, credentials::*, email_validation::*, go_back_button::*, impersonation::*,
+    lang_select::*, list_page_control::*, load_status::*, loading::*, markdown::*, modal::*,
+    navbar::*, password_validation::*, status_message::*, system_prompt_picker::*, theme_toggle::*,
+    titled_child_page::*, users::*,
 };
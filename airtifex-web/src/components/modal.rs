@@ -1,3 +1,5 @@
+use crate::i18n::t;
+
 use leptos::*;
 
 #[component]
@@ -47,14 +49,14 @@ where
                   on:click=move |_| remove_action_fn()
                   class="btn btn-danger"
                 >
-                  "Remove"
+                  {move || t(cx, "button.remove")}
                 </button>
                 <button
                   type="button"
                   class="btn btn-secondary"
                   data-bs-dismiss="modal"
                 >
-                  "Cancel"
+                  {move || t(cx, "button.cancel")}
                 </button>
         }
         .into_view(cx),
@@ -1,4 +1,8 @@
-use crate::{pages, Page, PageStack};
+use crate::{
+    components::{avatar::*, lang_select::*, theme_toggle::*},
+    i18n::{t, use_lang},
+    pages, Page, PageStack,
+};
 use airtifex_core::user::AuthenticatedUser;
 
 use leptos::*;
@@ -15,27 +19,35 @@ pub fn NavItem(
     page_stack: ReadSignal<PageStack>,
     nav: &'static NavElement,
 ) -> impl IntoView {
+    // only changes when the route does so that items don't rerender on every page stack update
+    let current = create_memo(cx, move |_| page_stack.get().current().clone());
+    let lang = use_lang(cx);
     match nav {
         NavElement::Main(page) => {
+            let is_current = move || current.get().root_page() == *page;
             let classes = move || {
-                if page_stack.get().current().root_page() == *page {
-                    "nav-link selected"
+                if is_current() {
+                    "nav-link active"
                 } else {
                     "nav-link"
                 }
             };
+            let aria_current = move || is_current().then_some("page");
             view! { cx,
                 <li class="nav-item sb-item">
-                    <a href=page.raw_path() class=classes aria-current="page">
+                    <a href=page.raw_path() class=classes aria-current=aria_current>
                         <img class="me-2" src=page.icon()/>
-                        <span class="fw-bold text-white">{page.nav_display()}</span>
+                        <span class="fw-bold text-white">{move || page.nav_display(lang.get())}</span>
                     </a>
                 </li>
             }
             .into_view(cx)
         }
         NavElement::Sub(root, sub) => {
-            let is_current = move || page_stack.get().current().root_page() == *root;
+            let is_current = move || current.get().root_page() == *root;
+            // the id doesn't depend on the language so that it stays the same when it changes
+            let collapse_id = format!("{}-collapse", root.nav_id().replace('.', "-"));
+            let collapse_target = format!("#{collapse_id}");
             let aria_expanded = move || is_current().to_string();
             let collapsed = move || {
                 if is_current() {
@@ -46,23 +58,23 @@ pub fn NavItem(
             };
             let parent_classes = move || {
                 if is_current() {
-                    "btn btn-toggle text-start nav-link w-100 text-white fw-bold selected"
+                    "btn btn-toggle text-start nav-link w-100 text-white fw-bold active"
                 } else {
                     "btn btn-toggle text-start nav-link w-100 collapsed text-white fw-bold"
                 }
             };
             view! { cx,
                 <li class="nav-item sb-item">
-                  <button class=parent_classes data-bs-toggle="collapse" data-bs-target="#images-collapse" aria-expanded={aria_expanded}>
+                  <button class=parent_classes data-bs-toggle="collapse" data-bs-target=collapse_target aria-expanded={aria_expanded}>
                       <img src=root.icon()/>
-                      {root.nav_display()}
+                      {move || root.nav_display(lang.get())}
                   </button>
-                  <div id="images-collapse" class=collapsed>
+                  <div id=collapse_id class=collapsed>
                     <ul class="list-unstyled fw-normal pb-1">
                       { move || {
                           sub.iter().map(|p| {
-                            let classes = move || if page_stack.get().current() == p {
-                              "ms-5 ps-2 nav-link selected"
+                            let classes = move || if current.get().nav_page() == *p {
+                              "ms-5 ps-2 nav-link active"
                             } else {
                               "ms-5 ps-2 nav-link"
                             };
@@ -72,7 +84,7 @@ pub fn NavItem(
                                 style="cursor: pointer;"
                                 on:click=move |_| pages::goto(cx, p.raw_path()).expect("subpage")
                               >
-                                <p class="text-white text-decoration-none fw-bold">"└ "{p.nav_display()}</p>
+                                <p class="text-white text-decoration-none fw-bold">"└ "{move || p.nav_display(lang.get())}</p>
                               </li>
                             }.into_view(cx)
                           }).collect::<Vec<_>>()
@@ -90,6 +102,7 @@ pub fn NavBar<F>(
     cx: Scope,
     page_stack: ReadSignal<PageStack>,
     user_info: RwSignal<Option<AuthenticatedUser>>,
+    avatar: ReadSignal<Option<String>>,
     on_logout: F,
 ) -> impl IntoView
 where
@@ -111,12 +124,16 @@ where
          { nav_items }
          </ul>
          <hr/>
+         <ThemeToggle />
+         <LangSelect />
+         <hr/>
          <div class="dropdown">
            <a href="#" class="d-flex align-items-center text-white text-decoration-none dropdown-toggle" id="dropdownUser1" data-bs-toggle="dropdown" aria-expanded="false">
-               <strong>{&user.username}</strong>
+               <Avatar username=user.username.clone() src=avatar.into() size=32 />
+               <strong class="ms-2">{&user.username}</strong>
            </a>
            <ul class="dropdown-menu dropdown-menu-dark text-small shadow" aria-labelledby="dropdownUser1">
-             <li><A class="dropdown-item" href=Page::UserProfile.raw_path()>"Profile"</A></li>
+             <li><A class="dropdown-item" href=Page::UserProfile.raw_path()>{move || t(cx, "nav.profile")}</A></li>
              <li><hr class="dropdown-divider"/></li>
              <li>
                <A
@@ -127,7 +144,7 @@ where
                  move |_| on_logout()
                }>
                    <img class="me-2" src="/icons/log-out.svg"/>
-                   "Logout"
+                   {move || t(cx, "nav.logout")}
                </A>
              </li>
            </ul>
@@ -0,0 +1,62 @@
+use crate::{api, components::status_message::Message, pages};
+
+use leptos::*;
+
+/// Select of the system prompts of the library, the content of the picked one is passed to
+/// `on_pick`. The library is fetched again whenever `library_version` changes.
+#[component]
+pub fn SystemPromptPicker<F>(
+    cx: Scope,
+    authorized_api: RwSignal<Option<api::AuthorizedApi>>,
+    status_message: RwSignal<Message>,
+    library_version: RwSignal<u32>,
+    on_pick: F,
+) -> impl IntoView
+where
+    F: Fn(String) + Copy + 'static,
+{
+    let system_prompts = create_resource(
+        cx,
+        move || library_version.get(),
+        move |_| async move {
+            let Some(api) = authorized_api.get() else {
+                return vec![];
+            };
+            match api.system_prompts().await {
+                Ok(prompts) => prompts,
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to load the system prompts - {e}"));
+                    });
+                    vec![]
+                }
+            }
+        },
+    );
+
+    view! { cx,
+      <select
+        class="form-select"
+        on:change=move |ev| {
+            let id = event_target_value(&ev);
+            let picked = system_prompts
+                .read(cx)
+                .unwrap_or_default()
+                .into_iter()
+                .find(|p| p.id == id);
+            if let Some(picked) = picked {
+                on_pick(picked.content);
+            }
+        }
+      >
+        <option value="" selected>"From the library..."</option>
+        { move || {
+          system_prompts.read(cx).unwrap_or_default().into_iter().map(|p| {
+              let name = if p.owned { p.name } else { format!("{} (shared)", p.name) };
+              view!{ cx, <option value=p.id>{name}</option> }.into_view(cx)
+          }).collect::<Vec<_>>()
+        }}
+      </select>
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{i18n::t, preferences::use_preferences};
+use airtifex_core::user::UiTheme;
+
+use gloo_storage::{LocalStorage, Storage};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+/// Also read by the inline script in `index.html` that applies the theme before the first paint.
+const THEME_STORAGE_KEY: &str = "theme";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+        }
+    }
+
+    pub fn toggled(&self) -> Self {
+        match self {
+            Theme::Dark => Theme::Light,
+            Theme::Light => Theme::Dark,
+        }
+    }
+
+    /// Loads the saved theme falling back to the `prefers-color-scheme` of the OS.
+    pub fn load() -> Self {
+        if let Ok(theme) = LocalStorage::get(THEME_STORAGE_KEY) {
+            return theme;
+        }
+        let prefers_light = web_sys::window()
+            .and_then(|w| {
+                w.match_media("(prefers-color-scheme: light)")
+                    .ok()
+                    .flatten()
+            })
+            .map(|m| m.matches())
+            .unwrap_or_default();
+        if prefers_light {
+            Theme::Light
+        } else {
+            Theme::Dark
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = LocalStorage::set(THEME_STORAGE_KEY, self) {
+            log::error!("failed to save theme - {e}");
+        }
+    }
+
+    /// Sets the `data-theme` attribute on the root element.
+    pub fn apply(&self) {
+        if let Some(root) = web_sys::window()
+            .and_then(|w| w.document())
+            .and_then(|d| d.document_element())
+        {
+            if let Err(e) = root.set_attribute("data-theme", self.as_str()) {
+                log::error!("failed to apply theme - {e:?}");
+            }
+        }
+    }
+}
+
+impl From<UiTheme> for Theme {
+    fn from(theme: UiTheme) -> Self {
+        match theme {
+            UiTheme::Dark => Theme::Dark,
+            UiTheme::Light => Theme::Light,
+        }
+    }
+}
+
+impl From<Theme> for UiTheme {
+    fn from(theme: Theme) -> Self {
+        match theme {
+            Theme::Dark => UiTheme::Dark,
+            Theme::Light => UiTheme::Light,
+        }
+    }
+}
+
+/// Creates the theme signal and makes it available to all components through the context.
+pub fn provide_theme(cx: Scope) -> RwSignal<Theme> {
+    let theme = create_rw_signal(cx, Theme::load());
+    create_effect(cx, move |_| {
+        let theme = theme.get();
+        theme.apply();
+        theme.save();
+    });
+    provide_context(cx, theme);
+    theme
+}
+
+/// Returns the current theme signal provided by [`provide_theme`].
+pub fn use_theme(cx: Scope) -> RwSignal<Theme> {
+    use_context::<RwSignal<Theme>>(cx).expect("theme context")
+}
+
+#[component]
+pub fn ThemeToggle(cx: Scope) -> impl IntoView {
+    let theme = use_theme(cx);
+    let preferences = use_preferences(cx);
+    let icon = move || match theme.get() {
+        Theme::Dark => "/icons/sun.svg",
+        Theme::Light => "/icons/moon.svg",
+    };
+    let label = move || match theme.get() {
+        Theme::Dark => t(cx, "theme.light"),
+        Theme::Light => t(cx, "theme.dark"),
+    };
+    view! { cx,
+      <button
+        class="btn btn-outline-lighter nav-link w-100 text-start"
+        on:click=move |_| {
+            let toggled = theme.get().toggled();
+            preferences.change(|p| p.theme = Some(toggled.into()));
+        }
+      >
+          <img class="me-2" src=icon />
+          <span class="fw-bold text-white">{label}</span>
+      </button>
+    }
+}
@@ -1,26 +1,58 @@
-use crate::pages;
-use airtifex_core::user::ListUserEntry;
+use crate::{api, components::avatar::*, pages};
+use airtifex_core::user::{AccountType, ListUserEntry};
 
 use leptos::*;
 
 #[component]
 pub fn UserListEntry(
     cx: Scope,
+    authorized_api: RwSignal<Option<api::AuthorizedApi>>,
     user: ListUserEntry,
     remove_user: WriteSignal<Option<String>>,
+    impersonate: Action<String, ()>,
 ) -> impl IntoView {
+    let username = user.username.clone();
+    let avatar = create_resource(
+        cx,
+        || (),
+        move |_| {
+            let username = username.clone();
+            async move {
+                match authorized_api.get()?.user_avatar(&username).await {
+                    Ok(avatar) => avatar,
+                    Err(e) => {
+                        log::error!("failed to fetch avatar of {username} - {e}");
+                        None
+                    }
+                }
+            }
+        },
+    );
+    let avatar = Signal::derive(cx, move || avatar.read(cx).flatten());
     let pw_change_href = format!("/users/{}/password", &user.username);
     let edit_href = format!("/users/{}/edit", &user.username);
     let edit_href2 = edit_href.clone();
+    // admins can't be impersonated
+    let impersonated = (user.account_type != AccountType::Admin).then(|| user.username.clone());
     view! {cx,
       <tr class="text-white no-border align-middle">
           <td
             style="cursor: pointer;"
             on:click = move |_| pages::goto(cx, &edit_href2).expect("user edit page")
-          >{ user.username.clone() }</td>
+          >
+            <Avatar username=user.username.clone() src=avatar size=32 />
+            <span class="ms-2">{ user.username.clone() }</span>
+          </td>
           <td>{ user.email }</td>
           <td>{ user.account_type.to_str() }</td>
           <td>{ user.registration_date.format("%a, %d %b %Y %H:%M:%S").to_string() }</td>
+          <td>
+            {
+                user.last_login
+                    .map(|date| date.format("%a, %d %b %Y %H:%M:%S").to_string())
+                    .unwrap_or_else(|| "never".into())
+            }
+          </td>
           <td>
               <div class="btn-group" role="user toolbar" aria-label="user toolbar">
                   <button
@@ -42,6 +74,15 @@ pub fn UserListEntry(
                       <img src="/icons/key.svg" />
                       "Change password"
                   </a>
+                  {impersonated.map(|username| view! { cx,
+                      <button
+                        class="btn btn-outline-lighter d-flex flex-row"
+                        on:click=move |_| impersonate.dispatch(username.clone())
+                      >
+                          <img src="/icons/log-in.svg" />
+                          "Impersonate"
+                      </button>
+                  })}
               </div>
           </td>
       </tr>
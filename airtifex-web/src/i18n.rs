@@ -0,0 +1,199 @@
+//! Translations of the user facing strings of the UI. Every string has an id that is looked up
+//! in the catalog of the selected language, strings missing from a catalog are shown in English.
+
+use gloo_storage::{LocalStorage, Storage};
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+const LANG_STORAGE_KEY: &str = "lang";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+}
+
+impl Lang {
+    pub const ALL: &'static [Lang] = &[Lang::En, Lang::De];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::De => "de",
+        }
+    }
+
+    pub fn parse_str(s: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|lang| lang.as_str() == s)
+    }
+
+    /// Name of the language in the language itself.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lang::En => "English",
+            Lang::De => "Deutsch",
+        }
+    }
+
+    fn catalog(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Lang::En => EN,
+            Lang::De => DE,
+        }
+    }
+
+    /// Returns the string `id` in this language, in English when it isn't translated.
+    pub fn t(&self, id: &'static str) -> &'static str {
+        lookup(self.catalog(), id)
+            .or_else(|| lookup(EN, id))
+            .unwrap_or_else(|| {
+                log::warn!("missing string {id}");
+                id
+            })
+    }
+
+    /// Loads the saved language falling back to the language of the browser.
+    pub fn load() -> Self {
+        if let Ok(lang) = LocalStorage::get(LANG_STORAGE_KEY) {
+            return lang;
+        }
+        web_sys::window()
+            .and_then(|w| w.navigator().language())
+            .and_then(|lang| {
+                let prefix = lang.split('-').next().unwrap_or_default().to_lowercase();
+                Self::parse_str(&prefix)
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = LocalStorage::set(LANG_STORAGE_KEY, self) {
+            log::error!("failed to save language - {e}");
+        }
+    }
+
+    /// Sets the `lang` attribute on the root element.
+    pub fn apply(&self) {
+        if let Some(root) = web_sys::window()
+            .and_then(|w| w.document())
+            .and_then(|d| d.document_element())
+        {
+            if let Err(e) = root.set_attribute("lang", self.as_str()) {
+                log::error!("failed to apply language - {e:?}");
+            }
+        }
+    }
+}
+
+fn lookup(catalog: &[(&'static str, &'static str)], id: &str) -> Option<&'static str> {
+    catalog
+        .iter()
+        .find(|(key, _)| *key == id)
+        .map(|(_, text)| *text)
+}
+
+/// Creates the language signal and makes it available to all components through the context.
+pub fn provide_lang(cx: Scope) -> RwSignal<Lang> {
+    let lang = create_rw_signal(cx, Lang::load());
+    create_effect(cx, move |_| {
+        let lang = lang.get();
+        lang.apply();
+        lang.save();
+    });
+    provide_context(cx, lang);
+    lang
+}
+
+/// Returns the current language signal provided by [`provide_lang`].
+pub fn use_lang(cx: Scope) -> RwSignal<Lang> {
+    use_context::<RwSignal<Lang>>(cx).expect("language context")
+}
+
+/// Returns the string `id` in the current language, called within a closure of a view the
+/// string changes together with the language.
+pub fn t(cx: Scope, id: &'static str) -> &'static str {
+    use_lang(cx).get().t(id)
+}
+
+const EN: &[(&str, &str)] = &[
+    ("page.home.title", "Home"),
+    ("page.users.title", "Users"),
+    ("page.user_add.title", "Add user"),
+    ("page.user_edit.title", "Edit user"),
+    ("page.user_password.title", "Change password"),
+    ("page.user_profile.title", "Profile"),
+    ("page.chat.title", "Chat"),
+    ("page.prompt.title", "Prompt"),
+    ("page.prompt_generate.title", "Generate Prompt"),
+    ("page.prompt_list.title", "Prompt History"),
+    ("page.generate_image.title", "Generate Image"),
+    ("page.login.title", "Login"),
+    ("page.not_found.title", "404"),
+    ("nav.home", "Home"),
+    ("nav.users", "Users"),
+    ("nav.chat", "Chat"),
+    ("nav.prompt", "Prompt"),
+    ("nav.prompt_generate", "generate"),
+    ("nav.prompt_list", "history"),
+    ("nav.generate_image", "Generate Image"),
+    ("nav.login", "Login"),
+    ("nav.profile", "Profile"),
+    ("nav.logout", "Logout"),
+    ("theme.light", "Light theme"),
+    ("theme.dark", "Dark theme"),
+    ("lang.label", "Language"),
+    ("button.go_back", "Go back"),
+    ("button.remove", "Remove"),
+    ("button.cancel", "Cancel"),
+    ("login.prompt", "Please login to your account"),
+    ("login.action", "Login"),
+    ("login.username", "Username"),
+    ("login.password", "Password"),
+    ("queue.position", "position {position} in queue"),
+    (
+        "not_found.message",
+        "Oh my 404! The page you're looking for doesn't exist so I brought you back home ;)",
+    ),
+];
+
+const DE: &[(&str, &str)] = &[
+    ("page.home.title", "Startseite"),
+    ("page.users.title", "Benutzer"),
+    ("page.user_add.title", "Benutzer hinzufügen"),
+    ("page.user_edit.title", "Benutzer bearbeiten"),
+    ("page.user_password.title", "Passwort ändern"),
+    ("page.user_profile.title", "Profil"),
+    ("page.chat.title", "Chat"),
+    ("page.prompt.title", "Prompt"),
+    ("page.prompt_generate.title", "Prompt generieren"),
+    ("page.prompt_list.title", "Prompt-Verlauf"),
+    ("page.generate_image.title", "Bild generieren"),
+    ("page.login.title", "Anmelden"),
+    ("nav.home", "Startseite"),
+    ("nav.users", "Benutzer"),
+    ("nav.chat", "Chat"),
+    ("nav.prompt", "Prompt"),
+    ("nav.prompt_generate", "generieren"),
+    ("nav.prompt_list", "Verlauf"),
+    ("nav.generate_image", "Bild generieren"),
+    ("nav.login", "Anmelden"),
+    ("nav.profile", "Profil"),
+    ("nav.logout", "Abmelden"),
+    ("theme.light", "Helles Design"),
+    ("theme.dark", "Dunkles Design"),
+    ("lang.label", "Sprache"),
+    ("button.go_back", "Zurück"),
+    ("button.remove", "Entfernen"),
+    ("button.cancel", "Abbrechen"),
+    ("login.prompt", "Bitte melde dich mit deinem Konto an"),
+    ("login.action", "Anmelden"),
+    ("login.username", "Benutzername"),
+    ("login.password", "Passwort"),
+    ("queue.position", "Position {position} in der Warteschlange"),
+    (
+        "not_found.message",
+        "Oh je, 404! Die gesuchte Seite existiert nicht, deshalb geht es zurück zur Startseite ;)",
+    ),
+];
@@ -1,4 +1,8 @@
-use crate::{api, components::status_message::Message, pages};
+use crate::{api, components::status_message::Message, pages, web_util};
+use airtifex_core::{
+    llm::{ChatStreamMessage, StopReason},
+    QueueStatus,
+};
 
 use futures::StreamExt;
 use leptos::*;
@@ -52,11 +56,207 @@ pub async fn read_inference_stream(
             }
         }
         Err(err) => {
+            pages::goto_login_if_expired(cx, &err, authorized_api);
             let e = err.to_string();
-            pages::goto_login_if_expired(cx, &e, authorized_api);
             status_message.update(|m| {
                 *m = Message::Error(format!("failed to generate an answer - {e}"));
             })
         }
     }
 }
+
+/// How many times an interrupted chat response stream is resumed before giving up.
+const MAX_STREAM_RECONNECTS: usize = 5;
+/// Delay before resuming an interrupted chat response stream in milliseconds.
+const STREAM_RECONNECT_DELAY: i32 = 1000;
+
+enum StreamOutcome {
+    Done { reason: StopReason },
+    Cancelled,
+    Failed(String),
+    Interrupted(String),
+}
+
+/// Reads the server-sent events of a chat response into `response_view`, `queue_status` holds
+/// the place of the request in the queue until the model starts it. When the connection breaks
+/// before the response is done the stream is resumed after the last received event.
+#[allow(clippy::too_many_arguments)]
+pub async fn read_chat_event_stream(
+    cx: Scope,
+    chat_id: &str,
+    resp: Result<gloo_net::http::Response, api::Error>,
+    authorized_api: RwSignal<Option<api::AuthorizedApi>>,
+    response_view: RwSignal<String>,
+    queue_status: RwSignal<Option<QueueStatus>>,
+    status_message: RwSignal<Message>,
+    should_cancel: RwSignal<bool>,
+) {
+    let mut resp = resp;
+    let mut last_event_id = 0;
+    let mut reconnects = 0;
+    response_view.update(|rsp| *rsp = "".into());
+
+    loop {
+        let response = match resp {
+            Ok(response) => response,
+            Err(err) => {
+                pages::goto_login_if_expired(cx, &err, authorized_api);
+                let e = err.to_string();
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to generate an answer - {e}"));
+                });
+                return;
+            }
+        };
+
+        let outcome = read_chat_events(
+            response,
+            &mut last_event_id,
+            response_view,
+            queue_status,
+            should_cancel,
+        )
+        .await;
+        // a resumed stream starts with the current place in the queue again
+        queue_status.update(|q| *q = None);
+        let e = match outcome {
+            StreamOutcome::Done {
+                reason: StopReason::Repetition,
+            } => {
+                status_message.update(|m| {
+                    *m =
+                        Message::Error("the answer was cut because it kept repeating itself".into())
+                });
+                return;
+            }
+            StreamOutcome::Done {
+                reason: StopReason::Aborted,
+            } => {
+                status_message
+                    .update(|m| *m = Message::Error("the answer was aborted by an admin".into()));
+                return;
+            }
+            StreamOutcome::Done {
+                reason: StopReason::Moderated,
+            } => {
+                status_message.update(|m| {
+                    *m = Message::Error("the answer was stopped by the content filter".into())
+                });
+                return;
+            }
+            StreamOutcome::Done {
+                reason: StopReason::EmptyAnswer,
+            } => {
+                status_message
+                    .update(|m| *m = Message::Error("the model returned an empty response".into()));
+                return;
+            }
+            StreamOutcome::Done { .. } | StreamOutcome::Cancelled => return,
+            StreamOutcome::Failed(e) => {
+                status_message.update(|m| *m = Message::Error(e));
+                return;
+            }
+            StreamOutcome::Interrupted(e) => e,
+        };
+
+        let api = authorized_api.get();
+        let Some(api) = api.filter(|_| reconnects < MAX_STREAM_RECONNECTS) else {
+            status_message.update(|m| *m = Message::Error(format!("response interrupted - {e}")));
+            return;
+        };
+        reconnects += 1;
+        log::warn!("response stream interrupted - {e}, reconnecting ({reconnects})");
+        let _ = web_util::sleep(STREAM_RECONNECT_DELAY).await;
+        if should_cancel.get() {
+            should_cancel.update(|c| *c = false);
+            return;
+        }
+        resp = api.chat_resume_stream(chat_id, last_event_id).await;
+    }
+}
+
+async fn read_chat_events(
+    response: gloo_net::http::Response,
+    last_event_id: &mut usize,
+    response_view: RwSignal<String>,
+    queue_status: RwSignal<Option<QueueStatus>>,
+    should_cancel: RwSignal<bool>,
+) -> StreamOutcome {
+    if !response.ok() {
+        let e = match response.text().await {
+            Ok(text) => text,
+            Err(e) => e.to_string(),
+        };
+        return StreamOutcome::Failed(format!("failed to generate an answer - {e}"));
+    }
+    let Some(body) = response.body() else {
+        return StreamOutcome::Failed("response body empty".into());
+    };
+    let body = body.unchecked_into::<wasm_streams::readable::sys::ReadableStream>();
+    let body = wasm_streams::ReadableStream::from_raw(body);
+    let mut reader = body.into_stream();
+    let mut buffer = Vec::new();
+
+    loop {
+        if should_cancel.get() {
+            should_cancel.update(|c| *c = false);
+            return StreamOutcome::Cancelled;
+        }
+        match reader.next().await {
+            Some(Ok(chunk)) => {
+                buffer.extend(
+                    js_sys::Array::from(&chunk)
+                        .iter()
+                        .map(|v| v.as_f64().unwrap_or_default() as u8),
+                );
+                // events are separated by an empty line
+                while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+                    let event = buffer.drain(..end + 2).collect::<Vec<_>>();
+                    let event = String::from_utf8_lossy(&event);
+                    let (mut name, mut id, mut data) = ("message", None, String::new());
+                    for line in event.lines() {
+                        if let Some(value) = line.strip_prefix("event:") {
+                            name = value.trim();
+                        } else if let Some(value) = line.strip_prefix("id:") {
+                            id = value.trim().parse().ok();
+                        } else if let Some(value) = line.strip_prefix("data:") {
+                            data.push_str(value.strip_prefix(' ').unwrap_or(value));
+                        }
+                    }
+                    match name {
+                        "queue" => {
+                            if let Ok(status) = serde_json::from_str(&data) {
+                                queue_status.update(|q| *q = Some(status));
+                            }
+                        }
+                        "started" => queue_status.update(|q| *q = None),
+                        // keep-alive comments come without data
+                        _ if data.is_empty() => {}
+                        _ => match serde_json::from_str::<ChatStreamMessage>(&data) {
+                            Ok(ChatStreamMessage::Token { content }) => {
+                                response_view.update(|rsp| rsp.push_str(&content));
+                            }
+                            Ok(ChatStreamMessage::Usage { generated_tokens }) => {
+                                log::debug!("the answer took {generated_tokens} tokens");
+                            }
+                            Ok(ChatStreamMessage::Done { reason }) => {
+                                return StreamOutcome::Done { reason }
+                            }
+                            Ok(ChatStreamMessage::Error { message }) => {
+                                return StreamOutcome::Failed(message)
+                            }
+                            Err(e) => log::warn!("invalid {name} event - {e}"),
+                        },
+                    }
+                    if let Some(id) = id {
+                        *last_event_id = id;
+                    }
+                }
+            }
+            Some(Err(e)) => {
+                return StreamOutcome::Interrupted(e.as_string().unwrap_or_default());
+            }
+            None => return StreamOutcome::Interrupted("connection closed".into()),
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use airtifex_core::user::AuthenticatedUser;
+use airtifex_core::{admin::ImpersonationResponse, user::AuthenticatedUser, JsonWebToken};
 
 use gloo_storage::{LocalStorage, Storage};
 use leptos::*;
@@ -7,32 +7,45 @@ use leptos_router::*;
 
 mod api;
 mod components;
+mod i18n;
 mod inference;
+mod markdown;
 mod pages;
+mod preferences;
 mod web_util;
 
-use components::{navbar::*, status_message::Message};
+use components::{
+    impersonation::ImpersonationBanner, load_status::LoadStatusBanner, navbar::*,
+    status_message::Message, theme_toggle::provide_theme,
+};
 use pages::*;
 
 const DEFAULT_API_URL: &str = "/api";
 const API_TOKEN_STORAGE_KEY: &str = "api-token";
+/// Token of the admin session kept while the admin impersonates a user.
+const ADMIN_API_TOKEN_STORAGE_KEY: &str = "admin-api-token";
 
 #[component]
 pub fn App(cx: Scope) -> impl IntoView {
     provide_meta_context(cx);
+    provide_theme(cx);
+    let lang = i18n::provide_lang(cx);
     // -- signals -- //
 
     let authorized_api = create_rw_signal(cx, None::<api::AuthorizedApi>);
+    let preferences = preferences::provide_preferences(cx, authorized_api);
     let user_info = create_rw_signal(cx, None::<AuthenticatedUser>);
+    let user_avatar = create_rw_signal(cx, None::<String>);
     let logged_in = Signal::derive(cx, move || user_info.get().is_some());
     let page_stack = create_rw_signal(cx, PageStack::load());
 
     let global_message = create_rw_signal(cx, Message::Empty);
     let users_message = create_rw_signal(cx, Message::Empty);
-    let subtitle = create_rw_signal(cx, None::<String>);
+    // id of the subtitle in the string catalog
+    let subtitle = create_rw_signal(cx, None::<&'static str>);
     let title = Signal::derive(cx, move || {
         if let Some(subtitle) = subtitle.get() {
-            format!("AIrtifex - {subtitle}")
+            format!("AIrtifex - {}", lang.get().t(subtitle))
         } else {
             format!("AIrtifex")
         }
@@ -45,6 +58,10 @@ pub fn App(cx: Scope) -> impl IntoView {
             Some(api) => match api.me().await {
                 Ok(info) => {
                     log::info!("{info:?}");
+                    match api.user_avatar(&info.username).await {
+                        Ok(avatar) => user_avatar.update(|a| *a = avatar),
+                        Err(err) => log::error!("Unable to fetch user avatar: {err}"),
+                    }
                     user_info.update(|i| *i = Some(info));
                 }
                 Err(err) => {
@@ -62,8 +79,11 @@ pub fn App(cx: Scope) -> impl IntoView {
     let unauthorized_api = api::UnauthorizedApi::new(DEFAULT_API_URL);
     if let Ok(token) = LocalStorage::get(API_TOKEN_STORAGE_KEY) {
         let api = api::AuthorizedApi::new(DEFAULT_API_URL, token);
-        authorized_api.update(|a| *a = Some(api));
+        authorized_api.update(|a| *a = Some(api.clone()));
         fetch_user_info.dispatch(());
+        spawn_local(async move {
+            preferences.sync(&api).await;
+        });
     }
 
     log::debug!("User is logged in: {}", logged_in.get());
@@ -83,9 +103,25 @@ pub fn App(cx: Scope) -> impl IntoView {
     });
 
     let logout = create_action(cx, move |_| async move {
+        if let Some(api) = authorized_api.get() {
+            if let Err(e) = api.logout().await {
+                log::error!("Unable to revoke refresh token: {e}");
+            }
+        }
+        // logging out of an impersonation ends the admin session as well
+        if let Ok(token) = LocalStorage::get(ADMIN_API_TOKEN_STORAGE_KEY) {
+            LocalStorage::delete(ADMIN_API_TOKEN_STORAGE_KEY);
+            if let Err(e) = api::AuthorizedApi::new(DEFAULT_API_URL, token)
+                .logout()
+                .await
+            {
+                log::error!("Unable to revoke refresh token of the admin session: {e}");
+            }
+        }
         authorized_api.update(|api: &mut Option<api::AuthorizedApi>| {
             *api = None;
         });
+        user_avatar.update(|avatar| *avatar = None);
         user_info.update(|user: &mut Option<AuthenticatedUser>| {
             *user = None;
         })
@@ -95,22 +131,55 @@ pub fn App(cx: Scope) -> impl IntoView {
         logout.dispatch(());
     };
 
+    // the admin session is put aside while the admin acts as another user, the impersonation
+    // token can't be refreshed
+    let on_impersonate = move |response: ImpersonationResponse| {
+        let Some(api) = authorized_api.get() else {
+            return;
+        };
+        if let Err(e) = LocalStorage::set(ADMIN_API_TOKEN_STORAGE_KEY, api.token()) {
+            log::error!("Unable to keep the admin session: {e}");
+            return;
+        }
+        let token = JsonWebToken {
+            token: response.token,
+            refresh_token: String::new(),
+        };
+        authorized_api.update(|a| *a = Some(api::AuthorizedApi::new(DEFAULT_API_URL, token)));
+        fetch_user_info.dispatch(());
+    };
+
+    let on_return_to_admin = move || {
+        match LocalStorage::get::<JsonWebToken>(ADMIN_API_TOKEN_STORAGE_KEY) {
+            Ok(token) => {
+                LocalStorage::delete(ADMIN_API_TOKEN_STORAGE_KEY);
+                authorized_api
+                    .update(|a| *a = Some(api::AuthorizedApi::new(DEFAULT_API_URL, token)));
+                fetch_user_info.dispatch(());
+            }
+            // without the admin session there is nothing to return to
+            Err(_) => logout.dispatch(()),
+        }
+    };
+
     view! { cx,
           <Link rel="icon" sizes="16x16 32x32 96x96 180x180 256x256 512x512" href="/favicon.ico" />
           <Script src="/popper.min.js" />
           <Script src="/bootstrap.min.js" />
           <Title text=move || title.get() />
           <Router>
+            <LoadStatusBanner api=unauthorized_api />
+            <ImpersonationBanner user_info on_return=on_return_to_admin />
             <main>
               <Routes>
                 <Route
                   path=Page::Home.raw_path()
                   view=move |cx| {
                       page_stack.update(|v| v.push(Page::Home));
-                      subtitle.update(|sub| *sub = Some("Home".into()));
+                      subtitle.update(|sub| *sub = Some("page.home.title"));
 
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <Home authorized_api user_info global_message />
                       }.into_view(cx)
                   }
@@ -125,10 +194,10 @@ pub fn App(cx: Scope) -> impl IntoView {
                       if !user_info.get().map(|user| user.is_admin()).unwrap_or_default() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some("Users".into()));
+                      subtitle.update(|sub| *sub = Some("page.users.title"));
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
-                        <Users authorized_api users_message />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
+                        <Users authorized_api users_message on_impersonate />
                       }.into_view(cx)
                   }
                 />
@@ -138,9 +207,9 @@ pub fn App(cx: Scope) -> impl IntoView {
                       if !user_info.get().map(|user| user.is_admin()).unwrap_or_default() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some("Add user".into()));
+                      subtitle.update(|sub| *sub = Some("page.user_add.title"));
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <UserAdd authorized_api page_stack users_message />
                       }.into_view(cx)
                   }
@@ -151,9 +220,9 @@ pub fn App(cx: Scope) -> impl IntoView {
                       if !user_info.get().map(|user| user.is_admin()).unwrap_or_default() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some("Change password".into()));
+                      subtitle.update(|sub| *sub = Some("page.user_password.title"));
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <UserPasswordChange authorized_api page_stack users_message />
                       }.into_view(cx)
                   }
@@ -164,11 +233,11 @@ pub fn App(cx: Scope) -> impl IntoView {
                       if user_info.get().is_none() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some("Profile".into()));
+                      subtitle.update(|sub| *sub = Some("page.user_profile.title"));
 
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
-                        <UserProfile page_stack user_info=user_info.read_only() />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
+                        <UserProfile authorized_api page_stack user_info=user_info.read_only() avatar=user_avatar />
                       }.into_view(cx)
                   }
                 />
@@ -182,10 +251,10 @@ pub fn App(cx: Scope) -> impl IntoView {
                       }).unwrap_or_default() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some("Edit user".into()));
+                      subtitle.update(|sub| *sub = Some("page.user_edit.title"));
 
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <UserEdit authorized_api page_stack users_message />
                       }.into_view(cx)
                   }
@@ -199,10 +268,10 @@ pub fn App(cx: Scope) -> impl IntoView {
                       if user_info.get().is_none() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some("Chat".into()));
+                      subtitle.update(|sub| *sub = Some("page.chat.title"));
 
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <Chat authorized_api page_stack />
                       }.into_view(cx)
                   }
@@ -213,10 +282,10 @@ pub fn App(cx: Scope) -> impl IntoView {
                       if user_info.get().is_none() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some("Chat".into()));
+                      subtitle.update(|sub| *sub = Some("page.chat.title"));
 
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <ChatView authorized_api page_stack />
                       }.into_view(cx)
                   }
@@ -230,10 +299,10 @@ pub fn App(cx: Scope) -> impl IntoView {
                       if user_info.get().is_none() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some(Page::PromptGenerate.title().into()));
+                      subtitle.update(|sub| *sub = Some(Page::PromptGenerate.title_id()));
 
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <PromptGenerate authorized_api page_stack />
                       }.into_view(cx)
                   }
@@ -245,10 +314,10 @@ pub fn App(cx: Scope) -> impl IntoView {
                       if user_info.get().is_none() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some(Page::PromptList.title().into()));
+                      subtitle.update(|sub| *sub = Some(Page::PromptList.title_id()));
 
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <PromptList authorized_api page_stack />
                       }.into_view(cx)
                   }
@@ -260,10 +329,10 @@ pub fn App(cx: Scope) -> impl IntoView {
                       if user_info.get().is_none() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some(Page::PromptView("".into()).title().into()));
+                      subtitle.update(|sub| *sub = Some(Page::PromptView("".into()).title_id()));
 
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <PromptView authorized_api page_stack />
                       }.into_view(cx)
                   }
@@ -277,11 +346,11 @@ pub fn App(cx: Scope) -> impl IntoView {
                       if user_info.get().is_none() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some(Page::GenerateImage.title().into()));
+                      subtitle.update(|sub| *sub = Some(Page::GenerateImage.title_id()));
 
 
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <GenerateImage authorized_api page_stack />
                       }.into_view(cx)
                   }
@@ -292,10 +361,10 @@ pub fn App(cx: Scope) -> impl IntoView {
                       if user_info.get().is_none() {
                         return redirect_home(cx).into_view(cx);
                       }
-                      subtitle.update(|sub| *sub = Some(Page::GeneratedImageView("".into()).title().into()));
+                      subtitle.update(|sub| *sub = Some(Page::GeneratedImageView("".into()).title_id()));
 
                       view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <ImageView authorized_api page_stack />
                       }.into_view(cx)
                   }
@@ -312,15 +381,20 @@ pub fn App(cx: Scope) -> impl IntoView {
                             return view!{cx, <Redirect path=redirect />}.into_view(cx);
                         }
                       }
-                      subtitle.update(|sub| *sub = Some("Login".into()));
+                      subtitle.update(|sub| *sub = Some("page.login.title"));
                       view! { cx,
                         <Login
                           api = unauthorized_api
-                          on_success = move |api| {
+                          on_success = move |api: api::AuthorizedApi| {
                               log::info!("Successfully logged in");
-                              authorized_api.update(|v| *v = Some(api));
-                              pages::goto(cx, Page::Home.raw_path()).expect("home page");
+                              authorized_api.update(|v| *v = Some(api.clone()));
                               fetch_user_info.dispatch(());
+                              // the landing page is known once the preferences of the user are synced
+                              let navigate = use_navigate(cx);
+                              spawn_local(async move {
+                                  let landing_page = preferences.sync(&api).await.landing_page.unwrap_or_default();
+                                  navigate(Page::from(landing_page).raw_path(), Default::default()).expect("landing page");
+                              });
                           } />
                       }.into_view(cx)
                   }
@@ -329,10 +403,10 @@ pub fn App(cx: Scope) -> impl IntoView {
                     path="*"
                     view=move |cx| {
                         page_stack.update(|v| v.push(Page::Home));
-                        subtitle.update(|sub| *sub = Some("404".into()));
-                        global_message.update(|m| *m = Message::Error("Oh my 404! The page you're looking for doesn't exist so I brought you back home ;)".into()));
+                        subtitle.update(|sub| *sub = Some("page.not_found.title"));
+                        global_message.update(|m| *m = Message::Error(i18n::t(cx, "not_found.message").into()));
                         view! { cx,
-                        <NavBar page_stack=page_stack.read_only() user_info on_logout />
+                        <NavBar page_stack=page_stack.read_only() user_info avatar=user_avatar.read_only() on_logout />
                         <Home authorized_api user_info global_message />
                     }
                     }
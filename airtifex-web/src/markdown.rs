@@ -0,0 +1,558 @@
+//! Minimal Markdown parser for the answers of language models. Only the elements models commonly
+//! produce are supported, anything else is rendered as plain text.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    Paragraph(String),
+    Heading(u8, String),
+    /// Unterminated fences, like the ones of a response that is still streamed, contain the
+    /// rest of the text.
+    Code {
+        lang: String,
+        code: String,
+    },
+    List {
+        ordered: bool,
+        items: Vec<String>,
+    },
+    Quote(String),
+    Rule,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Code(String),
+    Strong(String),
+    Emphasis(String),
+    Link { text: String, url: String },
+}
+
+struct ParsedBlock {
+    block: Block,
+    /// Byte offset right after the last line of the block.
+    end: usize,
+}
+
+/// Parses Markdown that is received in chunks. Only the blocks that can still change are parsed
+/// again when text is appended, so streaming a long answer doesn't parse it over and over.
+#[derive(Default)]
+pub struct MarkdownStream {
+    text: String,
+    /// Start of the first block that isn't complete.
+    offset: usize,
+    tail: Vec<Block>,
+}
+
+impl MarkdownStream {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Blocks at the end of the text that may still change when more text is appended.
+    pub fn tail(&self) -> &[Block] {
+        &self.tail
+    }
+
+    /// Appends `chunk` and returns the blocks that became complete because of it.
+    pub fn push_str(&mut self, chunk: &str) -> Vec<Block> {
+        self.text.push_str(chunk);
+        let rest = &self.text[self.offset..];
+        let mut blocks = parse_blocks(rest);
+        // a block is complete once the line after it was received in full, before that the
+        // line might still turn out to continue it
+        let complete = blocks
+            .iter()
+            .take_while(|b| rest[b.end..].contains('\n'))
+            .count();
+        self.tail = blocks
+            .split_off(complete)
+            .into_iter()
+            .map(|b| b.block)
+            .collect();
+        if let Some(last) = blocks.last() {
+            self.offset += last.end;
+        }
+        blocks.into_iter().map(|b| b.block).collect()
+    }
+}
+
+pub fn parse(text: &str) -> Vec<Block> {
+    parse_blocks(text).into_iter().map(|b| b.block).collect()
+}
+
+/// Lines of `text` without line endings together with the offset right after each of them.
+fn lines(text: &str) -> impl Iterator<Item = (&str, usize)> {
+    text.split_inclusive('\n').scan(0, |offset, line| {
+        *offset += line.len();
+        Some((line.trim_end_matches(['\n', '\r']), *offset))
+    })
+}
+
+fn parse_blocks(text: &str) -> Vec<ParsedBlock> {
+    let mut blocks = vec![];
+    let mut lines = lines(text).peekable();
+
+    while let Some((line, mut end)) = lines.next() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        let block = if let Some(lang) = fence(trimmed) {
+            let mut code = vec![];
+            for (line, line_end) in lines.by_ref() {
+                end = line_end;
+                let trimmed = line.trim();
+                if trimmed.starts_with("```") && trimmed.chars().all(|c| c == '`') {
+                    break;
+                }
+                code.push(line);
+            }
+            Block::Code {
+                lang: lang.to_string(),
+                code: code.join("\n"),
+            }
+        } else if let Some((level, heading)) = heading(trimmed) {
+            Block::Heading(level, heading.to_string())
+        } else if is_rule(trimmed) {
+            Block::Rule
+        } else if let Some((ordered, item)) = list_item(trimmed) {
+            let mut items = vec![item.to_string()];
+            while let Some(&(line, line_end)) = lines.peek() {
+                let trimmed = line.trim_start();
+                if trimmed.is_empty() {
+                    break;
+                }
+                match list_item(trimmed) {
+                    Some((o, item)) if o == ordered => items.push(item.to_string()),
+                    Some(_) => break,
+                    None if starts_block(trimmed) => break,
+                    None => {
+                        let last = items.last_mut().expect("list has an item");
+                        last.push('\n');
+                        last.push_str(trimmed);
+                    }
+                }
+                end = line_end;
+                lines.next();
+            }
+            Block::List { ordered, items }
+        } else if trimmed.starts_with('>') {
+            let mut quote = vec![strip_quote(trimmed)];
+            while let Some(&(line, line_end)) = lines.peek() {
+                let trimmed = line.trim_start();
+                if !trimmed.starts_with('>') {
+                    break;
+                }
+                quote.push(strip_quote(trimmed));
+                end = line_end;
+                lines.next();
+            }
+            Block::Quote(quote.join("\n"))
+        } else {
+            let mut paragraph = vec![line.trim()];
+            while let Some(&(line, line_end)) = lines.peek() {
+                let trimmed = line.trim_start();
+                if trimmed.is_empty() || starts_block(trimmed) {
+                    break;
+                }
+                paragraph.push(line.trim());
+                end = line_end;
+                lines.next();
+            }
+            Block::Paragraph(paragraph.join("\n"))
+        };
+        blocks.push(ParsedBlock { block, end });
+    }
+
+    blocks
+}
+
+fn starts_block(line: &str) -> bool {
+    fence(line).is_some()
+        || heading(line).is_some()
+        || is_rule(line)
+        || list_item(line).is_some()
+        || line.starts_with('>')
+}
+
+/// Returns the language of an opening code fence.
+fn fence(line: &str) -> Option<&str> {
+    line.strip_prefix("```")
+        .map(|info| info.trim_start_matches('`').trim())
+}
+
+fn heading(line: &str) -> Option<(u8, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    line[level..]
+        .strip_prefix(' ')
+        .map(|heading| (level as u8, heading.trim()))
+}
+
+fn is_rule(line: &str) -> bool {
+    let line = line.trim_end();
+    line.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|c| line.chars().all(|l| l == *c))
+}
+
+/// Returns whether the item is ordered and its text.
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            return Some((false, item));
+        }
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 || digits > 9 {
+        return None;
+    }
+    let rest = &line[digits..];
+    rest.strip_prefix(". ")
+        .or_else(|| rest.strip_prefix(") "))
+        .map(|item| (true, item))
+}
+
+fn strip_quote(line: &str) -> &str {
+    let line = line.trim_start_matches('>');
+    line.strip_prefix(' ').unwrap_or(line)
+}
+
+pub fn parse_inline(text: &str) -> Vec<Inline> {
+    let mut inlines = vec![];
+    let mut plain = String::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let parsed = match c {
+            '`' => delimited(rest, "`").map(|(code, len)| (Inline::Code(code.into()), len)),
+            '*' | '_' if rest[1..].starts_with(c) => delimited(rest, &rest[..2])
+                .map(|(strong, len)| (Inline::Strong(strong.into()), len)),
+            // `_` inside of words, like in snake_case identifiers, isn't emphasis
+            '_' if plain.ends_with(|c: char| c.is_alphanumeric()) => None,
+            '*' | '_' => delimited(rest, &rest[..1])
+                .filter(|(emphasis, _)| !emphasis.starts_with(' '))
+                .map(|(emphasis, len)| (Inline::Emphasis(emphasis.into()), len)),
+            '[' => link(rest),
+            _ => None,
+        };
+        match parsed {
+            Some((inline, len)) => {
+                if !plain.is_empty() {
+                    inlines.push(Inline::Text(std::mem::take(&mut plain)));
+                }
+                inlines.push(inline);
+                rest = &rest[len..];
+            }
+            None => {
+                plain.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if !plain.is_empty() {
+        inlines.push(Inline::Text(plain));
+    }
+
+    inlines
+}
+
+/// Returns the non empty text between `delimiter` at the start of `text` and the next one
+/// together with the length of the whole span.
+fn delimited<'a>(text: &'a str, delimiter: &str) -> Option<(&'a str, usize)> {
+    let inner = &text[delimiter.len()..];
+    let end = inner.find(delimiter).filter(|end| *end > 0)?;
+    Some((&inner[..end], delimiter.len() * 2 + end))
+}
+
+fn link(text: &str) -> Option<(Inline, usize)> {
+    let text_end = text.find("](")?;
+    let url_end = text[text_end..].find(')')? + text_end;
+    let url = &text[text_end + 2..url_end];
+    // only web links, anything else could run scripts
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return None;
+    }
+    Some((
+        Inline::Link {
+            text: text[1..text_end].into(),
+            url: url.into(),
+        },
+        url_end + 1,
+    ))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenKind {
+    Plain,
+    Keyword,
+    String,
+    Number,
+    Comment,
+}
+
+impl TokenKind {
+    pub fn class(self) -> &'static str {
+        match self {
+            TokenKind::Plain => "",
+            TokenKind::Keyword => "hl-keyword",
+            TokenKind::String => "hl-string",
+            TokenKind::Number => "hl-number",
+            TokenKind::Comment => "hl-comment",
+        }
+    }
+}
+
+struct Syntax {
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    keywords: &'static [&'static str],
+}
+
+const C_LIKE_KEYWORDS: &[&str] = &[
+    "auto",
+    "break",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extends",
+    "false",
+    "final",
+    "float",
+    "for",
+    "func",
+    "function",
+    "go",
+    "if",
+    "implements",
+    "import",
+    "int",
+    "interface",
+    "let",
+    "long",
+    "namespace",
+    "new",
+    "null",
+    "nullptr",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "short",
+    "static",
+    "struct",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typedef",
+    "undefined",
+    "var",
+    "void",
+    "while",
+    "async",
+    "await",
+    "export",
+    "from",
+    "type",
+    "val",
+    "fun",
+    "bool",
+    "string",
+];
+
+const SYNTAXES: &[(&[&str], Syntax)] = &[
+    (
+        &["rust", "rs"],
+        Syntax {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"'],
+            keywords: &[
+                "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else",
+                "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match",
+                "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct",
+                "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+            ],
+        },
+    ),
+    (
+        &[
+            "c",
+            "cpp",
+            "c++",
+            "h",
+            "java",
+            "kotlin",
+            "go",
+            "js",
+            "javascript",
+            "ts",
+            "typescript",
+            "cs",
+            "csharp",
+            "swift",
+        ],
+        Syntax {
+            line_comments: &["//"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['"', '\'', '`'],
+            keywords: C_LIKE_KEYWORDS,
+        },
+    ),
+    (
+        &["python", "py"],
+        Syntax {
+            line_comments: &["#"],
+            block_comment: None,
+            quotes: &['"', '\''],
+            keywords: &[
+                "and", "as", "assert", "async", "await", "break", "class", "continue", "def",
+                "del", "elif", "else", "except", "False", "finally", "for", "from", "global", "if",
+                "import", "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise",
+                "return", "True", "try", "while", "with", "yield",
+            ],
+        },
+    ),
+    (
+        &["sh", "bash", "shell", "zsh", "console"],
+        Syntax {
+            line_comments: &["#"],
+            block_comment: None,
+            quotes: &['"', '\''],
+            keywords: &[
+                "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function",
+                "if", "in", "local", "return", "then", "while",
+            ],
+        },
+    ),
+    (
+        &["sql"],
+        Syntax {
+            line_comments: &["--"],
+            block_comment: Some(("/*", "*/")),
+            quotes: &['\''],
+            keywords: &[
+                "SELECT",
+                "FROM",
+                "WHERE",
+                "INSERT",
+                "INTO",
+                "VALUES",
+                "UPDATE",
+                "SET",
+                "DELETE",
+                "CREATE",
+                "TABLE",
+                "DROP",
+                "ALTER",
+                "JOIN",
+                "INNER",
+                "LEFT",
+                "RIGHT",
+                "ON",
+                "AND",
+                "OR",
+                "NOT",
+                "NULL",
+                "ORDER",
+                "BY",
+                "GROUP",
+                "LIMIT",
+                "OFFSET",
+                "AS",
+                "PRIMARY",
+                "KEY",
+                "REFERENCES",
+                "INDEX",
+            ],
+        },
+    ),
+];
+
+/// Splits `code` into tokens to highlight, code of unknown languages is a single plain token.
+pub fn highlight(lang: &str, code: &str) -> Vec<(TokenKind, String)> {
+    let lang = lang.to_lowercase();
+    let Some((_, syntax)) = SYNTAXES
+        .iter()
+        .find(|(names, _)| names.contains(&lang.as_str()))
+    else {
+        return vec![(TokenKind::Plain, code.to_string())];
+    };
+
+    let mut tokens: Vec<(TokenKind, String)> = vec![];
+    let mut push = |kind, text: &str| match tokens.last_mut() {
+        Some((last, token)) if *last == kind => token.push_str(text),
+        _ => tokens.push((kind, text.to_string())),
+    };
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        let (kind, len) = if syntax.line_comments.iter().any(|p| rest.starts_with(p)) {
+            (TokenKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else if let Some((start, end)) = syntax
+            .block_comment
+            .filter(|(start, _)| rest.starts_with(start))
+        {
+            let len = rest[start.len()..]
+                .find(end)
+                .map(|i| start.len() + i + end.len())
+                .unwrap_or(rest.len());
+            (TokenKind::Comment, len)
+        } else if syntax.quotes.contains(&c) {
+            (TokenKind::String, string_len(rest, c))
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(is_ident(c) || c == '.'))
+                .unwrap_or(rest.len());
+            (TokenKind::Number, len)
+        } else if is_ident(c) {
+            let len = rest.find(|c: char| !is_ident(c)).unwrap_or(rest.len());
+            let kind = if syntax.keywords.contains(&&rest[..len]) {
+                TokenKind::Keyword
+            } else {
+                TokenKind::Plain
+            };
+            (kind, len)
+        } else {
+            (TokenKind::Plain, c.len_utf8())
+        };
+        push(kind, &rest[..len]);
+        rest = &rest[len..];
+    }
+
+    tokens
+}
+
+/// Length of the string literal at the start of `text` up to the closing `quote` or the end of
+/// the line.
+fn string_len(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            '\n' => return i,
+            c if c == quote && !escaped => return i + 1,
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    text.len()
+}
@@ -1,9 +1,18 @@
 use crate::{
     api,
-    components::{modal::*, status_message::*},
-    pages, Page, PageStack,
+    components::{list_page_control::*, modal::*, status_message::*, system_prompt_picker::*},
+    i18n::t,
+    pages,
+    preferences::use_preferences,
+    web_util, Page, PageStack,
+};
+use airtifex_core::{
+    llm::{
+        ChatEntryType, ChatListPage, ChatListQuery, ChatOverview, ChatSearchQuery,
+        ChatSearchResult, ChatStartRequest, InferenceSettings,
+    },
+    user::UserSettings,
 };
-use airtifex_core::llm::{ChatListEntry, ChatStartRequest, InferenceSettings};
 
 use leptos::*;
 
@@ -17,6 +26,8 @@ pub fn Chat(
     page_stack: RwSignal<PageStack>,
 ) -> impl IntoView {
     let current_list_page = create_rw_signal::<u32>(cx, 1);
+    let items_per_page = use_preferences(cx).get().items_per_page;
+    let page_size = create_rw_signal(cx, items_per_page.map_or(25, |n| n as usize));
 
     let status_message = create_rw_signal(cx, Message::Empty);
     let remove_chat_id = create_rw_signal(cx, None::<String>);
@@ -26,50 +37,126 @@ pub fn Chat(
     let selected_model = create_rw_signal(cx, String::new());
 
     let num_predict = create_rw_signal(cx, None::<usize>);
+    let system_prompt = create_rw_signal(cx, None::<String>);
     let n_batch = create_rw_signal(cx, None::<usize>);
     let top_k = create_rw_signal(cx, None::<usize>);
     let top_p = create_rw_signal(cx, None::<f32>);
     let repeat_penalty = create_rw_signal(cx, None::<f32>);
     let temp = create_rw_signal(cx, None::<f32>);
+    let user_settings = create_rw_signal(cx, UserSettings::default());
 
     let chats = create_resource(
         cx,
         move || current_list_page.get(),
-        move |_current_list_page| async move {
+        move |current_list_page| async move {
+            let query = ChatListQuery {
+                page: Some(current_list_page),
+                page_size: Some(page_size.get() as u32),
+            };
             match authorized_api.get() {
-                Some(api) => match api.chat_list().await {
+                Some(api) => match api.chat_list(query).await {
                     Ok(chats) => chats,
                     Err(e) => {
-                        let e = e.to_string();
                         pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
                         status_message.update(|msg| *msg = Message::Error(e));
-                        vec![]
+                        ChatListPage::default()
                     }
                 },
                 None => {
                     status_message
                         .update(|msg| *msg = Message::Error("connection to API failed".into()));
-                    vec![]
+                    ChatListPage::default()
                 }
             }
         },
     );
 
+    // prefills the settings that weren't set yet with the defaults of the user
+    let load_settings_action = create_action(cx, move |_| async move {
+        let Some(api) = authorized_api.get() else {
+            return;
+        };
+        match api.user_settings().await {
+            Ok(settings) => {
+                let defaults = settings.chat.clone();
+                num_predict.update(|v| *v = v.or(defaults.num_predict));
+                system_prompt.update(|v| *v = v.take().or(defaults.system_prompt));
+                n_batch.update(|v| *v = v.or(defaults.n_batch));
+                top_k.update(|v| *v = v.or(defaults.top_k));
+                top_p.update(|v| *v = v.or(defaults.top_p));
+                repeat_penalty.update(|v| *v = v.or(defaults.repeat_penalty));
+                temp.update(|v| *v = v.or(defaults.temp));
+                user_settings.update(|s| *s = settings);
+            }
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                let e = e.to_string();
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to load default settings - {e}"));
+                });
+            }
+        }
+    });
+    load_settings_action.dispatch(());
+
+    let save_settings_action = create_action(cx, move |_| async move {
+        let Some(api) = authorized_api.get() else {
+            status_message.update(|m| {
+                *m = Message::Error("failed to connect to API".into());
+            });
+            return;
+        };
+        let mut settings = user_settings.get();
+        settings.chat = InferenceSettings {
+            num_predict: num_predict.get(),
+            system_prompt: system_prompt.get(),
+            n_batch: n_batch.get(),
+            top_k: top_k.get(),
+            top_p: top_p.get(),
+            repeat_penalty: repeat_penalty.get(),
+            temp: temp.get(),
+            // not editable in the form
+            ..settings.chat
+        };
+        match api.user_settings_update(&settings).await {
+            Ok(_) => {
+                user_settings.update(|s| *s = settings);
+                status_message.update(|m| {
+                    *m = Message::Success("saved the current settings as defaults".into());
+                });
+            }
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                let e = e.to_string();
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to save default settings - {e}"));
+                });
+            }
+        }
+    });
+
     let remove_chat_action = create_action(cx, move |_| async move {
         if let Some(api) = authorized_api.get() {
             if let (Some(title), Some(id)) = (remove_chat_title.get(), remove_chat_id.get()) {
-                if let Err(e) = api.chat_remove(&id).await {
-                    let e = e.to_string();
-                    pages::goto_login_if_expired(cx, &e, authorized_api);
-                    status_message.update(|m| {
-                        *m = Message::Error(format!("failed to remove chat - {e}"));
-                    });
-                } else {
-                    status_message.update(|m| {
-                        *m = Message::Success(format!("successfully removed chat \"{title}\""));
-                    });
-                    current_list_page.update(|p| *p += 1);
-                    current_list_page.update(|p| *p -= 1);
+                match api.chat_remove(&id).await {
+                    Ok(response) => {
+                        let n = response.deleted_entries;
+                        status_message.update(|m| {
+                            *m = Message::Success(format!(
+                                "successfully removed chat \"{title}\" with {n} messages"
+                            ));
+                        });
+                        current_list_page.update(|p| *p += 1);
+                        current_list_page.update(|p| *p -= 1);
+                    }
+                    Err(e) => {
+                        pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
+                        status_message.update(|m| {
+                            *m = Message::Error(format!("failed to remove chat - {e}"));
+                        });
+                    }
                 }
             }
         } else {
@@ -88,12 +175,15 @@ pub fn Chat(
                 model: Some(selected_model.get()),
                 settings: InferenceSettings {
                     num_predict: num_predict.get(),
-                    system_prompt: None,
+                    system_prompt: system_prompt.get(),
                     n_batch: n_batch.get(),
                     top_k: top_k.get(),
                     top_p: top_p.get(),
                     repeat_penalty: repeat_penalty.get(),
                     temp: temp.get(),
+                    mirostat: None,
+                    mirostat_tau: None,
+                    mirostat_eta: None,
                 },
             };
             match api.chat_start_new(request).await {
@@ -135,15 +225,23 @@ pub fn Chat(
         view!{cx,
            <main class="bg-dark text-white d-flex flex-column p-1 pt-3 overflow-auto" >
                  <div class="d-flex pb-3">
-                     <h1 class="display-5 p-1">{Page::Chat.title()}</h1>
+                     <h1 class="display-5 p-1">{move || t(cx, Page::Chat.title_id())}</h1>
                  </div>
                  <NewChatForm
                      authorized_api selected_model status_message chat_title dispatch_new_chat_action
-                     num_predict n_batch top_k top_p repeat_penalty temp
+                     num_predict system_prompt n_batch top_k top_p repeat_penalty temp
+                     user_settings save_settings_action
                  />
+                 <ChatSearch authorized_api status_message />
                  <div class="card bg-darker m-3">
                     <StatusMessage message=status_message />
-                    <ChatListEntries chats remove_chat_id=remove_chat_id remove_chat_title=remove_chat_title />
+                    <ChatListEntries
+                      chats
+                      current_list_page
+                      page_size=page_size.read_only()
+                      remove_chat_id=remove_chat_id
+                      remove_chat_title=remove_chat_title
+                    />
                  </div>
            </main>
            {remove_confirm_modal}
@@ -160,11 +258,14 @@ fn NewChatForm<F>(
     selected_model: RwSignal<String>,
     chat_title: RwSignal<String>,
     num_predict: RwSignal<Option<usize>>,
+    system_prompt: RwSignal<Option<String>>,
     n_batch: RwSignal<Option<usize>>,
     top_k: RwSignal<Option<usize>>,
     top_p: RwSignal<Option<f32>>,
     repeat_penalty: RwSignal<Option<f32>>,
     temp: RwSignal<Option<f32>>,
+    user_settings: RwSignal<UserSettings>,
+    save_settings_action: Action<(), ()>,
     dispatch_new_chat_action: F,
 ) -> impl IntoView
 where
@@ -173,6 +274,7 @@ where
     let current_list_page = create_rw_signal::<u32>(cx, 1);
 
     let is_advanced_settings_open = create_rw_signal(cx, false);
+    let library_version = create_rw_signal::<u32>(cx, 0);
 
     let settings_icon = Signal::derive(cx, move || {
         if is_advanced_settings_open.get() {
@@ -274,11 +376,34 @@ where
                       if is_advanced_settings_open.get() {
                           view!{ cx,
                           <div>
+                              <div class="input-group mb-2">
+                                 <label class="input-group-text">"Persona"</label>
+                                 <SystemPromptPicker
+                                   authorized_api status_message library_version
+                                   on_pick=move |content: String| system_prompt.update(|v| *v = Some(content))
+                                 />
+                              </div>
+                              <div class="input-group mb-3">
+                                 <label class="input-group-text">"System prompt"</label>
+                                 <textarea
+                                   class = "form-control"
+                                   rows="3"
+                                   placeholder = "Your name is Assistant and you are a helpful virtual assistant..."
+                                   prop:value = move || system_prompt.get().unwrap_or_default()
+                                   on:keyup = move |ev: ev::KeyboardEvent| {
+                                     let val = event_target_value(&ev);
+                                     system_prompt.update(|v| *v = if val.is_empty() { None } else { Some(val) });
+                                   }
+                                 >
+                                 </textarea>
+                              </div>
+
                               <div class="input-group mb-3">
                                  <label class="input-group-text">"Max new tokens"</label>
                                  <input
                                    class = "form-control"
                                    placeholder = "1024"
+                                   prop:value = move || user_settings.with(|s| s.chat.num_predict.map(|v| v.to_string()).unwrap_or_default())
                                    on:keyup = move |ev: ev::KeyboardEvent| {
                                      match &*ev.key() {
                                          "Enter" => {
@@ -298,6 +423,7 @@ where
                                  <input
                                    class = "form-control"
                                    placeholder = "8"
+                                   prop:value = move || user_settings.with(|s| s.chat.n_batch.map(|v| v.to_string()).unwrap_or_default())
                                    on:keyup = move |ev: ev::KeyboardEvent| {
                                      match &*ev.key() {
                                          "Enter" => {
@@ -318,6 +444,7 @@ where
                                    <input
                                      class = "form-control"
                                      placeholder = "40"
+                                     prop:value = move || user_settings.with(|s| s.chat.top_k.map(|v| v.to_string()).unwrap_or_default())
                                      on:keyup = move |ev: ev::KeyboardEvent| {
                                        match &*ev.key() {
                                            "Enter" => {
@@ -336,6 +463,7 @@ where
                                    <input
                                      class = "form-control"
                                      placeholder = "0.95"
+                                     prop:value = move || user_settings.with(|s| s.chat.top_p.map(|v| v.to_string()).unwrap_or_default())
                                      on:keyup = move |ev: ev::KeyboardEvent| {
                                        match &*ev.key() {
                                            "Enter" => {
@@ -356,6 +484,7 @@ where
                                  <input
                                    class = "form-control"
                                    placeholder = "1.30"
+                                   prop:value = move || user_settings.with(|s| s.chat.repeat_penalty.map(|v| v.to_string()).unwrap_or_default())
                                    on:keyup = move |ev: ev::KeyboardEvent| {
                                      match &*ev.key() {
                                          "Enter" => {
@@ -375,6 +504,7 @@ where
                                  <input
                                    class = "form-control"
                                    placeholder = "0.80"
+                                   prop:value = move || user_settings.with(|s| s.chat.temp.map(|v| v.to_string()).unwrap_or_default())
                                    on:keyup = move |ev: ev::KeyboardEvent| {
                                      match &*ev.key() {
                                          "Enter" => {
@@ -389,6 +519,13 @@ where
                                  />
                               </div>
 
+                              <button
+                                 class="btn btn-outline-lighter rounded mb-3"
+                                 on:click=move |_| save_settings_action.dispatch(())
+                              >
+                              "Save as defaults"
+                              </button>
+
                           </div>
                           }.into_view(cx)
                       } else {
@@ -414,16 +551,131 @@ where
     .into_view(cx)
 }
 
+#[component]
+fn ChatSearch(
+    cx: Scope,
+    authorized_api: RwSignal<Option<api::AuthorizedApi>>,
+    status_message: RwSignal<Message>,
+) -> impl IntoView {
+    let search_query = create_rw_signal(cx, String::new());
+    let search_results = create_rw_signal(cx, None::<Vec<ChatSearchResult>>);
+
+    let search_action = create_action(cx, move |q: &String| {
+        let query = ChatSearchQuery { q: q.clone() };
+        async move {
+            if query.q.trim().is_empty() {
+                search_results.update(|r| *r = None);
+                return;
+            }
+            if let Some(api) = authorized_api.get() {
+                match api.chat_search(query).await {
+                    Ok(results) => search_results.update(|r| *r = Some(results)),
+                    Err(e) => {
+                        pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
+                        status_message.update(|m| {
+                            *m = Message::Error(format!("failed to search chats - {e}"));
+                        });
+                    }
+                }
+            } else {
+                status_message.update(|m| {
+                    *m = Message::Error("failed to connect to API".into());
+                });
+            }
+        }
+    });
+
+    view! { cx,
+        <div class="card bg-darker m-3">
+          <div class="card-body d-flex flex-column px-5">
+            <form
+              on:submit=|ev|ev.prevent_default()
+              class="row text-start"
+            >
+              <div class="input-group">
+                 <label class="input-group-text">"Search"</label>
+                 <input
+                   class = "form-control"
+                   placeholder = "Search previous conversations..."
+                   on:keyup = move |ev: ev::KeyboardEvent| {
+                     let val = event_target_value(&ev);
+                     search_query.update(|v|*v = val);
+                     if &*ev.key() == "Enter" {
+                        search_action.dispatch(search_query.get());
+                     }
+                   }
+                 />
+                 <button
+                   class="btn btn-outline-lighter"
+                   on:click=move |_| search_action.dispatch(search_query.get())
+                 >
+                 "Search"
+                 </button>
+              </div>
+            </form>
+            {move || match search_results.get() {
+                Some(results) if results.is_empty() => view! { cx,
+                    <p class="text-secondary mt-3 mb-0">"No matching messages found"</p>
+                }.into_view(cx),
+                Some(results) => view! { cx,
+                  <table class="table table-hover table-responsive text-white mt-3 mb-0">
+                    <tbody>
+                    {
+                      results.into_iter().map(|result| {
+                          let href = format!("/chat/{}", result.chat_id);
+                          let prefix = match result.entry_type {
+                              ChatEntryType::User => "User: ",
+                              ChatEntryType::Bot => "Chat: ",
+                          };
+                          view!{ cx,
+                            <tr
+                              class="text-white no-border align-middle"
+                              style="cursor: pointer;"
+                              on:click=move |_| pages::goto(cx, &href).expect("chat page")
+                            >
+                              <td class="fitwidth text-airtifex-light">{result.chat_title}</td>
+                              <td><strong>{prefix}</strong>{result.snippet}</td>
+                              <td align="center" class="text-secondary">{result.entry_date.format("%a, %d %b %Y %H:%M:%S").to_string()}</td>
+                            </tr>
+                          }.into_view(cx)
+                      }).collect::<Vec<_>>()
+                    }
+                    </tbody>
+                  </table>
+                }.into_view(cx),
+                None => view! { cx, <></> }.into_view(cx),
+            }}
+          </div>
+        </div>
+    }
+    .into_view(cx)
+}
+
 #[component]
 fn ChatListEntries(
     cx: Scope,
-    chats: Resource<u32, Vec<ChatListEntry>>,
+    chats: Resource<u32, ChatListPage>,
+    current_list_page: RwSignal<u32>,
+    page_size: ReadSignal<usize>,
     remove_chat_id: RwSignal<Option<String>>,
     remove_chat_title: RwSignal<Option<String>>,
 ) -> impl IntoView {
+    let elem_count = Signal::derive(cx, move || {
+        chats
+            .read(cx)
+            .map(|page| page.chats.len())
+            .unwrap_or_default()
+    });
+    let total = Signal::derive(cx, move || {
+        chats
+            .read(cx)
+            .map(|page| page.total as usize)
+            .unwrap_or_default()
+    });
     view! { cx, { move || {
-        if let Some(chats) = chats.read(cx) {
-            if !chats.is_empty() {
+        if let Some(page) = chats.read(cx) {
+            if !page.chats.is_empty() {
                 return view! { cx,
                 <div class="card-body d-flex flex-column px-5 pb-5">
                   <table class="table table-hover table-striped table-responsive text-white">
@@ -431,18 +683,20 @@ fn ChatListEntries(
                     <tr>
                       <th scope="col">"Previous conversations"</th>
                       <th class="text-center" scope="col">"Model"</th>
-                      <th class="text-center" scope="col">"Start date"</th>
+                      <th class="text-center" scope="col">"Messages"</th>
+                      <th class="text-center" scope="col">"Last activity"</th>
                       <th scope="col"></th>
                     </tr>
                     </thead>
                     <tbody>
                    {
-                      chats.into_iter().map(|chat| {
+                      page.chats.into_iter().map(|chat| {
                           view!{cx, <ChatListEntry chat remove_chat_id remove_chat_title />}.into_view(cx)
                       }).collect::<Vec<_>>()
                    }
                     </tbody>
                   </table>
+                  <ListPageControl current_list_page elem_count page_size total />
                 </div>
                 }.into_view(cx)
             }
@@ -455,12 +709,26 @@ fn ChatListEntries(
 #[component]
 fn ChatListEntry(
     cx: Scope,
-    chat: ChatListEntry,
+    chat: ChatOverview,
     remove_chat_id: RwSignal<Option<String>>,
     remove_chat_title: RwSignal<Option<String>>,
 ) -> impl IntoView {
     let edit_href = format!("/chat/{}", chat.id);
     let edit_href2 = edit_href.clone();
+    let author = match chat.last_entry_type {
+        Some(ChatEntryType::Bot) => "Chat: ",
+        Some(ChatEntryType::User) => "User: ",
+        None => "",
+    };
+    let preview = chat
+        .last_message
+        .clone()
+        .map(|message| format!("{author}{message}"))
+        .unwrap_or_else(|| "No messages yet".into());
+    let title_class = if chat.unread { "fw-bold" } else { "" };
+    let unread_badge = chat.unread.then(|| {
+        view! { cx, <span class="badge rounded-pill bg-airtifex ms-2">"new"</span> }
+    });
     view! {cx, <tr
                 class="text-white no-border align-middle"
               >
@@ -470,10 +738,18 @@ fn ChatListEntry(
                         pages::goto(cx, &edit_href2).expect("chat page");
                     }
                   >
-                    {chat.title.clone()}
+                    <div class=title_class>{chat.title.clone()}{unread_badge}</div>
+                    <div class="text-secondary small">{preview}</div>
                   </td>
                   <td align="center" class="text-airtifex-light">{chat.model}</td>
-                  <td align="center" class="text-secondary">{chat.start_date.format("%a, %d %b %Y %H:%M:%S").to_string()}</td>
+                  <td align="center" class="text-secondary">{chat.message_count}</td>
+                  <td
+                    align="center"
+                    class="text-secondary"
+                    title=chat.last_updated.format("%a, %d %b %Y %H:%M:%S").to_string()
+                  >
+                    {web_util::relative_time(chat.last_updated.timestamp_millis())}
+                  </td>
                   <td align="right">
                       <div class="btn-group" role="chat toolbar" aria-label="chat toolbar">
                           <button
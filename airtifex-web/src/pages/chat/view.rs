@@ -1,15 +1,22 @@
 use crate::{
     api,
-    components::{loading::*, status_message::*, titled_child_page::*},
-    inference::read_inference_stream,
+    components::{
+        loading::*, markdown::*, modal::*, status_message::*, system_prompt_picker::*,
+        titled_child_page::*,
+    },
+    inference::read_chat_event_stream,
     pages, web_util, Page, PageStack,
 };
-use airtifex_core::llm::ChatResponseRequest;
+use airtifex_core::llm::{
+    ChatContextTurnsUpdateRequest, ChatEntryEditRequest, ChatEntryParams, ChatEntryType,
+    ChatForkQuery, ChatResponseRequest, ChatShareRequest, ChatSystemPromptUpdateRequest,
+    StreamGranularity, SystemPromptRequest,
+};
 
 use leptos::*;
 use leptos_router::*;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Entry {
     User,
     Chat,
@@ -34,13 +41,23 @@ pub fn ChatView(
     let prompt = create_rw_signal(cx, String::new());
     let responses = create_rw_signal(cx, vec![]);
     let last_response = create_rw_signal(cx, (Entry::None, String::new()));
+    // parameters of the answers in the history, by their position
+    let entry_params = create_rw_signal::<Vec<Option<ChatEntryParams>>>(cx, vec![]);
     let infered_response = create_rw_signal(cx, String::new());
+    let queue_status = create_rw_signal(cx, None);
     let status_message = create_rw_signal(cx, Message::Empty);
 
     let is_inference_running = create_rw_signal(cx, false);
     let should_cancel = create_rw_signal(cx, false);
 
     let is_details_open = create_rw_signal(cx, false);
+    let system_prompt = create_rw_signal(cx, String::new());
+    let library_version = create_rw_signal::<u32>(cx, 0);
+    let library_name = create_rw_signal(cx, String::new());
+    let context_turns = create_rw_signal(cx, String::new());
+    let remove_entry_index = create_rw_signal::<Option<usize>>(cx, None);
+    let remove_entry_preview = create_rw_signal::<Option<String>>(cx, None);
+    let share_system_prompt = create_rw_signal(cx, false);
 
     let chat_id = Signal::derive(cx, move || params.get().ok().and_then(|p| p.chat_id));
 
@@ -52,8 +69,8 @@ pub fn ChatView(
                 (Some(api), Some(id)) => match api.chat(&id).await {
                     Ok(chat) => Some(chat),
                     Err(e) => {
-                        let e = e.to_string();
                         pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
                         status_message.update(|msg| *msg = Message::Error(e));
                         None
                     }
@@ -78,9 +95,178 @@ pub fn ChatView(
             .unwrap_or("".into())
     });
 
+    create_effect(cx, move |_| {
+        if let Some(Some(chat)) = chat.read(cx) {
+            system_prompt.update(|p| *p = chat.settings.system_prompt.unwrap_or_default());
+            context_turns.update(|t| {
+                *t = chat
+                    .context_turns
+                    .map(|n| n.to_string())
+                    .unwrap_or_default()
+            });
+        }
+    });
+
+    let system_prompt_update_action = create_action(cx, move |p: &String| {
+        let p = p.clone();
+        let request = ChatSystemPromptUpdateRequest {
+            system_prompt: if p.is_empty() { None } else { Some(p) },
+        };
+        async move {
+            match (authorized_api.get(), chat_id.get()) {
+                (Some(api), Some(id)) => {
+                    if let Err(e) = api.chat_update_system_prompt(&id, request).await {
+                        pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
+                        status_message.update(|m| {
+                            *m = Message::Error(format!("failed to update system prompt - {e}"));
+                        });
+                    } else {
+                        status_message.update(|m| {
+                            *m = Message::Success(
+                                "system prompt updated, it will be used for the next responses"
+                                    .into(),
+                            );
+                        });
+                        dummy_chat_signal.update(|s| *s += 1);
+                    }
+                }
+                _ => {
+                    status_message.update(|m| {
+                        *m = Message::Error("failed to connect to API".into());
+                    });
+                }
+            }
+        }
+    });
+
+    let save_to_library_action = create_action(cx, move |_| {
+        let request = SystemPromptRequest {
+            name: library_name.get(),
+            content: system_prompt.get(),
+            shared: false,
+        };
+        async move {
+            let Some(api) = authorized_api.get() else {
+                status_message.update(|m| {
+                    *m = Message::Error("failed to connect to API".into());
+                });
+                return;
+            };
+            match api.system_prompt_create(request).await {
+                Ok(saved) => {
+                    status_message.update(|m| {
+                        *m = Message::Success(format!(
+                            "saved the system prompt to the library as \"{}\"",
+                            saved.name
+                        ));
+                    });
+                    library_name.update(|n| n.clear());
+                    library_version.update(|v| *v += 1);
+                }
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to save the system prompt - {e}"));
+                    });
+                }
+            }
+        }
+    });
+
+    let share_action = create_action(cx, move |include_system_prompt: &bool| {
+        let request = ChatShareRequest {
+            include_system_prompt: *include_system_prompt,
+        };
+        async move {
+            let (Some(api), Some(id)) = (authorized_api.get(), chat_id.get()) else {
+                return;
+            };
+            match api.chat_share(&id, request).await {
+                Ok(share) => {
+                    let copied = web_util::copy_to_clipboard(&share.url).await.is_ok();
+                    let expires = share.expires.format("%a, %d %b %Y %H:%M:%S");
+                    status_message.update(|m| {
+                        *m = Message::Success(if copied {
+                            format!("Link copied to the clipboard, it is valid until {expires}")
+                        } else {
+                            format!("Share link valid until {expires}: {}", share.url)
+                        });
+                    });
+                }
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to share chat - {e}"));
+                    });
+                }
+            }
+        }
+    });
+
+    let revoke_shares_action = create_action(cx, move |_: &()| async move {
+        let (Some(api), Some(id)) = (authorized_api.get(), chat_id.get()) else {
+            return;
+        };
+        match api.chat_revoke_shares(&id).await {
+            Ok(()) => status_message.update(|m| {
+                *m = Message::Success("All share links of the chat were revoked".into());
+            }),
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                let e = e.to_string();
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to revoke share links - {e}"));
+                });
+            }
+        }
+    });
+
+    let context_turns_update_action = create_action(cx, move |turns: &String| {
+        let turns = turns.trim().to_string();
+        async move {
+            let context_turns = if turns.is_empty() {
+                None
+            } else if let Ok(turns) = turns.parse() {
+                Some(turns)
+            } else {
+                status_message.update(|m| {
+                    *m = Message::Error("the number of turns has to be a positive number".into());
+                });
+                return;
+            };
+            let request = ChatContextTurnsUpdateRequest { context_turns };
+            match (authorized_api.get(), chat_id.get()) {
+                (Some(api), Some(id)) => {
+                    if let Err(e) = api.chat_update_context_turns(&id, request).await {
+                        pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
+                        status_message.update(|m| {
+                            *m = Message::Error(format!("failed to update context turns - {e}"));
+                        });
+                    } else {
+                        status_message.update(|m| {
+                            *m = Message::Success(
+                                "context turns updated, they will be used for the next responses"
+                                    .into(),
+                            );
+                        });
+                        dummy_chat_signal.update(|s| *s += 1);
+                    }
+                }
+                _ => {
+                    status_message.update(|m| {
+                        *m = Message::Error("failed to connect to API".into());
+                    });
+                }
+            }
+        }
+    });
+
     let history = create_resource(
         cx,
-        move || current_list_page.get(),
+        move || (current_list_page.get(), dummy_chat_signal.get()),
         move |_current_list_page| async move {
             match (authorized_api.get(), chat_id.get()) {
                 (Some(api), Some(id)) => match api.chat_history(&id).await {
@@ -89,8 +275,8 @@ pub fn ChatView(
                         chats
                     }
                     Err(e) => {
-                        let e = e.to_string();
                         pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
                         status_message.update(|msg| *msg = Message::Error(e));
                         vec![]
                     }
@@ -106,13 +292,14 @@ pub fn ChatView(
 
     create_effect(cx, move |_| {
         if let Some(history) = history.read(cx) {
+            entry_params.update(|p| *p = history.iter().map(|e| e.params.clone()).collect());
             responses.update(|rsp| {
                 *rsp = history
                     .into_iter()
                     .map(|entry| {
                         let ty = match entry.entry_type {
-                            airtifex_core::llm::ChatEntryType::Bot => Entry::Chat,
-                            airtifex_core::llm::ChatEntryType::User => Entry::User,
+                            ChatEntryType::Bot => Entry::Chat,
+                            ChatEntryType::User => Entry::User,
                         };
                         (ty, entry.content)
                     })
@@ -127,9 +314,18 @@ pub fn ChatView(
         }
     });
 
+    // the streamed response is rendered again only when its entry changes, its text is
+    // rendered incrementally by the message
+    let last_entry = create_memo(cx, move |_| last_response.with(|(entry, _)| *entry));
+    let last_text = Signal::derive(cx, move || last_response.with(|(_, rsp)| rsp.clone()));
+
     let prompt_submit_action = create_action(cx, move |p: &String| {
         let p = p.clone();
-        let request = ChatResponseRequest { prompt: p };
+        let request = ChatResponseRequest {
+            prompt: p,
+            stream_granularity: StreamGranularity::Word,
+            ..Default::default()
+        };
         async move {
             let id = if let Some(id) = chat_id.get() {
                 id
@@ -155,11 +351,13 @@ pub fn ChatView(
                     rsp.push((Entry::User, request.prompt.clone()));
                 });
                 let resp = api.chat_get_response(request, &id).await;
-                read_inference_stream(
+                read_chat_event_stream(
                     cx,
+                    &id,
                     resp,
                     authorized_api,
                     infered_response,
+                    queue_status,
                     status_message,
                     should_cancel,
                 )
@@ -179,6 +377,187 @@ pub fn ChatView(
         }
     });
 
+    // streamed messages only get an id once they are saved, so the message to branch from is
+    // looked up by its position in the history
+    let branch_action = create_action(cx, move |index: &usize| {
+        let index = *index;
+        async move {
+            let (Some(api), Some(id)) = (authorized_api.get(), chat_id.get()) else {
+                status_message.update(|m| {
+                    *m = Message::Error("failed to connect to API".into());
+                });
+                return;
+            };
+            let result = match api.chat_history(&id).await {
+                Ok(history) => match history.get(index) {
+                    Some(entry) => {
+                        api.chat_fork(
+                            &id,
+                            ChatForkQuery {
+                                at: entry.id.clone(),
+                            },
+                        )
+                        .await
+                    }
+                    None => Err(api::Error::ApiError(
+                        "the message isn't saved yet, try again in a moment".into(),
+                    )),
+                },
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(response) => {
+                    if is_inference_running.get() {
+                        should_cancel.update(|c| *c = true);
+                    }
+                    let page = Page::ChatView(response.chat_id);
+                    pages::goto(cx, page.path()).expect("chat page");
+                    // the route stays the same so the resources have to be reloaded
+                    dummy_chat_signal.update(|s| *s += 1);
+                }
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to branch the chat - {e}"));
+                    });
+                }
+            }
+        }
+    });
+
+    // like branching the edited prompt is looked up by its position in the history, the messages
+    // after it are replaced by the new answer
+    let edit_action = create_action(cx, move |(index, content): &(usize, String)| {
+        let (index, content) = (*index, content.clone());
+        async move {
+            let (Some(api), Some(id)) = (authorized_api.get(), chat_id.get()) else {
+                status_message.update(|m| {
+                    *m = Message::Error("failed to connect to API".into());
+                });
+                return;
+            };
+            let entry_id = match api.chat_history(&id).await {
+                Ok(history) => match history.get(index) {
+                    Some(entry) if entry.entry_type == ChatEntryType::User => Ok(entry.id.clone()),
+                    Some(_) => Err(api::Error::ApiError(
+                        "only your own messages can be edited".into(),
+                    )),
+                    None => Err(api::Error::ApiError(
+                        "the message isn't saved yet, try again in a moment".into(),
+                    )),
+                },
+                Err(e) => Err(e),
+            };
+            let entry_id = match entry_id {
+                Ok(entry_id) => entry_id,
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to edit the message - {e}"));
+                    });
+                    return;
+                }
+            };
+
+            if is_inference_running.get() && !should_cancel.get() {
+                should_cancel.update(|c| *c = true);
+                // wait for any other job to cancel
+                let _ = web_util::sleep(100).await;
+            }
+            is_inference_running.update(|r| *r = true);
+            status_message.update(|s| *s = Message::Empty);
+            last_response.update(|(e, rsp)| {
+                *e = Entry::Chat;
+                rsp.clear();
+            });
+            responses.update(|rsp| {
+                rsp.truncate(index);
+                rsp.push((Entry::User, content.clone()));
+            });
+            entry_params.update(|p| p.truncate(index));
+            let request = ChatEntryEditRequest {
+                content,
+                stream_granularity: StreamGranularity::Word,
+            };
+            let resp = api.chat_edit_entry(&id, &entry_id, request).await;
+            let is_rejected = resp.as_ref().map(|r| !r.ok()).unwrap_or(true);
+            read_chat_event_stream(
+                cx,
+                &id,
+                resp,
+                authorized_api,
+                infered_response,
+                queue_status,
+                status_message,
+                should_cancel,
+            )
+            .await;
+            if is_rejected {
+                // nothing was changed, show the saved history again
+                dummy_chat_signal.update(|s| *s += 1);
+            } else {
+                responses.update(|rsp| rsp.push(last_response.get()));
+            }
+            last_response.update(|(e, rsp)| {
+                *e = Entry::None;
+                rsp.clear()
+            });
+
+            is_inference_running.update(|r| *r = false);
+        }
+    });
+
+    // like branching the removed message is looked up by its position in the history, its prompt
+    // or answer is removed together with it
+    let remove_entry_action = create_action(cx, move |_: &()| async move {
+        let Some(index) = remove_entry_index.get() else {
+            return;
+        };
+        let (Some(api), Some(id)) = (authorized_api.get(), chat_id.get()) else {
+            status_message.update(|m| {
+                *m = Message::Error("failed to connect to API".into());
+            });
+            return;
+        };
+        let result = match api.chat_history(&id).await {
+            Ok(history) => match history.get(index) {
+                Some(entry) => api.chat_remove_entry(&id, &entry.id).await,
+                None => Err(api::Error::ApiError(
+                    "the message isn't saved yet, try again in a moment".into(),
+                )),
+            },
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(response) => {
+                let n = response.deleted_entries;
+                status_message.update(|m| {
+                    *m = Message::Success(format!("successfully removed {n} messages"));
+                });
+                dummy_chat_signal.update(|s| *s += 1);
+            }
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to remove the message - {e}"));
+                });
+            }
+        }
+    });
+    let dispatch_remove_entry_action = move || remove_entry_action.dispatch(());
+
+    let remove_confirm_modal = move || {
+        view! { cx,
+          <RemoveModal
+            modal_id="removeChatEntryModal"
+            target="message"
+            entry=remove_entry_preview.read_only()
+            remove_action_fn=dispatch_remove_entry_action
+          />
+        }
+        .into_view(cx)
+    };
+
     let dispatch_prompt_submit = move || {
         prompt_submit_action.dispatch(prompt.get());
         prompt.update(|v| *v = "".into())
@@ -236,6 +615,111 @@ pub fn ChatView(
                             </tbody>
                         </table>
                     </div>
+                    <div class="card-body pt-0">
+                        <form
+                          on:submit=|ev|ev.prevent_default()
+                          class="text-start"
+                        >
+                            <div class="input-group">
+                                <label class="input-group-text">"System prompt"</label>
+                                <textarea
+                                  class = "form-control"
+                                  rows="3"
+                                  placeholder = "Default system prompt"
+                                  prop:value=move || system_prompt.get()
+                                  on:input = move |ev| {
+                                    let val = event_target_value(&ev);
+                                    system_prompt.update(|v|*v = val);
+                                  }
+                                >
+                                </textarea>
+                                <button
+                                    class="btn btn-outline-lighter"
+                                    on:click=move |_| system_prompt_update_action.dispatch(system_prompt.get())
+                                >
+                                "Save"
+                                </button>
+                            </div>
+                        </form>
+                        <form
+                          on:submit=|ev|ev.prevent_default()
+                          class="text-start mt-2"
+                        >
+                            <div class="input-group">
+                                <label class="input-group-text">"Library"</label>
+                                <SystemPromptPicker
+                                  authorized_api status_message library_version
+                                  on_pick=move |content: String| system_prompt.update(|v| *v = content)
+                                />
+                                <input
+                                  class = "form-control"
+                                  placeholder = "Name"
+                                  prop:value=move || library_name.get()
+                                  on:input = move |ev| {
+                                    let val = event_target_value(&ev);
+                                    library_name.update(|v|*v = val);
+                                  }
+                                />
+                                <button
+                                    class="btn btn-outline-lighter"
+                                    on:click=move |_| save_to_library_action.dispatch(())
+                                >
+                                "Save to library"
+                                </button>
+                            </div>
+                        </form>
+                        <form
+                          on:submit=|ev|ev.prevent_default()
+                          class="text-start mt-2"
+                        >
+                            <div class="input-group">
+                                <label class="input-group-text">"Context turns"</label>
+                                <input
+                                  class = "form-control"
+                                  type="number"
+                                  min="0"
+                                  placeholder = "Whole conversation"
+                                  prop:value=move || context_turns.get()
+                                  on:input = move |ev| {
+                                    let val = event_target_value(&ev);
+                                    context_turns.update(|v|*v = val);
+                                  }
+                                />
+                                <button
+                                    class="btn btn-outline-lighter"
+                                    on:click=move |_| context_turns_update_action.dispatch(context_turns.get())
+                                >
+                                "Save"
+                                </button>
+                            </div>
+                        </form>
+                        <div class="d-flex flex-row align-items-center mt-2">
+                            <div class="form-check me-auto">
+                                <input
+                                  class="form-check-input"
+                                  type="checkbox"
+                                  id="share-system-prompt"
+                                  prop:checked=move || share_system_prompt.get()
+                                  on:change=move |_| share_system_prompt.update(|s| *s = !*s)
+                                />
+                                <label class="form-check-label" for="share-system-prompt">"Include the system prompt in share links"</label>
+                            </div>
+                            <button
+                                class="btn btn-outline-lighter me-1"
+                                title="Copy a link to a read-only transcript of this chat"
+                                on:click=move |_| share_action.dispatch(share_system_prompt.get())
+                            >
+                            "Share"
+                            </button>
+                            <button
+                                class="btn btn-outline-lighter"
+                                title="Invalidate all share links of this chat"
+                                on:click=move |_| revoke_shares_action.dispatch(())
+                            >
+                            "Revoke links"
+                            </button>
+                        </div>
+                    </div>
                  </div>
                  }.into_view(cx)
              } else {
@@ -263,15 +747,31 @@ pub fn ChatView(
                  <div class="px-5 py-2">
                    <div class="w-100 h-100">
                        { move || {
-                           responses.get().iter().chain([last_response.get()].iter()).map(|(entry, rsp)| {
-                               let (class, prefix) = match entry {
-                                   Entry::User => ("fs-5","User: "),
-                                   Entry::Chat => ("text-airtifex-light fs-5", "Chat: "),
-                                   Entry::None => ("fs-5", ""),
-                               };
-                               view!{cx, <p><strong class=class>{prefix}</strong><pre class="fs-6 ms-3">{rsp}</pre></p>
-                               }}.into_view(cx)).collect::<Vec<_>>()
+                           responses.get().into_iter().enumerate().map(|(index, (entry, rsp))| view!{cx,
+                               <ChatMessage
+                                   entry=entry
+                                   text=Signal::derive(cx, move || rsp.clone())
+                                   params=entry_params.with(|p| p.get(index).cloned().flatten())
+                                   index=index
+                                   branch_action=branch_action
+                                   edit_action=edit_action
+                                   remove_entry_index=remove_entry_index
+                                   remove_entry_preview=remove_entry_preview
+                               />
+                           }).collect::<Vec<_>>()
                        }}
+                       { move || view!{cx,
+                           <ChatMessage
+                               entry=last_entry.get()
+                               text=last_text
+                               params=None
+                               branch_action=branch_action
+                               edit_action=edit_action
+                               remove_entry_index=remove_entry_index
+                               remove_entry_preview=remove_entry_preview
+                           />
+                       }}
+                       <QueuePosition status=queue_status.read_only() />
                        <Dots is_loading=is_inference_running.read_only() />
                        <p style="height: 12rem"></p>
                    </div>
@@ -328,7 +828,167 @@ pub fn ChatView(
                  </div>
              </div>
            </main>
+           {remove_confirm_modal}
         }.into_view(cx)
      }}
     }
 }
+
+/// Message of the chat, `index` is its position in the history. Messages without it, like the
+/// one that is being streamed, can't be branched from, edited or removed. Only messages of the
+/// user are editable, `params` of an answer are shown in its info.
+#[component]
+fn ChatMessage(
+    cx: Scope,
+    entry: Entry,
+    text: Signal<String>,
+    params: Option<ChatEntryParams>,
+    #[prop(optional)] index: Option<usize>,
+    branch_action: Action<usize, ()>,
+    edit_action: Action<(usize, String), ()>,
+    remove_entry_index: RwSignal<Option<usize>>,
+    remove_entry_preview: RwSignal<Option<String>>,
+) -> impl IntoView {
+    let is_editing = create_rw_signal(cx, false);
+    let is_info_open = create_rw_signal(cx, false);
+    let draft = create_rw_signal(cx, String::new());
+    let (class, prefix) = match entry {
+        Entry::User => ("fs-5", "User: "),
+        Entry::Chat => ("text-airtifex-light fs-5", "Chat: "),
+        Entry::None => ("fs-5", ""),
+    };
+    let branch_button = index.map(|index| {
+        view! { cx,
+            <button
+                class="btn btn-sm btn-outline-lighter rounded py-0 ms-2"
+                title="Continue the chat from this message in a new chat"
+                on:click=move |_| branch_action.dispatch(index)
+            >
+                "Branch from here"
+            </button>
+        }
+    });
+    let edit_button = index.filter(|_| entry == Entry::User).map(|_| {
+        view! { cx,
+            <button
+                class="btn btn-sm btn-outline-lighter rounded py-0 ms-2"
+                title="Edit the message and answer it again, the messages after it are removed"
+                prop:disabled=move || is_editing.get()
+                on:click=move |_| {
+                    draft.update(|d| *d = text.get());
+                    is_editing.update(|e| *e = true);
+                }
+            >
+                "Edit"
+            </button>
+        }
+    });
+    let remove_button = index.map(|index| {
+        view! { cx,
+            <button
+                class="btn btn-sm btn-outline-lighter rounded py-0 ms-2"
+                title="Remove the message together with its prompt or answer"
+                data-bs-toggle="modal"
+                data-bs-target="#removeChatEntryModal"
+                on:focus=move |_| {
+                    let preview = text.with(|t| t.chars().take(40).collect::<String>());
+                    remove_entry_index.update(|i| *i = Some(index));
+                    remove_entry_preview.update(|p| *p = Some(format!("\"{preview}\"")));
+                }
+            >
+                "Remove"
+            </button>
+        }
+    });
+    let info_button = params.as_ref().map(|_| {
+        view! { cx,
+            <button
+                class="btn btn-sm btn-outline-lighter rounded py-0 ms-2"
+                title="Show the model and parameters this answer was generated with"
+                on:click=move |_| is_info_open.update(|o| *o = !*o)
+            >
+                "Info"
+            </button>
+        }
+    });
+    let info = params.map(|params| {
+        let num_predict = params
+            .num_predict
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| "unlimited".into());
+        view! { cx,
+            <div
+                class="card bg-dark border-secondary text-white position-absolute p-2 mt-1 small"
+                style="z-index: 10"
+                class:d-none=move || !is_info_open.get()
+            >
+                <table class="table table-dark table-sm mb-0 font-monospace">
+                    <tbody>
+                        <tr><td>"Model"</td><td class="text-airtifex-yellow">{params.model}</td></tr>
+                        <tr><td>"Temperature"</td><td class="text-airtifex-yellow">{params.temp}</td></tr>
+                        <tr><td>"Top K"</td><td class="text-airtifex-yellow">{params.top_k}</td></tr>
+                        <tr><td>"Top P"</td><td class="text-airtifex-yellow">{params.top_p}</td></tr>
+                        <tr><td>"Repeat penalty"</td><td class="text-airtifex-yellow">{params.repeat_penalty}</td></tr>
+                        <tr><td>"Repeat last N"</td><td class="text-airtifex-yellow">{params.repeat_last_n}</td></tr>
+                        <tr><td>"Max tokens"</td><td class="text-airtifex-yellow">{num_predict}</td></tr>
+                    </tbody>
+                </table>
+            </div>
+        }
+    });
+    let save_edit = move || {
+        if let Some(index) = index {
+            edit_action.dispatch((index, draft.get()));
+        }
+        is_editing.update(|e| *e = false);
+    };
+
+    view! { cx,
+        <div class="position-relative">
+            <strong class=class>{prefix}</strong>
+            {branch_button}
+            {edit_button}
+            {remove_button}
+            {info_button}
+            {info}
+            {match entry {
+                Entry::Chat => view! { cx,
+                    <div class="fs-6 ms-3 mb-3"><Markdown text=text /></div>
+                }.into_view(cx),
+                _ => (move || if is_editing.get() {
+                    view! { cx,
+                        <form on:submit=|ev| ev.prevent_default() class="ms-3 mb-3">
+                            <textarea
+                                class="form-control"
+                                rows="3"
+                                prop:value=move || draft.get()
+                                on:input=move |ev| {
+                                    let val = event_target_value(&ev);
+                                    draft.update(|d| *d = val);
+                                }
+                            >
+                            </textarea>
+                            <div class="d-flex flex-row mt-2">
+                                <button
+                                    class="btn btn-sm btn-outline-lighter rounded me-1"
+                                    prop:disabled=move || draft.get().trim().is_empty()
+                                    on:click=move |_| save_edit()
+                                >
+                                    "Save and regenerate"
+                                </button>
+                                <button
+                                    class="btn btn-sm btn-outline-lighter rounded"
+                                    on:click=move |_| is_editing.update(|e| *e = false)
+                                >
+                                    "Cancel"
+                                </button>
+                            </div>
+                        </form>
+                    }.into_view(cx)
+                } else {
+                    view! { cx, <pre class="fs-6 ms-3">{move || text.get()}</pre> }.into_view(cx)
+                }).into_view(cx),
+            }}
+        </div>
+    }
+}
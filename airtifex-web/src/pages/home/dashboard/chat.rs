@@ -1,10 +1,13 @@
 use crate::{pages, Page};
 
-use airtifex_core::llm::{ChatListEntry, UserChatCounters};
+use airtifex_core::llm::{ChatOverview, UserChatCounters};
 use leptos::*;
 
+/// Number of the most recently updated chats shown.
+pub const DISPLAY_COUNT: usize = 5;
+
 #[component]
-pub fn RecentChats(cx: Scope, chats: Resource<(), Vec<ChatListEntry>>) -> impl IntoView {
+pub fn RecentChats(cx: Scope, chats: Resource<(), Vec<ChatOverview>>) -> impl IntoView {
     view! { cx, { move || {
         if let Some(chats) = chats.read(cx) {
             return view! { cx,
@@ -20,7 +23,6 @@ pub fn RecentChats(cx: Scope, chats: Resource<(), Vec<ChatListEntry>>) -> impl I
                     </thead>
                     <tbody class="text-start">
                 {
-                    const DISPLAY_COUNT: usize = 5;
                     let count = chats.len();
                     let mut views = chats.into_iter().take(DISPLAY_COUNT).map(|chat| {
                         view!{cx, <RecentChatEntry chat />}.into_view(cx)
@@ -60,7 +62,7 @@ pub fn RecentChats(cx: Scope, chats: Resource<(), Vec<ChatListEntry>>) -> impl I
 }
 
 #[component]
-fn RecentChatEntry(cx: Scope, chat: ChatListEntry) -> impl IntoView {
+fn RecentChatEntry(cx: Scope, chat: ChatOverview) -> impl IntoView {
     let view_href = format!("{}/{}", Page::Chat.raw_path(), chat.id);
     view! {cx, <tr
                 class="text-white no-border"
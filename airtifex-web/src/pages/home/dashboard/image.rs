@@ -1,6 +1,6 @@
 use crate::{pages, Page};
 
-use airtifex_core::image::ImageInspect;
+use airtifex_core::image::{ImageInspect, ImageStatus};
 use leptos::*;
 
 #[component]
@@ -62,10 +62,15 @@ pub fn RecentImages(cx: Scope, images: Resource<(), Vec<ImageInspect>>) -> impl
 #[component]
 fn RecentImageEntry(cx: Scope, image: ImageInspect) -> impl IntoView {
     let view_href = format!("{}/{}", Page::GenerateImage.raw_path(), image.id);
-    let is_finished = if !image.processing {
-        view! { cx, <span class="text-airtifex-green">"✓"</span>}
-    } else {
-        view! { cx, <span class="text-airtifex-yellow">"✗"</span>}
+    let is_finished = match image.status {
+        ImageStatus::Done => view! { cx, <span class="text-airtifex-green">"✓"</span>},
+        ImageStatus::Failed => {
+            view! { cx, <span class="text-danger" title=image.error.clone()>"failed"</span>}
+        }
+        ImageStatus::Cancelled => view! { cx, <span class="text-secondary">"cancelled"</span>},
+        ImageStatus::Queued | ImageStatus::Running => {
+            view! { cx, <span class="text-airtifex-yellow">"✗"</span>}
+        }
     };
     view! {cx, <tr
                 class="text-white no-border"
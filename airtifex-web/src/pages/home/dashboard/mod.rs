@@ -1,13 +1,15 @@
 mod chat;
 mod image;
+mod stats;
 
 use chat::*;
 use image::*;
+use stats::*;
 
 use crate::{api::AuthorizedApi, components::status_message::*, pages, web_util};
 use airtifex_core::{
     image::ImageModelListEntry,
-    llm::{LlmListEntry, UserChatCounters},
+    llm::{ChatListQuery, LlmListEntry, UserChatCounters},
 };
 
 use leptos::*;
@@ -17,6 +19,7 @@ pub fn Dashboard(
     cx: Scope,
     authorized_api: RwSignal<Option<AuthorizedApi>>,
     global_message: RwSignal<Message>,
+    is_admin: bool,
 ) -> impl IntoView {
     let window_size = web_util::WindowSize::signal(cx).expect("window size");
 
@@ -25,14 +28,18 @@ pub fn Dashboard(
         move || (),
         move |_| async move {
             match authorized_api.get() {
-                Some(api) => match api.chat_list().await {
-                    Ok(mut chats) => {
-                        chats.reverse();
-                        chats
-                    }
+                Some(api) => match api
+                    .chat_list(ChatListQuery {
+                        page: Some(1),
+                        // one more than shown to know whether there are more
+                        page_size: Some(DISPLAY_COUNT as u32 + 1),
+                    })
+                    .await
+                {
+                    Ok(page) => page.chats,
                     Err(e) => {
-                        let e = e.to_string();
                         pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
                         global_message.update(|msg| *msg = Message::Error(e));
                         vec![]
                     }
@@ -57,8 +64,8 @@ pub fn Dashboard(
                         images
                     }
                     Err(e) => {
-                        let e = e.to_string();
                         pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
                         global_message.update(|msg| *msg = Message::Error(e));
                         vec![]
                     }
@@ -135,9 +142,34 @@ pub fn Dashboard(
         },
     );
 
+    let system_stats = create_resource(
+        cx,
+        move || (),
+        move |_| async move {
+            if !is_admin {
+                return None;
+            }
+            match authorized_api.get() {
+                Some(api) => match api.admin_stats().await {
+                    Ok(stats) => Some(stats),
+                    Err(e) => {
+                        global_message.update(|msg| *msg = Message::Error(e.to_string()));
+                        None
+                    }
+                },
+                None => {
+                    global_message
+                        .update(|msg| *msg = Message::Error("connection to API failed".into()));
+                    None
+                }
+            }
+        },
+    );
+
     let inner_view = move || {
         if window_size.get().width < 992 {
             view! { cx,
+                <SystemStatsCards stats=system_stats />
                 <div class="d-flex flex-row col-12 py-3">
                     <div class="d-flex flex-row col-12">
                         <RecentChats chats />
@@ -167,6 +199,7 @@ pub fn Dashboard(
             .into_view(cx)
         } else {
             view! { cx,
+                <SystemStatsCards stats=system_stats />
                 <div class="d-flex flex-row col-12 pb-3">
                     <div class="d-flex flex-row justify-content-center col-6 pe-2">
                         <RecentChats chats />
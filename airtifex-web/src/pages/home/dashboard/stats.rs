@@ -0,0 +1,37 @@
+use airtifex_core::admin::SystemStats;
+use leptos::*;
+
+/// Usage of the whole server, only loaded for admins.
+#[component]
+pub fn SystemStatsCards(cx: Scope, stats: Resource<(), Option<SystemStats>>) -> impl IntoView {
+    view! { cx, { move || {
+        if let Some(Some(stats)) = stats.read(cx) {
+            let cards = [
+                ("Users", stats.total_users.to_string()),
+                ("Images", stats.total_images.to_string()),
+                ("Images (24h)", stats.images_last_day.to_string()),
+                ("Chat messages", stats.total_chat_messages.to_string()),
+                ("Inference queue", stats.inference_queue_depth.to_string()),
+                ("Running sessions", stats.running_sessions.to_string()),
+            ];
+            return view! { cx,
+            <div class="d-flex flex-row flex-wrap col-12 pb-3">
+            {
+                cards.into_iter().map(|(title, value)| {
+                    view!{ cx,
+                        <div class="col-6 col-lg-2 p-1">
+                            <div class="card bg-darker p-3 h-100">
+                                <div class="text-secondary">{title}</div>
+                                <div class="display-6 text-airtifex">{value}</div>
+                            </div>
+                        </div>
+                    }.into_view(cx)
+                }).collect::<Vec<_>>()
+            }
+            </div>
+            }.into_view(cx)
+       }
+       view!{ cx, <></>}.into_view(cx)
+    }}}
+    .into_view(cx)
+}
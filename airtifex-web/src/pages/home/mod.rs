@@ -4,7 +4,7 @@ pub use dashboard::*;
 
 use crate::{api::AuthorizedApi, components::status_message::*, Page};
 
-use airtifex_core::user::AuthenticatedUser;
+use airtifex_core::user::{AccountType, AuthenticatedUser};
 use leptos::*;
 use leptos_router::*;
 
@@ -20,7 +20,8 @@ pub fn Home(
     view! { cx,
       {move || {
        let inner_view = match user_info.get() {
-        Some(_info) => {
+        Some(info) => {
+            let is_admin = info.account_type == AccountType::Admin;
             let classes = move || if window_size.get().width < 992 {
                 "text-center d-flex flex-column mx-3 w-100"
             } else {
@@ -29,7 +30,7 @@ pub fn Home(
             view!{ cx,
             <div class=classes>
                 <StatusMessage message=global_message />
-                <Dashboard authorized_api global_message />
+                <Dashboard authorized_api global_message is_admin />
             </div>
             }.into_view(cx)
         },
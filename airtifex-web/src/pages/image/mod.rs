@@ -1,26 +1,39 @@
 use crate::{
     api,
     components::{modal::*, status_message::*},
-    pages, web_util, Page, PageStack,
+    i18n::t,
+    pages,
+    preferences::use_preferences,
+    web_util, Page, PageStack,
+};
+use airtifex_core::{
+    image::{
+        ImageDeleteBatchRequest, ImageFeedQuery, ImageGenerateRequest, ImageInspect,
+        ImagePresetInspect, ImagePresetRequest, ImageSettings, ImageStatus, ImageTagCount,
+        InputImage,
+    },
+    user::UserSettings,
 };
-use airtifex_core::image::{ImageGenerateRequest, ImageInspect, InputImage};
 
 use leptos::*;
+use leptos_router::*;
 
 pub mod view;
 
 pub use view::*;
 
+/// Distance from the bottom of the page in pixels at which the next images are loaded.
+const FEED_SCROLL_THRESHOLD: i32 = 200;
+
 #[component]
 pub fn GenerateImage(
     cx: Scope,
     authorized_api: RwSignal<Option<api::AuthorizedApi>>,
     page_stack: RwSignal<PageStack>,
 ) -> impl IntoView {
-    let current_list_page = create_rw_signal::<u32>(cx, 1);
-
     let status_message = create_rw_signal(cx, Message::Empty);
     let remove_image_id = create_rw_signal(cx, None::<String>);
+    let selected_images = create_rw_signal(cx, Vec::<String>::new());
 
     let input_image = create_rw_signal(cx, None::<web_sys::File>);
     let strength = create_rw_signal(cx, 0.7);
@@ -28,42 +41,163 @@ pub fn GenerateImage(
 
     let width = create_rw_signal(cx, None::<i64>);
     let height = create_rw_signal(cx, None::<i64>);
-    let prompt = create_rw_signal(cx, String::new());
+    // a sample can be regenerated by navigating here with its seed and prompt in the query
+    let query = use_query_map(cx).get();
+    let prompt = create_rw_signal(cx, query.get("prompt").cloned().unwrap_or_default());
     let selected_model = create_rw_signal(cx, String::new());
     let n_steps = create_rw_signal(cx, None::<usize>);
-    let seed = create_rw_signal(cx, None::<i64>);
+    let seed = create_rw_signal(cx, query.get("seed").and_then(|s| s.parse::<i64>().ok()));
     let num_samples = create_rw_signal(cx, None::<i64>);
     let guidance_scale = create_rw_signal(cx, None::<f64>);
+    let preview_every = create_rw_signal(cx, None::<usize>);
+    let user_settings = create_rw_signal(cx, UserSettings::default());
+    let preferences = use_preferences(cx);
+
+    let images = create_rw_signal(cx, Vec::<ImageInspect>::new());
+    let next_cursor = create_rw_signal(cx, None::<String>);
+    let is_feed_exhausted = create_rw_signal(cx, false);
+    let is_feed_loading = create_rw_signal(cx, false);
+    // only images of the user with all of these tags are listed
+    let tag_filter = create_rw_signal(cx, Vec::<String>::new());
+    // only the favorites of the user are listed
+    let favorites_only = create_rw_signal(cx, false);
+
+    // loads the next page of the feed or the first one again when `reload` is set
+    let load_images_action = create_action(cx, move |reload: &bool| {
+        let reload = *reload;
+        async move {
+            if !reload && (is_feed_loading.get() || is_feed_exhausted.get()) {
+                return;
+            }
+            let Some(api) = authorized_api.get() else {
+                status_message
+                    .update(|msg| *msg = Message::Error("connection to API failed".into()));
+                return;
+            };
+            is_feed_loading.update(|l| *l = true);
+            let query = ImageFeedQuery {
+                cursor: if reload { None } else { next_cursor.get() },
+                limit: preferences.get().items_per_page,
+                favorites_only: favorites_only.get().then_some(true),
+                favorites_first: None,
+                tags: tag_filter.get(),
+            };
+            match api.image_feed(query).await {
+                Ok(page) => {
+                    images.update(|images| {
+                        if reload {
+                            images.clear();
+                        }
+                        images.extend(page.images);
+                    });
+                    is_feed_exhausted.update(|e| *e = page.next_cursor.is_none());
+                    next_cursor.update(|c| *c = page.next_cursor);
+                }
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    status_message.update(|msg| *msg = Message::Error(e));
+                }
+            }
+            is_feed_loading.update(|l| *l = false);
+        }
+    });
+    load_images_action.dispatch(true);
 
-    let images = create_resource(
+    let user_tags = create_resource(
         cx,
-        move || current_list_page.get(),
-        move |_current_list_page| async move {
+        move || (),
+        move |_| async move {
             match authorized_api.get() {
-                Some(api) => match api.image_list().await {
-                    Ok(images) => images,
+                Some(api) => match api.image_tags().await {
+                    Ok(tags) => tags,
                     Err(e) => {
-                        let e = e.to_string();
-                        pages::goto_login_if_expired(cx, &e, authorized_api);
-                        status_message.update(|msg| *msg = Message::Error(e));
+                        status_message.update(|msg| *msg = Message::Error(e.to_string()));
                         vec![]
                     }
                 },
-                None => {
-                    status_message
-                        .update(|msg| *msg = Message::Error("connection to API failed".into()));
-                    vec![]
-                }
+                None => vec![],
             }
         },
     );
 
+    // the feed starts over whenever the tag filter or the tab changes
+    create_effect(cx, move |previous: Option<()>| {
+        tag_filter.with(|_| ());
+        favorites_only.with(|_| ());
+        if previous.is_some() {
+            load_images_action.dispatch(true);
+        }
+    });
+
+    // prefills the parameters that weren't set yet with the defaults of the user
+    let load_settings_action = create_action(cx, move |_| async move {
+        let Some(api) = authorized_api.get() else {
+            return;
+        };
+        match api.user_settings().await {
+            Ok(settings) => {
+                let defaults = settings.image.clone();
+                width.update(|v| *v = v.or(defaults.width));
+                height.update(|v| *v = v.or(defaults.height));
+                n_steps.update(|v| *v = v.or(defaults.n_steps));
+                num_samples.update(|v| *v = v.or(defaults.num_samples));
+                guidance_scale.update(|v| *v = v.or(defaults.guidance_scale));
+                if let Some(default_strength) = defaults.strength {
+                    strength.update(|v| *v = default_strength);
+                }
+                user_settings.update(|s| *s = settings);
+            }
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                let e = e.to_string();
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to load default settings - {e}"));
+                });
+            }
+        }
+    });
+    load_settings_action.dispatch(());
+
+    let save_settings_action = create_action(cx, move |_| async move {
+        let Some(api) = authorized_api.get() else {
+            status_message.update(|m| {
+                *m = Message::Error("failed to connect to API".into());
+            });
+            return;
+        };
+        let mut settings = user_settings.get();
+        settings.image = ImageSettings {
+            width: width.get(),
+            height: height.get(),
+            n_steps: n_steps.get(),
+            num_samples: num_samples.get(),
+            guidance_scale: guidance_scale.get(),
+            strength: Some(strength.get()),
+        };
+        match api.user_settings_update(&settings).await {
+            Ok(_) => {
+                user_settings.update(|s| *s = settings);
+                status_message.update(|m| {
+                    *m = Message::Success("saved the current settings as defaults".into());
+                });
+            }
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                let e = e.to_string();
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to save default settings - {e}"));
+                });
+            }
+        }
+    });
+
     let remove_image_action = create_action(cx, move |_| async move {
         if let Some(api) = authorized_api.get() {
             if let Some(id) = remove_image_id.get() {
                 if let Err(e) = api.image_delete(&id).await {
-                    let e = e.to_string();
                     pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
                     status_message.update(|m| {
                         *m = Message::Error(format!("failed to remove image - {e}"));
                     });
@@ -71,8 +205,7 @@ pub fn GenerateImage(
                     status_message.update(|m| {
                         *m = Message::Success(format!("successfully removed image {id}"));
                     });
-                    current_list_page.update(|p| *p += 1);
-                    current_list_page.update(|p| *p -= 1);
+                    load_images_action.dispatch(true);
                 }
             }
         } else {
@@ -82,6 +215,94 @@ pub fn GenerateImage(
         }
     });
 
+    let remove_selected_action = create_action(cx, move |_| async move {
+        let Some(api) = authorized_api.get() else {
+            status_message.update(|m| {
+                *m = Message::Error("failed to connect to API".into());
+            });
+            return;
+        };
+        let request = ImageDeleteBatchRequest {
+            ids: selected_images.get(),
+        };
+        match api.image_delete_batch(request).await {
+            Ok(response) => {
+                status_message.update(|m| {
+                    *m = if response.skipped > 0 {
+                        Message::Error(format!(
+                            "removed {} images, skipped {} that don't exist or belong to other users",
+                            response.deleted, response.skipped
+                        ))
+                    } else {
+                        Message::Success(format!("successfully removed {} images", response.deleted))
+                    };
+                });
+                selected_images.update(|s| s.clear());
+                load_images_action.dispatch(true);
+            }
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                let e = e.to_string();
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to remove images - {e}"));
+                });
+            }
+        }
+    });
+
+    let retry_image_action = create_action(cx, move |id: &String| {
+        let id = id.clone();
+        async move {
+            if let Some(api) = authorized_api.get() {
+                if let Err(e) = api.image_retry(&id).await {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to retry image - {e}"));
+                    });
+                } else {
+                    status_message.update(|m| {
+                        *m = Message::Success(format!("queued image {id} again"));
+                    });
+                    load_images_action.dispatch(true);
+                }
+            } else {
+                status_message.update(|m| {
+                    *m = Message::Error("failed to connect to API".into());
+                });
+            }
+        }
+    });
+
+    let favorite_action = create_action(cx, move |id: &String| {
+        let id = id.clone();
+        async move {
+            let Some(api) = authorized_api.get() else {
+                status_message.update(|m| {
+                    *m = Message::Error("failed to connect to API".into());
+                });
+                return;
+            };
+            match api.image_toggle_favorite(&id).await {
+                Ok(response) => images.update(|images| {
+                    if let Some(image) = images.iter_mut().find(|image| image.id == id) {
+                        image.is_favorite = response.is_favorite;
+                    }
+                    if favorites_only.get() {
+                        images.retain(|image| image.is_favorite);
+                    }
+                }),
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to star image - {e}"));
+                    });
+                }
+            }
+        }
+    });
+
     let new_image_action = create_action(cx, move |_| async move {
         if let Some(api) = authorized_api.get() {
             let data = if let Some(f) = input_image.get() {
@@ -131,6 +352,8 @@ pub fn GenerateImage(
                 seed: seed.get(),
                 num_samples: num_samples.get(),
                 guidance_scale: guidance_scale.get(),
+                preview_every: preview_every.get(),
+                sampler: None,
             };
             match api.image_generate(request).await {
                 Ok(response) => {
@@ -140,8 +363,7 @@ pub fn GenerateImage(
                             response.image_id
                         ));
                     });
-                    current_list_page.update(|p| *p += 1);
-                    current_list_page.update(|p| *p -= 1);
+                    load_images_action.dispatch(true);
                 }
                 Err(e) => {
                     status_message.update(|m| {
@@ -171,26 +393,111 @@ pub fn GenerateImage(
         .into_view(cx)
     };
 
+    let remove_selected_modal = move || {
+        let title = create_rw_signal(cx, "Remove images".to_string());
+        let body = Signal::derive(cx, move || {
+            view! { cx,
+                <p>{move || format!(
+                    "Are you sure you want to remove {} selected images?",
+                    selected_images.with(|s| s.len())
+                )}</p>
+            }
+            .into_view(cx)
+        });
+        let footer = create_rw_signal(
+            cx,
+            view! { cx,
+                <button
+                  type="button"
+                  data-bs-dismiss="modal"
+                  on:click=move |_| remove_selected_action.dispatch(())
+                  class="btn btn-danger"
+                >
+                  "Remove"
+                </button>
+                <button type="button" class="btn btn-secondary" data-bs-dismiss="modal">
+                  "Cancel"
+                </button>
+            }
+            .into_view(cx),
+        );
+        view! { cx,
+          <Modal
+            modal_id="removeSelectedImagesModal"
+            title=title.read_only()
+            body=body
+            footer=footer.read_only()
+          />
+        }
+        .into_view(cx)
+    };
+
     view! { cx,
       {move || {
         page_stack.update(|p| p.push(Page::GenerateImage));
 
         view!{cx,
-           <main class="bg-dark text-white d-flex flex-column p-1 pt-3 overflow-auto" >
+           <main
+             class="bg-dark text-white d-flex flex-column p-1 pt-3 overflow-auto"
+             on:scroll=move |ev| {
+                 let main = event_target::<web_sys::Element>(&ev);
+                 if main.scroll_top() + main.client_height()
+                     >= main.scroll_height() - FEED_SCROLL_THRESHOLD
+                 {
+                     load_images_action.dispatch(false);
+                 }
+             }
+           >
                  <div class="d-flex pb-3">
-                     <h1 class="display-5 p-1">{Page::GenerateImage.title()}</h1>
+                     <h1 class="display-5 p-1">{move || t(cx, Page::GenerateImage.title_id())}</h1>
                  </div>
                  <GenerateImageForm
                      authorized_api status_message prompt width height n_steps seed num_samples
-                     selected_model dispatch_new_image_action guidance_scale input_image mask
-                     strength
+                     selected_model dispatch_new_image_action guidance_scale preview_every
+                     input_image mask strength user_settings save_settings_action
                  />
                  <div class="card bg-darker m-3">
                     <StatusMessage message=status_message />
-                    <ImageListEntries images remove_image_id />
+                    {move || if selected_images.with(|s| s.is_empty()) {
+                        view! { cx, <></> }.into_view(cx)
+                    } else {
+                        view! { cx,
+                          <div class="d-flex px-5 pt-3">
+                            <button
+                              class="btn btn-outline-lighter ms-auto"
+                              data-bs-toggle="modal"
+                              data-bs-target="#removeSelectedImagesModal"
+                            >
+                              <img src="/icons/minus-circle.svg" class="me-2" />
+                              {move || format!("Remove selected ({})", selected_images.with(|s| s.len()))}
+                            </button>
+                          </div>
+                        }
+                        .into_view(cx)
+                    }}
+                    <FavoriteTabs favorites_only />
+                    <TagFilter user_tags tag_filter />
+                    <ImageListEntries
+                      images selected_images remove_image_id retry_image_action favorite_action tag_filter
+                    />
+                    {move || if is_feed_exhausted.get() {
+                        view! { cx, <></> }.into_view(cx)
+                    } else {
+                        view! { cx,
+                          <button
+                            class="btn btn-outline-lighter mx-auto mb-3"
+                            disabled=move || is_feed_loading.get()
+                            on:click=move |_| load_images_action.dispatch(false)
+                          >
+                            "Load more"
+                          </button>
+                        }
+                        .into_view(cx)
+                    }}
                  </div>
            </main>
            {remove_confirm_modal}
+           {remove_selected_modal}
         }.into_view(cx)
      }}
     }
@@ -208,19 +515,20 @@ fn GenerateImageForm<F>(
     seed: RwSignal<Option<i64>>,
     num_samples: RwSignal<Option<i64>>,
     guidance_scale: RwSignal<Option<f64>>,
+    preview_every: RwSignal<Option<usize>>,
     selected_model: RwSignal<String>,
     input_image: RwSignal<Option<web_sys::File>>,
     mask: RwSignal<Option<web_sys::File>>,
     strength: RwSignal<f64>,
+    user_settings: RwSignal<UserSettings>,
+    save_settings_action: Action<(), ()>,
     dispatch_new_image_action: F,
 ) -> impl IntoView
 where
     F: FnOnce() + Copy + 'static,
 {
     let current_list_page = create_rw_signal(cx, 1);
-    let is_advanced_settings_open = create_rw_signal(cx, false);
-    let is_input_image_visible = create_rw_signal(cx, false);
-    let is_mask_visible = create_rw_signal(cx, false);
+    let is_advanced_settings_open = create_rw_signal(cx, seed.get().is_some());
 
     let settings_icon = Signal::derive(cx, move || {
         if is_advanced_settings_open.get() {
@@ -255,13 +563,50 @@ where
         if let Some(models) = models.read(cx) {
             if let Some(first) = models.first() {
                 selected_model.update(|m| *m = first.name.clone());
-                is_input_image_visible
-                    .update(|v| *v = first.features.inpaint || first.features.image_to_image);
-                is_mask_visible.update(|v| *v = first.features.inpaint);
             }
         }
     });
 
+    // controls of features the selected model doesn't support are disabled
+    let selected_model_entry = Signal::derive(cx, move || {
+        let name = selected_model.get();
+        models
+            .read(cx)
+            .and_then(|models| models.into_iter().find(|m| m.name == name))
+    });
+    let supports_input_image = Signal::derive(cx, move || {
+        selected_model_entry
+            .get()
+            .is_some_and(|m| m.features.inpaint || m.features.image_to_image)
+    });
+    let supports_mask = Signal::derive(cx, move || {
+        selected_model_entry
+            .get()
+            .is_some_and(|m| m.features.inpaint)
+    });
+    let max_width = Signal::derive(cx, move || {
+        selected_model_entry
+            .get()
+            .and_then(|m| m.capabilities.max_width)
+    });
+    let max_height = Signal::derive(cx, move || {
+        selected_model_entry
+            .get()
+            .and_then(|m| m.capabilities.max_height)
+    });
+    create_effect(cx, move |_| {
+        if !supports_input_image.get() {
+            input_image.set(None);
+        }
+        if !supports_mask.get() {
+            mask.set(None);
+        }
+    });
+    let size_class = move |value: Option<i64>, max: Option<i64>| match (value, max) {
+        (Some(value), Some(max)) if value > max => "form-control is-invalid",
+        _ => "form-control",
+    };
+
     view! { cx,
         <>
         <div class="card bg-darker m-3">
@@ -271,13 +616,18 @@ where
                       class="row text-start"
                     >
 
+                      <ImagePresets
+                        authorized_api status_message prompt width height n_steps seed num_samples
+                        guidance_scale strength user_settings is_advanced_settings_open
+                      />
+
                       <div class="input-group mb-3">
                          <label class="input-group-text">"Prompt"</label>
                          <textarea
                            class = "form-control"
                            required
                            rows="2"
-                           value=move || prompt.get()
+                           prop:value=move || prompt.get()
                            placeholder = "..."
                            on:keyup = move |ev: ev::KeyboardEvent| {
                                 match (&*ev.key(), ev.shift_key()) {
@@ -317,23 +667,19 @@ where
                         }}
                         </select>
                       </div>
-                      {move || if is_input_image_visible.get() {
-                        view!{ cx,
-                        <div class="input-group mb-3">
-                            <label class="input-group-text">"Input Image (optional)"</label>
-                            <input
-                            type="file"
-                            accept="image/png, image/jpeg"
-                            class="form-control"
-                            on:change = move |ev| {
-                                input_image.update(|v| *v = web_util::extract_file_from_html_input(ev));
-                            }
-                            />
-                        </div>
-                        }.into_view(cx)
-                      } else {
-                        view!{ cx, <></> }.into_view(cx)
-                      }}
+                      <div class="input-group mb-3">
+                          <label class="input-group-text">"Input Image (optional)"</label>
+                          <input
+                          type="file"
+                          accept="image/png, image/jpeg"
+                          class="form-control"
+                          title=move || if supports_input_image.get() { "" } else { "not supported by the selected model" }
+                          prop:disabled=move || !supports_input_image.get()
+                          on:change = move |ev| {
+                              input_image.update(|v| *v = web_util::extract_file_from_html_input(ev));
+                          }
+                          />
+                      </div>
                       {move || if input_image.get().is_some() {
                         view!{ cx,
                         <div class="input-group mb-3">
@@ -344,7 +690,7 @@ where
                             min = "0"
                             max = "1"
                             step = "0.01"
-                            value = {move || strength.get()}
+                            prop:value = move || strength.get()
                             on:change = move |ev| {
                                 let val = event_target_value(&ev);
                                 strength.update(|v|*v = val.parse().ok().unwrap_or(0.7));
@@ -356,23 +702,19 @@ where
                         view!{ cx, <></> }.into_view(cx)
                       }}
 
-                      {move || if is_mask_visible.get() {
-                        view!{ cx,
-                        <div class="input-group mb-3">
-                          <label class="input-group-text">"Mask (optional)"</label>
-                          <input
-                            type="file"
-                            accept="image/png, image/jpeg"
-                            class="form-control"
-                            on:change = move |ev| {
-                              mask.update(|v| *v = web_util::extract_file_from_html_input(ev));
-                            }
-                          />
-                        </div>
-                        }.into_view(cx)
-                      } else {
-                        view!{ cx, <></> }.into_view(cx)
-                      }}
+                      <div class="input-group mb-3">
+                        <label class="input-group-text">"Mask (optional)"</label>
+                        <input
+                          type="file"
+                          accept="image/png, image/jpeg"
+                          class="form-control"
+                          title=move || if supports_mask.get() { "" } else { "not supported by the selected model" }
+                          prop:disabled=move || !supports_mask.get()
+                          on:change = move |ev| {
+                            mask.update(|v| *v = web_util::extract_file_from_html_input(ev));
+                          }
+                        />
+                      </div>
 
                     <button
                        class="btn-btn-airtifex btn-outline rounded mx-auto mb-2"
@@ -388,8 +730,9 @@ where
                               <div class="input-group mb-3">
                                  <label class="input-group-text">"Width"</label>
                                  <input
-                                   class = "form-control"
+                                   class = move || size_class(width.get(), max_width.get())
                                    placeholder = "256"
+                                   prop:value = move || width.get().map(|v| v.to_string()).unwrap_or_default()
                                    on:keyup = move |ev: ev::KeyboardEvent| {
                                      match &*ev.key() {
                                          "Enter" => {
@@ -402,13 +745,17 @@ where
                                      }
                                    }
                                  />
+                                 {move || max_width.get().map(|max| view!{ cx,
+                                   <span class="input-group-text">"max "{max}</span>
+                                 })}
                               </div>
 
                               <div class="input-group mb-3">
                                  <label class="input-group-text">"Height"</label>
                                  <input
-                                   class = "form-control"
+                                   class = move || size_class(height.get(), max_height.get())
                                    placeholder = "256"
+                                   prop:value = move || height.get().map(|v| v.to_string()).unwrap_or_default()
                                    on:keyup = move |ev: ev::KeyboardEvent| {
                                      match &*ev.key() {
                                          "Enter" => {
@@ -421,6 +768,9 @@ where
                                      }
                                    }
                                  />
+                                 {move || max_height.get().map(|max| view!{ cx,
+                                   <span class="input-group-text">"max "{max}</span>
+                                 })}
                               </div>
 
                               <div class="row">
@@ -429,6 +779,7 @@ where
                                    <input
                                      class = "form-control"
                                      placeholder = "15"
+                                     prop:value = move || n_steps.get().map(|v| v.to_string()).unwrap_or_default()
                                      on:keyup = move |ev: ev::KeyboardEvent| {
                                        match &*ev.key() {
                                            "Enter" => {
@@ -447,6 +798,7 @@ where
                                    <input
                                      class = "form-control"
                                      placeholder = "1337"
+                                     prop:value = move || seed.get().map(|s| s.to_string()).unwrap_or_default()
                                      on:keyup = move |ev: ev::KeyboardEvent| {
                                        match &*ev.key() {
                                            "Enter" => {
@@ -467,6 +819,7 @@ where
                                  <input
                                    class = "form-control"
                                    placeholder = "1"
+                                   prop:value = move || num_samples.get().map(|v| v.to_string()).unwrap_or_default()
                                    on:keyup = move |ev: ev::KeyboardEvent| {
                                      match &*ev.key() {
                                          "Enter" => {
@@ -486,6 +839,7 @@ where
                                  <input
                                    class = "form-control"
                                    placeholder = "7.5"
+                                   prop:value = move || guidance_scale.get().map(|v| v.to_string()).unwrap_or_default()
                                    on:keyup = move |ev: ev::KeyboardEvent| {
                                      match &*ev.key() {
                                          "Enter" => {
@@ -500,6 +854,35 @@ where
                                  />
                               </div>
 
+                              <div class="input-group mb-3">
+                                 <label class="input-group-text">"Preview every"</label>
+                                 <input
+                                   class = "form-control"
+                                   placeholder = "no previews"
+                                   title = "Show an intermediate image every this many steps while generating, previews slow the generation down"
+                                   prop:value = move || preview_every.get().map(|v| v.to_string()).unwrap_or_default()
+                                   on:keyup = move |ev: ev::KeyboardEvent| {
+                                     match &*ev.key() {
+                                         "Enter" => {
+                                            dispatch_new_image_action();
+                                         }
+                                         _=> {
+                                            let val = event_target_value(&ev);
+                                            preview_every.update(|v|*v = val.parse().ok());
+                                         }
+                                     }
+                                   }
+                                 />
+                                 <span class="input-group-text">"steps"</span>
+                              </div>
+
+                              <button
+                                 class="btn btn-outline-lighter rounded mb-3"
+                                 on:click=move |_| save_settings_action.dispatch(())
+                              >
+                              "Save as defaults"
+                              </button>
+
                           </div>
                           }.into_view(cx)
                       } else {
@@ -526,20 +909,241 @@ where
     .into_view(cx)
 }
 
+/// Saved parameters of the user, selecting one fills the form with them.
+#[component]
+fn ImagePresets(
+    cx: Scope,
+    authorized_api: RwSignal<Option<api::AuthorizedApi>>,
+    status_message: RwSignal<Message>,
+    prompt: RwSignal<String>,
+    width: RwSignal<Option<i64>>,
+    height: RwSignal<Option<i64>>,
+    n_steps: RwSignal<Option<usize>>,
+    seed: RwSignal<Option<i64>>,
+    num_samples: RwSignal<Option<i64>>,
+    guidance_scale: RwSignal<Option<f64>>,
+    strength: RwSignal<f64>,
+    user_settings: RwSignal<UserSettings>,
+    is_advanced_settings_open: RwSignal<bool>,
+) -> impl IntoView {
+    let presets = create_rw_signal(cx, Vec::<ImagePresetInspect>::new());
+    let selected_preset = create_rw_signal(cx, None::<String>);
+    let preset_name = create_rw_signal(cx, String::new());
+
+    let load_presets_action = create_action(cx, move |_| async move {
+        let Some(api) = authorized_api.get() else {
+            return;
+        };
+        match api.image_presets().await {
+            Ok(list) => presets.update(|p| *p = list),
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                let e = e.to_string();
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to load presets - {e}"));
+                });
+            }
+        }
+    });
+    load_presets_action.dispatch(());
+
+    // parameters the preset doesn't set fall back to the defaults of the user
+    let apply_preset = move |id: String| {
+        let Some(preset) = presets.with(|p| p.iter().find(|p| p.id == id).cloned()) else {
+            selected_preset.update(|s| *s = None);
+            return;
+        };
+        let defaults = user_settings.with(|s| s.image.clone());
+        let settings = preset.settings;
+        if let Some(preset_prompt) = preset.prompt {
+            prompt.update(|p| *p = preset_prompt);
+        }
+        width.update(|v| *v = settings.width.or(defaults.width));
+        height.update(|v| *v = settings.height.or(defaults.height));
+        n_steps.update(|v| *v = settings.n_steps.or(defaults.n_steps));
+        seed.update(|v| *v = preset.seed);
+        num_samples.update(|v| *v = settings.num_samples.or(defaults.num_samples));
+        guidance_scale.update(|v| *v = settings.guidance_scale.or(defaults.guidance_scale));
+        if let Some(preset_strength) = settings.strength.or(defaults.strength) {
+            strength.update(|v| *v = preset_strength);
+        }
+        preset_name.update(|n| *n = preset.name);
+        selected_preset.update(|s| *s = Some(preset.id));
+        is_advanced_settings_open.update(|o| *o = true);
+    };
+
+    // saving under the name of an existing preset replaces its parameters
+    let save_preset_action = create_action(cx, move |_| async move {
+        let Some(api) = authorized_api.get() else {
+            status_message.update(|m| {
+                *m = Message::Error("failed to connect to API".into());
+            });
+            return;
+        };
+        let name = preset_name.get().trim().to_string();
+        let current_prompt = prompt.get();
+        let request = ImagePresetRequest {
+            name: name.clone(),
+            prompt: (!current_prompt.trim().is_empty()).then_some(current_prompt),
+            seed: seed.get(),
+            settings: ImageSettings {
+                width: width.get(),
+                height: height.get(),
+                n_steps: n_steps.get(),
+                num_samples: num_samples.get(),
+                guidance_scale: guidance_scale.get(),
+                strength: Some(strength.get()),
+            },
+        };
+        let existing = presets.with(|p| p.iter().find(|p| p.name == name).map(|p| p.id.clone()));
+        let result = match existing {
+            Some(id) => api.image_preset_update(&id, request).await,
+            None => api.image_preset_create(request).await,
+        };
+        match result {
+            Ok(preset) => {
+                status_message.update(|m| {
+                    *m = Message::Success(format!("saved preset {}", preset.name));
+                });
+                selected_preset.update(|s| *s = Some(preset.id));
+                load_presets_action.dispatch(());
+            }
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                let e = e.to_string();
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to save preset - {e}"));
+                });
+            }
+        }
+    });
+
+    let remove_preset_action = create_action(cx, move |_| async move {
+        let Some(api) = authorized_api.get() else {
+            status_message.update(|m| {
+                *m = Message::Error("failed to connect to API".into());
+            });
+            return;
+        };
+        let Some(id) = selected_preset.get() else {
+            return;
+        };
+        match api.image_preset_delete(&id).await {
+            Ok(_) => {
+                status_message.update(|m| {
+                    *m = Message::Success(format!("removed preset {}", preset_name.get()));
+                });
+                selected_preset.update(|s| *s = None);
+                preset_name.update(|n| n.clear());
+                load_presets_action.dispatch(());
+            }
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                let e = e.to_string();
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to remove preset - {e}"));
+                });
+            }
+        }
+    });
+
+    view! { cx,
+      <div class="input-group mb-3">
+        <label class="input-group-text">"Preset"</label>
+        <select
+          class="form-select"
+          id="imagePresetSelector"
+          on:change=move |ev| apply_preset(event_target_value(&ev))
+        >
+        { move || {
+          let current = selected_preset.get();
+          let none = if current.is_none() {
+              view! { cx, <option value="" selected>"none"</option> }.into_view(cx)
+          } else {
+              view! { cx, <option value="">"none"</option> }.into_view(cx)
+          };
+          let options = presets.get().into_iter().map(|p| {
+              if current.as_ref() == Some(&p.id) {
+                  view! { cx, <option value=p.id selected>{p.name}</option> }.into_view(cx)
+              } else {
+                  view! { cx, <option value=p.id>{p.name}</option> }.into_view(cx)
+              }
+          });
+          std::iter::once(none).chain(options).collect::<Vec<_>>()
+        }}
+        </select>
+        <input
+          class="form-control"
+          placeholder="Preset name"
+          prop:value=move || preset_name.get()
+          on:keyup=move |ev: ev::KeyboardEvent| {
+              let val = event_target_value(&ev);
+              preset_name.update(|n| *n = val);
+          }
+        />
+        <button
+          class="btn btn-outline-lighter"
+          title="Save the current settings as preset"
+          prop:disabled=move || preset_name.with(|n| n.trim().is_empty())
+          on:click=move |_| save_preset_action.dispatch(())
+        >
+          <img src="/icons/save.svg" />
+        </button>
+        <button
+          class="btn btn-outline-lighter"
+          title="Remove the selected preset"
+          prop:disabled=move || selected_preset.with(Option::is_none)
+          on:click=move |_| remove_preset_action.dispatch(())
+        >
+          <img src="/icons/minus-circle.svg" />
+        </button>
+      </div>
+    }
+}
+
 #[component]
 fn ImageListEntries(
     cx: Scope,
-    images: Resource<u32, Vec<ImageInspect>>,
+    images: RwSignal<Vec<ImageInspect>>,
+    selected_images: RwSignal<Vec<String>>,
     remove_image_id: RwSignal<Option<String>>,
+    retry_image_action: Action<String, ()>,
+    favorite_action: Action<String, ()>,
+    tag_filter: RwSignal<Vec<String>>,
 ) -> impl IntoView {
+    let is_all_selected = move || {
+        images.with(|images| {
+            !images.is_empty()
+                && selected_images.with(|s| images.iter().all(|image| s.contains(&image.id)))
+        })
+    };
+    let toggle_all = move |_: web_sys::Event| {
+        if is_all_selected() {
+            selected_images.update(|s| s.clear());
+        } else {
+            let ids = images.with(|images| images.iter().map(|image| image.id.clone()).collect());
+            selected_images.update(|s| *s = ids);
+        }
+    };
+
     view! { cx, { move || {
-        if let Some(images) = images.read(cx) {
-            if !images.is_empty() {
+        let images = images.get();
+        if !images.is_empty() {
                 return view! { cx,
                 <div class="card-body d-flex flex-column px-5 pb-5">
                   <table class="table table-hover table-striped table-responsive text-white">
                     <thead>
                     <tr>
+                      <th scope="col">
+                        <input
+                          type="checkbox"
+                          class="form-check-input"
+                          title="Select all"
+                          prop:checked=is_all_selected
+                          on:change=toggle_all
+                        />
+                      </th>
+                      <th scope="col">""</th>
                       <th scope="col">""</th>
                       <th class="col-3" scope="col">"Prompt"</th>
                       <th class="text-center" scope="col">"Model"</th>
@@ -548,22 +1152,21 @@ fn ImageListEntries(
                       <th class="text-center" scope="col">"Seed"</th>
                       <th class="text-center" scope="col">"N Steps"</th>
                       <th class="text-center" scope="col">"N Samples"</th>
-                      <th class="text-center" scope="col">"Finished"</th>
+                      <th class="text-center" scope="col">"Status"</th>
                       <th scope="col"></th>
                     </tr>
                     </thead>
                     <tbody>
                    {
                       images.into_iter().map(|image| {
-                          view!{cx, <ImageListEntry image remove_image_id />}.into_view(cx)
+                          view!{cx, <ImageListEntry image selected_images remove_image_id retry_image_action favorite_action tag_filter />}.into_view(cx)
                       }).collect::<Vec<_>>()
                    }
                     </tbody>
                   </table>
                 </div>
                 }.into_view(cx)
-            }
-       }
+        }
        view!{ cx, <></>}.into_view(cx)
     }}}
     .into_view(cx)
@@ -573,18 +1176,74 @@ fn ImageListEntries(
 fn ImageListEntry(
     cx: Scope,
     image: ImageInspect,
+    selected_images: RwSignal<Vec<String>>,
     remove_image_id: RwSignal<Option<String>>,
+    retry_image_action: Action<String, ()>,
+    favorite_action: Action<String, ()>,
+    tag_filter: RwSignal<Vec<String>>,
 ) -> impl IntoView {
+    let select_id = image.id.clone();
+    let is_selected = {
+        let id = image.id.clone();
+        move || selected_images.with(|s| s.contains(&id))
+    };
+    let favorite_id = image.id.clone();
+    let is_favorite = image.is_favorite;
     let view_href = format!("{}/{}", Page::GenerateImage.raw_path(), image.id);
     let view_href2 = view_href.clone();
-    let is_finished = if !image.processing {
-        view! { cx, <span class="text-airtifex-green">"✓"</span>}
+    let is_finished = match image.status {
+        ImageStatus::Done => view! { cx, <span class="text-airtifex-green">"✓"</span>},
+        ImageStatus::Failed => {
+            view! { cx, <span class="text-danger" title=image.error.clone()>"failed"</span>}
+        }
+        ImageStatus::Cancelled => view! { cx, <span class="text-secondary">"cancelled"</span>},
+        ImageStatus::Queued | ImageStatus::Running => {
+            view! { cx, <span class="text-airtifex-yellow">"✗"</span>}
+        }
+    };
+    let retry = if image.status.can_retry() {
+        let id = image.id.clone();
+        view! { cx,
+          <button
+            class="btn btn-outline-lighter"
+            on:click=move |_| retry_image_action.dispatch(id.clone())
+          >
+              <img src="/icons/send.svg" class="me-2" />
+              "Retry"
+          </button>
+        }
+        .into_view(cx)
     } else {
-        view! { cx, <span class="text-airtifex-yellow">"✗"</span>}
+        view! { cx, <></> }.into_view(cx)
     };
     view! {cx, <tr
                 class="text-white no-border align-middle"
               >
+                  <td class="fitwidth">
+                    <input
+                      type="checkbox"
+                      class="form-check-input"
+                      prop:checked=is_selected
+                      on:change=move |_| selected_images.update(|s| {
+                          if let Some(i) = s.iter().position(|id| id == &select_id) {
+                              s.remove(i);
+                          } else {
+                              s.push(select_id.clone());
+                          }
+                      })
+                    />
+                  </td>
+                  <td class="fitwidth">
+                      <button
+                        class="btn btn-sm"
+                        class:text-airtifex-yellow=is_favorite
+                        class:text-secondary={!is_favorite}
+                        title={if is_favorite { "Remove from favorites" } else { "Add to favorites" }}
+                        on:click=move |_| favorite_action.dispatch(favorite_id.clone())
+                      >
+                          {if is_favorite { "★" } else { "☆" }}
+                      </button>
+                  </td>
                   <td class="fitwidth">
                   { move || {
                     if let Some(thumbnail) = &image.thumbnail {
@@ -602,6 +1261,31 @@ fn ImageListEntry(
                     }
                   >
                     {image.prompt}
+                    <div>
+                    {
+                      image.tags.into_iter().map(|tag| {
+                          let filter_tag = tag.clone();
+                          view! { cx,
+                            <span
+                              class="badge rounded-pill bg-secondary me-1"
+                              title="Show only images with this tag"
+                              on:click=move |ev| {
+                                  // don't open the image
+                                  ev.stop_propagation();
+                                  tag_filter.update(|tags| {
+                                      if !tags.contains(&filter_tag) {
+                                          tags.push(filter_tag.clone());
+                                      }
+                                  });
+                              }
+                            >
+                              {tag}
+                            </span>
+                          }
+                          .into_view(cx)
+                      }).collect::<Vec<_>>()
+                    }
+                    </div>
                   </td>
                   <td align="center" class="text-airtifex-light">{image.model}</td>
                   <td align="center">{image.width}</td>
@@ -623,6 +1307,7 @@ fn ImageListEntry(
                               <img src="/icons/minus-circle.svg" class="me-2" />
                               "Remove"
                           </button>
+                          {retry}
                           <button
                             class="btn btn-outline-lighter"
                             on:click=move |_| {
@@ -638,3 +1323,94 @@ fn ImageListEntry(
     }
     .into_view(cx)
 }
+
+/// Tabs that switch between all images and the favorites of the user.
+#[component]
+fn FavoriteTabs(cx: Scope, favorites_only: RwSignal<bool>) -> impl IntoView {
+    view! { cx,
+      <ul class="nav nav-tabs px-5 pt-3">
+        <li class="nav-item">
+          <button
+            class="nav-link text-white"
+            class:active=move || !favorites_only.get()
+            on:click=move |_| favorites_only.update(|f| *f = false)
+          >
+            "All"
+          </button>
+        </li>
+        <li class="nav-item">
+          <button
+            class="nav-link text-white"
+            class:active=move || favorites_only.get()
+            on:click=move |_| favorites_only.update(|f| *f = true)
+          >
+            "★ Favorites"
+          </button>
+        </li>
+      </ul>
+    }
+}
+
+/// Tags of the images of the user, clicking one toggles it in the filter.
+#[component]
+fn TagFilter(
+    cx: Scope,
+    user_tags: Resource<(), Vec<ImageTagCount>>,
+    tag_filter: RwSignal<Vec<String>>,
+) -> impl IntoView {
+    view! { cx, { move || {
+        let mut tags = user_tags
+            .read(cx)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|t| (t.tag, Some(t.count)))
+            .collect::<Vec<_>>();
+        // tags taken from images of other users aren't in the list of the user
+        for tag in tag_filter.get() {
+            if !tags.iter().any(|(t, _)| t == &tag) {
+                tags.push((tag, None));
+            }
+        }
+        if tags.is_empty() {
+            return view! { cx, <></> }.into_view(cx);
+        }
+
+        view! { cx,
+          <div class="d-flex flex-row flex-wrap align-items-center px-5 pt-3">
+            <span class="text-secondary me-2">"Tags:"</span>
+            {
+              tags.into_iter().map(|(tag, count)| {
+                  let is_active = tag_filter.with(|f| f.contains(&tag));
+                  let class = if is_active {
+                      "badge rounded-pill bg-airtifex me-1"
+                  } else {
+                      "badge rounded-pill bg-secondary me-1"
+                  };
+                  let label = match count {
+                      Some(count) => format!("{tag} ({count})"),
+                      None => tag.clone(),
+                  };
+                  view! { cx,
+                    <span
+                      class=class
+                      style="cursor: pointer;"
+                      on:click=move |_| tag_filter.update(|f| {
+                          if let Some(i) = f.iter().position(|t| t == &tag) {
+                              f.remove(i);
+                          } else {
+                              f.push(tag.clone());
+                          }
+                      })
+                    >
+                      {label}
+                    </span>
+                  }
+                  .into_view(cx)
+              }).collect::<Vec<_>>()
+            }
+          </div>
+        }
+        .into_view(cx)
+    }}}
+    .into_view(cx)
+}
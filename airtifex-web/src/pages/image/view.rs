@@ -1,11 +1,26 @@
 use crate::{
     api,
-    components::{status_message::*, titled_child_page::*},
+    components::{loading::*, status_message::*, titled_child_page::*},
     pages, web_util, Page, PageStack,
 };
+use airtifex_core::{
+    image::{ImageGridQuery, ImagePreview, ImageProgress, ImageStatus, ImageVariationsQuery},
+    QueueStatus,
+};
 
+use futures::StreamExt;
 use leptos::*;
 use leptos_router::*;
+use wasm_bindgen::JsCast;
+
+/// Delay before reloading the samples of a finished generation in milliseconds, the samples are
+/// saved shortly after the generation ends.
+const SAMPLES_RELOAD_DELAY: i32 = 1000;
+
+/// Interval between checks of a sample that is generated again in milliseconds.
+const REROLL_POLL_INTERVAL: i32 = 2000;
+/// Checks of a sample that is generated again before giving up on waiting for it.
+const REROLL_POLL_ATTEMPTS: u32 = 300;
 
 #[derive(Params, PartialEq, Clone, Debug)]
 pub struct ImageParams {
@@ -34,8 +49,8 @@ pub fn ImageView(
                 (Some(api), Some(id)) => match api.image_info(&id).await {
                     Ok(meta) => Some(meta),
                     Err(e) => {
-                        let e = e.to_string();
                         pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
                         status_message.update(|msg| *msg = Message::Error(e));
                         None
                     }
@@ -57,8 +72,8 @@ pub fn ImageView(
                 (Some(api), Some(id)) => match api.image_samples(&id).await {
                     Ok(images) => Some(images),
                     Err(e) => {
-                        let e = e.to_string();
                         pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
                         status_message.update(|msg| *msg = Message::Error(e));
                         None
                     }
@@ -72,6 +87,268 @@ pub fn ImageView(
         },
     );
 
+    let reproduce_action = create_action(cx, move |id: &String| {
+        let id = id.clone();
+        async move {
+            if let Some(api) = authorized_api.get() {
+                match api.image_reproduce(&id).await {
+                    Ok(response) => {
+                        let page = Page::GeneratedImageView(response.image_id);
+                        pages::goto(cx, page.path()).expect("generated image page");
+                        // the route stays the same so the resources have to be reloaded
+                        dummy_images_signal.update(|s| *s += 1);
+                    }
+                    Err(e) => {
+                        pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
+                        status_message.update(|m| {
+                            *m = Message::Error(format!("failed to reproduce image - {e}"));
+                        });
+                    }
+                }
+            } else {
+                status_message.update(|m| {
+                    *m = Message::Error("failed to connect to API".into());
+                });
+            }
+        }
+    });
+
+    let grid_action = create_action(cx, move |id: &String| {
+        let id = id.clone();
+        let query = ImageGridQuery {
+            cols: None,
+            seeds: Some(true),
+        };
+        async move {
+            let Some(api) = authorized_api.get() else {
+                return;
+            };
+            let result = match api.image_grid(&id, query).await {
+                Ok(grid) => web_util::download_png(&format!("{id}-grid.png"), &grid)
+                    .map_err(|e| e.as_string().unwrap_or_default()),
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    Err(e.to_string())
+                }
+            };
+            if let Err(e) = result {
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to download the grid - {e}"));
+                });
+            }
+        }
+    });
+
+    let variations_action = create_action(cx, move |id: &String| {
+        let id = id.clone();
+        async move {
+            let Some(api) = authorized_api.get() else {
+                status_message.update(|m| {
+                    *m = Message::Error("failed to connect to API".into());
+                });
+                return;
+            };
+            match api
+                .image_variations(&id, ImageVariationsQuery::default())
+                .await
+            {
+                Ok(response) => {
+                    let page = Page::GeneratedImageView(response.image_id);
+                    pages::goto(cx, page.path()).expect("generated image page");
+                    // the route stays the same so the resources have to be reloaded
+                    dummy_images_signal.update(|s| *s += 1);
+                }
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to generate variations - {e}"));
+                    });
+                }
+            }
+        }
+    });
+
+    let share_action = create_action(cx, move |(id, n_sample): &(String, i32)| {
+        let (id, n_sample) = (id.clone(), *n_sample);
+        async move {
+            let Some(api) = authorized_api.get() else {
+                return;
+            };
+            match api.image_share(&id, n_sample).await {
+                Ok(share) => {
+                    let copied = web_util::copy_to_clipboard(&share.url).await.is_ok();
+                    let expires = share.expires.format("%a, %d %b %Y %H:%M:%S");
+                    status_message.update(|m| {
+                        *m = Message::Success(if copied {
+                            format!("Link copied to the clipboard, it is valid until {expires}")
+                        } else {
+                            format!("Share link valid until {expires}: {}", share.url)
+                        });
+                    });
+                }
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to share image - {e}"));
+                    });
+                }
+            }
+        }
+    });
+
+    // samples that are being generated again
+    let rerolling = create_rw_signal(cx, Vec::<i32>::new());
+    let reroll_action = create_action(cx, move |(id, n_sample): &(String, i32)| {
+        let (id, n_sample) = (id.clone(), *n_sample);
+        async move {
+            let Some(api) = authorized_api.get() else {
+                return;
+            };
+            let seed = match api.image_sample_regenerate(&id, n_sample).await {
+                Ok(response) => response.seed,
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to reroll sample {n_sample} - {e}"));
+                    });
+                    return;
+                }
+            };
+            rerolling.update(|r| r.push(n_sample));
+            // the sample is replaced once it is generated with the new seed
+            let mut replaced = false;
+            for _ in 0..REROLL_POLL_ATTEMPTS {
+                let _ = web_util::sleep(REROLL_POLL_INTERVAL).await;
+                match api.image_sample(&id, n_sample).await {
+                    Ok(sample) if sample.actual_seed == seed => {
+                        replaced = true;
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        pages::goto_login_if_expired(cx, &e, authorized_api);
+                        break;
+                    }
+                }
+            }
+            rerolling.update(|r| r.retain(|n| *n != n_sample));
+            if replaced {
+                dummy_images_signal.update(|s| *s += 1);
+            } else {
+                status_message.update(|m| {
+                    *m = Message::Error(format!("sample {n_sample} wasn't generated again"));
+                });
+            }
+        }
+    });
+
+    let revoke_shares_action = create_action(cx, move |id: &String| {
+        let id = id.clone();
+        async move {
+            let Some(api) = authorized_api.get() else {
+                return;
+            };
+            match api.image_revoke_shares(&id).await {
+                Ok(()) => status_message.update(|m| {
+                    *m = Message::Success("All share links of the image were revoked".into());
+                }),
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to revoke share links - {e}"));
+                    });
+                }
+            }
+        }
+    });
+
+    let progress = create_rw_signal(cx, None::<ImageProgress>);
+    let preview = create_rw_signal(cx, None::<ImagePreview>);
+    let queue_status = create_rw_signal(cx, None::<QueueStatus>);
+    // image whose progress is followed, reloading its metadata doesn't start another stream
+    let followed_image = create_rw_signal(cx, None::<String>);
+
+    let progress_action = create_action(cx, move |id: &String| {
+        let id = id.clone();
+        async move {
+            let Some(api) = authorized_api.get() else {
+                return;
+            };
+            let result = match api.image_progress_stream(&id).await {
+                Ok(response) => {
+                    read_progress_events(response, progress, preview, queue_status).await
+                }
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    Err(e.to_string())
+                }
+            };
+            if let Err(e) = result {
+                status_message.update(|m| {
+                    *m = Message::Error(format!("failed to follow the generation - {e}"));
+                });
+            }
+            let _ = web_util::sleep(SAMPLES_RELOAD_DELAY).await;
+            progress.update(|p| *p = None);
+            preview.update(|p| *p = None);
+            queue_status.update(|q| *q = None);
+            dummy_images_signal.update(|s| *s += 1);
+        }
+    });
+
+    create_effect(cx, move |_| {
+        if let Some(Some(metadata)) = metadata.read(cx) {
+            let is_running = matches!(metadata.status, ImageStatus::Queued | ImageStatus::Running);
+            if is_running && followed_image.get().as_ref() != Some(&metadata.id) {
+                followed_image.update(|f| *f = Some(metadata.id.clone()));
+                progress_action.dispatch(metadata.id);
+            }
+        }
+    });
+
+    let tags = create_rw_signal(cx, Vec::<String>::new());
+    let new_tag = create_rw_signal(cx, String::new());
+    create_effect(cx, move |_| {
+        if let Some(Some(metadata)) = metadata.read(cx) {
+            tags.update(|t| *t = metadata.tags);
+        }
+    });
+
+    // adds the tag when `add` is set and removes it otherwise
+    let tag_action = create_action(cx, move |(id, tag, add): &(String, String, bool)| {
+        let (id, tag, add) = (id.clone(), tag.clone(), *add);
+        async move {
+            let Some(api) = authorized_api.get() else {
+                return;
+            };
+            let result = if add {
+                api.image_tag_add(&id, &tag).await
+            } else {
+                api.image_tag_remove(&id, &tag).await
+            };
+            match result {
+                Ok(image_tags) => {
+                    tags.update(|t| *t = image_tags);
+                    if add {
+                        new_tag.update(|t| t.clear());
+                    }
+                }
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    status_message.update(|m| {
+                        *m = Message::Error(format!("failed to update tags - {e}"));
+                    });
+                }
+            }
+        }
+    });
+
     let image_id = Signal::derive(cx, move || {
         metadata
             .read(cx)
@@ -104,12 +381,53 @@ pub fn ImageView(
             } else {
                 "/icons/plus-circle.svg"
             };
-            let is_finished = if !metadata.processing {
-                view! { cx, <span class="text-airtifex-green">"✓"</span>}
-            } else {
-                view! { cx, <span class="text-airtifex-yellow">"✗"</span>}
+            let id = metadata.id.clone();
+            let revoke_id = metadata.id.clone();
+            let variations_id = metadata.id.clone();
+            let grid_id = metadata.id.clone();
+            let has_samples = !metadata.status.is_processing();
+            let tag_image_id = metadata.id.clone();
+            let tag_list = {
+                let id = metadata.id.clone();
+                move || {
+                    tags.get()
+                        .into_iter()
+                        .map(|tag| {
+                            let remove = (id.clone(), tag.clone(), false);
+                            view! { cx,
+                              <span class="badge rounded-pill bg-secondary me-1">
+                                {tag}
+                                <span
+                                  class="ms-1"
+                                  style="cursor: pointer;"
+                                  title="Remove tag"
+                                  on:click=move |_| tag_action.dispatch(remove.clone())
+                                >
+                                  "×"
+                                </span>
+                              </span>
+                            }
+                            .into_view(cx)
+                        })
+                        .collect::<Vec<_>>()
+                }
+            };
+            let is_finished = match metadata.status {
+                ImageStatus::Done => view! { cx, <span class="text-airtifex-green">"✓"</span>},
+                ImageStatus::Failed => view! { cx,
+                    <span class="text-danger">
+                        {format!("failed - {}", metadata.error.clone().unwrap_or_default())}
+                    </span>
+                },
+                ImageStatus::Cancelled => view! { cx,
+                    <span class="text-secondary">"cancelled, nobody followed the generation"</span>
+                },
+                ImageStatus::Queued | ImageStatus::Running => {
+                    view! { cx, <span class="text-airtifex-yellow">"✗"</span>}
+                }
             };
             view! { cx,
+             <div class="d-flex flex-row">
              <button
                 class="btn-btn-airtifex btn-outline rounded me-auto ms-2 mb-2"
                 on:click=move|_|is_details_open.update(|o| *o = !*o)
@@ -117,6 +435,58 @@ pub fn ImageView(
               <img class="me-2" src=icon />
               "Details"
              </button>
+             <button
+                class="btn btn-outline-lighter rounded me-2 mb-2"
+                title="Generate the image again with the same parameters and seed"
+                on:click=move |_| reproduce_action.dispatch(id.clone())
+             >
+              <img class="me-2" src="/icons/refresh-cw.svg" />
+              "Reproduce"
+             </button>
+             <button
+                class="btn btn-outline-lighter rounded me-2 mb-2"
+                title="Generate more images like this one with new seeds"
+                on:click=move |_| variations_action.dispatch(variations_id.clone())
+             >
+              <img class="me-2" src="/icons/plus-circle.svg" />
+              "Make variations"
+             </button>
+             <button
+                class="btn btn-outline-lighter rounded me-2 mb-2"
+                title="Download the samples tiled into a single image with their seeds"
+                disabled=!has_samples
+                on:click=move |_| grid_action.dispatch(grid_id.clone())
+             >
+              <img class="me-2" src="/icons/download.svg" />
+              "Download grid"
+             </button>
+             <button
+                class="btn btn-outline-lighter rounded me-2 mb-2"
+                title="Invalidate all share links of this image"
+                on:click=move |_| revoke_shares_action.dispatch(revoke_id.clone())
+             >
+              <img class="me-2" src="/icons/minus-circle.svg" />
+              "Revoke links"
+             </button>
+             </div>
+             <div class="d-flex flex-row flex-wrap align-items-center ms-2 mb-2">
+              <span class="text-secondary me-2">"Tags:"</span>
+              {tag_list}
+              <input
+                class="form-control form-control-sm w-auto"
+                placeholder="add tag..."
+                prop:value=move || new_tag.get()
+                on:keyup=move |ev: ev::KeyboardEvent| {
+                    let val = event_target_value(&ev);
+                    if ev.key() == "Enter" && !val.trim().is_empty() {
+                        tag_action.dispatch((tag_image_id.clone(), val, true));
+                    } else {
+                        new_tag.update(|t| *t = val);
+                    }
+                }
+              />
+             </div>
+             <StatusMessage message=status_message></StatusMessage>
              { if is_details_open.get() {
                  view!{ cx,
                  <div class="card bg-darker">
@@ -159,6 +529,24 @@ pub fn ImageView(
                                     <td class="fitwidth text-white">"Seed: "</td>
                                     <td class="text-airtifex-yellow text-center">{metadata.seed}</td>
                                 </tr>
+                                {metadata.source_image_id.clone().map(|source| {
+                                    let page = Page::GeneratedImageView(source.clone());
+                                    view! { cx,
+                                      <tr class="no-border">
+                                        <td class="fitwidth text-white">"Variation of: "</td>
+                                        <td
+                                          class="text-airtifex-light text-center"
+                                          style="cursor: pointer;"
+                                          on:click=move |_| {
+                                              pages::goto(cx, page.path()).expect("generated image page");
+                                              dummy_images_signal.update(|s| *s += 1);
+                                          }
+                                        >
+                                          {source}
+                                        </td>
+                                      </tr>
+                                    }
+                                })}
                             </tbody>
                         </table>
                     </div>
@@ -222,12 +610,82 @@ pub fn ImageView(
              }}
              <div class="mx-auto p-3">
                 <h2>"Generated images:"</h2>
+             <QueuePosition status=queue_status.read_only() />
+             {move || {
+                let Some(progress) = progress.get() else {
+                    return view! {cx, <></> }.into_view(cx);
+                };
+                let size = size.get();
+                let status = format!(
+                    "Sample {}/{}, step {}/{}",
+                    progress.n_sample, progress.num_samples, progress.step, progress.n_steps
+                );
+                // the latest preview is shown until the sample it belongs to is done
+                let preview = preview
+                    .get()
+                    .filter(|p| p.n_sample == progress.n_sample)
+                    .map(|p| {
+                        let src = format!("data:image/png;base64,{}", p.data);
+                        view! {cx,
+                            <img class="p-2" src=src width=size.0 height=size.1></img>
+                        }.into_view(cx)
+                    })
+                    .unwrap_or_else(|| view! {cx, <></> }.into_view(cx));
+                view! {cx,
+                    <div class="d-inline-flex flex-column">
+                        {preview}
+                        <span class="text-airtifex-yellow font-monospace px-2">{status}</span>
+                    </div>
+                }.into_view(cx)
+             }}
              {move || {
                 let size = size.get();
+                let is_done = matches!(
+                    metadata.read(cx),
+                    Some(Some(m)) if m.status == ImageStatus::Done
+                );
                 if let Some(Some(images)) = images.read(cx) {
                      images.into_iter().map(|i| {
                         let src= web_util::encode_image_base64(&i.data);
-                        view!{cx, <img class="p-2" src=src width=size.0 height=size.1></img>}.into_view(cx)
+                        let seed = i.actual_seed;
+                        let n_sample = i.n_sample;
+                        let reroll = (i.image_id.clone(), i.n_sample);
+                        let share = (i.image_id, i.n_sample);
+                        let is_rerolling = move || rerolling.get().contains(&n_sample);
+                        view!{cx,
+                            <div class="d-inline-flex flex-column">
+                                <img class="p-2" src=src width=size.0 height=size.1></img>
+                                <div class="d-flex flex-row align-items-center px-2">
+                                    <span class="text-secondary font-monospace me-auto">"Seed: "{seed}</span>
+                                    <button
+                                        class="btn btn-outline-lighter rounded"
+                                        on:click=move |_| {
+                                            let prompt = String::from(js_sys::encode_uri_component(&prompt.get()));
+                                            let path = format!("{}?seed={seed}&prompt={prompt}", Page::GenerateImage.raw_path());
+                                            pages::goto(cx, path).expect("generate image page");
+                                        }
+                                    >
+                                    <img class="me-2" src="/icons/refresh-cw.svg" />
+                                    "Reuse seed"
+                                    </button>
+                                    <button
+                                        class="btn btn-outline-lighter rounded ms-2"
+                                        title="Generate this sample again with a new seed, the other samples are kept"
+                                        disabled=move || !is_done || is_rerolling()
+                                        on:click=move |_| reroll_action.dispatch(reroll.clone())
+                                    >
+                                    {move || if is_rerolling() { "Rerolling..." } else { "Reroll" }}
+                                    </button>
+                                    <button
+                                        class="btn btn-outline-lighter rounded ms-2"
+                                        title="Copy a link that opens the image without an account"
+                                        on:click=move |_| share_action.dispatch(share.clone())
+                                    >
+                                    "Share"
+                                    </button>
+                                </div>
+                            </div>
+                        }.into_view(cx)
                     }).collect::<Vec<_>>()
                 } else {
                     vec![]
@@ -239,3 +697,66 @@ pub fn ImageView(
      }}
     }
 }
+
+/// Follows the server-sent events of an image generation until its `done` event, `preview`
+/// keeps only the latest preview and `queue_status` the place in the queue until the
+/// generation starts.
+async fn read_progress_events(
+    response: gloo_net::http::Response,
+    progress: RwSignal<Option<ImageProgress>>,
+    preview: RwSignal<Option<ImagePreview>>,
+    queue_status: RwSignal<Option<QueueStatus>>,
+) -> Result<(), String> {
+    if !response.ok() {
+        return Err(response.text().await.unwrap_or_else(|e| e.to_string()));
+    }
+    let Some(body) = response.body() else {
+        return Err("response body empty".into());
+    };
+    let body = body.unchecked_into::<wasm_streams::readable::sys::ReadableStream>();
+    let mut reader = wasm_streams::ReadableStream::from_raw(body).into_stream();
+    let mut buffer = Vec::new();
+
+    while let Some(chunk) = reader.next().await {
+        let chunk = chunk.map_err(|e| e.as_string().unwrap_or_default())?;
+        buffer.extend(
+            js_sys::Array::from(&chunk)
+                .iter()
+                .map(|v| v.as_f64().unwrap_or_default() as u8),
+        );
+        // events are separated by an empty line
+        while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+            let event = buffer.drain(..end + 2).collect::<Vec<_>>();
+            let event = String::from_utf8_lossy(&event);
+            let (mut name, mut data) = ("message", String::new());
+            for line in event.lines() {
+                if let Some(value) = line.strip_prefix("event:") {
+                    name = value.trim();
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data.push_str(value.strip_prefix(' ').unwrap_or(value));
+                }
+            }
+            match name {
+                "done" => return Ok(()),
+                "queue" => {
+                    if let Ok(status) = serde_json::from_str::<QueueStatus>(&data) {
+                        queue_status.update(|q| *q = Some(status));
+                    }
+                }
+                "started" => queue_status.update(|q| *q = None),
+                "progress" => {
+                    if let Ok(p) = serde_json::from_str::<ImageProgress>(&data) {
+                        progress.update(|progress| *progress = Some(p));
+                    }
+                }
+                "preview" => {
+                    if let Ok(p) = serde_json::from_str::<ImagePreview>(&data) {
+                        preview.update(|preview| *preview = Some(p));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Err("connection closed".into())
+}
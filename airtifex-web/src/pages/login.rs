@@ -1,6 +1,7 @@
 use crate::{
     api::{AuthorizedApi, UnauthorizedApi},
     components::{credentials::*, status_message::*},
+    i18n::t,
 };
 use airtifex_core::auth::Credentials;
 
@@ -47,8 +48,8 @@ where
                             <div class="col-md-8 mx-auto text-center py-5">
                                 <h1 class="display-5 font-monospace">"Welcome to "<span class="fw-bold"><span class="text-airtifex">"AI"</span>"rtifex"</span></h1>
                                 <CredentialsForm
-                                title = "Please login to your account"
-                                action_label = "Login"
+                                title = t(cx, "login.prompt")
+                                action_label = t(cx, "login.action")
                                 action = login_action
                                 message
                                 disabled
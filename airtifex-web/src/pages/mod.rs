@@ -7,7 +7,8 @@ pub mod users;
 
 pub use self::{chat::*, home::*, image::*, login::*, prompt::*, users::*};
 
-use crate::components::navbar::NavElement;
+use crate::{components::navbar::NavElement, i18n::Lang};
+use airtifex_core::{api_response::ErrorCode, user::LandingPage};
 
 use gloo_storage::{LocalStorage, Storage};
 use leptos::*;
@@ -51,6 +52,14 @@ impl Page {
             Self::Login => Self::Login,
         }
     }
+    /// The entry of a navbar sub group that represents this page.
+    pub fn nav_page(&self) -> Self {
+        match self {
+            Self::Prompt => Self::PromptGenerate,
+            Self::PromptView(_) => Self::PromptList,
+            page => page.clone(),
+        }
+    }
     pub fn raw_path(&self) -> &'static str {
         match self {
             Self::Home => "/",
@@ -106,41 +115,47 @@ impl Page {
         }
     }
 
-    pub fn title(&self) -> &'static str {
+    /// Id of the title of the page in the string catalog.
+    pub fn title_id(&self) -> &'static str {
         match self {
-            Self::Home => "Home",
+            Self::Home => "page.home.title",
             Self::Users
             | Self::UserAdd
             | Self::UserEdit(_)
             | Self::UserPasswordChange(_)
-            | Self::UserProfile => "Users",
-            Self::Chat | Self::ChatView(_) => "Chat",
-            Self::Prompt => "Prompt",
-            Self::PromptGenerate => "Generate Prompt",
-            Self::PromptList => "Prompt History",
-            Self::PromptView(_) => "Prompt",
-            Self::Login => "Login",
-            Self::GenerateImage | Self::GeneratedImageView(_) => "Generate Image",
+            | Self::UserProfile => "page.users.title",
+            Self::Chat | Self::ChatView(_) => "page.chat.title",
+            Self::Prompt => "page.prompt.title",
+            Self::PromptGenerate => "page.prompt_generate.title",
+            Self::PromptList => "page.prompt_list.title",
+            Self::PromptView(_) => "page.prompt.title",
+            Self::Login => "page.login.title",
+            Self::GenerateImage | Self::GeneratedImageView(_) => "page.generate_image.title",
         }
     }
 
-    pub fn nav_display(&self) -> &'static str {
+    /// Id of the navbar label of the page in the string catalog.
+    pub fn nav_id(&self) -> &'static str {
         match self {
-            Self::Home => "Home",
+            Self::Home => "nav.home",
             Self::Users
             | Self::UserAdd
             | Self::UserEdit(_)
             | Self::UserPasswordChange(_)
-            | Self::UserProfile => "Users",
-            Self::Chat | Self::ChatView(_) => "Chat",
-            Self::Prompt | Self::PromptView(_) => "Prompt",
-            Self::PromptGenerate => "generate",
-            Self::PromptList => "history",
-            Self::Login => "Login",
-            Self::GenerateImage | Self::GeneratedImageView(_) => "Generate Image",
+            | Self::UserProfile => "nav.users",
+            Self::Chat | Self::ChatView(_) => "nav.chat",
+            Self::Prompt | Self::PromptView(_) => "nav.prompt",
+            Self::PromptGenerate => "nav.prompt_generate",
+            Self::PromptList => "nav.prompt_list",
+            Self::Login => "nav.login",
+            Self::GenerateImage | Self::GeneratedImageView(_) => "nav.generate_image",
         }
     }
 
+    pub fn nav_display(&self, lang: Lang) -> &'static str {
+        lang.t(self.nav_id())
+    }
+
     pub fn main_user_pages() -> &'static [NavElement] {
         &[
             NavElement::Main(Self::Home),
@@ -161,6 +176,17 @@ impl Page {
     }
 }
 
+impl From<LandingPage> for Page {
+    fn from(page: LandingPage) -> Self {
+        match page {
+            LandingPage::Home => Self::Home,
+            LandingPage::Chat => Self::Chat,
+            LandingPage::Prompt => Self::PromptGenerate,
+            LandingPage::Image => Self::GenerateImage,
+        }
+    }
+}
+
 impl AsRef<str> for Page {
     fn as_ref(&self) -> &str {
         self.raw_path()
@@ -206,12 +232,14 @@ impl PageStack {
 
 pub fn goto_login_if_expired(
     cx: Scope,
-    e: impl AsRef<str>,
+    e: &crate::api::Error,
     api: RwSignal<Option<crate::api::AuthorizedApi>>,
 ) {
     use leptos_router::*;
 
-    if e.as_ref().contains("ExpiredSignature") {
+    // expired access tokens are refreshed by the api, the session only ends once the refresh
+    // token is rejected as well
+    if e.code() == Some(ErrorCode::Unauthorized) {
         api.update(|a| *a = None);
         let navigate = use_navigate(cx);
         navigate(Page::Login.raw_path(), Default::default()).expect("login page");
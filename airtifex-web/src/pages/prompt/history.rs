@@ -1,5 +1,7 @@
 use crate::{api, components::status_message::*, pages, web_util, Page, PageStack};
-use airtifex_core::llm::PromptInspect;
+use airtifex_core::llm::{
+    PromptBundle, PromptDuplicateAction, PromptImportQuery, PromptInspect, PromptListQuery,
+};
 
 use leptos::*;
 
@@ -11,21 +13,31 @@ pub fn PromptList(
 ) -> impl IntoView {
     let status_message = create_rw_signal(cx, Message::Empty);
     let remove_prompt_id = create_rw_signal(cx, None);
+    let favorites_only = create_rw_signal(cx, false);
+    let on_duplicate = create_rw_signal(cx, PromptDuplicateAction::Skip);
+    let prompts = create_rw_signal(cx, Vec::<PromptInspect>::new());
+    let dragged = create_rw_signal(cx, None::<usize>);
+    let dummy_prompts_signal = create_rw_signal::<u32>(cx, 1);
 
-    let prompts = create_resource(
+    let fetched_prompts = create_resource(
         cx,
-        move || (),
-        move |_| async move {
+        move || (favorites_only.get(), dummy_prompts_signal.get()),
+        move |(favorites_only, _)| async move {
             match authorized_api.get() {
-                Some(api) => match api.prompt_list().await {
-                    Ok(prompts) => prompts,
-                    Err(e) => {
-                        let e = e.to_string();
-                        pages::goto_login_if_expired(cx, &e, authorized_api);
-                        status_message.update(|msg| *msg = Message::Error(e));
-                        vec![]
+                Some(api) => {
+                    let query = PromptListQuery {
+                        favorites_only: Some(favorites_only),
+                    };
+                    match api.prompt_list(query).await {
+                        Ok(prompts) => prompts,
+                        Err(e) => {
+                            pages::goto_login_if_expired(cx, &e, authorized_api);
+                            let e = e.to_string();
+                            status_message.update(|msg| *msg = Message::Error(e));
+                            vec![]
+                        }
                     }
-                },
+                }
                 None => {
                     status_message
                         .update(|msg| *msg = Message::Error("connection to API failed".into()));
@@ -35,15 +47,185 @@ pub fn PromptList(
         },
     );
 
+    create_effect(cx, move |_| {
+        if let Some(fetched) = fetched_prompts.read(cx) {
+            prompts.update(|p| *p = fetched);
+        }
+    });
+
+    let reorder_action = create_action(cx, move |ids: &Vec<String>| {
+        let ids = ids.clone();
+        async move {
+            let Some(api) = authorized_api.get() else {
+                return;
+            };
+            if let Err(e) = api.prompt_reorder(ids).await {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                let e = e.to_string();
+                status_message.update(|msg| *msg = Message::Error(e));
+                // show the order that is actually stored
+                dummy_prompts_signal.update(|s| *s += 1);
+            }
+        }
+    });
+
+    let favorite_action = create_action(cx, move |(id, is_favorite): &(String, bool)| {
+        let (id, is_favorite) = (id.clone(), *is_favorite);
+        async move {
+            let Some(api) = authorized_api.get() else {
+                return;
+            };
+            match api.prompt_set_favorite(&id, is_favorite).await {
+                Ok(()) => prompts.update(|prompts| {
+                    if let Some(prompt) = prompts.iter_mut().find(|p| p.id == id) {
+                        prompt.is_favorite = is_favorite;
+                    }
+                    if favorites_only.get() {
+                        prompts.retain(|p| p.is_favorite);
+                    }
+                    sort_favorites_first(prompts);
+                }),
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    status_message.update(|msg| *msg = Message::Error(e));
+                }
+            }
+        }
+    });
+
+    let export_action = create_action(cx, move |_| async move {
+        let Some(api) = authorized_api.get() else {
+            status_message.update(|msg| *msg = Message::Error("failed to connect to API".into()));
+            return;
+        };
+        let result = match api.prompt_export().await {
+            Ok(bundle) => serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string()),
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                Err(e.to_string())
+            }
+        };
+        let result = result.and_then(|json| {
+            web_util::download_text("prompts.json", "application/json", &json)
+                .map_err(|e| e.as_string().unwrap_or_default())
+        });
+        if let Err(e) = result {
+            status_message
+                .update(|msg| *msg = Message::Error(format!("failed to export prompts - {e}")));
+        }
+    });
+
+    let import_action = create_action(cx, move |file: &web_sys::File| {
+        let file = file.clone();
+        async move {
+            let Some(api) = authorized_api.get() else {
+                status_message
+                    .update(|msg| *msg = Message::Error("failed to connect to API".into()));
+                return;
+            };
+            let bundle = match web_util::read_file(file).await {
+                Ok(data) => serde_json::from_slice::<PromptBundle>(&data)
+                    .map_err(|e| format!("invalid prompt bundle - {e}")),
+                Err(e) => Err(format!(
+                    "failed to read file - {}",
+                    e.as_string().unwrap_or_default()
+                )),
+            };
+            let bundle = match bundle {
+                Ok(bundle) => bundle,
+                Err(e) => {
+                    status_message.update(|msg| *msg = Message::Error(e));
+                    return;
+                }
+            };
+            let query = PromptImportQuery {
+                on_duplicate: Some(on_duplicate.get()),
+            };
+            match api.prompt_import(&bundle, query).await {
+                Ok(imported) => {
+                    status_message.update(|msg| {
+                        *msg = Message::Success(format!(
+                            "imported prompts: {} created, {} merged, {} skipped",
+                            imported.created, imported.merged, imported.skipped
+                        ))
+                    });
+                    dummy_prompts_signal.update(|s| *s += 1);
+                }
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    status_message.update(|msg| {
+                        *msg = Message::Error(format!("failed to import prompts - {e}"))
+                    });
+                }
+            }
+        }
+    });
+
     view! {cx, {move || {
       page_stack.update(|p| p.push(Page::PromptList));
       view! { cx,
         <main class="bg-dark text-white d-flex flex-column p-3 overflow-auto" >
             <div class="card bg-darker">
                 <div class="card-body d-flex flex-column">
+                <div class="d-flex flex-wrap align-items-center mb-3">
+                  <div class="form-check form-switch me-auto">
+                    <input
+                      class="form-check-input"
+                      type="checkbox"
+                      id="favoritesOnlySwitch"
+                      prop:checked={move || favorites_only.get()}
+                      on:input=move |_| favorites_only.update(|v| *v = !*v)
+                    />
+                    <label class="form-check-label" for="favoritesOnlySwitch">"Favorites only"</label>
+                  </div>
+                  <div class="input-group w-auto">
+                    <label class="input-group-text" for="duplicatePromptSelector">"Duplicates"</label>
+                    <select
+                      class="form-select"
+                      id="duplicatePromptSelector"
+                      title="What happens to imported prompts that are already saved"
+                      on:change=move |ev| {
+                          let action = match event_target_value(&ev).as_str() {
+                              "merge" => PromptDuplicateAction::Merge,
+                              _ => PromptDuplicateAction::Skip,
+                          };
+                          on_duplicate.update(|a| *a = action);
+                      }
+                    >
+                      <option value="skip" selected>"Skip"</option>
+                      <option value="merge">"Merge"</option>
+                    </select>
+                    <label class="btn btn-outline-lighter" for="promptImportInput">
+                      <img src="/icons/upload.svg" class="me-2" />
+                      "Import"
+                    </label>
+                    <button
+                      class="btn btn-outline-lighter"
+                      on:click=move |_| export_action.dispatch(())
+                    >
+                      <img src="/icons/download.svg" class="me-2" />
+                      "Export"
+                    </button>
+                  </div>
+                  <input
+                    id="promptImportInput"
+                    class="hidden"
+                    type="file"
+                    accept="application/json,.json"
+                    on:change=move |ev| {
+                        if let Some(file) = web_util::extract_file_from_html_input(ev) {
+                            import_action.dispatch(file);
+                        }
+                    }
+                  />
+                </div>
+                <StatusMessage message=status_message />
                 <table class="table table-hover table-striped table-responsive text-white">
                     <thead>
                     <tr>
+                    <th scope="col">""</th>
                     <th scope="col">"Prompt"</th>
                     <th scope="col">"Response"</th>
                     <th class="text-center" scope="col">"Model"</th>
@@ -53,8 +235,18 @@ pub fn PromptList(
                     </thead>
                     <tbody>
                     {
-                    prompts.read(cx).unwrap_or_default().into_iter().map(|prompt| {
-                        view!{cx, <PromptListEntry prompt remove_prompt_id />}.into_view(cx)
+                    move || prompts.get().into_iter().enumerate().map(|(index, prompt)| {
+                        view!{cx,
+                          <PromptListEntry
+                            prompt
+                            index
+                            prompts
+                            dragged
+                            remove_prompt_id
+                            reorder_action
+                            favorite_action
+                          />
+                        }.into_view(cx)
                     }).collect::<Vec<_>>()
                     }
                     </tbody>
@@ -67,14 +259,36 @@ pub fn PromptList(
     .into_view(cx)
 }
 
+/// Keeps favorites above the other prompts without changing the order within each group.
+fn sort_favorites_first(prompts: &mut [PromptInspect]) {
+    prompts.sort_by_key(|p| !p.is_favorite);
+}
+
+/// Moves the prompt at `from` to `to`, returns the ids in their new order.
+fn move_prompt(prompts: &mut Vec<PromptInspect>, from: usize, to: usize) -> Vec<String> {
+    if from < prompts.len() && to < prompts.len() {
+        let prompt = prompts.remove(from);
+        prompts.insert(to, prompt);
+        sort_favorites_first(prompts);
+    }
+    prompts.iter().map(|p| p.id.clone()).collect()
+}
+
 #[component]
 fn PromptListEntry(
     cx: Scope,
     prompt: PromptInspect,
+    index: usize,
+    prompts: RwSignal<Vec<PromptInspect>>,
+    dragged: RwSignal<Option<usize>>,
     remove_prompt_id: RwSignal<Option<String>>,
+    reorder_action: Action<Vec<String>, ()>,
+    favorite_action: Action<(String, bool), ()>,
 ) -> impl IntoView {
     let open_href = format!("/prompt/{}", prompt.id);
     let open_href2 = open_href.clone();
+    let favorite_id = prompt.id.clone();
+    let is_favorite = prompt.is_favorite;
 
     let window_size = web_util::WindowSize::signal(cx).expect("window size");
     let char_count = Signal::derive(cx, move || {
@@ -84,7 +298,39 @@ fn PromptListEntry(
 
     view! {cx, <tr
                 class="text-white no-border align-middle"
+                class:opacity-50=move || dragged.get() == Some(index)
+                style="cursor: move;"
+                draggable="true"
+                on:dragstart=move |ev: ev::DragEvent| {
+                    // firefox only starts dragging when some data is set
+                    if let Some(data) = ev.data_transfer() {
+                        data.set_effect_allowed("move");
+                        let _ = data.set_data("text/plain", &index.to_string());
+                    }
+                    dragged.update(|d| *d = Some(index));
+                }
+                on:dragover=move |ev: ev::DragEvent| ev.prevent_default()
+                on:drop=move |ev: ev::DragEvent| {
+                    ev.prevent_default();
+                    if let Some(from) = dragged.get().filter(|from| *from != index) {
+                        let mut ids = vec![];
+                        prompts.update(|p| ids = move_prompt(p, from, index));
+                        reorder_action.dispatch(ids);
+                    }
+                }
+                on:dragend=move |_| dragged.update(|d| *d = None)
               >
+                  <td class="fitwidth">
+                      <button
+                        class="btn btn-sm"
+                        class:text-airtifex-yellow=is_favorite
+                        class:text-secondary={!is_favorite}
+                        title={if is_favorite { "Remove from favorites" } else { "Add to favorites" }}
+                        on:click=move |_| favorite_action.dispatch((favorite_id.clone(), !is_favorite))
+                      >
+                          {if is_favorite { "★" } else { "☆" }}
+                      </button>
+                  </td>
                   <td
                     style="cursor: pointer;"
                     on:click=move |_| {
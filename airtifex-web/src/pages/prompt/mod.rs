@@ -7,12 +7,17 @@ pub use view::*;
 use crate::{
     api,
     components::{loading::*, status_message::*},
+    i18n::t,
     inference::read_inference_stream,
-    web_util, Page, PageStack,
+    pages, web_util, Page, PageStack,
+};
+use airtifex_core::llm::{
+    template_variables, OneshotInferenceRequest, PromptGenerateRequest, PromptInspect,
 };
-use airtifex_core::llm::OneshotInferenceRequest;
 
 use leptos::*;
+use leptos_router::*;
+use std::collections::HashMap;
 
 #[component]
 pub fn PromptGenerate(
@@ -21,6 +26,7 @@ pub fn PromptGenerate(
     page_stack: RwSignal<PageStack>,
 ) -> impl IntoView {
     let status_message = create_rw_signal(cx, Message::Empty);
+    let template_id = use_query_map(cx).get().get("template").cloned();
 
     let selected_model = create_rw_signal(cx, String::new());
 
@@ -34,6 +40,42 @@ pub fn PromptGenerate(
     let play_back_tokens = create_rw_signal(cx, true);
     let save = create_rw_signal(cx, true);
     let response_view = create_rw_signal(cx, String::new());
+    let template = create_rw_signal(cx, None::<PromptInspect>);
+    let variable_values = create_rw_signal(cx, HashMap::<String, String>::new());
+
+    // a saved template is generated again as long as it wasn't edited
+    let unchanged_template = move || template.get().filter(|t| t.prompt == prompt.get());
+    let variables = Signal::derive(cx, move || match unchanged_template() {
+        Some(template) => template.variables,
+        None => template_variables(&prompt.get()),
+    });
+
+    let saved_template = create_resource(
+        cx,
+        move || template_id.clone(),
+        move |id| async move {
+            match (authorized_api.get(), id) {
+                (Some(api), Some(id)) => match api.prompt_inspect(&id).await {
+                    Ok(prompt) => Some(prompt),
+                    Err(e) => {
+                        pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
+                        status_message.update(|msg| *msg = Message::Error(e));
+                        None
+                    }
+                },
+                _ => None,
+            }
+        },
+    );
+
+    create_effect(cx, move |_| {
+        if let Some(Some(saved)) = saved_template.read(cx) {
+            prompt.update(|p| *p = saved.prompt.clone());
+            selected_model.update(|m| *m = saved.model.clone());
+            template.update(|t| *t = Some(saved));
+        }
+    });
 
     let is_inference_running = create_rw_signal(cx, false);
     let should_cancel = create_rw_signal(cx, false);
@@ -49,19 +91,44 @@ pub fn PromptGenerate(
             }
             is_inference_running.update(|r| *r = true);
 
-            let request = OneshotInferenceRequest {
-                model: selected_model.get(),
-                num_predict: num_predict.get(),
-                prompt: prompt.get(),
-                n_batch: n_batch.get(),
-                top_k: top_k.get(),
-                top_p: top_p.get(),
-                repeat_penalty: repeat_penalty.get(),
-                temp: temp.get(),
-                play_back_tokens: play_back_tokens.get(),
-                save: save.get(),
+            let variables = variables.get();
+            let values = variable_values.with(|values| {
+                values
+                    .iter()
+                    .filter(|(name, _)| variables.contains(name))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect::<HashMap<_, _>>()
+            });
+
+            let resp = if let Some(template) = unchanged_template() {
+                let request = PromptGenerateRequest {
+                    values,
+                    play_back_tokens: play_back_tokens.get(),
+                    save: save.get(),
+                    no_cache: false,
+                };
+                api.prompt_generate(request, &template.id).await
+            } else {
+                let request = OneshotInferenceRequest {
+                    model: selected_model.get(),
+                    num_predict: num_predict.get(),
+                    prompt: prompt.get(),
+                    n_batch: n_batch.get(),
+                    top_k: top_k.get(),
+                    top_p: top_p.get(),
+                    repeat_penalty: repeat_penalty.get(),
+                    temp: temp.get(),
+                    mirostat: None,
+                    mirostat_tau: None,
+                    mirostat_eta: None,
+                    variables,
+                    values,
+                    play_back_tokens: play_back_tokens.get(),
+                    save: save.get(),
+                    no_cache: false,
+                };
+                api.oneshot_inference(request).await
             };
-            let resp = api.oneshot_inference(request).await;
             read_inference_stream(
                 cx,
                 resp,
@@ -98,7 +165,7 @@ pub fn PromptGenerate(
         view!{cx,
            <main class="bg-dark text-white d-flex flex-column p-1 overflow-auto" >
                  <div class="d-flex">
-                     <h1 class="display-5 p-1">{Page::Prompt.title()}</h1>
+                     <h1 class="display-5 p-1">{move || t(cx, Page::Prompt.title_id())}</h1>
                  </div>
                  <div class=flex>
                      <div class="col-lg-6 col-sm-12 px-3 pb-3">
@@ -110,7 +177,8 @@ pub fn PromptGenerate(
                      <div class="col-lg-6 col-sm-12 px-3">
                       <Prompt
                           authorized_api selected_model status_message dispatch_inference_action
-                          num_predict prompt n_batch top_k top_p repeat_penalty temp should_cancel
+                          num_predict prompt variables variable_values n_batch top_k top_p
+                          repeat_penalty temp should_cancel
                           is_inference_running play_back_tokens save
                       />
                      </div>
@@ -129,6 +197,8 @@ fn Prompt<F>(
     selected_model: RwSignal<String>,
     num_predict: RwSignal<Option<usize>>,
     prompt: RwSignal<String>,
+    variables: Signal<Vec<String>>,
+    variable_values: RwSignal<HashMap<String, String>>,
     n_batch: RwSignal<Option<usize>>,
     top_k: RwSignal<Option<usize>>,
     top_p: RwSignal<Option<f32>>,
@@ -177,7 +247,7 @@ where
 
     create_effect(cx, move |_| {
         if let Some(models) = models.read(cx) {
-            if let Some(first) = models.first() {
+            if let Some(first) = models.first().filter(|_| selected_model.get().is_empty()) {
                 selected_model.update(|m| *m = first.name.clone());
             }
         }
@@ -209,11 +279,38 @@ where
                             }
                         }
                     }
+                    prop:value=move || prompt.get()
                   >
                   {prompt}
                   </textarea>
               </div>
 
+              { move || {
+                  variables.get().into_iter().map(|name| {
+                      let value = {
+                          let name = name.clone();
+                          move || variable_values.with(|v| v.get(&name).cloned().unwrap_or_default())
+                      };
+                      let label = name.clone();
+                      view!{ cx,
+                      <div class="input-group mb-3">
+                          <label class="input-group-text">{label}</label>
+                          <input
+                            class = "form-control"
+                            placeholder = "..."
+                            prop:value=value
+                            on:keyup = move |ev: ev::KeyboardEvent| {
+                                let val = event_target_value(&ev);
+                                variable_values.update(|v| {
+                                    v.insert(name.clone(), val);
+                                });
+                            }
+                          />
+                      </div>
+                      }
+                  }).collect::<Vec<_>>()
+              }}
+
               <div class="input-group mb-3">
                 <label class="input-group-text">"Model"</label>
                 <select
@@ -360,7 +457,10 @@ where
             <div class="d-flex flex-row mt-3">
               <button
                   class="btn btn-outline-lighter rounded ms-auto me-1"
-                  prop:disabled = move || prompt.get().is_empty()
+                  prop:disabled = move || {
+                      prompt.get().is_empty()
+                          || variable_values.with(|v| variables.get().iter().any(|name| !v.contains_key(name)))
+                  }
                   on:click=move |_| dispatch_inference_action()
               >
               <img class="me-2" src="/icons/send.svg" />
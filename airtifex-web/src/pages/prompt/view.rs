@@ -33,8 +33,8 @@ pub fn PromptView(
                 (Some(api), Some(id)) => match api.prompt_inspect(&id).await {
                     Ok(prompt) => Some(prompt),
                     Err(e) => {
-                        let e = e.to_string();
                         pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
                         status_message.update(|msg| *msg = Message::Error(e));
                         None
                     }
@@ -148,6 +148,22 @@ pub fn PromptView(
                                     <pre>
                                         {prompt.prompt}
                                     </pre>
+                                    {
+                                        if prompt.variables.is_empty() {
+                                            view!{cx, <></>}.into_view(cx)
+                                        } else {
+                                            let generate_href = format!("{}?template={}", Page::PromptGenerate.raw_path(), prompt.id);
+                                            view!{cx,
+                                            <button
+                                                class="btn btn-outline-lighter rounded"
+                                                on:click=move |_| pages::goto(cx, &generate_href).expect("prompt generate page")
+                                            >
+                                                <img class="me-2" src="/icons/send.svg" />
+                                                "Generate with other values"
+                                            </button>
+                                            }.into_view(cx)
+                                        }
+                                    }
                                 </div>
                             </div>
                             <div class=card_classes>
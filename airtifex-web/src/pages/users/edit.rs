@@ -49,8 +49,8 @@ pub fn UserEdit(
                         Some(user)
                     }
                     Err(e) => {
-                        let e = e.to_string();
                         pages::goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
                         users_message.update(|msg| *msg = Message::Error(e));
                         None
                     }
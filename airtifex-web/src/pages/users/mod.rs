@@ -1,11 +1,16 @@
 use crate::{
     api,
     components::{list_page_control::*, modal::*, status_message::*, users::list_entry::*},
-    pages::goto_login_if_expired,
+    i18n::t,
+    pages::{goto, goto_login_if_expired},
+    preferences::use_preferences,
     Page,
 };
 
-use airtifex_core::user::ListQuery;
+use airtifex_core::{
+    admin::ImpersonationResponse,
+    user::{AccountType, ListOrder, ListQuery, UserListPage},
+};
 use leptos::*;
 
 pub mod add;
@@ -19,46 +24,99 @@ pub use password_change::*;
 pub use profile::*;
 
 #[component]
-pub fn Users(
+pub fn Users<F>(
     cx: Scope,
     authorized_api: RwSignal<Option<api::AuthorizedApi>>,
     users_message: RwSignal<Message>,
-) -> impl IntoView {
+    on_impersonate: F,
+) -> impl IntoView
+where
+    F: Fn(ImpersonationResponse) + 'static + Copy,
+{
     let current_list_page = create_rw_signal::<u32>(cx, 1);
-    let page_size = create_rw_signal::<usize>(cx, 25);
+    let items_per_page = use_preferences(cx).get().items_per_page;
+    let page_size = create_rw_signal(cx, items_per_page.map_or(25, |n| n as usize));
     let remove_user = create_rw_signal(cx, None::<String>);
 
+    let search = create_rw_signal(cx, String::new());
+    let account_type = create_rw_signal(cx, None::<AccountType>);
+    let order_by = create_rw_signal(cx, None::<ListOrder>);
+    let descending = create_rw_signal(cx, false);
+
     let users = create_resource(
         cx,
-        move || current_list_page.get(),
-        move |_current_list_page| async move {
+        move || {
+            (
+                current_list_page.get(),
+                search.get(),
+                account_type.get(),
+                order_by.get(),
+                descending.get(),
+            )
+        },
+        move |(current_list_page, search, account_type, order_by, descending)| async move {
             let query = ListQuery {
-                page: Some(current_list_page.get()),
+                page: Some(current_list_page),
                 page_size: Some(page_size.get() as u32),
-                order_by: None,
+                order_by,
+                descending: Some(descending),
+                search: Some(search).filter(|s| !s.trim().is_empty()),
+                account_type,
             };
             match authorized_api.get() {
                 Some(api) => match api.user_list(query).await {
                     Ok(users) => users,
                     Err(e) => {
-                        let e = e.to_string();
                         goto_login_if_expired(cx, &e, authorized_api);
+                        let e = e.to_string();
                         users_message.update(|msg| *msg = Message::Error(e));
-                        vec![]
+                        UserListPage::default()
                     }
                 },
                 None => {
                     users_message
                         .update(|msg| *msg = Message::Error("connection to API failed".into()));
-                    vec![]
+                    UserListPage::default()
                 }
             }
         },
     );
     let elem_count = Signal::derive(cx, move || {
-        users.read(cx).map(|users| users.len()).unwrap_or_default()
+        users
+            .read(cx)
+            .map(|page| page.users.len())
+            .unwrap_or_default()
+    });
+    let total = Signal::derive(cx, move || {
+        users
+            .read(cx)
+            .map(|page| page.total as usize)
+            .unwrap_or_default()
     });
 
+    // clicking the column the list is sorted by reverses the order
+    let sort_by = move |column: ListOrder| {
+        if order_by.get() == Some(column) {
+            descending.update(|d| *d = !*d);
+        } else {
+            order_by.set(Some(column));
+            descending.set(false);
+        }
+        current_list_page.set(1);
+    };
+    let sortable_header = move |column: ListOrder, title: &'static str| {
+        let arrow = move || match (order_by.get() == Some(column), descending.get()) {
+            (true, false) => " ▲",
+            (true, true) => " ▼",
+            (false, _) => "",
+        };
+        view! { cx,
+          <th scope="col" style="cursor: pointer;" on:click=move |_| sort_by(column)>
+            {title}{arrow}
+          </th>
+        }
+    };
+
     let remove_user_action = create_action(cx, move |username: &String| {
         let username = username.clone();
         async move {
@@ -85,6 +143,29 @@ pub fn Users(
         }
     });
 
+    let impersonate_action = create_action(cx, move |username: &String| {
+        let username = username.clone();
+        async move {
+            let Some(api) = authorized_api.get() else {
+                users_message.update(|m| {
+                    *m = Message::Error("failed to connect to API".into());
+                });
+                return;
+            };
+            match api.user_impersonate(&username).await {
+                Ok(response) => {
+                    on_impersonate(response);
+                    if let Err(e) = goto(cx, Page::Home.raw_path()) {
+                        log::error!("failed to open the home page - {e}");
+                    }
+                }
+                Err(err) => users_message.update(|m| {
+                    *m = Message::Error(format!("failed to impersonate {username} - {err}"));
+                }),
+            }
+        }
+    });
+
     let dispatch_remove_user =
         move || remove_user_action.dispatch(remove_user.get().unwrap_or_default());
 
@@ -105,7 +186,7 @@ pub fn Users(
             view!{ cx,
                <main class="bg-dark text-white d-flex flex-column p-1 pt-3" >
                  <div class="d-flex pb-3">
-                     <h1 class="display-5 p-1">{Page::Users.title()}</h1>
+                     <h1 class="display-5 p-1">{move || t(cx, Page::Users.title_id())}</h1>
                  </div>
                  <div class="btn-toolbar mx-3">
                      <a href="/users/add">
@@ -115,35 +196,60 @@ pub fn Users(
                          </button>
                      </a>
                  </div>
+                 <div class="d-flex flex-row mx-3 mt-3">
+                     <input
+                       type="search"
+                       class="form-control me-2"
+                       placeholder="Search by username or email"
+                       prop:value=move || search.get()
+                       on:input=move |ev| {
+                           search.set(event_target_value(&ev));
+                           current_list_page.set(1);
+                       }
+                     />
+                     <select
+                       class="form-select w-auto"
+                       on:change=move |ev| {
+                           account_type.set(AccountType::parse_str(event_target_value(&ev)));
+                           current_list_page.set(1);
+                       }
+                     >
+                       <option value="" selected=true>"All account types"</option>
+                       <option value="admin">"admin"</option>
+                       <option value="user">"user"</option>
+                       <option value="service">"service"</option>
+                     </select>
+                 </div>
                  <StatusMessage message=users_message/>
                  <div>
                  <Suspense fallback=move || view! {cx, <p> "Loading users..."</p> }>
                  { move || {
                     let users = users.read(cx);
                     {match users {
-                      Some(users) => {
+                      Some(page) => {
                           view!{cx,
                         <div class="card bg-darker m-3">
                             <div class="card-body">
                                 <table class="table table-hover table-striped table-responsive text-white">
                                   <thead>
                                   <tr class="align-middle">
-                                    <th scope="col">"Username"</th>
-                                    <th scope="col">"Email"</th>
-                                    <th scope="col">"Account type"</th>
-                                    <th scope="col">"Registration date"</th>
+                                    {sortable_header(ListOrder::Username, "Username")}
+                                    {sortable_header(ListOrder::Email, "Email")}
+                                    {sortable_header(ListOrder::AccountType, "Account type")}
+                                    {sortable_header(ListOrder::RegistrationDate, "Registration date")}
+                                    {sortable_header(ListOrder::LastLogin, "Last login")}
                                     <th scope="col" class="col-2"></th>
                                   </tr>
                                   </thead>
                                   <tbody>
                                   {
-                                  users.into_iter().map(|user| {
-                                      view!{cx, <UserListEntry user remove_user=remove_user.write_only()></UserListEntry>}
+                                  page.users.into_iter().map(|user| {
+                                      view!{cx, <UserListEntry authorized_api user remove_user=remove_user.write_only() impersonate=impersonate_action></UserListEntry>}
                                   }).collect::<Vec<_>>()
                                   }
                                   </tbody>
                                 </table>
-                                <ListPageControl current_list_page elem_count page_size=page_size.read_only() />
+                                <ListPageControl current_list_page elem_count page_size=page_size.read_only() total />
                             </div>
                         </div>
                                   }.into_view(cx)
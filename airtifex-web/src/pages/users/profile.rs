@@ -1,14 +1,77 @@
-use crate::{pages, Page, PageStack};
-use airtifex_core::user::AuthenticatedUser;
+use crate::{
+    api,
+    components::{avatar::*, status_message::*},
+    i18n::t,
+    pages,
+    preferences::use_preferences,
+    web_util, Page, PageStack,
+};
+use airtifex_core::user::{AuthenticatedUser, LandingPage, UiTheme};
 
 use leptos::*;
 
 #[component]
 pub fn UserProfile(
     cx: Scope,
+    authorized_api: RwSignal<Option<api::AuthorizedApi>>,
     page_stack: RwSignal<PageStack>,
     user_info: ReadSignal<Option<AuthenticatedUser>>,
+    avatar: RwSignal<Option<String>>,
 ) -> impl IntoView {
+    let profile_message = create_rw_signal(cx, Message::Empty);
+    let preferences = use_preferences(cx);
+
+    let upload_avatar_action = create_action(cx, move |file: &web_sys::File| {
+        let file = file.clone();
+        async move {
+            let Some(api) = authorized_api.get() else {
+                profile_message.update(|m| *m = Message::Error("failed to connect to API".into()));
+                return;
+            };
+            let username = user_info
+                .get()
+                .map(|user| user.username)
+                .unwrap_or_default();
+            let result = match api.avatar_upload(file).await {
+                Ok(_) => api.user_avatar(&username).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(new_avatar) => {
+                    avatar.update(|a| *a = new_avatar);
+                    profile_message
+                        .update(|m| *m = Message::Success("Successfully changed avatar".into()));
+                }
+                Err(e) => {
+                    pages::goto_login_if_expired(cx, &e, authorized_api);
+                    let e = e.to_string();
+                    profile_message
+                        .update(|m| *m = Message::Error(format!("failed to change avatar - {e}")));
+                }
+            }
+        }
+    });
+
+    let remove_avatar_action = create_action(cx, move |_: &()| async move {
+        let Some(api) = authorized_api.get() else {
+            profile_message.update(|m| *m = Message::Error("failed to connect to API".into()));
+            return;
+        };
+        match api.avatar_remove().await {
+            Ok(_) => {
+                avatar.update(|a| *a = None);
+                profile_message
+                    .update(|m| *m = Message::Success("Successfully removed avatar".into()));
+            }
+            Err(e) => {
+                pages::goto_login_if_expired(cx, &e, authorized_api);
+                let e = e.to_string();
+                profile_message
+                    .update(|m| *m = Message::Error(format!("failed to remove avatar - {e}")));
+            }
+        }
+    });
+
     view! { cx,
       {move || {
         match user_info.get() {
@@ -27,6 +90,38 @@ pub fn UserProfile(
                                  </button>
                              </a>
                          </div>
+                         <div class="card bg-darker m-3">
+                             <div class="card-body d-flex align-items-center">
+                                <Avatar username=user.username.clone() src=avatar.read_only().into() size=96 />
+                                <div class="btn-toolbar ms-3">
+                                    <label class="btn btn-outline-lighter rounded me-2" for="avatarInput">
+                                        <img class="me-2" src="/icons/image.svg" />
+                                        "Upload avatar"
+                                    </label>
+                                    <input
+                                        id="avatarInput"
+                                        class="hidden"
+                                        type="file"
+                                        accept="image/png,image/jpeg,image/gif,image/bmp"
+                                        on:change=move |ev| {
+                                            if let Some(file) = web_util::extract_file_from_html_input(ev) {
+                                                upload_avatar_action.dispatch(file);
+                                            }
+                                        }
+                                    />
+                                    {move || avatar.get().is_some().then(|| view!{cx,
+                                        <button
+                                            class="btn btn-outline-lighter rounded"
+                                            on:click=move |_| remove_avatar_action.dispatch(())
+                                        >
+                                            <img class="me-2" src="/icons/x.svg" />
+                                            "Remove avatar"
+                                        </button>
+                                    })}
+                                </div>
+                             </div>
+                         </div>
+                         <StatusMessage message=profile_message></StatusMessage>
                          <div class="card bg-darker m-3">
                              <div class="card-body">
                                 <table class="table table-hover text-white mb-0">
@@ -58,6 +153,68 @@ pub fn UserProfile(
                                 <div class="flex-fill"></div>
                              </div>
                          </div>
+                         <div class="card bg-darker m-3">
+                             <div class="card-body">
+                                <h5 class="card-title">"Preferences"</h5>
+                                <p class="text-secondary small">"Synced between all devices you are logged in on."</p>
+                                <div class="input-group mb-3">
+                                    <label class="input-group-text" for="themePreference">"Theme"</label>
+                                    <select
+                                      class="form-select"
+                                      id="themePreference"
+                                      on:change=move |ev| {
+                                          let theme = UiTheme::parse_str(event_target_value(&ev));
+                                          preferences.change(|p| p.theme = theme);
+                                      }
+                                    >
+                                      <option value="" selected=move || preferences.get().theme.is_none()>"Device default"</option>
+                                      <option value="dark" selected=move || preferences.get().theme == Some(UiTheme::Dark)>"Dark"</option>
+                                      <option value="light" selected=move || preferences.get().theme == Some(UiTheme::Light)>"Light"</option>
+                                    </select>
+                                </div>
+                                <div class="input-group mb-3">
+                                    <label class="input-group-text" for="landingPagePreference">"Page after login"</label>
+                                    <select
+                                      class="form-select"
+                                      id="landingPagePreference"
+                                      on:change=move |ev| {
+                                          let page = LandingPage::parse_str(event_target_value(&ev));
+                                          preferences.change(|p| p.landing_page = page);
+                                      }
+                                    >
+                                    {LandingPage::ALL.iter().map(|page| view! { cx,
+                                        <option
+                                          value=page.to_str()
+                                          selected=move || preferences.get().landing_page.unwrap_or_default() == *page
+                                        >
+                                          {move || t(cx, Page::from(*page).root_page().nav_id())}
+                                        </option>
+                                    }).collect::<Vec<_>>()}
+                                    </select>
+                                </div>
+                                <div class="input-group">
+                                    <label class="input-group-text" for="itemsPerPagePreference">"Items per page"</label>
+                                    <input
+                                      class="form-control"
+                                      id="itemsPerPagePreference"
+                                      type="number"
+                                      min="1"
+                                      max="100"
+                                      placeholder="Default"
+                                      prop:value=move || preferences.get().items_per_page.map(|n| n.to_string()).unwrap_or_default()
+                                      on:change=move |ev| {
+                                          let value = event_target_value(&ev);
+                                          let items = value.trim().parse::<u32>().ok().filter(|n| (1..=100).contains(n));
+                                          if items.is_none() && !value.trim().is_empty() {
+                                              profile_message.update(|m| *m = Message::Error("items per page must be between 1 and 100".into()));
+                                              return;
+                                          }
+                                          preferences.change(|p| p.items_per_page = items);
+                                      }
+                                    />
+                                </div>
+                             </div>
+                         </div>
                    </main>
                 }.into_view(cx)
             }
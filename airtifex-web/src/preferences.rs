@@ -0,0 +1,100 @@
+//! Preferences of the UI that are synced between the devices of a user. A copy is kept in the
+//! local storage so that it applies before the user is logged in, once logged in the copy that
+//! was changed last replaces the other one.
+
+use crate::{
+    api::AuthorizedApi,
+    components::theme_toggle::{use_theme, Theme},
+};
+use airtifex_core::user::UiPreferences;
+
+use gloo_storage::{LocalStorage, Storage};
+use leptos::*;
+
+const PREFERENCES_STORAGE_KEY: &str = "ui-preferences";
+
+/// Preferences of this device and the API they are synced with.
+#[derive(Clone, Copy)]
+pub struct Preferences {
+    preferences: RwSignal<UiPreferences>,
+    authorized_api: RwSignal<Option<AuthorizedApi>>,
+}
+
+impl Preferences {
+    pub fn get(&self) -> UiPreferences {
+        self.preferences.get()
+    }
+
+    /// Changes the preferences on this device and sends them to the server when logged in.
+    pub fn change(&self, f: impl FnOnce(&mut UiPreferences)) {
+        self.preferences.update(|preferences| {
+            f(preferences);
+            preferences.touch();
+        });
+        if let Some(api) = self.authorized_api.get() {
+            let this = *self;
+            spawn_local(async move {
+                this.sync(&api).await;
+            });
+        }
+    }
+
+    /// Replaces the preferences of the server or of this device, whichever were changed earlier,
+    /// and returns the preferences that apply afterwards.
+    pub async fn sync(&self, api: &AuthorizedApi) -> UiPreferences {
+        let local = self.preferences.get();
+        let server = match api.user_preferences().await {
+            Ok(server) => server,
+            Err(e) => {
+                log::error!("failed to fetch preferences - {e}");
+                return local;
+            }
+        };
+        let synced = if local.is_newer_than(&server) {
+            // the server keeps its copy if it was changed in the meantime and returns it
+            match api.user_preferences_update(&local).await {
+                Ok(stored) => stored,
+                Err(e) => {
+                    log::error!("failed to save preferences - {e}");
+                    return local;
+                }
+            }
+        } else {
+            server
+        };
+        if synced != local {
+            self.preferences.update(|p| *p = synced.clone());
+        }
+        synced
+    }
+}
+
+/// Creates the preferences and makes them available to all components through the context, the
+/// theme has to be provided first as the preferred theme is applied to it.
+pub fn provide_preferences(
+    cx: Scope,
+    authorized_api: RwSignal<Option<AuthorizedApi>>,
+) -> Preferences {
+    let stored = LocalStorage::get(PREFERENCES_STORAGE_KEY).unwrap_or_default();
+    let preferences = Preferences {
+        preferences: create_rw_signal(cx, stored),
+        authorized_api,
+    };
+    let theme = use_theme(cx);
+    create_effect(cx, move |_| {
+        let preferences = preferences.get();
+        if let Err(e) = LocalStorage::set(PREFERENCES_STORAGE_KEY, &preferences) {
+            log::error!("failed to save preferences - {e}");
+        }
+        if let Some(preferred) = preferences.theme {
+            theme.update(|t| *t = Theme::from(preferred));
+        }
+    });
+    provide_context(cx, preferences);
+    preferences
+}
+
+/// Returns the preferences provided by [`provide_preferences`].
+pub fn use_preferences(cx: Scope) -> Preferences {
+    use_context::<Preferences>(cx).expect("preferences context")
+}
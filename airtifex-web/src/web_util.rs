@@ -57,6 +57,51 @@ pub fn sleep(ms: i32) -> wasm_bindgen_futures::JsFuture {
     wasm_bindgen_futures::JsFuture::from(sleep_promise(ms))
 }
 
+/// Writes `text` to the clipboard. The clipboard API is looked up dynamically as its web-sys
+/// bindings are unstable.
+pub async fn copy_to_clipboard(text: &str) -> Result<(), JsValue> {
+    let navigator = web_sys::window()
+        .ok_or("Failed to get window object")?
+        .navigator();
+    let clipboard = js_sys::Reflect::get(&navigator, &"clipboard".into())?;
+    let write_text =
+        js_sys::Reflect::get(&clipboard, &"writeText".into())?.dyn_into::<js_sys::Function>()?;
+    let promise = write_text
+        .call1(&clipboard, &text.into())?
+        .dyn_into::<js_sys::Promise>()?;
+    wasm_bindgen_futures::JsFuture::from(promise)
+        .await
+        .map(|_| ())
+}
+
+/// Saves `content` as a file named `filename` through a temporary link.
+pub fn download_text(filename: &str, mime: &str, content: &str) -> Result<(), JsValue> {
+    let href = format!(
+        "data:{mime};charset=utf-8,{}",
+        String::from(js_sys::encode_uri_component(content))
+    );
+    download(filename, &href)
+}
+
+/// Saves the PNG `image` as a file named `filename` through a temporary link.
+pub fn download_png(filename: &str, image: &[u8]) -> Result<(), JsValue> {
+    download(filename, &encode_image_base64(image))
+}
+
+/// Follows a temporary link to `href` that downloads it as `filename`. The click is looked up
+/// dynamically as the `HtmlElement` bindings aren't enabled.
+fn download(filename: &str, href: &str) -> Result<(), JsValue> {
+    let document = web_sys::window()
+        .ok_or("Failed to get window object")?
+        .document()
+        .ok_or("Failed to get document")?;
+    let link = document.create_element("a")?;
+    link.set_attribute("href", href)?;
+    link.set_attribute("download", filename)?;
+    let click = js_sys::Reflect::get(&link, &"click".into())?.dyn_into::<js_sys::Function>()?;
+    click.call0(&link).map(|_| ())
+}
+
 /// Encodes image so that it can be used in an <img src=> tag
 pub fn encode_image_base64(image: &[u8]) -> String {
     use base64::engine::Engine;
@@ -120,6 +165,21 @@ pub fn display_limited_str(s: &str, limit: usize) -> String {
     }
 }
 
+/// Describes how long ago a time given in milliseconds since the epoch was, like `5 minutes ago`.
+pub fn relative_time(timestamp_millis: i64) -> String {
+    let secs = ((js_sys::Date::now() as i64 - timestamp_millis) / 1000).max(0);
+    let (n, unit) = match secs {
+        0..=59 => return "just now".into(),
+        60..=3_599 => (secs / 60, "minute"),
+        3_600..=86_399 => (secs / 3_600, "hour"),
+        86_400..=2_591_999 => (secs / 86_400, "day"),
+        2_592_000..=31_535_999 => (secs / 2_592_000, "month"),
+        _ => (secs / 31_536_000, "year"),
+    };
+    let plural = if n == 1 { "" } else { "s" };
+    format!("{n} {unit}{plural} ago")
+}
+
 pub fn get_resolved_path(cx: Scope) -> String {
     let location = use_location(cx);
     location.pathname.get()